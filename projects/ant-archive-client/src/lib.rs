@@ -80,6 +80,46 @@ impl AntArchiveClient {
         }
     }
 
+    /// Upload an object from a stream of chunks, without buffering it. The object is only
+    /// committed by ant-archive once the stream has ended successfully.
+    pub async fn put_object_stream<S>(
+        &self,
+        bucket: &str,
+        key: &str,
+        stream: S,
+    ) -> Result<(), AntArchiveClientError>
+    where
+        S: futures_core::TryStream + Send + 'static,
+        S::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+        bytes::Bytes: From<S::Ok>,
+    {
+        let res = self
+            .client
+            .put(format!("{}/o/{}/{}", self.url().await?, bucket, key))
+            .bearer_auth(&self.token)
+            .body(reqwest::Body::wrap_stream(stream))
+            .send()
+            .await?;
+
+        let status = res.status();
+        let body = res
+            .text()
+            .await
+            .unwrap_or("<error failed to deserialize response>".to_string());
+
+        if status == StatusCode::CREATED {
+            Ok(())
+        } else {
+            Err(AntArchiveClientError::ObjectRequestFailed {
+                method: "PUT".to_string(),
+                bucket: bucket.to_string(),
+                key: key.to_string(),
+                status: status,
+                body: body,
+            })
+        }
+    }
+
     pub async fn get_object(
         &self,
         bucket: &str,
@@ -109,6 +149,38 @@ impl AntArchiveClient {
         }
     }
 
+    /// Download an object as a stream of chunks, without buffering it.
+    pub async fn get_object_stream(
+        &self,
+        bucket: &str,
+        key: &str,
+    ) -> Result<
+        Option<impl futures_core::Stream<Item = reqwest::Result<bytes::Bytes>>>,
+        AntArchiveClientError,
+    > {
+        let res = self
+            .client
+            .get(format!("{}/o/{}/{}", self.url().await?, bucket, key))
+            .bearer_auth(&self.token)
+            .send()
+            .await?;
+
+        match res.status() {
+            StatusCode::OK => Ok(Some(res.bytes_stream())),
+            StatusCode::NOT_FOUND => Ok(None),
+            s => Err(AntArchiveClientError::ObjectRequestFailed {
+                method: "GET".to_string(),
+                bucket: bucket.to_string(),
+                key: key.to_string(),
+                status: s,
+                body: res
+                    .text()
+                    .await
+                    .unwrap_or("<error failed to deserialize response>".to_string()),
+            }),
+        }
+    }
+
     pub async fn delete(&self, bucket: &str, key: &str) -> Result<bool, AntArchiveClientError> {
         let res = self
             .client
@@ -0,0 +1,26 @@
+BEGIN;
+
+create table restore_verification (
+  restore_verification_id identifier primary key default ('rv-' || random_string(16)), -- Unique ID for the verification run.
+
+  backup_id identifier not null, -- The backup that was restored.
+
+  is_success boolean not null, -- Whether the backup restored and passed every sanity check.
+  ant_count bigint, -- Rows in the restored 'ant' table, null if the restore never got that far.
+  registered_user_count bigint, -- Rows in the restored 'registered_user' table, null if the restore never got that far.
+  failure_reason text, -- Null on success, otherwise a human-readable explanation.
+
+  started_at timestamp with time zone not null, -- When the restore was started.
+  finished_at timestamp with time zone not null default now(), -- When the verdict was reached.
+
+  created_at timestamp with time zone not null default now(),
+  updated_at timestamp with time zone not null default now(),
+
+  foreign key (backup_id) references backup(backup_id)
+);
+
+create index idx_restore_verification_backup_id on restore_verification (backup_id, finished_at);
+
+insert into migration (migration_label) values ('add-restore-verification');
+
+COMMIT;
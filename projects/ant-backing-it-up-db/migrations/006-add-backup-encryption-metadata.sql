@@ -0,0 +1,16 @@
+BEGIN;
+
+-- Backups are streamed through zstd and a chunked AES-256-GCM cipher into ant-archive. Each backup
+-- has its own data key, which is stored wrapped (encrypted) by the ant_backing_it_up_key master key.
+-- For these backups, destination_filepath is the ant-archive object key and encryption_nonce is
+-- the nonce prefix of the chunked stream.
+alter table backup add column encryption_format varchar(64); -- Null for legacy backups, e.g. 'zstd+aes-256-gcm-stream-v1'
+alter table backup add column encryption_key_id varchar(64); -- The ID of the master key that wrapped the data key.
+alter table backup add column wrapped_data_key bytea; -- The data key, encrypted by the master key.
+alter table backup add column wrapped_data_key_nonce bytea; -- The nonce used to wrap the data key.
+alter table backup add column size_bytes bigint; -- Size of the uploaded (compressed, encrypted) object.
+alter table backup add column checksum_sha256 varchar(64); -- Hex SHA-256 of the uploaded object.
+
+insert into migration (migration_label) values ('add-backup-encryption-metadata');
+
+COMMIT;
@@ -0,0 +1,55 @@
+BEGIN;
+
+-- Physical base backups (pg_basebackup), the starting point for point-in-time recovery.
+-- Stored in ant-archive the same way as the streaming logical backups.
+create table base_backup (
+  base_backup_id identifier primary key default ('bb-' || random_string(16)), -- Unique ID for the base backup.
+
+  project varchar(255) not null, -- The project that was backed up, e.g. 'ant-data-farm'.
+  database_host varchar(255) not null, -- The database host name, e.g. 'antworker001.hosts.typesofants.org'
+  database_port int not null, -- The database host port, e.g. '7000'
+
+  archive_key varchar(1024) not null, -- The ant-archive object key of the tar.
+  encryption_nonce bytea not null, -- The nonce prefix of the chunked stream.
+  encryption_key_id varchar(64) not null, -- The ID of the master key that wrapped the data key.
+  wrapped_data_key bytea not null, -- The data key, encrypted by the master key.
+  wrapped_data_key_nonce bytea not null, -- The nonce used to wrap the data key.
+  size_bytes bigint not null, -- Size of the uploaded (compressed, encrypted) object.
+  checksum_sha256 varchar(64) not null, -- Hex SHA-256 of the uploaded object.
+
+  started_at timestamp with time zone not null, -- When pg_basebackup was started.
+  finished_at timestamp with time zone not null default now(), -- Recovery can target any time after this.
+
+  created_at timestamp with time zone not null default now(),
+  updated_at timestamp with time zone not null default now(),
+  deleted_at timestamp with time zone
+);
+
+create index idx_base_backup_project on base_backup (project, finished_at);
+
+-- WAL segments and timeline history files shipped by each database's archive_command.
+create table wal_segment (
+  wal_segment_id identifier primary key default ('wal-' || random_string(16)), -- Unique ID for the segment.
+
+  project varchar(255) not null, -- The project whose database produced the segment.
+  segment_name varchar(64) not null, -- The %f of archive_command, e.g. '000000010000000000000001' or '00000002.history'
+
+  unique (project, segment_name),
+
+  plaintext_sha256 varchar(64) not null, -- Hex SHA-256 of the segment itself, to accept identical re-archives.
+
+  archive_key varchar(1024) not null, -- The ant-archive object key of the segment.
+  encryption_nonce bytea not null, -- The nonce prefix of the chunked stream.
+  encryption_key_id varchar(64) not null, -- The ID of the master key that wrapped the data key.
+  wrapped_data_key bytea not null, -- The data key, encrypted by the master key.
+  wrapped_data_key_nonce bytea not null, -- The nonce used to wrap the data key.
+  size_bytes bigint not null, -- Size of the uploaded (compressed, encrypted) object.
+  checksum_sha256 varchar(64) not null, -- Hex SHA-256 of the uploaded object.
+
+  created_at timestamp with time zone not null default now(), -- When the segment was archived.
+  updated_at timestamp with time zone not null default now()
+);
+
+insert into migration (migration_label) values ('add-wal-archive');
+
+COMMIT;
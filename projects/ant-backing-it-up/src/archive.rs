@@ -0,0 +1,241 @@
+use std::{
+    path::Path,
+    process::Stdio,
+    sync::{Arc, Mutex},
+};
+
+use ant_archive_client::AntArchiveClient;
+use anyhow::{anyhow, Context};
+use async_compression::tokio::bufread::{ZstdDecoder, ZstdEncoder};
+use futures::StreamExt;
+use sha2::{Digest, Sha256};
+use stdext::function_name;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt, BufReader};
+use tracing::warn;
+
+use crate::{crypto, ANT_ARCHIVE_BUCKET};
+
+/// Everything needed to find, check and decrypt an object written by [`upload_encrypted`].
+#[derive(Default)]
+pub struct EncryptedObject {
+    pub archive_key: String,
+    pub nonce_prefix: Vec<u8>,
+    pub wrapped_key: crypto::WrappedKey,
+    pub size_bytes: u64,
+    pub checksum_sha256: String,
+}
+
+/// Stream `reader` through zstd and a fresh per-object data key into ant-archive. Only a single
+/// chunk is held in memory at once, regardless of how much `reader` produces.
+pub async fn upload_encrypted<R>(
+    ant_archive: &AntArchiveClient,
+    archive_key: &str,
+    reader: R,
+) -> Result<EncryptedObject, anyhow::Error>
+where
+    R: AsyncRead + Unpin + Send + 'static,
+{
+    let (data_key, wrapped_key) = crypto::generate_data_key()?;
+    let nonce_prefix = crypto::generate_nonce_prefix();
+
+    let compressed = ZstdEncoder::new(BufReader::new(reader));
+    let summary = Arc::new(Mutex::new(crypto::StreamSummary::default()));
+    let encrypted = crypto::encrypt_stream(compressed, data_key, nonce_prefix, summary.clone());
+
+    ant_archive
+        .put_object_stream(ANT_ARCHIVE_BUCKET, archive_key, encrypted)
+        .await
+        .with_context(|| format!("{}: ant-archive put {}", function_name!(), archive_key))?;
+
+    let summary = summary.lock().unwrap();
+    Ok(EncryptedObject {
+        archive_key: archive_key.to_string(),
+        nonce_prefix: nonce_prefix.to_vec(),
+        wrapped_key,
+        size_bytes: summary.size_bytes,
+        checksum_sha256: summary.checksum(),
+    })
+}
+
+/// Run `cmd` and stream its stdout into ant-archive with [`upload_encrypted`]. If the command
+/// fails, the uploaded object is partial and is deleted again.
+pub async fn upload_command_output(
+    ant_archive: &AntArchiveClient,
+    archive_key: &str,
+    cmd: std::process::Command,
+) -> Result<EncryptedObject, anyhow::Error> {
+    let mut child = tokio::process::Command::from(cmd)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .with_context(|| format!("{}: spawn", function_name!()))?;
+
+    // Drain stderr concurrently, the command blocks if the pipe fills up.
+    let mut stderr = child.stderr.take().expect("piped stderr");
+    let stderr_handle = tokio::spawn(async move {
+        let mut buf = String::new();
+        let _ = stderr.read_to_string(&mut buf).await;
+        buf
+    });
+
+    let stdout = child.stdout.take().expect("piped stdout");
+    let upload = upload_encrypted(ant_archive, archive_key, stdout).await;
+
+    let status = child
+        .wait()
+        .await
+        .with_context(|| format!("{}: wait", function_name!()))?;
+    let stderr = stderr_handle.await.unwrap_or_default();
+
+    let object =
+        upload.with_context(|| format!("{}: upload, stderr: {}", function_name!(), stderr))?;
+
+    if !status.success() {
+        // The stream ended cleanly as far as ant-archive is concerned, but the output is partial.
+        if let Err(e) = ant_archive.delete(ANT_ARCHIVE_BUCKET, archive_key).await {
+            warn!("Failed to delete partial object {}: {}", archive_key, e);
+        }
+
+        return Err(anyhow!("command failed ({status}), stderr: {stderr}"));
+    }
+
+    Ok(object)
+}
+
+/// Download, check, decrypt and decompress an object written by [`upload_encrypted`].
+pub async fn download_decrypted(
+    ant_archive: &AntArchiveClient,
+    object: &EncryptedObject,
+) -> Result<Vec<u8>, anyhow::Error> {
+    let ciphertext = ant_archive
+        .get_object(ANT_ARCHIVE_BUCKET, &object.archive_key)
+        .await
+        .with_context(|| format!("{}: ant-archive get", function_name!()))?
+        .ok_or(anyhow!("{} missing from ant-archive", object.archive_key))?;
+
+    let actual = hex::encode(Sha256::digest(&ciphertext));
+    if actual != object.checksum_sha256 {
+        return Err(anyhow!(
+            "{} checksum mismatch, expected {} but got {}",
+            object.archive_key,
+            object.checksum_sha256,
+            actual
+        ));
+    }
+
+    let data_key = crypto::unwrap_data_key(&object.wrapped_key)?;
+    let nonce_prefix: [u8; 7] = object
+        .nonce_prefix
+        .as_slice()
+        .try_into()
+        .with_context(|| format!("{}: nonce prefix", function_name!()))?;
+
+    let compressed = crypto::decrypt_stream(&data_key, &nonce_prefix, &ciphertext)?;
+
+    let mut plaintext = vec![];
+    ZstdDecoder::new(compressed.as_slice())
+        .read_to_end(&mut plaintext)
+        .await
+        .with_context(|| format!("{}: zstd decode", function_name!()))?;
+
+    Ok(plaintext)
+}
+
+/// Like [`download_decrypted`], but streams the object into the file at `path` instead of memory.
+/// The ciphertext is staged next to it, to check it before decrypting anything.
+pub async fn download_decrypted_to_file(
+    ant_archive: &AntArchiveClient,
+    object: &EncryptedObject,
+    path: &Path,
+) -> Result<(), anyhow::Error> {
+    let staged = path.with_extension("enc");
+    let res = download_checked(ant_archive, object, &staged).await;
+    let res = match res {
+        Ok(()) => decrypt_file(object, &staged, path).await,
+        Err(e) => Err(e),
+    };
+
+    if let Err(e) = tokio::fs::remove_file(&staged).await {
+        warn!("Failed to remove {}: {}", staged.display(), e);
+    }
+
+    res
+}
+
+/// Download the ciphertext of `object` into `path`, checking it against its checksum.
+async fn download_checked(
+    ant_archive: &AntArchiveClient,
+    object: &EncryptedObject,
+    path: &Path,
+) -> Result<(), anyhow::Error> {
+    let mut stream = ant_archive
+        .get_object_stream(ANT_ARCHIVE_BUCKET, &object.archive_key)
+        .await
+        .with_context(|| format!("{}: ant-archive get", function_name!()))?
+        .ok_or(anyhow!("{} missing from ant-archive", object.archive_key))?;
+
+    let mut file = tokio::fs::File::create(path)
+        .await
+        .with_context(|| format!("{}: create {}", function_name!(), path.display()))?;
+    let mut sha256 = Sha256::new();
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk.with_context(|| format!("{}: ant-archive get", function_name!()))?;
+        sha256.update(&chunk);
+        file.write_all(&chunk).await?;
+    }
+    file.flush().await?;
+
+    let actual = hex::encode(sha256.finalize());
+    if actual != object.checksum_sha256 {
+        return Err(anyhow!(
+            "{} checksum mismatch, expected {} but got {}",
+            object.archive_key,
+            object.checksum_sha256,
+            actual
+        ));
+    }
+
+    Ok(())
+}
+
+/// Decrypt and decompress the ciphertext of `object` at `from` into `to`, a chunk at a time.
+async fn decrypt_file(
+    object: &EncryptedObject,
+    from: &Path,
+    to: &Path,
+) -> Result<(), anyhow::Error> {
+    let data_key = crypto::unwrap_data_key(&object.wrapped_key)?;
+    let nonce_prefix: [u8; 7] = object
+        .nonce_prefix
+        .as_slice()
+        .try_into()
+        .with_context(|| format!("{}: nonce prefix", function_name!()))?;
+
+    let ciphertext = BufReader::new(tokio::fs::File::open(from).await?);
+    let mut plaintext = tokio::fs::File::create(to)
+        .await
+        .with_context(|| format!("{}: create {}", function_name!(), to.display()))?;
+
+    // Decrypted chunks are decompressed as they come, through an in-memory pipe.
+    let (mut compressed_writer, compressed_reader) = tokio::io::duplex(64 * 1024);
+    let decrypt = async move {
+        crypto::decrypt_to_writer(&data_key, &nonce_prefix, ciphertext, &mut compressed_writer)
+            .await?;
+        compressed_writer.shutdown().await?;
+        Ok::<_, anyhow::Error>(())
+    };
+    let decompress = async {
+        tokio::io::copy(
+            &mut ZstdDecoder::new(BufReader::new(compressed_reader)),
+            &mut plaintext,
+        )
+        .await
+        .with_context(|| format!("{}: zstd decode", function_name!()))?;
+        plaintext.flush().await?;
+        Ok::<_, anyhow::Error>(())
+    };
+    tokio::try_join!(decrypt, decompress)?;
+
+    Ok(())
+}
@@ -89,18 +89,24 @@ async fn main() {
         .expect("list backups")
         .unwrap_or_else(|| panic!("Project has never had a backup!"));
 
+    let (destination_host, destination_port) =
+        match (&backup.destination_host, backup.destination_port) {
+            (Some(host), Some(port)) => (host.clone(), port),
+            _ => panic!("Backup {} was not stored on ant-fs!", backup.backup_id),
+        };
+
     info!(
         "Retrieving backup {} @ {} on [{}:{}/{}]",
         backup.project,
         backup.created_at,
-        backup.destination_host,
-        backup.destination_port,
+        destination_host,
+        destination_port,
         backup.destination_filepath
     );
 
     let ant_fs = AntFsClient::new(
-        &backup.destination_host,
-        backup.destination_port,
+        &destination_host,
+        destination_port,
         username.to_string(),
         password.to_string(),
         false,
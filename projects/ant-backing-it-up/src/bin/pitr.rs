@@ -0,0 +1,94 @@
+use std::{path::PathBuf, sync::Arc};
+
+use ant_archive_client::AntArchiveClient;
+use ant_backing_it_up::{
+    restore,
+    storage_client::{AntBackingItUpStorageClient, DatabaseParams},
+};
+use ant_library::sd::reader::ServiceDiscovery;
+use chrono::{DateTime, Utc};
+use clap::Parser;
+use tracing::info;
+
+/// Prepare a Postgres data directory that recovers a project's database to a point in time, from
+/// the latest base backup before that time and the WAL archive.
+#[derive(Parser, Debug)]
+#[command(about, long_about = None)]
+struct Args {
+    /// The project, e.g. 'ant-data-farm'.
+    project: String,
+
+    /// The RFC 3339 timestamp to recover to, e.g. 2025-06-01T12:00:00Z. Defaults to the end of
+    /// the WAL archive.
+    #[clap(long)]
+    target_time: Option<DateTime<Utc>>,
+
+    /// The empty directory to unpack the base backup into.
+    #[clap(long)]
+    data_dir: PathBuf,
+
+    /// The ant-backing-it-up URL that the recovering Postgres fetches WAL segments from.
+    #[clap(long)]
+    wal_url: String,
+}
+
+#[tokio::main]
+async fn main() {
+    let args = Args::parse();
+
+    ant_library::set_global_logs("pitr-restore");
+
+    dotenv::from_path(
+        PathBuf::from(
+            dotenv::var("TYPESOFANTS_SECRET_DIR").expect("No TYPESOFANTS_SECRET_DIR variable"),
+        )
+        .join("build.cfg"),
+    )
+    .unwrap();
+
+    let db = AntBackingItUpStorageClient::connect(&DatabaseParams {
+        db_name: ant_library::secret::load_secret("ant_backing_it_up_db_db").unwrap(),
+        username: ant_library::secret::load_secret("ant_backing_it_up_db_user").unwrap(),
+        password: ant_library::secret::load_secret("ant_backing_it_up_db_password").unwrap(),
+        host: dotenv::var("ANT_BACKING_IT_UP_DB_HOST")
+            .expect("No ANT_BACKING_IT_UP_DB_HOST variable."),
+        port: dotenv::var("ANT_BACKING_IT_UP_DB_PORT")
+            .expect("No ANT_BACKING_IT_UP_DB_PORT variable.")
+            .parse::<u16>()
+            .expect("port was not u16"),
+    })
+    .await
+    .expect("db param");
+
+    let sd = Arc::new(ServiceDiscovery::new(
+        dotenv::var("ANT_MATCHMAKER_HTTP_PORT")
+            .expect("No ANT_MATCHMAKER_HTTP_PORT variable.")
+            .parse::<u16>()
+            .expect("port was not u16"),
+    ));
+    let ant_archive = AntArchiveClient::new(
+        sd,
+        ant_library::secret::load_secret("ant_archive_token").expect("ant_archive_token secret"),
+    );
+
+    let base_backup = restore::prepare_point_in_time_recovery(
+        &db,
+        &ant_archive,
+        &args.project,
+        args.target_time.as_ref(),
+        &args.data_dir,
+        &args.wal_url,
+    )
+    .await
+    .expect("prepare recovery");
+
+    info!(
+        "Prepared {} from base backup {}. Start Postgres on it to replay WAL to {}:\n  postgres -D {}",
+        args.data_dir.display(),
+        base_backup.base_backup_id,
+        args.target_time
+            .map(|t| t.to_rfc3339())
+            .unwrap_or("the end of the archive".to_string()),
+        args.data_dir.display()
+    );
+}
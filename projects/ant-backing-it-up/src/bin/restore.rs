@@ -0,0 +1,122 @@
+use std::{path::PathBuf, sync::Arc};
+
+use ant_archive_client::AntArchiveClient;
+use ant_backing_it_up::{
+    restore,
+    storage_client::{AntBackingItUpStorageClient, DatabaseParams},
+};
+use ant_library::sd::reader::ServiceDiscovery;
+use clap::Parser;
+use tracing::{error, info};
+
+/// Restore a backup. By default the backup is restored into a scratch database, sanity checked,
+/// and the verdict is recorded. With --into-source-database the backup replaces the live database.
+#[derive(Parser, Debug)]
+#[command(about, long_about = None)]
+struct Args {
+    project: String,
+
+    /// The backup to restore. Defaults to the latest backup of the project.
+    #[clap(long)]
+    backup_id: Option<String>,
+
+    /// Restore over the project's own database instead of a scratch database.
+    #[clap(long, default_value_t = false)]
+    into_source_database: bool,
+
+    /// Required with --into-source-database, must be "{db_name}@{host}:{port}" of the target.
+    #[clap(long)]
+    confirm: Option<String>,
+}
+
+#[tokio::main]
+async fn main() {
+    let args = Args::parse();
+
+    ant_library::set_global_logs("restore-backup");
+
+    dotenv::from_path(
+        PathBuf::from(
+            dotenv::var("TYPESOFANTS_SECRET_DIR").expect("No TYPESOFANTS_SECRET_DIR variable"),
+        )
+        .join("build.cfg"),
+    )
+    .unwrap();
+
+    let db = AntBackingItUpStorageClient::connect(&DatabaseParams {
+        db_name: ant_library::secret::load_secret("ant_backing_it_up_db_db").unwrap(),
+        username: ant_library::secret::load_secret("ant_backing_it_up_db_user").unwrap(),
+        password: ant_library::secret::load_secret("ant_backing_it_up_db_password").unwrap(),
+        host: dotenv::var("ANT_BACKING_IT_UP_DB_HOST")
+            .expect("No ANT_BACKING_IT_UP_DB_HOST variable."),
+        port: dotenv::var("ANT_BACKING_IT_UP_DB_PORT")
+            .expect("No ANT_BACKING_IT_UP_DB_PORT variable.")
+            .parse::<u16>()
+            .expect("port was not u16"),
+    })
+    .await
+    .expect("db param");
+
+    let sd = Arc::new(ServiceDiscovery::new(
+        dotenv::var("ANT_MATCHMAKER_HTTP_PORT")
+            .expect("No ANT_MATCHMAKER_HTTP_PORT variable.")
+            .parse::<u16>()
+            .expect("port was not u16"),
+    ));
+    let ant_archive = AntArchiveClient::new(
+        sd,
+        ant_library::secret::load_secret("ant_archive_token").expect("ant_archive_token secret"),
+    );
+
+    let source = ant_backing_it_up::source_database_params(&args.project)
+        .expect("source database")
+        .unwrap_or_else(|| panic!("Unsupported project {}", args.project));
+
+    let backup = match &args.backup_id {
+        Some(backup_id) => db.get_backup(backup_id).await,
+        None => db.get_latest_backup_for_project(&args.project).await,
+    }
+    .expect("get backup")
+    .unwrap_or_else(|| panic!("No such backup for {}!", args.project));
+
+    let root_dir = PathBuf::from(".")
+        .join("restore")
+        .join(dotenv::var("TYPESOFANTS_ENV").unwrap());
+    std::fs::create_dir_all(&root_dir).unwrap();
+
+    if args.into_source_database {
+        let confirmation = args
+            .confirm
+            .expect("--into-source-database requires --confirm {db_name}@{host}:{port}");
+
+        restore::restore_into_target(&root_dir, &backup, &source, &confirmation, &ant_archive)
+            .await
+            .expect("restore");
+
+        info!("Restored {} into {}!", backup.backup_id, confirmation);
+        return;
+    }
+
+    let started_at = chrono::Utc::now();
+    let verdict = restore::verify_backup(&root_dir, &backup, &source, &ant_archive).await;
+    let verification = db
+        .record_restore_verification(&backup.backup_id, &verdict, &started_at)
+        .await
+        .expect("record verification");
+
+    if verification.is_success {
+        info!(
+            "Backup {} verified: {} ants, {} users",
+            backup.backup_id,
+            verification.ant_count.unwrap_or_default(),
+            verification.registered_user_count.unwrap_or_default()
+        );
+    } else {
+        error!(
+            "Backup {} failed verification: {}",
+            backup.backup_id,
+            verification.failure_reason.unwrap_or_default()
+        );
+        std::process::exit(1);
+    }
+}
@@ -1,8 +1,14 @@
+use std::sync::{Arc, Mutex};
+
 use aes_gcm::{
-    aead::{Aead, OsRng},
-    AeadCore, Aes256Gcm, KeyInit,
+    aead::{rand_core::RngCore, Aead, OsRng},
+    AeadCore, Aes256Gcm, Key, KeyInit, Nonce,
 };
-use sha2::digest::generic_array::GenericArray;
+use anyhow::anyhow;
+use bytes::Bytes;
+use futures::Stream;
+use sha2::{digest::generic_array::GenericArray, Digest, Sha256};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 
 pub fn decrypt_backup(nonce: &Vec<u8>, ciphertext: &Vec<u8>) -> Vec<u8> {
     let key = ant_library::secret::load_secret_binary("ant_backing_it_up_key")
@@ -38,3 +44,342 @@ pub fn encrypt_backup(plaintext: &Vec<u8>) -> Encryption {
         nonce: nonce.to_vec(),
     }
 }
+
+/// The `encryption_format` of backups written by [`encrypt_stream`].
+pub const STREAM_FORMAT: &str = "zstd+aes-256-gcm-stream-v1";
+
+/// Plaintext bytes per encrypted chunk. Each chunk is framed as a big-endian u32 ciphertext length
+/// followed by the ciphertext (plaintext + 16 byte tag).
+const STREAM_CHUNK_SIZE: usize = 64 * 1024;
+
+/// The master key and its ID. The ID is derived from the key itself, so that a rotated
+/// `ant_backing_it_up_key` is detectable from the backups it wrapped.
+fn master_key() -> Result<([u8; 32], String), anyhow::Error> {
+    let key = ant_library::secret::load_secret_binary("ant_backing_it_up_key")?;
+    let key: [u8; 32] = key
+        .get(0..32)
+        .ok_or(anyhow!("ant_backing_it_up_key must be at least 32 bytes"))?
+        .try_into()?;
+
+    let key_id = hex::encode(&Sha256::digest(key)[0..8]);
+
+    Ok((key, key_id))
+}
+
+/// A per-backup data key, encrypted by the master key.
+#[derive(Default)]
+pub struct WrappedKey {
+    pub key_id: String,
+    pub nonce: Vec<u8>,
+    pub ciphertext: Vec<u8>,
+}
+
+/// Generate a fresh data key for a single backup. Returns the plaintext key, to encrypt the backup
+/// with, and its wrapped form, to store.
+pub fn generate_data_key() -> Result<([u8; 32], WrappedKey), anyhow::Error> {
+    let (master, key_id) = master_key()?;
+
+    let data_key: [u8; 32] = Aes256Gcm::generate_key(&mut OsRng).into();
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let ciphertext = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&master))
+        .encrypt(&nonce, data_key.as_ref())
+        .map_err(|e| anyhow!("data key wrap failed: {e}"))?;
+
+    Ok((
+        data_key,
+        WrappedKey {
+            key_id,
+            nonce: nonce.to_vec(),
+            ciphertext,
+        },
+    ))
+}
+
+pub fn unwrap_data_key(wrapped: &WrappedKey) -> Result<[u8; 32], anyhow::Error> {
+    let (master, key_id) = master_key()?;
+    if key_id != wrapped.key_id {
+        return Err(anyhow!(
+            "data key was wrapped by master key {}, but the current master key is {}",
+            wrapped.key_id,
+            key_id
+        ));
+    }
+
+    let data_key = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&master))
+        .decrypt(
+            Nonce::from_slice(&wrapped.nonce),
+            wrapped.ciphertext.as_ref(),
+        )
+        .map_err(|e| anyhow!("data key unwrap failed: {e}"))?;
+
+    data_key
+        .try_into()
+        .map_err(|_| anyhow!("data key was not 32 bytes"))
+}
+
+/// Random per-backup prefix of every chunk nonce.
+pub fn generate_nonce_prefix() -> [u8; 7] {
+    let mut prefix = [0u8; 7];
+    OsRng.fill_bytes(&mut prefix);
+    prefix
+}
+
+/// Nonce for chunk `index`: the 7 byte prefix, the 4 byte chunk index and a final byte marking the
+/// last chunk, so that truncating the stream at a chunk boundary fails to decrypt.
+fn chunk_nonce(prefix: &[u8; 7], index: u32, last: bool) -> [u8; 12] {
+    let mut nonce = [0u8; 12];
+    nonce[..7].copy_from_slice(prefix);
+    nonce[7..11].copy_from_slice(&index.to_be_bytes());
+    nonce[11] = last as u8;
+    nonce
+}
+
+/// Reads up to `buf.len()` bytes, stopping early only at EOF.
+async fn fill_fully<R: AsyncRead + Unpin>(r: &mut R, buf: &mut [u8]) -> std::io::Result<usize> {
+    let mut n = 0;
+    while n < buf.len() {
+        match r.read(&mut buf[n..]).await? {
+            0 => break,
+            read => n += read,
+        }
+    }
+    Ok(n)
+}
+
+/// Size and checksum of everything an [`encrypt_stream`] has emitted so far.
+#[derive(Default)]
+pub struct StreamSummary {
+    pub size_bytes: u64,
+    sha256: Sha256,
+}
+
+impl StreamSummary {
+    pub fn checksum(&self) -> String {
+        hex::encode(self.sha256.clone().finalize())
+    }
+}
+
+/// Encrypt `reader` chunk by chunk, so that only a single chunk is held in memory at once. The
+/// summary is updated as chunks are emitted, and is complete once the stream has ended.
+pub fn encrypt_stream<R>(
+    reader: R,
+    data_key: [u8; 32],
+    nonce_prefix: [u8; 7],
+    summary: Arc<Mutex<StreamSummary>>,
+) -> impl Stream<Item = std::io::Result<Bytes>>
+where
+    R: AsyncRead + Unpin,
+{
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&data_key));
+
+    futures::stream::try_unfold(
+        (reader, cipher, 0u32, false),
+        move |(mut reader, cipher, index, done)| {
+            let summary = summary.clone();
+            async move {
+                if done {
+                    return Ok(None);
+                }
+
+                let mut buf = vec![0u8; STREAM_CHUNK_SIZE];
+                let n = fill_fully(&mut reader, &mut buf).await?;
+                let last = n < STREAM_CHUNK_SIZE;
+
+                let nonce = chunk_nonce(&nonce_prefix, index, last);
+                let ciphertext = cipher
+                    .encrypt(Nonce::from_slice(&nonce), &buf[..n])
+                    .map_err(|e| std::io::Error::other(format!("chunk encryption failed: {e}")))?;
+
+                let mut frame = Vec::with_capacity(4 + ciphertext.len());
+                frame.extend_from_slice(&(ciphertext.len() as u32).to_be_bytes());
+                frame.extend_from_slice(&ciphertext);
+
+                {
+                    let mut summary = summary.lock().unwrap();
+                    summary.size_bytes += frame.len() as u64;
+                    summary.sha256.update(&frame);
+                }
+
+                let next_index = index
+                    .checked_add(1)
+                    .ok_or(std::io::Error::other("backup has too many chunks"))?;
+
+                Ok(Some((
+                    Bytes::from(frame),
+                    (reader, cipher, next_index, last),
+                )))
+            }
+        },
+    )
+}
+
+/// Decrypt the output of [`encrypt_stream`].
+pub fn decrypt_stream(
+    data_key: &[u8; 32],
+    nonce_prefix: &[u8; 7],
+    ciphertext: &[u8],
+) -> Result<Vec<u8>, anyhow::Error> {
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(data_key));
+
+    let mut plaintext = vec![];
+    let mut rest = ciphertext;
+    let mut index = 0u32;
+    loop {
+        if rest.len() < 4 {
+            return Err(anyhow!("backup truncated at chunk {index}"));
+        }
+        let len = u32::from_be_bytes(rest[..4].try_into()?) as usize;
+        rest = &rest[4..];
+        if rest.len() < len {
+            return Err(anyhow!("backup truncated at chunk {index}"));
+        }
+        let (chunk, remaining) = rest.split_at(len);
+        rest = remaining;
+
+        let last = rest.is_empty();
+        let nonce = chunk_nonce(nonce_prefix, index, last);
+        let chunk = cipher
+            .decrypt(Nonce::from_slice(&nonce), chunk)
+            .map_err(|e| anyhow!("chunk {index} decryption failed: {e}"))?;
+        plaintext.extend_from_slice(&chunk);
+
+        if last {
+            return Ok(plaintext);
+        }
+        index += 1;
+    }
+}
+
+/// Decrypt the output of [`encrypt_stream`] from `reader` into `writer`, chunk by chunk, so that
+/// only a single chunk is held in memory at once. Returns the number of plaintext bytes written.
+pub async fn decrypt_to_writer<R, W>(
+    data_key: &[u8; 32],
+    nonce_prefix: &[u8; 7],
+    mut reader: R,
+    writer: &mut W,
+) -> Result<u64, anyhow::Error>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(data_key));
+
+    let mut header = [0u8; 4];
+    if fill_fully(&mut reader, &mut header).await? < 4 {
+        return Err(anyhow!("backup truncated at chunk 0"));
+    }
+
+    let mut written = 0u64;
+    let mut index = 0u32;
+    loop {
+        let mut chunk = vec![0u8; u32::from_be_bytes(header) as usize];
+        if fill_fully(&mut reader, &mut chunk).await? < chunk.len() {
+            return Err(anyhow!("backup truncated at chunk {index}"));
+        }
+
+        // The chunk is the last one if nothing follows it.
+        let last = match fill_fully(&mut reader, &mut header).await? {
+            0 => true,
+            4 => false,
+            _ => return Err(anyhow!("backup truncated after chunk {index}")),
+        };
+
+        let nonce = chunk_nonce(nonce_prefix, index, last);
+        let plaintext = cipher
+            .decrypt(Nonce::from_slice(&nonce), chunk.as_slice())
+            .map_err(|e| anyhow!("chunk {index} decryption failed: {e}"))?;
+        writer.write_all(&plaintext).await?;
+        written += plaintext.len() as u64;
+
+        if last {
+            writer.flush().await?;
+            return Ok(written);
+        }
+        index += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use futures::TryStreamExt;
+
+    use super::*;
+
+    async fn roundtrip(plaintext: Vec<u8>) {
+        let data_key = [7u8; 32];
+        let nonce_prefix = generate_nonce_prefix();
+        let summary = Arc::new(Mutex::new(StreamSummary::default()));
+
+        let frames: Vec<Bytes> = encrypt_stream(
+            plaintext.as_slice(),
+            data_key,
+            nonce_prefix,
+            summary.clone(),
+        )
+        .try_collect()
+        .await
+        .unwrap();
+        let ciphertext = frames.concat();
+
+        let summary = summary.lock().unwrap();
+        assert_eq!(summary.size_bytes, ciphertext.len() as u64);
+        assert_eq!(summary.checksum(), hex::encode(Sha256::digest(&ciphertext)));
+
+        let decrypted = decrypt_stream(&data_key, &nonce_prefix, &ciphertext).unwrap();
+        assert_eq!(decrypted, plaintext);
+
+        let mut written = vec![];
+        let n = decrypt_to_writer(
+            &data_key,
+            &nonce_prefix,
+            ciphertext.as_slice(),
+            &mut written,
+        )
+        .await
+        .unwrap();
+        assert_eq!(n, plaintext.len() as u64);
+        assert_eq!(written, plaintext);
+    }
+
+    #[tokio::test]
+    async fn stream_roundtrips_empty_input() {
+        roundtrip(vec![]).await;
+    }
+
+    #[tokio::test]
+    async fn stream_roundtrips_exact_chunk_multiple() {
+        roundtrip(vec![1u8; STREAM_CHUNK_SIZE * 2]).await;
+    }
+
+    #[tokio::test]
+    async fn stream_roundtrips_partial_chunk() {
+        roundtrip((0..STREAM_CHUNK_SIZE * 3 + 17).map(|i| i as u8).collect()).await;
+    }
+
+    #[tokio::test]
+    async fn stream_truncated_at_chunk_boundary_fails() {
+        let data_key = [7u8; 32];
+        let nonce_prefix = generate_nonce_prefix();
+        let plaintext = vec![1u8; STREAM_CHUNK_SIZE * 2 + 1];
+
+        let frames: Vec<Bytes> = encrypt_stream(
+            plaintext.as_slice(),
+            data_key,
+            nonce_prefix,
+            Arc::new(Mutex::new(StreamSummary::default())),
+        )
+        .try_collect()
+        .await
+        .unwrap();
+
+        let truncated = frames[..2].concat();
+        assert!(decrypt_stream(&data_key, &nonce_prefix, &truncated).is_err());
+        assert!(
+            decrypt_to_writer(&data_key, &nonce_prefix, truncated.as_slice(), &mut vec![])
+                .await
+                .is_err()
+        );
+    }
+}
@@ -1,14 +1,11 @@
-use std::io::{Read, Write};
-
 use ant_library::routes::Routes;
 use anyhow::Context;
 use axum::{
-    extract::State,
+    extract::{Path, State},
     response::IntoResponse,
     routing::{get, post},
     Json, Router,
 };
-use bytes::Bytes;
 use chrono::{DateTime, Datelike, Timelike, Utc};
 use http::{header, Method, StatusCode};
 use postgresql_commands::{pg_dump::PgDumpBuilder, traits::CommandToString, CommandBuilder};
@@ -16,16 +13,49 @@ use serde::{Deserialize, Serialize};
 use tower::ServiceBuilder;
 use tower_http::{catch_panic::CatchPanicLayer, cors::CorsLayer};
 use tracing::{debug, error, info, warn};
-use zip::write::SimpleFileOptions;
 
 use crate::{
+    restore::RestoreVerdict,
     state::AntBackingItUpState,
-    storage_client::{Backup, DatabaseParams},
+    storage_client::{Backup, DatabaseParams, RestoreVerification},
 };
 
+pub mod archive;
 pub mod crypto;
+pub mod restore;
 pub mod state;
 pub mod storage_client;
+pub mod wal;
+
+/// The ant-archive bucket that backups are uploaded to.
+pub const ANT_ARCHIVE_BUCKET: &str = "b-typesofants";
+
+/// The projects whose databases can be backed up. Each project's database is found through
+/// the {PROJECT}_HOST and {PROJECT}_PORT variables and the {project}_db/_user/_password secrets.
+pub const SOURCE_PROJECTS: [&str; 3] = ["ant-data-farm", "ant-archive-db", "ant-zookeeper-db"];
+
+/// The connection parameters for a project's database, from the environment and secrets. None if
+/// the project is not backed up, an error if its database is not configured in this environment.
+pub fn source_database_params(project: &str) -> Result<Option<DatabaseParams>, anyhow::Error> {
+    if !SOURCE_PROJECTS.contains(&project) {
+        return Ok(None);
+    }
+
+    let env_prefix = project.to_uppercase().replace("-", "_");
+    let secret_prefix = project.replace("-", "_");
+
+    Ok(Some(DatabaseParams {
+        host: dotenv::var(format!("{env_prefix}_HOST"))
+            .with_context(|| format!("No {env_prefix}_HOST variable"))?,
+        port: dotenv::var(format!("{env_prefix}_PORT"))
+            .with_context(|| format!("No {env_prefix}_PORT variable"))?
+            .parse::<u16>()
+            .with_context(|| format!("{env_prefix}_PORT was not u16"))?,
+        db_name: ant_library::secret::load_secret(&format!("{secret_prefix}_db"))?,
+        username: ant_library::secret::load_secret(&format!("{secret_prefix}_user"))?,
+        password: ant_library::secret::load_secret(&format!("{secret_prefix}_password"))?,
+    }))
+}
 
 #[derive(Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -60,40 +90,48 @@ pub struct BackupRequest {
 #[derive(Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct BackupResponse {
-    occurred_at: DateTime<Utc>,
+    pub backup_id: String,
+    pub occurred_at: DateTime<Utc>,
+}
+
+/// The pg_dump invocation for a project's database. The dump drops and recreates the database it
+/// was taken from, see [`restore::ScratchDatabase::restore`].
+pub fn backup_command(params: &DatabaseParams) -> PgDumpBuilder {
+    PgDumpBuilder::new()
+        .create()
+        .clean()
+        .if_exists()
+        .serializable_deferrable()
+        .dbname(&params.db_name)
+        .username(&params.username)
+        .pg_password(&params.password)
+        .host(&params.host)
+        .port(params.port)
 }
 
+/// Streams `pg_dump` through zstd and a per-backup data key into ant-archive, see
+/// [`archive::upload_encrypted`].
 async fn post_backup(
     State(AntBackingItUpState {
-        root_dir,
-        mut db,
-        mut ant_fs,
-        ant_archive,
-        ..
+        db, ant_archive, ..
     }): State<AntBackingItUpState>,
     Json(req): Json<BackupRequest>,
-) -> Result<impl IntoResponse, impl IntoResponse> {
-    // First, backup the remote database to a local file
-    let db_params: DatabaseParams = match req.source_project.as_str() {
-        "ant-data-farm" => DatabaseParams {
-            host: dotenv::var("ANT_DATA_FARM_HOST").expect("No ANT_DATA_FARM_HOST variable"),
-            port: dotenv::var("ANT_DATA_FARM_PORT")
-                .expect("No ANT_DATA_FARM_PORT variable")
-                .parse::<u16>()
-                .expect("port was not u16"),
-            db_name: ant_library::secret::load_secret("ant_data_farm_db").unwrap(),
-            username: ant_library::secret::load_secret("ant_data_farm_user").unwrap(),
-            password: ant_library::secret::load_secret("ant_data_farm_password").unwrap(),
-        },
-        p => {
-            warn!("ANT-ERR-007: Unsupported project {p}");
+) -> Result<impl IntoResponse, StatusCode> {
+    let db_params: DatabaseParams = match source_database_params(&req.source_project) {
+        Ok(Some(params)) => params,
+        Ok(None) => {
+            warn!("ANT-ERR-007: Unsupported project {}", req.source_project);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+        Err(e) => {
+            error!("ANT-ERR-204: {} database: {e:#}", req.source_project);
             return Err(StatusCode::INTERNAL_SERVER_ERROR);
         }
     };
 
     let now = chrono::Utc::now();
-    let local_sql_filename = format!(
-        "{}.{}-{}-{}.{}-{}-{}.bak.sql",
+    let key = format!(
+        "ant-backing-it-up/backups/{}/pgdumpbackup.{}-{}-{}.{}-{}-{}.bak.sql.zst.enc",
         req.source_project,
         now.year(),
         now.month(),
@@ -102,162 +140,160 @@ async fn post_backup(
         now.minute(),
         now.second()
     );
-    let local_sql_path = root_dir.join(&local_sql_filename);
 
-    let mut cmd = PgDumpBuilder::new()
-        .create()
-        .clean()
-        .if_exists()
-        .serializable_deferrable()
-        .dbname(&db_params.db_name)
-        .username(&db_params.username)
-        .pg_password(&db_params.password)
-        .host(&db_params.host)
-        .port(db_params.port)
-        .file(&local_sql_path)
-        .build();
+    let cmd = backup_command(&db_params).build();
 
     info!("Executing backup: {}", cmd.to_command_string());
 
-    let out = cmd.output().map_err(|e| {
-        error!("ANT-ERR-008: pg_dump execution: {e}");
-        StatusCode::INTERNAL_SERVER_ERROR
-    })?;
+    let object = archive::upload_command_output(&ant_archive, &key, cmd)
+        .await
+        .map_err(|e| {
+            error!("ANT-ERR-011: pg_dump backup failed: {e:#}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
 
-    let stdout = String::from_utf8(out.stdout).map_err(|e| {
-        error!("ANT-ERR-009: stdout not utf8: {e}");
-        StatusCode::INTERNAL_SERVER_ERROR
-    })?;
-    let stderr = String::from_utf8(out.stderr).map_err(|e| {
-        error!("ANT-ERR-010: stdout not utf8: {e}");
+    info!(
+        "Uploaded {} backup, sha256 {}",
+        humansize::format_size(object.size_bytes, humansize::DECIMAL),
+        object.checksum_sha256
+    );
+
+    // Then save all of that in the database.
+    info!("Recording backup job...");
+    let backup = db
+        .record_backup(&req.source_project, &db_params, &object)
+        .await
+        .map_err(|e| {
+            error!("ANT-ERR-012: db query failed: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    info!("Backup job successful.");
+
+    return Ok((
+        StatusCode::OK,
+        Json(BackupResponse {
+            backup_id: backup.backup_id,
+            occurred_at: backup.created_at,
+        }),
+    ));
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VerifyBackupRequest {
+    /// The project whose backup should be verified, e.g. 'ant-data-farm'.
+    pub project: String,
+    /// The backup to verify. If absent, the latest backup of the project is verified.
+    pub backup_id: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VerifyBackupResponse {
+    pub verification: RestoreVerification,
+}
+
+async fn post_verify_backup(
+    State(AntBackingItUpState {
+        root_dir,
+        db,
+        ant_archive,
+        ..
+    }): State<AntBackingItUpState>,
+    Json(req): Json<VerifyBackupRequest>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let source = source_database_params(&req.project)
+        .map_err(|e| {
+            error!("ANT-ERR-205: {} database: {e:#}", req.project);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or_else(|| {
+            warn!("ANT-ERR-138: Unsupported project {}", req.project);
+            StatusCode::BAD_REQUEST
+        })?;
+
+    let backup = match &req.backup_id {
+        Some(backup_id) => db.get_backup(backup_id).await,
+        None => db.get_latest_backup_for_project(&req.project).await,
+    }
+    .map_err(|e| {
+        error!("ANT-ERR-139: db: {e}");
         StatusCode::INTERNAL_SERVER_ERROR
-    })?;
+    })?
+    .ok_or(StatusCode::NOT_FOUND)?;
 
-    if !out.status.success() {
-        error!(
-            "ANT-ERR-011: pg_dump failed.\nstdout: {}\nstderr: {}",
-            stdout, stderr
+    if backup.project != req.project {
+        warn!(
+            "ANT-ERR-140: Backup {} belongs to {}, not {}",
+            backup.backup_id, backup.project, req.project
         );
-        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        return Err(StatusCode::BAD_REQUEST);
     }
 
-    let md: std::fs::Metadata = std::fs::metadata(&local_sql_path).unwrap();
+    let started_at = chrono::Utc::now();
+    info!("Verifying backup {}...", backup.backup_id);
+    let verdict: RestoreVerdict =
+        restore::verify_backup(&root_dir, &backup, &source, &ant_archive).await;
 
-    info!(
-        "Reading {} backup SQL: {}",
-        humansize::format_size(md.len(), humansize::DECIMAL),
-        local_sql_path.display()
-    );
-    let sql_plaintext = std::fs::read(&local_sql_path).unwrap();
-
-    // .read_to_end(&mut sql_plaintext)
-    // .unwrap();
-
-    // let local_zip_filename = format!("{local_sql_filename}.zip");
-    // let local_zip_path = root_dir.join(&local_zip_filename);
-    // let local_zip_file = std::fs::File::create(&local_zip_path).unwrap();
-    // info!("Creating zip archive: {}", local_zip_path.display());
-    // let mut zip = zip::ZipWriter::new(local_zip_file);
-
-    // info!(
-    //     "Writing {} bytes to zip archive: {}",
-    //     humansize::format_size(sql_plaintext.len(), humansize::DECIMAL),
-    //     local_zip_path.display()
-    // );
-    // zip.start_file(
-    //     local_sql_filename,
-    //     SimpleFileOptions::default()
-    //         .compression_method(zip::CompressionMethod::Xz)
-    //         .large_file(true),
-    // )
-    // .expect("zip create file");
-    // zip.write_all(&sql_plaintext).expect("zip write");
-    // zip.finish().expect("zip flush");
-    // let mut plaintext_zip_file = std::fs::File::open(&local_zip_path).expect("open zip file");
-
-    // info!("Reading zip archive: {}", local_zip_path.display());
-    // let mut plaintext_zip_buf: Vec<u8> = vec![];
-    // plaintext_zip_file
-    // .read_to_end(&mut plaintext_zip_buf)
-    // .expect("plaintext zip read");
-
-    // info!("Deleting zip archive: {}", local_zip_path.display());
-    // std::fs::remove_file(&local_zip_path)
-    // .expect(&format!("removing zip file: {}", local_zip_path.display()));
-
-    // info!("Encrypting zip archive...");
-    // let encryption = crypto::encrypt_backup(&plaintext_zip_buf);
-
-    // Then save that file to an ant-fs worker.
-    // info!("Saving to ant-fs...");
-    // let remote_filename = format!("{}.enc", &local_zip_filename);
-
-    // let bytes = Bytes::from(encryption.ciphertext);
-
-    // {
-    //     ant_fs
-    //         .put_file(&remote_filename, bytes.clone())
-    //         .await
-    //         .context("ant-fs operation")
-    //         .unwrap();
-    // }
-
-    let key = {
-        info!("Saving plaintext to ant-archive...");
-        let plaintext_bytes = Bytes::from(sql_plaintext);
-
-        let key = format!(
-            "ant-backing-it-up/backups/{}/pgdumpbackup.{}-{}-{}.{}-{}-{}.bak.sql.zip",
-            req.source_project,
-            now.year(),
-            now.month(),
-            now.day(),
-            now.hour(),
-            now.minute(),
-            now.second()
+    if verdict.is_success {
+        info!("Backup {} verified: {:?}", backup.backup_id, verdict);
+    } else {
+        error!(
+            "ANT-ERR-141: Backup {} failed verification: {:?}",
+            backup.backup_id, verdict
         );
+    }
 
-        ant_archive
-            .put_object("b-typesofants", &key, plaintext_bytes.clone())
-            .await
-            .context("ant-archive operation")
-            .unwrap();
-
-        key
-    };
+    let verification = db
+        .record_restore_verification(&backup.backup_id, &verdict, &started_at)
+        .await
+        .map_err(|e| {
+            error!("ANT-ERR-142: db: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
 
-    // Then save all of that in the database.
-    info!("Recording backup job...");
-    // db.record_backup(&req.source_project, &db_params, &encryption.nonce, &key)
-    // db.record_backup(&req.source_project, &db_params, &None, &key)
-    //     .await
-    //     .map_err(|e| {
-    //         error!("ANT-ERR-012: db query failed: {e}");
-    //         StatusCode::INTERNAL_SERVER_ERROR
-    //     })?;
-
-    info!("Removing backup SQL: {}", local_sql_path.display());
-    std::fs::remove_file(&local_sql_path).expect(&format!(
-        "removing pg_dump output: {}",
-        local_sql_path.display()
-    ));
+    return Ok((StatusCode::OK, Json(VerifyBackupResponse { verification })));
+}
 
-    info!("Backup job successful.");
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListVerificationsResponse {
+    pub verifications: Vec<RestoreVerification>,
+}
 
-    return Ok(StatusCode::OK);
+async fn list_verifications(
+    State(AntBackingItUpState { db, .. }): State<AntBackingItUpState>,
+    Path(backup_id): Path<String>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let verifications = db
+        .get_restore_verifications(&backup_id)
+        .await
+        .map_err(|e| {
+            error!("ANT-ERR-143: db: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    return Ok((
+        StatusCode::OK,
+        Json(ListVerificationsResponse { verifications }),
+    ));
 }
 
 pub fn make_routes(s: AntBackingItUpState) -> Result<Router, anyhow::Error> {
     debug!("Initializing API route...");
 
     let cors = CorsLayer::new()
-        .allow_methods([Method::GET, Method::POST, Method::POST])
+        .allow_methods([Method::GET, Method::POST, Method::PUT])
         .allow_headers([header::CONTENT_TYPE]);
 
     debug!("Initializing site routes...");
     let app = Routes::new()
         .get("/backups", get(list_backups))
         .post("/backup", post(post_backup))
+        .post("/backup/verify", post(post_verify_backup))
+        .get("/backup/{backup_id}/verifications", get(list_verifications))
+        .merge_routes(wal::routes())
         .build()
         .with_state(s)
         .layer(
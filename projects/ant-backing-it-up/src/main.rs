@@ -2,7 +2,9 @@ use std::{fs::create_dir_all, net::SocketAddr, path::PathBuf, sync::Arc, time::D
 
 use ant_archive_client::AntArchiveClient;
 use ant_backing_it_up::{
-    state::AntBackingItUpState, storage_client::AntBackingItUpStorageClient, BackupRequest,
+    source_database_params, state::AntBackingItUpState,
+    storage_client::AntBackingItUpStorageClient, wal::BaseBackupRequest, BackupRequest,
+    VerifyBackupRequest, SOURCE_PROJECTS,
 };
 use ant_fs_client::{AntFsClient, AntFsHostPorts};
 use ant_library::sd::reader::ServiceDiscovery;
@@ -112,12 +114,45 @@ async fn main() {
                     .send()
                     .await;
 
+                // Verifying after a failed backup would verify the previous one instead.
                 match res {
-                    Ok(res) => {
+                    Ok(res) if res.status().is_success() => {
                         info!("backup {} status: {}", project, res.status());
                     }
+                    Ok(res) => {
+                        error!(
+                            "ANT-ERR-203: backup {} failed with status: {}",
+                            project,
+                            res.status()
+                        );
+                        continue;
+                    }
                     Err(e) => {
                         error!("ANT-ERR-013: backup {} failed: {}", project, e);
+                        continue;
+                    }
+                }
+
+                info!("Verifying latest backup of {}...", project);
+                let res = client
+                    .post(format!(
+                        "http://{}:{}/backup/verify",
+                        addr.ip(),
+                        addr.port()
+                    ))
+                    .json(&VerifyBackupRequest {
+                        project: project.to_string(),
+                        backup_id: None,
+                    })
+                    .send()
+                    .await;
+
+                match res {
+                    Ok(res) => {
+                        info!("verify {} status: {}", project, res.status());
+                    }
+                    Err(e) => {
+                        error!("ANT-ERR-144: verify {} failed: {}", project, e);
                     }
                 }
             }
@@ -126,5 +161,48 @@ async fn main() {
         }
     });
 
-    join_all(vec![api_handle, cron_handle]).await;
+    // Base backups are the starting point of point-in-time recovery, WAL is replayed from the
+    // latest one. Daily keeps the amount of WAL to replay bounded.
+    let base_backup_handle = tokio::spawn(async move {
+        let client = reqwest::Client::new();
+
+        loop {
+            for project in SOURCE_PROJECTS {
+                // Not every environment runs every database.
+                if let Err(e) = source_database_params(project) {
+                    info!("Not creating base backup of {}: {e:#}", project);
+                    continue;
+                }
+
+                info!("Creating base backup of {}...", project);
+                let res = client
+                    .post(format!("http://{}:{}/base-backup", addr.ip(), addr.port()))
+                    .json(&BaseBackupRequest {
+                        project: project.to_string(),
+                    })
+                    .send()
+                    .await;
+
+                match res {
+                    Ok(res) if res.status().is_success() => {
+                        info!("base backup {} status: {}", project, res.status());
+                    }
+                    Ok(res) => {
+                        error!(
+                            "ANT-ERR-207: base backup {} failed with status: {}",
+                            project,
+                            res.status()
+                        );
+                    }
+                    Err(e) => {
+                        error!("ANT-ERR-161: base backup {} failed: {}", project, e);
+                    }
+                }
+            }
+
+            sleep(Duration::ZERO.add_hours(24)).await;
+        }
+    });
+
+    join_all(vec![api_handle, cron_handle, base_backup_handle]).await;
 }
@@ -0,0 +1,782 @@
+use std::{
+    io::{Cursor, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use ant_archive_client::AntArchiveClient;
+use ant_fs_client::AntFsClient;
+use ant_library::sd::pg::PostgresManager;
+use anyhow::{anyhow, Context};
+use bb8_postgres::bb8::Pool;
+use chrono::{DateTime, Utc};
+use postgresql_commands::{psql::PsqlBuilder, traits::CommandToString, CommandBuilder};
+use postgresql_embedded::PostgreSQL;
+use serde::{Deserialize, Serialize};
+use stdext::function_name;
+use tracing::{debug, info, warn};
+
+use crate::{
+    archive, crypto,
+    storage_client::{AntBackingItUpStorageClient, Backup, BaseBackup, DatabaseParams},
+    ANT_ARCHIVE_BUCKET,
+};
+
+/// The database every backed-up project keeps its data in. Dumps are taken with `--create`, so
+/// restoring them recreates this database from the maintenance database.
+const RESTORED_DATABASE_NAME: &str = "typesofants";
+
+/// A table that must exist with at least `min_rows` rows for a restore to be considered healthy.
+struct SanityCheck {
+    table: &'static str,
+    min_rows: i64,
+}
+
+const ANT_DATA_FARM_SANITY_CHECKS: [SanityCheck; 2] = [
+    SanityCheck {
+        table: "ant",
+        min_rows: 1,
+    },
+    SanityCheck {
+        table: "registered_user",
+        min_rows: 1,
+    },
+];
+
+/// The checks for a project. Projects without checks only have to restore cleanly.
+fn sanity_checks(project: &str) -> &'static [SanityCheck] {
+    match project {
+        "ant-data-farm" => &ANT_DATA_FARM_SANITY_CHECKS,
+        _ => &[],
+    }
+}
+
+/// The outcome of restoring a single backup and running the sanity checks against it.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct RestoreVerdict {
+    pub is_success: bool,
+    pub ant_count: Option<i64>,
+    pub registered_user_count: Option<i64>,
+    pub failure_reason: Option<String>,
+}
+
+impl RestoreVerdict {
+    fn failed(reason: String) -> Self {
+        RestoreVerdict {
+            is_success: false,
+            ant_count: None,
+            registered_user_count: None,
+            failure_reason: Some(reason),
+        }
+    }
+}
+
+/// Parse the first "username:password" line of the `ant_fs_client_creds` secret.
+fn ant_fs_client_creds() -> Result<(String, String), anyhow::Error> {
+    let creds = ant_library::secret::load_secret("ant_fs_client_creds")?;
+    let line = creds
+        .split("\n")
+        .next()
+        .ok_or(anyhow!("ant_fs_client_creds was empty"))?;
+    let (username, password) = line
+        .split_once(":")
+        .ok_or(anyhow!("ant_fs_client_creds was not username:password"))?;
+
+    Ok((username.to_string(), password.to_string()))
+}
+
+/// Download a backup and return the plaintext SQL that pg_dump produced.
+///
+/// Backups on ant-fs are zipped and encrypted with the nonce stored alongside the backup. Backups
+/// in ant-archive are either the raw pg_dump output, or zstd compressed and encrypted with a
+/// per-backup data key.
+pub async fn download_backup(
+    backup: &Backup,
+    ant_archive: &AntArchiveClient,
+) -> Result<Vec<u8>, anyhow::Error> {
+    match (&backup.destination_host, backup.destination_port) {
+        (Some(host), Some(port)) => {
+            info!(
+                "Downloading backup {} from ant-fs [{}:{}/{}]",
+                backup.backup_id, host, port, backup.destination_filepath
+            );
+
+            let (username, password) = ant_fs_client_creds()?;
+            let ant_fs = AntFsClient::new(host, port, username, password, false);
+            let ciphertext = ant_fs
+                .get_file(&backup.destination_filepath)
+                .await
+                .with_context(|| format!("{}: ant-fs get", function_name!()))?
+                .ok_or(anyhow!(
+                    "backup {} missing from ant-fs",
+                    backup.destination_filepath
+                ))?;
+
+            let plaintext_zip = crypto::decrypt_backup(&backup.encryption_nonce, &ciphertext);
+
+            let mut reader = zip::ZipArchive::new(Cursor::new(plaintext_zip))
+                .with_context(|| format!("{}: open zip", function_name!()))?;
+            let mut zip_file = reader
+                .by_index(0)
+                .with_context(|| format!("{}: zip entry", function_name!()))?;
+
+            let mut sql = vec![];
+            zip_file
+                .read_to_end(&mut sql)
+                .with_context(|| format!("{}: read zip entry", function_name!()))?;
+
+            Ok(sql)
+        }
+        _ => {
+            info!(
+                "Downloading backup {} from ant-archive [{}/{}]",
+                backup.backup_id, ANT_ARCHIVE_BUCKET, backup.destination_filepath
+            );
+
+            if let Some(object) = backup.encrypted_object() {
+                return archive::download_decrypted(ant_archive, &object).await;
+            }
+            if let Some(format) = &backup.encryption_format {
+                return Err(anyhow!("unknown backup encryption format {format}"));
+            }
+
+            // Legacy backups were uploaded as the plaintext pg_dump output.
+            let sql = ant_archive
+                .get_object(ANT_ARCHIVE_BUCKET, &backup.destination_filepath)
+                .await
+                .with_context(|| format!("{}: ant-archive get", function_name!()))?
+                .ok_or(anyhow!(
+                    "backup {} missing from ant-archive",
+                    backup.destination_filepath
+                ))?;
+
+            Ok(sql)
+        }
+    }
+}
+
+/// Run a pg_dump SQL file through psql, stopping on the first error.
+fn run_psql(builder: PsqlBuilder, sql_path: &Path) -> Result<(), anyhow::Error> {
+    let mut cmd = builder
+        .no_psqlrc()
+        .quiet()
+        .variable(("ON_ERROR_STOP", "1"))
+        .file(sql_path)
+        .build();
+
+    debug!("Executing restore: {}", cmd.to_command_string());
+
+    let out = cmd
+        .output()
+        .with_context(|| format!("{}: psql execution", function_name!()))?;
+
+    if !out.status.success() {
+        return Err(anyhow!(
+            "psql failed.\nstdout: {}\nstderr: {}",
+            String::from_utf8_lossy(&out.stdout),
+            String::from_utf8_lossy(&out.stderr)
+        ));
+    }
+
+    Ok(())
+}
+
+/// A throwaway Postgres instance that backups are restored into. The instance and its data
+/// directory are removed when this is dropped.
+pub struct ScratchDatabase {
+    pg: PostgreSQL,
+}
+
+impl ScratchDatabase {
+    /// Start a scratch instance, installing the Postgres binaries under `root_dir` if necessary.
+    ///
+    /// `roles` are created up front so that the `OWNER TO` and `GRANT` statements in the dump can
+    /// be replayed as they were written.
+    pub async fn start(root_dir: &Path, roles: &[&str]) -> Result<Self, anyhow::Error> {
+        let pg_dir = root_dir.join("pgscratch");
+        std::fs::create_dir_all(&pg_dir)
+            .with_context(|| format!("{}: create {}", function_name!(), pg_dir.display()))?;
+
+        let mut pg = PostgreSQL::new(postgresql_embedded::Settings {
+            temporary: true,
+            installation_dir: pg_dir,
+            ..Default::default()
+        });
+
+        pg.setup()
+            .await
+            .with_context(|| format!("{}: setup", function_name!()))?;
+        pg.start()
+            .await
+            .with_context(|| format!("{}: start", function_name!()))?;
+
+        let scratch = ScratchDatabase { pg };
+
+        let maintenance = scratch.pool("postgres").await?;
+        let conn = maintenance.get().await?;
+        for role in roles {
+            let exists = conn
+                .query_opt("select 1 from pg_roles where rolname = $1", &[role])
+                .await
+                .with_context(|| format!("{}: lookup role {}", function_name!(), role))?
+                .is_some();
+            if exists {
+                continue;
+            }
+
+            conn.batch_execute(&format!(
+                "create role \"{}\" with login",
+                role.replace("\"", "\"\"")
+            ))
+            .await
+            .with_context(|| format!("{}: create role {}", function_name!(), role))?;
+        }
+
+        Ok(scratch)
+    }
+
+    async fn pool(&self, database_name: &str) -> Result<Pool<PostgresManager>, anyhow::Error> {
+        let settings = self.pg.settings();
+        let manager = PostgresManager::new_static(
+            &settings.host,
+            settings.port,
+            database_name,
+            &settings.username,
+            &settings.password,
+        );
+
+        Ok(Pool::builder().max_size(1).build(manager).await?)
+    }
+
+    /// Replay a pg_dump SQL file. The dump recreates its own database, so psql connects to the
+    /// maintenance database.
+    pub fn restore(&self, sql_path: &Path) -> Result<(), anyhow::Error> {
+        run_psql(
+            PsqlBuilder::from(self.pg.settings()).dbname("postgres"),
+            sql_path,
+        )
+    }
+
+    /// Count the rows of every sanity-checked table in the restored database.
+    pub async fn verify(&self, project: &str) -> RestoreVerdict {
+        let pool = match self.pool(RESTORED_DATABASE_NAME).await {
+            Ok(pool) => pool,
+            Err(e) => return RestoreVerdict::failed(format!("connect to restored db: {e}")),
+        };
+        let conn = match pool.get().await {
+            Ok(conn) => conn,
+            Err(e) => return RestoreVerdict::failed(format!("connect to restored db: {e}")),
+        };
+
+        let mut verdict = RestoreVerdict {
+            is_success: true,
+            ant_count: None,
+            registered_user_count: None,
+            failure_reason: None,
+        };
+        let mut failures: Vec<String> = vec![];
+
+        for check in sanity_checks(project) {
+            let count = conn
+                .query_one(
+                    &format!(
+                        "select count(*) as row_count from {}.{}",
+                        RESTORED_DATABASE_NAME, check.table
+                    ),
+                    &[],
+                )
+                .await
+                .map(|row| row.get::<_, i64>("row_count"));
+
+            match count {
+                Ok(count) => {
+                    info!("Restored table {} has {} rows", check.table, count);
+                    match check.table {
+                        "ant" => verdict.ant_count = Some(count),
+                        "registered_user" => verdict.registered_user_count = Some(count),
+                        _ => {}
+                    }
+
+                    if count < check.min_rows {
+                        failures.push(format!(
+                            "{} has {} rows, expected at least {}",
+                            check.table, count, check.min_rows
+                        ));
+                    }
+                }
+                Err(e) => failures.push(format!("{} not queryable: {}", check.table, e)),
+            }
+        }
+
+        if !failures.is_empty() {
+            verdict.is_success = false;
+            verdict.failure_reason = Some(failures.join("; "));
+        }
+
+        verdict
+    }
+}
+
+/// Write the SQL to a local file under `root_dir`, so that psql can replay it.
+fn write_sql_file(root_dir: &Path, backup: &Backup, sql: &[u8]) -> Result<PathBuf, anyhow::Error> {
+    let sql_path = root_dir.join(format!("{}.restore.sql", backup.backup_id));
+    std::fs::write(&sql_path, sql)
+        .with_context(|| format!("{}: write {}", function_name!(), sql_path.display()))?;
+
+    Ok(sql_path)
+}
+
+/// Download a backup, restore it into a scratch database and run the sanity checks. Any error
+/// along the way is folded into a failed verdict rather than returned, so that it can be recorded.
+pub async fn verify_backup(
+    root_dir: &Path,
+    backup: &Backup,
+    source: &DatabaseParams,
+    ant_archive: &AntArchiveClient,
+) -> RestoreVerdict {
+    let sql = match download_backup(backup, ant_archive).await {
+        Ok(sql) => sql,
+        Err(e) => return RestoreVerdict::failed(format!("download: {e:#}")),
+    };
+
+    let sql_path = match write_sql_file(root_dir, backup, &sql) {
+        Ok(path) => path,
+        Err(e) => return RestoreVerdict::failed(format!("write: {e:#}")),
+    };
+
+    let verdict = async {
+        let scratch =
+            match ScratchDatabase::start(root_dir, &[&source.username, "monitoring"]).await {
+                Ok(scratch) => scratch,
+                Err(e) => return RestoreVerdict::failed(format!("scratch database: {e:#}")),
+            };
+
+        info!(
+            "Restoring backup {} into scratch database",
+            backup.backup_id
+        );
+        if let Err(e) = scratch.restore(&sql_path) {
+            return RestoreVerdict::failed(format!("restore: {e:#}"));
+        }
+
+        scratch.verify(&backup.project).await
+    }
+    .await;
+
+    if let Err(e) = std::fs::remove_file(&sql_path) {
+        warn!("Failed to remove {}: {}", sql_path.display(), e);
+    }
+
+    verdict
+}
+
+/// Restore a backup over a live database. The dump drops and recreates the database, so the
+/// caller has to repeat the target back as `confirmation`, in the form `{db_name}@{host}:{port}`.
+pub async fn restore_into_target(
+    root_dir: &Path,
+    backup: &Backup,
+    target: &DatabaseParams,
+    confirmation: &str,
+    ant_archive: &AntArchiveClient,
+) -> Result<(), anyhow::Error> {
+    let expected = format!("{}@{}:{}", target.db_name, target.host, target.port);
+    if confirmation != expected {
+        return Err(anyhow!(
+            "refusing to restore over {expected}, confirmation was '{confirmation}'"
+        ));
+    }
+
+    let sql = download_backup(backup, ant_archive).await?;
+    let sql_path = write_sql_file(root_dir, backup, &sql)?;
+
+    warn!("Restoring backup {} over {}", backup.backup_id, expected);
+    let res = run_psql(
+        PsqlBuilder::new()
+            .host(&target.host)
+            .port(target.port)
+            .username(&target.username)
+            .pg_password(&target.password)
+            .dbname("postgres"),
+        &sql_path,
+    );
+
+    std::fs::remove_file(&sql_path)
+        .with_context(|| format!("{}: remove {}", function_name!(), sql_path.display()))?;
+
+    res
+}
+
+/// Prepare `data_dir` to recover `project`'s database to `target_time`, or to the end of the WAL
+/// archive if there is no target. The latest base backup that finished before the target is
+/// unpacked once the WAL archive is known to reach from it to the target, and Postgres is
+/// configured to replay WAL from `wal_url` (ant-backing-it-up's `/wal/{project}` routes) when it
+/// is next started on `data_dir`.
+pub async fn prepare_point_in_time_recovery(
+    db: &AntBackingItUpStorageClient,
+    ant_archive: &AntArchiveClient,
+    project: &str,
+    target_time: Option<&DateTime<Utc>>,
+    data_dir: &Path,
+    wal_url: &str,
+) -> Result<BaseBackup, anyhow::Error> {
+    if std::fs::exists(data_dir)? && std::fs::read_dir(data_dir)?.next().is_some() {
+        return Err(anyhow!("{} is not empty", data_dir.display()));
+    }
+
+    let base_backup = db
+        .get_base_backup_for_recovery(project, target_time)
+        .await?
+        .ok_or(anyhow!(
+            "no base backup of {project} before {target_time:?}"
+        ))?;
+
+    // Recovery from a base backup taken without WAL needs at least the segments written while it
+    // ran, and stops short of the target if the archive ends before it.
+    let wal_segments = db
+        .get_wal_segments_for_recovery(project, &base_backup, target_time)
+        .await?;
+    let (first, last) = match (wal_segments.first(), wal_segments.last()) {
+        (Some(first), Some(last)) => (first, last),
+        _ => {
+            return Err(anyhow!(
+                "no WAL of {project} archived since base backup {}",
+                base_backup.base_backup_id
+            ))
+        }
+    };
+    let archived_until = wal_segments
+        .iter()
+        .map(|segment| segment.created_at)
+        .max()
+        .unwrap_or(last.created_at);
+    if let Some(target_time) = target_time {
+        if archived_until < *target_time {
+            return Err(anyhow!(
+                "the WAL archive of {project} ends at {archived_until}, before {target_time}"
+            ));
+        }
+    }
+    info!(
+        "Recovery replays {} WAL segments, {} to {}",
+        wal_segments.len(),
+        first.segment_name,
+        last.segment_name
+    );
+
+    info!(
+        "Unpacking base backup {} from {} into {}",
+        base_backup.base_backup_id,
+        base_backup.finished_at,
+        data_dir.display()
+    );
+    // Base backups are as large as the database, they go through disk instead of memory.
+    let tar_path = data_dir.with_extension("basebackup.tar");
+    archive::download_decrypted_to_file(ant_archive, &base_backup.object, &tar_path).await?;
+    std::fs::create_dir_all(data_dir)?;
+    let unpacked = std::fs::File::open(&tar_path)
+        .and_then(|tar_file| tar::Archive::new(tar_file).unpack(data_dir))
+        .with_context(|| format!("{}: unpack", function_name!()));
+    std::fs::remove_file(&tar_path)
+        .with_context(|| format!("{}: remove {}", function_name!(), tar_path.display()))?;
+    unpacked?;
+
+    let mut recovery_conf = format!(
+        "\n# Added by ant-backing-it-up for point-in-time recovery of {}\n\
+         restore_command = 'curl --fail --silent --show-error --output %p \"{}/wal/{}/%f\"'\n\
+         recovery_target_action = 'promote'\n",
+        base_backup.base_backup_id,
+        wal_url.trim_end_matches('/'),
+        project
+    );
+    if let Some(target_time) = target_time {
+        recovery_conf.push_str(&format!(
+            "recovery_target_time = '{}'\n",
+            target_time.to_rfc3339()
+        ));
+    }
+
+    let mut auto_conf = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(data_dir.join("postgresql.auto.conf"))?;
+    auto_conf.write_all(recovery_conf.as_bytes())?;
+    std::fs::File::create(data_dir.join("recovery.signal"))?;
+
+    Ok(base_backup)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use ant_library::{db::DatabaseConfig, sd::reader::ServiceDiscovery};
+    use ant_library_test::db::TestDatabase;
+    use tracing_test::traced_test;
+
+    use super::*;
+    use crate::{archive::EncryptedObject, backup_command};
+
+    fn params(config: &DatabaseConfig) -> DatabaseParams {
+        DatabaseParams {
+            host: config.host.clone(),
+            port: config.port,
+            db_name: config.database_name.clone(),
+            username: config.database_user.clone(),
+            password: config.database_password.clone(),
+        }
+    }
+
+    /// A migrated database of `project`, as it would be backed up.
+    async fn source_database(project: &str) -> TestDatabase {
+        let guard = TestDatabase::new(project).await;
+        ant_library::db::database_connection(&guard.config)
+            .await
+            .unwrap();
+        guard
+    }
+
+    /// Dump `source` the way backups are taken, with the scratch instance's pg_dump.
+    fn dump(scratch: &ScratchDatabase, source: &DatabaseParams, sql_path: &Path) {
+        let out = backup_command(source)
+            .program_dir(scratch.pg.settings().binary_dir())
+            .file(sql_path)
+            .build()
+            .output()
+            .unwrap();
+        assert!(
+            out.status.success(),
+            "pg_dump failed: {}",
+            String::from_utf8_lossy(&out.stderr)
+        );
+    }
+
+    fn scratch_root() -> PathBuf {
+        PathBuf::from(env!("CARGO_WORKSPACE_DIR")).join("build")
+    }
+
+    /// An ant-archive client whose service discovery has nothing to resolve.
+    fn unreachable_ant_archive() -> AntArchiveClient {
+        AntArchiveClient::new(Arc::new(ServiceDiscovery::new(1)), "token")
+    }
+
+    async fn storage() -> (AntBackingItUpStorageClient, TestDatabase) {
+        let guard = source_database("ant-backing-it-up-db").await;
+        let db = AntBackingItUpStorageClient::connect(&params(&guard.config))
+            .await
+            .unwrap();
+        (db, guard)
+    }
+
+    #[tokio::test]
+    #[traced_test]
+    async fn restore_of_real_dump_passes_sanity_checks_and_is_recorded() {
+        let source_guard = source_database("ant-data-farm").await;
+        let source = params(&source_guard.config);
+        let (db, _db_guard) = storage().await;
+        let dir = tempfile::tempdir().unwrap();
+        let sql_path = dir.path().join("dump.sql");
+
+        let started_at = Utc::now();
+        let scratch = ScratchDatabase::start(&scratch_root(), &[&source.username, "monitoring"])
+            .await
+            .unwrap();
+        dump(&scratch, &source, &sql_path);
+        scratch.restore(&sql_path).unwrap();
+        let verdict = scratch.verify("ant-data-farm").await;
+
+        assert!(verdict.is_success, "{:?}", verdict.failure_reason);
+        assert!(verdict.ant_count.unwrap() > 0);
+        assert!(verdict.registered_user_count.unwrap() > 0);
+
+        let backup = db
+            .record_backup("ant-data-farm", &source, &EncryptedObject::default())
+            .await
+            .unwrap();
+        db.record_restore_verification(&backup.backup_id, &verdict, &started_at)
+            .await
+            .unwrap();
+
+        let verifications = db
+            .get_restore_verifications(&backup.backup_id)
+            .await
+            .unwrap();
+        assert_eq!(verifications.len(), 1);
+        assert!(verifications[0].is_success);
+        assert_eq!(verifications[0].ant_count, verdict.ant_count);
+        assert_eq!(
+            verifications[0].registered_user_count,
+            verdict.registered_user_count
+        );
+        assert_eq!(verifications[0].failure_reason, None);
+    }
+
+    #[tokio::test]
+    #[traced_test]
+    async fn restore_of_another_projects_dump_fails_sanity_checks() {
+        let (_, source_guard) = storage().await;
+        let source = params(&source_guard.config);
+        let dir = tempfile::tempdir().unwrap();
+        let sql_path = dir.path().join("dump.sql");
+
+        let scratch = ScratchDatabase::start(&scratch_root(), &[&source.username, "monitoring"])
+            .await
+            .unwrap();
+        dump(&scratch, &source, &sql_path);
+        scratch.restore(&sql_path).unwrap();
+        let verdict = scratch.verify("ant-data-farm").await;
+
+        assert!(!verdict.is_success);
+        assert_eq!(verdict.ant_count, None);
+        let reason = verdict.failure_reason.unwrap();
+        assert!(reason.contains("ant not queryable"), "{reason}");
+        assert!(reason.contains("registered_user not queryable"), "{reason}");
+    }
+
+    #[tokio::test]
+    #[traced_test]
+    async fn verify_backup_records_a_failed_download() {
+        let (db, db_guard) = storage().await;
+        let source = params(&db_guard.config);
+        let dir = tempfile::tempdir().unwrap();
+
+        let backup = db
+            .record_backup("ant-data-farm", &source, &EncryptedObject::default())
+            .await
+            .unwrap();
+        let started_at = Utc::now();
+        let verdict = verify_backup(dir.path(), &backup, &source, &unreachable_ant_archive()).await;
+
+        assert!(!verdict.is_success);
+        assert!(verdict
+            .failure_reason
+            .as_ref()
+            .unwrap()
+            .starts_with("download: "));
+
+        db.record_restore_verification(&backup.backup_id, &verdict, &started_at)
+            .await
+            .unwrap();
+        let verifications = db
+            .get_restore_verifications(&backup.backup_id)
+            .await
+            .unwrap();
+        assert_eq!(verifications.len(), 1);
+        assert!(!verifications[0].is_success);
+        assert_eq!(verifications[0].failure_reason, verdict.failure_reason);
+    }
+
+    #[tokio::test]
+    #[traced_test]
+    async fn restore_into_target_refuses_without_confirmation() {
+        let (db, target_guard) = storage().await;
+        let target = params(&target_guard.config);
+        let dir = tempfile::tempdir().unwrap();
+        let backup = db
+            .record_backup("ant-data-farm", &target, &EncryptedObject::default())
+            .await
+            .unwrap();
+
+        for confirmation in [
+            "",
+            "typesofants",
+            &format!("typesofants@{}:{}", target.host, target.port + 1),
+        ] {
+            let err = restore_into_target(
+                dir.path(),
+                &backup,
+                &target,
+                confirmation,
+                &unreachable_ant_archive(),
+            )
+            .await
+            .unwrap_err();
+
+            assert!(err.to_string().starts_with("refusing"), "{err}");
+        }
+
+        // The backup was never downloaded, so nothing was left behind.
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+
+        // With the right confirmation, the guard lets the restore through to the download.
+        let err = restore_into_target(
+            dir.path(),
+            &backup,
+            &target,
+            &format!("typesofants@{}:{}", target.host, target.port),
+            &unreachable_ant_archive(),
+        )
+        .await
+        .unwrap_err();
+        assert!(!err.to_string().starts_with("refusing"), "{err}");
+    }
+
+    #[tokio::test]
+    #[traced_test]
+    async fn point_in_time_recovery_refuses_without_base_backup_or_wal() {
+        let (db, guard) = storage().await;
+        let source = params(&guard.config);
+        let dir = tempfile::tempdir().unwrap();
+        let data_dir = dir.path().join("pitr");
+        let ant_archive = unreachable_ant_archive();
+        let prepare = |target_time: Option<DateTime<Utc>>| {
+            let (db, data_dir, ant_archive) = (&db, &data_dir, &ant_archive);
+            async move {
+                prepare_point_in_time_recovery(
+                    db,
+                    ant_archive,
+                    "ant-data-farm",
+                    target_time.as_ref(),
+                    data_dir,
+                    "http://localhost:3238",
+                )
+                .await
+                .map(|base_backup| base_backup.base_backup_id)
+                .unwrap_err()
+                .to_string()
+            }
+        };
+
+        let err = prepare(None).await;
+        assert!(err.starts_with("no base backup of ant-data-farm"), "{err}");
+
+        let started_at = Utc::now() - chrono::Duration::minutes(5);
+        let base_backup = db
+            .record_base_backup(
+                "ant-data-farm",
+                &source,
+                &EncryptedObject::default(),
+                &started_at,
+            )
+            .await
+            .unwrap();
+        let err = prepare(None).await;
+        assert_eq!(
+            err,
+            format!(
+                "no WAL of ant-data-farm archived since base backup {}",
+                base_backup.base_backup_id
+            )
+        );
+
+        db.record_wal_segment(
+            "ant-data-farm",
+            "000000010000000000000001",
+            "sha256",
+            &EncryptedObject::default(),
+        )
+        .await
+        .unwrap();
+        let err = prepare(Some(Utc::now() + chrono::Duration::hours(1))).await;
+        assert!(
+            err.starts_with("the WAL archive of ant-data-farm ends at"),
+            "{err}"
+        );
+
+        // Nothing was downloaded or unpacked before refusing.
+        assert!(!std::fs::exists(&data_dir).unwrap());
+
+        // With the WAL archive reaching the target, recovery goes on to fetch the base backup.
+        let err = prepare(None).await;
+        assert!(!err.contains("WAL"), "{err}");
+    }
+}
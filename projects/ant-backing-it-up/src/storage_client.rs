@@ -7,6 +7,11 @@ use std::sync::Arc;
 use tokio_postgres::Row;
 use tracing::debug;
 
+use crate::{
+    archive::EncryptedObject,
+    crypto::{self, WrappedKey},
+};
+
 #[derive(Clone)]
 pub struct AntBackingItUpStorageClient {
     db: Pool<PostgresManager>,
@@ -28,13 +33,110 @@ pub struct Backup {
     pub database_host: String,
     pub database_port: u16,
     pub encryption_nonce: Vec<u8>,
-    pub destination_host: String,
-    pub destination_port: u16,
+    pub destination_host: Option<String>,
+    pub destination_port: Option<u16>,
     pub destination_filepath: String,
+    pub encryption_format: Option<String>,
+    pub encryption_key_id: Option<String>,
+    pub wrapped_data_key: Option<Vec<u8>>,
+    pub wrapped_data_key_nonce: Option<Vec<u8>>,
+    pub size_bytes: Option<i64>,
+    pub checksum_sha256: Option<String>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
 
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BaseBackup {
+    pub base_backup_id: String,
+    pub project: String,
+    pub database_host: String,
+    pub database_port: u16,
+    pub archive_key: String,
+    pub size_bytes: i64,
+    pub checksum_sha256: String,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: DateTime<Utc>,
+    #[serde(skip)]
+    pub object: EncryptedObject,
+}
+
+fn row_to_base_backup(row: &Row) -> BaseBackup {
+    BaseBackup {
+        base_backup_id: row.get("base_backup_id"),
+        project: row.get("project"),
+        database_host: row.get("database_host"),
+        database_port: row.get::<_, i32>("database_port") as u16,
+        archive_key: row.get("archive_key"),
+        size_bytes: row.get("size_bytes"),
+        checksum_sha256: row.get("checksum_sha256"),
+        started_at: row.get("started_at"),
+        finished_at: row.get("finished_at"),
+        object: row_to_encrypted_object(row),
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WalSegment {
+    pub wal_segment_id: String,
+    pub project: String,
+    pub segment_name: String,
+    pub plaintext_sha256: String,
+    pub created_at: DateTime<Utc>,
+    #[serde(skip)]
+    pub object: EncryptedObject,
+}
+
+fn row_to_wal_segment(row: &Row) -> WalSegment {
+    WalSegment {
+        wal_segment_id: row.get("wal_segment_id"),
+        project: row.get("project"),
+        segment_name: row.get("segment_name"),
+        plaintext_sha256: row.get("plaintext_sha256"),
+        created_at: row.get("created_at"),
+        object: row_to_encrypted_object(row),
+    }
+}
+
+/// Read the encryption columns shared by the base_backup and wal_segment tables.
+fn row_to_encrypted_object(row: &Row) -> EncryptedObject {
+    EncryptedObject {
+        archive_key: row.get("archive_key"),
+        nonce_prefix: row.get("encryption_nonce"),
+        wrapped_key: WrappedKey {
+            key_id: row.get("encryption_key_id"),
+            nonce: row.get("wrapped_data_key_nonce"),
+            ciphertext: row.get("wrapped_data_key"),
+        },
+        size_bytes: row.get::<_, i64>("size_bytes") as u64,
+        checksum_sha256: row.get("checksum_sha256"),
+    }
+}
+
+impl Backup {
+    /// The ant-archive object of a backup taken by the streaming backup pipeline, or None for
+    /// legacy backups.
+    pub fn encrypted_object(&self) -> Option<EncryptedObject> {
+        if self.encryption_format.as_deref() != Some(crypto::STREAM_FORMAT) {
+            return None;
+        }
+
+        Some(EncryptedObject {
+            archive_key: self.destination_filepath.clone(),
+            nonce_prefix: self.encryption_nonce.clone(),
+            wrapped_key: WrappedKey {
+                key_id: self.encryption_key_id.clone().unwrap_or_default(),
+                nonce: self.wrapped_data_key_nonce.clone().unwrap_or_default(),
+                ciphertext: self.wrapped_data_key.clone().unwrap_or_default(),
+            },
+            size_bytes: self.size_bytes.unwrap_or_default() as u64,
+            checksum_sha256: self.checksum_sha256.clone().unwrap_or_default(),
+        })
+    }
+}
+
 fn row_to_backup(row: &Row) -> Backup {
     Backup {
         backup_id: row.get("backup_id"),
@@ -43,13 +145,47 @@ fn row_to_backup(row: &Row) -> Backup {
         database_port: row.get::<_, i32>("database_port") as u16,
         encryption_nonce: row.get("encryption_nonce"),
         destination_host: row.get("destination_host"),
-        destination_port: row.get::<_, i32>("destination_port") as u16,
+        destination_port: row
+            .get::<_, Option<i32>>("destination_port")
+            .map(|p| p as u16),
         destination_filepath: row.get("destination_filepath"),
+        encryption_format: row.get("encryption_format"),
+        encryption_key_id: row.get("encryption_key_id"),
+        wrapped_data_key: row.get("wrapped_data_key"),
+        wrapped_data_key_nonce: row.get("wrapped_data_key_nonce"),
+        size_bytes: row.get("size_bytes"),
+        checksum_sha256: row.get("checksum_sha256"),
         created_at: row.get("created_at"),
         updated_at: row.get("updated_at"),
     }
 }
 
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RestoreVerification {
+    pub restore_verification_id: String,
+    pub backup_id: String,
+    pub is_success: bool,
+    pub ant_count: Option<i64>,
+    pub registered_user_count: Option<i64>,
+    pub failure_reason: Option<String>,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: DateTime<Utc>,
+}
+
+fn row_to_restore_verification(row: &Row) -> RestoreVerification {
+    RestoreVerification {
+        restore_verification_id: row.get("restore_verification_id"),
+        backup_id: row.get("backup_id"),
+        is_success: row.get("is_success"),
+        ant_count: row.get("ant_count"),
+        registered_user_count: row.get("registered_user_count"),
+        failure_reason: row.get("failure_reason"),
+        started_at: row.get("started_at"),
+        finished_at: row.get("finished_at"),
+    }
+}
+
 impl AntBackingItUpStorageClient {
     pub async fn connect(params: &DatabaseParams) -> Result<Self, anyhow::Error> {
         debug!(
@@ -107,6 +243,12 @@ impl AntBackingItUpStorageClient {
         destination_host,
         destination_port,
         destination_filepath,
+        encryption_format,
+        encryption_key_id,
+        wrapped_data_key,
+        wrapped_data_key_nonce,
+        size_bytes,
+        checksum_sha256,
         created_at,
         updated_at
       from backup
@@ -120,6 +262,39 @@ impl AntBackingItUpStorageClient {
         Ok(row.map(|row| row_to_backup(&row)))
     }
 
+    pub async fn get_backup(&self, backup_id: &str) -> Result<Option<Backup>, anyhow::Error> {
+        let row = self
+            .db
+            .get()
+            .await?
+            .query_opt(
+                "
+      select
+        backup_id,
+        project,
+        database_host,
+        database_port,
+        encryption_nonce,
+        destination_host,
+        destination_port,
+        destination_filepath,
+        encryption_format,
+        encryption_key_id,
+        wrapped_data_key,
+        wrapped_data_key_nonce,
+        size_bytes,
+        checksum_sha256,
+        created_at,
+        updated_at
+      from backup
+      where backup_id = $1 and deleted_at is null",
+                &[&backup_id],
+            )
+            .await?;
+
+        Ok(row.map(|row| row_to_backup(&row)))
+    }
+
     pub async fn get_all_backups(&self) -> Result<Vec<Backup>, anyhow::Error> {
         let rows = self
             .db
@@ -136,6 +311,12 @@ impl AntBackingItUpStorageClient {
         destination_host,
         destination_port,
         destination_filepath,
+        encryption_format,
+        encryption_key_id,
+        wrapped_data_key,
+        wrapped_data_key_nonce,
+        size_bytes,
+        checksum_sha256,
         created_at,
         updated_at
       from backup
@@ -151,35 +332,563 @@ impl AntBackingItUpStorageClient {
     }
 
     pub async fn record_backup(
-        &mut self,
+        &self,
         project: &str,
         source: &DatabaseParams,
-        encryption_nonce: &Option<Vec<u8>>,
-        destination_filepath: &str,
-    ) -> Result<DateTime<Utc>, anyhow::Error> {
-        let created_at: DateTime<Utc> = self
+        object: &EncryptedObject,
+    ) -> Result<Backup, anyhow::Error> {
+        let row = self
             .db
             .get()
             .await?
             .query_one(
                 "
                 insert into backup
-                  (project, database_host, database_port, encryption_nonce, destination_filepath)
+                  (
+                    project,
+                    database_host,
+                    database_port,
+                    encryption_nonce,
+                    destination_filepath,
+                    encryption_format,
+                    encryption_key_id,
+                    wrapped_data_key,
+                    wrapped_data_key_nonce,
+                    size_bytes,
+                    checksum_sha256
+                  )
                 values
-                  ($1, $2, $3, $4, $5, $6, $7)
-                returning created_at
+                  ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+                returning
+                  backup_id,
+                  project,
+                  database_host,
+                  database_port,
+                  encryption_nonce,
+                  destination_host,
+                  destination_port,
+                  destination_filepath,
+                  encryption_format,
+                  encryption_key_id,
+                  wrapped_data_key,
+                  wrapped_data_key_nonce,
+                  size_bytes,
+                  checksum_sha256,
+                  created_at,
+                  updated_at
               ",
                 &[
                     &project,
                     &source.host,
                     &(source.port as i32),
+                    &object.nonce_prefix,
+                    &object.archive_key,
+                    &crypto::STREAM_FORMAT,
+                    &object.wrapped_key.key_id,
+                    &object.wrapped_key.ciphertext,
+                    &object.wrapped_key.nonce,
+                    &(object.size_bytes as i64),
+                    &object.checksum_sha256,
+                ],
+            )
+            .await?;
+
+        Ok(row_to_backup(&row))
+    }
+
+    pub async fn record_restore_verification(
+        &self,
+        backup_id: &str,
+        verdict: &crate::restore::RestoreVerdict,
+        started_at: &DateTime<Utc>,
+    ) -> Result<RestoreVerification, anyhow::Error> {
+        let row = self
+            .db
+            .get()
+            .await?
+            .query_one(
+                "
+                insert into restore_verification
+                  (backup_id, is_success, ant_count, registered_user_count, failure_reason, started_at)
+                values
+                  ($1::text, $2, $3, $4, $5, $6)
+                returning
+                  restore_verification_id,
+                  backup_id,
+                  is_success,
+                  ant_count,
+                  registered_user_count,
+                  failure_reason,
+                  started_at,
+                  finished_at
+              ",
+                &[
+                    &backup_id,
+                    &verdict.is_success,
+                    &verdict.ant_count,
+                    &verdict.registered_user_count,
+                    &verdict.failure_reason,
+                    started_at,
+                ],
+            )
+            .await?;
+
+        Ok(row_to_restore_verification(&row))
+    }
+
+    pub async fn get_restore_verifications(
+        &self,
+        backup_id: &str,
+    ) -> Result<Vec<RestoreVerification>, anyhow::Error> {
+        let rows = self
+            .db
+            .get()
+            .await?
+            .query(
+                "
+      select
+        restore_verification_id,
+        backup_id,
+        is_success,
+        ant_count,
+        registered_user_count,
+        failure_reason,
+        started_at,
+        finished_at
+      from restore_verification
+      where backup_id = $1
+      order by finished_at desc
+      ",
+                &[&backup_id],
+            )
+            .await?
+            .into_iter()
+            .map(|row| row_to_restore_verification(&row))
+            .collect();
+
+        Ok(rows)
+    }
+
+    pub async fn record_base_backup(
+        &self,
+        project: &str,
+        source: &DatabaseParams,
+        object: &EncryptedObject,
+        started_at: &DateTime<Utc>,
+    ) -> Result<BaseBackup, anyhow::Error> {
+        let row = self
+            .db
+            .get()
+            .await?
+            .query_one(
+                "
+                insert into base_backup
+                  (
+                    project,
+                    database_host,
+                    database_port,
+                    archive_key,
                     encryption_nonce,
-                    &destination_filepath,
+                    encryption_key_id,
+                    wrapped_data_key,
+                    wrapped_data_key_nonce,
+                    size_bytes,
+                    checksum_sha256,
+                    started_at
+                  )
+                values
+                  ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+                returning *
+              ",
+                &[
+                    &project,
+                    &source.host,
+                    &(source.port as i32),
+                    &object.archive_key,
+                    &object.nonce_prefix,
+                    &object.wrapped_key.key_id,
+                    &object.wrapped_key.ciphertext,
+                    &object.wrapped_key.nonce,
+                    &(object.size_bytes as i64),
+                    &object.checksum_sha256,
+                    started_at,
+                ],
+            )
+            .await?;
+
+        Ok(row_to_base_backup(&row))
+    }
+
+    /// The latest base backup that finished before `target`, the starting point for recovering to
+    /// `target`. Without a target, the latest base backup.
+    pub async fn get_base_backup_for_recovery(
+        &self,
+        project: &str,
+        target: Option<&DateTime<Utc>>,
+    ) -> Result<Option<BaseBackup>, anyhow::Error> {
+        let row = self
+            .db
+            .get()
+            .await?
+            .query_opt(
+                "
+      select *
+      from base_backup
+      where
+        project = $1
+        and deleted_at is null
+        and ($2::timestamptz is null or finished_at <= $2)
+      order by finished_at desc
+      limit 1",
+                &[&project, &target],
+            )
+            .await?;
+
+        Ok(row.map(|row| row_to_base_backup(&row)))
+    }
+
+    pub async fn get_base_backups(&self, project: &str) -> Result<Vec<BaseBackup>, anyhow::Error> {
+        let rows = self
+            .db
+            .get()
+            .await?
+            .query(
+                "
+      select *
+      from base_backup
+      where project = $1 and deleted_at is null
+      order by finished_at desc
+      ",
+                &[&project],
+            )
+            .await?
+            .into_iter()
+            .map(|row| row_to_base_backup(&row))
+            .collect();
+
+        Ok(rows)
+    }
+
+    pub async fn record_wal_segment(
+        &self,
+        project: &str,
+        segment_name: &str,
+        plaintext_sha256: &str,
+        object: &EncryptedObject,
+    ) -> Result<WalSegment, anyhow::Error> {
+        let row = self
+            .db
+            .get()
+            .await?
+            .query_one(
+                "
+                insert into wal_segment
+                  (
+                    project,
+                    segment_name,
+                    plaintext_sha256,
+                    archive_key,
+                    encryption_nonce,
+                    encryption_key_id,
+                    wrapped_data_key,
+                    wrapped_data_key_nonce,
+                    size_bytes,
+                    checksum_sha256
+                  )
+                values
+                  ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+                returning *
+              ",
+                &[
+                    &project,
+                    &segment_name,
+                    &plaintext_sha256,
+                    &object.archive_key,
+                    &object.nonce_prefix,
+                    &object.wrapped_key.key_id,
+                    &object.wrapped_key.ciphertext,
+                    &object.wrapped_key.nonce,
+                    &(object.size_bytes as i64),
+                    &object.checksum_sha256,
                 ],
             )
+            .await?;
+
+        Ok(row_to_wal_segment(&row))
+    }
+
+    /// The WAL segments that recovering from `base_backup` to `target` replays, in WAL order: every
+    /// segment archived since the base backup started, up to the first one archived after
+    /// `target`, which holds it. Without a target, every segment since the base backup. Timeline
+    /// history files are left out, restore_command fetches those by name.
+    pub async fn get_wal_segments_for_recovery(
+        &self,
+        project: &str,
+        base_backup: &BaseBackup,
+        target: Option<&DateTime<Utc>>,
+    ) -> Result<Vec<WalSegment>, anyhow::Error> {
+        let rows = self
+            .db
+            .get()
             .await?
-            .get("created_at");
+            .query(
+                "
+      select *
+      from wal_segment
+      where
+        project = $1
+        and segment_name not like '%.%'
+        and created_at >= $2
+        and created_at <= coalesce(
+          (
+            select min(created_at)
+            from wal_segment
+            where project = $1 and segment_name not like '%.%' and created_at >= $3
+          ),
+          'infinity'
+        )
+      order by segment_name",
+                &[&project, &base_backup.started_at, &target],
+            )
+            .await?
+            .into_iter()
+            .map(|row| row_to_wal_segment(&row))
+            .collect();
+
+        Ok(rows)
+    }
+
+    pub async fn get_wal_segment(
+        &self,
+        project: &str,
+        segment_name: &str,
+    ) -> Result<Option<WalSegment>, anyhow::Error> {
+        let row = self
+            .db
+            .get()
+            .await?
+            .query_opt(
+                "
+      select *
+      from wal_segment
+      where project = $1 and segment_name = $2",
+                &[&project, &segment_name],
+            )
+            .await?;
 
-        Ok(created_at)
+        Ok(row.map(|row| row_to_wal_segment(&row)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ant_library_test::db::TestDatabase;
+    use chrono::Duration;
+    use tracing_test::traced_test;
+
+    use super::*;
+
+    struct Fixture {
+        db: AntBackingItUpStorageClient,
+        source: DatabaseParams,
+        _guard: TestDatabase,
+    }
+
+    impl Fixture {
+        async fn new() -> Self {
+            let guard = TestDatabase::new("ant-backing-it-up-db").await;
+            ant_library::db::database_connection(&guard.config)
+                .await
+                .unwrap();
+            let source = DatabaseParams {
+                host: guard.config.host.clone(),
+                port: guard.config.port,
+                db_name: guard.config.database_name.clone(),
+                username: guard.config.database_user.clone(),
+                password: guard.config.database_password.clone(),
+            };
+            let db = AntBackingItUpStorageClient::connect(&source).await.unwrap();
+
+            Fixture {
+                db,
+                source,
+                _guard: guard,
+            }
+        }
+
+        async fn base_backup(
+            &self,
+            started_at: DateTime<Utc>,
+            finished_at: DateTime<Utc>,
+        ) -> BaseBackup {
+            let base_backup = self
+                .db
+                .record_base_backup(
+                    "ant-data-farm",
+                    &self.source,
+                    &EncryptedObject::default(),
+                    &started_at,
+                )
+                .await
+                .unwrap();
+            self.db
+                .db
+                .get()
+                .await
+                .unwrap()
+                .execute(
+                    "update base_backup set finished_at = $1 where base_backup_id = $2::text",
+                    &[&finished_at, &base_backup.base_backup_id],
+                )
+                .await
+                .unwrap();
+
+            BaseBackup {
+                finished_at,
+                ..base_backup
+            }
+        }
+
+        async fn wal_segment(&self, segment_name: &str, archived_at: DateTime<Utc>) {
+            self.db
+                .record_wal_segment(
+                    "ant-data-farm",
+                    segment_name,
+                    "sha256",
+                    &EncryptedObject::default(),
+                )
+                .await
+                .unwrap();
+            self.db
+                .db
+                .get()
+                .await
+                .unwrap()
+                .execute(
+                    "update wal_segment set created_at = $1 where segment_name = $2",
+                    &[&archived_at, &segment_name],
+                )
+                .await
+                .unwrap();
+        }
+
+        async fn chosen(&self, target: Option<DateTime<Utc>>) -> Option<String> {
+            self.db
+                .get_base_backup_for_recovery("ant-data-farm", target.as_ref())
+                .await
+                .unwrap()
+                .map(|base_backup| base_backup.base_backup_id)
+        }
+
+        async fn segment_names(
+            &self,
+            base_backup: &BaseBackup,
+            target: Option<&DateTime<Utc>>,
+        ) -> Vec<String> {
+            self.db
+                .get_wal_segments_for_recovery("ant-data-farm", base_backup, target)
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|segment| segment.segment_name)
+                .collect()
+        }
+    }
+
+    #[tokio::test]
+    #[traced_test]
+    async fn base_backup_for_recovery_is_latest_finished_before_target() {
+        let fixture = Fixture::new().await;
+        let now = Utc::now();
+        let oldest = fixture
+            .base_backup(now - Duration::hours(4), now - Duration::hours(3))
+            .await;
+        let middle = fixture
+            .base_backup(now - Duration::hours(3), now - Duration::hours(2))
+            .await;
+        let latest = fixture
+            .base_backup(now - Duration::hours(2), now - Duration::hours(1))
+            .await;
+
+        assert_eq!(
+            fixture.chosen(None).await,
+            Some(latest.base_backup_id.clone())
+        );
+        assert_eq!(
+            fixture.chosen(Some(now)).await,
+            Some(latest.base_backup_id.clone())
+        );
+        assert_eq!(
+            fixture.chosen(Some(now - Duration::minutes(90))).await,
+            Some(middle.base_backup_id.clone())
+        );
+        // A base backup that was still running at the target can't recover to it.
+        assert_eq!(
+            fixture.chosen(Some(now - Duration::minutes(150))).await,
+            Some(oldest.base_backup_id.clone())
+        );
+        assert_eq!(fixture.chosen(Some(now - Duration::hours(5))).await, None);
+    }
+
+    #[tokio::test]
+    #[traced_test]
+    async fn wal_segments_for_recovery_are_in_wal_order_from_base_backup_to_target() {
+        let fixture = Fixture::new().await;
+        let now = Utc::now();
+        let minutes_ago = |minutes: i64| now - Duration::minutes(minutes);
+        let base_backup = fixture.base_backup(minutes_ago(60), minutes_ago(55)).await;
+
+        // Already archived when the base backup started, so not needed to recover from it.
+        fixture
+            .wal_segment("000000010000000000000009", minutes_ago(61))
+            .await;
+        // Archived out of order, e.g. after archive_command retries.
+        fixture
+            .wal_segment("00000001000000000000000B", minutes_ago(41))
+            .await;
+        fixture
+            .wal_segment("00000001000000000000000A", minutes_ago(40))
+            .await;
+        fixture
+            .wal_segment("000000010000000000000010", minutes_ago(20))
+            .await;
+        fixture
+            .wal_segment("00000002.history", minutes_ago(30))
+            .await;
+        fixture
+            .wal_segment("000000020000000000000011", minutes_ago(10))
+            .await;
+
+        assert_eq!(
+            fixture.segment_names(&base_backup, None).await,
+            vec![
+                "00000001000000000000000A",
+                "00000001000000000000000B",
+                "000000010000000000000010",
+                "000000020000000000000011",
+            ]
+        );
+        // The segment archived after the target holds it, later ones aren't needed.
+        assert_eq!(
+            fixture
+                .segment_names(&base_backup, Some(&minutes_ago(30)))
+                .await,
+            vec![
+                "00000001000000000000000A",
+                "00000001000000000000000B",
+                "000000010000000000000010",
+            ]
+        );
+        assert_eq!(
+            fixture
+                .segment_names(&base_backup, Some(&minutes_ago(40)))
+                .await,
+            vec!["00000001000000000000000A", "00000001000000000000000B"]
+        );
+        // Past the end of the archive, every segment is replayed and recovery stops short.
+        assert_eq!(
+            fixture.segment_names(&base_backup, Some(&now)).await.len(),
+            4
+        );
     }
 }
@@ -0,0 +1,310 @@
+//! Continuous WAL archiving, for point-in-time recovery.
+//!
+//! Each database's `archive_command` PUTs its WAL segments here, and its `restore_command` GETs
+//! them back during recovery:
+//! ```bash
+//! archive_command = 'curl --fail --silent --show-error --upload-file %p "$ANT_BACKING_IT_UP_URL/wal/ant-data-farm/%f"'
+//! restore_command = 'curl --fail --silent --show-error --output %p "$ANT_BACKING_IT_UP_URL/wal/ant-data-farm/%f"'
+//! ```
+//! Recovery starts from a physical base backup, see `POST /base-backup`.
+
+use std::io::Cursor;
+
+use ant_library::routes::Routes;
+use axum::{
+    body::Bytes,
+    extract::{DefaultBodyLimit, Path, State},
+    response::IntoResponse,
+    routing::{get, post, put},
+    Json,
+};
+use chrono::{Datelike, Timelike};
+use http::StatusCode;
+use postgresql_commands::{
+    pg_basebackup::PgBaseBackupBuilder, traits::CommandToString, CommandBuilder,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::{error, info, warn};
+
+use crate::{
+    archive, source_database_params,
+    state::AntBackingItUpState,
+    storage_client::{BaseBackup, DatabaseParams},
+    SOURCE_PROJECTS,
+};
+
+/// WAL segments are 16MiB by default, leave room for larger configured segment sizes.
+const WAL_SEGMENT_BODY_LIMIT: usize = 64 * 1024 * 1024;
+
+/// Segment names are the %f of archive_command: hex WAL segment names, plus the occasional
+/// `.history`, `.partial` or `.backup` suffix.
+fn is_valid_segment_name(segment_name: &str) -> bool {
+    !segment_name.is_empty()
+        && segment_name.len() <= 64
+        && segment_name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '.')
+        && !segment_name.starts_with('.')
+}
+
+/// Archive a single WAL segment. Archiving the same segment twice succeeds if the content is
+/// identical, as Postgres retries archive_command after crashes, and fails otherwise so that a
+/// diverged timeline never silently overwrites an archived one.
+async fn put_wal_segment(
+    State(AntBackingItUpState {
+        db, ant_archive, ..
+    }): State<AntBackingItUpState>,
+    Path((project, segment_name)): Path<(String, String)>,
+    body: Bytes,
+) -> Result<impl IntoResponse, StatusCode> {
+    if !SOURCE_PROJECTS.contains(&project.as_str()) || !is_valid_segment_name(&segment_name) {
+        warn!("ANT-ERR-149: Refusing WAL segment {project}/{segment_name}");
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let plaintext_sha256 = hex::encode(Sha256::digest(&body));
+
+    let existing = db
+        .get_wal_segment(&project, &segment_name)
+        .await
+        .map_err(|e| {
+            error!("ANT-ERR-150: db: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    if let Some(existing) = existing {
+        if existing.plaintext_sha256 == plaintext_sha256 {
+            info!("WAL segment {project}/{segment_name} already archived");
+            return Ok(StatusCode::OK);
+        }
+
+        error!(
+            "ANT-ERR-151: WAL segment {project}/{segment_name} already archived with different content"
+        );
+        return Err(StatusCode::CONFLICT);
+    }
+
+    let archive_key = format!("ant-backing-it-up/wal/{project}/{segment_name}.zst.enc");
+    let object = archive::upload_encrypted(&ant_archive, &archive_key, Cursor::new(body))
+        .await
+        .map_err(|e| {
+            error!("ANT-ERR-152: WAL segment {project}/{segment_name} upload failed: {e:#}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    db.record_wal_segment(&project, &segment_name, &plaintext_sha256, &object)
+        .await
+        .map_err(|e| {
+            error!("ANT-ERR-153: db: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    info!("Archived WAL segment {project}/{segment_name}");
+    return Ok(StatusCode::CREATED);
+}
+
+/// Fetch a WAL segment back, for restore_command. A missing segment is a 404, which
+/// restore_command reports as "not archived", the normal end of recovery.
+async fn get_wal_segment(
+    State(AntBackingItUpState {
+        db, ant_archive, ..
+    }): State<AntBackingItUpState>,
+    Path((project, segment_name)): Path<(String, String)>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let segment = db
+        .get_wal_segment(&project, &segment_name)
+        .await
+        .map_err(|e| {
+            error!("ANT-ERR-154: db: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let plaintext = archive::download_decrypted(&ant_archive, &segment.object)
+        .await
+        .map_err(|e| {
+            error!("ANT-ERR-155: WAL segment {project}/{segment_name} download failed: {e:#}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    if hex::encode(Sha256::digest(&plaintext)) != segment.plaintext_sha256 {
+        error!("ANT-ERR-156: WAL segment {project}/{segment_name} checksum mismatch");
+        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    return Ok((StatusCode::OK, plaintext));
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BaseBackupRequest {
+    /// The project, e.g. 'ant-data-farm'.
+    pub project: String,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BaseBackupResponse {
+    pub base_backup: BaseBackup,
+}
+
+/// The pg_basebackup invocation for a project's database, as a single tar on stdout. WAL is not
+/// included, recovery replays it from the WAL archive.
+pub fn base_backup_command(params: &DatabaseParams) -> std::process::Command {
+    PgBaseBackupBuilder::new()
+        .pgdata("-")
+        .format("tar")
+        .wal_method("none")
+        .checkpoint("fast")
+        .label("ant-backing-it-up")
+        .username(&params.username)
+        .pg_password(&params.password)
+        .host(&params.host)
+        .port(params.port)
+        .build()
+}
+
+async fn post_base_backup(
+    State(AntBackingItUpState {
+        db, ant_archive, ..
+    }): State<AntBackingItUpState>,
+    Json(req): Json<BaseBackupRequest>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let params = source_database_params(&req.project)
+        .map_err(|e| {
+            error!("ANT-ERR-206: {} database: {e:#}", req.project);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or_else(|| {
+            warn!("ANT-ERR-157: Unsupported project {}", req.project);
+            StatusCode::BAD_REQUEST
+        })?;
+
+    let started_at = chrono::Utc::now();
+    let archive_key = format!(
+        "ant-backing-it-up/base-backups/{}/basebackup.{}-{}-{}.{}-{}-{}.tar.zst.enc",
+        req.project,
+        started_at.year(),
+        started_at.month(),
+        started_at.day(),
+        started_at.hour(),
+        started_at.minute(),
+        started_at.second()
+    );
+
+    let cmd = base_backup_command(&params);
+    info!("Executing base backup: {}", cmd.to_command_string());
+
+    let object = archive::upload_command_output(&ant_archive, &archive_key, cmd)
+        .await
+        .map_err(|e| {
+            error!("ANT-ERR-158: pg_basebackup failed: {e:#}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    let base_backup = db
+        .record_base_backup(&req.project, &params, &object, &started_at)
+        .await
+        .map_err(|e| {
+            error!("ANT-ERR-159: db: {e}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    info!(
+        "Base backup {} of {} successful.",
+        base_backup.base_backup_id, req.project
+    );
+
+    return Ok((StatusCode::OK, Json(BaseBackupResponse { base_backup })));
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListBaseBackupsResponse {
+    pub base_backups: Vec<BaseBackup>,
+}
+
+async fn list_base_backups(
+    State(AntBackingItUpState { db, .. }): State<AntBackingItUpState>,
+    Path(project): Path<String>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let base_backups = db.get_base_backups(&project).await.map_err(|e| {
+        error!("ANT-ERR-160: db: {e}");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    return Ok((
+        StatusCode::OK,
+        Json(ListBaseBackupsResponse { base_backups }),
+    ));
+}
+
+pub fn routes() -> Routes<AntBackingItUpState> {
+    Routes::new()
+        .put(
+            "/wal/{project}/{segment_name}",
+            put(put_wal_segment).layer(DefaultBodyLimit::max(WAL_SEGMENT_BODY_LIMIT)),
+        )
+        .get("/wal/{project}/{segment_name}", get(get_wal_segment))
+        .post("/base-backup", post(post_base_backup))
+        .get("/base-backups/{project}", get(list_base_backups))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn segment_names_from_archive_command_are_valid() {
+        for segment_name in [
+            "000000010000000000000001",
+            "0000000A00000003000000FF",
+            "00000002.history",
+            "000000010000000000000004.partial",
+            "000000010000000000000002.00000028.backup",
+        ] {
+            assert!(is_valid_segment_name(segment_name), "{segment_name}");
+        }
+    }
+
+    #[test]
+    fn segment_names_that_escape_the_archive_are_invalid() {
+        for segment_name in [
+            "",
+            ".history",
+            "..",
+            "../000000010000000000000001",
+            "wal/000000010000000000000001",
+            "00000001 0000000000000001",
+            &"0".repeat(65),
+        ] {
+            assert!(!is_valid_segment_name(segment_name), "{segment_name}");
+        }
+    }
+
+    #[test]
+    fn segment_names_sort_in_wal_order() {
+        // Timeline, then log, then segment, all fixed-width uppercase hex, so that recovery can
+        // replay segments in name order.
+        let mut segment_names = vec![
+            "000000020000000000000001",
+            "0000000100000001000000A0",
+            "00000001000000000000000A",
+            "000000010000000000000009",
+            "0000000100000000000000FF",
+        ];
+        segment_names.sort();
+
+        assert_eq!(
+            segment_names,
+            vec![
+                "000000010000000000000009",
+                "00000001000000000000000A",
+                "0000000100000000000000FF",
+                "0000000100000001000000A0",
+                "000000020000000000000001",
+            ]
+        );
+        assert!(segment_names.iter().all(|name| is_valid_segment_name(name)));
+    }
+}
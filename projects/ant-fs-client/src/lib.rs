@@ -1,10 +1,15 @@
 use std::sync::Arc;
 
 use ant_library::sd::reader::ServiceDiscovery;
-use reqwest::{Client, StatusCode};
+use chrono::{DateTime, Utc};
+use reqwest::{Client, StatusCode, header::HeaderMap};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use tracing::error;
 
+/// The header carrying a file's hex SHA-256, on uploads and HEAD requests.
+const SHA256_HEADER: &str = "x-ant-fs-sha256";
+
 pub type AntFsHostPorts = Vec<AntFsHostPort>;
 
 #[derive(Serialize, Deserialize)]
@@ -13,6 +18,43 @@ pub struct AntFsHostPort {
     pub tls: bool,
 }
 
+/// A file as recorded in the user's index on ant-fs.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct FileMetadata {
+    pub path: String,
+    pub size_bytes: u64,
+    pub sha256: String,
+    pub created_at: DateTime<Utc>,
+    pub modified_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListFilesResponse {
+    pub files: Vec<FileMetadata>,
+    pub used_bytes: u64,
+    pub quota_bytes: Option<u64>,
+}
+
+fn metadata_from_headers(path: &str, headers: &HeaderMap) -> Result<FileMetadata, anyhow::Error> {
+    let header = |name: &str| -> Result<&str, anyhow::Error> {
+        headers
+            .get(name)
+            .ok_or_else(|| anyhow::Error::msg(format!("Missing {name} header")))?
+            .to_str()
+            .map_err(|e| e.into())
+    };
+
+    Ok(FileMetadata {
+        path: path.trim_start_matches('/').to_string(),
+        size_bytes: header("content-length")?.parse()?,
+        sha256: header(SHA256_HEADER)?.to_string(),
+        created_at: DateTime::parse_from_rfc3339(header("x-ant-fs-created-at")?)?.to_utc(),
+        modified_at: DateTime::parse_from_rfc3339(header("x-ant-fs-modified-at")?)?.to_utc(),
+    })
+}
+
 #[derive(Clone)]
 pub struct AntFsClient {
     sd: Option<Arc<ServiceDiscovery>>,
@@ -104,22 +146,41 @@ impl AntFsClient {
         }
     }
 
+    /// Upload a file. The server verifies the SHA-256 of what it received before replacing the
+    /// previous version, and the digest it stored is checked again here.
     pub async fn put_file(&mut self, path: &str, bytes: bytes::Bytes) -> Result<(), anyhow::Error> {
+        let sha256 = base16ct::lower::encode_string(&Sha256::digest(&bytes));
+
         let response = self
             .client
             .put(self.url(path).await?)
             .basic_auth(self.username.clone(), Some(self.password.clone()))
+            .header(SHA256_HEADER, &sha256)
             .body(bytes)
             .send()
             .await?;
 
-        match response.error_for_status() {
-            Ok(_res) => Ok(()),
+        let response = match response.error_for_status() {
+            Ok(res) => res,
             Err(e) => {
                 error!("ANT-ERR-016: Failed to put ant-fs file {e}");
-                Err(e.into())
+                return Err(e.into());
             }
+        };
+
+        let stored = response
+            .headers()
+            .get(SHA256_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or_default();
+        if stored != sha256 {
+            error!("ANT-ERR-174: ant-fs stored {path} with SHA-256 {stored}, expected {sha256}");
+            return Err(anyhow::Error::msg(format!(
+                "ant-fs stored {path} with SHA-256 {stored}, expected {sha256}"
+            )));
         }
+
+        Ok(())
     }
 
     pub async fn get_file(&self, path: &str) -> Result<Option<Vec<u8>>, anyhow::Error> {
@@ -140,4 +201,43 @@ impl AntFsClient {
             }
         };
     }
+
+    /// The metadata of a single file, or None if it does not exist.
+    pub async fn stat(&self, path: &str) -> Result<Option<FileMetadata>, anyhow::Error> {
+        let response = self
+            .client
+            .head(self.url(path).await?)
+            .basic_auth(self.username.clone(), Some(self.password.clone()))
+            .send()
+            .await?;
+
+        match response.status() {
+            StatusCode::OK => return Ok(Some(metadata_from_headers(path, response.headers())?)),
+            StatusCode::NOT_FOUND => return Ok(None),
+            _ => {
+                let e = response.error_for_status().unwrap_err();
+                error!("ANT-ERR-171: Failed to stat ant-fs file {e}");
+                return Err(e.into());
+            }
+        };
+    }
+
+    /// List the files whose path starts with `prefix`, an empty prefix lists everything.
+    pub async fn list(&self, prefix: &str) -> Result<ListFilesResponse, anyhow::Error> {
+        let response = self
+            .client
+            .get(self.url("").await?)
+            .query(&[("prefix", prefix)])
+            .basic_auth(self.username.clone(), Some(self.password.clone()))
+            .send()
+            .await?;
+
+        match response.error_for_status() {
+            Ok(res) => Ok(res.json::<ListFilesResponse>().await?),
+            Err(e) => {
+                error!("ANT-ERR-172: Failed to list ant-fs files {e}");
+                Err(e.into())
+            }
+        }
+    }
 }
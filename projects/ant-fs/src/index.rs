@@ -0,0 +1,89 @@
+//! The per-user sidecar index. Files are stored under the hash of their path, so the index is the
+//! only record of the original path, and of the size, checksum and timestamps of each file.
+//!
+//! It lives at `<root>/<username>/index.json`, next to the hashed files and the `tmp` directory of
+//! in-flight uploads. Hashed filenames are hex, so they never collide with either.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct FileMetadata {
+    /// The path the file was uploaded under, without the leading slash.
+    pub path: String,
+    pub size_bytes: u64,
+    /// Hex SHA-256 of the file contents.
+    pub sha256: String,
+    pub created_at: DateTime<Utc>,
+    pub modified_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct UserIndex {
+    /// Keyed by path.
+    pub files: BTreeMap<String, FileMetadata>,
+}
+
+impl UserIndex {
+    fn index_path(root: &Path, username: &str) -> PathBuf {
+        root.join(username).join("index.json")
+    }
+
+    /// Load a user's index. A user that never uploaded anything has an empty index.
+    pub async fn load(root: &Path, username: &str) -> Result<UserIndex, anyhow::Error> {
+        let path = Self::index_path(root, username);
+        let content = match tokio::fs::read(&path).await {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(UserIndex::default()),
+            Err(e) => return Err(e).context(format!("read {}", path.display())),
+        };
+
+        serde_json::from_slice(&content).with_context(|| format!("parse {}", path.display()))
+    }
+
+    /// Write the index to a temporary file then rename it over the old one, so that a crash never
+    /// leaves a truncated index behind.
+    pub async fn save(&self, root: &Path, username: &str) -> Result<(), anyhow::Error> {
+        let path = Self::index_path(root, username);
+        let tmp = path.with_extension("json.tmp");
+
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .with_context(|| format!("create {}", parent.display()))?;
+        }
+
+        tokio::fs::write(&tmp, serde_json::to_vec(self)?)
+            .await
+            .with_context(|| format!("write {}", tmp.display()))?;
+        tokio::fs::rename(&tmp, &path)
+            .await
+            .with_context(|| format!("rename {}", tmp.display()))?;
+
+        Ok(())
+    }
+
+    /// Total bytes stored, not counting `except_path`, the file about to be replaced.
+    pub fn used_bytes(&self, except_path: &str) -> u64 {
+        self.files
+            .values()
+            .filter(|f| f.path != except_path)
+            .map(|f| f.size_bytes)
+            .sum()
+    }
+
+    pub fn list(&self, prefix: &str) -> Vec<FileMetadata> {
+        self.files
+            .range(prefix.to_string()..)
+            .take_while(|(path, _)| path.starts_with(prefix))
+            .map(|(_, f)| f.clone())
+            .collect()
+    }
+}
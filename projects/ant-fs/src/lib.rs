@@ -1,11 +1,11 @@
 use ant_library::routes::Routes;
 use axum::{
     body::Body,
-    extract::{DefaultBodyLimit, Path, State},
-    http::StatusCode,
+    extract::{DefaultBodyLimit, Path, Query, State},
+    http::{HeaderMap, HeaderName, HeaderValue, StatusCode},
     response::IntoResponse,
-    routing::{get, post, put},
-    Router,
+    routing::{get, head, post, put},
+    Json, Router,
 };
 use axum_extra::{
     headers::{authorization::Basic, Authorization},
@@ -14,15 +14,45 @@ use axum_extra::{
 use base64ct::{Base64, Encoding};
 use futures::StreamExt;
 use http::{header, Method};
+use index::{FileMetadata, UserIndex};
+use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
-use std::{io::ErrorKind, path::PathBuf};
-use tokio::io::AsyncWriteExt;
+use std::{io::ErrorKind, path::PathBuf, sync::Arc};
+use tempfile::{NamedTempFile, TempPath};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    sync::Mutex,
+};
 use tokio_util::io::ReaderStream;
 use tower::ServiceBuilder;
 use tower_http::{catch_panic::CatchPanicLayer, cors::CorsLayer, limit::RequestBodyLimitLayer};
-use tracing::{debug, error, info};
+use tracing::{debug, error, info, warn};
+
+pub mod index;
+
+#[derive(Clone)]
+struct AntFsState {
+    root: PathBuf,
+
+    /// Serializes reads and writes of the user indexes.
+    index_lock: Arc<Mutex<()>>,
+}
+
+/// An entry of the `ant_fs_users` secret, one per line: `username:base64(sha256(password))`, with
+/// an optional byte quota as a third field, e.g. `ants:...:1073741824`. No quota means unlimited.
+struct AntFsUser {
+    username: String,
+    quota_bytes: Option<u64>,
+}
 
-fn bearer_authorization(auth: &Authorization<Basic>) -> Result<(), (StatusCode, String)> {
+fn internal_error() -> (StatusCode, String) {
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        "Internal server error, please retry.".to_string(),
+    )
+}
+
+fn bearer_authorization(auth: &Authorization<Basic>) -> Result<AntFsUser, (StatusCode, String)> {
     let tokens = ant_library::secret::load_secret("ant_fs_users").map_err(|e| {
         error!("ANT-ERR-018: Failed to read authorized users: {e}");
         (
@@ -31,7 +61,7 @@ fn bearer_authorization(auth: &Authorization<Basic>) -> Result<(), (StatusCode,
         )
     })?;
 
-    if !tokens.trim().split("\n").filter(|&t| t != "").any(|t| {
+    let user = tokens.trim().split("\n").filter(|&t| t != "").find(|t| {
         let segments: Vec<&str> = t.split(":").collect();
         let user = segments[0];
         let pass = segments[1];
@@ -40,11 +70,24 @@ fn bearer_authorization(auth: &Authorization<Basic>) -> Result<(), (StatusCode,
         let pass_attempt = Base64::encode_string(&pass_attempt_hash);
 
         return user == auth.0.username() && pass == pass_attempt;
-    }) {
+    });
+
+    let Some(user) = user else {
         return Err((StatusCode::UNAUTHORIZED, "Access denied.".to_string()));
-    }
+    };
 
-    Ok(())
+    let quota_bytes = match user.split(":").nth(2) {
+        None => None,
+        Some(quota) => Some(quota.trim().parse::<u64>().map_err(|e| {
+            error!("ANT-ERR-162: Invalid quota for {}: {e}", auth.0.username());
+            internal_error()
+        })?),
+    };
+
+    Ok(AntFsUser {
+        username: auth.0.username().to_string(),
+        quota_bytes,
+    })
 }
 
 /// Resolve a request path to its on-disk location: `<root>/<username>/<hash>`,
@@ -60,12 +103,12 @@ fn user_file_path(root: &PathBuf, username: &str, path: &str) -> PathBuf {
 
 async fn download(
     TypedHeader(auth): TypedHeader<Authorization<Basic>>,
-    State(root): State<PathBuf>,
+    State(AntFsState { root, .. }): State<AntFsState>,
     Path(path): Path<String>,
 ) -> Result<impl IntoResponse, (StatusCode, String)> {
-    bearer_authorization(&auth)?;
+    let user = bearer_authorization(&auth)?;
 
-    let full_path = user_file_path(&root, auth.0.username(), &path);
+    let full_path = user_file_path(&root, &user.username, &path);
 
     let file = tokio::fs::File::open(&full_path)
         .await
@@ -76,10 +119,7 @@ async fn download(
             ),
             _ => {
                 error!("ANT-ERR-019: Failed to open file: {err}");
-                (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    "Internal server error, please retry.".to_string(),
-                )
+                internal_error()
             }
         })?;
 
@@ -87,47 +127,297 @@ async fn download(
     Ok(Body::from_stream(ReaderStream::new(file)))
 }
 
+/// The metadata of a file as response headers, for HEAD requests.
+fn metadata_headers(metadata: &FileMetadata) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    let mut insert = |name: &'static str, value: String| {
+        if let Ok(value) = HeaderValue::from_str(&value) {
+            headers.insert(name, value);
+        }
+    };
+
+    insert("content-length", metadata.size_bytes.to_string());
+    insert("etag", format!("\"{}\"", metadata.sha256));
+    insert(
+        "last-modified",
+        metadata
+            .modified_at
+            .format("%a, %d %b %Y %H:%M:%S GMT")
+            .to_string(),
+    );
+    insert(SHA256_HEADER, metadata.sha256.clone());
+    insert("x-ant-fs-created-at", metadata.created_at.to_rfc3339());
+    insert("x-ant-fs-modified-at", metadata.modified_at.to_rfc3339());
+
+    headers
+}
+
+/// Build the index entry of a file that was uploaded before the index existed, from disk.
+async fn metadata_from_disk(
+    full_path: &PathBuf,
+    path: &str,
+) -> Result<Option<FileMetadata>, std::io::Error> {
+    let mut file = match tokio::fs::File::open(full_path).await {
+        Ok(file) => file,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+
+    let fs_metadata = file.metadata().await?;
+    let modified_at: chrono::DateTime<chrono::Utc> = fs_metadata.modified()?.into();
+
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        let n = file.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+
+    Ok(Some(FileMetadata {
+        path: path.to_string(),
+        size_bytes: fs_metadata.len(),
+        sha256: base16ct::lower::encode_string(&hasher.finalize()),
+        created_at: modified_at,
+        modified_at,
+    }))
+}
+
+/// HEAD a file: its metadata as headers, without the contents. Files uploaded before the index
+/// existed are indexed on the first HEAD.
+async fn stat(
+    TypedHeader(auth): TypedHeader<Authorization<Basic>>,
+    State(AntFsState { root, index_lock }): State<AntFsState>,
+    Path(path): Path<String>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let user = bearer_authorization(&auth)?;
+
+    let _guard = index_lock.lock().await;
+    let mut index = UserIndex::load(&root, &user.username).await.map_err(|e| {
+        error!("ANT-ERR-163: Failed to load index: {e:#}");
+        internal_error()
+    })?;
+
+    if let Some(metadata) = index.files.get(&path) {
+        return Ok((StatusCode::OK, metadata_headers(metadata)));
+    }
+
+    let full_path = user_file_path(&root, &user.username, &path);
+    let metadata = metadata_from_disk(&full_path, &path)
+        .await
+        .map_err(|e| {
+            error!("ANT-ERR-164: Failed to read file: {e}");
+            internal_error()
+        })?
+        .ok_or((
+            StatusCode::NOT_FOUND,
+            format!("error: {} does not exist.\n", &path),
+        ))?;
+
+    info!("Indexing {}...", &path);
+    index.files.insert(path.clone(), metadata.clone());
+    index.save(&root, &user.username).await.map_err(|e| {
+        error!("ANT-ERR-165: Failed to save index: {e:#}");
+        internal_error()
+    })?;
+
+    Ok((StatusCode::OK, metadata_headers(&metadata)))
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListFilesQuery {
+    /// Only list paths starting with this, e.g. `backups/2024/`.
+    pub prefix: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListFilesResponse {
+    pub files: Vec<FileMetadata>,
+    pub used_bytes: u64,
+    pub quota_bytes: Option<u64>,
+}
+
+async fn list(
+    TypedHeader(auth): TypedHeader<Authorization<Basic>>,
+    State(AntFsState { root, index_lock }): State<AntFsState>,
+    Query(query): Query<ListFilesQuery>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let user = bearer_authorization(&auth)?;
+
+    let _guard = index_lock.lock().await;
+    let index = UserIndex::load(&root, &user.username).await.map_err(|e| {
+        error!("ANT-ERR-166: Failed to load index: {e:#}");
+        internal_error()
+    })?;
+
+    Ok(Json(ListFilesResponse {
+        files: index.list(query.prefix.as_deref().unwrap_or("")),
+        used_bytes: index.used_bytes(""),
+        quota_bytes: user.quota_bytes,
+    }))
+}
+
+fn quota_exceeded(path: &str) -> (StatusCode, String) {
+    (
+        StatusCode::INSUFFICIENT_STORAGE,
+        format!("error: {} would exceed your quota.\n", path),
+    )
+}
+
+/// The header carrying a file's hex SHA-256: optionally sent with an upload to have it verified
+/// before the file is committed, and returned with uploads and HEAD requests.
+pub const SHA256_HEADER: &str = "x-ant-fs-sha256";
+
 async fn upload(
     TypedHeader(auth): TypedHeader<Authorization<Basic>>,
-    State(root): State<PathBuf>,
+    State(AntFsState { root, index_lock }): State<AntFsState>,
     Path(path): Path<String>,
+    headers: HeaderMap,
     body: Body,
 ) -> Result<impl IntoResponse, (StatusCode, String)> {
-    bearer_authorization(&auth)?;
+    let user = bearer_authorization(&auth)?;
 
-    let full_path = user_file_path(&root, auth.0.username(), &path);
+    let expected_sha256 = match headers.get(SHA256_HEADER) {
+        None => None,
+        Some(value) => Some(
+            value
+                .to_str()
+                .map_err(|_| {
+                    (
+                        StatusCode::BAD_REQUEST,
+                        format!("error: {SHA256_HEADER} is not valid.\n"),
+                    )
+                })?
+                .to_lowercase(),
+        ),
+    };
+
+    let full_path = user_file_path(&root, &user.username, &path);
     info!("Uploading {}...", &path);
 
-    if let Err(e) = stream_body_to_file(&full_path, body).await {
-        // Don't leave a partially-written file behind on failure.
-        let _ = tokio::fs::remove_file(&full_path).await;
-        return Err(e);
+    // Stop reading the body as soon as the quota is exceeded. Checked again when committing, in
+    // case concurrent uploads took the same space.
+    let budget = match user.quota_bytes {
+        None => None,
+        Some(quota) => {
+            let _guard = index_lock.lock().await;
+            let index = UserIndex::load(&root, &user.username).await.map_err(|e| {
+                error!("ANT-ERR-167: Failed to load index: {e:#}");
+                internal_error()
+            })?;
+            Some(quota.saturating_sub(index.used_bytes(&path)))
+        }
+    };
+
+    // Until it is persisted, the upload only exists as a temporary file, which is deleted when
+    // dropped. Readers keep seeing the previous version of the file.
+    let (tmp, size_bytes, sha256) =
+        match stream_body_to_tmp_file(&root, &user.username, body, budget).await {
+            Ok(written) => written,
+            Err(e) if e.0 == StatusCode::INSUFFICIENT_STORAGE => {
+                warn!("{} exceeded their quota uploading {}", user.username, &path);
+                return Err(quota_exceeded(&path));
+            }
+            Err(e) => return Err(e),
+        };
+
+    if let Some(expected) = expected_sha256 {
+        if expected != sha256 {
+            warn!(
+                "Upload of {} had SHA-256 {sha256}, expected {expected}",
+                &path
+            );
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!(
+                    "error: {} has SHA-256 {sha256}, expected {expected}.\n",
+                    &path
+                ),
+            ));
+        }
     }
 
-    Ok(StatusCode::OK)
-}
+    let _guard = index_lock.lock().await;
+    let mut index = UserIndex::load(&root, &user.username).await.map_err(|e| {
+        error!("ANT-ERR-168: Failed to load index: {e:#}");
+        internal_error()
+    })?;
 
-/// Stream a request body to disk chunk-by-chunk so memory stays O(chunk_size)
-/// rather than O(file_size). Creates the user's namespace directory on demand.
-async fn stream_body_to_file(full_path: &PathBuf, body: Body) -> Result<(), (StatusCode, String)> {
-    if let Some(parent) = full_path.parent() {
-        tokio::fs::create_dir_all(parent).await.map_err(|err| {
-            error!("ANT-ERR-020: Failed to create directories: {err}");
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Internal server error, please retry.".to_string(),
-            )
-        })?;
+    if let Some(quota) = user.quota_bytes {
+        if index.used_bytes(&path) + size_bytes > quota {
+            warn!("{} exceeded their quota uploading {}", user.username, &path);
+            return Err(quota_exceeded(&path));
+        }
     }
 
-    let mut file = tokio::fs::File::create(full_path).await.map_err(|err| {
-        error!("ANT-ERR-021: Failed to create file: {err}");
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            "Internal server error, please retry.".to_string(),
-        )
+    // Atomically replace the previous version, if any.
+    tmp.persist(&full_path).map_err(|e| {
+        error!("ANT-ERR-173: Failed to persist file: {e}");
+        internal_error()
     })?;
 
+    let now = chrono::Utc::now();
+    let created_at = index.files.get(&path).map(|f| f.created_at).unwrap_or(now);
+    let metadata = FileMetadata {
+        path: path.clone(),
+        size_bytes,
+        sha256,
+        created_at,
+        modified_at: now,
+    };
+    index.files.insert(path.clone(), metadata.clone());
+    index.save(&root, &user.username).await.map_err(|e| {
+        error!("ANT-ERR-169: Failed to save index: {e:#}");
+        internal_error()
+    })?;
+
+    let mut headers = HeaderMap::new();
+    if let Ok(value) = HeaderValue::from_str(&metadata.sha256) {
+        headers.insert(SHA256_HEADER, value);
+    }
+
+    Ok((StatusCode::OK, headers, Json(metadata)))
+}
+
+/// Stream a request body to a temporary file in the user's namespace, chunk-by-chunk so memory
+/// stays O(chunk_size) rather than O(file_size). The temporary file is on the same filesystem as
+/// the final path, so persisting it is an atomic rename. Fails with 507 once more than `budget`
+/// bytes were read, and otherwise returns the size and hex SHA-256 of what was written.
+async fn stream_body_to_tmp_file(
+    root: &std::path::Path,
+    username: &str,
+    body: Body,
+    budget: Option<u64>,
+) -> Result<(TempPath, u64, String), (StatusCode, String)> {
+    let tmp_dir = root.join(username).join("tmp");
+    tokio::fs::create_dir_all(&tmp_dir).await.map_err(|err| {
+        error!("ANT-ERR-020: Failed to create directories: {err}");
+        internal_error()
+    })?;
+
+    let tmp = NamedTempFile::new_in(&tmp_dir)
+        .map_err(|err| {
+            error!("ANT-ERR-021: Failed to create file: {err}");
+            internal_error()
+        })?
+        .into_temp_path();
+
+    let mut file = tokio::fs::OpenOptions::new()
+        .write(true)
+        .open(&tmp)
+        .await
+        .map_err(|err| {
+            error!("ANT-ERR-215: Failed to open file: {err}");
+            internal_error()
+        })?;
+
+    let mut hasher = Sha256::new();
+    let mut size_bytes: u64 = 0;
+
     let mut stream = body.into_data_stream();
     while let Some(chunk) = stream.next().await {
         let chunk = chunk.map_err(|err| {
@@ -137,34 +427,55 @@ async fn stream_body_to_file(full_path: &PathBuf, body: Body) -> Result<(), (Sta
                 "Failed to read request body.".to_string(),
             )
         })?;
+
+        size_bytes += chunk.len() as u64;
+        if budget.is_some_and(|budget| size_bytes > budget) {
+            return Err((StatusCode::INSUFFICIENT_STORAGE, String::new()));
+        }
+
+        hasher.update(&chunk);
         file.write_all(&chunk).await.map_err(|err| {
             error!("ANT-ERR-023: Failed to write file: {err}");
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Internal server error, please retry.".to_string(),
-            )
+            internal_error()
         })?;
     }
 
-    file.flush().await.map_err(|err| {
+    // Flush to disk, so the rename never exposes a file whose contents are not there yet.
+    file.sync_all().await.map_err(|err| {
         error!("ANT-ERR-024: Failed to flush file: {err}");
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            "Internal server error, please retry.".to_string(),
-        )
+        internal_error()
     })?;
 
+    Ok((
+        tmp,
+        size_bytes,
+        base16ct::lower::encode_string(&hasher.finalize()),
+    ))
+}
+
+async fn remove_from_index(
+    root: &PathBuf,
+    index_lock: &Mutex<()>,
+    username: &str,
+    path: &str,
+) -> Result<(), anyhow::Error> {
+    let _guard = index_lock.lock().await;
+    let mut index = UserIndex::load(root, username).await?;
+    if index.files.remove(path).is_some() {
+        index.save(root, username).await?;
+    }
+
     Ok(())
 }
 
 async fn delete(
     TypedHeader(auth): TypedHeader<Authorization<Basic>>,
-    State(root): State<PathBuf>,
+    State(AntFsState { root, index_lock }): State<AntFsState>,
     Path(path): Path<String>,
 ) -> Result<impl IntoResponse, (StatusCode, String)> {
-    bearer_authorization(&auth)?;
+    let user = bearer_authorization(&auth)?;
 
-    let full_path = user_file_path(&root, auth.0.username(), &path);
+    let full_path = user_file_path(&root, &user.username, &path);
     info!("Deleting {}...", &path);
 
     tokio::fs::remove_file(&full_path).await.map_err(|err| {
@@ -181,6 +492,13 @@ async fn delete(
         }
     })?;
 
+    remove_from_index(&root, &index_lock, &user.username, &path)
+        .await
+        .map_err(|e| {
+            error!("ANT-ERR-170: Failed to update index: {e:#}");
+            internal_error()
+        })?;
+
     return Ok(StatusCode::OK.into_response());
 }
 
@@ -188,17 +506,25 @@ pub fn make_routes(root: PathBuf) -> Result<Router, anyhow::Error> {
     debug!("Initializing API route...");
 
     let cors = CorsLayer::new()
-        .allow_methods([Method::GET, Method::POST, Method::POST])
-        .allow_headers([header::CONTENT_TYPE]);
+        .allow_methods([Method::GET, Method::HEAD, Method::POST, Method::PUT])
+        .allow_headers([header::CONTENT_TYPE, HeaderName::from_static(SHA256_HEADER)])
+        .expose_headers([HeaderName::from_static(SHA256_HEADER)]);
+
+    let state = AntFsState {
+        root,
+        index_lock: Arc::new(Mutex::new(())),
+    };
 
     debug!("Initializing site routes...");
     let app = Routes::new()
+        .get("/", get(list))
         .put("/{*path}", put(upload))
         .post("/{*path}", post(upload))
         .get("/{*path}", get(download))
+        .head("/{*path}", head(stat))
         .delete("/{*path}", axum::routing::delete(delete))
         .build()
-        .with_state(root)
+        .with_state(state)
         .layer(
             ServiceBuilder::new()
                 .layer(ant_library::middleware::http_log_layer())
@@ -1,3 +1,4 @@
+use ant_fs::ListFilesResponse;
 use http::StatusCode;
 use stdext::function_name;
 use tracing_test::traced_test;
@@ -271,3 +272,192 @@ async fn route_namespaces_files_by_user() {
         assert_eq!(res.bytes().await, "content-two".as_bytes());
     }
 }
+
+#[tokio::test]
+#[traced_test]
+async fn route_lists_files_by_prefix() {
+    let (fixture, header) = test_router_auth(function_name!()).await;
+
+    for (path, content) in [
+        ("/backups/2024/a.sql", "aaa"),
+        ("/backups/2025/b.sql", "bbbb"),
+        ("/other.txt", "c"),
+    ] {
+        let res = fixture
+            .client
+            .put(path)
+            .header("Authorization", &header)
+            .body(content.as_bytes())
+            .send()
+            .await;
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    {
+        let res = fixture
+            .client
+            .get("/?prefix=backups/")
+            .header("Authorization", &header)
+            .send()
+            .await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let res: ListFilesResponse = res.json().await;
+        let paths: Vec<&str> = res.files.iter().map(|f| f.path.as_str()).collect();
+        assert_eq!(paths, vec!["backups/2024/a.sql", "backups/2025/b.sql"]);
+        assert_eq!(res.files[1].size_bytes, 4);
+        assert_eq!(res.used_bytes, 8);
+        assert_eq!(res.quota_bytes, None);
+    }
+
+    {
+        let res = fixture
+            .client
+            .get("/")
+            .header("Authorization", &header)
+            .send()
+            .await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let res: ListFilesResponse = res.json().await;
+        assert_eq!(res.files.len(), 3);
+    }
+}
+
+#[tokio::test]
+#[traced_test]
+async fn route_head_returns_metadata() {
+    let (fixture, header) = test_router_auth(function_name!()).await;
+
+    {
+        let res = fixture
+            .client
+            .head("/file.txt")
+            .header("Authorization", &header)
+            .send()
+            .await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+
+    {
+        let res = fixture
+            .client
+            .put("/file.txt")
+            .header("Authorization", &header)
+            .body("some content here".as_bytes())
+            .send()
+            .await;
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    {
+        let res = fixture
+            .client
+            .head("/file.txt")
+            .header("Authorization", &header)
+            .send()
+            .await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers()["content-length"], "17");
+        assert_eq!(
+            res.headers()["x-ant-fs-sha256"],
+            "55b0699b60744c1dbdadec19cc166b41eea91ba9559a41fe0a91bcbe807c2f66"
+        );
+        assert_eq!(res.bytes().await.len(), 0);
+    }
+}
+
+#[tokio::test]
+#[traced_test]
+async fn route_returns_507_over_quota() {
+    let fixture = test_router_no_auth(function_name!()).await;
+    let user2 = "Basic dXNlcjI6dGVzdC1wYXNzd29yZDI="; // user2:test-password2, 32 byte quota
+
+    {
+        let res = fixture
+            .client
+            .put("/first.txt")
+            .header("Authorization", user2)
+            .body([0u8; 20].to_vec())
+            .send()
+            .await;
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    {
+        let res = fixture
+            .client
+            .put("/second.txt")
+            .header("Authorization", user2)
+            .body([0u8; 20].to_vec())
+            .send()
+            .await;
+        assert_eq!(res.status(), StatusCode::INSUFFICIENT_STORAGE);
+    }
+
+    // Replacing a file only counts the new size against the quota.
+    {
+        let res = fixture
+            .client
+            .put("/first.txt")
+            .header("Authorization", user2)
+            .body([0u8; 30].to_vec())
+            .send()
+            .await;
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    {
+        let res = fixture
+            .client
+            .get("/second.txt")
+            .header("Authorization", user2)
+            .send()
+            .await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+}
+
+#[tokio::test]
+#[traced_test]
+async fn route_verifies_expected_sha256() {
+    let (fixture, header) = test_router_auth(function_name!()).await;
+    let sha256 = "55b0699b60744c1dbdadec19cc166b41eea91ba9559a41fe0a91bcbe807c2f66"; // "some content here"
+
+    {
+        let res = fixture
+            .client
+            .put("/file.txt")
+            .header("Authorization", &header)
+            .header("x-ant-fs-sha256", sha256)
+            .body("some content here".as_bytes())
+            .send()
+            .await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers()["x-ant-fs-sha256"], sha256);
+    }
+
+    // A corrupted upload is rejected, and the previous version is kept.
+    {
+        let res = fixture
+            .client
+            .put("/file.txt")
+            .header("Authorization", &header)
+            .header("x-ant-fs-sha256", sha256)
+            .body("some content HERE".as_bytes())
+            .send()
+            .await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    }
+
+    {
+        let res = fixture
+            .client
+            .get("/file.txt")
+            .header("Authorization", &header)
+            .send()
+            .await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.bytes().await, "some content here".as_bytes());
+    }
+}
@@ -0,0 +1,24 @@
+#!/bin/sh
+
+# Run by the nginx image before it starts, see /docker-entrypoint.d.
+#
+# The routes are rendered by ant-zookeeper and pushed into the mounted /etc/nginx/routes, which is
+# empty until its first route update. Until then, serve the static routes of fallback-routes.conf,
+# written to the same file the route update replaces.
+
+set -eu
+
+routes=/etc/nginx/routes/routes.conf
+
+if [ -f "$routes" ]; then
+  echo "Serving the routes pushed by ant-zookeeper."
+  exit 0
+fi
+
+echo "No routes pushed by ant-zookeeper yet, serving the fallback routes."
+mkdir -p "$(dirname "$routes")"
+
+# Only substitute the variables that are set, like the templates of the nginx image, so the $host
+# and $remote_addr of nginx stay.
+defined_envs=$(printf '${%s} ' $(env | cut -d= -f1))
+envsubst "$defined_envs" < /etc/nginx/fallback-routes.conf.template > "$routes"
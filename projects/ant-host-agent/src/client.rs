@@ -1,11 +1,22 @@
+use std::collections::HashSet;
+
+use ant_library::provenance::SignedProvenance;
 use async_trait::async_trait;
 use reqwest::{
     multipart::{Form, Part},
-    Client,
+    Client, StatusCode,
 };
 use tracing::info;
 
-use crate::routes::service::{DisableServiceRequest, EnableServiceRequest, InstallServiceRequest};
+use crate::routes::{
+    gateway::{PutGatewayCertificateRequest, PutGatewayRoutesRequest},
+    monitoring::PutAlertRulesRequest,
+    service::{
+        DisableServiceRequest, EnableServiceRequest, GetServiceRequest, GetServiceResponse,
+        HeldBlobsRequest, HeldBlobsResponse, InstallServiceRequest, ServiceInfo,
+        UninstallServiceRequest,
+    },
+};
 
 #[derive(Clone)]
 pub struct AntHostAgentClient {
@@ -36,6 +47,7 @@ impl AntHostAgentClient {
         project: &str,
         version: &str,
         mut service_file: R,
+        provenance: &SignedProvenance,
     ) -> Result<(), anyhow::Error>
     where
         R: std::io::Read,
@@ -43,7 +55,9 @@ impl AntHostAgentClient {
         let mut buf = Vec::new();
         service_file.read_to_end(&mut buf)?;
         let part = Part::bytes(buf);
-        let form = Form::new().part("file", part);
+        let form = Form::new()
+            .part("file", part)
+            .text("provenance", serde_json::to_string(provenance)?);
 
         self.client
             .post(self.endpoint("/service/service-registration"))
@@ -57,6 +71,54 @@ impl AntHostAgentClient {
         Ok(())
     }
 
+    /// Which of `sha256s` the host holds already, or none if it cannot register deployments in
+    /// parts.
+    pub async fn held_blobs(
+        &self,
+        sha256s: Vec<String>,
+    ) -> Result<Option<HashSet<String>>, anyhow::Error> {
+        let res = self
+            .client
+            .post(self.endpoint("/service/service-blobs"))
+            .json(&HeldBlobsRequest { sha256s })
+            .send()
+            .await?;
+        if res.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+
+        let res: HeldBlobsResponse = res.error_for_status()?.json().await?;
+        Ok(Some(res.held.into_iter().collect()))
+    }
+
+    /// Register a deployment by its tree, streaming only the files packed in `blobs`, see
+    /// `ServiceTree::pack_blobs`. The provenance is for the tree, exactly as in `tree`.
+    pub async fn register_service_delta(
+        &self,
+        project: &str,
+        version: &str,
+        tree: Vec<u8>,
+        blobs: tokio::fs::File,
+        provenance: &SignedProvenance,
+    ) -> Result<(), anyhow::Error> {
+        let blobs_len = blobs.metadata().await?.len();
+        let form = Form::new()
+            .part("tree", Part::bytes(tree))
+            .part("blobs", Part::stream_with_length(blobs, blobs_len))
+            .text("provenance", serde_json::to_string(provenance)?);
+
+        self.client
+            .post(self.endpoint("/service/service-delta-registration"))
+            .header("X-Ant-Service-Id", project)
+            .header("X-Ant-Version", version)
+            .multipart(form)
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(())
+    }
+
     pub async fn install_service(&self, req: InstallServiceRequest) -> Result<(), anyhow::Error> {
         info!(
             "ant_host_agent POST /service/service-installation : {}",
@@ -101,6 +163,101 @@ impl AntHostAgentClient {
 
         Ok(())
     }
+
+    pub async fn uninstall_service(
+        &self,
+        req: UninstallServiceRequest,
+    ) -> Result<(), anyhow::Error> {
+        info!(
+            "ant_host_agent DELETE /service/service-installation : {}",
+            serde_json::to_string(&req)?
+        );
+        self.client
+            .delete(self.endpoint("/service/service-installation"))
+            .json(&req)
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(())
+    }
+
+    /// The version the service is running on the host, none if it is not installed.
+    pub async fn get_service(
+        &self,
+        service_id: &str,
+    ) -> Result<Option<ServiceInfo>, anyhow::Error> {
+        let res = self
+            .client
+            .get(self.endpoint("/service/service"))
+            .query(&GetServiceRequest {
+                service_id: service_id.to_string(),
+            })
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<GetServiceResponse>()
+            .await?;
+
+        Ok(res.service)
+    }
+
+    pub async fn put_gateway_routes(
+        &self,
+        req: PutGatewayRoutesRequest,
+    ) -> Result<(), anyhow::Error> {
+        info!("ant_host_agent PUT /gateway/routes");
+        let res = self
+            .client
+            .put(self.endpoint("/gateway/routes"))
+            .json(&req)
+            .send()
+            .await?;
+
+        if res.status() == reqwest::StatusCode::BAD_REQUEST {
+            return Err(anyhow::Error::msg(res.text().await?));
+        }
+        res.error_for_status()?;
+
+        Ok(())
+    }
+
+    pub async fn put_gateway_certificate(
+        &self,
+        req: PutGatewayCertificateRequest,
+    ) -> Result<(), anyhow::Error> {
+        info!("ant_host_agent PUT /gateway/certificate");
+        let res = self
+            .client
+            .put(self.endpoint("/gateway/certificate"))
+            .json(&req)
+            .send()
+            .await?;
+
+        if res.status() == reqwest::StatusCode::BAD_REQUEST {
+            return Err(anyhow::Error::msg(res.text().await?));
+        }
+        res.error_for_status()?;
+
+        Ok(())
+    }
+
+    pub async fn put_alert_rules(&self, req: PutAlertRulesRequest) -> Result<(), anyhow::Error> {
+        info!("ant_host_agent PUT /monitoring/alert-rules");
+        let res = self
+            .client
+            .put(self.endpoint("/monitoring/alert-rules"))
+            .json(&req)
+            .send()
+            .await?;
+
+        if res.status() == reqwest::StatusCode::BAD_REQUEST {
+            return Err(anyhow::Error::msg(res.text().await?));
+        }
+        res.error_for_status()?;
+
+        Ok(())
+    }
 }
 
 #[async_trait]
@@ -0,0 +1,23 @@
+/// Run `command` with `args` appended, returning its stderr if it fails.
+pub(crate) async fn run(command: &[String], args: &[&str]) -> Result<(), anyhow::Error> {
+    let (program, prefix) = command
+        .split_first()
+        .ok_or_else(|| anyhow::Error::msg("no command configured"))?;
+
+    let output = tokio::process::Command::new(program)
+        .args(prefix)
+        .args(args)
+        .output()
+        .await?;
+
+    if !output.status.success() {
+        return Err(anyhow::Error::msg(format!(
+            "{} exited with {}: {}",
+            args.join(" "),
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+
+    Ok(())
+}
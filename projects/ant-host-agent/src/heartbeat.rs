@@ -0,0 +1,333 @@
+//! Periodic heartbeats to ant-zookeeper, with how the host is doing and what it runs, for its host
+//! inventory. ant-zookeeper leaves hosts out of new deployments once their heartbeats stop.
+
+use std::{
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, instrument, warn};
+use zbus_systemd::{systemd1::ManagerProxy, zbus};
+
+use crate::state::AntHostAgentState;
+
+/// How often heartbeats are sent.
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct InstalledService {
+    pub service_id: String,
+    /// The VERSION of the installation its "current" directory points to.
+    pub version: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct UnitState {
+    pub unit_name: String,
+    /// e.g. "loaded" or "not-found".
+    pub load_state: String,
+    /// e.g. "active", "activating" or "failed".
+    pub active_state: String,
+    /// e.g. "running" or "dead".
+    pub sub_state: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Heartbeat {
+    pub host_id: String,
+    pub uptime_seconds: u64,
+    /// Of the filesystem with the persist directories of the services.
+    pub disk_total_bytes: u64,
+    pub disk_available_bytes: u64,
+    pub memory_total_bytes: u64,
+    pub memory_available_bytes: u64,
+    pub services: Vec<InstalledService>,
+    /// The systemd units of `services`.
+    pub units: Vec<UnitState>,
+}
+
+pub struct HeartbeatConfig {
+    /// Where ant-zookeeper listens, like `http://localhost:3235`.
+    pub zookeeper_url: String,
+    /// The bearer token of the host, a client with the "host" role named like it.
+    pub zookeeper_token: String,
+    /// The host in services.json this agent runs on.
+    pub host_id: String,
+    pub interval: Duration,
+}
+
+/// The hostname of this host, the ID of the host by default.
+pub fn hostname() -> Result<String, anyhow::Error> {
+    Ok(std::fs::read_to_string("/proc/sys/kernel/hostname")
+        .context("read hostname")?
+        .trim()
+        .to_string())
+}
+
+/// The seconds since boot, from the contents of /proc/uptime.
+fn parse_uptime(contents: &str) -> Result<u64, anyhow::Error> {
+    let seconds = contents
+        .split_whitespace()
+        .next()
+        .ok_or_else(|| anyhow::Error::msg("empty uptime"))?
+        .parse::<f64>()?;
+
+    Ok(seconds as u64)
+}
+
+/// The total and available bytes of memory, from the contents of /proc/meminfo.
+fn parse_meminfo(contents: &str) -> Result<(u64, u64), anyhow::Error> {
+    let kilobytes = |key: &str| -> Result<u64, anyhow::Error> {
+        let line = contents
+            .lines()
+            .find_map(|line| line.strip_prefix(key)?.strip_prefix(':'))
+            .ok_or_else(|| anyhow::Error::msg(format!("no {key} in meminfo")))?;
+
+        Ok(line.trim().trim_end_matches("kB").trim().parse::<u64>()?)
+    };
+
+    Ok((
+        kilobytes("MemTotal")? * 1024,
+        kilobytes("MemAvailable")? * 1024,
+    ))
+}
+
+/// The total and available bytes, from the output of `df --block-size=1 --output=size,avail`.
+fn parse_df(output: &str) -> Result<(u64, u64), anyhow::Error> {
+    let line = output
+        .lines()
+        .nth(1)
+        .ok_or_else(|| anyhow::Error::msg(format!("no filesystem in df output: {output}")))?;
+
+    match line.split_whitespace().collect::<Vec<_>>()[..] {
+        [total, available] => Ok((total.parse()?, available.parse()?)),
+        _ => Err(anyhow::Error::msg(format!("unexpected df output: {line}"))),
+    }
+}
+
+async fn disk_bytes(dir: &Path) -> Result<(u64, u64), anyhow::Error> {
+    let output = tokio::process::Command::new("df")
+        .args(["--block-size=1", "--output=size,avail"])
+        .arg(dir)
+        .output()
+        .await
+        .context("df")?;
+    if !output.status.success() {
+        return Err(anyhow::Error::msg(format!(
+            "df exited with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+
+    parse_df(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// The services installed under `install_root`, those with a "current" installation, by ID.
+pub fn installed_services(install_root: &Path) -> Result<Vec<InstalledService>, anyhow::Error> {
+    let mut services = vec![];
+    for entry in std::fs::read_dir(install_root)? {
+        let entry = entry?;
+        let Ok(service_id) = entry.file_name().into_string() else {
+            continue;
+        };
+
+        match std::fs::read_to_string(entry.path().join("current").join("VERSION")) {
+            Ok(version) => services.push(InstalledService {
+                service_id,
+                version: version.trim().to_string(),
+            }),
+            Err(e) if e.kind() == ErrorKind::NotFound || e.kind() == ErrorKind::NotADirectory => {
+                continue
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+    services.sort_by(|a, b| a.service_id.cmp(&b.service_id));
+
+    Ok(services)
+}
+
+async fn unit_states(services: &[InstalledService]) -> Result<Vec<UnitState>, anyhow::Error> {
+    let conn = zbus::Connection::system()
+        .await
+        .context("systemd connection")?;
+    let manager = ManagerProxy::new(&conn).await.context("manager init")?;
+
+    let units = manager
+        .list_units_by_names(
+            services
+                .iter()
+                .map(|s| format!("{}.service", s.service_id))
+                .collect(),
+        )
+        .await
+        .context("list units")?
+        .into_iter()
+        .map(
+            |(unit_name, _, load_state, active_state, sub_state, ..)| UnitState {
+                unit_name,
+                load_state,
+                active_state,
+                sub_state,
+            },
+        )
+        .collect();
+
+    Ok(units)
+}
+
+/// The heartbeat of this host as of now. Unit states are left out if systemd can't be reached.
+pub async fn collect(state: &AntHostAgentState, host_id: &str) -> Result<Heartbeat, anyhow::Error> {
+    let uptime_seconds = parse_uptime(&std::fs::read_to_string("/proc/uptime")?)?;
+    let (memory_total_bytes, memory_available_bytes) =
+        parse_meminfo(&std::fs::read_to_string("/proc/meminfo")?)?;
+    let (disk_total_bytes, disk_available_bytes) = disk_bytes(&state.persist_root_dir).await?;
+    let services = installed_services(&state.install_root_dir)?;
+
+    let units = match unit_states(&services).await {
+        Ok(units) => units,
+        Err(e) => {
+            warn!("ANT-ERR-199: Failed to read unit states for heartbeat: {e}");
+            vec![]
+        }
+    };
+
+    Ok(Heartbeat {
+        host_id: host_id.to_string(),
+        uptime_seconds,
+        disk_total_bytes,
+        disk_available_bytes,
+        memory_total_bytes,
+        memory_available_bytes,
+        services,
+        units,
+    })
+}
+
+async fn send(
+    client: &reqwest::Client,
+    config: &HeartbeatConfig,
+    heartbeat: &Heartbeat,
+) -> Result<(), anyhow::Error> {
+    let res = client
+        .post(format!("{}/host/heartbeat", config.zookeeper_url))
+        .bearer_auth(&config.zookeeper_token)
+        .json(heartbeat)
+        .send()
+        .await?;
+
+    if !res.status().is_success() {
+        let status = res.status();
+        let body = res.text().await.unwrap_or_default();
+        return Err(anyhow::Error::msg(format!(
+            "heartbeat failed with {status}: {body}"
+        )));
+    }
+
+    Ok(())
+}
+
+/// Send a heartbeat every `config.interval`, forever. Failures are logged and retried with the next
+/// heartbeat, ant-zookeeper only minds a few missing in a row.
+#[instrument(skip(state, config), fields(host_id = %config.host_id))]
+pub async fn run(state: AntHostAgentState, config: HeartbeatConfig) {
+    let client = reqwest::Client::new();
+    let mut interval = tokio::time::interval(config.interval);
+
+    loop {
+        interval.tick().await;
+
+        let sent = match collect(&state, &config.host_id).await {
+            Ok(heartbeat) => send(&client, &config, &heartbeat).await,
+            Err(e) => Err(e),
+        };
+        match sent {
+            Ok(()) => debug!("Sent heartbeat."),
+            Err(e) => warn!("ANT-ERR-200: Failed to send heartbeat: {e}"),
+        }
+    }
+}
+
+/// The config of heartbeats from `ANT_HOST_AGENT_ZOOKEEPER_URL`, none if it is not set.
+pub fn config_from_env() -> Result<Option<HeartbeatConfig>, anyhow::Error> {
+    let Ok(zookeeper_url) = dotenv::var("ANT_HOST_AGENT_ZOOKEEPER_URL") else {
+        return Ok(None);
+    };
+
+    let token_file = PathBuf::from(
+        dotenv::var("ANT_HOST_AGENT_ZOOKEEPER_TOKEN_FILE")
+            .context("ANT_HOST_AGENT_ZOOKEEPER_TOKEN_FILE")?,
+    );
+    let zookeeper_token = std::fs::read_to_string(&token_file)
+        .with_context(|| format!("read {}", token_file.display()))?
+        .trim()
+        .to_string();
+
+    let host_id = match dotenv::var("ANT_HOST_AGENT_HOST_ID") {
+        Ok(host_id) => host_id,
+        Err(_) => hostname()?,
+    };
+
+    Ok(Some(HeartbeatConfig {
+        zookeeper_url: zookeeper_url.trim_end_matches('/').to_string(),
+        zookeeper_token,
+        host_id,
+        interval: HEARTBEAT_INTERVAL,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{installed_services, parse_df, parse_meminfo, parse_uptime, InstalledService};
+
+    #[test]
+    fn parses_proc_and_df() {
+        assert_eq!(parse_uptime("350735.47 234388.90\n").unwrap(), 350735);
+
+        let meminfo = "MemTotal:        3884096 kB\nMemFree:          220172 kB\nMemAvailable:    2458344 kB\n";
+        assert_eq!(
+            parse_meminfo(meminfo).unwrap(),
+            (3884096 * 1024, 2458344 * 1024)
+        );
+        assert!(parse_meminfo("MemTotal: 1 kB\n").is_err());
+
+        let df = "     1B-blocks       Avail\n 62517018624 41021493248\n";
+        assert_eq!(parse_df(df).unwrap(), (62517018624, 41021493248));
+        assert!(parse_df("     1B-blocks       Avail\n").is_err());
+    }
+
+    #[test]
+    fn installed_services_have_a_current_version() {
+        let root = tempfile::tempdir().unwrap();
+        for (service_id, version) in [("ant-on-the-web", "v2"), ("ant-gateway", "v1")] {
+            let dir = root.path().join(service_id).join(format!("{version}.1"));
+            std::fs::create_dir_all(&dir).unwrap();
+            std::fs::write(dir.join("VERSION"), format!("{version}\n")).unwrap();
+            std::os::unix::fs::symlink(&dir, root.path().join(service_id).join("current")).unwrap();
+        }
+        // Registered, but never installed.
+        std::fs::create_dir_all(root.path().join("ant-fs").join("v1.1")).unwrap();
+        std::fs::write(root.path().join("stray-file"), b"").unwrap();
+
+        assert_eq!(
+            installed_services(root.path()).unwrap(),
+            vec![
+                InstalledService {
+                    service_id: "ant-gateway".to_string(),
+                    version: "v1".to_string(),
+                },
+                InstalledService {
+                    service_id: "ant-on-the-web".to_string(),
+                    version: "v2".to_string(),
+                },
+            ]
+        );
+    }
+}
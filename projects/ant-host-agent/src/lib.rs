@@ -1,8 +1,11 @@
 pub mod client;
+mod command;
 mod err;
+pub mod heartbeat;
 pub mod routes;
 pub mod state;
 pub mod systemd;
+pub mod tree;
 
 use ant_library::routes::Routes;
 use axum::{
@@ -17,12 +20,14 @@ use crate::state::AntHostAgentState;
 
 pub fn make_routes(state: AntHostAgentState) -> Result<Router, anyhow::Error> {
     let cors = CorsLayer::new()
-        .allow_methods([Method::GET, Method::POST])
+        .allow_methods([Method::GET, Method::POST, Method::PUT])
         .allow_origin(tower_http::cors::Any)
         .allow_headers([CONTENT_TYPE]);
 
     let api: Router = Routes::new()
         .nest_routes("/service", crate::routes::service::routes())
+        .nest_routes("/gateway", crate::routes::gateway::routes())
+        .nest_routes("/monitoring", crate::routes::monitoring::routes())
         .get("/ping", get(ant_library::api_ping))
         .post("/ping", post(ant_library::api_ping))
         .build()
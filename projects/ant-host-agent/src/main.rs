@@ -1,4 +1,7 @@
-use ant_host_agent::{make_routes, state::AntHostAgentState};
+use ant_host_agent::{
+    make_routes,
+    state::{load_trusted_keys, AntHostAgentState},
+};
 use ant_library::sd::writer::ServiceDiscoveryWriter;
 use std::{fs, net::SocketAddr, path::PathBuf, sync::Arc};
 use tracing::{debug, error, info, instrument};
@@ -9,6 +12,10 @@ async fn main() {
     ant_library::set_global_logs("ant-host-agent");
 
     info!("Initializing state...");
+    let install_root_dir = PathBuf::from(
+        dotenv::var("ANT_HOST_AGENT_INSTALL_ROOT_DIR")
+            .expect("No ANT_HOST_AGENT_INSTALL_ROOT_DIR variable."),
+    );
     let state = AntHostAgentState {
         sd: Arc::new(ServiceDiscoveryWriter::new(
             dotenv::var("ANT_MATCHMAKER_HTTP_PORT")
@@ -27,10 +34,37 @@ async fn main() {
         )
         .join("fs")
         .join("archives"),
-        install_root_dir: PathBuf::from(
-            dotenv::var("ANT_HOST_AGENT_INSTALL_ROOT_DIR")
-                .expect("No ANT_HOST_AGENT_INSTALL_ROOT_DIR variable."),
+        install_root_dir: install_root_dir.clone(),
+        persist_root_dir: PathBuf::from(
+            dotenv::var("ANT_HOST_AGENT_PERSIST_ROOT_DIR")
+                .expect("No ANT_HOST_AGENT_PERSIST_ROOT_DIR variable."),
         ),
+        trusted_keys: load_trusted_keys(
+            dotenv::var("ANT_HOST_AGENT_TRUSTED_KEYS").ok().as_deref(),
+            dotenv::var("ANT_HOST_AGENT_INSTALL_UNVERIFIED").is_ok_and(|v| v == "true"),
+        )
+        .expect("Failed to load the trusted keys"),
+        gateway_exec: ["docker", "exec", "ant-gateway"]
+            .map(|arg| arg.to_string())
+            .to_vec(),
+        monitor_check_rules: vec![
+            install_root_dir
+                .join("ant-monitor")
+                .join("current")
+                .join("promtool")
+                .to_string_lossy()
+                .to_string(),
+            "check".to_string(),
+            "rules".to_string(),
+        ],
+        monitor_reload: [
+            "systemctl",
+            "kill",
+            "--signal=SIGHUP",
+            "ant-monitor.service",
+        ]
+        .map(|arg| arg.to_string())
+        .to_vec(),
     };
 
     info!("Init directory: {}", state.archive_root_dir.display());
@@ -63,6 +97,17 @@ async fn main() {
         error!("ANT-ERR-028: Failed to ensure typesofants.slice exists: {slice}");
     }
 
+    match ant_host_agent::heartbeat::config_from_env().expect("heartbeat config") {
+        Some(config) => {
+            info!(
+                "Sending heartbeats to {} as {}.",
+                config.zookeeper_url, config.host_id
+            );
+            tokio::task::spawn(ant_host_agent::heartbeat::run(state.clone(), config));
+        }
+        None => info!("No ANT_HOST_AGENT_ZOOKEEPER_URL, not sending heartbeats."),
+    }
+
     info!("Starting server...");
     let port = dotenv::var("PORT")
         .expect("Could not find PORT environment variable")
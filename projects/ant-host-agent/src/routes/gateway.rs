@@ -0,0 +1,166 @@
+//! The routes of the ant-gateway nginx, rendered by ant-zookeeper from the anthill.json `routing`
+//! of every project.
+//!
+//! The config is written to `<persist root>/ant-gateway/routes/routes.conf`, which the gateway
+//! container mounts at /etc/nginx/routes, then checked and reloaded with nginx inside the
+//! container. A config nginx rejects is rolled back before returning.
+//!
+//! The TLS certificate renewed by ant-zookeeper replaces the `tls_cert` and `tls_key` secrets of
+//! the installed gateway, which the container mounts at /run/secrets, and is rolled back the same
+//! way.
+
+use std::{
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use ant_library::routes::Routes;
+use axum::{extract::State, response::IntoResponse, routing::put, Json};
+use hyper::StatusCode;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::{command, err::AntHostAgentError, state::AntHostAgentState};
+
+const GATEWAY_SERVICE_ID: &str = "ant-gateway";
+const ROUTES_FILE_NAME: &str = "routes.conf";
+const CERTIFICATE_SECRET_NAME: &str = "tls_cert";
+const PRIVATE_KEY_SECRET_NAME: &str = "tls_key";
+
+pub fn gateway_routes_path(persist_root_dir: &Path) -> PathBuf {
+    persist_root_dir
+        .join(GATEWAY_SERVICE_ID)
+        .join("routes")
+        .join(ROUTES_FILE_NAME)
+}
+
+/// The secrets of the installed gateway, e.g. `<install root>/ant-gateway/current/secrets`.
+pub fn gateway_secrets_dir(install_root_dir: &Path) -> PathBuf {
+    install_root_dir
+        .join(GATEWAY_SERVICE_ID)
+        .join("current")
+        .join("secrets")
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PutGatewayRoutesRequest {
+    /// The full nginx config of the gateway's routes.
+    pub config: String,
+}
+
+async fn put_gateway_routes(
+    State(state): State<AntHostAgentState>,
+    Json(req): Json<PutGatewayRoutesRequest>,
+) -> Result<impl IntoResponse, AntHostAgentError> {
+    let path = gateway_routes_path(&state.persist_root_dir);
+    let dir = path.parent().expect("routes file has a parent");
+    tokio::fs::create_dir_all(dir)
+        .await
+        .map_err(|e| AntHostAgentError::InternalServerError("ANT-ERR-175", Some(e.into())))?;
+
+    let previous = match tokio::fs::read(&path).await {
+        Ok(previous) => Some(previous),
+        Err(e) if e.kind() == ErrorKind::NotFound => None,
+        Err(e) => {
+            return Err(AntHostAgentError::InternalServerError(
+                "ANT-ERR-176",
+                Some(e.into()),
+            ))
+        }
+    };
+
+    let tmp = path.with_extension("conf.tmp");
+    tokio::fs::write(&tmp, req.config.as_bytes()).await?;
+    tokio::fs::rename(&tmp, &path).await?;
+
+    if let Err(e) = command::run(&state.gateway_exec, &["nginx", "-t"]).await {
+        warn!("Gateway rejected routes, rolling back: {e}");
+        match previous {
+            Some(previous) => tokio::fs::write(&path, previous).await?,
+            None => tokio::fs::remove_file(&path).await?,
+        }
+
+        return Err(AntHostAgentError::validation(
+            &format!("Gateway rejected routes: {e}"),
+            None,
+        ));
+    }
+
+    command::run(&state.gateway_exec, &["nginx", "-s", "reload"])
+        .await
+        .map_err(|e| AntHostAgentError::InternalServerError("ANT-ERR-177", Some(e)))?;
+
+    info!("Gateway routes updated: {}", path.display());
+    Ok((StatusCode::OK, "Gateway routes updated."))
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PutGatewayCertificateRequest {
+    /// The PEM certificate chain, the new `tls_cert` secret.
+    pub certificate: String,
+    /// The PEM private key of the certificate, the new `tls_key` secret.
+    pub private_key: String,
+}
+
+async fn put_gateway_certificate(
+    State(state): State<AntHostAgentState>,
+    Json(req): Json<PutGatewayCertificateRequest>,
+) -> Result<impl IntoResponse, AntHostAgentError> {
+    let dir = gateway_secrets_dir(&state.install_root_dir);
+    if !tokio::fs::try_exists(&dir).await? {
+        return Err(AntHostAgentError::validation_msg(&format!(
+            "{GATEWAY_SERVICE_ID} has no secrets installed, did it deploy?"
+        )));
+    }
+
+    let files = [
+        (
+            dir.join(format!("{CERTIFICATE_SECRET_NAME}.secret")),
+            req.certificate,
+        ),
+        (
+            dir.join(format!("{PRIVATE_KEY_SECRET_NAME}.secret")),
+            req.private_key,
+        ),
+    ];
+
+    let mut previous = vec![];
+    for (path, _) in &files {
+        let content = tokio::fs::read(path)
+            .await
+            .map_err(|e| AntHostAgentError::InternalServerError("ANT-ERR-189", Some(e.into())))?;
+        previous.push((path, content));
+    }
+
+    // The container mounts the files themselves, so they are overwritten rather than replaced.
+    for (path, content) in &files {
+        tokio::fs::write(path, content.as_bytes()).await?;
+    }
+
+    if let Err(e) = command::run(&state.gateway_exec, &["nginx", "-t"]).await {
+        warn!("Gateway rejected certificate, rolling back: {e}");
+        for (path, content) in previous {
+            tokio::fs::write(path, content).await?;
+        }
+
+        return Err(AntHostAgentError::validation(
+            &format!("Gateway rejected certificate: {e}"),
+            None,
+        ));
+    }
+
+    command::run(&state.gateway_exec, &["nginx", "-s", "reload"])
+        .await
+        .map_err(|e| AntHostAgentError::InternalServerError("ANT-ERR-190", Some(e)))?;
+
+    info!("Gateway certificate updated: {}", dir.display());
+    Ok((StatusCode::OK, "Gateway certificate updated."))
+}
+
+pub fn routes() -> Routes<AntHostAgentState> {
+    Routes::new()
+        .put("/routes", put(put_gateway_routes))
+        .put("/certificate", put(put_gateway_certificate))
+}
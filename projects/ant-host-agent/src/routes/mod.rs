@@ -1 +1,3 @@
+pub mod gateway;
+pub mod monitoring;
 pub mod service;
@@ -0,0 +1,104 @@
+//! The Prometheus alert rules of the services, loaded by ant-monitor.
+//!
+//! Each service has a rules file per environment in `<persist root>/ant-monitor/rules/`, which
+//! ant-monitor links into its working directory and loads with `rule_files`. Rules are checked with
+//! promtool before replacing the previous file, then Prometheus is reloaded.
+
+use std::{
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use ant_library::routes::Routes;
+use axum::{extract::State, response::IntoResponse, routing::put, Json};
+use hyper::StatusCode;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::{command, err::AntHostAgentError, state::AntHostAgentState};
+
+const MONITOR_SERVICE_ID: &str = "ant-monitor";
+
+pub fn alert_rules_path(persist_root_dir: &Path, service_id: &str, environment: &str) -> PathBuf {
+    persist_root_dir
+        .join(MONITOR_SERVICE_ID)
+        .join("rules")
+        .join(format!("{service_id}.{environment}.yml"))
+}
+
+fn valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PutAlertRulesRequest {
+    pub service_id: String,
+    pub environment: String,
+    /// The Prometheus rules file of the service, or None to remove its alert rules.
+    pub rules: Option<String>,
+}
+
+async fn put_alert_rules(
+    State(state): State<AntHostAgentState>,
+    Json(req): Json<PutAlertRulesRequest>,
+) -> Result<impl IntoResponse, AntHostAgentError> {
+    if !valid_name(&req.service_id) || !valid_name(&req.environment) {
+        return Err(AntHostAgentError::validation_msg(
+            "Invalid service ID or environment.",
+        ));
+    }
+
+    let path = alert_rules_path(&state.persist_root_dir, &req.service_id, &req.environment);
+    let dir = path.parent().expect("rules file has a parent");
+    tokio::fs::create_dir_all(dir)
+        .await
+        .map_err(|e| AntHostAgentError::InternalServerError("ANT-ERR-178", Some(e.into())))?;
+
+    match &req.rules {
+        Some(rules) => {
+            let tmp = path.with_extension("yml.tmp");
+            tokio::fs::write(&tmp, rules.as_bytes()).await?;
+
+            if let Err(e) = command::run(
+                &state.monitor_check_rules,
+                &[tmp.to_str().expect("utf-8 path")],
+            )
+            .await
+            {
+                warn!("Alert rules of {} rejected: {e}", req.service_id);
+                tokio::fs::remove_file(&tmp).await?;
+                return Err(AntHostAgentError::validation(
+                    &format!("Alert rules rejected: {e}"),
+                    None,
+                ));
+            }
+
+            tokio::fs::rename(&tmp, &path).await?;
+        }
+        None => match tokio::fs::remove_file(&path).await {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => {
+                return Err(AntHostAgentError::InternalServerError(
+                    "ANT-ERR-179",
+                    Some(e.into()),
+                ))
+            }
+        },
+    }
+
+    command::run(&state.monitor_reload, &[])
+        .await
+        .map_err(|e| AntHostAgentError::InternalServerError("ANT-ERR-180", Some(e)))?;
+
+    info!("Alert rules updated: {}", path.display());
+    Ok((StatusCode::OK, "Alert rules updated."))
+}
+
+pub fn routes() -> Routes<AntHostAgentState> {
+    Routes::new().put("/alert-rules", put(put_alert_rules))
+}
@@ -1,4 +1,5 @@
 use ant_library::headers::{XAntServiceIdHeader, XAntVersionHeader};
+use ant_library::provenance::{file_sha256, SignedProvenance};
 use anthill_manifest::{AnthillCluster, AnthillManifest, AnthillManifestError};
 use anyhow::Context;
 use flate2::read::GzDecoder;
@@ -33,12 +34,21 @@ use crate::{
     err::AntHostAgentError,
     state::AntHostAgentState,
     systemd::{restart_unit, SystemdUnitError},
+    tree::{blob_path, collect_blobs, store_blobs, ServiceTree},
 };
 
 fn unit_name(service_id: &str) -> String {
     format!("{service_id}.service")
 }
 
+fn unit_path(service_id: &str) -> PathBuf {
+    PathBuf::from("/")
+        .join("etc")
+        .join("systemd")
+        .join("system")
+        .join(unit_name(service_id))
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct EnableServiceRequest {
     pub service_id: String,
@@ -115,11 +125,7 @@ WantedBy=multi-user.target
 /// the symlinked "current" directory, which can switch per version.
 #[instrument]
 async fn ensure_systemd(service_id: &str) -> Result<(), anyhow::Error> {
-    let unit_path = PathBuf::from("/")
-        .join("etc")
-        .join("systemd")
-        .join("system")
-        .join(unit_name(service_id));
+    let unit_path = unit_path(service_id);
 
     let content = systemd_unit_file_content(service_id);
 
@@ -270,6 +276,63 @@ fn deployment_file_name(service_id: &str, version: &str) -> String {
     format!("deployment.{service_id}.{version}.tar.gz")
 }
 
+/// The signed provenance ant-zookeeper registered the deployment file with.
+fn provenance_file_name(service_id: &str, version: &str) -> String {
+    format!("deployment.{service_id}.{version}.provenance.json")
+}
+
+/// The tree of a deployment registered in parts, assembled from the blob store on install.
+fn tree_file_name(service_id: &str, version: &str) -> String {
+    format!("deployment.{service_id}.{version}.tree.json")
+}
+
+/// Where every file of the deployments registered in parts is kept, by its SHA256.
+fn blob_store_dir(state: &AntHostAgentState) -> PathBuf {
+    state.archive_root_dir.join("blobs").join("sha256")
+}
+
+/// Every tree registered, for the installed version of each service or to be installed next.
+fn registered_trees(state: &AntHostAgentState) -> Result<Vec<ServiceTree>, anyhow::Error> {
+    let mut trees = vec![];
+    for entry in std::fs::read_dir(&state.archive_root_dir)? {
+        let path = entry?.path();
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        if name.starts_with("deployment.") && name.ends_with(".tree.json") {
+            let tree = serde_json::from_reader(std::fs::File::open(&path)?)
+                .with_context(|| format!("malformed tree {}", path.display()))?;
+            trees.push(tree);
+        }
+    }
+
+    Ok(trees)
+}
+
+/// Forget the trees of `service_id` other than the one of `installed`, then remove the blobs no
+/// tree needs anymore.
+fn collect_service_blobs(
+    state: &AntHostAgentState,
+    service_id: &str,
+    installed: Option<&str>,
+) -> Result<(), anyhow::Error> {
+    let prefix = format!("deployment.{service_id}.");
+    let keep = installed.map(|version| tree_file_name(service_id, version));
+    for entry in std::fs::read_dir(&state.archive_root_dir)? {
+        let path = entry?.path();
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        if name.starts_with(&prefix)
+            && name.ends_with(".tree.json")
+            && keep.as_deref() != Some(&*name)
+        {
+            remove_if_exists(&path)?;
+        }
+    }
+
+    let removed = collect_blobs(&blob_store_dir(state), || registered_trees(state))?;
+    info!("Removed {removed} blobs no longer needed after [{service_id}]");
+
+    Ok(())
+}
+
 fn versioned_install_dir(
     install_root: &PathBuf,
     service_id: &str,
@@ -337,8 +400,17 @@ async fn install_service(
     State(state): State<AntHostAgentState>,
     Json(req): Json<InstallServiceRequest>,
 ) -> Result<impl IntoResponse, AntHostAgentError> {
-    let file_name = deployment_file_name(&req.service_id, &req.version);
-    let file_path = state.archive_root_dir.join(file_name);
+    // Registered in parts as a tree, or as a whole tarball.
+    let tree_path = state
+        .archive_root_dir
+        .join(tree_file_name(&req.service_id, &req.version));
+    let is_tree = std::fs::exists(&tree_path)?;
+    let file_path = match is_tree {
+        true => tree_path,
+        false => state
+            .archive_root_dir
+            .join(deployment_file_name(&req.service_id, &req.version)),
+    };
     info!(
         "Installing [{}] version [{}] from [{}]...",
         req.service_id,
@@ -357,14 +429,80 @@ async fn install_service(
         _ => AntHostAgentError::InternalServerError("ANT-ERR-115", Some(e.into())),
     })?;
 
+    // Only install deployment files signed by a trusted key, for exactly this content.
+    match &state.trusted_keys {
+        None => warn!(
+            "ANT-ERR-209: Installing [{}] without verifying its provenance, as asked",
+            req.service_id
+        ),
+        Some(trusted_keys) => {
+            let provenance_path = state
+                .archive_root_dir
+                .join(provenance_file_name(&req.service_id, &req.version));
+            let provenance =
+                std::fs::read_to_string(&provenance_path).map_err(|e| match e.kind() {
+                    ErrorKind::NotFound => AntHostAgentError::validation_msg(&format!(
+                        "No provenance found for: {} version {}",
+                        req.service_id, req.version
+                    )),
+                    _ => AntHostAgentError::InternalServerError("ANT-ERR-196", Some(e.into())),
+                })?;
+
+            let verified = serde_json::from_str::<SignedProvenance>(&provenance)
+                .map_err(anyhow::Error::from)
+                .and_then(|signed| {
+                    trusted_keys.verify_artifact(
+                        &signed,
+                        &req.service_id,
+                        &req.version,
+                        &file_sha256(&file_path)?,
+                    )
+                })
+                .map_err(|e| {
+                    warn!(
+                        "ANT-ERR-197: Untrusted deployment of [{}] refused: {e}",
+                        req.service_id
+                    );
+                    AntHostAgentError::validation(
+                        "Provenance of the deployment could not be verified.",
+                        Some(e),
+                    )
+                })?;
+            info!(
+                "Deployment built from commit [{}] by [{}]",
+                verified.commit, verified.builder
+            );
+        }
+    }
+
     let versioned_install_dir = {
         let tmp_dst = temp_dir(&state)?;
 
-        let mut deployment = tar::Archive::new(GzDecoder::new(file));
-        info!("Unpacking installation to: {}", tmp_dst.path().display());
-        deployment
-            .unpack(&tmp_dst)
-            .context("attempted to unpack malformed tarfile")?;
+        if is_tree {
+            let tree: ServiceTree = serde_json::from_reader(file).context("malformed tree")?;
+            info!(
+                "Assembling {} files of installation to: {}",
+                tree.entries.len(),
+                tmp_dst.path().display()
+            );
+            tree.assemble(&blob_store_dir(&state), tmp_dst.path())
+                .map_err(|e| {
+                    warn!(
+                        "ANT-ERR-198: Failed to assemble [{}] version [{}]: {e}",
+                        req.service_id, req.version
+                    );
+                    AntHostAgentError::validation(
+                        "Deployment could not be assembled, register it again.",
+                        Some(e),
+                    )
+                })?;
+        } else {
+            let mut deployment = tar::Archive::new(GzDecoder::new(file));
+            info!("Unpacking installation to: {}", tmp_dst.path().display());
+            deployment
+                .unpack(&tmp_dst)
+                .context("attempted to unpack malformed tarfile")?;
+        }
 
         let manifest = match AnthillManifest::from_file(&tmp_dst.path().join("anthill.json")) {
             Ok(manifest) => Ok(Some(manifest)),
@@ -503,6 +641,10 @@ async fn install_service(
         }
     }
 
+    if let Err(e) = collect_service_blobs(&state, &req.service_id, Some(&req.version)) {
+        warn!("ANT-ERR-211: Failed to remove unused blobs: {e:?}");
+    }
+
     Ok((StatusCode::OK, "Service installed."))
 }
 
@@ -526,40 +668,134 @@ async fn disable_unit(manager: &ManagerProxy<'_>, unit_name: &String) {
 
 #[derive(Deserialize, Serialize)]
 pub struct DisableServiceRequest {
-    service_id: String,
+    pub service_id: String,
 }
 
-async fn disable_service(
-    State(state): State<AntHostAgentState>,
-    Json(req): Json<DisableServiceRequest>,
-) -> Result<impl IntoResponse, AntHostAgentError> {
+/// Stop and disable the unit of a service, and deregister it from the Consul cluster it joined.
+async fn stop_service(state: &AntHostAgentState, service_id: &str) -> Result<(), anyhow::Error> {
     let conn = zbus::Connection::system().await.expect("system connection");
     let manager = zbus_systemd::systemd1::ManagerProxy::new(&conn)
         .await
         .expect("manager init");
 
-    let unit_name = unit_name(&req.service_id);
+    let unit_name = unit_name(service_id);
 
     disable_unit(&manager, &unit_name).await;
-    manager
-        .kill_unit(unit_name, "all".to_string(), 9)
-        .await
-        .unwrap();
+    match manager.kill_unit(unit_name, "all".to_string(), 9).await {
+        Ok(()) => {}
+        Err(zbus::Error::MethodError(name, msg, _)) => {
+            info!("No such service running: {name}, {msg:?}. Ignoring...");
+        }
+        Err(e) => return Err(e).context("kill unit"),
+    }
 
     manager.reload().await.expect("reload");
 
+    // The manifest of the installed version says which cluster the service registered with.
+    let current_manifest = state
+        .install_root_dir
+        .join(service_id)
+        .join("current")
+        .join("anthill.json");
+    let cluster = match AnthillManifest::from_file(&current_manifest) {
+        Ok(manifest) => manifest.cluster,
+        Err(e) => {
+            warn!("No manifest at {}: {e}", current_manifest.display());
+            AnthillCluster::IsolatedEnvironment
+        }
+    };
+
     // De-register (unless of course it's the service registry itself)
-    if req.service_id != "ant-matchmaker" {
-        state
-            .sd
-            .deregister_local_service(&req.service_id)
-            .await
-            .context("deregister ant-matchmaker service")?;
+    if service_id != "ant-matchmaker" {
+        match cluster {
+            AnthillCluster::GlobalInfrastructure => state
+                .infra_sd
+                .deregister_local_service(service_id)
+                .await
+                .context("deregister ant-matchmaker-infra service")?,
+            AnthillCluster::IsolatedEnvironment => state
+                .sd
+                .deregister_local_service(service_id)
+                .await
+                .context("deregister ant-matchmaker service")?,
+        }
     }
 
+    Ok(())
+}
+
+async fn disable_service(
+    State(state): State<AntHostAgentState>,
+    Json(req): Json<DisableServiceRequest>,
+) -> Result<impl IntoResponse, AntHostAgentError> {
+    stop_service(&state, &req.service_id).await?;
+
     Ok((StatusCode::OK, "Service disabled."))
 }
 
+#[derive(Deserialize, Serialize)]
+pub struct UninstallServiceRequest {
+    pub service_id: String,
+}
+
+/// The inverse of installing and enabling a service: stop it, deregister it, then remove all of its
+/// installed versions and its systemd unit. Used to roll back the first-ever deployment of a
+/// service to a host, when there is no previous version to go back to.
+async fn uninstall_service(
+    State(state): State<AntHostAgentState>,
+    Json(req): Json<UninstallServiceRequest>,
+) -> Result<impl IntoResponse, AntHostAgentError> {
+    if req.service_id.is_empty()
+        || !req
+            .service_id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+        || req.service_id.starts_with('.')
+    {
+        return Err(AntHostAgentError::validation_msg("Invalid service ID."));
+    }
+
+    stop_service(&state, &req.service_id).await?;
+
+    let install_dir = state.install_root_dir.join(&req.service_id);
+    info!("Removing installations: {}", install_dir.display());
+    match std::fs::remove_dir_all(&install_dir) {
+        Ok(()) => {}
+        Err(e) if e.kind() == ErrorKind::NotFound => {}
+        Err(e) => {
+            return Err(AntHostAgentError::InternalServerError(
+                "ANT-ERR-181",
+                Some(e.into()),
+            ))
+        }
+    }
+
+    if let Err(e) = collect_service_blobs(&state, &req.service_id, None) {
+        warn!("ANT-ERR-214: Failed to remove unused blobs: {e:?}");
+    }
+
+    let unit_path = unit_path(&req.service_id);
+    info!("Removing systemd unit: {}", unit_path.display());
+    match std::fs::remove_file(&unit_path) {
+        Ok(()) => {}
+        Err(e) if e.kind() == ErrorKind::NotFound => {}
+        Err(e) => {
+            return Err(AntHostAgentError::InternalServerError(
+                "ANT-ERR-182",
+                Some(e.into()),
+            ))
+        }
+    }
+
+    let conn = zbus::Connection::system().await.expect("system connection");
+    let manager = zbus_systemd::systemd1::ManagerProxy::new(&conn)
+        .await
+        .expect("manager init");
+    manager.reload().await.expect("reload");
+
+    Ok((StatusCode::OK, "Service uninstalled."))
+}
+
 async fn register_service(
     State(state): State<AntHostAgentState>,
     TypedHeader(service_id): TypedHeader<XAntServiceIdHeader>,
@@ -571,30 +807,166 @@ async fn register_service(
         .join(deployment_file_name(&service_id.0, &version.0));
     info!("Registering service: {}", path.display());
     let mut file = File::create(&path).await?;
+    remove_if_exists(
+        &state
+            .archive_root_dir
+            .join(tree_file_name(&service_id.0, &version.0)),
+    )?;
+
+    // The deployment file, and the provenance ant-zookeeper signed for it, checked on install.
+    let mut file_found = false;
+    while let Some(mut field) = multipart.next_field().await.map_err(|e| {
+        AntHostAgentError::validation("No field found in multipart request!", Some(e.into()))
+    })? {
+        if field.name() == Some("provenance") {
+            let provenance = field.text().await.map_err(|e| {
+                AntHostAgentError::validation("Provenance could not be read!", Some(e.into()))
+            })?;
+            tokio::fs::write(
+                state
+                    .archive_root_dir
+                    .join(provenance_file_name(&service_id.0, &version.0)),
+                provenance,
+            )
+            .await?;
+            continue;
+        }
 
-    let mut field = multipart
-        .next_field()
-        .await
-        .map_err(|e| {
-            AntHostAgentError::validation("No field found in multipart request!", Some(e.into()))
-        })?
-        .ok_or(AntHostAgentError::validation_msg(
+        file_found = true;
+        while let Some(bytes) = field.chunk().await? {
+            info!(
+                "Wrote [{}] to [{}]...",
+                humansize::format_size(bytes.len(), DECIMAL),
+                path.display()
+            );
+            file.write_all(&bytes).await?;
+        }
+    }
+    if !file_found {
+        return Err(AntHostAgentError::validation_msg(
             "No bytes field found in request!",
-        ))?;
-
-    while let Some(bytes) = field.chunk().await.unwrap() {
-        info!(
-            "Wrote [{}] to [{}]...",
-            humansize::format_size(bytes.len(), DECIMAL),
-            path.display()
-        );
-        file.write_all(&bytes).await?;
+        ));
     }
     file.flush().await?;
 
     Ok((StatusCode::OK, "Service registered."))
 }
 
+fn remove_if_exists(path: &Path) -> Result<(), std::io::Error> {
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
+        r => r,
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct HeldBlobsRequest {
+    pub sha256s: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct HeldBlobsResponse {
+    /// Those of the requested SHA256s that are in the blob store.
+    pub held: Vec<String>,
+}
+
+/// Which files the host already holds, so they need not be sent again.
+async fn held_blobs(
+    State(state): State<AntHostAgentState>,
+    Json(req): Json<HeldBlobsRequest>,
+) -> Result<impl IntoResponse, AntHostAgentError> {
+    let store = blob_store_dir(&state);
+
+    let mut held = vec![];
+    for sha256 in req.sha256s {
+        let path = blob_path(&store, &sha256)
+            .map_err(|e| AntHostAgentError::validation("Malformed SHA256!", Some(e)))?;
+        if std::fs::exists(&path)? {
+            held.push(sha256);
+        }
+    }
+
+    Ok(Json(HeldBlobsResponse { held }))
+}
+
+/// Register a deployment in parts: its tree, the files of it the host does not hold yet, and the
+/// provenance ant-zookeeper signed for the tree.
+async fn register_service_delta(
+    State(state): State<AntHostAgentState>,
+    TypedHeader(service_id): TypedHeader<XAntServiceIdHeader>,
+    TypedHeader(version): TypedHeader<XAntVersionHeader>,
+    mut multipart: Multipart,
+) -> Result<impl IntoResponse, AntHostAgentError> {
+    let tree_path = state
+        .archive_root_dir
+        .join(tree_file_name(&service_id.0, &version.0));
+    info!("Registering service in parts: {}", tree_path.display());
+
+    let mut tree_found = false;
+    while let Some(mut field) = multipart.next_field().await.map_err(|e| {
+        AntHostAgentError::validation("No field found in multipart request!", Some(e.into()))
+    })? {
+        match field.name() {
+            Some("tree") => {
+                let tree = field.bytes().await.map_err(|e| {
+                    AntHostAgentError::validation("Tree could not be read!", Some(e.into()))
+                })?;
+                serde_json::from_slice::<ServiceTree>(&tree).map_err(|e| {
+                    AntHostAgentError::validation("Malformed tree!", Some(e.into()))
+                })?;
+                // Kept as sent, its SHA256 is what the provenance is for.
+                tokio::fs::write(&tree_path, &tree).await?;
+                tree_found = true;
+            }
+            Some("provenance") => {
+                let provenance = field.text().await.map_err(|e| {
+                    AntHostAgentError::validation("Provenance could not be read!", Some(e.into()))
+                })?;
+                tokio::fs::write(
+                    state
+                        .archive_root_dir
+                        .join(provenance_file_name(&service_id.0, &version.0)),
+                    provenance,
+                )
+                .await?;
+            }
+            _ => {
+                let tmp_dir = temp_dir(&state)?;
+                let blobs_path = tmp_dir.path().join("blobs.tar.gz");
+                let mut blobs = File::create(&blobs_path).await?;
+                while let Some(bytes) = field.chunk().await? {
+                    blobs.write_all(&bytes).await?;
+                }
+                blobs.flush().await?;
+
+                let stored =
+                    store_blobs(std::fs::File::open(&blobs_path)?, &blob_store_dir(&state))
+                        .map_err(|e| {
+                            AntHostAgentError::validation("Blobs could not be stored!", Some(e))
+                        })?;
+                info!(
+                    "Stored {stored} blobs, {}",
+                    humansize::format_size(std::fs::metadata(&blobs_path)?.len(), DECIMAL)
+                );
+            }
+        }
+    }
+    if !tree_found {
+        return Err(AntHostAgentError::validation_msg(
+            "No tree field found in request!",
+        ));
+    }
+
+    // Installs from the tree from now on, not a tarball of a previous registration.
+    remove_if_exists(
+        &state
+            .archive_root_dir
+            .join(deployment_file_name(&service_id.0, &version.0)),
+    )?;
+
+    Ok((StatusCode::OK, "Service registered."))
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct GetServiceRequest {
     pub service_id: String,
@@ -642,12 +1014,20 @@ pub fn routes() -> Routes<AntHostAgentState> {
         .post("/service", post(enable_service))
         .delete("/service", delete(disable_service))
         .post("/service-installation", post(install_service))
+        .delete("/service-installation", delete(uninstall_service))
         .post(
             "/service-registration",
             post(register_service).layer(
                 DefaultBodyLimit::max(1000 * 1000 * 1000), // 1GB
             ),
         )
+        .post("/service-blobs", post(held_blobs))
+        .post(
+            "/service-delta-registration",
+            post(register_service_delta).layer(
+                DefaultBodyLimit::max(1000 * 1000 * 1000), // 1GB
+            ),
+        )
 }
 
 #[cfg(test)]
@@ -1,6 +1,8 @@
 use std::{path::PathBuf, sync::Arc};
 
-use ant_library::sd::writer::ServiceDiscoveryWriter;
+use ant_library::{provenance::TrustedKeys, sd::writer::ServiceDiscoveryWriter};
+use anyhow::Context;
+use tracing::warn;
 
 #[derive(Debug, Clone)]
 pub struct AntHostAgentState {
@@ -16,4 +18,51 @@ pub struct AntHostAgentState {
     ///
     /// This directory DOES NOT belong to ant-host-agent, be careful with it!
     pub install_root_dir: PathBuf,
+
+    /// The persist directories of the services, see PERSIST_DIR.
+    ///
+    /// This directory DOES NOT belong to ant-host-agent either.
+    pub persist_root_dir: PathBuf,
+
+    /// The keys deployment files must be signed by to be installed, ant-zookeeper's. None only if
+    /// installing unverified was asked for, see `load_trusted_keys`.
+    pub trusted_keys: Option<Arc<TrustedKeys>>,
+
+    /// The command prefix to run a command inside the ant-gateway container, e.g.
+    /// `docker exec ant-gateway`.
+    pub gateway_exec: Vec<String>,
+
+    /// The command to check a Prometheus rules file, given as the last argument, e.g.
+    /// `promtool check rules`.
+    pub monitor_check_rules: Vec<String>,
+
+    /// The command to reload ant-monitor's Prometheus after its rules changed.
+    pub monitor_reload: Vec<String>,
+}
+
+/// The keys deployment files must be signed by, from the PEM file at `path`, the
+/// `ANT_HOST_AGENT_TRUSTED_KEYS` ant-zookeeper provisions with every deployment of the agent. Fails
+/// without them, unless `install_unverified` explicitly asks to install deployments without
+/// verifying their provenance, like for the bootstrap deployment of an agent.
+pub fn load_trusted_keys(
+    path: Option<&str>,
+    install_unverified: bool,
+) -> Result<Option<Arc<TrustedKeys>>, anyhow::Error> {
+    if install_unverified {
+        warn!("ANT-ERR-208: Installing deployments without verifying their provenance, as asked!");
+        return Ok(None);
+    }
+
+    let path = path.ok_or_else(|| {
+        anyhow::Error::msg(
+            "No ANT_HOST_AGENT_TRUSTED_KEYS variable, set ANT_HOST_AGENT_INSTALL_UNVERIFIED=true \
+             to install deployments without verifying their provenance",
+        )
+    })?;
+    let pem = std::fs::read_to_string(path)
+        .with_context(|| format!("read ANT_HOST_AGENT_TRUSTED_KEYS at {path}"))?;
+    let trusted_keys = TrustedKeys::from_pem(&pem)
+        .context("ANT_HOST_AGENT_TRUSTED_KEYS were not Ed25519 public keys in PEM")?;
+
+    Ok(Some(Arc::new(trusted_keys)))
 }
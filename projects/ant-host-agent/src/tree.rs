@@ -0,0 +1,455 @@
+//! The files of a deployment as a tree of content hashes, so only the files a host does not hold
+//! yet are sent to it. The host keeps every file it receives in a store by its SHA256, and
+//! assembles deployments from there, verifying every file on the way. Files that differ with every
+//! deployment, like its secrets, are sent inline with the tree instead of kept.
+
+use std::{
+    collections::{BTreeSet, HashSet},
+    fs::File,
+    io::Read,
+    os::unix::fs::PermissionsExt,
+    path::{Component, Path, PathBuf},
+};
+
+use ant_library::provenance::file_sha256;
+use base64ct::{Base64, Encoding};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ServiceTreeEntry {
+    /// The path relative to the root of the deployment, with `/` between components.
+    pub path: String,
+    /// The SHA256 of the file in hex, none for directories.
+    pub sha256: Option<String>,
+    /// The permission bits, e.g. 0o755.
+    pub mode: u32,
+    pub size_bytes: u64,
+    /// The content of the file in base64, for files sent with the tree rather than from the store.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub inline: Option<String>,
+}
+
+/// Every file and directory of a deployment, sorted by path.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ServiceTree {
+    pub entries: Vec<ServiceTreeEntry>,
+}
+
+/// Whether `s` looks like a SHA256 in hex, so it can name a file in the store.
+fn is_sha256(s: &str) -> bool {
+    s.len() == 64
+        && s.bytes()
+            .all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
+}
+
+/// The path of the file with the SHA256 `sha256` in the store at `store`.
+pub fn blob_path(store: &Path, sha256: &str) -> Result<PathBuf, anyhow::Error> {
+    if !is_sha256(sha256) {
+        return Err(anyhow::Error::msg(format!("not a sha256: {sha256}")));
+    }
+
+    Ok(store.join(sha256))
+}
+
+/// The relative path of an entry, refusing any that would leave the root of the deployment.
+fn entry_path(path: &str) -> Result<PathBuf, anyhow::Error> {
+    let path = PathBuf::from(path);
+    if path.as_os_str().is_empty() || !path.components().all(|c| matches!(c, Component::Normal(_)))
+    {
+        return Err(anyhow::Error::msg(format!(
+            "entry {} is not a relative path",
+            path.display()
+        )));
+    }
+
+    Ok(path)
+}
+
+impl ServiceTree {
+    /// The tree of every file and directory under `root`.
+    pub fn from_dir(root: &Path) -> Result<Self, anyhow::Error> {
+        fn walk(
+            root: &Path,
+            dir: &Path,
+            entries: &mut Vec<ServiceTreeEntry>,
+        ) -> Result<(), anyhow::Error> {
+            for entry in std::fs::read_dir(dir)? {
+                let path = entry?.path();
+                let metadata = std::fs::metadata(&path)?;
+                let relative = path
+                    .strip_prefix(root)?
+                    .to_str()
+                    .ok_or_else(|| anyhow::Error::msg(format!("{} is not UTF-8", path.display())))?
+                    .to_string();
+
+                if metadata.is_dir() {
+                    entries.push(ServiceTreeEntry {
+                        path: relative,
+                        sha256: None,
+                        mode: metadata.permissions().mode() & 0o7777,
+                        size_bytes: 0,
+                        inline: None,
+                    });
+                    walk(root, &path, entries)?;
+                } else {
+                    entries.push(ServiceTreeEntry {
+                        path: relative,
+                        sha256: Some(file_sha256(&path)?),
+                        mode: metadata.permissions().mode() & 0o7777,
+                        size_bytes: metadata.len(),
+                        inline: None,
+                    });
+                }
+            }
+
+            Ok(())
+        }
+
+        let mut entries = vec![];
+        walk(root, root, &mut entries)?;
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+
+        Ok(ServiceTree { entries })
+    }
+
+    /// Send the files under `root` whose path `is_inline` with the tree, instead of from the store.
+    pub fn inlining(
+        mut self,
+        root: &Path,
+        is_inline: impl Fn(&str) -> bool,
+    ) -> Result<Self, anyhow::Error> {
+        for entry in &mut self.entries {
+            if entry.sha256.is_some() && is_inline(&entry.path) {
+                let content = std::fs::read(root.join(entry_path(&entry.path)?))?;
+                entry.inline = Some(Base64::encode_string(&content));
+            }
+        }
+
+        Ok(self)
+    }
+
+    /// The SHA256 of every file in the tree that is assembled from the store, once each.
+    pub fn sha256s(&self) -> Vec<String> {
+        self.entries
+            .iter()
+            .filter(|entry| entry.inline.is_none())
+            .filter_map(|entry| entry.sha256.clone())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect()
+    }
+
+    pub fn size_bytes(&self) -> u64 {
+        self.entries.iter().map(|entry| entry.size_bytes).sum()
+    }
+
+    /// Pack the files of the tree under `root` that are not `held`, into a gzipped tarball at
+    /// `dst` with one entry per content, named by its SHA256. Returns how many files and bytes it
+    /// packed.
+    pub fn pack_blobs(
+        &self,
+        root: &Path,
+        held: &HashSet<String>,
+        dst: &Path,
+    ) -> Result<(usize, u64), anyhow::Error> {
+        let mut archive = tar::Builder::new(GzEncoder::new(
+            File::create_new(dst)?,
+            Compression::default(),
+        ));
+
+        let mut packed = HashSet::new();
+        let mut size_bytes = 0;
+        for entry in &self.entries {
+            let Some(sha256) = &entry.sha256 else {
+                continue;
+            };
+            if entry.inline.is_some() || held.contains(sha256) || !packed.insert(sha256) {
+                continue;
+            }
+
+            archive.append_path_with_name(root.join(entry_path(&entry.path)?), sha256)?;
+            size_bytes += entry.size_bytes;
+        }
+
+        archive.into_inner()?.finish()?;
+
+        Ok((packed.len(), size_bytes))
+    }
+
+    /// Assemble the tree at `dst` from the files in the store at `store`, failing if any is
+    /// missing or does not have the content the tree says.
+    pub fn assemble(&self, store: &Path, dst: &Path) -> Result<(), anyhow::Error> {
+        for entry in &self.entries {
+            let path = dst.join(entry_path(&entry.path)?);
+
+            match &entry.sha256 {
+                None => std::fs::create_dir_all(&path)?,
+                Some(sha256) => {
+                    if let Some(parent) = path.parent() {
+                        std::fs::create_dir_all(parent)?;
+                    }
+                    match &entry.inline {
+                        Some(content) => std::fs::write(
+                            &path,
+                            Base64::decode_vec(content).map_err(|e| {
+                                anyhow::Error::msg(format!("malformed {}: {e}", entry.path))
+                            })?,
+                        )?,
+                        None => {
+                            std::fs::copy(blob_path(store, sha256)?, &path).map_err(|e| {
+                                anyhow::Error::msg(format!(
+                                    "missing {sha256} for {}: {e}",
+                                    entry.path
+                                ))
+                            })?;
+                        }
+                    }
+
+                    let actual = file_sha256(&path)?;
+                    if actual != *sha256 {
+                        return Err(anyhow::Error::msg(format!(
+                            "{} has sha256 {actual}, not {sha256}",
+                            entry.path
+                        )));
+                    }
+                    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(entry.mode))?;
+                }
+            }
+        }
+
+        // Directories last, they may not be writable once they have their mode.
+        for entry in self.entries.iter().rev().filter(|e| e.sha256.is_none()) {
+            std::fs::set_permissions(
+                dst.join(entry_path(&entry.path)?),
+                std::fs::Permissions::from_mode(entry.mode),
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Keep the files of a tarball packed by `ServiceTree::pack_blobs` in the store at `store`,
+/// refusing any whose content does not match its name. Returns how many files it kept.
+pub fn store_blobs<R: Read>(packed: R, store: &Path) -> Result<usize, anyhow::Error> {
+    std::fs::create_dir_all(store)?;
+
+    let mut archive = tar::Archive::new(GzDecoder::new(packed));
+    let mut stored = 0;
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let sha256 = entry
+            .path()?
+            .to_str()
+            .ok_or_else(|| anyhow::Error::msg("blob name is not UTF-8"))?
+            .to_string();
+        let path = blob_path(store, &sha256)?;
+
+        // Written aside first, so the store never has a file under the wrong name.
+        let tmp_path = store.join(format!(".{sha256}.tmp"));
+        std::io::copy(&mut entry, &mut File::create(&tmp_path)?)?;
+
+        let actual = file_sha256(&tmp_path)?;
+        if actual != sha256 {
+            std::fs::remove_file(&tmp_path)?;
+            return Err(anyhow::Error::msg(format!(
+                "blob {sha256} has sha256 {actual}"
+            )));
+        }
+
+        std::fs::rename(&tmp_path, &path)?;
+        stored += 1;
+    }
+
+    Ok(stored)
+}
+
+/// Remove the files in the store at `store` that none of the trees `referenced` returns needs
+/// anymore, returning how many. The store is listed before `referenced` is called, and trees are
+/// registered before their files are stored, so files stored meanwhile are never removed.
+pub fn collect_blobs(
+    store: &Path,
+    referenced: impl FnOnce() -> Result<Vec<ServiceTree>, anyhow::Error>,
+) -> Result<usize, anyhow::Error> {
+    let entries = match std::fs::read_dir(store) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e.into()),
+    };
+    let mut blobs = vec![];
+    for entry in entries {
+        let name = entry?.file_name().to_string_lossy().to_string();
+        // Not files still being stored.
+        if is_sha256(&name) {
+            blobs.push(name);
+        }
+    }
+
+    let referenced = referenced()?
+        .iter()
+        .flat_map(|tree| tree.sha256s())
+        .collect::<HashSet<_>>();
+
+    let mut removed = 0;
+    for sha256 in blobs.iter().filter(|sha256| !referenced.contains(*sha256)) {
+        std::fs::remove_file(blob_path(store, sha256)?)?;
+        removed += 1;
+    }
+
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashSet, fs::File, os::unix::fs::PermissionsExt};
+
+    use super::{collect_blobs, store_blobs, ServiceTree};
+
+    #[test]
+    fn tree_round_trips_through_the_store() {
+        let src = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(src.path().join("secrets")).unwrap();
+        std::fs::create_dir_all(src.path().join("empty")).unwrap();
+        std::fs::write(src.path().join("run.sh"), b"#!/bin/bash").unwrap();
+        std::fs::set_permissions(
+            src.path().join("run.sh"),
+            std::fs::Permissions::from_mode(0o755),
+        )
+        .unwrap();
+        std::fs::write(src.path().join("VERSION"), b"v1").unwrap();
+        std::fs::write(src.path().join("secrets").join("jwt.secret"), b"v1").unwrap();
+
+        let tree = ServiceTree::from_dir(src.path()).unwrap();
+        let paths = tree
+            .entries
+            .iter()
+            .map(|entry| entry.path.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            paths,
+            vec![
+                "VERSION",
+                "empty",
+                "run.sh",
+                "secrets",
+                "secrets/jwt.secret"
+            ]
+        );
+        // VERSION and the secret have the same content.
+        assert_eq!(tree.sha256s().len(), 2);
+
+        // The host holds run.sh already, so only the other content is packed.
+        let run_sh = tree.entries[2].sha256.clone().unwrap();
+        let work = tempfile::tempdir().unwrap();
+        let store = work.path().join("store");
+        std::fs::create_dir_all(&store).unwrap();
+        std::fs::copy(src.path().join("run.sh"), store.join(&run_sh)).unwrap();
+
+        let packed = work.path().join("blobs.tar.gz");
+        let (count, size_bytes) = tree
+            .pack_blobs(src.path(), &HashSet::from([run_sh]), &packed)
+            .unwrap();
+        assert_eq!((count, size_bytes), (1, 2));
+        assert_eq!(
+            store_blobs(File::open(&packed).unwrap(), &store).unwrap(),
+            1
+        );
+
+        let dst = work.path().join("assembled");
+        tree.assemble(&store, &dst).unwrap();
+        assert_eq!(ServiceTree::from_dir(&dst).unwrap(), tree);
+    }
+
+    #[test]
+    fn inline_files_are_sent_with_the_tree() {
+        let src = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(src.path().join("secrets")).unwrap();
+        std::fs::write(src.path().join("run.sh"), b"#!/bin/bash").unwrap();
+        std::fs::write(src.path().join("secrets").join("jwt.secret"), b"hunter2").unwrap();
+
+        let tree = ServiceTree::from_dir(src.path())
+            .unwrap()
+            .inlining(src.path(), |path| path.starts_with("secrets/"))
+            .unwrap();
+        assert_eq!(tree.sha256s().len(), 1);
+
+        let work = tempfile::tempdir().unwrap();
+        let store = work.path().join("store");
+        let packed = work.path().join("blobs.tar.gz");
+        let (count, _) = tree
+            .pack_blobs(src.path(), &HashSet::new(), &packed)
+            .unwrap();
+        assert_eq!(count, 1);
+        assert_eq!(
+            store_blobs(File::open(&packed).unwrap(), &store).unwrap(),
+            1
+        );
+
+        let dst = work.path().join("assembled");
+        tree.assemble(&store, &dst).unwrap();
+        assert_eq!(
+            std::fs::read(dst.join("secrets").join("jwt.secret")).unwrap(),
+            b"hunter2"
+        );
+
+        // Inline content is verified like the store.
+        let mut tampered = tree.clone();
+        tampered.entries[2].inline = Some("aHVudGVyMw==".to_string());
+        assert!(tampered
+            .assemble(&store, &work.path().join("tampered"))
+            .is_err());
+    }
+
+    #[test]
+    fn collect_blobs_keeps_referenced_files() {
+        let src = tempfile::tempdir().unwrap();
+        std::fs::write(src.path().join("VERSION"), b"v1").unwrap();
+        let v1 = ServiceTree::from_dir(src.path()).unwrap();
+        std::fs::write(src.path().join("VERSION"), b"v2").unwrap();
+        let v2 = ServiceTree::from_dir(src.path()).unwrap();
+
+        let work = tempfile::tempdir().unwrap();
+        let store = work.path().join("store");
+        assert_eq!(collect_blobs(&store, || Ok(vec![])).unwrap(), 0);
+
+        std::fs::create_dir_all(&store).unwrap();
+        for tree in [&v1, &v2] {
+            let sha256 = tree.sha256s().remove(0);
+            std::fs::write(store.join(&sha256), b"").unwrap();
+        }
+        std::fs::write(store.join(".deadbeef.tmp"), b"").unwrap();
+
+        assert_eq!(collect_blobs(&store, || Ok(vec![v2.clone()])).unwrap(), 1);
+        assert!(!std::fs::exists(store.join(&v1.sha256s()[0])).unwrap());
+        assert!(std::fs::exists(store.join(&v2.sha256s()[0])).unwrap());
+        assert!(std::fs::exists(store.join(".deadbeef.tmp")).unwrap());
+    }
+
+    #[test]
+    fn assemble_refuses_tampered_blobs_and_paths() {
+        let src = tempfile::tempdir().unwrap();
+        std::fs::write(src.path().join("VERSION"), b"v1").unwrap();
+        let tree = ServiceTree::from_dir(src.path()).unwrap();
+        let sha256 = tree.entries[0].sha256.clone().unwrap();
+
+        let work = tempfile::tempdir().unwrap();
+        let store = work.path().join("store");
+        std::fs::create_dir_all(&store).unwrap();
+
+        // Missing from the store
+        assert!(tree.assemble(&store, &work.path().join("a")).is_err());
+
+        // Not the content it is named by
+        std::fs::write(store.join(&sha256), b"v2").unwrap();
+        assert!(tree.assemble(&store, &work.path().join("b")).is_err());
+
+        // Outside of the deployment
+        std::fs::write(store.join(&sha256), b"v1").unwrap();
+        let mut escaping = tree.clone();
+        escaping.entries[0].path = "../VERSION".to_string();
+        assert!(escaping.assemble(&store, &work.path().join("c")).is_err());
+
+        tree.assemble(&store, &work.path().join("d")).unwrap();
+    }
+}
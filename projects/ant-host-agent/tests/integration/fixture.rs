@@ -1,13 +1,20 @@
 use std::{
     fs::{create_dir_all, remove_dir_all},
-    path::PathBuf,
+    path::{Path, PathBuf},
     sync::Arc,
 };
 
-use ant_host_agent::{make_routes, state::AntHostAgentState};
-use ant_library::sd::writer::ServiceDiscoveryWriter;
+use ant_host_agent::{
+    make_routes,
+    state::{load_trusted_keys, AntHostAgentState},
+};
+use ant_library::{
+    provenance::{file_sha256, Provenance, ProvenanceSigner, TrustedKeys},
+    sd::writer::ServiceDiscoveryWriter,
+};
 use ant_library_test::{axum_test_client::TestClient, consul_fixture::ConsulFixture};
 use flate2::{write::GzEncoder, Compression};
+use reqwest::multipart::Form;
 use tempfile::NamedTempFile;
 
 pub struct TestFixture {
@@ -15,7 +22,11 @@ pub struct TestFixture {
     consul: ConsulFixture,
 
     pub test_root_dir: PathBuf,
+    pub install_root_dir: PathBuf,
+    pub persist_root_dir: PathBuf,
     pub client: TestClient,
+    /// Signs deployment files like ant-zookeeper, trusted by the agent.
+    pub signer: ProvenanceSigner,
 }
 
 impl Drop for TestFixture {
@@ -26,6 +37,21 @@ impl Drop for TestFixture {
 
 impl TestFixture {
     pub async fn new(name: &str) -> Self {
+        Self::new_with_exec(name, &["true"]).await
+    }
+
+    /// A fixture running `exec` in place of the commands that check and reload the gateway and
+    /// monitoring configs, e.g. `docker exec ant-gateway`.
+    pub async fn new_with_exec(name: &str, exec: &[&str]) -> Self {
+        Self::new_with(name, exec, true).await
+    }
+
+    /// A fixture of an agent asked to install unverified, like for its bootstrap deployment.
+    pub async fn new_installing_unverified(name: &str) -> Self {
+        Self::new_with(name, &["true"], false).await
+    }
+
+    async fn new_with(name: &str, exec: &[&str], trusted: bool) -> Self {
         let test_root_dir = PathBuf::from(dotenv::var("CARGO_MANIFEST_DIR").unwrap())
             .join("test-fs")
             .join(name);
@@ -37,13 +63,27 @@ impl TestFixture {
         let install_root_dir = test_root_dir.join("service");
         create_dir_all(&install_root_dir).unwrap();
 
+        let persist_root_dir = test_root_dir.join("persist");
+
         let consul = ConsulFixture::new().await;
 
+        let signer = ProvenanceSigner::generate().unwrap();
+
         let state = AntHostAgentState {
             sd: Arc::new(ServiceDiscoveryWriter::new(consul.port())),
             infra_sd: Arc::new(ServiceDiscoveryWriter::new(consul.port())),
             archive_root_dir: archive_root_dir.clone(),
             install_root_dir: install_root_dir.clone(),
+            persist_root_dir: persist_root_dir.clone(),
+            trusted_keys: match trusted {
+                true => Some(Arc::new(
+                    TrustedKeys::from_pem(&signer.public_key_pem().unwrap()).unwrap(),
+                )),
+                false => load_trusted_keys(None, true).unwrap(),
+            },
+            gateway_exec: exec.iter().map(|arg| arg.to_string()).collect(),
+            monitor_check_rules: exec.iter().map(|arg| arg.to_string()).collect(),
+            monitor_reload: exec.iter().map(|arg| arg.to_string()).collect(),
         };
 
         let client = TestClient::new(make_routes(state.clone()).unwrap()).await;
@@ -52,10 +92,43 @@ impl TestFixture {
             client,
             consul,
             test_root_dir,
+            install_root_dir,
+            persist_root_dir,
             archive_root_dir,
+            signer,
         }
     }
 
+    /// The provenance of the deployment file at `path`, signed by `signer`.
+    pub fn sign(
+        &self,
+        signer: &ProvenanceSigner,
+        path: &Path,
+        service_id: &str,
+        version: &str,
+    ) -> String {
+        let signed = signer
+            .sign(&Provenance {
+                project: service_id.to_string(),
+                version: version.to_string(),
+                commit: "deadbeef".to_string(),
+                builder: "ant-zookeeper".to_string(),
+                architecture: "x86_64".to_string(),
+                sha256: file_sha256(path).unwrap(),
+                built_at: chrono::Utc::now(),
+            })
+            .unwrap();
+        serde_json::to_string(&signed).unwrap()
+    }
+
+    /// A service registration of the deployment file at `path`, with its signed provenance.
+    pub async fn registration_form(&self, path: &Path, service_id: &str, version: &str) -> Form {
+        Form::new().file("file", path).await.unwrap().text(
+            "provenance",
+            self.sign(&self.signer, path, service_id, version),
+        )
+    }
+
     /// Create a tarfile from a test directory in the "archives/" directory
     pub fn make_tarfile_fixture(&self, tar_dir_name: &str) -> NamedTempFile {
         std::fs::create_dir_all(self.archive_root_dir.join("tmp")).unwrap();
@@ -0,0 +1,113 @@
+use ant_host_agent::routes::gateway::{
+    gateway_routes_path, gateway_secrets_dir, PutGatewayCertificateRequest, PutGatewayRoutesRequest,
+};
+use hyper::StatusCode;
+use stdext::function_name;
+use tracing_test::traced_test;
+
+use crate::fixture::TestFixture;
+
+const ROUTES: &str = "upstream ant-on-the-web {\n    server antworker002:3231;\n}\n";
+
+#[traced_test]
+#[tokio::test]
+async fn gateway_routes_are_written() {
+    let fixture = TestFixture::new(function_name!()).await;
+
+    let response = fixture
+        .client
+        .put("/gateway/routes")
+        .json(&PutGatewayRoutesRequest {
+            config: ROUTES.to_string(),
+        })
+        .send()
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let written = std::fs::read_to_string(gateway_routes_path(&fixture.persist_root_dir)).unwrap();
+    assert_eq!(written, ROUTES);
+}
+
+#[traced_test]
+#[tokio::test]
+async fn gateway_routes_rejected_by_nginx_are_rolled_back() {
+    let fixture = TestFixture::new_with_exec(function_name!(), &["false"]).await;
+
+    let path = gateway_routes_path(&fixture.persist_root_dir);
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    std::fs::write(&path, ROUTES).unwrap();
+
+    let response = fixture
+        .client
+        .put("/gateway/routes")
+        .json(&PutGatewayRoutesRequest {
+            config: "this is not nginx".to_string(),
+        })
+        .send()
+        .await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), ROUTES);
+}
+
+#[traced_test]
+#[tokio::test]
+async fn gateway_certificate_replaces_secrets() {
+    let fixture = TestFixture::new(function_name!()).await;
+
+    let secrets = gateway_secrets_dir(&fixture.install_root_dir);
+    std::fs::create_dir_all(&secrets).unwrap();
+    std::fs::write(secrets.join("tls_cert.secret"), "old cert").unwrap();
+    std::fs::write(secrets.join("tls_key.secret"), "old key").unwrap();
+
+    let response = fixture
+        .client
+        .put("/gateway/certificate")
+        .json(&PutGatewayCertificateRequest {
+            certificate: "new cert".to_string(),
+            private_key: "new key".to_string(),
+        })
+        .send()
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    assert_eq!(
+        std::fs::read_to_string(secrets.join("tls_cert.secret")).unwrap(),
+        "new cert"
+    );
+    assert_eq!(
+        std::fs::read_to_string(secrets.join("tls_key.secret")).unwrap(),
+        "new key"
+    );
+}
+
+#[traced_test]
+#[tokio::test]
+async fn gateway_certificate_rejected_by_nginx_is_rolled_back() {
+    let fixture = TestFixture::new_with_exec(function_name!(), &["false"]).await;
+
+    let secrets = gateway_secrets_dir(&fixture.install_root_dir);
+    std::fs::create_dir_all(&secrets).unwrap();
+    std::fs::write(secrets.join("tls_cert.secret"), "old cert").unwrap();
+    std::fs::write(secrets.join("tls_key.secret"), "old key").unwrap();
+
+    let response = fixture
+        .client
+        .put("/gateway/certificate")
+        .json(&PutGatewayCertificateRequest {
+            certificate: "not a cert".to_string(),
+            private_key: "not a key".to_string(),
+        })
+        .send()
+        .await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    assert_eq!(
+        std::fs::read_to_string(secrets.join("tls_cert.secret")).unwrap(),
+        "old cert"
+    );
+    assert_eq!(
+        std::fs::read_to_string(secrets.join("tls_key.secret")).unwrap(),
+        "old key"
+    );
+}
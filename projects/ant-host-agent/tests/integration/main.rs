@@ -1,3 +1,5 @@
 pub mod fixture;
+pub mod gateway;
+pub mod monitoring;
 pub mod ping;
 pub mod service;
@@ -0,0 +1,77 @@
+use ant_host_agent::routes::monitoring::{alert_rules_path, PutAlertRulesRequest};
+use hyper::StatusCode;
+use stdext::function_name;
+use tracing_test::traced_test;
+
+use crate::fixture::TestFixture;
+
+const RULES: &str = "groups:\n  - name: ant-on-the-web\n    rules: []\n";
+
+fn put_rules(rules: Option<&str>) -> PutAlertRulesRequest {
+    PutAlertRulesRequest {
+        service_id: "ant-on-the-web".to_string(),
+        environment: "prod".to_string(),
+        rules: rules.map(|r| r.to_string()),
+    }
+}
+
+#[traced_test]
+#[tokio::test]
+async fn alert_rules_are_written_and_removed() {
+    let fixture = TestFixture::new(function_name!()).await;
+    let path = alert_rules_path(&fixture.persist_root_dir, "ant-on-the-web", "prod");
+
+    let response = fixture
+        .client
+        .put("/monitoring/alert-rules")
+        .json(&put_rules(Some(RULES)))
+        .send()
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), RULES);
+
+    let response = fixture
+        .client
+        .put("/monitoring/alert-rules")
+        .json(&put_rules(None))
+        .send()
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(!path.exists());
+}
+
+#[traced_test]
+#[tokio::test]
+async fn alert_rules_rejected_by_promtool_are_not_written() {
+    let fixture = TestFixture::new_with_exec(function_name!(), &["false"]).await;
+
+    let path = alert_rules_path(&fixture.persist_root_dir, "ant-on-the-web", "prod");
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    std::fs::write(&path, RULES).unwrap();
+
+    let response = fixture
+        .client
+        .put("/monitoring/alert-rules")
+        .json(&put_rules(Some("this is not yaml: [")))
+        .send()
+        .await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), RULES);
+}
+
+#[traced_test]
+#[tokio::test]
+async fn alert_rules_with_invalid_service_id_are_rejected() {
+    let fixture = TestFixture::new(function_name!()).await;
+
+    let mut req = put_rules(Some(RULES));
+    req.service_id = "../ant-gateway".to_string();
+    let response = fixture
+        .client
+        .put("/monitoring/alert-rules")
+        .json(&req)
+        .send()
+        .await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
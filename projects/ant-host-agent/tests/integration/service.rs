@@ -1,8 +1,17 @@
-use ant_host_agent::routes::service::{GetServiceResponse, InstallServiceRequest};
+use ant_host_agent::{
+    routes::service::{
+        GetServiceResponse, HeldBlobsRequest, HeldBlobsResponse, InstallServiceRequest,
+        UninstallServiceRequest,
+    },
+    state::load_trusted_keys,
+    tree::ServiceTree,
+};
+use ant_library::provenance::ProvenanceSigner;
 use assertables::assert_contains;
 use hyper::StatusCode;
-use reqwest::multipart::Form;
+use reqwest::multipart::{Form, Part};
 use serde_json::json;
+use std::{collections::HashSet, path::PathBuf};
 use stdext::function_name;
 use tokio::test;
 use tracing_test::traced_test;
@@ -47,6 +56,27 @@ async fn service_installation_fails_invalid_inputs() {
     }
 }
 
+#[test]
+#[traced_test]
+async fn service_uninstallation_fails_invalid_service_id() {
+    let fixture = TestFixture::new(function_name!()).await;
+
+    for service_id in ["", "..", "../etc", "proj1/current"] {
+        let req = UninstallServiceRequest {
+            service_id: service_id.to_string(),
+        };
+
+        let response = fixture
+            .client
+            .delete("/service/service-installation")
+            .json(&req)
+            .send()
+            .await;
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+}
+
 #[test]
 #[traced_test]
 async fn service_registration_plus_installation_smoke() {
@@ -56,7 +86,7 @@ async fn service_registration_plus_installation_smoke() {
     {
         let file = fixture.make_tarfile_fixture("deployment.proj1.v1");
 
-        let req = Form::new().file("file", file.path()).await.unwrap();
+        let req = fixture.registration_form(file.path(), "proj1", "v1").await;
 
         let response = fixture
             .client
@@ -161,7 +191,9 @@ async fn service_registration_plus_installation_docker_smoke() {
     {
         let file = fixture.make_tarfile_fixture("deployment.docker-proj1.v1");
 
-        let req = Form::new().file("file", file.path()).await.unwrap();
+        let req = fixture
+            .registration_form(file.path(), "docker-proj1", "v1")
+            .await;
 
         let response = fixture
             .client
@@ -219,7 +251,9 @@ async fn service_install_replaces_from_env_file_and_keeps_unknown_variables() {
         let file =
             fixture.make_tarfile_fixture("test-replaces-from-env-file-and-keeps-unknown-variables");
 
-        let req = Form::new().file("file", file.path()).await.unwrap();
+        let req = fixture
+            .registration_form(file.path(), "ant-host-agent", "v8")
+            .await;
 
         let response = fixture
             .client
@@ -282,7 +316,9 @@ async fn service_install_returns_200_with_same_destination_version() {
     {
         let file = fixture.make_tarfile_fixture("deployment.proj1.v1");
 
-        let req = Form::new().file("file", file.path()).await.unwrap();
+        let req = fixture
+            .registration_form(file.path(), "ant-host-agent", "v8")
+            .await;
 
         let response = fixture
             .client
@@ -337,7 +373,9 @@ async fn service_install_returns_200_with_same_destination_version() {
     {
         let file = fixture.make_tarfile_fixture("deployment.proj1.v1-different");
 
-        let req = Form::new().file("file", file.path()).await.unwrap();
+        let req = fixture
+            .registration_form(file.path(), "ant-host-agent", "v8")
+            .await;
 
         let response = fixture
             .client
@@ -398,7 +436,7 @@ async fn service_registration_plus_installation_unversioned_smoke() {
     {
         let file = fixture.make_tarfile_fixture("deployment.proj1.v1.global");
 
-        let req = Form::new().file("file", file.path()).await.unwrap();
+        let req = fixture.registration_form(file.path(), "proj1", "v1").await;
 
         let response = fixture
             .client
@@ -444,3 +482,327 @@ async fn service_registration_plus_installation_unversioned_smoke() {
         assert!(std::fs::exists(dir.join(".env")).unwrap());
     }
 }
+
+#[test]
+#[traced_test]
+async fn service_install_refuses_unsigned_or_untrusted_deployments() {
+    let fixture = TestFixture::new(function_name!()).await;
+    let file = fixture.make_tarfile_fixture("deployment.proj1.v1");
+    let untrusted = ProvenanceSigner::generate().unwrap();
+
+    let forms = [
+        // No provenance at all
+        Form::new().file("file", file.path()).await.unwrap(),
+        // Signed by a key the agent does not trust
+        Form::new().file("file", file.path()).await.unwrap().text(
+            "provenance",
+            fixture.sign(&untrusted, file.path(), "proj1", "v1"),
+        ),
+        // Signed for another version
+        Form::new().file("file", file.path()).await.unwrap().text(
+            "provenance",
+            fixture.sign(&fixture.signer, file.path(), "proj1", "v2"),
+        ),
+    ];
+
+    for form in forms {
+        let response = fixture
+            .client
+            .post("/service/service-registration")
+            .header("X-Ant-Service-Id", "proj1")
+            .header("X-Ant-Version", "v1")
+            .multipart(form)
+            .send()
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = fixture
+            .client
+            .post("/service/service-installation")
+            .json(&InstallServiceRequest {
+                service_id: "proj1".to_string(),
+                version: "v1".to_string(),
+            })
+            .send()
+            .await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_contains!(response.text().await.to_lowercase(), "provenance");
+        assert!(!std::fs::exists(fixture.install_root_dir.join("proj1").join("v1.1")).unwrap());
+    }
+}
+
+#[test]
+#[traced_test]
+async fn service_trusted_keys_required_unless_installing_unverified() {
+    let fixture = TestFixture::new(function_name!()).await;
+
+    let err = load_trusted_keys(None, false).unwrap_err();
+    assert_contains!(err.to_string(), "ANT_HOST_AGENT_INSTALL_UNVERIFIED");
+
+    let path = fixture.test_root_dir.join("trusted-keys.pem");
+    assert!(load_trusted_keys(path.to_str(), false).is_err());
+
+    std::fs::write(&path, fixture.signer.public_key_pem().unwrap()).unwrap();
+    assert!(load_trusted_keys(path.to_str(), false).unwrap().is_some());
+
+    assert!(load_trusted_keys(None, true).unwrap().is_none());
+    assert!(logs_contain("ANT-ERR-208"));
+}
+
+#[test]
+#[traced_test]
+async fn service_install_unverified_skips_verification() {
+    let fixture = TestFixture::new_installing_unverified(function_name!()).await;
+    let file = fixture.make_tarfile_fixture("deployment.proj1.v1");
+
+    let response = fixture
+        .client
+        .post("/service/service-registration")
+        .header("X-Ant-Service-Id", "proj1")
+        .header("X-Ant-Version", "v1")
+        .multipart(Form::new().file("file", file.path()).await.unwrap())
+        .send()
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = fixture
+        .client
+        .post("/service/service-installation")
+        .json(&InstallServiceRequest {
+            service_id: "proj1".to_string(),
+            version: "v1".to_string(),
+        })
+        .send()
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(std::fs::exists(fixture.install_root_dir.join("proj1").join("v1.1")).unwrap());
+    assert!(logs_contain("ANT-ERR-209"));
+}
+
+#[test]
+#[traced_test]
+async fn service_registration_in_parts_sends_only_new_files() {
+    let fixture = TestFixture::new(function_name!()).await;
+
+    let src = fixture.test_root_dir.join("src");
+    dircpy::copy_dir(
+        PathBuf::from(dotenv::var("CARGO_MANIFEST_DIR").unwrap())
+            .join("tests")
+            .join("integration")
+            .join("archives")
+            .join("deployment.proj1.v1"),
+        &src,
+    )
+    .unwrap();
+
+    for version in ["v1", "v2"] {
+        std::fs::write(src.join("VERSION"), version).unwrap();
+        let tree = ServiceTree::from_dir(&src).unwrap();
+
+        let held: HeldBlobsResponse = fixture
+            .client
+            .post("/service/service-blobs")
+            .json(&HeldBlobsRequest {
+                sha256s: tree.sha256s(),
+            })
+            .send()
+            .await
+            .json()
+            .await;
+        let held = held.held.into_iter().collect::<HashSet<_>>();
+
+        let blobs_path = fixture
+            .test_root_dir
+            .join(format!("blobs.{version}.tar.gz"));
+        let (count, _) = tree.pack_blobs(&src, &held, &blobs_path).unwrap();
+        match version {
+            "v1" => assert_eq!(count, tree.sha256s().len()),
+            // Only VERSION changed since
+            _ => assert_eq!(count, 1),
+        }
+
+        let tree_path = fixture.test_root_dir.join(format!("tree.{version}.json"));
+        std::fs::write(&tree_path, serde_json::to_vec(&tree).unwrap()).unwrap();
+        let form = Form::new()
+            .part("tree", Part::bytes(std::fs::read(&tree_path).unwrap()))
+            .part("blobs", Part::bytes(std::fs::read(&blobs_path).unwrap()))
+            .text(
+                "provenance",
+                fixture.sign(&fixture.signer, &tree_path, "proj1", version),
+            );
+        let response = fixture
+            .client
+            .post("/service/service-delta-registration")
+            .header("X-Ant-Service-Id", "proj1")
+            .header("X-Ant-Version", version)
+            .multipart(form)
+            .send()
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = fixture
+            .client
+            .post("/service/service-installation")
+            .json(&InstallServiceRequest {
+                service_id: "proj1".to_string(),
+                version: version.to_string(),
+            })
+            .send()
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let dir = fixture
+            .install_root_dir
+            .join("proj1")
+            .join(format!("{version}.1"));
+        let files = |tree: ServiceTree| {
+            tree.entries
+                .into_iter()
+                .map(|entry| (entry.path, entry.sha256))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(files(ServiceTree::from_dir(&dir).unwrap()), files(tree));
+    }
+}
+
+#[test]
+#[traced_test]
+async fn service_installation_in_parts_removes_unused_blobs() {
+    let fixture = TestFixture::new(function_name!()).await;
+    let store = fixture
+        .test_root_dir
+        .join("fs")
+        .join("blobs")
+        .join("sha256");
+
+    let src = fixture.test_root_dir.join("src");
+    dircpy::copy_dir(
+        PathBuf::from(dotenv::var("CARGO_MANIFEST_DIR").unwrap())
+            .join("tests")
+            .join("integration")
+            .join("archives")
+            .join("deployment.proj1.v1"),
+        &src,
+    )
+    .unwrap();
+    std::fs::create_dir_all(src.join("secrets")).unwrap();
+
+    let mut trees = vec![];
+    for version in ["v1", "v2"] {
+        std::fs::write(src.join("VERSION"), version).unwrap();
+        std::fs::write(src.join("secrets").join("jwt.secret"), version).unwrap();
+        let tree = ServiceTree::from_dir(&src)
+            .unwrap()
+            .inlining(&src, |path| path.starts_with("secrets/"))
+            .unwrap();
+
+        let blobs_path = fixture
+            .test_root_dir
+            .join(format!("blobs.{version}.tar.gz"));
+        tree.pack_blobs(&src, &HashSet::new(), &blobs_path).unwrap();
+
+        let tree_path = fixture.test_root_dir.join(format!("tree.{version}.json"));
+        std::fs::write(&tree_path, serde_json::to_vec(&tree).unwrap()).unwrap();
+        let form = Form::new()
+            .part("tree", Part::bytes(std::fs::read(&tree_path).unwrap()))
+            .part("blobs", Part::bytes(std::fs::read(&blobs_path).unwrap()))
+            .text(
+                "provenance",
+                fixture.sign(&fixture.signer, &tree_path, "proj1", version),
+            );
+        let response = fixture
+            .client
+            .post("/service/service-delta-registration")
+            .header("X-Ant-Service-Id", "proj1")
+            .header("X-Ant-Version", version)
+            .multipart(form)
+            .send()
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = fixture
+            .client
+            .post("/service/service-installation")
+            .json(&InstallServiceRequest {
+                service_id: "proj1".to_string(),
+                version: version.to_string(),
+            })
+            .send()
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let dir = fixture
+            .install_root_dir
+            .join("proj1")
+            .join(format!("{version}.1"));
+        assert_eq!(
+            std::fs::read_to_string(dir.join("secrets").join("jwt.secret")).unwrap(),
+            version
+        );
+        trees.push(tree);
+    }
+
+    // Only what v2 is assembled from is kept, never the secrets.
+    let mut held = std::fs::read_dir(&store)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+        .collect::<Vec<_>>();
+    held.sort();
+    assert_eq!(held, trees[1].sha256s());
+    assert!(!std::fs::exists(
+        fixture
+            .test_root_dir
+            .join("fs")
+            .join("deployment.proj1.v1.tree.json")
+    )
+    .unwrap());
+}
+
+#[test]
+#[traced_test]
+async fn service_registration_in_parts_refuses_tampered_blobs() {
+    let fixture = TestFixture::new(function_name!()).await;
+
+    // A blob named by the SHA256 of other content
+    let blob_dir = fixture.test_root_dir.join("blob");
+    std::fs::create_dir_all(&blob_dir).unwrap();
+    std::fs::write(blob_dir.join("a"), b"real").unwrap();
+    let real = ServiceTree::from_dir(&blob_dir).unwrap();
+    std::fs::write(blob_dir.join("a"), b"fake").unwrap();
+
+    let blobs_path = fixture.test_root_dir.join("blobs.tar.gz");
+    real.pack_blobs(&blob_dir, &HashSet::new(), &blobs_path)
+        .unwrap();
+
+    let tree_path = fixture.test_root_dir.join("tree.json");
+    std::fs::write(&tree_path, serde_json::to_vec(&real).unwrap()).unwrap();
+    let form = Form::new()
+        .part("tree", Part::bytes(std::fs::read(&tree_path).unwrap()))
+        .part("blobs", Part::bytes(std::fs::read(&blobs_path).unwrap()))
+        .text(
+            "provenance",
+            fixture.sign(&fixture.signer, &tree_path, "proj1", "v1"),
+        );
+    let response = fixture
+        .client
+        .post("/service/service-delta-registration")
+        .header("X-Ant-Service-Id", "proj1")
+        .header("X-Ant-Version", "v1")
+        .multipart(form)
+        .send()
+        .await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    // Nothing to assemble the tree from
+    let response = fixture
+        .client
+        .post("/service/service-installation")
+        .json(&InstallServiceRequest {
+            service_id: "proj1".to_string(),
+            version: "v1".to_string(),
+        })
+        .send()
+        .await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert!(!std::fs::exists(fixture.install_root_dir.join("proj1").join("v1.1")).unwrap());
+}
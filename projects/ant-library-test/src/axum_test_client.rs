@@ -44,6 +44,7 @@ use tokio::net::TcpListener;
 pub struct TestClient {
     client: reqwest::Client,
     addr: SocketAddr,
+    bearer_token: Option<String>,
 }
 
 impl TestClient {
@@ -63,7 +64,31 @@ impl TestClient {
             .build()
             .unwrap();
 
-        TestClient { client, addr }
+        TestClient {
+            client,
+            addr,
+            bearer_token: None,
+        }
+    }
+
+    /// The same client, sending `token` as the bearer token of every request.
+    pub fn with_bearer_token(&self, token: &str) -> Self {
+        TestClient {
+            bearer_token: Some(token.to_string()),
+            ..self.clone()
+        }
+    }
+
+    fn request(&self, method: http::Method, url: &str) -> RequestBuilder {
+        let builder = self
+            .client
+            .request(method, format!("http://{}{}", self.addr, url));
+        RequestBuilder {
+            builder: match &self.bearer_token {
+                Some(token) => builder.bearer_auth(token),
+                None => builder,
+            },
+        }
     }
 
     /// returns the base URL (http://ip:port) for this TestClient
@@ -75,39 +100,27 @@ impl TestClient {
     }
 
     pub fn get(&self, url: &str) -> RequestBuilder {
-        RequestBuilder {
-            builder: self.client.get(format!("http://{}{}", self.addr, url)),
-        }
+        self.request(http::Method::GET, url)
     }
 
     pub fn head(&self, url: &str) -> RequestBuilder {
-        RequestBuilder {
-            builder: self.client.head(format!("http://{}{}", self.addr, url)),
-        }
+        self.request(http::Method::HEAD, url)
     }
 
     pub fn post(&self, url: &str) -> RequestBuilder {
-        RequestBuilder {
-            builder: self.client.post(format!("http://{}{}", self.addr, url)),
-        }
+        self.request(http::Method::POST, url)
     }
 
     pub fn put(&self, url: &str) -> RequestBuilder {
-        RequestBuilder {
-            builder: self.client.put(format!("http://{}{}", self.addr, url)),
-        }
+        self.request(http::Method::PUT, url)
     }
 
     pub fn patch(&self, url: &str) -> RequestBuilder {
-        RequestBuilder {
-            builder: self.client.patch(format!("http://{}{}", self.addr, url)),
-        }
+        self.request(http::Method::PATCH, url)
     }
 
     pub fn delete(&self, url: &str) -> RequestBuilder {
-        RequestBuilder {
-            builder: self.client.delete(format!("http://{}{}", self.addr, url)),
-        }
+        self.request(http::Method::DELETE, url)
     }
 }
 
@@ -19,6 +19,7 @@ pub mod host_architecture;
 pub mod manifest_file;
 pub mod middleware;
 pub mod process;
+pub mod provenance;
 pub mod rng;
 pub mod routes;
 pub mod sd;
@@ -0,0 +1,286 @@
+//! Signed statements of where a build artifact came from. `anthill build` signs one for every
+//! artifact it builds, and ant-zookeeper for every service file it replicates to a host, so the
+//! receiving end only accepts artifacts from keys it trusts.
+//!
+//! Keys are Ed25519, in PEM: `openssl genpkey -algorithm ed25519 -out signing.key` for a signing
+//! key, and `openssl pkey -in signing.key -pubout` for its public key.
+
+use std::{collections::HashMap, fs::File, io::Read, path::Path};
+
+use base64ct::{Base64, Encoding};
+use chrono::{DateTime, Utc};
+use openssl::{
+    pkey::{PKey, Private, Public},
+    sign::{Signer, Verifier},
+};
+use serde::{Deserialize, Serialize};
+use sha2::Digest;
+
+/// What was built, from which commit, by what, and the SHA256 of the result.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Provenance {
+    pub project: String,
+    pub version: String,
+    /// The git commit the artifact was built from.
+    pub commit: String,
+    /// What built it, e.g. `anthill/0.1.0`.
+    pub builder: String,
+    pub architecture: String,
+    /// The SHA256 of the artifact, in hex.
+    pub sha256: String,
+    pub built_at: DateTime<Utc>,
+}
+
+/// A provenance statement, with the signature of a key over it.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SignedProvenance {
+    /// The provenance in JSON, exactly as it was signed.
+    pub statement: String,
+    /// The ID of the key that signed it, see `key_id`.
+    pub key_id: String,
+    /// The Ed25519 signature of the statement, in base64.
+    pub signature: String,
+}
+
+/// The SHA256 of the file at `path`, in hex, as provenance statements have it.
+pub fn file_sha256(path: &Path) -> Result<String, anyhow::Error> {
+    let mut file = File::open(path)?;
+    let mut hasher = sha2::Sha256::new();
+    let mut buf = [0u8; 64 * 1024];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect())
+}
+
+/// The ID of a public key, the start of the SHA256 of its DER encoding in hex.
+fn key_id(key: &PKey<Public>) -> Result<String, anyhow::Error> {
+    let digest = sha2::Sha256::digest(key.public_key_to_der()?);
+    Ok(digest.iter().take(8).map(|b| format!("{b:02x}")).collect())
+}
+
+pub struct ProvenanceSigner {
+    key: PKey<Private>,
+    public_key: PKey<Public>,
+    key_id: String,
+}
+
+impl ProvenanceSigner {
+    /// A signer from an Ed25519 private key in PEM.
+    pub fn from_pem(pem: &str) -> Result<Self, anyhow::Error> {
+        Self::new(PKey::private_key_from_pem(pem.as_bytes())?)
+    }
+
+    /// A signer with a new key, for tests and local development.
+    pub fn generate() -> Result<Self, anyhow::Error> {
+        Self::new(PKey::generate_ed25519()?)
+    }
+
+    fn new(key: PKey<Private>) -> Result<Self, anyhow::Error> {
+        if key.id() != openssl::pkey::Id::ED25519 {
+            return Err(anyhow::Error::msg("provenance keys must be Ed25519"));
+        }
+
+        let public_key = PKey::public_key_from_der(&key.public_key_to_der()?)?;
+        let key_id = key_id(&public_key)?;
+        Ok(ProvenanceSigner {
+            key,
+            public_key,
+            key_id,
+        })
+    }
+
+    pub fn key_id(&self) -> &str {
+        &self.key_id
+    }
+
+    /// The public key to trust the signatures of, in PEM.
+    pub fn public_key_pem(&self) -> Result<String, anyhow::Error> {
+        Ok(String::from_utf8(self.public_key.public_key_to_pem()?)?)
+    }
+
+    pub fn sign(&self, provenance: &Provenance) -> Result<SignedProvenance, anyhow::Error> {
+        let statement = serde_json::to_string(provenance)?;
+        let signature =
+            Signer::new_without_digest(&self.key)?.sign_oneshot_to_vec(statement.as_bytes())?;
+
+        Ok(SignedProvenance {
+            statement,
+            key_id: self.key_id.clone(),
+            signature: Base64::encode_string(&signature),
+        })
+    }
+}
+
+/// The public keys whose provenance statements are trusted.
+#[derive(Default, Debug)]
+pub struct TrustedKeys {
+    keys: HashMap<String, PKey<Public>>,
+}
+
+impl TrustedKeys {
+    /// Trust every Ed25519 public key in a PEM bundle.
+    pub fn from_pem(bundle: &str) -> Result<Self, anyhow::Error> {
+        const END: &str = "-----END PUBLIC KEY-----";
+
+        let mut keys = HashMap::new();
+        for block in bundle.split_inclusive(END).filter(|b| b.contains(END)) {
+            let key = PKey::public_key_from_pem(block.trim().as_bytes())?;
+            if key.id() != openssl::pkey::Id::ED25519 {
+                return Err(anyhow::Error::msg("provenance keys must be Ed25519"));
+            }
+            keys.insert(key_id(&key)?, key);
+        }
+
+        Ok(TrustedKeys { keys })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// The provenance of a statement signed by a trusted key, or why it is not trusted.
+    pub fn verify(&self, signed: &SignedProvenance) -> Result<Provenance, anyhow::Error> {
+        let key = self.keys.get(&signed.key_id).ok_or_else(|| {
+            anyhow::Error::msg(format!("signed by untrusted key {}", signed.key_id))
+        })?;
+
+        let signature = Base64::decode_vec(&signed.signature)
+            .map_err(|e| anyhow::Error::msg(format!("signature is not base64: {e}")))?;
+        let is_valid = Verifier::new_without_digest(key)?
+            .verify_oneshot(&signature, signed.statement.as_bytes())
+            .unwrap_or(false);
+        if !is_valid {
+            return Err(anyhow::Error::msg(format!(
+                "signature of key {} does not match the statement",
+                signed.key_id
+            )));
+        }
+
+        Ok(serde_json::from_str(&signed.statement)?)
+    }
+
+    /// The provenance of a statement signed by a trusted key, if it is for an artifact with the
+    /// SHA256 `sha256`, of the version `version` of `project`.
+    pub fn verify_artifact(
+        &self,
+        signed: &SignedProvenance,
+        project: &str,
+        version: &str,
+        sha256: &str,
+    ) -> Result<Provenance, anyhow::Error> {
+        let provenance = self.verify(signed)?;
+
+        if provenance.sha256 != sha256 {
+            return Err(anyhow::Error::msg(format!(
+                "provenance is for sha256 {}, not {sha256}",
+                provenance.sha256
+            )));
+        }
+        if provenance.project != project || provenance.version != version {
+            return Err(anyhow::Error::msg(format!(
+                "provenance is for {} {}, not {project} {version}",
+                provenance.project, provenance.version
+            )));
+        }
+
+        Ok(provenance)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use crate::provenance::{file_sha256, Provenance, ProvenanceSigner, TrustedKeys};
+
+    fn provenance() -> Provenance {
+        Provenance {
+            project: "ant-host-agent".to_string(),
+            version: "123-2026-1-1-0-0-deadbeef".to_string(),
+            commit: "deadbeef".to_string(),
+            builder: "anthill/0.1.0".to_string(),
+            architecture: "aarch64".to_string(),
+            sha256: "ab".repeat(32),
+            built_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn trusted_signatures_verify() {
+        let signer = ProvenanceSigner::generate().unwrap();
+        let other = ProvenanceSigner::generate().unwrap();
+        let bundle = signer.public_key_pem().unwrap() + &other.public_key_pem().unwrap();
+        let trusted = TrustedKeys::from_pem(&bundle).unwrap();
+
+        for signer in [signer, other] {
+            let signed = signer.sign(&provenance()).unwrap();
+            assert_eq!(signed.key_id, signer.key_id());
+            let verified = trusted
+                .verify_artifact(
+                    &signed,
+                    "ant-host-agent",
+                    "123-2026-1-1-0-0-deadbeef",
+                    &"ab".repeat(32),
+                )
+                .unwrap();
+            assert_eq!(verified.commit, "deadbeef");
+        }
+    }
+
+    #[test]
+    fn untrusted_or_tampered_signatures_do_not_verify() {
+        let signer = ProvenanceSigner::generate().unwrap();
+        let trusted = TrustedKeys::from_pem(&signer.public_key_pem().unwrap()).unwrap();
+
+        let untrusted = ProvenanceSigner::generate().unwrap();
+        assert!(trusted
+            .verify(&untrusted.sign(&provenance()).unwrap())
+            .is_err());
+
+        let mut tampered = signer.sign(&provenance()).unwrap();
+        tampered.statement = tampered.statement.replace("deadbeef", "cafebabe");
+        assert!(trusted.verify(&tampered).is_err());
+
+        let signed = signer.sign(&provenance()).unwrap();
+        assert!(trusted
+            .verify_artifact(
+                &signed,
+                "ant-host-agent",
+                "123-2026-1-1-0-0-deadbeef",
+                &"cd".repeat(32)
+            )
+            .is_err());
+        assert!(trusted
+            .verify_artifact(
+                &signed,
+                "ant-gateway",
+                "123-2026-1-1-0-0-deadbeef",
+                &"ab".repeat(32)
+            )
+            .is_err());
+    }
+
+    #[test]
+    fn file_sha256_is_hex() {
+        let path = std::env::temp_dir().join(format!("file-sha256-{}", std::process::id()));
+        std::fs::write(&path, b"abc").unwrap();
+
+        let sha256 = file_sha256(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            sha256,
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+}
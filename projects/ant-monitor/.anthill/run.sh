@@ -2,6 +2,9 @@
 
 set -euo pipefail
 
+mkdir -p "$PERSIST_DIR/rules"
+ln -sfn "$PERSIST_DIR/rules" rules
+
 echo "Starting service..."
 exec "${BIN:-./prometheus}" \
   --web.enable-admin-api \
@@ -0,0 +1,30 @@
+BEGIN;
+
+-- A migration file of a database project, applied by the pipeline to that project's database in an
+-- environment.
+create table database_migration (
+  database_migration_id text primary key default ('dbm-' || random_string(12)),
+
+  project_id text not null, -- The database project, e.g. 'ant-data-farm'.
+  environment varchar(16) not null, -- "prod", "beta", ...
+  host_id text not null, -- The host running the database instance.
+  migration_name text not null, -- The migration filename, e.g. '045_add_ant_tags.sql'.
+
+  unique (project_id, environment, host_id, migration_name),
+
+  migration_sha256 text not null, -- Hex SHA-256 of the migration file, applied migrations are immutable.
+
+  -- The revision that applied the migration. NULL for migrations applied before the pipeline managed
+  -- the database, that were adopted as-is.
+  revision_id text,
+
+  applied_at timestamp with time zone not null default now(),
+
+  foreign key (project_id) references project(project_id),
+  foreign key (revision_id) references revision(revision_id),
+  foreign key (host_id) references host(host_id)
+);
+
+insert into migration (migration_label) values ('add-database-migration');
+
+COMMIT;
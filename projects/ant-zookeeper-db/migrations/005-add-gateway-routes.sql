@@ -0,0 +1,40 @@
+BEGIN;
+
+-- The routes a project declares in its anthill.json, as of a revision, for the gateway of an
+-- environment. The gateway config is rendered from the latest routes of every project.
+create table gateway_route (
+  gateway_route_id text primary key default ('gr-' || random_string(12)),
+
+  environment varchar(16) not null, -- "prod", "beta", ...
+  project_id text not null, -- The project being routed to, e.g. 'ant-on-the-web'.
+  revision_id text not null, -- The revision of the project that declared these routes.
+
+  unique (environment, project_id, revision_id),
+
+  project_routes text not null, -- JSON-encoded, the routes and the port they are served on.
+
+  created_at timestamp with time zone not null default now(),
+
+  foreign key (project_id) references project(project_id),
+  foreign key (revision_id) references revision(revision_id)
+);
+
+-- The nginx config rendered for the gateway of an environment, by the revision that rendered it.
+create table gateway_config (
+  gateway_config_id text primary key default ('gc-' || random_string(12)),
+
+  environment varchar(16) not null, -- "prod", "beta", ...
+  revision_id text not null, -- The revision whose RouteUpdate rendered the config.
+
+  unique (environment, revision_id),
+
+  config text not null, -- The rendered nginx config.
+
+  created_at timestamp with time zone not null default now(),
+
+  foreign key (revision_id) references revision(revision_id)
+);
+
+insert into migration (migration_label) values ('add-gateway-routes');
+
+COMMIT;
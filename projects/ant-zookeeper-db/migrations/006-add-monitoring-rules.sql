@@ -0,0 +1,26 @@
+BEGIN;
+
+-- The alert rules or log rules a project ships in its artifact, as of a revision, for an
+-- environment. The latest rules of a project are the ones deployed, unwinding a revision deletes its
+-- rules and redeploys the ones before it.
+create table monitoring_rules (
+  monitoring_rules_id text primary key default ('mr-' || random_string(12)),
+
+  rules_kind varchar(16) not null, -- "alert" or "log".
+  environment varchar(16) not null, -- "prod", "beta", ...
+  project_id text not null, -- The project the rules are about, e.g. 'ant-on-the-web'.
+  revision_id text not null, -- The revision of the project that shipped these rules.
+
+  unique (rules_kind, environment, project_id, revision_id),
+
+  rules text not null, -- The rules file, as shipped.
+
+  created_at timestamp with time zone not null default now(),
+
+  foreign key (project_id) references project(project_id),
+  foreign key (revision_id) references revision(revision_id)
+);
+
+insert into migration (migration_label) values ('add-monitoring-rules');
+
+COMMIT;
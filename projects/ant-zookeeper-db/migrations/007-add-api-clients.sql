@@ -0,0 +1,33 @@
+BEGIN;
+
+-- The clients of the ant-zookeeper API, authenticated with a bearer token of which only the hash is
+-- stored.
+create table zookeeper_client (
+  client_id text primary key default ('zc-' || random_string(10)),
+
+  client_name text not null unique, -- e.g. 'kaspar' or 'github-actions'.
+  token_hash varchar(256) not null unique,
+  -- "viewer" can read, "deployer" can also drive deployments, "admin" can also change pipelines,
+  -- environments and certificates. "ci" can only register revisions and artifacts.
+  client_role varchar(16) not null,
+
+  created_at timestamp with time zone not null default now(),
+  updated_at timestamp with time zone not null default now()
+);
+
+-- Who changed what through the ant-zookeeper API.
+create table audit_log (
+  audit_log_id text primary key default ('audit-' || random_string(10)),
+
+  client_id text not null,
+  action varchar(64) not null, -- e.g. 'retry-job' or 'put-pipeline'.
+  details text not null, -- The JSON request that made the change.
+
+  created_at timestamp with time zone not null default now(),
+
+  foreign key (client_id) references zookeeper_client(client_id)
+);
+
+insert into migration (migration_label) values ('add-api-clients');
+
+COMMIT;
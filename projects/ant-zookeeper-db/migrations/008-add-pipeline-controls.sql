@@ -0,0 +1,19 @@
+BEGIN;
+
+-- A node that requires approval stays executable, but is not claimed until it is approved.
+alter table pipeline_engine_node
+  add column requires_approval boolean not null default false,
+  add column approved_at timestamp with time zone;
+
+-- While a project is paused, no nodes of its pipelines are claimed. Nodes already in progress finish.
+create table pipeline_engine_paused_project (
+  project_id text primary key,
+
+  created_at timestamp with time zone not null default now(),
+
+  foreign key (project_id) references project(project_id)
+);
+
+insert into migration (migration_label) values ('add-pipeline-controls');
+
+COMMIT;
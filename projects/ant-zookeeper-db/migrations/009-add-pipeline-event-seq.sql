@@ -0,0 +1,27 @@
+BEGIN;
+
+-- Pipeline events share the sequence of node events, so streams of both can be resumed from one ID.
+alter table pipeline_engine_pipeline_event
+  add column event_seq bigint not null default nextval('pipeline_engine_node_event_event_seq_seq');
+
+create index idx_pipeline_event_seq on pipeline_engine_pipeline_event (event_seq);
+create index idx_node_event_seq on pipeline_engine_node_event (event_seq);
+
+-- Transactions take their event_seq when they insert events, not when they commit, so a stream
+-- resuming after one event_seq could skip events of a transaction that committed later. Streams read
+-- transition_seq instead, numbered only after the events committed. See number_transitions.
+create sequence pipeline_engine_transition_seq;
+
+alter table pipeline_engine_node_event add column transition_seq bigint;
+alter table pipeline_engine_pipeline_event add column transition_seq bigint;
+
+create unique index idx_node_event_transition_seq on pipeline_engine_node_event (transition_seq);
+create unique index idx_pipeline_event_transition_seq on pipeline_engine_pipeline_event (transition_seq);
+create index idx_node_event_unnumbered on pipeline_engine_node_event (event_seq)
+  where transition_seq is null;
+create index idx_pipeline_event_unnumbered on pipeline_engine_pipeline_event (event_seq)
+  where transition_seq is null;
+
+insert into migration (migration_label) values ('add-pipeline-event-seq');
+
+COMMIT;
@@ -0,0 +1,25 @@
+BEGIN;
+
+-- The TLS certificate of each environment, renewed by ant-zookeeper in the background some days
+-- before it expires. The certificate itself is a version of the tls_cert and tls_key secrets.
+create table certificate (
+  environment varchar(16) primary key, -- "prod", "beta", ...
+  domains text[] not null, -- The domains on the certificate, the first being its subject.
+
+  state text not null default 'pending', -- pending | valid | failing
+  expires_at timestamp with time zone, -- Null until a certificate was issued.
+  next_attempt_at timestamp with time zone not null default now(), -- When to renew (or retry) next.
+  failed_attempts int not null default 0, -- Failed renewals in a row, reset by a success.
+  last_error text,
+
+  created_at timestamp with time zone not null default now(),
+  updated_at timestamp with time zone not null default now()
+);
+
+-- Certificates roll out to the gateway hosts in pipelines of their own project, with a revision per
+-- rollout, so they don't get in the way of the revisions of ant-gateway itself.
+insert into project (project_id, owned) values ('ant-gateway-certificate', true);
+
+insert into migration (migration_label) values ('add-certificate-renewal');
+
+COMMIT;
@@ -0,0 +1,41 @@
+BEGIN;
+
+-- Secrets are stored in the database, encrypted under the master key of ant-zookeeper, instead of
+-- as plaintext files in secrets-db. The files are imported once on startup. The versions registered
+-- so far, like the certificates of the gateway, only exist here: they are kept as
+-- secret_plaintext, and sealed by ant-zookeeper on its next startup, which then drops the column
+-- and makes secret_nonce and secret_value required (see secrets::seal_plaintext_secrets).
+drop table project_secret; -- Never written, revision_secret pins versions instead.
+
+alter table secret rename column secret_value to secret_plaintext;
+
+alter table secret
+  alter column secret_plaintext drop not null,
+  add column secret_value bytea, -- The value of the secret, null until secret_plaintext is sealed.
+  add column secret_host text, -- The host of a host-specific secret, null for the whole environment.
+  add column secret_nonce bytea, -- The AES-256-GCM nonce secret_value was encrypted with.
+  alter column secret_environment set not null,
+  drop constraint secret_secret_name_secret_version_secret_environment_key;
+
+comment on column secret.secret_value is 'The value of the secret, encrypted with AES-256-GCM, followed by the tag.';
+
+create unique index secret_version_key
+  on secret (secret_name, secret_environment, coalesce(secret_host, ''), secret_version);
+
+-- The version of each secret a revision deploys with, pinned the first time it is deployed to an
+-- environment (or host), so retries and unwinds of the revision never pick up a rotated secret.
+create table revision_secret (
+  revision_id text not null,
+  secret_id text not null,
+
+  primary key (revision_id, secret_id),
+
+  created_at timestamp with time zone not null default now(),
+
+  foreign key (revision_id) references revision(revision_id),
+  foreign key (secret_id) references secret(secret_id)
+);
+
+insert into migration (migration_label) values ('add-secret-store');
+
+COMMIT;
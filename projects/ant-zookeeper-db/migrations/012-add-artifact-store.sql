@@ -0,0 +1,13 @@
+BEGIN;
+
+-- Artifacts are stored by the SHA256 of their content, as artifacts-db/sha256/<fingerprint>.bld, so
+-- revisions with the same build share a file. Artifacts registered before keep the local_path they
+-- were saved under, and are collected like the others.
+comment on column artifact.local_path is 'The path of the artifact under artifacts-db, sha256/<fingerprint>.bld for new artifacts.';
+comment on column artifact.deleted_at is 'Present if the artifact was garbage collected, its revision can no longer be deployed.';
+
+create index artifact_fingerprint on artifact (fingerprint) where deleted_at is null;
+
+insert into migration (migration_label) values ('add-artifact-store');
+
+COMMIT;
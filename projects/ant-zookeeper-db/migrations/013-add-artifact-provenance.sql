@@ -0,0 +1,9 @@
+BEGIN;
+
+-- The signed provenance statement each artifact was registered with, from a builder key
+-- ant-zookeeper trusts. Null for artifacts registered before statements were required.
+alter table artifact add column provenance text;
+
+insert into migration (migration_label) values ('add-artifact-provenance');
+
+COMMIT;
@@ -0,0 +1,25 @@
+BEGIN;
+
+-- The latest heartbeat of each host running ant-host-agent, as the live inventory of the hosts.
+-- Hosts whose heartbeats stopped are unhealthy, and left out of new deployments.
+create table host_heartbeat (
+  host_id text primary key, -- The host in services.json.
+
+  uptime_seconds bigint not null,
+  disk_total_bytes bigint not null, -- Of the filesystem with the persist directories.
+  disk_available_bytes bigint not null,
+  memory_total_bytes bigint not null,
+  memory_available_bytes bigint not null,
+  services text not null, -- JSON, the installed services with their versions.
+  units text not null, -- JSON, the states of the systemd units of the services.
+
+  first_heartbeat_at timestamp with time zone not null default now(),
+  last_heartbeat_at timestamp with time zone not null default now()
+);
+
+-- ant-host-agent sends them as a client with the "host" role, named like its host.
+comment on column zookeeper_client.client_role is 'One of "viewer", "deployer", "admin", "ci" or "host".';
+
+insert into migration (migration_label) values ('add-host-inventory');
+
+COMMIT;
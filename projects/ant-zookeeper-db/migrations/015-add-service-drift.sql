@@ -0,0 +1,25 @@
+BEGIN;
+
+-- Whether the version each host runs of a service is the one ant-zookeeper last deployed there, as
+-- of the last check of the drift reconciler. Services no longer deployed to a host are removed.
+create table service_drift (
+  host_id text not null,
+  service_id text not null,
+
+  desired_revision_id text not null, -- The revision of the latest finished deployment to the host.
+  desired_version text not null,
+  actual_version text, -- Null if the service is not installed, or the host could not be asked.
+  state varchar(16) not null, -- 'in_sync', 'drifted', or 'unreachable'.
+  error text, -- Why the host could not be asked.
+
+  drifted_since timestamp with time zone, -- Since when it is drifted, null unless it is.
+  corrective_pipeline_id text, -- The pipeline opened to redeploy the desired revision, if any.
+
+  checked_at timestamp with time zone not null default now(),
+
+  primary key (host_id, service_id)
+);
+
+insert into migration (migration_label) values ('add-service-drift');
+
+COMMIT;
@@ -0,0 +1,41 @@
+BEGIN;
+
+-- While an environment has deployment windows, nodes deploying to it are only claimed inside one of
+-- them. A project with windows of its own in an environment only follows those.
+create table pipeline_engine_deployment_window (
+  window_id text primary key default ('window-' || random_string(12)),
+
+  project_id text, -- Null for every project without windows of its own in the environment.
+  environment text not null,
+
+  days integer[] not null, -- ISO days of the week, 1 for Monday through 7 for Sunday.
+  start_time time not null, -- In time_zone, inclusive.
+  end_time time not null, -- In time_zone, exclusive.
+  time_zone text not null default 'UTC', -- An IANA time zone, like 'Europe/Amsterdam'.
+
+  created_at timestamp with time zone not null default now(),
+
+  check (start_time < end_time),
+  foreign key (project_id) references project(project_id)
+);
+
+-- While frozen, nodes deploying to an environment are not claimed, inside its windows or not.
+create table pipeline_engine_deployment_freeze (
+  freeze_id text primary key default ('freeze-' || random_string(12)),
+
+  project_id text, -- Null for every project.
+  environment text, -- Null for every environment.
+
+  starts_at timestamp with time zone not null,
+  ends_at timestamp with time zone not null,
+  reason text not null,
+
+  created_at timestamp with time zone not null default now(),
+
+  check (starts_at < ends_at),
+  foreign key (project_id) references project(project_id)
+);
+
+insert into migration (migration_label) values ('add-deployment-windows');
+
+COMMIT;
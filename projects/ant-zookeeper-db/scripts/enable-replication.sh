@@ -0,0 +1,9 @@
+#!/bin/bash
+
+# Run by the postgres entrypoint on first boot, after the migrations. Allows pg_basebackup to
+# connect with the replication protocol, for ant-backing-it-up's base backups.
+# Existing clusters need the same line appended to pg_hba.conf by hand, then a reload.
+
+set -euo pipefail
+
+echo "host replication all all scram-sha-256" >> "$PGDATA/pg_hba.conf"
@@ -143,7 +143,8 @@ impl AntZooStorageClient {
             where
                 artifact.revision_id = $1 and
                 artifact.project_id = $2 and
-                artifact.architecture_id = $3
+                artifact.architecture_id = $3 and
+                artifact.deleted_at is null
             ",
                 &[&revision_id, &project, &arch.map(|a| a.as_str())],
             )
@@ -183,7 +184,8 @@ impl AntZooStorageClient {
             select artifact_id, project_id, architecture_id, build_version, size_bytes, fingerprint
             from artifact
             where
-                artifact.revision_id = $1
+                artifact.revision_id = $1 and
+                artifact.deleted_at is null
             ",
                 &[&revision_id],
             )
@@ -259,6 +261,7 @@ impl AntZooStorageClient {
         path: &Path,
         size_bytes: i64,
         fingerprint: &str,
+        provenance: &str,
     ) -> Result<String, anyhow::Error> {
         let mut con = self.db.get().await?;
 
@@ -268,9 +271,9 @@ impl AntZooStorageClient {
             .query_one(
                 "
             insert into artifact
-                (revision_id, project_id, architecture_id, build_version, local_path, size_bytes, fingerprint)
+                (revision_id, project_id, architecture_id, build_version, local_path, size_bytes, fingerprint, provenance)
             values
-                ($1, $2, $3, $4, $5, $6, $7)
+                ($1, $2, $3, $4, $5, $6, $7, $8)
             returning artifact_id
             ",
                 &[
@@ -284,6 +287,7 @@ impl AntZooStorageClient {
                         .expect(&format!("bad artifact path: {}", path.display())),
                     &size_bytes,
                     &fingerprint,
+                    &provenance,
                 ],
             )
             .await
@@ -310,6 +314,9 @@ impl AntZooStorageClient {
         artifact_id: &str,
         version: &str,
         path: &Path,
+        size_bytes: i64,
+        fingerprint: &str,
+        provenance: &str,
     ) -> Result<(), anyhow::Error> {
         let con = self.db.get().await?;
 
@@ -319,6 +326,9 @@ impl AntZooStorageClient {
             set
                 build_version = $2,
                 local_path = $3,
+                size_bytes = $4,
+                fingerprint = $5,
+                provenance = $6,
                 updated_at = now()
             where
                 artifact_id = $1
@@ -330,6 +340,9 @@ impl AntZooStorageClient {
                     .as_os_str()
                     .to_str()
                     .expect(&format!("bad artifact path: {}", path.display())),
+                &size_bytes,
+                &fingerprint,
+                &provenance,
             ],
         )
         .await?;
@@ -337,60 +350,25 @@ impl AntZooStorageClient {
         Ok(())
     }
 
-    pub async fn register_new_secret_version(
+    /// The signed provenance statement an artifact was registered with, in JSON. None for artifacts
+    /// registered before they were required.
+    pub async fn get_artifact_provenance(
         &self,
-        secret_name: &str,
-        secret_environment: &str,
-        valid_for: chrono::Duration,
-        secret_value: &[u8],
-    ) -> Result<String, anyhow::Error> {
-        let existing_version: Option<i32> = self
+        artifact_id: &str,
+    ) -> Result<Option<String>, anyhow::Error> {
+        let provenance = self
             .db
             .get()
             .await?
             .query_opt(
-                "
-        select secret_version
-        where
-            secret_name = $1 and
-            secret_environment = $2 and
-            deleted_at is null
-        order by created_at desc
-        ",
-                &[&secret_name, &secret_environment],
-            )
-            .await?
-            .map(|row| row.get("secret_version"));
-
-        let row = self
-            .db
-            .get()
-            .await?
-            .query_one(
-                "
-        insert into secret(
-            secret_name,
-            secret_environment,
-            secret_version,
-            valid_for_seconds,
-            secret_value
-        )
-        values
-            ($1, $2, $3, $4, $5)
-        returning secret_id
-        ",
-                &[
-                    &secret_name,
-                    &secret_environment,
-                    &existing_version.unwrap_or(1),
-                    &valid_for.num_seconds(),
-                    &secret_value,
-                ],
+                "select provenance from artifact where artifact_id = $1",
+                &[&artifact_id],
             )
-            .await?;
+            .await
+            .with_context(|| format!("{}: {artifact_id}", function_name!()))?
+            .and_then(|row| row.get("provenance"));
 
-        let secret_id: String = row.get("secret_id");
-        Ok(secret_id)
+        Ok(provenance)
     }
 
     pub async fn create_deployment_pipeline(&self, name: &str) -> Result<String, anyhow::Error> {
@@ -1851,3 +1829,1587 @@ impl AntZooStorageClient {
         Ok(hosts)
     }
 }
+
+/// A migration file of a database project, applied to the database on a host in an environment.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct DatabaseMigration {
+    pub migration_name: String,
+    pub migration_sha256: String,
+    /// None if the migration was applied before the pipeline managed the database.
+    pub revision_id: Option<String>,
+    pub applied_at: DateTime<Utc>,
+}
+
+impl AntZooStorageClient {
+    /// The migrations applied to the database of `project` on `host_id`, in the order they were applied.
+    pub async fn list_database_migrations(
+        &self,
+        project: &str,
+        environment: &str,
+        host_id: &str,
+    ) -> Result<Vec<DatabaseMigration>, anyhow::Error> {
+        let migrations = self
+            .db
+            .get()
+            .await?
+            .query(
+                "
+            select migration_name, migration_sha256, revision_id, applied_at
+            from database_migration
+            where
+                project_id = $1 and
+                environment = $2 and
+                host_id = $3
+            order by applied_at asc, migration_name asc
+            ",
+                &[&project, &environment, &host_id],
+            )
+            .await
+            .with_context(|| format!("{}: {project} {environment} {host_id}", function_name!()))?
+            .iter()
+            .map(|row| DatabaseMigration {
+                migration_name: row.get("migration_name"),
+                migration_sha256: row.get("migration_sha256"),
+                revision_id: row.get("revision_id"),
+                applied_at: row.get("applied_at"),
+            })
+            .collect();
+
+        Ok(migrations)
+    }
+
+    pub async fn record_database_migration(
+        &self,
+        project: &str,
+        environment: &str,
+        host_id: &str,
+        migration_name: &str,
+        migration_sha256: &str,
+        revision_id: Option<&str>,
+    ) -> Result<(), anyhow::Error> {
+        self.db
+            .get()
+            .await?
+            .execute(
+                "
+            insert into database_migration
+                (project_id, environment, host_id, migration_name, migration_sha256, revision_id)
+            values
+                ($1, $2, $3, $4, $5, $6)
+            ",
+                &[
+                    &project,
+                    &environment,
+                    &host_id,
+                    &migration_name,
+                    &migration_sha256,
+                    &revision_id,
+                ],
+            )
+            .await
+            .with_context(|| format!("{}: {project} {migration_name}", function_name!()))?;
+
+        Ok(())
+    }
+
+    /// Forget a migration after its down-migration was applied.
+    pub async fn delete_database_migration(
+        &self,
+        project: &str,
+        environment: &str,
+        host_id: &str,
+        migration_name: &str,
+    ) -> Result<(), anyhow::Error> {
+        self.db
+            .get()
+            .await?
+            .execute(
+                "
+            delete from database_migration
+            where
+                project_id = $1 and
+                environment = $2 and
+                host_id = $3 and
+                migration_name = $4
+            ",
+                &[&project, &environment, &host_id, &migration_name],
+            )
+            .await
+            .with_context(|| format!("{}: {project} {migration_name}", function_name!()))?;
+
+        Ok(())
+    }
+}
+
+impl AntZooStorageClient {
+    /// The project a revision belongs to.
+    pub async fn get_revision_project(
+        &self,
+        revision_id: &str,
+    ) -> Result<Option<String>, anyhow::Error> {
+        let project = self
+            .db
+            .get()
+            .await?
+            .query_opt(
+                "select project_id from revision where revision_id = $1",
+                &[&revision_id],
+            )
+            .await
+            .with_context(|| format!("{}: {}", function_name!(), revision_id))?
+            .map(|row| row.get("project_id"));
+
+        Ok(project)
+    }
+
+    pub async fn upsert_gateway_routes(
+        &self,
+        environment: &str,
+        project: &str,
+        revision_id: &str,
+        project_routes: &str,
+    ) -> Result<(), anyhow::Error> {
+        self.db
+            .get()
+            .await?
+            .execute(
+                "
+            insert into gateway_route
+                (environment, project_id, revision_id, project_routes)
+            values
+                ($1, $2, $3, $4)
+            on conflict (environment, project_id, revision_id) do update
+            set
+                project_routes = excluded.project_routes,
+                created_at = now()
+            ",
+                &[&environment, &project, &revision_id, &project_routes],
+            )
+            .await
+            .with_context(|| format!("{}: {environment} {project}", function_name!()))?;
+
+        Ok(())
+    }
+
+    pub async fn delete_gateway_routes(
+        &self,
+        environment: &str,
+        project: &str,
+        revision_id: &str,
+    ) -> Result<(), anyhow::Error> {
+        self.db
+            .get()
+            .await?
+            .execute(
+                "
+            delete from gateway_route
+            where
+                environment = $1 and
+                project_id = $2 and
+                revision_id = $3
+            ",
+                &[&environment, &project, &revision_id],
+            )
+            .await
+            .with_context(|| format!("{}: {environment} {project}", function_name!()))?;
+
+        Ok(())
+    }
+
+    /// The latest routes of each project in the environment, as (project, JSON-encoded routes).
+    pub async fn list_gateway_routes(
+        &self,
+        environment: &str,
+    ) -> Result<Vec<(String, String)>, anyhow::Error> {
+        let routes = self
+            .db
+            .get()
+            .await?
+            .query(
+                "
+            select distinct on (project_id) project_id, project_routes
+            from gateway_route
+            where environment = $1
+            order by project_id, created_at desc
+            ",
+                &[&environment],
+            )
+            .await
+            .with_context(|| format!("{}: {environment}", function_name!()))?
+            .iter()
+            .map(|row| (row.get("project_id"), row.get("project_routes")))
+            .collect();
+
+        Ok(routes)
+    }
+
+    pub async fn record_gateway_config(
+        &self,
+        environment: &str,
+        revision_id: &str,
+        config: &str,
+    ) -> Result<(), anyhow::Error> {
+        self.db
+            .get()
+            .await?
+            .execute(
+                "
+            insert into gateway_config
+                (environment, revision_id, config)
+            values
+                ($1, $2, $3)
+            on conflict (environment, revision_id) do update
+            set
+                config = excluded.config,
+                created_at = now()
+            ",
+                &[&environment, &revision_id, &config],
+            )
+            .await
+            .with_context(|| format!("{}: {environment} {revision_id}", function_name!()))?;
+
+        Ok(())
+    }
+
+    pub async fn delete_gateway_config(
+        &self,
+        environment: &str,
+        revision_id: &str,
+    ) -> Result<(), anyhow::Error> {
+        self.db
+            .get()
+            .await?
+            .execute(
+                "delete from gateway_config where environment = $1 and revision_id = $2",
+                &[&environment, &revision_id],
+            )
+            .await
+            .with_context(|| format!("{}: {environment} {revision_id}", function_name!()))?;
+
+        Ok(())
+    }
+
+    /// The most recently rendered gateway config of the environment.
+    pub async fn get_latest_gateway_config(
+        &self,
+        environment: &str,
+    ) -> Result<Option<String>, anyhow::Error> {
+        let config = self
+            .db
+            .get()
+            .await?
+            .query_opt(
+                "
+            select config
+            from gateway_config
+            where environment = $1
+            order by created_at desc
+            limit 1
+            ",
+                &[&environment],
+            )
+            .await
+            .with_context(|| format!("{}: {environment}", function_name!()))?
+            .map(|row| row.get("config"));
+
+        Ok(config)
+    }
+
+    /// Record the alert or log rules (`rules_kind`) a revision of a project ships for an
+    /// environment.
+    pub async fn upsert_monitoring_rules(
+        &self,
+        rules_kind: &str,
+        environment: &str,
+        project: &str,
+        revision_id: &str,
+        rules: &str,
+    ) -> Result<(), anyhow::Error> {
+        self.db
+            .get()
+            .await?
+            .execute(
+                "
+            insert into monitoring_rules
+                (rules_kind, environment, project_id, revision_id, rules)
+            values
+                ($1, $2, $3, $4, $5)
+            on conflict (rules_kind, environment, project_id, revision_id) do update
+            set
+                rules = excluded.rules,
+                created_at = now()
+            ",
+                &[&rules_kind, &environment, &project, &revision_id, &rules],
+            )
+            .await
+            .with_context(|| {
+                format!(
+                    "{}: {rules_kind} {environment} {project} {revision_id}",
+                    function_name!()
+                )
+            })?;
+
+        Ok(())
+    }
+
+    pub async fn delete_monitoring_rules(
+        &self,
+        rules_kind: &str,
+        environment: &str,
+        project: &str,
+        revision_id: &str,
+    ) -> Result<(), anyhow::Error> {
+        self.db
+            .get()
+            .await?
+            .execute(
+                "
+            delete from monitoring_rules
+            where
+                rules_kind = $1 and
+                environment = $2 and
+                project_id = $3 and
+                revision_id = $4
+            ",
+                &[&rules_kind, &environment, &project, &revision_id],
+            )
+            .await
+            .with_context(|| {
+                format!(
+                    "{}: {rules_kind} {environment} {project} {revision_id}",
+                    function_name!()
+                )
+            })?;
+
+        Ok(())
+    }
+
+    /// The most recently recorded alert or log rules of a project in an environment.
+    pub async fn get_latest_monitoring_rules(
+        &self,
+        rules_kind: &str,
+        environment: &str,
+        project: &str,
+    ) -> Result<Option<String>, anyhow::Error> {
+        let rules = self
+            .db
+            .get()
+            .await?
+            .query_opt(
+                "
+            select rules
+            from monitoring_rules
+            where rules_kind = $1 and environment = $2 and project_id = $3
+            order by created_at desc
+            limit 1
+            ",
+                &[&rules_kind, &environment, &project],
+            )
+            .await
+            .with_context(|| format!("{}: {rules_kind} {environment} {project}", function_name!()))?
+            .map(|row| row.get("rules"));
+
+        Ok(rules)
+    }
+}
+
+/// The TLS certificate of an environment, as tracked for renewal.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Certificate {
+    pub environment: String,
+    pub domains: Vec<String>,
+    /// "pending" until first issued, "valid" after a renewal, and "failing" after a failed one.
+    pub state: String,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub next_attempt_at: DateTime<Utc>,
+    pub failed_attempts: i32,
+    pub last_error: Option<String>,
+}
+
+impl AntZooStorageClient {
+    fn row_to_certificate(&self, row: &Row) -> Certificate {
+        Certificate {
+            environment: row.get("environment"),
+            domains: row.get("domains"),
+            state: row.get("state"),
+            expires_at: row.get("expires_at"),
+            next_attempt_at: row.get("next_attempt_at"),
+            failed_attempts: row.get("failed_attempts"),
+            last_error: row.get("last_error"),
+        }
+    }
+
+    /// Track the certificate of an environment for `domains`, and have it renewed right away.
+    pub async fn upsert_certificate(
+        &self,
+        environment: &str,
+        domains: &[String],
+    ) -> Result<(), anyhow::Error> {
+        self.db
+            .get()
+            .await?
+            .execute(
+                "
+            insert into certificate
+                (environment, domains)
+            values
+                ($1, $2)
+            on conflict (environment) do update
+            set
+                domains = excluded.domains,
+                next_attempt_at = now(),
+                updated_at = now()
+            ",
+                &[&environment, &domains],
+            )
+            .await
+            .with_context(|| format!("{}: {environment}", function_name!()))?;
+
+        Ok(())
+    }
+
+    pub async fn list_certificates(&self) -> Result<Vec<Certificate>, anyhow::Error> {
+        let certificates = self
+            .db
+            .get()
+            .await?
+            .query("select * from certificate order by environment", &[])
+            .await
+            .context(function_name!())?
+            .iter()
+            .map(|row| self.row_to_certificate(row))
+            .collect();
+
+        Ok(certificates)
+    }
+
+    /// Take a certificate that is due for renewal, if any. It is not due again for `lease`, so other
+    /// zookeepers leave it alone while it is renewed.
+    pub async fn claim_due_certificate(
+        &self,
+        lease: chrono::Duration,
+    ) -> Result<Option<Certificate>, anyhow::Error> {
+        let certificate = self
+            .db
+            .get()
+            .await?
+            .query_opt(
+                "
+            update certificate
+            set
+                next_attempt_at = now() + make_interval(secs => $1),
+                updated_at = now()
+            where environment = (
+                select environment
+                from certificate
+                where next_attempt_at <= now()
+                order by next_attempt_at
+                limit 1
+                for update skip locked
+            )
+            returning *
+            ",
+                &[&(lease.num_seconds() as f64)],
+            )
+            .await
+            .context(function_name!())?
+            .map(|row| self.row_to_certificate(&row));
+
+        Ok(certificate)
+    }
+
+    pub async fn record_certificate_renewed(
+        &self,
+        environment: &str,
+        expires_at: DateTime<Utc>,
+        next_attempt_at: DateTime<Utc>,
+    ) -> Result<(), anyhow::Error> {
+        self.db
+            .get()
+            .await?
+            .execute(
+                "
+            update certificate
+            set
+                state = 'valid',
+                expires_at = $2,
+                next_attempt_at = $3,
+                failed_attempts = 0,
+                last_error = null,
+                updated_at = now()
+            where environment = $1
+            ",
+                &[&environment, &expires_at, &next_attempt_at],
+            )
+            .await
+            .with_context(|| format!("{}: {environment}", function_name!()))?;
+
+        Ok(())
+    }
+
+    /// Returns how many renewals failed in a row, including this one.
+    pub async fn record_certificate_failure(
+        &self,
+        environment: &str,
+        error: &str,
+        next_attempt_at: DateTime<Utc>,
+    ) -> Result<i32, anyhow::Error> {
+        let failed_attempts = self
+            .db
+            .get()
+            .await?
+            .query_one(
+                "
+            update certificate
+            set
+                state = 'failing',
+                failed_attempts = failed_attempts + 1,
+                last_error = $2,
+                next_attempt_at = $3,
+                updated_at = now()
+            where environment = $1
+            returning failed_attempts
+            ",
+                &[&environment, &error, &next_attempt_at],
+            )
+            .await
+            .with_context(|| format!("{}: {environment}", function_name!()))?
+            .get("failed_attempts");
+
+        Ok(failed_attempts)
+    }
+}
+
+/// What a client of the ant-zookeeper API may do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ClientRole {
+    /// Only registers revisions and their artifacts, for build automation.
+    Ci,
+    /// Reads pipelines, deployments and host groups.
+    Viewer,
+    /// Reads, and drives deployments: iterating and retrying.
+    Deployer,
+    /// Does everything, including changing pipelines, environments and certificates.
+    Admin,
+    /// Only reports the heartbeats of the host it is named after, for ant-host-agent.
+    Host,
+}
+
+impl ClientRole {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ClientRole::Ci => "ci",
+            ClientRole::Viewer => "viewer",
+            ClientRole::Deployer => "deployer",
+            ClientRole::Admin => "admin",
+            ClientRole::Host => "host",
+        }
+    }
+
+    /// Whether a client with this role may do what `required` may do.
+    pub fn allows(&self, required: ClientRole) -> bool {
+        match (self, required) {
+            (ClientRole::Admin, _) => true,
+            // Only hosts vouch for themselves.
+            (ClientRole::Deployer, r) => !matches!(r, ClientRole::Admin | ClientRole::Host),
+            (ClientRole::Viewer, r) => r == ClientRole::Viewer,
+            (ClientRole::Ci, r) => r == ClientRole::Ci,
+            (ClientRole::Host, r) => r == ClientRole::Host,
+        }
+    }
+}
+
+impl FromStr for ClientRole {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ci" => Ok(ClientRole::Ci),
+            "viewer" => Ok(ClientRole::Viewer),
+            "deployer" => Ok(ClientRole::Deployer),
+            "admin" => Ok(ClientRole::Admin),
+            "host" => Ok(ClientRole::Host),
+            _ => Err(anyhow::Error::msg(format!("unknown client role: {s}"))),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditLogEntry {
+    pub audit_log_id: String,
+    pub client_id: String,
+    pub client_name: String,
+    pub action: String,
+    pub details: String,
+    pub created_at: DateTime<Utc>,
+}
+
+impl AntZooStorageClient {
+    /// Create a client with a token, or replace the token and role of the client with that name.
+    pub async fn upsert_client(
+        &self,
+        name: &str,
+        role: ClientRole,
+        token: &str,
+    ) -> Result<String, anyhow::Error> {
+        let token_hash = ant_library::crypto::make_token_hash(token);
+
+        let client_id = self
+            .db
+            .get()
+            .await?
+            .query_one(
+                "
+            insert into zookeeper_client
+                (client_name, token_hash, client_role)
+            values
+                ($1, $2, $3)
+            on conflict (client_name) do update
+            set
+                token_hash = excluded.token_hash,
+                client_role = excluded.client_role,
+                updated_at = now()
+            returning client_id
+            ",
+                &[&name, &token_hash, &role.as_str()],
+            )
+            .await
+            .with_context(|| format!("{}: {name}", function_name!()))?
+            .get("client_id");
+
+        Ok(client_id)
+    }
+
+    /// The (client ID, client name, role) of the client with the token, if any.
+    pub async fn authenticate_bearer(
+        &self,
+        token: &str,
+    ) -> Result<Option<(String, String, ClientRole)>, anyhow::Error> {
+        let token_hash = ant_library::crypto::make_token_hash(token);
+
+        let row = self
+            .db
+            .get()
+            .await?
+            .query_opt(
+                "
+            select client_id, client_name, client_role
+            from zookeeper_client
+            where token_hash = $1
+            ",
+                &[&token_hash],
+            )
+            .await
+            .context(function_name!())?;
+
+        match row {
+            None => Ok(None),
+            Some(row) => Ok(Some((
+                row.get("client_id"),
+                row.get("client_name"),
+                ClientRole::from_str(row.get("client_role"))?,
+            ))),
+        }
+    }
+
+    pub async fn record_audit(
+        &self,
+        client_id: &str,
+        action: &str,
+        details: &str,
+    ) -> Result<(), anyhow::Error> {
+        self.db
+            .get()
+            .await?
+            .execute(
+                "
+            insert into audit_log
+                (client_id, action, details)
+            values
+                ($1, $2, $3)
+            ",
+                &[&client_id, &action, &details],
+            )
+            .await
+            .with_context(|| format!("{}: {client_id} {action}", function_name!()))?;
+
+        Ok(())
+    }
+
+    /// The latest `limit` changes, newest first.
+    pub async fn list_audit_log(&self, limit: i64) -> Result<Vec<AuditLogEntry>, anyhow::Error> {
+        let entries = self
+            .db
+            .get()
+            .await?
+            .query(
+                "
+            select a.audit_log_id, a.client_id, c.client_name, a.action, a.details, a.created_at
+            from audit_log a
+                join zookeeper_client c on c.client_id = a.client_id
+            order by a.created_at desc
+            limit $1
+            ",
+                &[&limit],
+            )
+            .await
+            .context(function_name!())?
+            .iter()
+            .map(|row| AuditLogEntry {
+                audit_log_id: row.get("audit_log_id"),
+                client_id: row.get("client_id"),
+                client_name: row.get("client_name"),
+                action: row.get("action"),
+                details: row.get("details"),
+                created_at: row.get("created_at"),
+            })
+            .collect();
+
+        Ok(entries)
+    }
+}
+
+/// A version of a secret, without its value.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Secret {
+    pub secret_id: String,
+    pub name: String,
+    pub environment: String,
+    /// The host of a host-specific secret, none if it is for the whole environment.
+    pub host: Option<String>,
+    pub version: i32,
+    pub valid_for_seconds: i32,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A version of a secret with its value, encrypted with `nonce`.
+pub struct EncryptedSecret {
+    pub secret: Secret,
+    pub nonce: Vec<u8>,
+    pub value: Vec<u8>,
+}
+
+impl AntZooStorageClient {
+    fn row_to_secret(&self, row: &Row) -> Secret {
+        Secret {
+            secret_id: row.get("secret_id"),
+            name: row.get("secret_name"),
+            environment: row.get("secret_environment"),
+            host: row.get("secret_host"),
+            version: row.get("secret_version"),
+            valid_for_seconds: row.get("valid_for_seconds"),
+            created_at: row.get("created_at"),
+        }
+    }
+
+    fn row_to_encrypted_secret(&self, row: &Row) -> EncryptedSecret {
+        EncryptedSecret {
+            secret: self.row_to_secret(row),
+            nonce: row.get("secret_nonce"),
+            value: row.get("secret_value"),
+        }
+    }
+
+    /// Register the next version of a secret, with its value encrypted with `nonce`. Returns the
+    /// (secret_id, version).
+    pub async fn register_new_secret_version(
+        &self,
+        secret_name: &str,
+        secret_environment: &str,
+        secret_host: Option<&str>,
+        valid_for: chrono::Duration,
+        secret_nonce: &[u8],
+        secret_value: &[u8],
+    ) -> Result<(String, i32), anyhow::Error> {
+        // The unique index fails whichever of two concurrent registrations comes second.
+        let row = self
+            .db
+            .get()
+            .await?
+            .query_one(
+                "
+        insert into secret(
+            secret_name,
+            secret_environment,
+            secret_host,
+            secret_version,
+            valid_for_seconds,
+            secret_nonce,
+            secret_value
+        )
+        select
+            -- $2 is compared as text below, while secret_environment is a varchar.
+            $1, $2::text, $3, coalesce(max(secret_version), 0) + 1, $4, $5, $6
+        from secret
+        where
+            secret_name = $1 and
+            secret_environment = $2 and
+            secret_host is not distinct from $3
+        returning secret_id, secret_version
+        ",
+                &[
+                    &secret_name,
+                    &secret_environment,
+                    &secret_host,
+                    &(valid_for.num_seconds() as i32),
+                    &secret_nonce,
+                    &secret_value,
+                ],
+            )
+            .await
+            .with_context(|| {
+                format!(
+                    "{}: {secret_name} {secret_environment} {secret_host:?}",
+                    function_name!()
+                )
+            })?;
+
+        Ok((row.get("secret_id"), row.get("secret_version")))
+    }
+
+    /// Encrypt the versions of secrets the add-secret-store migration kept as plaintext, with
+    /// `seal` returning the (nonce, value) of a version. Only once all of them are sealed, the
+    /// plaintext column is dropped, in the same transaction. Returns how many were sealed, none
+    /// after the column is gone.
+    pub async fn seal_plaintext_secrets<F>(&self, seal: F) -> Result<usize, anyhow::Error>
+    where
+        F: Fn(&Secret, &[u8]) -> Result<(Vec<u8>, Vec<u8>), anyhow::Error>,
+    {
+        let mut con = self.db.get().await?;
+        let tx = con.transaction().await?;
+
+        // Keeps two instances starting at once from sealing the same versions.
+        tx.execute("lock table secret in exclusive mode", &[])
+            .await
+            .context(function_name!())?;
+
+        let has_plaintext = tx
+            .query_opt(
+                "
+            select 1
+            from information_schema.columns
+            where
+                table_schema = current_schema() and
+                table_name = 'secret' and
+                column_name = 'secret_plaintext'
+            ",
+                &[],
+            )
+            .await
+            .context(function_name!())?
+            .is_some();
+        if !has_plaintext {
+            return Ok(0);
+        }
+
+        let rows = tx
+            .query(
+                "
+            select *
+            from secret
+            where secret_plaintext is not null
+            order by secret_environment, secret_name, secret_host nulls first, secret_version
+            ",
+                &[],
+            )
+            .await
+            .context(function_name!())?;
+
+        for row in &rows {
+            let secret = self.row_to_secret(row);
+            let plaintext: Vec<u8> = row.get("secret_plaintext");
+            let (nonce, value) = seal(&secret, &plaintext)?;
+
+            tx.execute(
+                "
+            update secret
+            set
+                secret_nonce = $2,
+                secret_value = $3,
+                secret_plaintext = null,
+                updated_at = now()
+            where secret_id = $1
+            ",
+                &[&secret.secret_id, &nonce, &value],
+            )
+            .await
+            .with_context(|| format!("{}: {}", function_name!(), secret.secret_id))?;
+        }
+
+        tx.batch_execute(
+            "
+        alter table secret
+          drop column secret_plaintext,
+          alter column secret_nonce set not null,
+          alter column secret_value set not null;
+        ",
+        )
+        .await
+        .context(function_name!())?;
+
+        tx.commit().await?;
+
+        Ok(rows.len())
+    }
+
+    /// Every version of every secret, without their values.
+    pub async fn list_secrets(&self) -> Result<Vec<Secret>, anyhow::Error> {
+        let secrets = self
+            .db
+            .get()
+            .await?
+            .query(
+                "
+            select *
+            from secret
+            where deleted_at is null
+            order by secret_environment, secret_name, secret_host nulls first, secret_version
+            ",
+                &[],
+            )
+            .await
+            .context(function_name!())?
+            .iter()
+            .map(|row| self.row_to_secret(row))
+            .collect();
+
+        Ok(secrets)
+    }
+
+    /// A version of a secret, or its latest version if `version` is none.
+    pub async fn get_secret(
+        &self,
+        secret_name: &str,
+        secret_environment: &str,
+        secret_host: Option<&str>,
+        version: Option<i32>,
+    ) -> Result<Option<EncryptedSecret>, anyhow::Error> {
+        let secret = self
+            .db
+            .get()
+            .await?
+            .query_opt(
+                "
+            select *
+            from secret
+            where
+                secret_name = $1 and
+                secret_environment = $2 and
+                secret_host is not distinct from $3 and
+                ($4::int is null or secret_version = $4) and
+                deleted_at is null
+            order by secret_version desc
+            limit 1
+            ",
+                &[&secret_name, &secret_environment, &secret_host, &version],
+            )
+            .await
+            .with_context(|| {
+                format!(
+                    "{}: {secret_name} {secret_environment} {secret_host:?} {version:?}",
+                    function_name!()
+                )
+            })?
+            .map(|row| self.row_to_encrypted_secret(&row));
+
+        Ok(secret)
+    }
+
+    /// The version of a secret pinned to a revision, if it was pinned.
+    pub async fn get_pinned_secret(
+        &self,
+        revision_id: &str,
+        secret_name: &str,
+        secret_environment: &str,
+        secret_host: Option<&str>,
+    ) -> Result<Option<EncryptedSecret>, anyhow::Error> {
+        let secret = self
+            .db
+            .get()
+            .await?
+            .query_opt(
+                "
+            select secret.*
+            from revision_secret
+                join secret on secret.secret_id = revision_secret.secret_id
+            where
+                revision_secret.revision_id = $1 and
+                secret.secret_name = $2 and
+                secret.secret_environment = $3 and
+                secret.secret_host is not distinct from $4
+            order by secret.secret_version
+            limit 1
+            ",
+                &[
+                    &revision_id,
+                    &secret_name,
+                    &secret_environment,
+                    &secret_host,
+                ],
+            )
+            .await
+            .with_context(|| {
+                format!(
+                    "{}: {revision_id} {secret_name} {secret_environment} {secret_host:?}",
+                    function_name!()
+                )
+            })?
+            .map(|row| self.row_to_encrypted_secret(&row));
+
+        Ok(secret)
+    }
+
+    /// Pin a version of a secret to a revision. If a rotation raced another pin of the same revision,
+    /// the earliest version pinned is the one `get_pinned_secret` returns.
+    pub async fn pin_revision_secret(
+        &self,
+        revision_id: &str,
+        secret_id: &str,
+    ) -> Result<(), anyhow::Error> {
+        self.db
+            .get()
+            .await?
+            .execute(
+                "
+            insert into revision_secret
+                (revision_id, secret_id)
+            values
+                ($1, $2)
+            on conflict do nothing
+            ",
+                &[&revision_id, &secret_id],
+            )
+            .await
+            .with_context(|| format!("{}: {revision_id} {secret_id}", function_name!()))?;
+
+        Ok(())
+    }
+
+    /// The projects that were deployed with any version of a secret, for them to be redeployed once
+    /// it rotates.
+    pub async fn list_projects_using_secret(
+        &self,
+        secret_name: &str,
+        secret_environment: &str,
+    ) -> Result<Vec<String>, anyhow::Error> {
+        let projects = self
+            .db
+            .get()
+            .await?
+            .query(
+                "
+            select distinct revision.project_id
+            from revision_secret
+                join secret on secret.secret_id = revision_secret.secret_id
+                join revision on revision.revision_id = revision_secret.revision_id
+            where
+                secret.secret_name = $1 and
+                secret.secret_environment = $2
+            order by revision.project_id
+            ",
+                &[&secret_name, &secret_environment],
+            )
+            .await
+            .with_context(|| format!("{}: {secret_name} {secret_environment}", function_name!()))?
+            .iter()
+            .map(|row| row.get("project_id"))
+            .collect();
+
+        Ok(projects)
+    }
+
+    /// The latest activated revision of a project that has artifacts of its own.
+    pub async fn get_latest_deployable_revision(
+        &self,
+        project: &str,
+    ) -> Result<Option<String>, anyhow::Error> {
+        let revision_id = self
+            .db
+            .get()
+            .await?
+            .query_opt(
+                "
+            select revision.revision_id
+            from revision
+            where
+                revision.project_id = $1 and
+                revision.activated_at is not null and
+                exists (
+                    select 1 from artifact
+                    where artifact.revision_id = revision.revision_id and artifact.deleted_at is null
+                )
+            order by revision.revision_seq desc
+            limit 1
+            ",
+                &[&project],
+            )
+            .await
+            .with_context(|| format!("{}: {project}", function_name!()))?
+            .map(|row| row.get("revision_id"));
+
+        Ok(revision_id)
+    }
+
+    /// Register the artifacts of one revision as the artifacts of another, sharing their files.
+    pub async fn copy_revision_artifacts(
+        &self,
+        from_revision_id: &str,
+        to_revision_id: &str,
+    ) -> Result<u64, anyhow::Error> {
+        let copied = self
+            .db
+            .get()
+            .await?
+            .execute(
+                "
+            insert into artifact
+                (revision_id, project_id, architecture_id, build_version, local_path, size_bytes, fingerprint, provenance)
+            select
+                $2, project_id, architecture_id, build_version, local_path, size_bytes, fingerprint, provenance
+            from artifact
+            where revision_id = $1 and deleted_at is null
+            ",
+                &[&from_revision_id, &to_revision_id],
+            )
+            .await
+            .with_context(|| {
+                format!("{}: {from_revision_id} {to_revision_id}", function_name!())
+            })?;
+
+        Ok(copied)
+    }
+}
+
+/// An artifact registered to a revision, with the pipeline deploying that revision if any.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct StoredArtifact {
+    pub artifact_id: String,
+    pub revision_id: String,
+    pub project_id: String,
+    pub architecture: Option<String>,
+    pub build_version: String,
+    /// The path of the artifact under artifacts-db.
+    pub local_path: String,
+    pub size_bytes: i64,
+    pub fingerprint: String,
+    pub pipeline_id: Option<String>,
+    pub pipeline_state: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl AntZooStorageClient {
+    /// Every artifact that was not garbage collected, with the pipeline referencing its revision.
+    pub async fn list_stored_artifacts(&self) -> Result<Vec<StoredArtifact>, anyhow::Error> {
+        let artifacts = self
+            .db
+            .get()
+            .await?
+            .query(
+                "
+            select
+                artifact.artifact_id,
+                artifact.revision_id,
+                artifact.project_id,
+                artifact.architecture_id,
+                artifact.build_version,
+                artifact.local_path,
+                artifact.size_bytes,
+                artifact.fingerprint,
+                artifact.created_at,
+                pipeline_engine_pipeline.pipeline_id,
+                pipeline_engine_pipeline.state as pipeline_state
+            from artifact
+                join revision on artifact.revision_id = revision.revision_id
+                left join pipeline_engine_pipeline
+                    on pipeline_engine_pipeline.revision_id = artifact.revision_id
+            where artifact.deleted_at is null
+            order by artifact.fingerprint, revision.revision_seq
+            ",
+                &[],
+            )
+            .await
+            .context(function_name!())?
+            .iter()
+            .map(|row| StoredArtifact {
+                artifact_id: row.get("artifact_id"),
+                revision_id: row.get("revision_id"),
+                project_id: row.get("project_id"),
+                architecture: row.get("architecture_id"),
+                build_version: row.get("build_version"),
+                local_path: row.get("local_path"),
+                size_bytes: row.get("size_bytes"),
+                fingerprint: row.get("fingerprint"),
+                pipeline_id: row.get("pipeline_id"),
+                pipeline_state: row.get("pipeline_state"),
+                created_at: row.get("created_at"),
+            })
+            .collect();
+
+        Ok(artifacts)
+    }
+
+    /// The artifacts no longer retained. A project retains the artifacts of its last
+    /// `keep_deployed` deployed revisions (those with a finished pipeline), of revisions an active
+    /// or unwinding pipeline deploys, and of every revision after its last deployed one.
+    pub async fn list_unretained_artifacts(
+        &self,
+        keep_deployed: i64,
+    ) -> Result<Vec<String>, anyhow::Error> {
+        let artifact_ids = self
+            .db
+            .get()
+            .await?
+            .query(
+                "
+            with deployed as (
+                select
+                    revision.revision_id,
+                    revision.project_id,
+                    revision.revision_seq,
+                    row_number() over (
+                        partition by revision.project_id
+                        order by revision.revision_seq desc
+                    ) as recency
+                from revision
+                    join pipeline_engine_pipeline
+                        on pipeline_engine_pipeline.revision_id = revision.revision_id
+                where pipeline_engine_pipeline.state = 'finished'
+            )
+            select artifact.artifact_id
+            from artifact
+                join revision on artifact.revision_id = revision.revision_id
+            where
+                artifact.deleted_at is null and
+                not exists (
+                    select 1 from deployed
+                    where deployed.revision_id = artifact.revision_id and deployed.recency <= $1
+                ) and
+                not exists (
+                    select 1 from pipeline_engine_pipeline
+                    where
+                        pipeline_engine_pipeline.revision_id = artifact.revision_id and
+                        pipeline_engine_pipeline.state in ('active', 'unwinding')
+                ) and
+                exists (
+                    select 1 from deployed
+                    where
+                        deployed.project_id = revision.project_id and
+                        deployed.revision_seq > revision.revision_seq
+                )
+            order by artifact.artifact_id
+            ",
+                &[&keep_deployed],
+            )
+            .await
+            .with_context(|| format!("{}: {keep_deployed}", function_name!()))?
+            .iter()
+            .map(|row| row.get("artifact_id"))
+            .collect();
+
+        Ok(artifact_ids)
+    }
+
+    /// Mark artifacts as garbage collected. Returns how many were.
+    pub async fn delete_artifacts(&self, artifact_ids: &[String]) -> Result<u64, anyhow::Error> {
+        let deleted = self
+            .db
+            .get()
+            .await?
+            .execute(
+                "
+            update artifact
+            set
+                deleted_at = now(),
+                updated_at = now()
+            where artifact_id = any($1) and deleted_at is null
+            ",
+                &[&artifact_ids],
+            )
+            .await
+            .with_context(|| format!("{}: {artifact_ids:?}", function_name!()))?;
+
+        Ok(deleted)
+    }
+}
+
+/// The latest heartbeat of a host, see `ant_host_agent::heartbeat`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HostHeartbeat {
+    pub host_id: String,
+    pub uptime_seconds: i64,
+    pub disk_total_bytes: i64,
+    pub disk_available_bytes: i64,
+    pub memory_total_bytes: i64,
+    pub memory_available_bytes: i64,
+    /// JSON, the installed services with their versions.
+    pub services: String,
+    /// JSON, the states of the systemd units of the services.
+    pub units: String,
+    pub first_heartbeat_at: DateTime<Utc>,
+    pub last_heartbeat_at: DateTime<Utc>,
+}
+
+impl AntZooStorageClient {
+    fn row_to_host_heartbeat(&self, row: &Row) -> HostHeartbeat {
+        HostHeartbeat {
+            host_id: row.get("host_id"),
+            uptime_seconds: row.get("uptime_seconds"),
+            disk_total_bytes: row.get("disk_total_bytes"),
+            disk_available_bytes: row.get("disk_available_bytes"),
+            memory_total_bytes: row.get("memory_total_bytes"),
+            memory_available_bytes: row.get("memory_available_bytes"),
+            services: row.get("services"),
+            units: row.get("units"),
+            first_heartbeat_at: row.get("first_heartbeat_at"),
+            last_heartbeat_at: row.get("last_heartbeat_at"),
+        }
+    }
+
+    /// Replace the latest heartbeat of the host, ignoring the times of `heartbeat`: it is received
+    /// now.
+    pub async fn record_host_heartbeat(
+        &self,
+        heartbeat: &HostHeartbeat,
+    ) -> Result<(), anyhow::Error> {
+        self.db
+            .get()
+            .await?
+            .execute(
+                "
+            insert into host_heartbeat
+                (host_id, uptime_seconds, disk_total_bytes, disk_available_bytes,
+                 memory_total_bytes, memory_available_bytes, services, units)
+            values
+                ($1, $2, $3, $4, $5, $6, $7, $8)
+            on conflict (host_id) do update
+            set
+                uptime_seconds = excluded.uptime_seconds,
+                disk_total_bytes = excluded.disk_total_bytes,
+                disk_available_bytes = excluded.disk_available_bytes,
+                memory_total_bytes = excluded.memory_total_bytes,
+                memory_available_bytes = excluded.memory_available_bytes,
+                services = excluded.services,
+                units = excluded.units,
+                last_heartbeat_at = now()
+            ",
+                &[
+                    &heartbeat.host_id,
+                    &heartbeat.uptime_seconds,
+                    &heartbeat.disk_total_bytes,
+                    &heartbeat.disk_available_bytes,
+                    &heartbeat.memory_total_bytes,
+                    &heartbeat.memory_available_bytes,
+                    &heartbeat.services,
+                    &heartbeat.units,
+                ],
+            )
+            .await
+            .with_context(|| format!("{}: {}", function_name!(), heartbeat.host_id))?;
+
+        Ok(())
+    }
+
+    pub async fn list_host_heartbeats(&self) -> Result<Vec<HostHeartbeat>, anyhow::Error> {
+        let heartbeats = self
+            .db
+            .get()
+            .await?
+            .query("select * from host_heartbeat order by host_id", &[])
+            .await
+            .context(function_name!())?
+            .iter()
+            .map(|row| self.row_to_host_heartbeat(row))
+            .collect();
+
+        Ok(heartbeats)
+    }
+
+    /// The hosts whose last heartbeat is older than `unhealthy_after`. Hosts that never sent one
+    /// are not among them.
+    pub async fn list_unhealthy_hosts(
+        &self,
+        unhealthy_after: chrono::Duration,
+    ) -> Result<HashSet<String>, anyhow::Error> {
+        let hosts = self
+            .db
+            .get()
+            .await?
+            .query(
+                "
+            select host_id
+            from host_heartbeat
+            where last_heartbeat_at < now() - make_interval(secs => $1)
+            ",
+                &[&(unhealthy_after.num_seconds() as f64)],
+            )
+            .await
+            .context(function_name!())?
+            .iter()
+            .map(|row| row.get("host_id"))
+            .collect();
+
+        Ok(hosts)
+    }
+}
+
+/// Whether a host runs the version of a service ant-zookeeper last deployed there.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ServiceDrift {
+    pub host_id: String,
+    pub service_id: String,
+    pub desired_revision_id: String,
+    pub desired_version: String,
+    /// None if the service is not installed, or the host could not be asked.
+    pub actual_version: Option<String>,
+    /// "in_sync", "drifted", or "unreachable".
+    pub state: String,
+    /// Why the host could not be asked.
+    pub error: Option<String>,
+    pub drifted_since: Option<DateTime<Utc>>,
+    pub corrective_pipeline_id: Option<String>,
+    pub checked_at: DateTime<Utc>,
+}
+
+impl AntZooStorageClient {
+    fn row_to_service_drift(&self, row: &Row) -> ServiceDrift {
+        ServiceDrift {
+            host_id: row.get("host_id"),
+            service_id: row.get("service_id"),
+            desired_revision_id: row.get("desired_revision_id"),
+            desired_version: row.get("desired_version"),
+            actual_version: row.get("actual_version"),
+            state: row.get("state"),
+            error: row.get("error"),
+            drifted_since: row.get("drifted_since"),
+            corrective_pipeline_id: row.get("corrective_pipeline_id"),
+            checked_at: row.get("checked_at"),
+        }
+    }
+
+    /// Record the result of checking a service on a host, ignoring the times and corrective
+    /// pipeline of `drift`. A service drifted since before keeps its corrective pipeline, one back
+    /// in sync loses it. Returns the drift as recorded.
+    pub async fn record_service_drift(
+        &self,
+        drift: &ServiceDrift,
+    ) -> Result<ServiceDrift, anyhow::Error> {
+        let row = self
+            .db
+            .get()
+            .await?
+            .query_one(
+                "
+            insert into service_drift
+                (host_id, service_id, desired_revision_id, desired_version, actual_version, state,
+                 error, drifted_since)
+            values
+                ($1, $2, $3, $4, $5, $6, $7, case when $6 = 'drifted' then now() end)
+            on conflict (host_id, service_id) do update
+            set
+                desired_revision_id = excluded.desired_revision_id,
+                desired_version = excluded.desired_version,
+                actual_version = excluded.actual_version,
+                state = excluded.state,
+                error = excluded.error,
+                drifted_since = case
+                    when excluded.state = 'in_sync' then null
+                    else coalesce(service_drift.drifted_since, excluded.drifted_since)
+                end,
+                corrective_pipeline_id = case
+                    when excluded.state = 'in_sync' then null
+                    else service_drift.corrective_pipeline_id
+                end,
+                checked_at = now()
+            returning *
+            ",
+                &[
+                    &drift.host_id,
+                    &drift.service_id,
+                    &drift.desired_revision_id,
+                    &drift.desired_version,
+                    &drift.actual_version,
+                    &drift.state,
+                    &drift.error,
+                ],
+            )
+            .await
+            .with_context(|| {
+                format!(
+                    "{}: {} {}",
+                    function_name!(),
+                    drift.host_id,
+                    drift.service_id
+                )
+            })?;
+
+        Ok(self.row_to_service_drift(&row))
+    }
+
+    pub async fn set_service_drift_correction(
+        &self,
+        host_id: &str,
+        service_id: &str,
+        pipeline_id: &str,
+    ) -> Result<(), anyhow::Error> {
+        self.db
+            .get()
+            .await?
+            .execute(
+                "
+            update service_drift
+            set corrective_pipeline_id = $3
+            where host_id = $1 and service_id = $2
+            ",
+                &[&host_id, &service_id, &pipeline_id],
+            )
+            .await
+            .with_context(|| format!("{}: {host_id} {service_id}", function_name!()))?;
+
+        Ok(())
+    }
+
+    /// Remove the services not checked since `checked_at`, those no longer deployed to a host.
+    pub async fn delete_service_drift_checked_before(
+        &self,
+        checked_at: DateTime<Utc>,
+    ) -> Result<u64, anyhow::Error> {
+        let deleted = self
+            .db
+            .get()
+            .await?
+            .execute(
+                "delete from service_drift where checked_at < $1",
+                &[&checked_at],
+            )
+            .await
+            .context(function_name!())?;
+
+        Ok(deleted)
+    }
+
+    pub async fn list_service_drift(&self) -> Result<Vec<ServiceDrift>, anyhow::Error> {
+        let drift = self
+            .db
+            .get()
+            .await?
+            .query(
+                "select * from service_drift order by host_id, service_id",
+                &[],
+            )
+            .await
+            .context(function_name!())?
+            .iter()
+            .map(|row| self.row_to_service_drift(row))
+            .collect();
+
+        Ok(drift)
+    }
+}
@@ -1,16 +1,15 @@
 import { Pipeline } from "@/components/Pipeline";
 import { RefreshCounter } from "@/components/RefreshCounter";
+import { zookeeperHeaders } from "@/server/zookeeper";
 
 export default async function Home() {
-  const h1 = new Headers();
-  h1.append("Content-Type", "application/json");
+  const headers = zookeeperHeaders();
 
-  const res = await fetch("http://localhost:3235/pipeline/pipelines");
+  const res = await fetch("http://localhost:3235/pipeline/pipelines", {
+    headers,
+  });
   const body: { pipelineNames: string[] } = await res.json();
 
-  const h2 = new Headers();
-  h2.append("Content-Type", "application/json");
-
   const pipelines: string[] = body.pipelineNames;
   const responses: Record<string, any> = {};
 
@@ -21,7 +20,7 @@ export default async function Home() {
         {
           next: { revalidate: 2 },
           method: "GET",
-          headers: h2,
+          headers,
         },
       ).then((x) => x.json());
       console.log(res.name, res.events);
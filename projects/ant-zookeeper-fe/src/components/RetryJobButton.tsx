@@ -1,5 +1,7 @@
 "use client";
 
+import { retryJob } from "@/server/actions";
+
 export type RetryJobButtonProps = {
   jobId: string;
 };
@@ -8,15 +10,7 @@ export function RetryJobButton(props: RetryJobButtonProps) {
   return (
     <button
       onClick={() => {
-        fetch("http://localhost:3235/deployment/retry", {
-          method: "POST",
-          headers: {
-            "Content-Type": "application/json",
-          },
-          body: JSON.stringify({
-            jobId: props.jobId,
-          }),
-        });
+        retryJob(props.jobId);
       }}
     >
       retry?
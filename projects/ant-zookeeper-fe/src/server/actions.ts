@@ -1,7 +1,18 @@
 "use server";
 
 import { revalidatePath } from "next/cache";
+import { zookeeperHeaders } from "./zookeeper";
 
 export const refresh = async () => {
   revalidatePath("/");
 };
+
+export const retryJob = async (jobId: string) => {
+  await fetch("http://localhost:3235/deployment/retry", {
+    method: "POST",
+    headers: zookeeperHeaders(),
+    body: JSON.stringify({
+      jobId,
+    }),
+  });
+};
@@ -0,0 +1,10 @@
+/**
+ * The headers every request to ant-zookeeper needs, with the token of this frontend. Only import
+ * this from server code, the token must never reach the browser.
+ */
+export const zookeeperHeaders = (): Headers => {
+  const headers = new Headers();
+  headers.append("Content-Type", "application/json");
+  headers.append("Authorization", `Bearer ${process.env.ANT_ZOOKEEPER_TOKEN}`);
+  return headers;
+};
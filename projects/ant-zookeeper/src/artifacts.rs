@@ -0,0 +1,187 @@
+//! The build artifacts in artifacts-db, stored once per content as `sha256/<fingerprint>.bld` and
+//! shared by every revision registering the same build.
+//!
+//! Garbage collection marks the artifacts of revisions that are no longer retained as deleted, and
+//! removes the files no remaining artifact references.
+
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::{
+    fs::{artifact_persist_dir, services_persist_dir},
+    state::AntZookeeperState,
+};
+
+/// How many deployed revisions of each project are retained when not said otherwise.
+pub const DEFAULT_KEEP_DEPLOYED_REVISIONS: i64 = 5;
+
+/// Files more recent than this are never collected, they may be uploads not registered yet.
+const GRACE_PERIOD: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct GarbageCollection {
+    /// The artifacts no longer retained, their revisions can no longer be deployed.
+    pub artifact_ids: Vec<String>,
+    /// The files removed, relative to the root directory of ant-zookeeper.
+    pub files: Vec<String>,
+    pub freed_bytes: u64,
+}
+
+/// Every file under `dir`, recursively. None if it does not exist.
+fn list_files(dir: &Path) -> Result<Vec<PathBuf>, anyhow::Error> {
+    let mut files = vec![];
+    if !dir.exists() {
+        return Ok(files);
+    }
+
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            files.extend(list_files(&path)?);
+        } else {
+            files.push(path);
+        }
+    }
+
+    Ok(files)
+}
+
+/// The files under `store_dir` that are not `referenced`, relative to it, and were last modified
+/// before `modified_before`.
+fn unreferenced_files(
+    store_dir: &Path,
+    referenced: &HashSet<PathBuf>,
+    modified_before: SystemTime,
+) -> Result<Vec<PathBuf>, anyhow::Error> {
+    let mut files = vec![];
+    for path in list_files(store_dir)? {
+        if referenced.contains(path.strip_prefix(store_dir)?) {
+            continue;
+        }
+        if std::fs::metadata(&path)?.modified()? >= modified_before {
+            continue;
+        }
+        files.push(path);
+    }
+
+    files.sort();
+    Ok(files)
+}
+
+/// Collect the artifacts of revisions that are no longer retained, keeping the last
+/// `keep_deployed` deployed revisions of each project, then remove the files nothing references.
+/// With `dry_run`, only says what would be collected.
+pub async fn collect_garbage(
+    state: &AntZookeeperState,
+    keep_deployed: i64,
+    dry_run: bool,
+) -> Result<GarbageCollection, anyhow::Error> {
+    let artifact_ids = state.db.list_unretained_artifacts(keep_deployed).await?;
+    let unretained: HashSet<&String> = artifact_ids.iter().collect();
+
+    let referenced: HashSet<PathBuf> = state
+        .db
+        .list_stored_artifacts()
+        .await?
+        .into_iter()
+        .filter(|artifact| !unretained.contains(&artifact.artifact_id))
+        .map(|artifact| PathBuf::from(artifact.local_path))
+        .collect();
+
+    if !dry_run {
+        state.db.delete_artifacts(&artifact_ids).await?;
+    }
+
+    let mut files = unreferenced_files(
+        &artifact_persist_dir(&state.root_dir),
+        &referenced,
+        SystemTime::now() - GRACE_PERIOD,
+    )?;
+    // Replicated service files used to be kept forever, none of them are needed.
+    files.extend(list_files(&services_persist_dir(&state.root_dir))?);
+
+    let mut freed_bytes = 0;
+    for path in &files {
+        freed_bytes += tokio::fs::metadata(path).await?.len();
+        if !dry_run {
+            tokio::fs::remove_file(path).await?;
+        }
+    }
+
+    let files = files
+        .iter()
+        .map(|path| {
+            path.strip_prefix(&state.root_dir)
+                .unwrap_or(path)
+                .display()
+                .to_string()
+        })
+        .collect::<Vec<_>>();
+    info!(
+        "Collected {} artifacts and {} files, freeing {} (dry run: {dry_run}).",
+        artifact_ids.len(),
+        files.len(),
+        humansize::format_size(freed_bytes, humansize::DECIMAL)
+    );
+
+    Ok(GarbageCollection {
+        artifact_ids,
+        files,
+        freed_bytes,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashSet,
+        path::PathBuf,
+        time::{Duration, SystemTime},
+    };
+
+    use super::unreferenced_files;
+
+    #[test]
+    fn unreferenced_files_skips_referenced_and_recent_files() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("sha256")).unwrap();
+        for file in [
+            "sha256/aaaa.bld",
+            "sha256/bbbb.bld",
+            "ant-gateway.aarch64.v1.bld",
+        ] {
+            std::fs::write(dir.path().join(file), b"artifact").unwrap();
+        }
+        let referenced = HashSet::from([PathBuf::from("sha256/aaaa.bld")]);
+
+        let files = unreferenced_files(
+            dir.path(),
+            &referenced,
+            SystemTime::now() + Duration::from_secs(1),
+        )
+        .unwrap();
+        assert_eq!(
+            files,
+            vec![
+                dir.path().join("ant-gateway.aarch64.v1.bld"),
+                dir.path().join("sha256/bbbb.bld"),
+            ]
+        );
+
+        // Files written since are left alone.
+        let files = unreferenced_files(
+            dir.path(),
+            &referenced,
+            SystemTime::now() - Duration::from_secs(60),
+        )
+        .unwrap();
+        assert!(files.is_empty());
+    }
+}
@@ -0,0 +1,89 @@
+use ant_zookeeper_db::ClientRole;
+use axum::extract::{FromRef, FromRequestParts};
+use axum_extra::{
+    headers::{authorization::Bearer, Authorization},
+    TypedHeader,
+};
+use http::request::Parts;
+use serde::Serialize;
+use tracing::debug;
+
+use crate::{err::AntZookeeperError, state::AntZookeeperState};
+
+pub struct BearerClaims {
+    pub client_id: String,
+    pub client_name: String,
+    pub role: ClientRole,
+}
+
+impl BearerClaims {
+    /// Fails with 403 unless the client's role allows what `role` may do.
+    pub fn require(&self, role: ClientRole) -> Result<(), AntZookeeperError> {
+        if !self.role.allows(role) {
+            return Err(AntZookeeperError::Forbidden(format!(
+                "client {} is {}, requires {}",
+                self.client_name,
+                self.role.as_str(),
+                role.as_str()
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Record in the audit log that this client changed something with request `req`.
+    pub async fn audit<Req: Serialize>(
+        &self,
+        state: &AntZookeeperState,
+        action: &str,
+        req: &Req,
+    ) -> Result<(), AntZookeeperError> {
+        state
+            .db
+            .record_audit(&self.client_id, action, &serde_json::to_string(req)?)
+            .await
+            .map_err(|e| AntZookeeperError::InternalServerError {
+                id: "ANT-ERR-183",
+                err: Some(e),
+            })
+    }
+}
+
+/// Fails with 401 if no bearer token of a known client is present.
+impl<S> FromRequestParts<S> for BearerClaims
+where
+    AntZookeeperState: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = AntZookeeperError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let TypedHeader(auth) =
+            <TypedHeader<Authorization<Bearer>> as FromRequestParts<S>>::from_request_parts(
+                parts, state,
+            )
+            .await
+            .map_err(|e| {
+                debug!("No bearer token: {e}");
+                AntZookeeperError::Unauthorized
+            })?;
+
+        let state = AntZookeeperState::from_ref(state);
+        let (client_id, client_name, role) = state
+            .db
+            .authenticate_bearer(auth.token())
+            .await
+            .map_err(|e| AntZookeeperError::InternalServerError {
+                id: "ANT-ERR-184",
+                err: Some(e),
+            })?
+            .ok_or(AntZookeeperError::Unauthorized)?;
+
+        debug!("Client {client_name} ({client_id}) has role {role:?}");
+        Ok(BearerClaims {
+            client_id,
+            client_name,
+            role,
+        })
+    }
+}
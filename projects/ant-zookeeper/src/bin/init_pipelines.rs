@@ -19,6 +19,7 @@ async fn main() -> Result<(), anyhow::Error> {
     let client = AntZookeeperClient::new(AntZookeeperClientConfig {
         tls: false,
         endpoint: "localhost:3235".to_string(),
+        token: std::env::var("ANT_ZOOKEEPER_TOKEN").ok(),
     });
 
     let mut service_ids = services.list_service_ids();
@@ -0,0 +1,164 @@
+//! Issue certificates from an ACME directory: LetsEncrypt, or any other like a local Pebble.
+
+use std::{path::PathBuf, time::Duration};
+
+use acme_lib::{persist::FilePersist, Directory, DirectoryUrl};
+use rsa::{pkcs1::EncodeRsaPrivateKey, rand_core::OsRng, RsaPrivateKey};
+use tokio::{fs::create_dir_all, sync::Mutex, time::sleep};
+use tracing::{debug, info};
+
+use crate::{
+    cert::{acme_domain, CertificateAuthority, IssuedCertificate},
+    dns::Dns,
+};
+
+pub struct AcmeCertificateAuthority {
+    pub url: DirectoryUrl<'static>,
+    pub contact_email: String,
+    /// Where the ACME account and issued certificates are kept.
+    pub persist_dir: PathBuf,
+}
+
+async fn clean_acme_records(
+    dns: &Mutex<dyn Dns>,
+    domains: &[String],
+) -> Result<bool, anyhow::Error> {
+    // Clean old ACME records because LetsEncrypt won't validate if there is more than 1 record there...
+    let mut cleaned = false;
+    for domain in domains {
+        let records = dns
+            .lock()
+            .await
+            .list_txt_records(&acme_domain(domain))
+            .await?;
+
+        for record in records {
+            info!("Cleaning old ACME related record: {domain} => {record:?}");
+            dns.lock().await.delete_txt_record(&record.id).await?;
+            cleaned = true;
+        }
+    }
+
+    return Ok(cleaned);
+}
+
+/// `acme_lib` talks to the directory with blocking HTTP calls, so they run off the async runtime.
+async fn blocking<T: Send + 'static>(
+    f: impl FnOnce() -> Result<T, acme_lib::Error> + Send + 'static,
+) -> Result<T, anyhow::Error> {
+    Ok(tokio::task::spawn_blocking(f).await??)
+}
+
+#[async_trait::async_trait]
+impl CertificateAuthority for AcmeCertificateAuthority {
+    /// Takes a minute or so per domain, waiting for the DNS challenges to propagate.
+    async fn issue(
+        &self,
+        dns: &Mutex<dyn Dns>,
+        domains: &[String],
+    ) -> Result<IssuedCertificate, anyhow::Error> {
+        let (subject, alt_names) = domains
+            .split_first()
+            .ok_or_else(|| anyhow::Error::msg("No domains requested."))?;
+
+        create_dir_all(&self.persist_dir).await?;
+        let persist = FilePersist::new(&self.persist_dir);
+
+        let url = self.url.clone();
+        let contact_email = self.contact_email.clone();
+        let acc =
+            blocking(move || Directory::from_url(persist, url)?.account(&contact_email)).await?;
+
+        let cleaned = clean_acme_records(dns, domains).await?;
+        if cleaned {
+            info!("Sleeping for some time before continuing to let DNS records propagate...");
+            sleep(Duration::from_secs(10)).await;
+            info!("Continuing!");
+        }
+
+        info!("Creating new order...");
+        let subject = subject.clone();
+        let alt_names = alt_names.to_vec();
+        let mut order = blocking(move || {
+            acc.new_order(
+                &subject,
+                &alt_names.iter().map(String::as_str).collect::<Vec<&str>>(),
+            )
+        })
+        .await?;
+
+        let csr = loop {
+            if let Some(csr) = order.confirm_validations() {
+                info!("Challenge met, moving to providing private key material...");
+                break csr;
+            }
+
+            let auths;
+            (order, auths) = blocking(move || {
+                let auths = order.authorizations()?;
+                Ok((order, auths))
+            })
+            .await?;
+
+            for auth in auths {
+                let challenge = auth.dns_challenge();
+
+                info!("Putting DNS record...");
+                dns.lock()
+                    .await
+                    .put_txt_record(&acme_domain(&auth.domain_name()), challenge.dns_proof())
+                    .await?;
+
+                loop {
+                    info!("Polling dns for presence of record...");
+                    if dns
+                        .lock()
+                        .await
+                        .list_txt_records(&acme_domain(&auth.domain_name()))
+                        .await?
+                        .iter()
+                        .find(|record| record.content == challenge.dns_proof())
+                        .is_some()
+                    {
+                        break;
+                    }
+
+                    sleep(Duration::from_millis(500)).await;
+                }
+
+                info!("DNS records input, sleeping for propagation...");
+                sleep(Duration::from_millis(30_000)).await;
+
+                info!("Asking ACME to check...");
+                order = blocking(move || {
+                    challenge.validate(5_000)?;
+                    order.refresh()?;
+                    Ok(order)
+                })
+                .await?;
+            }
+        };
+
+        debug!("Generating private key...");
+        let mut rng = OsRng;
+        let priv_key = RsaPrivateKey::new(&mut rng, 2048)?;
+
+        debug!("Generating PEM document...");
+        let pem = priv_key.to_pkcs1_pem(base64ct::LineEnding::LF)?;
+
+        debug!("Finalizing order...");
+        let order_certificate = blocking(move || csr.finalize(&pem, 5_000)).await?;
+
+        debug!("Downloading certificate...");
+        let files = blocking(move || order_certificate.download_and_save_cert()).await?;
+
+        debug!("Deleting TXT records created during the challenge...");
+        clean_acme_records(dns, domains).await?;
+
+        return Ok(IssuedCertificate {
+            certificate: files.certificate().to_string(),
+            private_key: files.private_key().to_string(),
+            valid_days_left: files.valid_days_left(),
+        });
+    }
+}
@@ -0,0 +1,203 @@
+//! A certificate authority of its own, standing in for an ACME directory when running locally and
+//! in tests. It asks for the same DNS challenges, and checks them in the `Dns` itself.
+
+use openssl::{
+    asn1::Asn1Time,
+    bn::{BigNum, MsbOption},
+    hash::MessageDigest,
+    pkey::{PKey, Private},
+    rsa::Rsa,
+    x509::{
+        extension::{BasicConstraints, KeyUsage, SubjectAlternativeName},
+        X509Builder, X509NameBuilder, X509,
+    },
+};
+use tokio::sync::Mutex;
+use tracing::info;
+
+use crate::{
+    cert::{acme_domain, CertificateAuthority, IssuedCertificate},
+    dns::Dns,
+};
+
+pub struct LocalCertificateAuthority {
+    key: PKey<Private>,
+    certificate: X509,
+    /// How long the certificates it issues are valid for.
+    valid_days: u32,
+}
+
+fn random_serial() -> Result<openssl::asn1::Asn1Integer, anyhow::Error> {
+    let mut serial = BigNum::new()?;
+    serial.rand(128, MsbOption::MAYBE_ZERO, false)?;
+    Ok(serial.to_asn1_integer()?)
+}
+
+fn random_token() -> Result<String, anyhow::Error> {
+    let mut bytes = [0u8; 16];
+    openssl::rand::rand_bytes(&mut bytes)?;
+    Ok(bytes.iter().map(|b| format!("{b:02x}")).collect())
+}
+
+impl LocalCertificateAuthority {
+    /// A new authority with a self-signed root, issuing certificates valid for `valid_days`.
+    pub fn new(valid_days: u32) -> Result<Self, anyhow::Error> {
+        let key = PKey::from_rsa(Rsa::generate(2048)?)?;
+
+        let mut name = X509NameBuilder::new()?;
+        name.append_entry_by_text("CN", "ant-zookeeper local authority")?;
+        let name = name.build();
+
+        let serial = random_serial()?;
+        let mut builder = X509Builder::new()?;
+        builder.set_version(2)?;
+        builder.set_serial_number(&serial)?;
+        builder.set_subject_name(&name)?;
+        builder.set_issuer_name(&name)?;
+        builder.set_pubkey(&key)?;
+        builder.set_not_before(&*Asn1Time::days_from_now(0)?)?;
+        builder.set_not_after(&*Asn1Time::days_from_now(3650)?)?;
+        builder.append_extension(BasicConstraints::new().critical().ca().build()?)?;
+        builder.append_extension(
+            KeyUsage::new()
+                .critical()
+                .key_cert_sign()
+                .crl_sign()
+                .build()?,
+        )?;
+        builder.sign(&key, MessageDigest::sha256())?;
+
+        Ok(LocalCertificateAuthority {
+            key,
+            certificate: builder.build(),
+            valid_days,
+        })
+    }
+
+    /// The root certificate, in PEM, for clients to trust the issued certificates.
+    pub fn root_certificate(&self) -> Result<String, anyhow::Error> {
+        Ok(String::from_utf8(self.certificate.to_pem()?)?)
+    }
+
+    fn sign(&self, domains: &[String]) -> Result<IssuedCertificate, anyhow::Error> {
+        let key = PKey::from_rsa(Rsa::generate(2048)?)?;
+
+        let mut name = X509NameBuilder::new()?;
+        name.append_entry_by_text("CN", &domains[0])?;
+        let name = name.build();
+
+        let serial = random_serial()?;
+        let mut builder = X509Builder::new()?;
+        builder.set_version(2)?;
+        builder.set_serial_number(&serial)?;
+        builder.set_subject_name(&name)?;
+        builder.set_issuer_name(self.certificate.subject_name())?;
+        builder.set_pubkey(&key)?;
+        builder.set_not_before(&*Asn1Time::days_from_now(0)?)?;
+        builder.set_not_after(&*Asn1Time::days_from_now(self.valid_days)?)?;
+
+        let mut alt_names = SubjectAlternativeName::new();
+        for domain in domains {
+            alt_names.dns(domain);
+        }
+        let alt_names = alt_names.build(&builder.x509v3_context(Some(&self.certificate), None))?;
+        builder.append_extension(alt_names)?;
+        builder.sign(&self.key, MessageDigest::sha256())?;
+
+        let mut chain = builder.build().to_pem()?;
+        chain.extend(self.certificate.to_pem()?);
+
+        Ok(IssuedCertificate {
+            certificate: String::from_utf8(chain)?,
+            private_key: String::from_utf8(key.private_key_to_pem_pkcs8()?)?,
+            valid_days_left: self.valid_days as i64,
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl CertificateAuthority for LocalCertificateAuthority {
+    async fn issue(
+        &self,
+        dns: &Mutex<dyn Dns>,
+        domains: &[String],
+    ) -> Result<IssuedCertificate, anyhow::Error> {
+        if domains.is_empty() {
+            return Err(anyhow::Error::msg("No domains requested."));
+        }
+
+        for domain in domains {
+            let proof = random_token()?;
+            let record = dns
+                .lock()
+                .await
+                .put_txt_record(&acme_domain(domain), proof.clone())
+                .await?;
+
+            let is_proven = dns
+                .lock()
+                .await
+                .list_txt_records(&acme_domain(domain))
+                .await?
+                .iter()
+                .any(|r| r.content == proof);
+            dns.lock().await.delete_txt_record(&record.id).await?;
+
+            if !is_proven {
+                return Err(anyhow::Error::msg(format!(
+                    "DNS challenge of {domain} not found"
+                )));
+            }
+            info!("DNS challenge of {domain} met.");
+        }
+
+        self.sign(domains)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use openssl::x509::X509;
+    use tokio::sync::Mutex;
+
+    use super::LocalCertificateAuthority;
+    use crate::{
+        cert::CertificateAuthority,
+        dns::{Dns, LocalDns},
+    };
+
+    #[tokio::test]
+    async fn local_authority_issues_certificates_it_signed() {
+        let authority = LocalCertificateAuthority::new(90).unwrap();
+        let dns = Mutex::new(LocalDns::in_memory());
+
+        let domains = vec![
+            "typesofants.org".to_string(),
+            "www.typesofants.org".to_string(),
+        ];
+        let issued = authority.issue(&dns, &domains).await.unwrap();
+        assert_eq!(issued.valid_days_left, 90);
+
+        let chain = X509::stack_from_pem(issued.certificate.as_bytes()).unwrap();
+        assert_eq!(chain.len(), 2);
+        let root = X509::from_pem(authority.root_certificate().unwrap().as_bytes()).unwrap();
+        assert!(chain[0].verify(&root.public_key().unwrap()).unwrap());
+
+        let alt_names: Vec<String> = chain[0]
+            .subject_alt_names()
+            .unwrap()
+            .iter()
+            .filter_map(|name| name.dnsname().map(str::to_string))
+            .collect();
+        assert_eq!(alt_names, domains);
+
+        // The challenges are cleaned up.
+        assert!(dns
+            .lock()
+            .await
+            .list_txt_records("_acme-challenge.typesofants.org")
+            .await
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -0,0 +1,33 @@
+//! Certificates of the environments, issued by a `CertificateAuthority` and renewed in the
+//! background by the `renewer`.
+
+use tokio::sync::Mutex;
+
+use crate::dns::Dns;
+
+pub mod acme;
+pub mod local;
+pub mod renewer;
+
+/// A freshly issued certificate, in PEM.
+pub struct IssuedCertificate {
+    pub certificate: String,
+    pub private_key: String,
+    pub valid_days_left: i64,
+}
+
+#[async_trait::async_trait]
+pub trait CertificateAuthority: Send + Sync {
+    /// Issue a certificate for `domains`, the first being its subject, proving ownership of them
+    /// with DNS challenges in `dns`.
+    async fn issue(
+        &self,
+        dns: &Mutex<dyn Dns>,
+        domains: &[String],
+    ) -> Result<IssuedCertificate, anyhow::Error>;
+}
+
+/// The name of the TXT record proving ownership of `owned_domain` to an ACME directory.
+pub(crate) fn acme_domain(owned_domain: &str) -> String {
+    format!("_acme-challenge.{owned_domain}")
+}
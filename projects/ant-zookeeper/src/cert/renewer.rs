@@ -0,0 +1,289 @@
+//! Renew the certificate of each environment in the background, some days before it expires.
+//!
+//! Certificates are tracked in the `certificate` table, and a renewal is due at its
+//! `next_attempt_at`. Renewing issues a new certificate, stores it as the next version of the
+//! `tls_cert` and `tls_key` secrets, and starts a pipeline rolling it out to the gateway hosts of
+//! the environment. Failed renewals are retried with a backoff, and alert once they keep failing.
+
+use std::time::Duration;
+
+use ant_zookeeper_db::Certificate;
+use chrono::{DateTime, Utc};
+use tokio::sync::watch;
+use tracing::{error, info, warn};
+
+use crate::{
+    cert::IssuedCertificate,
+    event_loop::{
+        cert_rollout::{CERTIFICATE_SECRET, PRIVATE_KEY_SECRET},
+        route_update::{environment_hosts, GATEWAY_PROJECT},
+    },
+    pipeline::dag::build_certificate_rollout_dag,
+    secrets::put_secret,
+    state::AntZookeeperState,
+};
+
+/// The project of the pipelines rolling certificates out, registered by the migrations.
+pub const ROLLOUT_PROJECT: &str = "ant-gateway-certificate";
+
+#[derive(Debug, Clone)]
+pub struct RenewerConfig {
+    /// How long before a certificate expires to renew it.
+    pub renew_before: chrono::Duration,
+    /// How often to look for certificates due for renewal.
+    pub interval: Duration,
+    /// How long a zookeeper has to renew a certificate before another may try.
+    pub lease: chrono::Duration,
+    /// How long to wait before retrying a failed renewal, doubling with each failure.
+    pub retry_backoff: chrono::Duration,
+    /// The longest to wait between retries.
+    pub max_retry_backoff: chrono::Duration,
+    /// How many renewals in a row fail before alerting.
+    pub alert_after_failures: i32,
+}
+
+impl Default for RenewerConfig {
+    fn default() -> Self {
+        RenewerConfig {
+            renew_before: chrono::Duration::days(30),
+            interval: Duration::from_secs(60),
+            lease: chrono::Duration::hours(1),
+            retry_backoff: chrono::Duration::hours(1),
+            max_retry_backoff: chrono::Duration::hours(24),
+            alert_after_failures: 3,
+        }
+    }
+}
+
+/// Renew certificates as they become due until `shutdown` flips to `true`.
+pub async fn run(
+    state: AntZookeeperState,
+    config: RenewerConfig,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    while !*shutdown.borrow() {
+        if let Err(e) = renew_due(&state, &config).await {
+            error!("ANT-ERR-193: Certificate renewer failed: {e:?}");
+        }
+
+        tokio::select! {
+            _ = tokio::time::sleep(config.interval) => {}
+            _ = shutdown.changed() => {}
+        }
+    }
+}
+
+/// Renew every certificate that is due. Returns how many renewals were attempted.
+pub async fn renew_due(
+    state: &AntZookeeperState,
+    config: &RenewerConfig,
+) -> Result<usize, anyhow::Error> {
+    let mut attempts = 0;
+    while let Some(certificate) = state.db.claim_due_certificate(config.lease).await? {
+        attempts += 1;
+        renew(state, config, &certificate).await?;
+    }
+
+    Ok(attempts)
+}
+
+async fn renew(
+    state: &AntZookeeperState,
+    config: &RenewerConfig,
+    certificate: &Certificate,
+) -> Result<(), anyhow::Error> {
+    let environment = &certificate.environment;
+    info!(
+        "Renewing the {environment} certificate for {:?}...",
+        certificate.domains
+    );
+
+    match attempt_renewal(state, config, certificate).await {
+        Ok(expires_at) => {
+            info!("Renewed the {environment} certificate, it expires at {expires_at}.");
+        }
+        Err(e) => {
+            warn!("ANT-ERR-191: Renewing the {environment} certificate failed: {e:?}");
+            let failed_attempts = state
+                .db
+                .record_certificate_failure(
+                    environment,
+                    &format!("{e:#}"),
+                    Utc::now() + retry_backoff(config, certificate.failed_attempts + 1),
+                )
+                .await?;
+
+            if failed_attempts >= config.alert_after_failures {
+                error!(
+                    "ANT-ERR-192: Renewing the {environment} certificate failed {failed_attempts} \
+                     times in a row, it expires at {:?}",
+                    certificate.expires_at
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Issue and store a new certificate, and start rolling it out. Returns when it expires.
+async fn attempt_renewal(
+    state: &AntZookeeperState,
+    config: &RenewerConfig,
+    certificate: &Certificate,
+) -> Result<DateTime<Utc>, anyhow::Error> {
+    let environment = &certificate.environment;
+    let issued = state
+        .certificate_authority
+        .issue(&state.dns, &certificate.domains)
+        .await?;
+
+    let now = Utc::now();
+    // Subtract 1 day for some safety.
+    let expires_at = now + chrono::Duration::days(issued.valid_days_left - 1);
+    store_certificate(state, environment, &issued, expires_at - now).await?;
+    start_rollout(state, environment).await?;
+
+    state
+        .db
+        .record_certificate_renewed(
+            environment,
+            expires_at,
+            next_renewal(now, expires_at, config.renew_before),
+        )
+        .await?;
+
+    Ok(expires_at)
+}
+
+/// Store the certificate as the next version of the secrets of the environment, for the gateways
+/// deployed from now on.
+async fn store_certificate(
+    state: &AntZookeeperState,
+    environment: &str,
+    issued: &IssuedCertificate,
+    valid_for: chrono::Duration,
+) -> Result<(), anyhow::Error> {
+    for (name, value) in [
+        (CERTIFICATE_SECRET, &issued.certificate),
+        (PRIVATE_KEY_SECRET, &issued.private_key),
+    ] {
+        put_secret(state, name, environment, None, value.as_bytes(), valid_for).await?;
+    }
+
+    Ok(())
+}
+
+/// Start a pipeline rolling the certificate out to the gateway hosts of the environment.
+async fn start_rollout(state: &AntZookeeperState, environment: &str) -> Result<(), anyhow::Error> {
+    let hosts = environment_hosts(state, GATEWAY_PROJECT, environment);
+    if hosts.is_empty() {
+        warn!("No {GATEWAY_PROJECT} host in {environment} to roll the certificate out to");
+        return Ok(());
+    }
+
+    // Pipelines deploy a revision, so each rollout is a revision of its own, without artifacts.
+    let revision_id = state.db.create_revision(ROLLOUT_PROJECT).await?;
+    state.db.activate_revision(&revision_id).await?;
+
+    let pipeline_id = build_certificate_rollout_dag(
+        &state.engine,
+        ROLLOUT_PROJECT,
+        &revision_id,
+        environment,
+        &hosts,
+    )
+    .await?;
+    info!("Rolling the {environment} certificate out in {pipeline_id}.");
+
+    Ok(())
+}
+
+/// The expiry and failed renewals of every certificate, rendered for Prometheus on `GET /metrics`.
+pub fn render_metrics(certificates: &[Certificate]) -> String {
+    let mut metrics = String::from(
+        "# HELP ant_zookeeper_certificate_expiry_timestamp_seconds When the certificate of an \
+         environment expires\n\
+         # TYPE ant_zookeeper_certificate_expiry_timestamp_seconds gauge\n",
+    );
+    for c in certificates {
+        if let Some(expires_at) = c.expires_at {
+            metrics.push_str(&format!(
+                "ant_zookeeper_certificate_expiry_timestamp_seconds{{environment=\"{}\"}} {}\n",
+                c.environment,
+                expires_at.timestamp()
+            ));
+        }
+    }
+
+    metrics.push_str(
+        "# HELP ant_zookeeper_certificate_failed_renewals Renewals of the certificate of an \
+         environment that failed in a row\n\
+         # TYPE ant_zookeeper_certificate_failed_renewals gauge\n",
+    );
+    for c in certificates {
+        metrics.push_str(&format!(
+            "ant_zookeeper_certificate_failed_renewals{{environment=\"{}\"}} {}\n",
+            c.environment, c.failed_attempts
+        ));
+    }
+
+    metrics
+}
+
+/// When to renew a certificate expiring at `expires_at`: `renew_before` ahead, or halfway there
+/// for certificates valid for less than that.
+fn next_renewal(
+    now: DateTime<Utc>,
+    expires_at: DateTime<Utc>,
+    renew_before: chrono::Duration,
+) -> DateTime<Utc> {
+    let halfway = now + (expires_at - now) / 2;
+    (expires_at - renew_before).max(halfway)
+}
+
+/// How long to wait before the next attempt, after `failed_attempts` renewals failed in a row.
+fn retry_backoff(config: &RenewerConfig, failed_attempts: i32) -> chrono::Duration {
+    let doublings = (failed_attempts - 1).clamp(0, 16) as u32;
+    (config.retry_backoff * 2i32.pow(doublings)).min(config.max_retry_backoff)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+
+    use super::{next_renewal, retry_backoff, RenewerConfig};
+
+    #[test]
+    fn renews_ahead_of_expiry() {
+        let now = Utc.with_ymd_and_hms(2026, 1, 1, 0, 0, 0).unwrap();
+        let expires_at = now + chrono::Duration::days(89);
+
+        assert_eq!(
+            next_renewal(now, expires_at, chrono::Duration::days(30)),
+            now + chrono::Duration::days(59)
+        );
+    }
+
+    #[test]
+    fn renews_short_lived_certificates_halfway() {
+        let now = Utc.with_ymd_and_hms(2026, 1, 1, 0, 0, 0).unwrap();
+        let expires_at = now + chrono::Duration::days(6);
+
+        assert_eq!(
+            next_renewal(now, expires_at, chrono::Duration::days(30)),
+            now + chrono::Duration::days(3)
+        );
+    }
+
+    #[test]
+    fn retries_back_off_up_to_the_max() {
+        let config = RenewerConfig::default();
+
+        assert_eq!(retry_backoff(&config, 1), chrono::Duration::hours(1));
+        assert_eq!(retry_backoff(&config, 2), chrono::Duration::hours(2));
+        assert_eq!(retry_backoff(&config, 4), chrono::Duration::hours(8));
+        assert_eq!(retry_backoff(&config, 6), chrono::Duration::hours(24));
+        assert_eq!(retry_backoff(&config, 100), chrono::Duration::hours(24));
+    }
+}
@@ -1,17 +1,21 @@
 use std::path::Path;
 
-use ant_library::host_architecture::HostArchitecture;
+use ant_library::{host_architecture::HostArchitecture, provenance::SignedProvenance};
 use http::Method;
 use reqwest::{multipart::Form, Client};
 use serde::{Deserialize, Serialize};
 use tracing::error;
 
 use crate::routes::{
+    deployment::{
+        ApproveNodeRequest, ApproveNodeResponse, CancelPipelineRequest, CancelPipelineResponse,
+        PauseProjectRequest, PauseProjectResponse,
+    },
     pipeline::{
         AddHostToHostGroupRequest, AddHostToHostGroupResponse, CreateHostGroupRequest,
-        CreateHostGroupResponse, GetHostGroupRequest, GetHostGroupResponse, GetPipelineRequest,
-        GetPipelineResponse, PutPipelineRequest, PutPipelineResponse,
-        RemoveHostFromHostGroupRequest,
+        CreateHostGroupResponse, GetHostGroupRequest, GetHostGroupResponse,
+        GetPipelineGraphRequest, GetPipelineRequest, GetPipelineResponse, PutPipelineRequest,
+        PutPipelineResponse, RemoveHostFromHostGroupRequest,
     },
     service::{UpsertRevisionRequest, UpsertRevisionResponse},
 };
@@ -24,6 +28,8 @@ pub struct AntZookeeperClient {
 pub struct AntZookeeperClientConfig {
     pub tls: bool,
     pub endpoint: String,
+    /// The bearer token of the client, see `ant_zookeeper::auth`.
+    pub token: Option<String>,
 }
 
 impl AntZookeeperClient {
@@ -46,18 +52,21 @@ impl AntZookeeperClient {
         )
     }
 
+    fn request(&self, method: Method, path: &str) -> reqwest::RequestBuilder {
+        let req = self.client.request(method, self.endpoint(path));
+        match &self.config.token {
+            Some(token) => req.bearer_auth(token),
+            None => req,
+        }
+    }
+
     async fn send<Req: Serialize, Res: for<'a> Deserialize<'a>>(
         &self,
         method: Method,
         path: &'static str,
         req: Req,
     ) -> Result<Res, anyhow::Error> {
-        let res = self
-            .client
-            .request(method.clone(), &self.endpoint(path))
-            .json(&req)
-            .send()
-            .await?;
+        let res = self.request(method.clone(), path).json(&req).send().await?;
 
         if !res.status().is_success() {
             let status = res.status();
@@ -115,6 +124,30 @@ impl AntZookeeperClient {
         self.send(Method::GET, "/pipeline/pipeline", req).await
     }
 
+    /// The DAG of a pipeline engine pipeline, rendered as text in the requested format.
+    pub async fn get_pipeline_graph(
+        &self,
+        req: GetPipelineGraphRequest,
+    ) -> Result<String, anyhow::Error> {
+        let path = "/pipeline/graph";
+        let res = self.request(Method::GET, path).query(&req).send().await?;
+
+        if !res.status().is_success() {
+            let status = res.status();
+            let body = res.text().await.unwrap_or_default();
+            let msg = format!(
+                "GET {} failed with {}: {}",
+                self.endpoint(path),
+                status,
+                body
+            );
+            error!("ANT-ERR-202: {}", msg);
+            return Err(anyhow::Error::msg(msg));
+        }
+
+        Ok(res.text().await?)
+    }
+
     pub async fn put_pipeline(
         &self,
         req: PutPipelineRequest,
@@ -122,6 +155,34 @@ impl AntZookeeperClient {
         self.send(Method::POST, "/pipeline/pipeline", req).await
     }
 
+    pub async fn cancel_pipeline(
+        &self,
+        req: CancelPipelineRequest,
+    ) -> Result<CancelPipelineResponse, anyhow::Error> {
+        self.send(Method::POST, "/deployment/cancel", req).await
+    }
+
+    pub async fn approve_node(
+        &self,
+        req: ApproveNodeRequest,
+    ) -> Result<ApproveNodeResponse, anyhow::Error> {
+        self.send(Method::POST, "/deployment/approval", req).await
+    }
+
+    pub async fn pause_project(
+        &self,
+        req: PauseProjectRequest,
+    ) -> Result<PauseProjectResponse, anyhow::Error> {
+        self.send(Method::POST, "/deployment/pause", req).await
+    }
+
+    pub async fn resume_project(
+        &self,
+        req: PauseProjectRequest,
+    ) -> Result<PauseProjectResponse, anyhow::Error> {
+        self.send(Method::POST, "/deployment/resume", req).await
+    }
+
     pub async fn upsert_revision(
         &self,
         req: UpsertRevisionRequest,
@@ -136,14 +197,17 @@ impl AntZookeeperClient {
         arch: &HostArchitecture,
         version: &str,
         file_path: &Path,
+        provenance: &SignedProvenance,
     ) -> Result<(), anyhow::Error> {
-        let req = Form::new().file("file", file_path).await.unwrap();
+        let req = Form::new()
+            .file("file", file_path)
+            .await?
+            .text("provenance", serde_json::to_string(provenance)?);
 
         let path = "/service/artifact";
 
         let res = self
-            .client
-            .request(Method::POST, self.endpoint(path))
+            .request(Method::POST, path)
             .header("x-ant-revision", revision)
             .header("x-ant-project", project)
             .header("x-ant-architecture", arch.as_str())
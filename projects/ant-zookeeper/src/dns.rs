@@ -1,3 +1,8 @@
+use std::{
+    net::{Ipv4Addr, Ipv6Addr},
+    path::PathBuf,
+};
+
 use cloudflare::{
     endpoints::dns::dns::{self, DnsContent},
     framework::{
@@ -5,19 +10,97 @@ use cloudflare::{
         client::{async_api::Client, ClientConfig},
     },
 };
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
 use tracing::{debug, warn};
 
+/// The records ant-zookeeper manages: addresses of the routed domains, and TXT records for ACME
+/// challenges.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "content")]
+pub enum DnsRecordContent {
+    A(Ipv4Addr),
+    AAAA(Ipv6Addr),
+    CNAME(String),
+    TXT(String),
+}
+
+impl DnsRecordContent {
+    /// Whether the record resolves the name to an address, directly or through another name.
+    pub fn is_address(&self) -> bool {
+        matches!(
+            self,
+            DnsRecordContent::A(_) | DnsRecordContent::AAAA(_) | DnsRecordContent::CNAME(_)
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DnsRecord {
+    pub id: String,
+    pub name: String,
+    pub content: DnsRecordContent,
+}
+
+#[derive(Debug, Clone)]
+pub struct TxtRecord {
+    pub id: String,
+    pub content: String,
+}
+
 #[async_trait::async_trait]
-pub trait Dns: Send {
+pub trait Dns: Send + Sync {
+    /// Add a record for `name`. Returns that record, with its identifier.
+    /// Idempotent for identical records added onto the same name.
+    async fn put_record(
+        &self,
+        name: &str,
+        content: DnsRecordContent,
+    ) -> Result<DnsRecord, anyhow::Error>;
+
+    /// Delete a certain record directly by the identifier.
+    async fn delete_record(&self, record_id: &str) -> Result<(), anyhow::Error>;
+
+    /// List all records of the name, of the kinds in `DnsRecordContent`.
+    async fn list_records(&self, name: &str) -> Result<Vec<DnsRecord>, anyhow::Error>;
+
     /// Add a TXT record for `domain`, with value `val`. Returns that identifier.
     /// Idempotent for identical records added onto the same domain.
-    async fn put_txt_record(&self, domain: &str, val: String) -> Result<TxtRecord, anyhow::Error>;
+    async fn put_txt_record(&self, domain: &str, val: String) -> Result<TxtRecord, anyhow::Error> {
+        let record = self.put_record(domain, DnsRecordContent::TXT(val)).await?;
+        match record.content {
+            DnsRecordContent::TXT(content) => Ok(TxtRecord {
+                id: record.id,
+                content,
+            }),
+            other => Err(anyhow::Error::msg(format!(
+                "Put a TXT record, but got {other:?}"
+            ))),
+        }
+    }
 
     /// Delete a certain record directly by the identifier.
-    async fn delete_txt_record(&self, record_id: &str) -> Result<(), anyhow::Error>;
+    async fn delete_txt_record(&self, record_id: &str) -> Result<(), anyhow::Error> {
+        self.delete_record(record_id).await
+    }
 
     /// List all TXT records in the domain.
-    async fn list_txt_records(&self, domain: &str) -> Result<Vec<TxtRecord>, anyhow::Error>;
+    async fn list_txt_records(&self, domain: &str) -> Result<Vec<TxtRecord>, anyhow::Error> {
+        let txt_records = self
+            .list_records(domain)
+            .await?
+            .into_iter()
+            .filter_map(|record| match record.content {
+                DnsRecordContent::TXT(content) => Some(TxtRecord {
+                    id: record.id,
+                    content,
+                }),
+                _ => None,
+            })
+            .collect();
+
+        Ok(txt_records)
+    }
 }
 
 pub struct CloudFlareDns {
@@ -41,30 +124,47 @@ impl CloudFlareDns {
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct TxtRecord {
-    pub id: String,
-    pub content: String,
+fn from_cloudflare(content: DnsContent) -> Option<DnsRecordContent> {
+    match content {
+        DnsContent::A { content } => Some(DnsRecordContent::A(content)),
+        DnsContent::AAAA { content } => Some(DnsRecordContent::AAAA(content)),
+        DnsContent::CNAME { content } => Some(DnsRecordContent::CNAME(content)),
+        DnsContent::TXT { content } => Some(DnsRecordContent::TXT(content)),
+        _ => None,
+    }
+}
+
+fn to_cloudflare(content: DnsRecordContent) -> DnsContent {
+    match content {
+        DnsRecordContent::A(content) => DnsContent::A { content },
+        DnsRecordContent::AAAA(content) => DnsContent::AAAA { content },
+        DnsRecordContent::CNAME(content) => DnsContent::CNAME { content },
+        DnsRecordContent::TXT(content) => DnsContent::TXT { content },
+    }
 }
 
 #[async_trait::async_trait]
 impl Dns for CloudFlareDns {
-    async fn put_txt_record(&self, domain: &str, val: String) -> Result<TxtRecord, anyhow::Error> {
+    async fn put_record(
+        &self,
+        name: &str,
+        content: DnsRecordContent,
+    ) -> Result<DnsRecord, anyhow::Error> {
         if let Some(identical_record) = self
-            .list_txt_records(domain)
+            .list_records(name)
             .await?
-            .iter()
-            .find(|record| record.content == val)
+            .into_iter()
+            .find(|record| record.content == content)
         {
             warn!("ANT-ERR-069: Identical record already present, short-circuiting: {identical_record:?}");
-            return Ok(identical_record.clone());
+            return Ok(identical_record);
         }
 
         let request = dns::CreateDnsRecord {
             zone_identifier: &self.zone_id,
             params: dns::CreateDnsRecordParams {
-                name: &domain,
-                content: DnsContent::TXT { content: val },
+                name: &name,
+                content: to_cloudflare(content),
                 ttl: Some(1), // automatic
                 priority: None,
                 proxied: Some(false),
@@ -75,16 +175,15 @@ impl Dns for CloudFlareDns {
         let response = self.cloudflare.request(&request).await?;
         debug!("cloudflare:CreateDnsRecord:response {response:?}");
 
-        Ok(TxtRecord {
+        Ok(DnsRecord {
             id: response.result.id,
-            content: match response.result.content {
-                DnsContent::TXT { content } => content,
-                _ => panic!("Cannot be type other than TXT."),
-            },
+            name: response.result.name,
+            content: from_cloudflare(response.result.content)
+                .expect("Cannot be a type other than the one created."),
         })
     }
 
-    async fn delete_txt_record(&self, record_id: &str) -> Result<(), anyhow::Error> {
+    async fn delete_record(&self, record_id: &str) -> Result<(), anyhow::Error> {
         let request = dns::DeleteDnsRecord {
             zone_identifier: &self.zone_id,
             identifier: &record_id,
@@ -99,11 +198,11 @@ impl Dns for CloudFlareDns {
         Ok(())
     }
 
-    async fn list_txt_records(&self, domain: &str) -> Result<Vec<TxtRecord>, anyhow::Error> {
+    async fn list_records(&self, name: &str) -> Result<Vec<DnsRecord>, anyhow::Error> {
         let request = dns::ListDnsRecords {
             zone_identifier: &self.zone_id,
             params: dns::ListDnsRecordsParams {
-                name: Some(domain.to_string()),
+                name: Some(name.to_string()),
                 ..Default::default()
             },
         };
@@ -112,18 +211,181 @@ impl Dns for CloudFlareDns {
         let response = self.cloudflare.request(&request).await?;
         debug!("cloudflare:ListDnsRecords:response {response:?}");
 
-        let txt_records = response
+        let records = response
             .result
-            .iter()
-            .filter_map(|record| match &record.content {
-                DnsContent::TXT { content } => Some(TxtRecord {
-                    id: record.id.clone(),
-                    content: content.clone(),
-                }),
-                _ => None,
+            .into_iter()
+            .filter_map(|record| {
+                Some(DnsRecord {
+                    content: from_cloudflare(record.content)?,
+                    id: record.id,
+                    name: record.name,
+                })
             })
             .collect();
 
-        Ok(txt_records)
+        Ok(records)
+    }
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct LocalZone {
+    next_id: u64,
+    records: Vec<DnsRecord>,
+}
+
+/// A DNS zone kept in memory, and saved to a JSON file if it has one. For running ant-zookeeper
+/// locally and in tests, without Cloudflare.
+pub struct LocalDns {
+    path: Option<PathBuf>,
+    zone: Mutex<LocalZone>,
+}
+
+impl LocalDns {
+    pub fn in_memory() -> Self {
+        LocalDns {
+            path: None,
+            zone: Mutex::new(LocalZone::default()),
+        }
+    }
+
+    /// The zone saved at `path`, which is created on the first change if it doesn't exist.
+    pub fn from_file(path: PathBuf) -> Result<Self, anyhow::Error> {
+        let zone = match std::fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => LocalZone::default(),
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(LocalDns {
+            path: Some(path),
+            zone: Mutex::new(zone),
+        })
+    }
+
+    async fn save(&self, zone: &LocalZone) -> Result<(), anyhow::Error> {
+        if let Some(path) = &self.path {
+            if let Some(dir) = path.parent() {
+                tokio::fs::create_dir_all(dir).await?;
+            }
+            let tmp = path.with_extension("json.tmp");
+            tokio::fs::write(&tmp, serde_json::to_string_pretty(zone)?).await?;
+            tokio::fs::rename(&tmp, path).await?;
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl Dns for LocalDns {
+    async fn put_record(
+        &self,
+        name: &str,
+        content: DnsRecordContent,
+    ) -> Result<DnsRecord, anyhow::Error> {
+        let mut zone = self.zone.lock().await;
+        if let Some(identical_record) = zone
+            .records
+            .iter()
+            .find(|record| record.name == name && record.content == content)
+        {
+            return Ok(identical_record.clone());
+        }
+
+        zone.next_id += 1;
+        let record = DnsRecord {
+            id: format!("local-{}", zone.next_id),
+            name: name.to_string(),
+            content,
+        };
+        zone.records.push(record.clone());
+        self.save(&zone).await?;
+
+        Ok(record)
+    }
+
+    async fn delete_record(&self, record_id: &str) -> Result<(), anyhow::Error> {
+        let mut zone = self.zone.lock().await;
+        zone.records.retain(|record| record.id != record_id);
+        self.save(&zone).await?;
+
+        Ok(())
+    }
+
+    async fn list_records(&self, name: &str) -> Result<Vec<DnsRecord>, anyhow::Error> {
+        Ok(self
+            .zone
+            .lock()
+            .await
+            .records
+            .iter()
+            .filter(|record| record.name == name)
+            .cloned()
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::{Dns, DnsRecordContent, LocalDns};
+
+    #[tokio::test]
+    async fn local_dns_puts_records_idempotently() {
+        let dns = LocalDns::in_memory();
+
+        let a = dns
+            .put_record("typesofants.org", DnsRecordContent::A(Ipv4Addr::LOCALHOST))
+            .await
+            .unwrap();
+        let again = dns
+            .put_record("typesofants.org", DnsRecordContent::A(Ipv4Addr::LOCALHOST))
+            .await
+            .unwrap();
+        assert_eq!(a, again);
+
+        dns.put_txt_record("_acme-challenge.typesofants.org", "proof".to_string())
+            .await
+            .unwrap();
+        assert_eq!(
+            dns.list_records("typesofants.org").await.unwrap(),
+            vec![a.clone()]
+        );
+
+        dns.delete_record(&a.id).await.unwrap();
+        assert!(dns
+            .list_records("typesofants.org")
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            dns.list_txt_records("_acme-challenge.typesofants.org")
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn local_dns_survives_restarts() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("zone.json");
+
+        let dns = LocalDns::from_file(path.clone()).unwrap();
+        let record = dns
+            .put_record(
+                "beta.typesofants.org",
+                DnsRecordContent::CNAME("antworker002.hosts.typesofants.org".to_string()),
+            )
+            .await
+            .unwrap();
+
+        let dns = LocalDns::from_file(path).unwrap();
+        assert_eq!(
+            dns.list_records("beta.typesofants.org").await.unwrap(),
+            vec![record]
+        );
     }
 }
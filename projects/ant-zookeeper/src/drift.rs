@@ -0,0 +1,311 @@
+//! Find the services whose hosts run another version than the one last deployed there.
+//!
+//! The desired version of a service on a host is that of the revision of its latest finished
+//! `HostDeployment`, the actual one is what its ant-host-agent reports installed. Every check
+//! records each service as in sync, drifted, or unreachable in the `service_drift` table, for `GET
+//! /host/drift` and `GET /metrics`. When correcting, the zookeeper driving the pipelines gives
+//! drifted services a pipeline redeploying their desired revision to the drifted hosts, unless
+//! their project is busy with one already.
+
+use std::{
+    collections::{BTreeMap, HashSet},
+    str::FromStr,
+    time::Duration,
+};
+
+use ant_host_agent::client::AntHostAgentClientConfig;
+use ant_zookeeper_db::ServiceDrift;
+use chrono::Utc;
+use serde_json::json;
+use tokio::sync::watch;
+use tracing::{error, info, warn};
+
+use crate::{
+    fs::{read_artifact_manifest, revision_artifact_path},
+    inventory::unhealthy_hosts,
+    pipeline::{
+        dag::{build_dag, ProjectConfig},
+        resource_key::DeploymentResource,
+    },
+    state::AntZookeeperState,
+};
+
+/// The host runs the desired version.
+pub const IN_SYNC: &str = "in_sync";
+/// The host runs another version, or none at all.
+pub const DRIFTED: &str = "drifted";
+/// The ant-host-agent of the host could not be asked.
+pub const UNREACHABLE: &str = "unreachable";
+
+#[derive(Debug, Clone)]
+pub struct DriftConfig {
+    /// How often to check every host for drift.
+    pub interval: Duration,
+}
+
+impl Default for DriftConfig {
+    fn default() -> Self {
+        DriftConfig {
+            interval: Duration::from_secs(5 * 60),
+        }
+    }
+}
+
+/// Check for drift every interval until `shutdown` flips to `true`. Only the leader corrects it,
+/// see `correct_drifted`.
+pub async fn run(
+    state: AntZookeeperState,
+    config: DriftConfig,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    while !*shutdown.borrow() {
+        if let Err(e) = reconcile(&state).await {
+            error!("ANT-ERR-201: Drift reconciler failed: {e:?}");
+        }
+
+        tokio::select! {
+            _ = tokio::time::sleep(config.interval) => {}
+            _ = shutdown.changed() => {}
+        }
+    }
+}
+
+/// Check every service deployed to a host, returning the drift as recorded.
+pub async fn reconcile(state: &AntZookeeperState) -> Result<Vec<ServiceDrift>, anyhow::Error> {
+    let started_at = Utc::now();
+
+    let mut drift = vec![];
+    for (host_id, service_id, revision_id) in deployed_services(state).await? {
+        if let Some(d) = check(state, &host_id, &service_id, &revision_id).await? {
+            drift.push(state.db.record_service_drift(&d).await?);
+        }
+    }
+
+    // Services not deployed to a host anymore were not checked.
+    state
+        .db
+        .delete_service_drift_checked_before(started_at)
+        .await?;
+
+    let drifted = drift.iter().filter(|d| d.state == DRIFTED).count();
+    if drifted > 0 {
+        warn!("{drifted} services drifted from their deployments.");
+    }
+
+    Ok(drift)
+}
+
+/// Check the services recorded as drifted again, and correct those still drifted. Only called by
+/// the leader, so that no two zookeepers correct the same drift. Returns the pipelines opened.
+pub async fn correct_drifted(state: &AntZookeeperState) -> Result<Vec<String>, anyhow::Error> {
+    let recorded = state
+        .db
+        .list_service_drift()
+        .await?
+        .into_iter()
+        .filter(|d| d.state == DRIFTED)
+        .map(|d| (d.host_id, d.service_id))
+        .collect::<HashSet<_>>();
+    if recorded.is_empty() {
+        return Ok(vec![]);
+    }
+
+    // The recorded desired revision may have been deployed since, so check against the latest.
+    let mut drift = vec![];
+    for (host_id, service_id, revision_id) in deployed_services(state).await? {
+        if !recorded.contains(&(host_id.clone(), service_id.clone())) {
+            continue;
+        }
+        if let Some(d) = check(state, &host_id, &service_id, &revision_id).await? {
+            drift.push(state.db.record_service_drift(&d).await?);
+        }
+    }
+
+    correct(state, &drift).await
+}
+
+/// Every service with a finished deployment to a host still running it in services.json, as (host,
+/// service, revision of the latest deployment).
+async fn deployed_services(
+    state: &AntZookeeperState,
+) -> Result<Vec<(String, String, String)>, anyhow::Error> {
+    let mut services = vec![];
+    for (resource_key, revision_id) in state
+        .engine
+        .latest_finished_revisions(&json!({"type": "host_deployment"}))
+        .await?
+    {
+        let DeploymentResource::HostService {
+            host_id,
+            service_id,
+        } = DeploymentResource::from_str(&resource_key)?
+        else {
+            continue;
+        };
+
+        let still_deployed = state
+            .services
+            .list_hosts_with_service(service_id.as_str())
+            .iter()
+            .any(|(host, _)| *host == host_id.as_str());
+        if still_deployed {
+            services.push((host_id.to_string(), service_id.to_string(), revision_id));
+        }
+    }
+
+    Ok(services)
+}
+
+/// Compare the version of the revision with the one the host runs. None if the revision has no
+/// artifact for the host anymore.
+async fn check(
+    state: &AntZookeeperState,
+    host_id: &str,
+    service_id: &str,
+    revision_id: &str,
+) -> Result<Option<ServiceDrift>, anyhow::Error> {
+    let Some((_, arch)) = state.db.get_host(host_id).await? else {
+        return Ok(None);
+    };
+    let Some((_, desired_version, _)) = state
+        .db
+        .get_artifact_by_revision(revision_id, service_id, Some(&arch))
+        .await?
+    else {
+        warn!("No artifact for {service_id} in {revision_id} on {host_id}, not checking drift.");
+        return Ok(None);
+    };
+
+    let ant_host_agent =
+        state
+            .ant_host_agent_factory
+            .lock()
+            .await
+            .new_client(AntHostAgentClientConfig {
+                endpoint: host_id.to_string(),
+                port: 3232,
+            });
+
+    let (actual_version, drift_state, error) = match ant_host_agent.get_service(service_id).await {
+        Ok(Some(service)) if service.version == desired_version => {
+            (Some(service.version), IN_SYNC, None)
+        }
+        Ok(Some(service)) => (Some(service.version), DRIFTED, None),
+        Ok(None) => (None, DRIFTED, None),
+        Err(e) => (None, UNREACHABLE, Some(format!("{e:#}"))),
+    };
+
+    Ok(Some(ServiceDrift {
+        host_id: host_id.to_string(),
+        service_id: service_id.to_string(),
+        desired_revision_id: revision_id.to_string(),
+        desired_version,
+        actual_version,
+        state: drift_state.to_string(),
+        error,
+        drifted_since: None,
+        corrective_pipeline_id: None,
+        checked_at: Utc::now(),
+    }))
+}
+
+/// Open a pipeline redeploying the desired revision of each drifted service to its drifted hosts,
+/// as a revision of its own. Projects with an active pipeline or paused are left alone, a failed
+/// correction is retried on the next check. Returns the pipelines opened.
+async fn correct(
+    state: &AntZookeeperState,
+    drift: &[ServiceDrift],
+) -> Result<Vec<String>, anyhow::Error> {
+    let mut drifted_hosts = BTreeMap::<(&str, &str), HashSet<String>>::new();
+    for d in drift.iter().filter(|d| d.state == DRIFTED) {
+        drifted_hosts
+            .entry((&d.service_id, &d.desired_revision_id))
+            .or_default()
+            .insert(d.host_id.clone());
+    }
+
+    let unhealthy = unhealthy_hosts(state).await?;
+    let mut pipelines = vec![];
+    for ((project, desired_revision), hosts) in drifted_hosts {
+        if state.engine.is_paused(project).await?
+            || !state.engine.active_pipelines(project).await?.is_empty()
+        {
+            info!("Not correcting the drift of {project}, it is paused or deploying already.");
+            continue;
+        }
+
+        let manifest = read_artifact_manifest(
+            &revision_artifact_path(&state.db, &state.root_dir, desired_revision, project).await?,
+        )?;
+
+        // Only the service itself drifts, leave its database, routes and monitoring be.
+        let config = match ProjectConfig::from_manifest(project, &manifest, &state.services)
+            .only_hosts(&hosts)
+            .excluding_hosts(&unhealthy)
+        {
+            Ok(config) => ProjectConfig {
+                has_database: false,
+                has_routes: false,
+                has_alerts: false,
+                has_log_rules: false,
+                ..config
+            },
+            Err(e) => {
+                info!("Not correcting the drift of {project} until its hosts are healthy: {e:?}");
+                continue;
+            }
+        };
+        if config.beta_hosts.is_empty() && config.prod_hosts.is_empty() {
+            continue;
+        }
+
+        let revision = state.db.create_revision(project).await?;
+        state
+            .db
+            .copy_revision_artifacts(desired_revision, &revision)
+            .await?;
+        state.db.activate_revision(&revision).await?;
+
+        let pipeline_id = build_dag(&state.engine, &revision, &config).await?;
+        info!("Correcting the drift of {project} ({desired_revision} as {revision}) in {pipeline_id}.");
+
+        for host_id in &hosts {
+            state
+                .db
+                .set_service_drift_correction(host_id, project, &pipeline_id)
+                .await?;
+        }
+        pipelines.push(pipeline_id);
+    }
+
+    Ok(pipelines)
+}
+
+/// Which services drifted, or could not be checked, rendered for Prometheus on `GET /metrics`.
+pub fn render_metrics(drift: &[ServiceDrift]) -> String {
+    let mut metrics = String::new();
+    for (name, help, drift_state) in [
+        (
+            "ant_zookeeper_service_drifted",
+            "Whether a host runs another version of a service than last deployed there",
+            DRIFTED,
+        ),
+        (
+            "ant_zookeeper_service_drift_unreachable",
+            "Whether the version a host runs of a service could not be checked",
+            UNREACHABLE,
+        ),
+    ] {
+        metrics.push_str(&format!("# HELP {name} {help}\n# TYPE {name} gauge\n"));
+        for d in drift {
+            metrics.push_str(&format!(
+                "{name}{{host_id=\"{}\",service_id=\"{}\"}} {}\n",
+                d.host_id,
+                d.service_id,
+                u8::from(d.state == drift_state)
+            ));
+        }
+    }
+
+    metrics
+}
@@ -18,6 +18,8 @@ pub enum AntZookeeperError {
     },
     ValidationError(String),
     ResourceNotFound(String),
+    Unauthorized,
+    Forbidden(String),
 }
 
 impl AntZookeeperError {
@@ -38,6 +40,14 @@ impl AntZookeeperError {
                 debug!("Error: {:?}", self);
                 (StatusCode::BAD_REQUEST, Json(self))
             }
+            Self::Unauthorized => {
+                debug!("Error: {:?}", self);
+                (StatusCode::UNAUTHORIZED, Json(self))
+            }
+            Self::Forbidden(_) => {
+                debug!("Error: {:?}", self);
+                (StatusCode::FORBIDDEN, Json(self))
+            }
         }
     }
 }
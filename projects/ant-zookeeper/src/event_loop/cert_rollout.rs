@@ -0,0 +1,51 @@
+//! Push the certificate renewed by `cert::renewer` to the gateway hosts of an environment, which
+//! check and reload it.
+
+use ant_host_agent::{
+    client::AntHostAgentClientConfig, routes::gateway::PutGatewayCertificateRequest,
+};
+use tracing::info;
+
+use crate::{
+    event_loop::route_update::GATEWAY_PROJECT, secrets::get_revision_secret,
+    state::AntZookeeperState,
+};
+
+pub const CERTIFICATE_SECRET: &str = "tls_cert";
+pub const PRIVATE_KEY_SECRET: &str = "tls_key";
+
+/// Push the certificate of the environment the rollout `revision` pinned to the gateway on `host`.
+pub async fn roll_out_certificate(
+    state: &AntZookeeperState,
+    revision: &str,
+    environment: &str,
+    host: &str,
+) -> Result<(), anyhow::Error> {
+    let certificate = String::from_utf8(
+        get_revision_secret(state, revision, CERTIFICATE_SECRET, environment, None).await?,
+    )?;
+    let private_key = String::from_utf8(
+        get_revision_secret(state, revision, PRIVATE_KEY_SECRET, environment, None).await?,
+    )?;
+
+    let ant_host_agent =
+        state
+            .ant_host_agent_factory
+            .lock()
+            .await
+            .new_client(AntHostAgentClientConfig {
+                endpoint: host.to_string(),
+                port: 3232,
+            });
+
+    ant_host_agent
+        .put_gateway_certificate(PutGatewayCertificateRequest {
+            certificate,
+            private_key,
+        })
+        .await
+        .map_err(|e| anyhow::anyhow!("{GATEWAY_PROJECT} on {host} rejected certificate: {e}"))?;
+
+    info!("Pushed {environment} certificate to {GATEWAY_PROJECT} on {host}.");
+    Ok(())
+}
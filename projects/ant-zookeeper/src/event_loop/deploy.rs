@@ -27,3 +27,28 @@ pub async fn deploy_artifact(
 
     Ok(())
 }
+
+/// Remove `project` from `host` altogether, for unwinding the first deployment of it to the host.
+pub async fn undeploy_artifact(
+    state: &AntZookeeperState,
+    project: &str,
+    host: &str,
+) -> Result<(), anyhow::Error> {
+    let ant_host_agent =
+        state
+            .ant_host_agent_factory
+            .lock()
+            .await
+            .new_client(AntHostAgentClientConfig {
+                endpoint: host.to_string(),
+                port: 3232,
+            });
+
+    ant_host_agent
+        .uninstall_service(ant_host_agent::routes::service::UninstallServiceRequest {
+            service_id: project.to_string(),
+        })
+        .await?;
+
+    Ok(())
+}
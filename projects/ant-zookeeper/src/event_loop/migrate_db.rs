@@ -1,14 +1,809 @@
-use tracing::info;
+//! Apply the migrations of a database project to its database in an environment.
+//!
+//! The build packages the project's migration directory into the artifact as `.db-migrations/`.
+//! Migrations are applied in filename order, with the same conventions as `scripts/migrate.sh`:
+//! `.sql` files are executed as-is, so they bring their own transaction, and `.sh` files are run
+//! with the database credentials. Each applied migration is recorded, keyed by filename, and its
+//! checksum pinned: editing a migration after it was applied fails the pipeline.
+//!
+//! Databases migrated by hand before the pipeline managed them are adopted: a migration is recorded
+//! without being applied if the `migration_label`s it inserts are in the database's own `migration`
+//! table, or if it inserts none and a later migration was adopted. Migrations that can't be told
+//! apart fail the pipeline instead of being guessed, until they are recorded with
+//! `POST /deployment/migration`.
+//!
+//! Migrations are forward-only, unless the revision ships an explicit down-migration with the same
+//! filename under `.db-migrations/down/`. Unwinding a revision that applied migrations without
+//! down-migrations is refused.
 
-use crate::state::AntZookeeperState;
+use std::{
+    collections::{HashMap, HashSet},
+    fs::File,
+    path::{Path, PathBuf},
+};
 
+use ant_library::db::{database_connection, ConnectionPool, DatabaseConfig};
+use ant_zookeeper_db::DatabaseMigration;
+use anthill_manifest::{AnthillArchetype, AnthillManifest};
+use anyhow::Context;
+use flate2::read::GzDecoder;
+use tar::Archive;
+use tempfile::{tempdir_in, TempDir};
+use tracing::{info, warn};
+
+use crate::{
+    fs::{artifact_persist_dir, secret_file_name, write_secret_file},
+    secrets::get_revision_secret,
+    state::AntZookeeperState,
+};
+
+/// Where the build puts the migrations of a database project, within its artifact.
+const MIGRATIONS_DIR: &str = ".db-migrations";
+
+/// Where down-migrations live, within the migrations directory.
+const DOWN_MIGRATIONS_DIR: &str = "down";
+
+/// Passed to .sh migrations, which may (re)set the monitoring user's password.
+const MONITORING_PASSWORD_SECRET: &str = "ant_db_monitoring_password";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct MigrationFile {
+    /// The filename, e.g. "045_add_ant_tags.sql".
+    name: String,
+    path: PathBuf,
+    sha256: String,
+    /// The labels it inserts into the `migration` table of the database, see `migration_labels`.
+    labels: Vec<String>,
+}
+
+/// The `migration_label`s a migration inserts into the `migration` table of its database, e.g.
+/// "add-ant-tags" for `insert into migration (migration_label) values ('add-ant-tags');`. Shell
+/// migrations are read the same way, for the SQL they pass to psql.
+fn migration_labels(sql: &str) -> Vec<String> {
+    const INSERT: &str = "insert into migration";
+
+    let lower = sql.to_ascii_lowercase();
+    let mut labels = vec![];
+    let mut from = 0;
+    while let Some(i) = lower[from..].find(INSERT) {
+        from += i + INSERT.len();
+
+        // Not `insert into migration_other`, and only with the label among the columns.
+        let Some(columns) = sql[from..].trim_start().strip_prefix('(') else {
+            continue;
+        };
+        let Some((columns, rest)) = columns.split_once(')') else {
+            continue;
+        };
+        let Some(column) = columns
+            .split(',')
+            .position(|c| c.trim().eq_ignore_ascii_case("migration_label"))
+        else {
+            continue;
+        };
+        let rest = rest.trim_start();
+        if !rest
+            .get(..6)
+            .is_some_and(|v| v.eq_ignore_ascii_case("values"))
+        {
+            continue;
+        }
+
+        for row in value_rows(&rest[6..]) {
+            let label = row.get(column).and_then(|v| {
+                v.strip_prefix('\'')
+                    .and_then(|v| v.strip_suffix('\''))
+                    .map(|v| v.replace("''", "'"))
+            });
+            labels.extend(label);
+        }
+    }
+
+    labels
+}
+
+/// The values of each row of `(a, b), (c, d);`, up to the end of the statement.
+fn value_rows(sql: &str) -> Vec<Vec<String>> {
+    let mut rows = vec![];
+    let mut row = vec![];
+    let mut value = String::new();
+    let mut depth = 0;
+    let mut quoted = false;
+
+    for c in sql.chars() {
+        match c {
+            '\'' => quoted = !quoted,
+            _ if quoted => {}
+            ';' => break,
+            '(' => {
+                depth += 1;
+                if depth == 1 {
+                    continue;
+                }
+            }
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    row.push(value.trim().to_string());
+                    rows.push(std::mem::take(&mut row));
+                    value.clear();
+                    continue;
+                }
+            }
+            ',' if depth == 1 => {
+                row.push(value.trim().to_string());
+                value.clear();
+                continue;
+            }
+            _ => {}
+        }
+        if depth > 0 {
+            value.push(c);
+        }
+    }
+
+    rows
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Plan<'a> {
+    /// Applied before the pipeline managed the database, record it without applying it.
+    Adopt(&'a MigrationFile),
+    Apply(&'a MigrationFile),
+}
+
+/// The .sql and .sh files in `dir`, in the order they are applied.
+fn read_migration_files(dir: &Path) -> Result<Vec<MigrationFile>, anyhow::Error> {
+    let mut files = vec![];
+
+    for entry in std::fs::read_dir(dir).with_context(|| format!("read {}", dir.display()))? {
+        let entry = entry?;
+        if !entry.file_type()?.is_file() {
+            continue;
+        }
+
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().to_string();
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("sql") | Some("sh") => {}
+            _ => {
+                return Err(anyhow::Error::msg(format!(
+                    "migration {name} is neither .sql nor .sh"
+                )))
+            }
+        }
+
+        let content = std::fs::read(&path).with_context(|| format!("read {}", path.display()))?;
+        files.push(MigrationFile {
+            name,
+            path,
+            sha256: sha256::digest(&content),
+            labels: migration_labels(&String::from_utf8_lossy(&content)),
+        });
+    }
+
+    files.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(files)
+}
+
+/// Decide what to do with each migration file, given those already recorded for the database and
+/// the `migration_label`s the database itself has, None if it has no `migration` table yet.
+fn plan_migrations<'a>(
+    files: &'a [MigrationFile],
+    recorded: &[DatabaseMigration],
+    database_labels: Option<&HashSet<String>>,
+) -> Result<Vec<Plan<'a>>, anyhow::Error> {
+    let recorded: HashMap<&str, &DatabaseMigration> = recorded
+        .iter()
+        .map(|m| (m.migration_name.as_str(), m))
+        .collect();
+
+    // Files after the last one recorded were added since the pipeline managed the database.
+    let last_recorded = files
+        .iter()
+        .rposition(|f| recorded.contains_key(f.name.as_str()));
+
+    // Files before the last one applied by hand were applied by hand too, labels or not.
+    let last_adopted = database_labels.and_then(|database_labels| {
+        files.iter().rposition(|f| {
+            !f.labels.is_empty() && f.labels.iter().all(|l| database_labels.contains(l))
+        })
+    });
+
+    let mut plan = vec![];
+    for (i, file) in files.iter().enumerate() {
+        match recorded.get(file.name.as_str()) {
+            Some(m) if m.migration_sha256 != file.sha256 => {
+                return Err(anyhow::Error::msg(format!(
+                    "migration {} was changed after it was applied, add a new migration instead",
+                    file.name
+                )));
+            }
+            Some(_) => continue,
+            None => {}
+        }
+
+        let database_labels = match database_labels {
+            Some(labels) if !labels.is_empty() => labels,
+            // Nothing was applied to the database yet.
+            _ => {
+                plan.push(Plan::Apply(file));
+                continue;
+            }
+        };
+
+        let applied = file
+            .labels
+            .iter()
+            .filter(|l| database_labels.contains(*l))
+            .count();
+        match applied {
+            0 if file.labels.is_empty() && last_adopted.is_some_and(|last| i < last) => {
+                plan.push(Plan::Adopt(file))
+            }
+            0 if file.labels.is_empty() && last_recorded.is_some_and(|last| i > last) => {
+                plan.push(Plan::Apply(file))
+            }
+            0 if file.labels.is_empty() => {
+                return Err(anyhow::Error::msg(format!(
+                    "cannot tell whether migration {} was applied by hand, it inserts no \
+                     migration_label: record it with POST /deployment/migration if it was",
+                    file.name
+                )));
+            }
+            0 => plan.push(Plan::Apply(file)),
+            n if n == file.labels.len() => plan.push(Plan::Adopt(file)),
+            _ => {
+                return Err(anyhow::Error::msg(format!(
+                    "migration {} was only partly applied by hand, the database has {applied} of \
+                     its labels {:?}",
+                    file.name, file.labels
+                )));
+            }
+        }
+    }
+
+    Ok(plan)
+}
+
+/// Write the value of a secret the revision deploys with into `dir`. Returns the file.
+async fn write_revision_secret(
+    state: &AntZookeeperState,
+    revision: &str,
+    environment: &str,
+    dir: &Path,
+    name: String,
+) -> Result<PathBuf, anyhow::Error> {
+    let value = get_revision_secret(state, revision, &name, environment, None).await?;
+    let path = dir.join(secret_file_name(&name));
+    write_secret_file(&path, &value).await?;
+    Ok(path)
+}
+
+/// The database of a project on a host, and everything needed to migrate it.
+struct MigrationTarget {
+    host_id: String,
+    port: u16,
+    database_name_path: PathBuf,
+    username_path: PathBuf,
+    password_path: PathBuf,
+    monitoring_password_path: PathBuf,
+
+    /// The unpacked artifact of the revision.
+    unpacked: TempDir,
+}
+
+impl MigrationTarget {
+    async fn new(
+        state: &AntZookeeperState,
+        revision: &str,
+        service_id: &str,
+        environment: &str,
+        host_id: &str,
+    ) -> Result<Self, anyhow::Error> {
+        let (_, arch) = state
+            .db
+            .get_host(host_id)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("host not found: {host_id}"))?;
+
+        let (_, _, artifact_relative_path) = state
+            .db
+            .get_artifact_by_revision(revision, service_id, Some(&arch))
+            .await?
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "no artifact for revision={revision} service={service_id} arch={arch:?}"
+                )
+            })?;
+        let artifact_path = artifact_persist_dir(&state.root_dir).join(artifact_relative_path);
+
+        tokio::fs::create_dir_all(state.root_dir.join("tmp")).await?;
+        let unpacked = tempdir_in(state.root_dir.join("tmp"))?;
+        {
+            let artifact = File::open(&artifact_path)
+                .with_context(|| format!("open {}", artifact_path.display()))?;
+            Archive::new(GzDecoder::new(artifact)).unpack(unpacked.path())?;
+        }
+
+        let manifest = AnthillManifest::from_file(&unpacked.path().join("anthill.json"))
+            .context("failed to find anthill.json in unpacked contents")?;
+
+        let Some(AnthillArchetype::Postgres {
+            database_secret_name,
+            username_secret_name,
+            password_secret_name,
+            ..
+        }) = &manifest.archetype
+        else {
+            return Err(anyhow::Error::msg(format!(
+                "{service_id} is not a postgres project, it has no migrations"
+            )));
+        };
+
+        let port = manifest
+            .ports
+            .as_ref()
+            .and_then(|p| p.primary)
+            .ok_or_else(|| anyhow::anyhow!("{service_id} has no primary port"))?;
+
+        // Shell migrations take the credentials as files, written next to the unpacked artifact so
+        // they are deleted with it.
+        let secrets_dir = unpacked.path().join(".secrets");
+        tokio::fs::create_dir_all(&secrets_dir).await?;
+        let secret_path = |name: &str| {
+            write_revision_secret(state, revision, environment, &secrets_dir, name.to_string())
+        };
+
+        Ok(MigrationTarget {
+            host_id: host_id.to_string(),
+            port,
+            database_name_path: secret_path(database_secret_name).await?,
+            username_path: secret_path(username_secret_name).await?,
+            password_path: secret_path(password_secret_name).await?,
+            monitoring_password_path: secret_path(MONITORING_PASSWORD_SECRET).await?,
+            unpacked,
+        })
+    }
+
+    fn migrations_dir(&self) -> PathBuf {
+        self.unpacked.path().join(MIGRATIONS_DIR)
+    }
+
+    async fn password(&self) -> Result<String, anyhow::Error> {
+        let password = tokio::fs::read_to_string(&self.password_path)
+            .await
+            .with_context(|| format!("read {}", self.password_path.display()))?;
+        Ok(password.trim().to_string())
+    }
+
+    async fn connect(&self) -> Result<ConnectionPool, anyhow::Error> {
+        let read = |path: &PathBuf| {
+            std::fs::read_to_string(path)
+                .map(|s| s.trim().to_string())
+                .with_context(|| format!("read {}", path.display()))
+        };
+
+        database_connection(&DatabaseConfig {
+            port: self.port,
+            database_name: read(&self.database_name_path)?,
+            database_user: read(&self.username_path)?,
+            database_password: self.password().await?,
+            host: self.host_id.clone(),
+            migration_dirs: vec![],
+        })
+        .await
+    }
+
+    /// Execute a single migration file against the database.
+    async fn execute(
+        &self,
+        db: &ConnectionPool,
+        file: &MigrationFile,
+    ) -> Result<(), anyhow::Error> {
+        if file.name.ends_with(".sql") {
+            let sql = tokio::fs::read_to_string(&file.path).await?;
+            db.get()
+                .await?
+                .batch_execute(&sql)
+                .await
+                .with_context(|| format!("migration {} failed", file.name))?;
+            return Ok(());
+        }
+
+        let output = tokio::process::Command::new(&file.path)
+            .arg(&self.username_path)
+            .arg(&self.database_name_path)
+            .arg(&self.monitoring_password_path)
+            .arg(&self.host_id)
+            .arg(self.port.to_string())
+            .env("PGPASSWORD", self.password().await?)
+            .output()
+            .await
+            .with_context(|| format!("migration {} could not be run", file.name))?;
+
+        if !output.status.success() {
+            return Err(anyhow::Error::msg(format!(
+                "migration {} failed with {}: {}",
+                file.name,
+                output.status,
+                String::from_utf8_lossy(&output.stderr)
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+/// The hosts running the database of `service_id` in `environment`.
+fn database_hosts<'a>(
+    state: &'a AntZookeeperState,
+    service_id: &str,
+    environment: &str,
+) -> Result<Vec<&'a str>, anyhow::Error> {
+    let mut hosts: Vec<&str> = state
+        .services
+        .list_hosts_with_service(service_id)
+        .into_iter()
+        .filter(|(_, s)| s.env.to_string() == environment)
+        .map(|(h, _)| h)
+        .collect();
+    hosts.sort();
+
+    if hosts.is_empty() {
+        return Err(anyhow::Error::msg(format!(
+            "no host runs the {service_id} database in {environment}"
+        )));
+    }
+
+    Ok(hosts)
+}
+
+/// Apply the pending migrations of `revision` to the databases of `service_id` in `environment`.
 pub async fn migrate_db(
-    state: AntZookeeperState,
+    state: &AntZookeeperState,
+    revision: &str,
     service_id: &str,
-    host_id: &str,
     environment: &str,
 ) -> Result<(), anyhow::Error> {
-    info!("TODO: migrate the db");
+    for host_id in database_hosts(state, service_id, environment)? {
+        let target =
+            MigrationTarget::new(state, revision, service_id, environment, host_id).await?;
+        let files = read_migration_files(&target.migrations_dir())?;
+        let recorded = state
+            .db
+            .list_database_migrations(service_id, environment, host_id)
+            .await?;
+
+        let db = target.connect().await?;
+
+        // Databases migrated by hand before the pipeline managed them know their own progress.
+        let database_labels = match db
+            .get()
+            .await?
+            .query("select migration_label from migration", &[])
+            .await
+        {
+            Ok(rows) => Some(
+                rows.iter()
+                    .map(|row| row.get::<_, String>("migration_label"))
+                    .collect::<HashSet<_>>(),
+            ),
+            Err(e) => {
+                warn!("{service_id} on {host_id} has no migration table, applying all: {e}");
+                None
+            }
+        };
+
+        let plan = plan_migrations(&files, &recorded, database_labels.as_ref())?;
+        if plan.is_empty() {
+            info!("{service_id} {environment} on {host_id}: no pending migrations");
+            continue;
+        }
+
+        for step in plan {
+            match step {
+                Plan::Adopt(file) => {
+                    info!(
+                        "{service_id} on {host_id}: adopting already applied {}",
+                        file.name
+                    );
+                    state
+                        .db
+                        .record_database_migration(
+                            service_id,
+                            environment,
+                            host_id,
+                            &file.name,
+                            &file.sha256,
+                            None,
+                        )
+                        .await?;
+                }
+                Plan::Apply(file) => {
+                    info!("{service_id} on {host_id}: applying {}", file.name);
+                    target.execute(&db, file).await?;
+                    state
+                        .db
+                        .record_database_migration(
+                            service_id,
+                            environment,
+                            host_id,
+                            &file.name,
+                            &file.sha256,
+                            Some(revision),
+                        )
+                        .await?;
+                }
+            }
+        }
+
+        info!("{service_id} {environment} on {host_id}: migrated to revision {revision}");
+    }
 
     Ok(())
 }
+
+/// Revert the migrations `revision` applied, newest first, with its down-migrations. Refuses, without
+/// changing anything on that host, if any of them has no down-migration.
+pub async fn unmigrate_db(
+    state: &AntZookeeperState,
+    revision: &str,
+    service_id: &str,
+    environment: &str,
+) -> Result<(), anyhow::Error> {
+    for host_id in database_hosts(state, service_id, environment)? {
+        let applied: Vec<DatabaseMigration> = state
+            .db
+            .list_database_migrations(service_id, environment, host_id)
+            .await?
+            .into_iter()
+            .filter(|m| m.revision_id.as_deref() == Some(revision))
+            .rev()
+            .collect();
+
+        if applied.is_empty() {
+            info!("{service_id} {environment} on {host_id}: revision {revision} applied no migrations");
+            continue;
+        }
+
+        let target =
+            MigrationTarget::new(state, revision, service_id, environment, host_id).await?;
+        let down_dir = target.migrations_dir().join(DOWN_MIGRATIONS_DIR);
+        let down_files = if down_dir.exists() {
+            read_migration_files(&down_dir)?
+        } else {
+            vec![]
+        };
+
+        let mut down_migrations = vec![];
+        for m in &applied {
+            let down = down_files
+                .iter()
+                .find(|f| f.name == m.migration_name)
+                .ok_or_else(|| {
+                    anyhow::anyhow!(
+                        "refusing to unwind {service_id} on {host_id}: migration {} has no \
+                         down-migration in {MIGRATIONS_DIR}/{DOWN_MIGRATIONS_DIR}",
+                        m.migration_name
+                    )
+                })?;
+            down_migrations.push(down);
+        }
+
+        let db = target.connect().await?;
+        for down in down_migrations {
+            info!("{service_id} on {host_id}: reverting {}", down.name);
+            target.execute(&db, down).await?;
+            state
+                .db
+                .delete_database_migration(service_id, environment, host_id, &down.name)
+                .await?;
+        }
+
+        info!("{service_id} {environment} on {host_id}: reverted revision {revision}");
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(name: &str, sha256: &str, labels: &[&str]) -> MigrationFile {
+        MigrationFile {
+            name: name.to_string(),
+            path: PathBuf::from(name),
+            sha256: sha256.to_string(),
+            labels: labels.iter().map(|l| l.to_string()).collect(),
+        }
+    }
+
+    fn recorded(name: &str, sha256: &str) -> DatabaseMigration {
+        DatabaseMigration {
+            migration_name: name.to_string(),
+            migration_sha256: sha256.to_string(),
+            revision_id: None,
+            applied_at: chrono::Utc::now(),
+        }
+    }
+
+    fn labels(labels: &[&str]) -> HashSet<String> {
+        labels.iter().map(|l| l.to_string()).collect()
+    }
+
+    #[test]
+    fn labels_are_read_from_the_migration_inserts() {
+        assert_eq!(
+            migration_labels(
+                "BEGIN;\ncreate table ant_tag ();\n\
+                 insert into migration (migration_label) values ('add-ant-tags');\nCOMMIT;"
+            ),
+            vec!["add-ant-tags"]
+        );
+        assert_eq!(
+            migration_labels(
+                "INSERT INTO migration (migration_seq, migration_label, created_at)\n\
+                 values\n  (27, 'backfill-001', now()),\n  (28, 'it''s, (2)', now())\n;\n\
+                 insert into migration_other (migration_label) values ('other');"
+            ),
+            vec!["backfill-001", "it's, (2)"]
+        );
+        assert!(migration_labels("create table migration ();").is_empty());
+    }
+
+    #[test]
+    fn labels_are_read_from_shell_migrations() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("001-schema.sql"),
+            "insert into migration (migration_label) values ('bootstrap-schema');",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("002-reset-monitoring-user-password.sh"),
+            "#!/bin/bash\nMIGRATION=\"\nBEGIN;\n\
+             ALTER USER monitoring WITH PASSWORD '$MONITORING_PW';\n\
+             INSERT INTO migration (migration_label) VALUES ('reset-monitoring-user-pw');\n\
+             COMMIT;\n\"\npsql -c \"$MIGRATION\"\n",
+        )
+        .unwrap();
+
+        let files = read_migration_files(dir.path()).unwrap();
+
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[0].labels, vec!["bootstrap-schema"]);
+        assert_eq!(files[1].labels, vec!["reset-monitoring-user-pw"]);
+    }
+
+    #[test]
+    fn plan_skips_recorded_adopts_applied_and_applies_new() {
+        let files = vec![
+            file("001-schema.sql", "a", &["bootstrap-schema"]),
+            file("002-users.sql", "b", &["add-users"]),
+            file("003-tags.sql", "c", &["add-tags"]),
+        ];
+
+        let plan = plan_migrations(
+            &files,
+            &[recorded("001-schema.sql", "a")],
+            Some(&labels(&["bootstrap-schema", "add-users"])),
+        )
+        .unwrap();
+
+        assert_eq!(plan, vec![Plan::Adopt(&files[1]), Plan::Apply(&files[2])]);
+    }
+
+    #[test]
+    fn plan_adopts_by_label_across_a_gap_in_the_migration_table() {
+        // The .sh migration inserts no row, so 004 was the third row of the migration table.
+        let files = vec![
+            file("001-schema.sql", "a", &["bootstrap-schema"]),
+            file("002-users.sql", "b", &["add-users"]),
+            file("003-reset-password.sh", "c", &[]),
+            file("004-tags.sql", "d", &["add-tags"]),
+            file("005-likes.sql", "e", &["add-likes"]),
+        ];
+
+        let plan = plan_migrations(
+            &files,
+            &[
+                recorded("001-schema.sql", "a"),
+                recorded("002-users.sql", "b"),
+                recorded("003-reset-password.sh", "c"),
+            ],
+            Some(&labels(&["bootstrap-schema", "add-users", "add-tags"])),
+        )
+        .unwrap();
+
+        assert_eq!(plan, vec![Plan::Adopt(&files[3]), Plan::Apply(&files[4])]);
+    }
+
+    #[test]
+    fn plan_applies_everything_to_a_new_database() {
+        let files = vec![
+            file("001-schema.sql", "a", &["bootstrap-schema"]),
+            file("002-reset-password.sh", "b", &[]),
+        ];
+
+        for database_labels in [None, Some(&labels(&[]))] {
+            let plan = plan_migrations(&files, &[], database_labels).unwrap();
+            assert_eq!(plan, vec![Plan::Apply(&files[0]), Plan::Apply(&files[1])]);
+        }
+    }
+
+    #[test]
+    fn plan_applies_unlabeled_migrations_added_since_the_last_recorded() {
+        let files = vec![
+            file("001-schema.sql", "a", &["bootstrap-schema"]),
+            file("002-reset-password.sh", "b", &[]),
+        ];
+
+        let plan = plan_migrations(
+            &files,
+            &[recorded("001-schema.sql", "a")],
+            Some(&labels(&["bootstrap-schema"])),
+        )
+        .unwrap();
+
+        assert_eq!(plan, vec![Plan::Apply(&files[1])]);
+    }
+
+    #[test]
+    fn plan_adopts_unlabeled_migrations_before_an_adopted_one() {
+        let files = vec![
+            file("001-schema.sql", "a", &["bootstrap-schema"]),
+            file("002-grant.sh", "b", &[]),
+            file("003-tags.sql", "c", &["add-tags"]),
+            file("004-likes.sql", "d", &["add-likes"]),
+        ];
+
+        let plan = plan_migrations(
+            &files,
+            &[],
+            Some(&labels(&["bootstrap-schema", "add-tags"])),
+        )
+        .unwrap();
+
+        assert_eq!(
+            plan,
+            vec![
+                Plan::Adopt(&files[0]),
+                Plan::Adopt(&files[1]),
+                Plan::Adopt(&files[2]),
+                Plan::Apply(&files[3]),
+            ]
+        );
+    }
+
+    #[test]
+    fn plan_refuses_ambiguous_migrations() {
+        let files = vec![
+            file("001-schema.sql", "a", &["bootstrap-schema"]),
+            file("002-reset-password.sh", "b", &[]),
+            file("003-tags.sql", "c", &["add-tags"]),
+        ];
+
+        // Whether the .sh migration ran before the pipeline managed the database is unknown.
+        let err = plan_migrations(&files, &[], Some(&labels(&["bootstrap-schema"]))).unwrap_err();
+        assert!(err.to_string().contains("002-reset-password.sh"), "{err}");
+
+        let files = vec![file(
+            "001-schema.sql",
+            "a",
+            &["bootstrap-schema", "add-users"],
+        )];
+        let err = plan_migrations(&files, &[], Some(&labels(&["bootstrap-schema"]))).unwrap_err();
+        assert!(err.to_string().contains("partly applied"), "{err}");
+    }
+
+    #[test]
+    fn plan_refuses_changed_migrations() {
+        let files = vec![file("001-schema.sql", "changed", &["bootstrap-schema"])];
+
+        let err = plan_migrations(&files, &[recorded("001-schema.sql", "a")], None).unwrap_err();
+
+        assert!(err.to_string().contains("changed after it was applied"));
+    }
+}
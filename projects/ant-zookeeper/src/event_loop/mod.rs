@@ -1,17 +1,25 @@
 use std::collections::{HashSet, VecDeque};
 
 use ant_zookeeper_db::{AntZooStorageClient, Revision};
-use tracing::info;
+use anyhow::Context;
+use futures::future::join_all;
+use tracing::{error, info, info_span, Instrument};
 
+use crate::event_loop::perform::JobCompletion;
 use crate::event_loop::transition::{
     after, is_deployment_complete, is_doable, transition, DeploymentEvent, Event, Node,
 };
+use crate::state::AntZookeeperState;
 
+pub(crate) mod cert_rollout;
 pub(crate) mod deploy;
 pub(crate) mod migrate_db;
+pub(crate) mod monitoring_rules;
 pub mod perform;
 pub(crate) mod replicate;
+pub(crate) mod route_update;
 pub mod transition;
+pub(crate) mod verify;
 
 pub async fn drive_revisions(
     db: &AntZooStorageClient,
@@ -26,6 +34,114 @@ pub async fn drive_revisions(
     Ok(())
 }
 
+/// Schedule any deployment jobs available in the legacy pipelines, then perform all unfinished
+/// jobs. Returns how many jobs were worked on.
+pub async fn drive_deployment_jobs(state: &AntZookeeperState) -> Result<usize, anyhow::Error> {
+    drive_revisions(&state.db, 1)
+        .await
+        .with_context(|| "Pipeline orchestration failure")?;
+
+    let unfinished_jobs = state.db.list_unfinished_deployment_jobs().await?;
+    let job_count = unfinished_jobs.len();
+
+    if !unfinished_jobs.is_empty() {
+        info!("Processing {} unfinished jobs...", unfinished_jobs.len());
+    }
+
+    let handles = unfinished_jobs.into_iter().map(|job| {
+        let state = state.clone();
+        async move {
+            let event = DeploymentEvent(job.revision.clone(), job.event_document.clone().into());
+
+            let perform_fn = transition(&state.db, &event).await?.perform;
+
+            let is_success = match perform_fn {
+                None => {
+                    error!(
+                        "ANT-ERR-081: Deployment job rev={} event={} has no perform, but ignoring...",
+                        event.0,
+                        event.1.to_string()
+                    );
+
+                    // Just marking it complete, shouldn't have happened though
+                    Some(true)
+                }
+
+                Some(perform_fn) => {
+                    // Do the work.
+
+                    state.db.start_deployment_job(&job.job_id).await?;
+
+                    let work: Result<
+                        Result<JobCompletion<()>, anyhow::Error>,
+                        tokio::task::JoinError,
+                    > = tokio::spawn({
+                        let job_id = job.job_id.clone();
+                        let state = state.clone();
+                        let event2 = event.clone();
+                        let event3 = event.clone();
+                        let span = info_span!("job", job_id = job_id);
+                        async move {
+                            perform_fn(&state, event2).await.with_context(|| {
+                                format!(
+                                    "Failed to perform scheduled deployment job [{}] for event \
+                                     [{}]",
+                                    job_id, event3
+                                )
+                            })
+                        }
+                        .instrument(span)
+                    })
+                    .await;
+
+                    let is_success: Option<bool> = match &work {
+                        Ok(Ok(JobCompletion::Pending)) => None,
+                        Ok(Ok(JobCompletion::Finished(_))) => {
+                            info!("Completed [{}] for {event}", job.job_id);
+                            Some(true)
+                        }
+                        Ok(Err(e)) => {
+                            error!("ANT-ERR-082: Handler Error: {:?}", e);
+                            Some(false)
+                        }
+                        Err(e) => {
+                            error!("ANT-ERR-083: Orchestration Error: {:?}", e);
+                            Some(false)
+                        }
+                    };
+
+                    is_success
+                }
+            };
+
+            // If the job finished, set its status and finished_at timestamp.
+            if let Some(is_success) = is_success {
+                state
+                    .db
+                    .complete_deployment_job(
+                        &job.job_id,
+                        &job.revision,
+                        &job.event_document,
+                        is_success,
+                    )
+                    .await?;
+            } else {
+                // Clear the started_at field to set the job back to pending.
+                state.db.unstart_deployment_job(&job.job_id).await?;
+            }
+
+            return Ok(());
+        }
+    });
+
+    join_all(handles)
+        .await
+        .into_iter()
+        .collect::<Result<(), anyhow::Error>>()?;
+
+    Ok(job_count)
+}
+
 /// The set of actions currently "ready to go" for the given revision in the pipeline.
 ///
 /// Formally, they are the steps `a` such that `next(s) = a`, where `s` is a completed event for that revision.
@@ -0,0 +1,202 @@
+//! Deploy the alert rules and log rules a project ships in its artifact.
+//!
+//! The build packages the files named by the manifest's `monitoring` into the artifact as
+//! `.monitoring/alert-rules.yml` and `.monitoring/log-rules.yml`. Alert rules are pushed to
+//! ant-monitor through the ant-host-agent of its hosts, log rules to the "config" port of
+//! ant-lumberjack on each host of the project.
+//!
+//! The rules of every revision are recorded per environment, unwinding a revision deletes its rules
+//! and pushes the ones recorded before it, or removes the rules if there are none.
+
+use ant_host_agent::{client::AntHostAgentClientConfig, routes::monitoring::PutAlertRulesRequest};
+use tracing::{info, warn};
+
+use crate::{
+    fs::{read_artifact_file, revision_artifact_path},
+    state::AntZookeeperState,
+};
+
+const ALERT_RULES: &str = "alert";
+const LOG_RULES: &str = "log";
+
+const ALERT_RULES_FILE: &str = ".monitoring/alert-rules.yml";
+const LOG_RULES_FILE: &str = ".monitoring/log-rules.yml";
+
+const MONITOR_PROJECT: &str = "ant-monitor";
+
+/// The rules file `file` that `revision` of `project` ships.
+async fn revision_rules(
+    state: &AntZookeeperState,
+    revision: &str,
+    project: &str,
+    file: &str,
+) -> Result<String, anyhow::Error> {
+    let artifact_path =
+        revision_artifact_path(&state.db, &state.root_dir, revision, project).await?;
+
+    read_artifact_file(&artifact_path, file)?.ok_or_else(|| {
+        anyhow::Error::msg(format!(
+            "no {file} in {}, is it declared in .monitoring?",
+            artifact_path.display()
+        ))
+    })
+}
+
+/// Push the alert rules of a project to every ant-monitor host, or remove them with None.
+async fn push_alert_rules(
+    state: &AntZookeeperState,
+    service_id: &str,
+    environment: &str,
+    rules: Option<String>,
+) -> Result<(), anyhow::Error> {
+    let mut monitor_hosts: Vec<&str> = state
+        .services
+        .list_hosts_with_project(MONITOR_PROJECT)
+        .into_iter()
+        .map(|(h, _)| h)
+        .collect();
+    monitor_hosts.sort();
+
+    if monitor_hosts.is_empty() {
+        warn!("No {MONITOR_PROJECT} host, not pushing alert rules of {service_id}");
+        return Ok(());
+    }
+
+    for host in monitor_hosts {
+        let ant_host_agent =
+            state
+                .ant_host_agent_factory
+                .lock()
+                .await
+                .new_client(AntHostAgentClientConfig {
+                    endpoint: host.to_string(),
+                    port: 3232,
+                });
+
+        ant_host_agent
+            .put_alert_rules(PutAlertRulesRequest {
+                service_id: service_id.to_string(),
+                environment: environment.to_string(),
+                rules: rules.clone(),
+            })
+            .await
+            .map_err(|e| {
+                anyhow::anyhow!("{MONITOR_PROJECT} on {host} rejected alert rules: {e}")
+            })?;
+
+        info!("Pushed {environment} alert rules of {service_id} to {MONITOR_PROJECT} on {host}.");
+    }
+
+    Ok(())
+}
+
+/// Record and push the alert rules that `revision` ships for `environment`.
+pub async fn configure_alerts(
+    state: &AntZookeeperState,
+    revision: &str,
+    service_id: &str,
+    environment: &str,
+) -> Result<(), anyhow::Error> {
+    let rules = revision_rules(state, revision, service_id, ALERT_RULES_FILE).await?;
+    state
+        .db
+        .upsert_monitoring_rules(ALERT_RULES, environment, service_id, revision, &rules)
+        .await?;
+
+    push_alert_rules(state, service_id, environment, Some(rules)).await
+}
+
+/// Forget the alert rules recorded by `revision`, and push the ones from before it.
+pub async fn restore_alerts(
+    state: &AntZookeeperState,
+    revision: &str,
+    service_id: &str,
+    environment: &str,
+) -> Result<(), anyhow::Error> {
+    state
+        .db
+        .delete_monitoring_rules(ALERT_RULES, environment, service_id, revision)
+        .await?;
+
+    let previous = state
+        .db
+        .get_latest_monitoring_rules(ALERT_RULES, environment, service_id)
+        .await?;
+
+    push_alert_rules(state, service_id, environment, previous).await
+}
+
+/// The environment `service_id` runs in on `host_id`.
+fn host_environment(
+    state: &AntZookeeperState,
+    service_id: &str,
+    host_id: &str,
+) -> Result<String, anyhow::Error> {
+    state
+        .services
+        .service_instance(service_id, host_id)
+        .map(|s| s.env.to_string())
+        .ok_or_else(|| anyhow::anyhow!("{service_id} does not run on {host_id}"))
+}
+
+/// Push the log rules of a service to ant-lumberjack on a host, or remove them with None.
+async fn push_log_rules(
+    state: &AntZookeeperState,
+    service_id: &str,
+    host_id: &str,
+    rules: Option<String>,
+) -> Result<(), anyhow::Error> {
+    let port = state.lumberjack_config_port;
+    let path = format!("/rules/{service_id}");
+
+    let (status, body) = match rules {
+        Some(rules) => state.prober.http_put(host_id, port, &path, rules).await?,
+        None => state.prober.http_delete(host_id, port, &path).await?,
+    };
+    if !status.is_success() {
+        return Err(anyhow::Error::msg(format!(
+            "ant-lumberjack on {host_id} rejected log rules of {service_id}: {status} {body}"
+        )));
+    }
+
+    info!("Pushed log rules of {service_id} to ant-lumberjack on {host_id}.");
+    Ok(())
+}
+
+/// Record and push the log rules that `revision` ships to ant-lumberjack on `host_id`.
+pub async fn configure_log_rules(
+    state: &AntZookeeperState,
+    revision: &str,
+    service_id: &str,
+    host_id: &str,
+) -> Result<(), anyhow::Error> {
+    let environment = host_environment(state, service_id, host_id)?;
+    let rules = revision_rules(state, revision, service_id, LOG_RULES_FILE).await?;
+    state
+        .db
+        .upsert_monitoring_rules(LOG_RULES, &environment, service_id, revision, &rules)
+        .await?;
+
+    push_log_rules(state, service_id, host_id, Some(rules)).await
+}
+
+/// Forget the log rules recorded by `revision`, and push the ones from before it to `host_id`.
+pub async fn restore_log_rules(
+    state: &AntZookeeperState,
+    revision: &str,
+    service_id: &str,
+    host_id: &str,
+) -> Result<(), anyhow::Error> {
+    let environment = host_environment(state, service_id, host_id)?;
+    state
+        .db
+        .delete_monitoring_rules(LOG_RULES, &environment, service_id, revision)
+        .await?;
+
+    let previous = state
+        .db
+        .get_latest_monitoring_rules(LOG_RULES, &environment, service_id)
+        .await?;
+
+    push_log_rules(state, service_id, host_id, previous).await
+}
@@ -4,10 +4,14 @@ use std::{
     path::PathBuf,
 };
 
-use ant_host_agent::client::AntHostAgentClientConfig;
-use ant_library::services::ServiceInstance;
+use ant_host_agent::{client::AntHostAgentClientConfig, tree::ServiceTree};
+use ant_library::{
+    provenance::{file_sha256, Provenance, SignedProvenance},
+    services::ServiceInstance,
+};
 use anthill_manifest::AnthillManifest;
 use anyhow::Context;
+use chrono::Utc;
 use flate2::{read::GzDecoder, write::GzEncoder, Compression};
 use tar::Archive;
 use tempfile::tempdir_in;
@@ -19,15 +23,18 @@ use tracing::info;
 
 use crate::{
     fs::{
-        artifact_persist_dir, envs_persist_dir, global_envs_file_name,
-        host_specific_secret_file_path, project_envs_file_name, secret_file_name, secret_file_path,
-        services_file_name, services_persist_dir,
+        artifact_persist_dir, envs_persist_dir, global_envs_file_name, project_envs_file_name,
+        secret_file_name, write_secret_file,
     },
+    secrets::get_revision_secret,
     state::AntZookeeperState,
 };
 
+/// Write the secrets the project needs into its `secrets` directory, at the versions the revision
+/// deploys with.
 async fn inject_secrets(
     state: &AntZookeeperState,
+    revision: &str,
     host: &str,
     dest: &PathBuf,
     environment: &str,
@@ -38,25 +45,35 @@ async fn inject_secrets(
     let project_secrets_dir = dest.join("secrets");
 
     // Only make secrets directory if they have any
-    if !manifest.secrets.is_empty() {
+    let is_host_agent = manifest.project == "ant-host-agent";
+    if !manifest.secrets.is_empty() || is_host_agent {
         create_dir_all(&project_secrets_dir).await?;
     }
 
+    // ant-host-agent only installs what we signed, see its ANT_HOST_AGENT_TRUSTED_KEYS.
+    if is_host_agent {
+        let dest_file = project_secrets_dir.join(secret_file_name("trusted_keys"));
+        info!("Writing trusted keys -> [{}]", dest_file.display());
+        write_secret_file(
+            &dest_file,
+            state.provenance_signer.public_key_pem()?.as_bytes(),
+        )
+        .await?;
+    }
+
     for secret in manifest.secrets {
         let dest_file = project_secrets_dir.join(secret_file_name(&secret.name()));
+        let secret_host = secret.is_host_specific().then_some(host);
 
-        let src_file = if secret.is_host_specific() {
-            host_specific_secret_file_path(&state.root_dir, environment, secret.name(), host)
-        } else {
-            secret_file_path(&state.root_dir, environment, secret.name())
-        };
+        let value =
+            get_revision_secret(state, revision, secret.name(), environment, secret_host).await?;
 
         info!(
-            "Copying secret: [{}] -> [{}]",
-            src_file.display(),
+            "Writing secret: [{}] ({secret_host:?}) -> [{}]",
+            secret.name(),
             dest_file.display()
         );
-        tokio::fs::copy(src_file, dest_file).await?;
+        write_secret_file(&dest_file, &value).await?;
     }
 
     Ok(())
@@ -212,11 +229,15 @@ pub async fn replicate_artifact_step(
 ) -> Result<(), anyhow::Error> {
     let (_, host_arch) = state.db.get_host(&host).await?.expect("host exists");
 
-    let (_, version, artifact_relative_path) = state
+    let (artifact_id, version, artifact_relative_path) = state
         .db
         .get_artifact_by_revision(&revision, project, Some(&host_arch))
         .await?
-        .unwrap();
+        .ok_or_else(|| {
+            anyhow::Error::msg(format!(
+                "no artifact of {project} for {host_arch:?} in {revision}, or it was garbage collected"
+            ))
+        })?;
 
     let service_instance = state
         .services
@@ -227,87 +248,75 @@ pub async fn replicate_artifact_step(
 
     let artifact_path = artifact_persist_dir(&state.root_dir).join(artifact_relative_path);
 
-    // Construct a new tarball using the build artifact in artifact_path and inject .env and other files.
-    let service_file_path = {
-        let mut dir = tempdir_in(&state.root_dir.join("tmp"))?;
-        dir.disable_cleanup(true);
-
-        // Unpack to a directory
-        let unpack_dir_path = {
-            let artifact = File::open(&artifact_path)?;
-            let gz = GzDecoder::new(&artifact);
-            let mut archive = Archive::new(gz);
-
-            let unpack_dir_path = dir.path().join("unpack");
-            info!(
-                "Unpacking tarball [{}] to [{}]",
-                artifact_path.display(),
-                unpack_dir_path.display()
-            );
-            archive.unpack(&unpack_dir_path)?;
-
-            let manifest = AnthillManifest::from_file(&unpack_dir_path.join("anthill.json"))
-                .context("failed to find anthill.json in unpacked contents")?;
-
-            inject_env_file(
-                state,
-                service_instance,
-                &manifest,
-                &unpack_dir_path,
-                &project,
-                &version,
-                &environment,
-            )
-            .await?;
-
-            inject_secrets(state, host, &unpack_dir_path, &environment).await?;
+    // Unpack the build artifact in artifact_path and inject .env and other files. It is only kept
+    // until it is sent, in a directory removed when it goes out of scope.
+    let dir = tempdir_in(&state.root_dir.join("tmp"))?;
+    let unpack_dir_path = {
+        let artifact = File::open(&artifact_path)?;
+        let gz = GzDecoder::new(&artifact);
+        let mut archive = Archive::new(gz);
 
-            inject_version_file(&unpack_dir_path, &version).await?;
-
-            render_docker_compose(
-                state,
-                service_instance,
-                &manifest,
-                &unpack_dir_path,
-                &project,
-                &version,
-                &environment,
-            )
-            .await?;
+        let unpack_dir_path = dir.path().join("unpack");
+        info!(
+            "Unpacking tarball [{}] to [{}]",
+            artifact_path.display(),
+            unpack_dir_path.display()
+        );
+        archive.unpack(&unpack_dir_path)?;
 
-            unpack_dir_path
-        };
+        let manifest = AnthillManifest::from_file(&unpack_dir_path.join("anthill.json"))
+            .context("failed to find anthill.json in unpacked contents")?;
 
-        // Create a new tarball with the new files injected
-        let pack_file_path = {
-            let pack_file_path = dir.path().join("pack.tar");
-            info!("Repacking tarball to [{}].", pack_file_path.display());
-
-            let pack_file = File::create_new(&pack_file_path)?;
-            let mut archive = tar::Builder::new(GzEncoder::new(pack_file, Compression::default()));
+        inject_env_file(
+            state,
+            service_instance,
+            &manifest,
+            &unpack_dir_path,
+            project,
+            &version,
+            environment,
+        )
+        .await?;
 
-            archive.append_dir_all(".", &unpack_dir_path)?;
-            archive.finish()?;
+        inject_secrets(state, revision, host, &unpack_dir_path, environment).await?;
 
-            pack_file_path
-        };
+        inject_version_file(&unpack_dir_path, &version).await?;
 
-        create_dir_all(services_persist_dir(&state.root_dir)).await?;
-        let service_file_path = services_persist_dir(&state.root_dir).join(services_file_name(
-            &project,
-            Some(&host_arch),
+        render_docker_compose(
+            state,
+            service_instance,
+            &manifest,
+            &unpack_dir_path,
+            project,
             &version,
-        ));
-        info!(
-            "Copying tarball to final location [{}]",
-            service_file_path.display()
-        );
-        std::fs::copy(pack_file_path, &service_file_path)?;
+            environment,
+        )
+        .await?;
 
-        service_file_path
+        unpack_dir_path
     };
 
-    let service_file = File::open(service_file_path)?;
+    // The service file is not what the builder signed, so sign for it ourselves, carrying over the
+    // commit of the builder's statement, verified when the artifact was registered. Artifacts
+    // registered before provenance have none.
+    let commit = match state.db.get_artifact_provenance(&artifact_id).await? {
+        Some(provenance) => {
+            let signed: SignedProvenance = serde_json::from_str(&provenance)?;
+            serde_json::from_str::<Provenance>(&signed.statement)?.commit
+        }
+        None => "unknown".to_string(),
+    };
+    let sign = |sha256: String| {
+        state.provenance_signer.sign(&Provenance {
+            project: project.to_string(),
+            version: version.to_string(),
+            commit: commit.clone(),
+            builder: "ant-zookeeper".to_string(),
+            architecture: host_arch.as_str().to_string(),
+            sha256,
+            built_at: Utc::now(),
+        })
+    };
 
     // Send the service file to ant-host-agent
     let ant_host_agent =
@@ -320,10 +329,56 @@ pub async fn replicate_artifact_step(
                 port: 3232,
             });
 
-    info!("Replicating service file to: {host}");
-    ant_host_agent
-        .register_service(&project, &version, service_file)
-        .await?;
+    // Only the files the host does not hold yet are sent, with the tree to assemble them into. The
+    // injected files differ with every deployment, so they are sent with the tree rather than kept.
+    let tree = ServiceTree::from_dir(&unpack_dir_path)?.inlining(&unpack_dir_path, |path| {
+        path == ".env" || path.starts_with("secrets/")
+    })?;
+    match ant_host_agent.held_blobs(tree.sha256s()).await? {
+        Some(held) => {
+            let tree_path = dir.path().join("tree.json");
+            let tree_json = serde_json::to_vec(&tree)?;
+            std::fs::write(&tree_path, &tree_json)?;
+
+            let blobs_path = dir.path().join("blobs.tar.gz");
+            let (count, size_bytes) = tree.pack_blobs(&unpack_dir_path, &held, &blobs_path)?;
+            info!(
+                "Replicating {count} changed files of {} ({} of {}) to: {host}",
+                tree.entries.len(),
+                humansize::format_size(size_bytes, humansize::DECIMAL),
+                humansize::format_size(tree.size_bytes(), humansize::DECIMAL)
+            );
+
+            let provenance = sign(file_sha256(&tree_path)?)?;
+            ant_host_agent
+                .register_service_delta(
+                    project,
+                    &version,
+                    tree_json,
+                    tokio::fs::File::open(&blobs_path).await?,
+                    &provenance,
+                )
+                .await?;
+        }
+        None => {
+            // Hosts that cannot assemble deployments get all of it, as a new tarball.
+            let pack_file_path = dir.path().join("pack.tar");
+            info!("Repacking tarball to [{}].", pack_file_path.display());
+            {
+                let pack_file = File::create_new(&pack_file_path)?;
+                let mut archive =
+                    tar::Builder::new(GzEncoder::new(pack_file, Compression::default()));
+                archive.append_dir_all(".", &unpack_dir_path)?;
+                archive.into_inner()?.finish()?;
+            }
+
+            let provenance = sign(file_sha256(&pack_file_path)?)?;
+            info!("Replicating service file to: {host}");
+            ant_host_agent
+                .register_service(project, &version, File::open(&pack_file_path)?, &provenance)
+                .await?;
+        }
+    }
 
     info!("Installing service file file to: {host}");
     ant_host_agent
@@ -0,0 +1,602 @@
+//! Render the routes of the ant-gateway nginx from the anthill.json `routing` of every project, and
+//! push them to the gateway hosts of an environment.
+//!
+//! Each revision that deploys a project with routes records that project's routes for the
+//! environment, then the config is re-rendered from the latest routes of every project, with one
+//! upstream per project made of the hosts running it in that environment. The rendered config is
+//! recorded against the revision, so unwinding a revision pushes the config from before it.
+//!
+//! The routed domains also get DNS records pointing at the gateway hosts, if they have none yet.
+//! Records are never deleted, unwinding a revision leaves them pointing at the gateways.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use ant_host_agent::{client::AntHostAgentClientConfig, routes::gateway::PutGatewayRoutesRequest};
+use anthill_manifest::AnthillManifest;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+use tracing::{info, warn};
+
+use crate::{
+    dns::{Dns, DnsRecordContent},
+    fs::{read_artifact_manifest, revision_artifact_path},
+    state::AntZookeeperState,
+};
+
+pub(crate) const GATEWAY_PROJECT: &str = "ant-gateway";
+
+/// The environment whose domains are served as-is, other environments get a subdomain.
+const PROD_ENVIRONMENT: &str = "prod";
+
+const PROXY_SETTINGS: &str = "        proxy_set_header X-Real-IP $remote_addr;
+        proxy_set_header X-Forwarded-For $proxy_add_x_forwarded_for;
+        proxy_set_header X-Forwarded-Proto $scheme;
+        proxy_set_header Host $http_host;
+        proxy_set_header X-NginX-Proxy true;
+
+        proxy_redirect off;
+";
+
+const SSL_SETTINGS: &str = "    ssl_certificate     /run/secrets/tls_cert;
+    ssl_certificate_key /run/secrets/tls_key;
+
+    ssl_session_cache shared:le_nginx_SSL:10m;
+    ssl_session_timeout 1440m;
+    ssl_session_tickets off;
+
+    ssl_protocols TLSv1.2 TLSv1.3;
+    ssl_prefer_server_ciphers off;
+
+    ssl_ciphers \"ECDHE-ECDSA-AES128-GCM-SHA256:ECDHE-RSA-AES128-GCM-SHA256:ECDHE-ECDSA-AES256-GCM-SHA384:ECDHE-RSA-AES256-GCM-SHA384:ECDHE-ECDSA-CHACHA20-POLY1305:ECDHE-RSA-CHACHA20-POLY1305:DHE-RSA-AES128-GCM-SHA256:DHE-RSA-AES256-GCM-SHA384\";
+";
+
+/// The routes of a single project, as recorded for an environment.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+struct ProjectRoutes {
+    /// The primary port of the project, that the gateway proxies to.
+    port: Option<u16>,
+    routes: Vec<ProjectRoute>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+struct ProjectRoute {
+    domain: String,
+    /// Empty if the project serves the whole domain.
+    paths: Vec<String>,
+}
+
+impl ProjectRoutes {
+    fn from_manifest(manifest: &AnthillManifest) -> Result<Self, anyhow::Error> {
+        let routes = manifest
+            .routing
+            .iter()
+            .map(|route| -> Result<ProjectRoute, anyhow::Error> {
+                let domain = route.domain.clone().ok_or_else(|| {
+                    anyhow::Error::msg(format!(
+                        "{} has a route without a .routing.domain",
+                        manifest.project
+                    ))
+                })?;
+
+                Ok(ProjectRoute {
+                    domain,
+                    paths: route.paths.clone(),
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(ProjectRoutes {
+            port: manifest.ports.as_ref().and_then(|p| p.primary),
+            routes,
+        })
+    }
+}
+
+/// The domain a project's route is served under in an environment, e.g. "beta.typesofants.org".
+fn environment_domain(domain: &str, environment: &str) -> String {
+    if environment == PROD_ENVIRONMENT {
+        domain.to_string()
+    } else {
+        format!("{environment}.{domain}")
+    }
+}
+
+fn validate_domain(domain: &str) -> Result<(), anyhow::Error> {
+    if domain.is_empty()
+        || !domain
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.')
+    {
+        return Err(anyhow::Error::msg(format!(
+            "invalid route domain: {domain:?}"
+        )));
+    }
+
+    Ok(())
+}
+
+fn validate_path(path: &str) -> Result<(), anyhow::Error> {
+    if !path.starts_with('/')
+        || path
+            .chars()
+            .any(|c| c.is_whitespace() || matches!(c, ';' | '{' | '}' | '"' | '\''))
+    {
+        return Err(anyhow::Error::msg(format!("invalid route path: {path:?}")));
+    }
+
+    Ok(())
+}
+
+/// Render the gateway config of an environment from the routes of every project. `projects` are
+/// (project, routes, hosts running the project in the environment).
+fn render_gateway_config(
+    environment: &str,
+    projects: &[(String, ProjectRoutes, Vec<String>)],
+) -> Result<String, anyhow::Error> {
+    let mut upstreams: BTreeMap<&str, (u16, &Vec<String>)> = BTreeMap::new();
+    // domain -> path -> project
+    let mut domains: BTreeMap<String, BTreeMap<&str, &str>> = BTreeMap::new();
+
+    for (project, routes, hosts) in projects {
+        if routes.routes.is_empty() {
+            continue;
+        }
+
+        let port = routes.port.ok_or_else(|| {
+            anyhow::Error::msg(format!("{project} has routes but no primary port"))
+        })?;
+
+        if hosts.is_empty() {
+            warn!("{project} has routes but no hosts in {environment}, skipping");
+            continue;
+        }
+
+        upstreams.insert(project, (port, hosts));
+
+        for route in &routes.routes {
+            validate_domain(&route.domain)?;
+            let paths = domains
+                .entry(environment_domain(&route.domain, environment))
+                .or_default();
+
+            let route_paths: Vec<&str> = if route.paths.is_empty() {
+                vec!["/"]
+            } else {
+                route.paths.iter().map(|p| p.as_str()).collect()
+            };
+
+            for path in route_paths {
+                validate_path(path)?;
+                if let Some(other) = paths.insert(path, project) {
+                    return Err(anyhow::Error::msg(format!(
+                        "{} {path} is routed to both {other} and {project}",
+                        route.domain
+                    )));
+                }
+            }
+        }
+    }
+
+    let mut config = format!("# Rendered by ant-zookeeper for {environment}, do not edit.\n");
+
+    for (project, (port, hosts)) in &upstreams {
+        config.push_str(&format!("\nupstream {project} {{\n"));
+        for host in hosts.iter() {
+            config.push_str(&format!("    server {host}:{port};\n"));
+        }
+        config.push_str("}\n");
+    }
+
+    for (domain, paths) in &domains {
+        config.push_str(&format!(
+            "\nserver {{\n    listen 80;\n    server_name {domain};\n    return 308 \
+             https://$host$request_uri;\n}}\n"
+        ));
+
+        config.push_str(&format!(
+            "\nserver {{\n    listen 443 ssl;\n    listen [::]:443 ssl;\n    http2 on;\n    \
+             server_name {domain};\n"
+        ));
+        for (path, project) in paths {
+            config.push_str(&format!(
+                "\n    location {path} {{\n{PROXY_SETTINGS}        proxy_pass http://{project};\n    }}\n"
+            ));
+        }
+        config.push_str(&format!("\n{SSL_SETTINGS}}}\n"));
+    }
+
+    Ok(config)
+}
+
+/// The hosts running `project` in `environment`, sorted for a stable config.
+pub(crate) fn environment_hosts(
+    state: &AntZookeeperState,
+    project: &str,
+    environment: &str,
+) -> Vec<String> {
+    let mut hosts: Vec<String> = state
+        .services
+        .list_hosts_with_project(project)
+        .into_iter()
+        .filter(|(_, s)| s.env.to_string() == environment)
+        .map(|(h, _)| h.to_string())
+        .collect();
+    hosts.sort();
+    hosts
+}
+
+/// Render the gateway config of an environment from the latest recorded routes of every project.
+async fn render_environment(
+    state: &AntZookeeperState,
+    environment: &str,
+) -> Result<String, anyhow::Error> {
+    let mut projects = vec![];
+    for (project, routes) in state.db.list_gateway_routes(environment).await? {
+        let routes: ProjectRoutes = serde_json::from_str(&routes)?;
+        let hosts = environment_hosts(state, &project, environment);
+        projects.push((project, routes, hosts));
+    }
+
+    render_gateway_config(environment, &projects)
+}
+
+/// Push the config to every gateway host of the environment, which validate and reload it.
+async fn push_gateway_config(
+    state: &AntZookeeperState,
+    environment: &str,
+    config: &str,
+) -> Result<(), anyhow::Error> {
+    let gateway_hosts = environment_hosts(state, GATEWAY_PROJECT, environment);
+    if gateway_hosts.is_empty() {
+        warn!("No {GATEWAY_PROJECT} host in {environment}, not pushing routes");
+        return Ok(());
+    }
+
+    for host in gateway_hosts {
+        let ant_host_agent =
+            state
+                .ant_host_agent_factory
+                .lock()
+                .await
+                .new_client(AntHostAgentClientConfig {
+                    endpoint: host.to_string(),
+                    port: 3232,
+                });
+
+        ant_host_agent
+            .put_gateway_routes(PutGatewayRoutesRequest {
+                config: config.to_string(),
+            })
+            .await
+            .map_err(|e| anyhow::anyhow!("{GATEWAY_PROJECT} on {host} rejected routes: {e}"))?;
+
+        info!("Pushed {environment} routes to {GATEWAY_PROJECT} on {host}.");
+    }
+
+    Ok(())
+}
+
+/// Point every domain without an address record at the gateway hosts: their own A and AAAA
+/// records under `hosts_domain`, or a CNAME to the first of them if they have none.
+async fn ensure_domain_records(
+    dns: &Mutex<dyn Dns>,
+    hosts_domain: &str,
+    gateway_hosts: &[String],
+    domains: &BTreeSet<String>,
+) -> Result<(), anyhow::Error> {
+    let Some(first_host) = gateway_hosts.first() else {
+        return Ok(());
+    };
+
+    let dns = dns.lock().await;
+    let mut targets = vec![];
+    for host in gateway_hosts {
+        for record in dns.list_records(&format!("{host}.{hosts_domain}")).await? {
+            if matches!(
+                record.content,
+                DnsRecordContent::A(_) | DnsRecordContent::AAAA(_)
+            ) && !targets.contains(&record.content)
+            {
+                targets.push(record.content);
+            }
+        }
+    }
+    if targets.is_empty() {
+        targets.push(DnsRecordContent::CNAME(format!(
+            "{first_host}.{hosts_domain}"
+        )));
+    }
+
+    for domain in domains {
+        let records = dns.list_records(domain).await?;
+        if records.iter().any(|record| record.content.is_address()) {
+            continue;
+        }
+
+        for target in &targets {
+            dns.put_record(domain, target.clone()).await?;
+        }
+        info!("Pointed {domain} at the gateways: {targets:?}");
+    }
+
+    Ok(())
+}
+
+/// Create the DNS records of the routed domains of the environment, without failing the deployment
+/// since the domains may be managed by hand.
+async fn ensure_route_records(
+    state: &AntZookeeperState,
+    environment: &str,
+    routes: &ProjectRoutes,
+) {
+    let domains = routes
+        .routes
+        .iter()
+        .map(|route| environment_domain(&route.domain, environment))
+        .collect();
+    let gateway_hosts = environment_hosts(state, GATEWAY_PROJECT, environment);
+
+    if let Err(e) =
+        ensure_domain_records(&state.dns, &state.hosts_domain, &gateway_hosts, &domains).await
+    {
+        warn!("ANT-ERR-194: Failed to create the DNS records of {domains:?}: {e:?}");
+    }
+}
+
+/// Record the routes of the project deployed by `revision`, then render and push the gateway config
+/// of the environment.
+pub async fn update_routes(
+    state: &AntZookeeperState,
+    revision: &str,
+    environment: &str,
+) -> Result<(), anyhow::Error> {
+    let project = state
+        .db
+        .get_revision_project(revision)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("revision not found: {revision}"))?;
+
+    let manifest = read_artifact_manifest(
+        &revision_artifact_path(&state.db, &state.root_dir, revision, &project).await?,
+    )?;
+    let routes = ProjectRoutes::from_manifest(&manifest)?;
+    state
+        .db
+        .upsert_gateway_routes(
+            environment,
+            &project,
+            revision,
+            &serde_json::to_string(&routes)?,
+        )
+        .await?;
+    ensure_route_records(state, environment, &routes).await;
+
+    let config = render_environment(state, environment).await?;
+    state
+        .db
+        .record_gateway_config(environment, revision, &config)
+        .await?;
+
+    push_gateway_config(state, environment, &config).await
+}
+
+/// Forget the routes and config recorded by `revision`, and push the config from before it.
+pub async fn restore_routes(
+    state: &AntZookeeperState,
+    revision: &str,
+    environment: &str,
+) -> Result<(), anyhow::Error> {
+    let project = state
+        .db
+        .get_revision_project(revision)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("revision not found: {revision}"))?;
+
+    state
+        .db
+        .delete_gateway_routes(environment, &project, revision)
+        .await?;
+    state
+        .db
+        .delete_gateway_config(environment, revision)
+        .await?;
+
+    let config = match state.db.get_latest_gateway_config(environment).await? {
+        Some(config) => config,
+        None => render_environment(state, environment).await?,
+    };
+
+    push_gateway_config(state, environment, &config).await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeSet, net::Ipv4Addr};
+
+    use tokio::sync::Mutex;
+
+    use super::{
+        ensure_domain_records, environment_domain, render_gateway_config, ProjectRoute,
+        ProjectRoutes,
+    };
+    use crate::dns::{Dns, DnsRecordContent, LocalDns};
+
+    fn routes(port: Option<u16>, domain: &str, paths: &[&str]) -> ProjectRoutes {
+        ProjectRoutes {
+            port,
+            routes: vec![ProjectRoute {
+                domain: domain.to_string(),
+                paths: paths.iter().map(|p| p.to_string()).collect(),
+            }],
+        }
+    }
+
+    #[test]
+    fn environment_domains() {
+        assert_eq!(
+            environment_domain("typesofants.org", "prod"),
+            "typesofants.org"
+        );
+        assert_eq!(
+            environment_domain("typesofants.org", "beta"),
+            "beta.typesofants.org"
+        );
+    }
+
+    #[test]
+    fn renders_upstreams_and_locations() {
+        let config = render_gateway_config(
+            "beta",
+            &[
+                (
+                    "ant-looking-pretty".to_string(),
+                    routes(Some(3240), "typesofants.org", &[]),
+                    vec!["antworker001".to_string()],
+                ),
+                (
+                    "ant-on-the-web".to_string(),
+                    routes(Some(3231), "typesofants.org", &["/api"]),
+                    vec!["antworker001".to_string(), "antworker002".to_string()],
+                ),
+            ],
+        )
+        .unwrap();
+
+        assert!(config.contains(
+            "upstream ant-on-the-web {\n    server antworker001:3231;\n    server \
+             antworker002:3231;\n}"
+        ));
+        assert!(config.contains("upstream ant-looking-pretty {\n    server antworker001:3240;\n}"));
+        assert!(config.contains("server_name beta.typesofants.org;"));
+        assert!(config.contains("location / {"));
+        assert!(config.contains("location /api {"));
+        assert!(config.contains("proxy_pass http://ant-on-the-web;"));
+        assert_eq!(config.matches("listen 443 ssl;").count(), 1);
+    }
+
+    #[test]
+    fn skips_projects_without_hosts() {
+        let config = render_gateway_config(
+            "prod",
+            &[(
+                "ant-on-the-web".to_string(),
+                routes(Some(3231), "typesofants.org", &["/api"]),
+                vec![],
+            )],
+        )
+        .unwrap();
+
+        assert!(!config.contains("upstream"));
+        assert!(!config.contains("server_name"));
+    }
+
+    #[test]
+    fn rejects_conflicting_routes() {
+        let err = render_gateway_config(
+            "prod",
+            &[
+                (
+                    "ant-on-the-web".to_string(),
+                    routes(Some(3231), "typesofants.org", &["/api"]),
+                    vec!["antworker001".to_string()],
+                ),
+                (
+                    "ant-fs".to_string(),
+                    routes(Some(3299), "typesofants.org", &["/api"]),
+                    vec!["antworker001".to_string()],
+                ),
+            ],
+        )
+        .unwrap_err();
+
+        assert!(err.to_string().contains("routed to both"));
+    }
+
+    #[test]
+    fn rejects_invalid_routes() {
+        let hosts = vec!["antworker001".to_string()];
+        for invalid in [
+            routes(Some(3231), "typesofants.org; evil", &[]),
+            routes(Some(3231), "typesofants.org", &["api"]),
+            routes(Some(3231), "typesofants.org", &["/api { }"]),
+            routes(None, "typesofants.org", &["/api"]),
+        ] {
+            assert!(render_gateway_config(
+                "prod",
+                &[("ant-on-the-web".to_string(), invalid, hosts.clone())]
+            )
+            .is_err());
+        }
+    }
+
+    #[tokio::test]
+    async fn points_new_domains_at_the_gateways() {
+        let dns = Mutex::new(LocalDns::in_memory());
+        let gateway = DnsRecordContent::A(Ipv4Addr::new(10, 0, 0, 2));
+        let manual = DnsRecordContent::CNAME("elsewhere.typesofants.org".to_string());
+        {
+            let dns = dns.lock().await;
+            dns.put_record("antworker002.hosts.typesofants.org", gateway.clone())
+                .await
+                .unwrap();
+            dns.put_record("typesofants.org", manual.clone())
+                .await
+                .unwrap();
+        }
+
+        let domains = BTreeSet::from([
+            "typesofants.org".to_string(),
+            "beta.typesofants.org".to_string(),
+        ]);
+        ensure_domain_records(
+            &dns,
+            "hosts.typesofants.org",
+            &["antworker002".to_string()],
+            &domains,
+        )
+        .await
+        .unwrap();
+
+        let dns = dns.lock().await;
+        let contents = |records: Vec<crate::dns::DnsRecord>| -> Vec<DnsRecordContent> {
+            records.into_iter().map(|r| r.content).collect()
+        };
+        assert_eq!(
+            contents(dns.list_records("beta.typesofants.org").await.unwrap()),
+            vec![gateway]
+        );
+        // Domains with records of their own are left alone.
+        assert_eq!(
+            contents(dns.list_records("typesofants.org").await.unwrap()),
+            vec![manual]
+        );
+    }
+
+    #[tokio::test]
+    async fn points_new_domains_at_gateways_without_addresses_by_name() {
+        let dns = Mutex::new(LocalDns::in_memory());
+
+        ensure_domain_records(
+            &dns,
+            "hosts.typesofants.org",
+            &["antworker001".to_string(), "antworker002".to_string()],
+            &BTreeSet::from(["beta.typesofants.org".to_string()]),
+        )
+        .await
+        .unwrap();
+
+        let records = dns
+            .lock()
+            .await
+            .list_records("beta.typesofants.org")
+            .await
+            .unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(
+            records[0].content,
+            DnsRecordContent::CNAME("antworker001.hosts.typesofants.org".to_string())
+        );
+    }
+}
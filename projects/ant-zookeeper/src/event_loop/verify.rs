@@ -0,0 +1,265 @@
+use std::time::Duration;
+
+use anthill_manifest::{AnthillManifest, ErrorRateCheck, VerificationOptions};
+use http::StatusCode;
+use tracing::{info, warn};
+
+use crate::{
+    fs::{artifact_persist_dir, read_artifact_manifest},
+    state::AntZookeeperState,
+};
+
+/// Time between two probes of a service being verified.
+const PROBE_INTERVAL: Duration = Duration::from_secs(5);
+
+const DEFAULT_STARTUP_TIMEOUT_SECONDS: u64 = 60;
+
+/// Sum a counter across all of its series in the Prometheus text format. With `only_errors`, only
+/// series with a 5xx `status` label are counted.
+fn sum_counter(exposition: &str, metric: &str, only_errors: bool) -> f64 {
+    exposition
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| {
+            let (series, rest) = match line.find('{') {
+                Some(i) => {
+                    let end = line[i..].find('}')? + i;
+                    (&line[..end + 1], &line[end + 1..])
+                }
+                None => line.split_once(' ')?,
+            };
+
+            let name = series.split('{').next()?.trim();
+            if name != metric {
+                return None;
+            }
+
+            if only_errors && !series.contains("status=\"5") {
+                return None;
+            }
+
+            rest.split_whitespace().next()?.parse::<f64>().ok()
+        })
+        .sum()
+}
+
+struct ProbeTarget<'a> {
+    state: &'a AntZookeeperState,
+    manifest: &'a AnthillManifest,
+    options: &'a VerificationOptions,
+    host_id: &'a str,
+    service_id: &'a str,
+    environment: &'a str,
+}
+
+impl ProbeTarget<'_> {
+    /// Ping and check Consul once, returning why the service is unhealthy, if it is.
+    async fn probe(&self) -> Result<Option<String>, anyhow::Error> {
+        let primary = self.manifest.ports.as_ref().and_then(|p| p.primary);
+        if let Some(port) = primary.filter(|_| self.options.ping.unwrap_or(true)) {
+            match self
+                .state
+                .prober
+                .http_get(self.host_id, port, "/ping")
+                .await
+            {
+                Ok((StatusCode::OK, _)) => {}
+                Ok((status, body)) => {
+                    return Ok(Some(format!("GET /ping returned {status}: {body}")));
+                }
+                Err(e) => return Ok(Some(format!("GET /ping failed: {e}"))),
+            }
+        }
+
+        let consul_healthy = self
+            .state
+            .prober
+            .consul_healthy(
+                &self.manifest.cluster,
+                self.environment,
+                self.service_id,
+                self.host_id,
+            )
+            .await?;
+        if consul_healthy == Some(false) {
+            return Ok(Some("not passing its Consul health checks".to_string()));
+        }
+
+        Ok(None)
+    }
+
+    /// The (total, errors) request counts of the error-rate metric.
+    async fn request_counts(&self, check: &ErrorRateCheck) -> Result<(f64, f64), anyhow::Error> {
+        let metrics = self
+            .manifest
+            .ports
+            .as_ref()
+            .and_then(|p| p.metrics.as_ref())
+            .ok_or_else(|| {
+                anyhow::Error::msg(format!(
+                    "{} has an error rate check but no metrics port",
+                    self.service_id
+                ))
+            })?;
+
+        let path = match metrics {
+            anthill_manifest::Metrics::PortAndPath { path, .. } => path.as_str(),
+            anthill_manifest::Metrics::Port(_) => "/metrics",
+        };
+
+        let (status, exposition) = self
+            .state
+            .prober
+            .http_get(self.host_id, metrics.port(), path)
+            .await?;
+        if status != StatusCode::OK {
+            return Err(anyhow::Error::msg(format!("GET {path} returned {status}")));
+        }
+
+        Ok((
+            sum_counter(&exposition, &check.metric, false),
+            sum_counter(&exposition, &check.metric, true),
+        ))
+    }
+}
+
+/// Verify a freshly deployed service on a host: wait for it to pass its probes, then keep probing
+/// for the bake time, then check that its error rate over the bake time is tolerable. Returns an
+/// error, failing the pipeline node, if any of these fail.
+pub async fn verify_deployment(
+    state: &AntZookeeperState,
+    revision: &str,
+    service_id: &str,
+    environment: &str,
+    host_id: &str,
+) -> Result<(), anyhow::Error> {
+    let (_, arch) = state
+        .db
+        .get_host(host_id)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("host not found: {host_id}"))?;
+
+    let (_, version, artifact_relative_path) = state
+        .db
+        .get_artifact_by_revision(revision, service_id, Some(&arch))
+        .await?
+        .ok_or_else(|| {
+            anyhow::anyhow!(
+                "no artifact for revision={revision} service={service_id} arch={arch:?}"
+            )
+        })?;
+
+    let manifest = read_artifact_manifest(
+        &artifact_persist_dir(&state.root_dir).join(artifact_relative_path),
+    )?;
+    let options = manifest
+        .deployment
+        .as_ref()
+        .and_then(|d| d.verification.as_ref());
+    let default_options = VerificationOptions::default();
+    let options = options.unwrap_or(&default_options);
+
+    let target = ProbeTarget {
+        state,
+        manifest: &manifest,
+        options,
+        host_id,
+        service_id,
+        environment,
+    };
+
+    info!("Verifying {service_id} {version} on {host_id}...");
+
+    let startup_timeout = Duration::from_secs(
+        options
+            .startup_timeout_seconds
+            .unwrap_or(DEFAULT_STARTUP_TIMEOUT_SECONDS),
+    );
+    let started = tokio::time::Instant::now();
+    loop {
+        match target.probe().await? {
+            None => break,
+            Some(reason) if started.elapsed() >= startup_timeout => {
+                return Err(anyhow::Error::msg(format!(
+                    "{service_id} on {host_id} did not become healthy within {}s: {reason}",
+                    startup_timeout.as_secs()
+                )));
+            }
+            Some(reason) => {
+                info!("{service_id} on {host_id} not healthy yet: {reason}");
+                tokio::time::sleep(PROBE_INTERVAL).await;
+            }
+        }
+    }
+
+    let baseline = match &options.error_rate {
+        Some(check) => Some(target.request_counts(check).await?),
+        None => None,
+    };
+
+    let bake_time = Duration::from_secs(options.bake_time_seconds.unwrap_or(0));
+    let baking = tokio::time::Instant::now();
+    while baking.elapsed() < bake_time {
+        tokio::time::sleep(PROBE_INTERVAL.min(bake_time - baking.elapsed())).await;
+
+        if let Some(reason) = target.probe().await? {
+            return Err(anyhow::Error::msg(format!(
+                "{service_id} on {host_id} became unhealthy during bake time: {reason}"
+            )));
+        }
+    }
+
+    if let (Some(check), Some((total_before, errors_before))) = (&options.error_rate, baseline) {
+        let (total_after, errors_after) = target.request_counts(check).await?;
+        let total = total_after - total_before;
+        let errors = errors_after - errors_before;
+
+        if total > 0.0 {
+            let error_rate = errors / total;
+            info!("{service_id} on {host_id} error rate: {errors}/{total} = {error_rate:.4}");
+            if error_rate > check.max_error_rate {
+                return Err(anyhow::Error::msg(format!(
+                    "{service_id} on {host_id} error rate {error_rate:.4} exceeds {}",
+                    check.max_error_rate
+                )));
+            }
+        } else {
+            warn!("{service_id} on {host_id} served no requests during bake time");
+        }
+    }
+
+    info!("Verified {service_id} {version} on {host_id}.");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::sum_counter;
+
+    const EXPOSITION: &str = r#"# HELP ant_fs_http_requests_total Total requests
+# TYPE ant_fs_http_requests_total counter
+ant_fs_http_requests_total{method="GET",status="200",endpoint="/ping"} 90
+ant_fs_http_requests_total{method="GET",status="503",endpoint="/ping"} 4
+ant_fs_http_requests_total{method="PUT",status="500",endpoint="/x"} 6
+ant_fs_http_requests_duration_seconds_count{method="GET",status="500"} 100
+other_total 7
+"#;
+
+    #[test]
+    fn sums_all_series_of_the_metric() {
+        assert_eq!(
+            sum_counter(EXPOSITION, "ant_fs_http_requests_total", false),
+            100.0
+        );
+        assert_eq!(sum_counter(EXPOSITION, "other_total", false), 7.0);
+    }
+
+    #[test]
+    fn sums_only_5xx_series_for_errors() {
+        assert_eq!(
+            sum_counter(EXPOSITION, "ant_fs_http_requests_total", true),
+            10.0
+        );
+        assert_eq!(sum_counter(EXPOSITION, "missing_total", true), 0.0);
+    }
+}
@@ -1,22 +1,19 @@
-use std::path::PathBuf;
+use std::{fs::File, io::Read, path::PathBuf};
 
-use ant_library::host_architecture::HostArchitecture;
+use ant_zookeeper_db::AntZooStorageClient;
+use anthill_manifest::AnthillManifest;
+use anyhow::Context;
+use flate2::read::GzDecoder;
+use tar::Archive;
+use tokio::io::AsyncWriteExt;
 
 pub(crate) fn artifact_persist_dir(root_dir: &PathBuf) -> PathBuf {
     root_dir.join("artifacts-db")
 }
 
-pub(crate) fn artifact_file_name(
-    project: &str,
-    arch: Option<&HostArchitecture>,
-    version: &str,
-) -> String {
-    format!(
-        "{}.{}.{}.bld",
-        project,
-        arch.map(|a| a.as_str()).unwrap_or("noarch").to_string(),
-        version
-    )
+/// Where an artifact is stored under artifacts-db, by the SHA256 of its content.
+pub(crate) fn artifact_store_path(fingerprint: &str) -> PathBuf {
+    PathBuf::from("sha256").join(format!("{fingerprint}.bld"))
 }
 
 pub(crate) fn envs_persist_dir(root_dir: &PathBuf) -> PathBuf {
@@ -31,43 +28,93 @@ pub(crate) fn global_envs_file_name(environment: &str) -> String {
     format!("{environment}.build.cfg")
 }
 
-pub(crate) fn host_specific_secret_file_path(
-    root_dir: &PathBuf,
-    environment: &str,
-    secret_name: &str,
-    host: &str,
-) -> PathBuf {
-    root_dir
-        .join("secrets-db")
-        .join(environment)
-        .join("hosts")
-        .join(host)
-        .join(secret_file_name(secret_name))
+pub(crate) fn secret_file_name(secret_name: &str) -> String {
+    format!("{secret_name}.secret")
 }
 
-pub(crate) fn secret_file_path(
-    root_dir: &PathBuf,
-    environment: &str,
-    secret_name: &str,
-) -> PathBuf {
-    root_dir
-        .join("secrets-db")
-        .join(environment)
-        .join(secret_file_name(secret_name))
-}
+/// Write the value of a secret to a file only its owner can read.
+pub(crate) async fn write_secret_file(path: &PathBuf, value: &[u8]) -> Result<(), anyhow::Error> {
+    let mut file = tokio::fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)
+        .await
+        .with_context(|| format!("open {}", path.display()))?;
+    file.write_all(value).await?;
+    file.flush().await?;
 
-pub(crate) fn secret_file_name(secret_name: &str) -> String {
-    format!("{secret_name}.secret")
+    Ok(())
 }
 
+/// Where replicated service files used to be kept, before they were only kept until sent.
 pub(crate) fn services_persist_dir(root_dir: &PathBuf) -> PathBuf {
     root_dir.join("services-db")
 }
 
-pub(crate) fn services_file_name(
+/// The artifact of the project a revision deploys, any of them if it was built for many
+/// architectures.
+pub(crate) async fn revision_artifact_path(
+    db: &AntZooStorageClient,
+    root_dir: &PathBuf,
+    revision: &str,
     project: &str,
-    arch: Option<&HostArchitecture>,
-    version: &str,
-) -> String {
-    format!("{}.deployable", artifact_file_name(project, arch, version))
+) -> Result<PathBuf, anyhow::Error> {
+    let (_, _, arch, _, _, _) = db
+        .list_artifacts_for_revision_id(revision)
+        .await?
+        .into_iter()
+        .find(|(_, artifact_project, _, _, _, _)| artifact_project == project)
+        .ok_or_else(|| anyhow::anyhow!("no artifact for revision={revision} project={project}"))?;
+
+    let (_, _, artifact_relative_path) = db
+        .get_artifact_by_revision(revision, project, Some(&arch))
+        .await?
+        .ok_or_else(|| {
+            anyhow::anyhow!("no artifact for revision={revision} project={project} arch={arch:?}")
+        })?;
+
+    Ok(artifact_persist_dir(root_dir).join(artifact_relative_path))
+}
+
+/// Read a single file out of a build artifact, without unpacking the rest. None if the artifact
+/// does not contain it.
+pub(crate) fn read_artifact_file(
+    artifact_path: &PathBuf,
+    file: &str,
+) -> Result<Option<String>, anyhow::Error> {
+    let artifact =
+        File::open(artifact_path).with_context(|| format!("open {}", artifact_path.display()))?;
+    let mut archive = Archive::new(GzDecoder::new(artifact));
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.to_path_buf();
+        if path.strip_prefix(".").unwrap_or(&path) != PathBuf::from(file) {
+            continue;
+        }
+
+        let mut content = String::new();
+        entry
+            .read_to_string(&mut content)
+            .with_context(|| format!("read {file} in {}", artifact_path.display()))?;
+        return Ok(Some(content));
+    }
+
+    Ok(None)
+}
+
+/// Read the anthill.json out of a build artifact, without unpacking the rest.
+pub(crate) fn read_artifact_manifest(
+    artifact_path: &PathBuf,
+) -> Result<AnthillManifest, anyhow::Error> {
+    let manifest_buf = read_artifact_file(artifact_path, "anthill.json")?.ok_or_else(|| {
+        anyhow::Error::msg(format!("no anthill.json in {}", artifact_path.display()))
+    })?;
+
+    let manifest: AnthillManifest = serde_json::from_str(&manifest_buf)
+        .with_context(|| format!("malformed anthill.json in {}", artifact_path.display()))?;
+    manifest.validate()?;
+    Ok(manifest)
 }
@@ -0,0 +1,26 @@
+//! The live inventory of the hosts, from the heartbeats their ant-host-agents send every
+//! `HEARTBEAT_INTERVAL`.
+//!
+//! A host that missed `MISSED_HEARTBEATS_UNHEALTHY` heartbeats in a row is unhealthy, and left out
+//! of the deployments created from then on. Hosts that never sent a heartbeat, like those with an
+//! ant-host-agent from before heartbeats, are deployed to as before.
+
+use std::collections::HashSet;
+
+use ant_host_agent::heartbeat::HEARTBEAT_INTERVAL;
+
+use crate::state::AntZookeeperState;
+
+/// How many heartbeats in a row a host may miss before it is unhealthy.
+pub const MISSED_HEARTBEATS_UNHEALTHY: u32 = 3;
+
+/// How old the last heartbeat of an unhealthy host is.
+pub fn unhealthy_after() -> chrono::Duration {
+    chrono::Duration::from_std(HEARTBEAT_INTERVAL * MISSED_HEARTBEATS_UNHEALTHY)
+        .expect("heartbeat interval out of range")
+}
+
+/// The hosts new deployments leave out.
+pub async fn unhealthy_hosts(state: &AntZookeeperState) -> Result<HashSet<String>, anyhow::Error> {
+    state.db.list_unhealthy_hosts(unhealthy_after()).await
+}
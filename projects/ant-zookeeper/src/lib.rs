@@ -11,14 +11,21 @@ use tracing::debug;
 
 use crate::state::AntZookeeperState;
 
+pub mod artifacts;
+pub mod auth;
+pub mod cert;
 pub mod client;
 pub mod dns;
+pub mod drift;
 pub mod err;
 pub mod event_loop;
 mod fs;
+pub mod inventory;
 pub mod pipeline;
 pub mod pipeline_engine;
+pub mod probe;
 pub mod routes;
+pub mod secrets;
 pub mod state;
 
 #[derive(Serialize, Deserialize)]
@@ -31,8 +38,8 @@ pub fn make_routes(s: AntZookeeperState) -> Result<Router, anyhow::Error> {
     debug!("Initializing API route...");
 
     let cors = CorsLayer::new()
-        .allow_methods([Method::GET, Method::POST])
-        .allow_headers([header::CONTENT_TYPE])
+        .allow_methods([Method::GET, Method::POST, Method::PUT, Method::DELETE])
+        .allow_headers([header::CONTENT_TYPE, header::AUTHORIZATION])
         .allow_origin(AllowOrigin::any());
 
     debug!("Initializing site routes...");
@@ -40,8 +47,13 @@ pub fn make_routes(s: AntZookeeperState) -> Result<Router, anyhow::Error> {
         .nest_routes("/pipeline", routes::pipeline::routes())
         .nest_routes("/deployment", routes::deployment::routes())
         .nest_routes("/service", routes::service::routes())
+        .nest_routes("/artifact", routes::artifact::routes())
         .nest_routes("/cert", routes::cert::routes())
+        .nest_routes("/secret", routes::secret::routes())
         .nest_routes("/projects", routes::projects::routes())
+        .nest_routes("/audit", routes::audit::routes())
+        .nest_routes("/host", routes::host::routes())
+        .merge_routes(routes::metrics::routes())
         .build()
         .with_state(s)
         .layer(
@@ -4,14 +4,28 @@ use ant_host_agent::client::RemoteAntHostAgentClientFactory;
 use ant_library::{
     db::{DatabaseConfig, TypesOfAntsDatabase},
     find_up::find_up,
+    provenance::{ProvenanceSigner, TrustedKeys},
+    sd::reader::ServiceDiscovery,
     services::Services,
 };
-use ant_zookeeper::{dns::CloudFlareDns, state::AntZookeeperState};
+use ant_zookeeper::{
+    cert::{
+        acme::AcmeCertificateAuthority, local::LocalCertificateAuthority, renewer::RenewerConfig,
+        CertificateAuthority,
+    },
+    dns::{CloudFlareDns, Dns, LocalDns},
+    drift::DriftConfig,
+    pipeline::driver::{DriverConfig, DriverMetrics},
+    probe::RemoteProber,
+    secrets::SecretCipher,
+    state::AntZookeeperState,
+};
 use ant_zookeeper_db::AntZooStorageClient;
 use anyhow::Context;
-use rsa::rand_core::OsRng;
-use tokio::{signal, sync::Mutex};
-use tokio_cron_scheduler::{Job, JobScheduler};
+use tokio::{
+    signal,
+    sync::{broadcast, watch, Mutex},
+};
 use tracing::debug;
 
 #[tokio::main]
@@ -28,7 +42,7 @@ async fn main() -> Result<(), anyhow::Error> {
 
     let services = Services::from_path(&find_up("services.json"))?;
 
-    let db = AntZooStorageClient::connect(&DatabaseConfig {
+    let db_config = DatabaseConfig {
         port: std::env::var("ANT_ZOOKEEPER_DB_PORT")
             .context("ANT_ZOOKEEPER_DB_PORT")?
             .parse()?,
@@ -39,64 +53,135 @@ async fn main() -> Result<(), anyhow::Error> {
             .context("ANT_ZOOKEEPER_DB_HOST")?
             .parse()?,
         migration_dirs: vec![],
-    })
-    .await?;
+    };
+    let db = AntZooStorageClient::connect(&db_config).await?;
 
     let engine = ant_zookeeper::pipeline_engine::engine::PipelineEngine::new(db.pool()).await?;
 
+    // Cloudflare, or a zone of our own with ANT_ZOOKEEPER_DNS=local, for running without it.
+    let dns: Arc<Mutex<dyn Dns>> = match std::env::var("ANT_ZOOKEEPER_DNS").as_deref() {
+        Ok("local") => Arc::new(Mutex::new(LocalDns::from_file(
+            root_dir.join("dns-db").join("zone.json"),
+        )?)),
+        _ => Arc::new(Mutex::new(CloudFlareDns::new(
+            ant_library::secret::load_secret("cloudflare")?,
+            ant_library::secret::load_secret("cloudflare_zone_id")?,
+        ))),
+    };
+
+    // LetsEncrypt, an authority of our own with ANT_ZOOKEEPER_ACME_URL=local, or any other ACME
+    // directory, like a local Pebble.
+    let certificate_authority: Arc<dyn CertificateAuthority> =
+        match std::env::var("ANT_ZOOKEEPER_ACME_URL").ok().as_deref() {
+            Some("local") => Arc::new(LocalCertificateAuthority::new(90)?),
+            url => Arc::new(AcmeCertificateAuthority {
+                url: match url {
+                    None => acme_lib::DirectoryUrl::LetsEncrypt,
+                    Some(url) => {
+                        acme_lib::DirectoryUrl::Other(Box::leak(url.to_string().into_boxed_str()))
+                    }
+                },
+                contact_email: std::env::var("ANT_ZOOKEEPER_ACME_CONTACT_EMAIL")
+                    .context("ANT_ZOOKEEPER_ACME_CONTACT_EMAIL")?,
+                persist_dir: root_dir.join("certs-db"),
+            }),
+        };
+
     let state = AntZookeeperState {
         root_dir,
 
         services: Arc::new(services),
 
         db,
+        secret_cipher: Arc::new(SecretCipher::from_base64(
+            &ant_library::secret::load_secret("ant_zookeeper_secrets_key")?,
+        )?),
+        trusted_builders: Arc::new(TrustedKeys::from_pem(&ant_library::secret::load_secret(
+            "ant_zookeeper_trusted_builder_keys",
+        )?)?),
+        provenance_signer: Arc::new(ProvenanceSigner::from_pem(
+            &ant_library::secret::load_secret("ant_zookeeper_signing_key")?,
+        )?),
         engine: Arc::new(engine),
+        driver_metrics: Arc::new(DriverMetrics::default()),
+        transitions: broadcast::channel(16).0,
 
-        rng: OsRng,
-
-        dns: Arc::new(Mutex::new(CloudFlareDns::new(
-            ant_library::secret::load_secret("cloudflare")?,
-            ant_library::secret::load_secret("cloudflare_zone_id")?,
-        ))),
-        acme_url: acme_lib::DirectoryUrl::LetsEncrypt,
-        acme_contact_email: std::env::var("ANT_ZOOKEEPER_ACME_CONTACT_EMAIL")
-            .context("ANT_ZOOKEEPER_ACME_CONTACT_EMAIL")?,
+        dns,
+        certificate_authority,
+        hosts_domain: std::env::var("ANT_ZOOKEEPER_HOSTS_DOMAIN")
+            .unwrap_or_else(|_| "hosts.typesofants.org".to_string()),
 
         ant_host_agent_factory: Arc::new(Mutex::new(RemoteAntHostAgentClientFactory)),
+
+        prober: Arc::new(RemoteProber::new(
+            std::env::var("TYPESOFANTS_ENV").context("TYPESOFANTS_ENV")?,
+            Arc::new(ServiceDiscovery::new(
+                std::env::var("ANT_MATCHMAKER_HTTP_PORT")
+                    .context("ANT_MATCHMAKER_HTTP_PORT")?
+                    .parse()?,
+            )),
+            match std::env::var("ANT_MATCHMAKER_INFRA_HTTP_PORT") {
+                Ok(port) => Some(Arc::new(ServiceDiscovery::new(port.parse()?))),
+                Err(_) => None,
+            },
+        )),
+        lumberjack_config_port: std::env::var("ANT_LUMBERJACK_CONFIG_PORT")
+            .context("ANT_LUMBERJACK_CONFIG_PORT")?
+            .parse()?,
     };
 
-    let app = ant_zookeeper::make_routes(state)?;
+    // Secrets registered before they were encrypted are still plaintext, seal them first.
+    let sealed =
+        ant_zookeeper::secrets::seal_plaintext_secrets(&state.db, &state.secret_cipher).await?;
+    debug!("Sealed {sealed} plaintext secrets.");
+
+    // Secrets used to be plaintext files, bring over those not in the database yet.
+    let imported = ant_zookeeper::secrets::import_secret_files(&state).await?;
+    debug!("Imported {imported} secret files.");
+
+    let app = ant_zookeeper::make_routes(state.clone())?;
 
-    // Setup the iteration thread, to slowly progress the pipeline
     let port: u16 = dotenv::var("ANT_ZOOKEEPER_PORT")
         .context("ANT_ZOOKEEPER_PORT")?
         .parse()?;
 
-    let scheduler = JobScheduler::new().await.unwrap();
-    scheduler
-        .add(
-            Job::new_async("every 2 seconds", move |_, _| {
-                Box::pin(async move {
-                    reqwest::Client::new()
-                        .post(format!(
-                            "http://localhost:{}/deployment/iteration",
-                            port.clone()
-                        ))
-                        .send()
-                        .await
-                        .unwrap()
-                        .error_for_status()
-                        .unwrap();
-                })
-            })
-            .expect("job creation"),
-        )
-        .await
-        .expect("deployment iteration job");
-
-    scheduler.shutdown_on_ctrl_c();
-
-    scheduler.start().await.expect("start scheduler");
+    let (shutdown_tx, shutdown_rx) = watch::channel(false);
+
+    // Number pipeline transitions and wake up their streams as soon as the engine logs them.
+    tokio::spawn(ant_zookeeper::pipeline::transitions::listen(
+        state.engine.clone(),
+        db_config.clone(),
+        state.transitions.clone(),
+        shutdown_rx.clone(),
+    ));
+
+    // Renew certificates ahead of their expiry, rolling them out to the gateways in pipelines.
+    tokio::spawn(ant_zookeeper::cert::renewer::run(
+        state.clone(),
+        RenewerConfig::default(),
+        shutdown_rx.clone(),
+    ));
+
+    // Compare what every host runs with what was deployed there.
+    let drift_config = DriftConfig::default();
+    tokio::spawn(ant_zookeeper::drift::run(
+        state.clone(),
+        drift_config.clone(),
+        shutdown_rx.clone(),
+    ));
+
+    // Drive the pipelines forward in the background, draining their jobs once the server stops.
+    // The leader also redeploys drifted services, only when asked to.
+    let correct_drift = std::env::var("ANT_ZOOKEEPER_CORRECT_DRIFT").as_deref() == Ok("true");
+    let driver = tokio::spawn(ant_zookeeper::pipeline::driver::run(
+        state,
+        db_config,
+        DriverConfig {
+            drift_correction_interval: correct_drift.then_some(drift_config.interval),
+            ..DriverConfig::default()
+        },
+        shutdown_rx,
+    ));
 
     let addr = SocketAddr::from(([0, 0, 0, 0], port.clone()));
     debug!(
@@ -112,6 +197,9 @@ async fn main() -> Result<(), anyhow::Error> {
         .await
         .expect("server failed");
 
+    shutdown_tx.send(true)?;
+    driver.await?;
+
     Ok(())
 }
 
@@ -1,5 +1,12 @@
-use ant_library::services::ServiceEnv;
+use std::collections::HashSet;
 
+use ant_library::services::{ServiceEnv, Services};
+use anthill_manifest::{
+    AnthillArchetype, AnthillEnvironment, AnthillManifest, PipelineDefinition, PipelineStage,
+    PipelineSteps, WaveSize,
+};
+
+use crate::err::AntZookeeperError;
 use crate::pipeline::deployment_event::DeploymentEvent;
 use crate::pipeline::resource_key::{DeploymentResource, Identifier};
 use crate::pipeline_engine::engine::PipelineEngine;
@@ -11,10 +18,126 @@ pub struct ProjectConfig {
     pub has_routes: bool,
     pub has_alerts: bool,
     pub has_log_rules: bool,
+    /// The stages to deploy through, from the manifest of the project or `default_pipeline`.
+    pub pipeline: PipelineDefinition,
     pub beta_hosts: Vec<String>,
     pub prod_hosts: Vec<String>,
 }
 
+impl ProjectConfig {
+    /// The config of a project deploying `manifest` to the hosts running it in services.json.
+    /// Database projects get their migrations applied by the pipeline, projects with routes get the
+    /// gateway updated, and projects with monitoring get their alert and log rules deployed.
+    pub fn from_manifest(
+        project_id: &str,
+        manifest: &AnthillManifest,
+        services: &Services,
+    ) -> Self {
+        let hosts = services.list_hosts_with_service(project_id);
+        let hosts_in = |environment: fn(&ServiceEnv) -> bool| -> Vec<String> {
+            hosts
+                .iter()
+                .filter(|(_, s)| environment(&s.env))
+                .map(|(h, _)| h.to_string())
+                .collect()
+        };
+
+        let monitoring = manifest.monitoring.as_ref();
+        let deployment = manifest.deployment.as_ref();
+        ProjectConfig {
+            project_id: project_id.to_string(),
+            has_database: matches!(manifest.archetype, Some(AnthillArchetype::Postgres { .. })),
+            has_routes: !manifest.routing.is_empty(),
+            has_alerts: monitoring.is_some_and(|m| m.alert_rules.is_some()),
+            has_log_rules: monitoring.is_some_and(|m| m.log_rules.is_some()),
+            pipeline: match deployment.and_then(|d| d.pipeline.clone()) {
+                Some(pipeline) => pipeline,
+                None => default_pipeline(
+                    project_id,
+                    deployment
+                        .and_then(|d| d.requires_prod_approval)
+                        .unwrap_or(false),
+                ),
+            },
+            beta_hosts: hosts_in(|env| matches!(env, ServiceEnv::Beta)),
+            prod_hosts: hosts_in(|env| matches!(env, ServiceEnv::Prod)),
+        }
+    }
+
+    /// The config without `hosts`, like those missing their heartbeats, see `inventory`. Fails if
+    /// that leaves a stage without any of its hosts, the pipeline would skip the stage and the gate
+    /// after it.
+    pub fn excluding_hosts(mut self, hosts: &HashSet<String>) -> Result<Self, AntZookeeperError> {
+        let had_hosts = self
+            .pipeline
+            .stages
+            .iter()
+            .filter(|stage| !self.hosts(stage.environment).is_empty())
+            .map(|stage| stage.environment)
+            .collect::<Vec<_>>();
+
+        self.beta_hosts.retain(|h| !hosts.contains(h));
+        self.prod_hosts.retain(|h| !hosts.contains(h));
+
+        match had_hosts
+            .into_iter()
+            .find(|environment| self.hosts(*environment).is_empty())
+        {
+            Some(environment) => Err(AntZookeeperError::ValidationError(format!(
+                "Every {} host of {} is excluded: {hosts:?}",
+                environment.as_str(),
+                self.project_id
+            ))),
+            None => Ok(self),
+        }
+    }
+
+    /// The config with only `hosts`, like those drifted from their deployment, see `drift`.
+    pub fn only_hosts(mut self, hosts: &HashSet<String>) -> Self {
+        self.beta_hosts.retain(|h| hosts.contains(h));
+        self.prod_hosts.retain(|h| hosts.contains(h));
+        self
+    }
+
+    fn hosts(&self, environment: AnthillEnvironment) -> &[String] {
+        match environment {
+            AnthillEnvironment::Beta => &self.beta_hosts,
+            AnthillEnvironment::Prod => &self.prod_hosts,
+        }
+    }
+}
+
+/// The pipeline of projects that don't declare one: beta, then prod, each in waves of 1, 2 and
+/// then 4 hosts.
+pub fn default_pipeline(project_id: &str, requires_prod_approval: bool) -> PipelineDefinition {
+    // ant-host-agent has ZERO wave layout, and we also don't mark the nodes as "unwind_on_failure"
+    // since we have messy deploys.
+    let (waves, unwind_on_failure) = if project_id == "ant-host-agent" {
+        (vec![WaveSize::Percent(100)], false)
+    } else {
+        (
+            vec![WaveSize::Hosts(1), WaveSize::Hosts(2), WaveSize::Hosts(4)],
+            true,
+        )
+    };
+
+    let stage = |environment, requires_approval| PipelineStage {
+        environment,
+        waves: Some(waves.clone()),
+        requires_approval: Some(requires_approval),
+        bake_time_seconds: None,
+        unwind_on_failure: Some(unwind_on_failure),
+        steps: PipelineSteps::default(),
+    };
+
+    PipelineDefinition {
+        stages: vec![
+            stage(AnthillEnvironment::Beta, false),
+            stage(AnthillEnvironment::Prod, requires_prod_approval),
+        ],
+    }
+}
+
 fn id(s: &str) -> Identifier {
     Identifier::new(s).unwrap()
 }
@@ -38,36 +161,23 @@ fn no_unwind() -> NodeOptions {
     }
 }
 
-/// Returns a vector-of-vectors of the IDs passed in, e.g.
-/// [[1], [2, 3], [4, 5, 6]] for IDs 1 through 6.
-fn wave_layout(ids: &[String]) -> Vec<Vec<&String>> {
-    let sizes = [1, 2, 4];
+/// Returns a vector-of-vectors of the IDs passed in, in waves of the given sizes where the last
+/// size repeats, e.g. [[1], [2, 3], [4, 5, 6]] for IDs 1 through 6 and sizes 1, 2, 3.
+fn wave_layout<'a>(ids: &'a [String], sizes: &[WaveSize]) -> Vec<Vec<&'a String>> {
     let mut waves = vec![];
     let mut cursor = 0;
     let mut size_idx = 0;
 
-    loop {
-        let chunk_size = if size_idx < sizes.len() {
-            sizes[size_idx]
-        } else {
-            4
-        };
+    while cursor < ids.len() {
+        let chunk_size = sizes[size_idx.min(sizes.len() - 1)].hosts(ids.len());
 
-        let mut wave = vec![];
-        for _ in 0..chunk_size {
-            if cursor >= ids.len() {
-                if !wave.is_empty() {
-                    waves.push(wave);
-                }
-                return waves;
-            }
-            wave.push(&ids[cursor]);
-            cursor += 1;
-        }
-
-        waves.push(wave);
+        let end = (cursor + chunk_size).min(ids.len());
+        waves.push(ids[cursor..end].iter().collect());
+        cursor = end;
         size_idx += 1;
     }
+
+    waves
 }
 
 pub async fn build_dag(
@@ -79,55 +189,82 @@ pub async fn build_dag(
         .create_pipeline(&config.project_id, revision_id)
         .await?;
 
-    let mut beta_terminal_nodes = vec![];
+    // The environment and terminal nodes of the last stage that had hosts to deploy to.
+    let mut previous: Option<(AnthillEnvironment, Vec<String>)> = None;
 
-    if !config.beta_hosts.is_empty() {
-        beta_terminal_nodes = build_env_dag(
-            engine,
-            &pipeline_id,
-            config,
-            ServiceEnv::Beta,
-            &config.beta_hosts,
-            vec![],
-        )
-        .await?;
-    }
+    for stage in &config.pipeline.stages {
+        let hosts = config.hosts(stage.environment);
+        if hosts.is_empty() {
+            continue;
+        }
 
-    if !config.prod_hosts.is_empty() {
-        let prod_predecessors = if beta_terminal_nodes.is_empty() {
-            vec![]
-        } else {
-            let gate = engine
-                .add_node(
-                    &pipeline_id,
-                    node(
-                        DeploymentEvent::EnvironmentGate {
-                            from: ServiceEnv::Beta.to_string(),
-                            to: ServiceEnv::Prod.to_string(),
-                        },
-                        None,
-                        NodeOptions {
-                            is_unwind_boundary: true,
-                            unwind_on_failure: false,
-                        },
-                    ),
-                )
-                .await?;
-            for prev in &beta_terminal_nodes {
-                engine.add_edge(prev, &gate).await?;
+        let predecessors = match &previous {
+            None => vec![],
+            Some((from, terminal_nodes)) => {
+                let gate = engine
+                    .add_node(
+                        &pipeline_id,
+                        node(
+                            DeploymentEvent::EnvironmentGate {
+                                from: from.as_str().to_string(),
+                                to: stage.environment.as_str().to_string(),
+                            },
+                            None,
+                            NodeOptions {
+                                is_unwind_boundary: true,
+                                unwind_on_failure: false,
+                                requires_approval: stage.requires_approval.unwrap_or(false),
+                            },
+                        ),
+                    )
+                    .await?;
+                for prev in terminal_nodes {
+                    engine.add_edge(prev, &gate).await?;
+                }
+                vec![gate]
             }
-            vec![gate]
         };
 
-        build_env_dag(
-            engine,
-            &pipeline_id,
-            config,
-            ServiceEnv::Prod,
-            &config.prod_hosts,
-            prod_predecessors,
-        )
-        .await?;
+        let terminal_nodes =
+            build_env_dag(engine, &pipeline_id, config, stage, hosts, predecessors).await?;
+        previous = Some((stage.environment, terminal_nodes));
+    }
+
+    engine.seal(&pipeline_id).await?;
+
+    Ok(pipeline_id)
+}
+
+/// The pipeline rolling a renewed certificate out to the gateway `hosts` of an environment, one host
+/// at a time so a certificate the gateway rejects stops at the first host.
+pub async fn build_certificate_rollout_dag(
+    engine: &PipelineEngine,
+    project_id: &str,
+    revision_id: &str,
+    environment: &str,
+    hosts: &[String],
+) -> Result<String, anyhow::Error> {
+    let pipeline_id = engine.create_pipeline(project_id, revision_id).await?;
+
+    let mut previous: Option<String> = None;
+    for host in hosts {
+        let n = engine
+            .add_node(
+                &pipeline_id,
+                node(
+                    DeploymentEvent::CertificateRollout {
+                        host_id: host.clone(),
+                        environment: environment.to_string(),
+                    },
+                    Some(DeploymentResource::GatewayCertificate { host_id: id(host) }),
+                    no_unwind(),
+                ),
+            )
+            .await?;
+        if let Some(prev) = &previous {
+            engine.add_edge(prev, &n).await?;
+        }
+        previous = Some(n);
     }
 
     engine.seal(&pipeline_id).await?;
@@ -141,7 +278,7 @@ async fn host_triplet(
     pipeline_id: &str,
     host: &String,
     config: &ProjectConfig,
-    environment: &ServiceEnv,
+    environment: &str,
 
     unwind_on_failure: bool,
 ) -> Result<[String; 3], anyhow::Error> {
@@ -208,13 +345,14 @@ async fn build_env_dag(
     engine: &PipelineEngine,
     pipeline_id: &str,
     config: &ProjectConfig,
-    environment: ServiceEnv,
+    stage: &PipelineStage,
     hosts: &[String],
     previous_node_ids: Vec<String>,
 ) -> Result<Vec<String>, anyhow::Error> {
+    let environment = stage.environment.as_str();
     let mut chain_tip: Vec<String> = previous_node_ids;
 
-    if config.has_routes {
+    if config.has_routes && stage.steps.routes.unwrap_or(true) {
         let n = engine
             .add_node(
                 pipeline_id,
@@ -223,7 +361,7 @@ async fn build_env_dag(
                         environment: environment.to_string(),
                     },
                     Some(DeploymentResource::GatewayRouting {
-                        environment: id(&environment.to_string()),
+                        environment: id(environment),
                     }),
                     no_unwind(),
                 ),
@@ -235,7 +373,7 @@ async fn build_env_dag(
         chain_tip = vec![n];
     }
 
-    if config.has_alerts {
+    if config.has_alerts && stage.steps.alerts.unwrap_or(true) {
         let n = engine
             .add_node(
                 pipeline_id,
@@ -257,7 +395,7 @@ async fn build_env_dag(
         chain_tip = vec![n];
     }
 
-    if config.has_log_rules {
+    if config.has_log_rules && stage.steps.log_rules.unwrap_or(true) {
         let mut log_rule_nodes = vec![];
         for host in hosts {
             let n = engine
@@ -284,12 +422,33 @@ async fn build_env_dag(
         chain_tip = log_rule_nodes;
     }
 
-    let (waves, unwind_on_failure) = if config.project_id == "ant-host-agent" {
-        // We have ZERO wave layout here, and we also don't mark the nodes as "unwind_on_failure" since we have messy deploys.
-        (vec![hosts.iter().map(|h| h).collect()], false)
-    } else {
-        (wave_layout(hosts), true)
-    };
+    // The environment's database is migrated once, before any of its hosts are deployed to.
+    if config.has_database && stage.steps.database_migration.unwrap_or(true) {
+        let n = engine
+            .add_node(
+                pipeline_id,
+                node(
+                    DeploymentEvent::DatabaseMigration {
+                        service_id: config.project_id.clone(),
+                        environment: environment.to_string(),
+                    },
+                    Some(DeploymentResource::DatabaseMigration {
+                        service_id: id(&config.project_id),
+                        environment: id(environment),
+                    }),
+                    no_unwind(),
+                ),
+            )
+            .await?;
+        for prev in &chain_tip {
+            engine.add_edge(prev, &n).await?;
+        }
+        chain_tip = vec![n];
+    }
+
+    let default_waves = [WaveSize::Hosts(1), WaveSize::Hosts(2), WaveSize::Hosts(4)];
+    let waves = wave_layout(hosts, stage.waves.as_deref().unwrap_or(&default_waves));
+    let unwind_on_failure = stage.unwind_on_failure.unwrap_or(true);
 
     for wave in waves {
         let mut wave_terminal_nodes = vec![];
@@ -300,7 +459,7 @@ async fn build_env_dag(
                 pipeline_id,
                 host,
                 config,
-                &environment,
+                environment,
                 unwind_on_failure,
             )
             .await?;
@@ -312,37 +471,31 @@ async fn build_env_dag(
             engine.add_edge(&deploy, &verify).await?;
 
             wave_terminal_nodes.push(verify);
-
-            // For every host, apply the migrations
-            if config.has_database {
-                let n = engine
-                    .add_node(
-                        pipeline_id,
-                        node(
-                            DeploymentEvent::DatabaseMigration {
-                                service_id: config.project_id.clone(),
-                                // host: host.to_string(),
-                                environment: environment.to_string(),
-                            },
-                            Some(DeploymentResource::DatabaseMigration {
-                                service_id: id(&config.project_id),
-                                host: id(host),
-                                environment: id(&environment.to_string()),
-                            }),
-                            no_unwind(),
-                        ),
-                    )
-                    .await?;
-                for prev in &chain_tip {
-                    engine.add_edge(prev, &n).await?;
-                }
-                chain_tip = vec![n];
-            }
         }
 
         chain_tip = wave_terminal_nodes;
     }
 
+    if let Some(seconds) = stage.bake_time_seconds.filter(|s| *s > 0) {
+        let n = engine
+            .add_node(
+                pipeline_id,
+                node(
+                    DeploymentEvent::Bake {
+                        environment: environment.to_string(),
+                        seconds,
+                    },
+                    None,
+                    no_unwind(),
+                ),
+            )
+            .await?;
+        for prev in &chain_tip {
+            engine.add_edge(prev, &n).await?;
+        }
+        chain_tip = vec![n];
+    }
+
     Ok(chain_tip)
 }
 
@@ -392,6 +545,7 @@ mod tests {
             has_routes: false,
             has_alerts: false,
             has_log_rules: false,
+            pipeline: default_pipeline("ant-on-the-web", false),
             beta_hosts: vec!["w2".to_string()],
             prod_hosts: vec!["w1".to_string(), "w3".to_string(), "w4".to_string()],
         };
@@ -455,6 +609,65 @@ mod tests {
         }));
     }
 
+    fn web_config(beta_hosts: &[&str], prod_hosts: &[&str]) -> ProjectConfig {
+        ProjectConfig {
+            project_id: "ant-on-the-web".to_string(),
+            has_database: false,
+            has_routes: false,
+            has_alerts: false,
+            has_log_rules: false,
+            pipeline: default_pipeline("ant-on-the-web", true),
+            beta_hosts: beta_hosts.iter().map(|h| h.to_string()).collect(),
+            prod_hosts: prod_hosts.iter().map(|h| h.to_string()).collect(),
+        }
+    }
+
+    #[tokio::test]
+    #[traced_test]
+    async fn dag_leaves_out_excluded_hosts() {
+        let f = Fixture::new().await;
+        let rev = f.db.create_revision("ant-on-the-web").await.unwrap();
+
+        let config = web_config(&["w2", "w4"], &["w1", "w3"])
+            .excluding_hosts(&HashSet::from(["w2".to_string(), "w3".to_string()]))
+            .unwrap();
+
+        let pipeline_id = build_dag(&f.engine, &rev, &config).await.unwrap();
+        let nodes = f.engine.nodes(&pipeline_id).await.unwrap();
+
+        // Beta on w4, then prod on w1, still behind the gate that requires approval.
+        let hosts = events(&nodes)
+            .into_iter()
+            .filter_map(|e| match e {
+                DeploymentEvent::ArtifactReplication { host_id, .. } => Some(host_id),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(hosts, vec!["w4", "w1"]);
+        let gate = nodes
+            .iter()
+            .find(|n| n.event.contains("environment_gate"))
+            .unwrap();
+        f.engine.approve(&gate.node_id).await.unwrap();
+    }
+
+    #[test]
+    fn excluding_every_host_of_a_stage_fails() {
+        let excluded = HashSet::from(["w2".to_string()]);
+
+        let err = web_config(&["w2"], &["w1"])
+            .excluding_hosts(&excluded)
+            .err()
+            .unwrap();
+        assert!(
+            matches!(&err, AntZookeeperError::ValidationError(msg) if msg.contains("beta")),
+            "{err:?}"
+        );
+
+        // A stage without hosts to begin with is not excluded.
+        assert!(web_config(&[], &["w1"]).excluding_hosts(&excluded).is_ok());
+    }
+
     #[tokio::test]
     #[traced_test]
     async fn dag_with_database_migration() {
@@ -467,6 +680,7 @@ mod tests {
             has_routes: false,
             has_alerts: false,
             has_log_rules: false,
+            pipeline: default_pipeline("ant-data-farm", false),
             beta_hosts: vec!["w2".to_string()],
             prod_hosts: vec!["w1".to_string()],
         };
@@ -511,6 +725,7 @@ mod tests {
             has_routes: true,
             has_alerts: true,
             has_log_rules: true,
+            pipeline: default_pipeline("ant-on-the-web", false),
             beta_hosts: vec!["w2".to_string()],
             prod_hosts: vec!["w1".to_string()],
         };
@@ -554,6 +769,7 @@ mod tests {
             has_routes: true,
             has_alerts: false,
             has_log_rules: false,
+            pipeline: default_pipeline("ant-gateway", false),
             beta_hosts: vec![],
             prod_hosts: vec!["w1".to_string()],
         };
@@ -572,4 +788,156 @@ mod tests {
             .unwrap();
         assert_eq!(route_node.state, "executable");
     }
+
+    #[test]
+    fn dag_wave_layout_repeats_last_size() {
+        let ids: Vec<String> = (1..=8).map(|i| i.to_string()).collect();
+
+        let sizes: Vec<usize> = wave_layout(&ids, &[WaveSize::Hosts(1), WaveSize::Hosts(2)])
+            .iter()
+            .map(|w| w.len())
+            .collect();
+        assert_eq!(sizes, vec![1, 2, 2, 2, 1]);
+
+        let sizes: Vec<usize> = wave_layout(&ids, &[WaveSize::Percent(25), WaveSize::Percent(100)])
+            .iter()
+            .map(|w| w.len())
+            .collect();
+        assert_eq!(sizes, vec![2, 6]);
+    }
+
+    #[tokio::test]
+    #[traced_test]
+    async fn dag_declared_pipeline() {
+        let f = Fixture::new().await;
+        let rev = f.db.create_revision("ant-on-the-web").await.unwrap();
+
+        let pipeline: PipelineDefinition = serde_json::from_value(serde_json::json!({
+            "stages": [
+                {
+                    "environment": "prod",
+                    "waves": [{ "percent": 50 }],
+                    "bake_time_seconds": 600,
+                    "steps": { "routes": false }
+                },
+                {
+                    "environment": "beta",
+                    "requires_approval": true
+                }
+            ]
+        }))
+        .unwrap();
+        pipeline.validate().unwrap();
+
+        let config = ProjectConfig {
+            project_id: "ant-on-the-web".to_string(),
+            has_database: false,
+            has_routes: true,
+            has_alerts: false,
+            has_log_rules: false,
+            pipeline,
+            beta_hosts: vec!["w2".to_string()],
+            prod_hosts: vec![
+                "w1".to_string(),
+                "w3".to_string(),
+                "w4".to_string(),
+                "w5".to_string(),
+            ],
+        };
+
+        let pipeline_id = build_dag(&f.engine, &rev, &config).await.unwrap();
+
+        let nodes = f.engine.nodes(&pipeline_id).await.unwrap();
+        let edges = f.engine.edges(&pipeline_id).await.unwrap();
+        let find = |substr: &str| {
+            nodes
+                .iter()
+                .find(|n| n.event.contains(substr))
+                .unwrap()
+                .node_id
+                .clone()
+        };
+        let has_edge = |from: &str, to: &str| {
+            edges
+                .iter()
+                .any(|e| e.from_node_id == from && e.to_node_id == to)
+        };
+
+        // Prod: 4 hosts × 3 + bake = 13
+        // Gate: 1
+        // Beta: route update + 1 host × 3 = 4
+        // Total: 18
+        assert_eq!(nodes.len(), 18);
+
+        let node_events = events(&nodes);
+        assert_eq!(
+            node_events
+                .iter()
+                .filter(|e| matches!(e, DeploymentEvent::RouteUpdate { .. }))
+                .collect::<Vec<_>>(),
+            vec![&DeploymentEvent::RouteUpdate {
+                environment: "beta".to_string()
+            }]
+        );
+        assert!(node_events.contains(&DeploymentEvent::Bake {
+            environment: "prod".to_string(),
+            seconds: 600,
+        }));
+
+        // Prod in 2 waves of 2, then the bake, then the gate to beta.
+        let bake = find("\"bake\"");
+        let gate = find("environment_gate");
+        let w1_verify = find("{\"type\":\"deployment_verification\",\"host_id\":\"w1\"");
+        let w4_replicate = find("{\"type\":\"artifact_replication\",\"host_id\":\"w4\"");
+        let w5_verify = find("{\"type\":\"deployment_verification\",\"host_id\":\"w5\"");
+        assert!(has_edge(&w1_verify, &w4_replicate));
+        assert!(has_edge(&w5_verify, &bake));
+        assert!(has_edge(&bake, &gate));
+        assert!(has_edge(&gate, &find("route_update")));
+    }
+
+    #[tokio::test]
+    #[traced_test]
+    async fn dag_certificate_rollout_is_a_chain() {
+        let f = Fixture::new().await;
+        let rev =
+            f.db.create_revision("ant-gateway-certificate")
+                .await
+                .unwrap();
+
+        let hosts = vec!["w1".to_string(), "w3".to_string()];
+        let pipeline_id = build_certificate_rollout_dag(
+            &f.engine,
+            "ant-gateway-certificate",
+            &rev,
+            "prod",
+            &hosts,
+        )
+        .await
+        .unwrap();
+
+        let nodes = f.engine.nodes(&pipeline_id).await.unwrap();
+        let edges = f.engine.edges(&pipeline_id).await.unwrap();
+
+        assert_eq!(
+            events(&nodes),
+            vec![
+                DeploymentEvent::CertificateRollout {
+                    host_id: "w1".to_string(),
+                    environment: "prod".to_string(),
+                },
+                DeploymentEvent::CertificateRollout {
+                    host_id: "w3".to_string(),
+                    environment: "prod".to_string(),
+                },
+            ]
+        );
+        assert_eq!(edges.len(), 1);
+        assert_eq!(edges[0].from_node_id, nodes[0].node_id);
+        assert_eq!(edges[0].to_node_id, nodes[1].node_id);
+        assert_eq!(
+            nodes[0].resource_key.as_deref(),
+            Some("gateway_certificate:w1")
+        );
+    }
 }
@@ -37,4 +37,14 @@ pub enum DeploymentEvent {
         from: String,
         to: String,
     },
+    /// Pushes the latest certificate of the environment to the gateway on a host.
+    CertificateRollout {
+        host_id: String,
+        environment: String,
+    },
+    /// Waits after an environment is deployed to, before the pipeline moves on.
+    Bake {
+        environment: String,
+        seconds: u64,
+    },
 }
@@ -1,7 +1,17 @@
 use tracing::info;
 
 use crate::{
-    event_loop::{deploy::deploy_artifact, replicate::replicate_artifact_step},
+    event_loop::{
+        cert_rollout::roll_out_certificate,
+        deploy::{deploy_artifact, undeploy_artifact},
+        migrate_db::{migrate_db, unmigrate_db},
+        monitoring_rules::{
+            configure_alerts, configure_log_rules, restore_alerts, restore_log_rules,
+        },
+        replicate::replicate_artifact_step,
+        route_update::{restore_routes, update_routes},
+        verify::verify_deployment,
+    },
     pipeline::deployment_event::DeploymentEvent,
     pipeline_engine::engine::{Dispatch, DispatchDirection},
     state::AntZookeeperState,
@@ -40,9 +50,8 @@ pub async fn dispatch(state: AntZookeeperState, d: Dispatch) -> Result<(), anyho
                     Some(rev) => rev,
                     None => {
                         info!(host_id = %host_id, service_id = %service_id,
-                            "HostDeployment unwind: no previous revision, stopping service");
-                        // TODO: implement service stop
-                        return Ok(());
+                            "HostDeployment unwind: no previous revision, uninstalling service");
+                        return undeploy_artifact(&state, &service_id, &host_id).await;
                     }
                 },
             };
@@ -70,47 +79,89 @@ pub async fn dispatch(state: AntZookeeperState, d: Dispatch) -> Result<(), anyho
             host_id,
             service_id,
             environment,
-        } => {
-            info!(host_id = %host_id, service_id = %service_id, environment = %environment,
-                "DeploymentVerification: not yet implemented");
-            Ok(())
-        }
+        } => match &d.direction {
+            DispatchDirection::Deploy => {
+                verify_deployment(&state, &d.revision_id, &service_id, &environment, &host_id).await
+            }
+            DispatchDirection::Unwind { .. } => {
+                info!(host_id = %host_id, service_id = %service_id, environment = %environment,
+                        "DeploymentVerification unwind: no-op");
+                Ok(())
+            }
+        },
 
-        DeploymentEvent::RouteUpdate { environment } => {
-            info!(environment = %environment, "RouteUpdate: not yet implemented");
-            Ok(())
-        }
+        DeploymentEvent::RouteUpdate { environment } => match &d.direction {
+            DispatchDirection::Deploy => update_routes(&state, &d.revision_id, &environment).await,
+            DispatchDirection::Unwind { .. } => {
+                restore_routes(&state, &d.revision_id, &environment).await
+            }
+        },
 
         DeploymentEvent::AlertConfiguration {
             service_id,
             environment,
-        } => {
-            info!(service_id = %service_id, environment = %environment,
-                "AlertConfiguration: not yet implemented");
-            Ok(())
-        }
+        } => match &d.direction {
+            DispatchDirection::Deploy => {
+                configure_alerts(&state, &d.revision_id, &service_id, &environment).await
+            }
+            DispatchDirection::Unwind { .. } => {
+                restore_alerts(&state, &d.revision_id, &service_id, &environment).await
+            }
+        },
 
         DeploymentEvent::LogRuleConfiguration {
             host_id,
             service_id,
-        } => {
-            info!(host_id = %host_id, service_id = %service_id,
-                "LogRuleConfiguration: not yet implemented");
-            Ok(())
-        }
+        } => match &d.direction {
+            DispatchDirection::Deploy => {
+                configure_log_rules(&state, &d.revision_id, &service_id, &host_id).await
+            }
+            DispatchDirection::Unwind { .. } => {
+                restore_log_rules(&state, &d.revision_id, &service_id, &host_id).await
+            }
+        },
 
         DeploymentEvent::DatabaseMigration {
             service_id,
             environment,
-        } => {
-            info!(service_id = %service_id, environment = %environment,
-                "DatabaseMigration: not yet implemented");
-            Ok(())
-        }
+        } => match &d.direction {
+            DispatchDirection::Deploy => {
+                migrate_db(&state, &d.revision_id, &service_id, &environment).await
+            }
+            DispatchDirection::Unwind { .. } => {
+                unmigrate_db(&state, &d.revision_id, &service_id, &environment).await
+            }
+        },
 
         DeploymentEvent::EnvironmentGate { from, to } => {
             info!(from = %from, to = %to, "EnvironmentGate: pass-through");
             Ok(())
         }
+
+        DeploymentEvent::CertificateRollout {
+            host_id,
+            environment,
+        } => match &d.direction {
+            DispatchDirection::Deploy => {
+                roll_out_certificate(&state, &d.revision_id, &environment, &host_id).await
+            }
+            DispatchDirection::Unwind { .. } => {
+                info!(host_id = %host_id, environment = %environment,
+                        "CertificateRollout unwind: no-op (the renewed certificate stays)");
+                Ok(())
+            }
+        },
+
+        DeploymentEvent::Bake {
+            environment,
+            seconds,
+        } => match &d.direction {
+            DispatchDirection::Deploy => {
+                info!(environment = %environment, "Baking for {seconds} seconds");
+                tokio::time::sleep(std::time::Duration::from_secs(seconds)).await;
+                Ok(())
+            }
+            DispatchDirection::Unwind { .. } => Ok(()),
+        },
     }
 }
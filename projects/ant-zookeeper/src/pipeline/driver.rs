@@ -0,0 +1,640 @@
+use std::{
+    future::Future,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use ant_library::{db::DatabaseConfig, sd::pg::make_connection_string};
+use anyhow::Context;
+use futures::StreamExt;
+use tokio::{
+    sync::{mpsc, watch},
+    task::JoinHandle,
+};
+use tokio_postgres::{AsyncMessage, NoTls};
+use tracing::{debug, error, info, warn};
+
+use crate::{
+    drift,
+    event_loop::drive_deployment_jobs,
+    pipeline::dispatch::dispatch,
+    pipeline_engine::engine::{TickHandle, NODE_FINISHED_CHANNEL},
+    state::AntZookeeperState,
+};
+
+/// The key of the Postgres advisory lock held by the zookeeper driving the pipelines. Only one
+/// zookeeper holds it at a time, the others wait to take over if its session goes away.
+const LEADER_LOCK_KEY: i64 = 0x616e_745f_7a6f_6f6b; // "ant_zook"
+
+/// The channel that is `NOTIFY`d, with an empty payload, to have the leader tick right away.
+const DRIVER_WAKEUP_CHANNEL: &str = "pipeline_driver_wakeup";
+
+#[derive(Debug, Clone)]
+pub struct DriverConfig {
+    /// How long to wait between ticks while there is work to do.
+    pub min_interval: Duration,
+    /// The longest an idle leader waits between ticks, doubling from `min_interval`.
+    pub max_interval: Duration,
+    /// How long a follower waits between attempts to become the leader.
+    pub election_interval: Duration,
+    /// How often the leader corrects the services found drifted, none to only report drift.
+    pub drift_correction_interval: Option<Duration>,
+}
+
+impl Default for DriverConfig {
+    fn default() -> Self {
+        DriverConfig {
+            min_interval: Duration::from_secs(1),
+            max_interval: Duration::from_secs(30),
+            election_interval: Duration::from_secs(10),
+            drift_correction_interval: None,
+        }
+    }
+}
+
+/// Counters about the driver loop, rendered for Prometheus on `GET /metrics`.
+#[derive(Default)]
+pub struct DriverMetrics {
+    is_leader: AtomicBool,
+    ticks: AtomicU64,
+    tick_failures: AtomicU64,
+    tick_latency_ms_sum: AtomicU64,
+    in_flight_jobs: AtomicU64,
+}
+
+impl DriverMetrics {
+    fn record_tick(&self, latency: Duration, is_success: bool) {
+        self.ticks.fetch_add(1, Ordering::Relaxed);
+        self.tick_latency_ms_sum
+            .fetch_add(latency.as_millis() as u64, Ordering::Relaxed);
+        if !is_success {
+            self.tick_failures.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn render(&self) -> String {
+        let is_leader = self.is_leader.load(Ordering::Relaxed) as u8;
+        let ticks = self.ticks.load(Ordering::Relaxed);
+        let tick_failures = self.tick_failures.load(Ordering::Relaxed);
+        let latency_sum = self.tick_latency_ms_sum.load(Ordering::Relaxed) as f64 / 1000.0;
+        let in_flight_jobs = self.in_flight_jobs.load(Ordering::Relaxed);
+
+        format!(
+            "# HELP ant_zookeeper_driver_is_leader Whether this zookeeper drives the pipelines\n\
+             # TYPE ant_zookeeper_driver_is_leader gauge\n\
+             ant_zookeeper_driver_is_leader {is_leader}\n\
+             # HELP ant_zookeeper_driver_tick_failures_total Ticks that returned an error\n\
+             # TYPE ant_zookeeper_driver_tick_failures_total counter\n\
+             ant_zookeeper_driver_tick_failures_total {tick_failures}\n\
+             # HELP ant_zookeeper_driver_tick_seconds Latency of scheduling pipeline work\n\
+             # TYPE ant_zookeeper_driver_tick_seconds summary\n\
+             ant_zookeeper_driver_tick_seconds_sum {latency_sum}\n\
+             ant_zookeeper_driver_tick_seconds_count {ticks}\n\
+             # HELP ant_zookeeper_driver_in_flight_jobs Pipeline jobs still running\n\
+             # TYPE ant_zookeeper_driver_in_flight_jobs gauge\n\
+             ant_zookeeper_driver_in_flight_jobs {in_flight_jobs}\n"
+        )
+    }
+}
+
+/// Tick the pipeline engine, dispatching every runnable node to its deployment step.
+pub async fn tick(state: &AntZookeeperState) -> Result<TickHandle, anyhow::Error> {
+    let state_for_dispatch = state.clone();
+    state
+        .engine
+        .tick(move |d| {
+            let state = state_for_dispatch.clone();
+            async move { dispatch(state, d).await }
+        })
+        .await
+        .with_context(|| "Pipeline engine tick failure")
+}
+
+/// Tick once and wait for the jobs, like the leader would. While a zookeeper is the leader, it is
+/// woken up to tick instead, and this returns `false` right away.
+pub async fn iterate(state: &AntZookeeperState) -> Result<bool, anyhow::Error> {
+    let pool = state.db.pool();
+    let mut con = pool.get().await?;
+    let tx = con.transaction().await?;
+
+    if !lock_or_wake_leader(&tx).await? {
+        tx.commit().await?;
+        return Ok(false);
+    }
+
+    tick(state)
+        .await?
+        .join()
+        .await
+        .with_context(|| "Pipeline engine join failure")?;
+    drive_deployment_jobs(state).await?;
+
+    // Releases the leader lock.
+    tx.commit().await?;
+    Ok(true)
+}
+
+/// Take the leader lock until `tx` ends, or `NOTIFY` the leader holding it to tick.
+async fn lock_or_wake_leader(tx: &tokio_postgres::Transaction<'_>) -> Result<bool, anyhow::Error> {
+    let is_locked: bool = tx
+        .query_one(
+            "select pg_try_advisory_xact_lock($1) as locked",
+            &[&LEADER_LOCK_KEY],
+        )
+        .await?
+        .get("locked");
+    if !is_locked {
+        debug!("Waking up the zookeeper driving the pipelines");
+        tx.execute("select pg_notify($1, '')", &[&DRIVER_WAKEUP_CHANNEL])
+            .await?;
+    }
+
+    Ok(is_locked)
+}
+
+/// Drive the pipelines until `shutdown` flips to `true`, and wait for all started jobs to finish
+/// before returning.
+///
+/// Only the zookeeper holding the leader lock ticks. It ticks again right away when a job
+/// finishes or it is woken up, and otherwise backs off the longer there is nothing to do.
+pub async fn run(
+    state: AntZookeeperState,
+    db_config: DatabaseConfig,
+    config: DriverConfig,
+    shutdown: watch::Receiver<bool>,
+) {
+    let drift_corrected_at = Mutex::new(None::<Instant>);
+
+    drive(
+        &db_config,
+        &config,
+        &state.driver_metrics,
+        shutdown,
+        || tick(&state),
+        || async {
+            let legacy_jobs = drive_deployment_jobs(&state).await?;
+
+            let Some(interval) = config.drift_correction_interval else {
+                return Ok(legacy_jobs > 0);
+            };
+            let is_due = {
+                let mut corrected_at = drift_corrected_at.lock().unwrap();
+                let is_due = corrected_at.is_none_or(|at| at.elapsed() >= interval);
+                if is_due {
+                    *corrected_at = Some(Instant::now());
+                }
+                is_due
+            };
+            let corrections = match is_due {
+                true => drift::correct_drifted(&state).await.unwrap_or_else(|e| {
+                    error!("ANT-ERR-212: Failed to correct drift: {e:?}");
+                    vec![]
+                }),
+                false => vec![],
+            };
+
+            Ok(legacy_jobs > 0 || !corrections.is_empty())
+        },
+    )
+    .await;
+}
+
+/// The loop of `run`, ticking with `tick` and then doing the `other_work` of the leader, which
+/// returns whether there was any.
+async fn drive<T, TFut, W, WFut>(
+    db_config: &DatabaseConfig,
+    config: &DriverConfig,
+    metrics: &DriverMetrics,
+    mut shutdown: watch::Receiver<bool>,
+    tick: T,
+    other_work: W,
+) where
+    T: Fn() -> TFut,
+    TFut: Future<Output = Result<TickHandle, anyhow::Error>>,
+    W: Fn() -> WFut,
+    WFut: Future<Output = Result<bool, anyhow::Error>>,
+{
+    let mut in_flight: Vec<TickHandle> = Vec::new();
+
+    while !*shutdown.borrow() {
+        let result = lead(
+            db_config,
+            config,
+            metrics,
+            &mut shutdown,
+            &mut in_flight,
+            &tick,
+            &other_work,
+        )
+        .await;
+        if let Err(e) = result {
+            error!("ANT-ERR-185: Pipeline driver failed to lead: {e:?}");
+        }
+        metrics.is_leader.store(false, Ordering::Relaxed);
+
+        tokio::select! {
+            _ = tokio::time::sleep(config.election_interval) => {}
+            _ = shutdown.changed() => {}
+        }
+    }
+
+    let jobs: usize = in_flight.iter().map(|h| h.in_flight()).sum();
+    info!("Draining {jobs} pipeline jobs before shutdown...");
+    for handle in in_flight {
+        // Panicking jobs are already logged by join().
+        handle.join().await.ok();
+    }
+    metrics.in_flight_jobs.store(0, Ordering::Relaxed);
+}
+
+/// Try to become the leader, and drive the pipelines for as long as the lock is held. Returns
+/// `Ok` if another zookeeper is the leader, or once shutting down.
+async fn lead<T, TFut, W, WFut>(
+    db_config: &DatabaseConfig,
+    config: &DriverConfig,
+    metrics: &DriverMetrics,
+    shutdown: &mut watch::Receiver<bool>,
+    in_flight: &mut Vec<TickHandle>,
+    tick: &T,
+    other_work: &W,
+) -> Result<(), anyhow::Error>
+where
+    T: Fn() -> TFut,
+    TFut: Future<Output = Result<TickHandle, anyhow::Error>>,
+    W: Fn() -> WFut,
+    WFut: Future<Output = Result<bool, anyhow::Error>>,
+{
+    // Advisory locks belong to a session, so the lock can't be taken on a pooled connection.
+    let (client, mut notify_rx, connection_task) =
+        connect_listener(db_config, &[NODE_FINISHED_CHANNEL, DRIVER_WAKEUP_CHANNEL]).await?;
+
+    let is_leader: bool = client
+        .query_one(
+            "select pg_try_advisory_lock($1) as locked",
+            &[&LEADER_LOCK_KEY],
+        )
+        .await?
+        .get("locked");
+    if !is_leader {
+        debug!("Another zookeeper is driving the pipelines");
+        connection_task.abort();
+        return Ok(());
+    }
+
+    info!("Driving the pipelines as the leader");
+    metrics.is_leader.store(true, Ordering::Relaxed);
+
+    let mut interval = config.min_interval;
+    loop {
+        // Forget ticks whose jobs are all done.
+        let (done, running): (Vec<TickHandle>, Vec<TickHandle>) =
+            in_flight.drain(..).partition(|h| h.in_flight() == 0);
+        for handle in done {
+            // Panicking jobs are already logged by join().
+            handle.join().await.ok();
+        }
+        *in_flight = running;
+
+        let started = Instant::now();
+        let result = async {
+            let handle = tick().await?;
+            let is_busy = !handle.is_empty();
+            in_flight.push(handle);
+
+            let is_other_work = other_work().await?;
+            Ok::<bool, anyhow::Error>(is_busy || is_other_work)
+        }
+        .await;
+        metrics.record_tick(started.elapsed(), result.is_ok());
+
+        let is_busy = result.unwrap_or_else(|e| {
+            error!("ANT-ERR-186: Pipeline driver tick failed: {e:?}");
+            false
+        });
+
+        let jobs: usize = in_flight.iter().map(|h| h.in_flight()).sum();
+        metrics.in_flight_jobs.store(jobs as u64, Ordering::Relaxed);
+
+        interval = if is_busy {
+            config.min_interval
+        } else {
+            (interval * 2).min(config.max_interval)
+        };
+
+        tokio::select! {
+            _ = shutdown.changed() => break,
+            notification = notify_rx.recv() => match notification {
+                // Wake-ups have no payload.
+                Some(node_id) if node_id.is_empty() => {
+                    debug!("Woken up to tick");
+                    interval = config.min_interval;
+                }
+                Some(node_id) => {
+                    debug!(node_id = %node_id, "Woken up by finished node");
+                    interval = config.min_interval;
+                }
+                None => anyhow::bail!("Pipeline driver connection closed"),
+            },
+            _ = tokio::time::sleep(interval) => {}
+        }
+    }
+
+    client
+        .execute("select pg_advisory_unlock($1)", &[&LEADER_LOCK_KEY])
+        .await?;
+    connection_task.abort();
+
+    Ok(())
+}
+
+/// Open a connection outside of the pool that `LISTEN`s on `channels`. The payloads of their
+/// notifications are sent until the connection fails, after which the receiver closes.
+pub(crate) async fn connect_listener(
+    db_config: &DatabaseConfig,
+    channels: &[&str],
+) -> Result<
+    (
+        tokio_postgres::Client,
+        mpsc::UnboundedReceiver<String>,
+        JoinHandle<()>,
+    ),
+    anyhow::Error,
+> {
+    let (client, mut connection) = tokio_postgres::connect(
+        &make_connection_string(
+            &db_config.database_user,
+            &db_config.database_password,
+            &db_config.host,
+            db_config.port,
+            &db_config.database_name,
+        ),
+        NoTls,
+    )
+    .await
+    .with_context(|| format!("Connecting to listen on {channels:?}"))?;
+
+    let (notify_tx, notify_rx) = mpsc::unbounded_channel::<String>();
+    let connection_task = tokio::spawn(async move {
+        let mut messages = futures::stream::poll_fn(move |cx| connection.poll_message(cx));
+        while let Some(message) = messages.next().await {
+            match message {
+                Ok(AsyncMessage::Notification(n)) => {
+                    let _ = notify_tx.send(n.payload().to_string());
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    warn!("Listening connection failed: {e:?}");
+                    return;
+                }
+            }
+        }
+    });
+
+    for channel in channels {
+        client.batch_execute(&format!("listen {channel}")).await?;
+    }
+
+    Ok((client, notify_rx, connection_task))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{atomic::Ordering, Arc},
+        time::Duration,
+    };
+
+    use ant_library::db::TypesOfAntsDatabase;
+    use ant_library_test::db::TestDatabase;
+    use ant_zookeeper_db::AntZooStorageClient;
+    use serde_json::json;
+    use tokio::{
+        sync::{watch, Semaphore},
+        task::JoinHandle,
+    };
+
+    use super::{drive, lock_or_wake_leader, DriverConfig, DriverMetrics};
+    use crate::pipeline_engine::{
+        engine::PipelineEngine,
+        node::{NodeOptions, NodeSpec},
+    };
+
+    struct Fixture {
+        engine: Arc<PipelineEngine>,
+        db: AntZooStorageClient,
+        _guard: TestDatabase,
+    }
+
+    impl Fixture {
+        async fn new() -> Self {
+            let guard = TestDatabase::new("ant-zookeeper-db").await;
+            let db = AntZooStorageClient::connect(&guard.config).await.unwrap();
+            let engine = Arc::new(PipelineEngine::new(db.pool()).await.unwrap());
+            db.register_project("web", true).await.unwrap();
+
+            Fixture {
+                engine,
+                db,
+                _guard: guard,
+            }
+        }
+
+        /// A pipeline of `names` one after the other.
+        async fn pipeline(&self, names: &[&str]) -> String {
+            let revision = self.db.create_revision("web").await.unwrap();
+            let pipeline_id = self.engine.create_pipeline("web", &revision).await.unwrap();
+
+            let mut previous: Option<String> = None;
+            for name in names {
+                let node_id = self
+                    .engine
+                    .add_node(
+                        &pipeline_id,
+                        NodeSpec {
+                            event: json!({ "type": name }).to_string(),
+                            mutates: None,
+                            options: NodeOptions::default(),
+                        },
+                    )
+                    .await
+                    .unwrap();
+                if let Some(previous) = previous {
+                    self.engine.add_edge(&previous, &node_id).await.unwrap();
+                }
+                previous = Some(node_id);
+            }
+            self.engine.seal(&pipeline_id).await.unwrap();
+
+            pipeline_id
+        }
+
+        async fn states(&self, pipeline_id: &str) -> Vec<String> {
+            let nodes = self.engine.nodes(pipeline_id).await.unwrap();
+            nodes.into_iter().map(|n| n.state).collect()
+        }
+
+        /// Start a driver whose jobs wait for a permit of `jobs` before succeeding.
+        fn start(&self, config: DriverConfig, jobs: Arc<Semaphore>) -> Driver {
+            let metrics = Arc::new(DriverMetrics::default());
+            let (shutdown, shutdown_rx) = watch::channel(false);
+
+            let db_config = self._guard.config.clone();
+            let engine = self.engine.clone();
+            let task_metrics = metrics.clone();
+            let task = tokio::spawn(async move {
+                drive(
+                    &db_config,
+                    &config,
+                    &task_metrics,
+                    shutdown_rx,
+                    || {
+                        let jobs = jobs.clone();
+                        engine.tick(move |_| {
+                            let jobs = jobs.clone();
+                            async move {
+                                jobs.acquire().await?.forget();
+                                Ok(())
+                            }
+                        })
+                    },
+                    || async { Ok(false) },
+                )
+                .await
+            });
+
+            Driver {
+                metrics,
+                shutdown,
+                task,
+            }
+        }
+    }
+
+    struct Driver {
+        metrics: Arc<DriverMetrics>,
+        shutdown: watch::Sender<bool>,
+        task: JoinHandle<()>,
+    }
+
+    impl Driver {
+        fn is_leader(&self) -> bool {
+            self.metrics.is_leader.load(Ordering::Relaxed)
+        }
+
+        fn ticks(&self) -> u64 {
+            self.metrics.ticks.load(Ordering::Relaxed)
+        }
+
+        async fn stop(self) {
+            self.shutdown.send(true).unwrap();
+            self.task.await.unwrap();
+        }
+    }
+
+    /// Wait for `condition` to hold, for at most 10 seconds.
+    async fn eventually(condition: impl AsyncFn() -> bool) {
+        for _ in 0..200 {
+            if condition().await {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        panic!("Condition never held");
+    }
+
+    fn config(min_interval: u64, max_interval: u64) -> DriverConfig {
+        DriverConfig {
+            min_interval: Duration::from_millis(min_interval),
+            max_interval: Duration::from_millis(max_interval),
+            election_interval: Duration::from_millis(100),
+            drift_correction_interval: None,
+        }
+    }
+
+    fn unlimited() -> Arc<Semaphore> {
+        Arc::new(Semaphore::new(Semaphore::MAX_PERMITS))
+    }
+
+    #[tokio::test]
+    async fn one_driver_leads_and_another_takes_over() {
+        let f = Fixture::new().await;
+
+        let first = f.start(config(10, 100), unlimited());
+        eventually(async || first.is_leader()).await;
+
+        let second = f.start(config(10, 100), unlimited());
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        assert!(first.is_leader());
+        assert!(!second.is_leader());
+        assert_eq!(second.ticks(), 0);
+
+        first.stop().await;
+        eventually(async || second.is_leader()).await;
+
+        let pipeline_id = f.pipeline(&["a"]).await;
+        eventually(async || f.states(&pipeline_id).await == ["finished"]).await;
+
+        second.stop().await;
+    }
+
+    #[tokio::test]
+    async fn idle_driver_backs_off() {
+        let f = Fixture::new().await;
+
+        // Ticking every 10ms would be 100 ticks.
+        let driver = f.start(config(10, 160), unlimited());
+        eventually(async || driver.is_leader()).await;
+        tokio::time::sleep(Duration::from_secs(1)).await;
+
+        let ticks = driver.ticks();
+        assert!((3..20).contains(&ticks), "ticks={ticks}");
+
+        driver.stop().await;
+    }
+
+    #[tokio::test]
+    async fn driver_ticks_when_woken_up() {
+        let f = Fixture::new().await;
+
+        let driver = f.start(config(60_000, 60_000), unlimited());
+        eventually(async || driver.ticks() == 1).await;
+
+        // Finishing a job wakes it up to dispatch the next node.
+        let pipeline_id = f.pipeline(&["a", "b"]).await;
+        let pool = f.db.pool();
+        let mut con = pool.get().await.unwrap();
+        let tx = con.transaction().await.unwrap();
+        assert!(!lock_or_wake_leader(&tx).await.unwrap());
+        tx.commit().await.unwrap();
+
+        eventually(async || f.states(&pipeline_id).await == ["finished", "finished"]).await;
+
+        // Without a leader, the lock is taken instead.
+        driver.stop().await;
+        let tx = con.transaction().await.unwrap();
+        assert!(lock_or_wake_leader(&tx).await.unwrap());
+        tx.commit().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn stopping_driver_drains_jobs() {
+        let f = Fixture::new().await;
+
+        let jobs = Arc::new(Semaphore::new(0));
+        let driver = f.start(config(10, 100), jobs.clone());
+        let pipeline_id = f.pipeline(&["a"]).await;
+        eventually(async || driver.metrics.in_flight_jobs.load(Ordering::Relaxed) == 1).await;
+
+        driver.shutdown.send(true).unwrap();
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        assert!(!driver.task.is_finished());
+        assert_eq!(f.states(&pipeline_id).await, ["in_progress"]);
+
+        jobs.add_permits(1);
+        driver.task.await.unwrap();
+        assert_eq!(f.states(&pipeline_id).await, ["finished"]);
+    }
+}
@@ -0,0 +1,516 @@
+//! Render the DAG of a pipeline, as Graphviz DOT, a Mermaid flowchart, or a layered view for the
+//! terminal.
+//!
+//! Nodes are coloured by state, and edges labelled with the resource key of the node they lead
+//! to. Executable nodes held back by a node of an older revision on the same resource (see
+//! `PipelineEngine::why_blocked`) are highlighted, with the blocking node drawn outside the
+//! pipeline.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::pipeline_engine::engine::{BlockReason, Edge, Node, Pipeline, PipelineEngine};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum GraphFormat {
+    Dot,
+    Mermaid,
+    Terminal,
+}
+
+/// A node of an older revision holding back a node of the pipeline, as FIFO on their resource.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Contention {
+    pub blocking_node: Node,
+    pub blocking_pipeline: String,
+    pub blocking_revision: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GraphNode {
+    pub node: Node,
+    /// The longest path from a root to the node, in edges.
+    pub layer: usize,
+    /// Why the node does not run although executable, if it does not.
+    pub blocked: Option<BlockReason>,
+}
+
+impl GraphNode {
+    fn contention(&self) -> Option<Contention> {
+        match &self.blocked {
+            Some(BlockReason::ResourceContention {
+                blocking_node,
+                blocking_revision,
+                blocking_pipeline,
+            }) => Some(Contention {
+                blocking_node: blocking_node.clone(),
+                blocking_pipeline: blocking_pipeline.clone(),
+                blocking_revision: blocking_revision.clone(),
+            }),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PipelineGraph {
+    pub pipeline: Pipeline,
+    /// By layer, then in the order they were added.
+    pub nodes: Vec<GraphNode>,
+    pub edges: Vec<Edge>,
+}
+
+impl PipelineGraph {
+    /// The graph of the pipeline, None if there is no such pipeline.
+    pub async fn load(
+        engine: &PipelineEngine,
+        pipeline_id: &str,
+    ) -> Result<Option<Self>, anyhow::Error> {
+        let Some(pipeline) = engine.pipeline(pipeline_id).await? else {
+            return Ok(None);
+        };
+
+        let order: HashMap<String, usize> = engine
+            .nodes(pipeline_id)
+            .await?
+            .into_iter()
+            .enumerate()
+            .map(|(i, n)| (n.node_id, i))
+            .collect();
+
+        let mut nodes = vec![];
+        for (layer, mut layer_nodes) in engine
+            .nodes_layered(pipeline_id)
+            .await?
+            .into_iter()
+            .enumerate()
+        {
+            layer_nodes.sort_by_key(|n| order.get(&n.node_id).copied());
+            for node in layer_nodes {
+                let blocked = match node.state.as_str() {
+                    "executable" => engine.why_blocked(&node.node_id).await?,
+                    _ => None,
+                };
+                nodes.push(GraphNode {
+                    node,
+                    layer,
+                    blocked,
+                });
+            }
+        }
+
+        Ok(Some(PipelineGraph {
+            pipeline,
+            nodes,
+            edges: engine.edges(pipeline_id).await?,
+        }))
+    }
+
+    pub fn render(&self, format: GraphFormat) -> String {
+        match format {
+            GraphFormat::Dot => self.to_dot(),
+            GraphFormat::Mermaid => self.to_mermaid(),
+            GraphFormat::Terminal => self.to_terminal(),
+        }
+    }
+
+    fn title(&self) -> String {
+        format!(
+            "{} {} ({}, {})",
+            self.pipeline.pipeline_id,
+            self.pipeline.project_id,
+            self.pipeline.revision_id,
+            self.pipeline.state
+        )
+    }
+
+    fn resource_key(&self, node_id: &str) -> Option<&str> {
+        self.nodes
+            .iter()
+            .find(|n| n.node.node_id == node_id)
+            .and_then(|n| n.node.resource_key.as_deref())
+    }
+
+    pub fn to_dot(&self) -> String {
+        let mut dot = format!(
+            "digraph \"{}\" {{\n  label=\"{}\";\n  labelloc=t;\n  rankdir=LR;\n  node [shape=box, style=\"rounded,filled\"];\n",
+            escape_dot(&self.pipeline.pipeline_id),
+            escape_dot(&self.title())
+        );
+
+        for layer in 0..=self.nodes.iter().map(|n| n.layer).max().unwrap_or(0) {
+            dot.push_str("  { rank=same;");
+            for n in self.nodes.iter().filter(|n| n.layer == layer) {
+                dot.push_str(&format!(" \"{}\";", escape_dot(&n.node.node_id)));
+            }
+            dot.push_str(" }\n");
+        }
+
+        for n in &self.nodes {
+            let contended = match n.contention() {
+                Some(_) => ", color=red, penwidth=2",
+                None => "",
+            };
+            dot.push_str(&format!(
+                "  \"{}\" [label=\"{}\\n{}\", fillcolor=\"{}\"{contended}];\n",
+                escape_dot(&n.node.node_id),
+                escape_dot(&event_label(&n.node.event)),
+                escape_dot(&state_label(n)),
+                dot_colour(&n.node.state),
+            ));
+        }
+
+        for e in &self.edges {
+            let label = match self.resource_key(&e.to_node_id) {
+                Some(key) => format!(" [label=\"{}\"]", escape_dot(key)),
+                None => String::new(),
+            };
+            dot.push_str(&format!(
+                "  \"{}\" -> \"{}\"{label};\n",
+                escape_dot(&e.from_node_id),
+                escape_dot(&e.to_node_id)
+            ));
+        }
+
+        for n in &self.nodes {
+            let Some(c) = n.contention() else {
+                continue;
+            };
+            dot.push_str(&format!(
+                "  \"{}\" [label=\"{}\\n{} in {}\", style=\"rounded,dashed\", color=red];\n",
+                escape_dot(&c.blocking_node.node_id),
+                escape_dot(&event_label(&c.blocking_node.event)),
+                escape_dot(&c.blocking_node.state),
+                escape_dot(&c.blocking_revision),
+            ));
+            dot.push_str(&format!(
+                "  \"{}\" -> \"{}\" [label=\"FIFO {}\", style=dashed, color=red];\n",
+                escape_dot(&c.blocking_node.node_id),
+                escape_dot(&n.node.node_id),
+                escape_dot(c.blocking_node.resource_key.as_deref().unwrap_or_default()),
+            ));
+        }
+
+        dot.push_str("}\n");
+        dot
+    }
+
+    pub fn to_mermaid(&self) -> String {
+        // Node IDs are not all valid Mermaid IDs, number them instead.
+        let ids: HashMap<&str, String> = self
+            .nodes
+            .iter()
+            .enumerate()
+            .map(|(i, n)| (n.node.node_id.as_str(), format!("n{i}")))
+            .collect();
+
+        let mut mermaid = format!(
+            "---\ntitle: \"{}\"\n---\nflowchart LR\n",
+            escape_mermaid(&self.title())
+        );
+        for (state, colour) in NODE_STATES {
+            mermaid.push_str(&format!("  classDef {state} fill:{colour}\n"));
+        }
+        mermaid.push_str("  classDef contention stroke:red,stroke-width:2px,stroke-dasharray:4\n");
+
+        for n in &self.nodes {
+            mermaid.push_str(&format!(
+                "  {}[\"{}<br/>{}\"]:::{}\n",
+                ids[n.node.node_id.as_str()],
+                escape_mermaid(&event_label(&n.node.event)),
+                escape_mermaid(&state_label(n)),
+                n.node.state,
+            ));
+        }
+
+        for e in &self.edges {
+            let (Some(from), Some(to)) = (
+                ids.get(e.from_node_id.as_str()),
+                ids.get(e.to_node_id.as_str()),
+            ) else {
+                continue;
+            };
+            match self.resource_key(&e.to_node_id) {
+                Some(key) => {
+                    mermaid.push_str(&format!("  {from} -->|\"{}\"| {to}\n", escape_mermaid(key)))
+                }
+                None => mermaid.push_str(&format!("  {from} --> {to}\n")),
+            }
+        }
+
+        for (i, n) in self.nodes.iter().enumerate() {
+            let Some(c) = n.contention() else {
+                continue;
+            };
+            let to = &ids[n.node.node_id.as_str()];
+            mermaid.push_str(&format!(
+                "  b{i}[\"{}<br/>{} in {}\"]:::contention\n",
+                escape_mermaid(&event_label(&c.blocking_node.event)),
+                escape_mermaid(&c.blocking_node.state),
+                escape_mermaid(&c.blocking_revision),
+            ));
+            mermaid.push_str(&format!(
+                "  b{i} -.->|\"FIFO {}\"| {to}\n",
+                escape_mermaid(c.blocking_node.resource_key.as_deref().unwrap_or_default()),
+            ));
+            mermaid.push_str(&format!("  class {to} contention\n"));
+        }
+
+        mermaid
+    }
+
+    /// One line per node, by layer, in ANSI colours by state. Nodes are numbered, and list the
+    /// nodes they wait for with the resource key of the edge.
+    pub fn to_terminal(&self) -> String {
+        let numbers: HashMap<&str, usize> = self
+            .nodes
+            .iter()
+            .enumerate()
+            .map(|(i, n)| (n.node.node_id.as_str(), i + 1))
+            .collect();
+
+        let mut out = format!("{BOLD}{}{RESET}\n", self.title());
+        let mut layer = None;
+        for n in &self.nodes {
+            if layer != Some(n.layer) {
+                layer = Some(n.layer);
+                out.push_str(&format!("{BOLD}layer {}{RESET}\n", n.layer));
+            }
+
+            out.push_str(&format!(
+                "  [{}] {}{:<13}{RESET} {}",
+                numbers[n.node.node_id.as_str()],
+                ansi_colour(&n.node.state),
+                n.node.state,
+                event_label(&n.node.event),
+            ));
+
+            let after: Vec<String> = self
+                .edges
+                .iter()
+                .filter(|e| e.to_node_id == n.node.node_id)
+                .filter_map(|e| numbers.get(e.from_node_id.as_str()))
+                .map(|i| format!("[{i}]"))
+                .collect();
+            if !after.is_empty() {
+                out.push_str(&format!("  after {}", after.join(" ")));
+            }
+            if let Some(key) = &n.node.resource_key {
+                out.push_str(&format!("  {DIM}{key}{RESET}"));
+            }
+            out.push('\n');
+
+            if let Some(c) = n.contention() {
+                out.push_str(&format!(
+                    "      {RED}waits for {} ({}) of {} in {}{RESET}\n",
+                    c.blocking_node.node_id,
+                    c.blocking_node.state,
+                    c.blocking_revision,
+                    c.blocking_pipeline
+                ));
+            } else if let Some(reason) = &n.blocked {
+                out.push_str(&format!("      {YELLOW}{}{RESET}\n", block_label(reason)));
+            }
+        }
+
+        out
+    }
+}
+
+/// The states of nodes, with their colour.
+const NODE_STATES: [(&str, &str); 9] = [
+    ("pending", "#e0e0e0"),
+    ("executable", "#aed6f1"),
+    ("in_progress", "#f9e79f"),
+    ("finished", "#abebc6"),
+    ("failed", "#f5b7b1"),
+    ("cancelled", "#f2f3f4"),
+    ("unwinding", "#f8c471"),
+    ("unwound", "#d7bde2"),
+    ("unwind_failed", "#ec7063"),
+];
+
+fn dot_colour(state: &str) -> &'static str {
+    NODE_STATES
+        .iter()
+        .find(|(s, _)| *s == state)
+        .map(|(_, colour)| *colour)
+        .unwrap_or("white")
+}
+
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const DIM: &str = "\x1b[2m";
+const RED: &str = "\x1b[31m";
+const YELLOW: &str = "\x1b[33m";
+
+fn ansi_colour(state: &str) -> &'static str {
+    match state {
+        "finished" => "\x1b[32m",
+        "in_progress" | "unwinding" => YELLOW,
+        "executable" => "\x1b[34m",
+        "failed" | "unwind_failed" => RED,
+        "unwound" => "\x1b[35m",
+        _ => DIM,
+    }
+}
+
+/// The type of the event, then the values of its other fields, like "bake prod 600".
+fn event_label(event: &str) -> String {
+    let Ok(serde_json::Value::Object(fields)) = serde_json::from_str(event) else {
+        return event.to_string();
+    };
+
+    let value = |v: &serde_json::Value| match v {
+        serde_json::Value::String(s) => s.clone(),
+        v => v.to_string(),
+    };
+    fields
+        .get("type")
+        .into_iter()
+        .chain(fields.iter().filter(|(k, _)| *k != "type").map(|(_, v)| v))
+        .map(value)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn state_label(n: &GraphNode) -> String {
+    match &n.blocked {
+        Some(reason) => format!("{} ({})", n.node.state, block_label(reason)),
+        None => n.node.state.clone(),
+    }
+}
+
+fn block_label(reason: &BlockReason) -> String {
+    match reason {
+        BlockReason::NotExecutable { state, .. } => format!("not executable: {state}"),
+        BlockReason::AwaitingApproval => "awaiting approval".to_string(),
+        BlockReason::ProjectPaused { project_id } => format!("{project_id} paused"),
+        BlockReason::OutsideDeploymentWindow {
+            environment,
+            freeze,
+            ..
+        } => match freeze {
+            Some(freeze) => format!("{environment} frozen: {}", freeze.reason),
+            None => format!("outside the {environment} deployment windows"),
+        },
+        BlockReason::ResourceContention {
+            blocking_revision, ..
+        } => format!("waits for {blocking_revision}"),
+    }
+}
+
+fn escape_dot(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn escape_mermaid(s: &str) -> String {
+    s.replace('"', "#quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{GraphFormat, GraphNode, PipelineGraph};
+    use crate::pipeline_engine::engine::{BlockReason, Edge, Node, Pipeline};
+
+    fn node(node_id: &str, revision_id: &str, state: &str) -> Node {
+        Node {
+            node_id: node_id.to_string(),
+            revision_id: revision_id.to_string(),
+            event: format!(
+                "{{\"type\":\"host_deployment\",\"host_id\":\"antworker002\",\"service_id\":\"{node_id}\"}}"
+            ),
+            state: state.to_string(),
+            resource_key: Some("host_service:antworker002:ant-gateway".to_string()),
+        }
+    }
+
+    fn graph() -> PipelineGraph {
+        PipelineGraph {
+            pipeline: Pipeline {
+                pipeline_id: "pipe-2".to_string(),
+                project_id: "ant-gateway".to_string(),
+                revision_id: "rev-2".to_string(),
+                state: "active".to_string(),
+            },
+            nodes: vec![
+                GraphNode {
+                    node: node("node-a", "rev-2", "finished"),
+                    layer: 0,
+                    blocked: None,
+                },
+                GraphNode {
+                    node: node("node-b", "rev-2", "executable"),
+                    layer: 1,
+                    blocked: Some(BlockReason::ResourceContention {
+                        blocking_node: node("node-old", "rev-1", "failed"),
+                        blocking_revision: "rev-1".to_string(),
+                        blocking_pipeline: "pipe-1".to_string(),
+                    }),
+                },
+            ],
+            edges: vec![Edge {
+                edge_id: "edge-1".to_string(),
+                from_node_id: "node-a".to_string(),
+                to_node_id: "node-b".to_string(),
+            }],
+        }
+    }
+
+    #[test]
+    fn dot_colours_nodes_and_highlights_contention() {
+        let dot = graph().render(GraphFormat::Dot);
+
+        assert!(dot.starts_with("digraph \"pipe-2\" {"), "{dot}");
+        assert!(dot.contains("\"node-a\" [label=\"host_deployment antworker002 node-a\\nfinished\", fillcolor=\"#abebc6\"];"), "{dot}");
+        assert!(
+            dot.contains(
+                "\"node-a\" -> \"node-b\" [label=\"host_service:antworker002:ant-gateway\"];"
+            ),
+            "{dot}"
+        );
+        assert!(dot.contains("\"node-b\" [label=\"host_deployment antworker002 node-b\\nexecutable (waits for rev-1)\", fillcolor=\"#aed6f1\", color=red, penwidth=2];"), "{dot}");
+        assert!(dot.contains("\"node-old\" -> \"node-b\" [label=\"FIFO host_service:antworker002:ant-gateway\", style=dashed, color=red];"), "{dot}");
+        assert!(dot.ends_with("}\n"));
+    }
+
+    #[test]
+    fn mermaid_numbers_nodes() {
+        let mermaid = graph().render(GraphFormat::Mermaid);
+
+        assert!(mermaid.contains("flowchart LR\n"), "{mermaid}");
+        assert!(
+            mermaid.contains(
+                "  n0[\"host_deployment antworker002 node-a<br/>finished\"]:::finished\n"
+            ),
+            "{mermaid}"
+        );
+        assert!(
+            mermaid.contains("  n0 -->|\"host_service:antworker002:ant-gateway\"| n1\n"),
+            "{mermaid}"
+        );
+        assert!(
+            mermaid.contains("  b1 -.->|\"FIFO host_service:antworker002:ant-gateway\"| n1\n"),
+            "{mermaid}"
+        );
+        assert!(mermaid.contains("  class n1 contention\n"), "{mermaid}");
+    }
+
+    #[test]
+    fn terminal_lists_layers() {
+        let terminal = graph().render(GraphFormat::Terminal);
+
+        assert!(terminal.contains("layer 0"), "{terminal}");
+        assert!(terminal.contains("layer 1"), "{terminal}");
+        assert!(
+            terminal.contains("host_deployment antworker002 node-b  after [1]"),
+            "{terminal}"
+        );
+        assert!(
+            terminal.contains("waits for node-old (failed) of rev-1 in pipe-1"),
+            "{terminal}"
+        );
+    }
+}
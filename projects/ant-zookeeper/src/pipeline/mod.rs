@@ -1,4 +1,7 @@
 pub mod dag;
 pub mod deployment_event;
 pub mod dispatch;
+pub mod driver;
+pub mod graph;
 pub mod resource_key;
+pub mod transitions;
@@ -59,7 +59,6 @@ pub enum DeploymentResource {
     },
     /// Database schema migration for a service in an environment.
     DatabaseMigration {
-        host: Identifier,
         service_id: Identifier,
         environment: Identifier,
     },
@@ -72,6 +71,8 @@ pub enum DeploymentResource {
         host_id: Identifier,
         service_id: Identifier,
     },
+    /// The TLS certificate of the gateway on a specific host.
+    GatewayCertificate { host_id: Identifier },
 }
 
 impl Display for DeploymentResource {
@@ -83,9 +84,8 @@ impl Display for DeploymentResource {
             } => write!(f, "host_service:{host_id}:{service_id}"),
             DeploymentResource::DatabaseMigration {
                 service_id,
-                host,
                 environment,
-            } => write!(f, "database_migration:{service_id}:{host}:{environment}"),
+            } => write!(f, "database_migration:{service_id}:{environment}"),
             DeploymentResource::GatewayRouting { environment } => {
                 write!(f, "gateway_routing:{environment}")
             }
@@ -96,6 +96,9 @@ impl Display for DeploymentResource {
                 host_id,
                 service_id,
             } => write!(f, "log_rules:{host_id}:{service_id}"),
+            DeploymentResource::GatewayCertificate { host_id } => {
+                write!(f, "gateway_certificate:{host_id}")
+            }
         }
     }
 }
@@ -131,8 +134,7 @@ impl FromStr for DeploymentResource {
                 }
                 Ok(DeploymentResource::DatabaseMigration {
                     service_id: Identifier::new(fields[0])?,
-                    host: Identifier::new(fields[1])?,
-                    environment: Identifier::new(fields[2])?,
+                    environment: Identifier::new(fields[1])?,
                 })
             }
             "gateway_routing" => Ok(DeploymentResource::GatewayRouting {
@@ -151,6 +153,9 @@ impl FromStr for DeploymentResource {
                     service_id: Identifier::new(fields[1])?,
                 })
             }
+            "gateway_certificate" => Ok(DeploymentResource::GatewayCertificate {
+                host_id: Identifier::new(rest)?,
+            }),
             _ => anyhow::bail!("Unknown DeploymentResource variant: {variant}"),
         }
     }
@@ -173,7 +178,6 @@ mod tests {
             },
             DeploymentResource::DatabaseMigration {
                 service_id: id("ant-on-the-web-users-db"),
-                host: id("antworker002"),
                 environment: id("beta"),
             },
             DeploymentResource::GatewayRouting {
@@ -186,6 +190,9 @@ mod tests {
                 host_id: id("antworker002"),
                 service_id: id("ant-on-the-web"),
             },
+            DeploymentResource::GatewayCertificate {
+                host_id: id("antworker001"),
+            },
         ]
     }
 
@@ -0,0 +1,131 @@
+use std::{collections::VecDeque, convert::Infallible, sync::Arc, time::Duration};
+
+use ant_library::db::DatabaseConfig;
+use axum::response::sse::Event;
+use futures::Stream;
+use tokio::sync::{broadcast, watch};
+use tracing::{error, warn};
+
+use crate::{
+    pipeline::driver::connect_listener,
+    pipeline_engine::engine::{
+        PipelineEngine, TransitionEvent, TransitionScope, TRANSITION_CHANNEL,
+    },
+    state::AntZookeeperState,
+};
+
+/// How many transitions are read from the database at once.
+const PAGE_SIZE: i64 = 100;
+
+/// Transitions are also numbered, and streams check for them, this often in case a notification was
+/// missed.
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Number the transitions the engine logged, then wake up every stream of them.
+async fn number_and_wake(engine: &PipelineEngine, wake: &broadcast::Sender<()>) {
+    match engine.number_transitions().await {
+        // Nobody streaming is fine.
+        Ok(()) => {
+            let _ = wake.send(());
+        }
+        Err(e) => error!("ANT-ERR-213: Failed to number pipeline transitions: {e:?}"),
+    }
+}
+
+/// Number transitions and wake up every stream of them whenever the engine logs new ones, and at
+/// least every `POLL_INTERVAL` in case a notification was missed. Reconnects until `shutdown` flips
+/// to `true`. Streams only read numbered transitions, so one of these runs per process.
+pub async fn listen(
+    engine: Arc<PipelineEngine>,
+    db_config: DatabaseConfig,
+    wake: broadcast::Sender<()>,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    while !*shutdown.borrow() {
+        match connect_listener(&db_config, &[TRANSITION_CHANNEL]).await {
+            Ok((_client, mut notify_rx, connection_task)) => loop {
+                tokio::select! {
+                    notification = notify_rx.recv() => match notification {
+                        Some(_) => number_and_wake(&engine, &wake).await,
+                        None => break,
+                    },
+                    _ = tokio::time::sleep(POLL_INTERVAL) => number_and_wake(&engine, &wake).await,
+                    _ = shutdown.changed() => {
+                        connection_task.abort();
+                        break;
+                    }
+                }
+            },
+            Err(e) => warn!("Failed to listen for pipeline transitions: {e:?}"),
+        }
+
+        tokio::select! {
+            _ = tokio::time::sleep(POLL_INTERVAL) => number_and_wake(&engine, &wake).await,
+            _ = shutdown.changed() => {}
+        }
+    }
+}
+
+struct Cursor {
+    state: AntZookeeperState,
+    scope: TransitionScope,
+    after_seq: i64,
+    pending: VecDeque<TransitionEvent>,
+    wake: broadcast::Receiver<()>,
+}
+
+/// Stream the transitions in `scope` after `after_seq` as server-sent events, forever. Each
+/// event has the `event_seq` as its ID, for clients to resume with `Last-Event-ID`.
+pub fn stream(
+    state: AntZookeeperState,
+    scope: TransitionScope,
+    after_seq: i64,
+) -> impl Stream<Item = Result<Event, Infallible>> {
+    let cursor = Cursor {
+        wake: state.transitions.subscribe(),
+        state,
+        scope,
+        after_seq,
+        pending: VecDeque::new(),
+    };
+
+    futures::stream::unfold(cursor, |mut c| async move {
+        loop {
+            if let Some(transition) = c.pending.pop_front() {
+                c.after_seq = transition.event_seq;
+                let event = Event::default()
+                    .id(transition.event_seq.to_string())
+                    .event("transition")
+                    .json_data(&transition);
+                match event {
+                    Ok(event) => return Some((Ok(event), c)),
+                    Err(e) => {
+                        error!("ANT-ERR-187: Failed to serialize transition: {e:?}");
+                        return None;
+                    }
+                }
+            }
+
+            match c
+                .state
+                .engine
+                .transitions_since(&c.scope, c.after_seq, PAGE_SIZE)
+                .await
+            {
+                Ok(transitions) if !transitions.is_empty() => c.pending.extend(transitions),
+                Ok(_) => {
+                    tokio::select! {
+                        // Lagging behind just means there is something to read.
+                        _ = c.wake.recv() => {}
+                        _ = tokio::time::sleep(POLL_INTERVAL) => {}
+                    }
+                }
+                Err(e) => {
+                    // The client reconnects with the last ID it saw.
+                    error!("ANT-ERR-188: Failed to read pipeline transitions: {e:?}");
+                    return None;
+                }
+            }
+        }
+    })
+}
@@ -1,7 +1,14 @@
-use ant_library::db::ConnectionPool;
+use std::{collections::HashMap, sync::Arc};
+
+use ant_library::{
+    clock::{Clock, WallClock},
+    db::ConnectionPool,
+};
 use anyhow::Context;
+use chrono::{DateTime, NaiveTime, Utc};
 use serde::{Deserialize, Serialize};
 use stdext::function_name;
+use tokio_postgres::{GenericClient, Row};
 use tracing::{error, info, info_span, warn, Instrument};
 
 use super::node::NodeSpec;
@@ -70,6 +77,30 @@ pub struct NodeEvent {
     pub created_at: chrono::DateTime<chrono::Utc>,
 }
 
+/// A transition of a node, or of a whole pipeline, ordered by `event_seq` across all pipelines.
+/// Transitions are numbered in the order they committed, see `PipelineEngine::number_transitions`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransitionEvent {
+    pub event_seq: i64,
+    pub project_id: String,
+    pub pipeline_id: String,
+    /// `None` when the pipeline itself transitioned.
+    pub node_id: Option<String>,
+    pub to_state: String,
+    pub reason: String,
+    /// The error of the job that failed the node, for transitions into a failed state.
+    pub error: Option<String>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// Which pipelines to read the transitions of.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TransitionScope {
+    Project(String),
+    Pipeline(String),
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Edge {
     pub edge_id: String,
@@ -94,6 +125,20 @@ pub enum BlockReason {
         state: String,
         pending_predecessors: Vec<Node>,
     },
+    /// Node is executable but requires approval, and has not been approved.
+    AwaitingApproval,
+    /// Node is executable but the project of its pipeline is paused.
+    ProjectPaused { project_id: String },
+    /// Node is executable but the environment it deploys to is closed to its project: outside
+    /// every deployment window of the project there, or frozen.
+    OutsideDeploymentWindow {
+        project_id: String,
+        environment: String,
+        /// The deployment windows of the project in the environment.
+        windows: Vec<DeploymentWindow>,
+        /// The freeze the environment is in, if any.
+        freeze: Option<DeploymentFreeze>,
+    },
     /// Node is executable but an older revision has incomplete work on the same resource.
     ResourceContention {
         blocking_node: Node,
@@ -102,6 +147,37 @@ pub enum BlockReason {
     },
 }
 
+/// The days and times of day nodes deploying to an environment may be claimed in. Nodes without an
+/// `environment` in their event are never held.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeploymentWindow {
+    pub window_id: String,
+    /// None for every project without windows of its own in the environment.
+    pub project_id: Option<String>,
+    pub environment: String,
+    /// ISO days of the week, 1 for Monday through 7 for Sunday.
+    pub days: Vec<i32>,
+    /// Inclusive, in `time_zone`.
+    pub start_time: NaiveTime,
+    /// Exclusive, in `time_zone`.
+    pub end_time: NaiveTime,
+    /// An IANA time zone, like "Europe/Amsterdam".
+    pub time_zone: String,
+}
+
+/// A period nodes deploying to an environment are not claimed in, inside its windows or not.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeploymentFreeze {
+    pub freeze_id: String,
+    /// None for every project.
+    pub project_id: Option<String>,
+    /// None for every environment.
+    pub environment: Option<String>,
+    pub starts_at: DateTime<Utc>,
+    pub ends_at: DateTime<Utc>,
+    pub reason: String,
+}
+
 /// Handle given to a spawned task to report job completion.
 /// Calling `succeed()` or `fail()` atomically updates both the job and the node.
 #[derive(Clone)]
@@ -295,6 +371,8 @@ impl JobHandle {
             }
         }
 
+        notify_node_finished(&tx, &self.node_id).await?;
+
         tx.commit().await?;
 
         Ok(())
@@ -403,6 +481,8 @@ impl JobHandle {
         )
         .await?;
 
+        notify_node_finished(&tx, &self.node_id).await?;
+
         tx.commit().await?;
 
         Ok(())
@@ -414,6 +494,19 @@ pub struct TickHandle {
 }
 
 impl TickHandle {
+    /// Whether the tick dispatched no jobs at all.
+    pub fn is_empty(&self) -> bool {
+        self.tasks.is_empty()
+    }
+
+    /// How many of the jobs spawned by the tick are still running.
+    pub fn in_flight(&self) -> usize {
+        self.tasks
+            .iter()
+            .filter(|(_, _, handle)| !handle.is_finished())
+            .count()
+    }
+
     pub async fn join(self) -> Result<(), anyhow::Error> {
         let results = futures::future::join_all(self.tasks.into_iter().map(
             |(node_id, job_id, handle)| async move {
@@ -756,6 +849,38 @@ impl PipelineEngine {
     }
 }
 
+/// The channel that is `NOTIFY`d whenever transitions are logged, with an empty payload. Read the
+/// transitions themselves with `PipelineEngine::transitions_since`.
+pub const TRANSITION_CHANNEL: &str = "pipeline_engine_transition";
+
+/// Postgres folds identical notifications of a transaction into one, so this is cheap to repeat.
+async fn notify_transition(tx: &tokio_postgres::Transaction<'_>) -> Result<(), anyhow::Error> {
+    tx.execute("select pg_notify($1, '')", &[&TRANSITION_CHANNEL])
+        .await
+        .with_context(|| "notify_transition")?;
+    Ok(())
+}
+
+/// The channel that `NOTIFY`s the ID of every node whose job has finished, successfully or not.
+pub const NODE_FINISHED_CHANNEL: &str = "pipeline_engine_node_finished";
+
+/// Delivered to listeners when the transaction commits, so never before the node is finished.
+async fn notify_node_finished(
+    tx: &tokio_postgres::Transaction<'_>,
+    node_id: &str,
+) -> Result<(), anyhow::Error> {
+    tx.execute(
+        "select pg_notify($1, $2)",
+        &[&NODE_FINISHED_CHANNEL, &node_id],
+    )
+    .await
+    .with_context(|| format!("notify_node_finished: node={node_id}"))?;
+    Ok(())
+}
+
+/// Serializes the numbering of transitions, see `PipelineEngine::number_transitions`.
+const TRANSITION_LOCK_KEY: i64 = 0x616e_745f_7472_616e; // "ant_tran"
+
 async fn log_node_transition(
     tx: &tokio_postgres::Transaction<'_>,
     node_id: &str,
@@ -773,6 +898,7 @@ async fn log_node_transition(
     )
     .await
     .with_context(|| format!("log_node_transition: node={node_id} ->{to_state} reason={reason}"))?;
+    notify_transition(tx).await?;
     Ok(())
 }
 
@@ -795,6 +921,7 @@ async fn log_node_transitions_bulk(
     )
     .await
     .with_context(|| format!("log_node_transitions_bulk: ->{to_state} reason={reason}"))?;
+    notify_transition(tx).await?;
     Ok(())
 }
 
@@ -817,9 +944,109 @@ async fn log_pipeline_transition(
     .with_context(|| {
         format!("log_pipeline_transition: pipeline={pipeline_id} ->{to_state} reason={reason}")
     })?;
+    notify_transition(tx).await?;
     Ok(())
 }
 
+/// The environment a node deploys to, from the `environment` of its event, if any.
+fn node_environment(event: &str) -> Option<String> {
+    serde_json::from_str::<serde_json::Value>(event)
+        .ok()?
+        .get("environment")?
+        .as_str()
+        .map(str::to_string)
+}
+
+fn row_to_deployment_window(row: &Row) -> DeploymentWindow {
+    DeploymentWindow {
+        window_id: row.get("window_id"),
+        project_id: row.get("project_id"),
+        environment: row.get("environment"),
+        days: row.get("days"),
+        start_time: row.get("start_time"),
+        end_time: row.get("end_time"),
+        time_zone: row.get("time_zone"),
+    }
+}
+
+fn row_to_deployment_freeze(row: &Row) -> DeploymentFreeze {
+    DeploymentFreeze {
+        freeze_id: row.get("freeze_id"),
+        project_id: row.get("project_id"),
+        environment: row.get("environment"),
+        starts_at: row.get("starts_at"),
+        ends_at: row.get("ends_at"),
+        reason: row.get("reason"),
+    }
+}
+
+/// Whether `environment` is closed to `project_id` at `now`: frozen, or with deployment windows,
+/// none of which `now` is in. If so, returns the windows of the project there and the freeze.
+async fn deployment_window_closed<C: GenericClient>(
+    client: &C,
+    project_id: &str,
+    environment: &str,
+    now: DateTime<Utc>,
+) -> Result<Option<(Vec<DeploymentWindow>, Option<DeploymentFreeze>)>, anyhow::Error> {
+    let freeze = client
+        .query_opt(
+            "
+            select *
+            from pipeline_engine_deployment_freeze
+            where
+                (project_id is null or project_id = $1)
+                and (environment is null or environment = $2)
+                and starts_at <= $3
+                and ends_at > $3
+            order by ends_at desc
+            limit 1
+            ",
+            &[&project_id, &environment, &now],
+        )
+        .await
+        .with_context(|| format!("{}: freeze {project_id} {environment}", function_name!()))?
+        .map(|r| row_to_deployment_freeze(&r));
+
+    let rows = client
+        .query(
+            "
+            select
+                w.*,
+                (
+                    extract(isodow from $3::timestamptz at time zone w.time_zone)::integer = any(w.days)
+                    and ($3::timestamptz at time zone w.time_zone)::time >= w.start_time
+                    and ($3::timestamptz at time zone w.time_zone)::time < w.end_time
+                ) as is_open
+            from pipeline_engine_deployment_window w
+            where
+                w.environment = $2
+                and (
+                    w.project_id = $1
+                    or (
+                        w.project_id is null
+                        and not exists (
+                            select 1
+                            from pipeline_engine_deployment_window own
+                            where own.project_id = $1 and own.environment = $2
+                        )
+                    )
+                )
+            order by w.window_id
+            ",
+            &[&project_id, &environment, &now],
+        )
+        .await
+        .with_context(|| format!("{}: windows {project_id} {environment}", function_name!()))?;
+
+    let is_open = rows.is_empty() || rows.iter().any(|r| r.get::<_, bool>("is_open"));
+    if is_open && freeze.is_none() {
+        return Ok(None);
+    }
+
+    let windows = rows.iter().map(row_to_deployment_window).collect();
+    Ok(Some((windows, freeze)))
+}
+
 fn assert_all_nodes_claimed(expected: usize, actual: u64) {
     assert_eq!(
         actual as usize, expected,
@@ -846,12 +1073,23 @@ fn assert_node_was_in_progress(method: &str, node_id: &str, updated: u64) {
 
 pub struct PipelineEngine {
     db: ConnectionPool,
+    /// Tells whether environments are inside their deployment windows.
+    clock: Arc<dyn Clock>,
 }
 
 impl PipelineEngine {
     /// Create a new engine backed by the given connection pool. Cheap — no state beyond the pool.
     pub async fn new(db: ConnectionPool) -> Result<Self, anyhow::Error> {
-        Ok(Self { db })
+        Ok(Self {
+            db,
+            clock: Arc::new(WallClock),
+        })
+    }
+
+    /// The engine, checking deployment windows against `clock` instead of the wall clock.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
     }
 
     /// Returns the underlying connection pool (for test setup and raw queries).
@@ -906,9 +1144,9 @@ impl PipelineEngine {
             .query_one(
                 "
                 insert into pipeline_engine_node
-                    (pipeline_id, event, resource_key, is_unwind_boundary, unwind_on_failure, state)
+                    (pipeline_id, event, resource_key, is_unwind_boundary, unwind_on_failure, requires_approval, state)
                 values
-                    ($1, $2, $3, $4, $5, 'pending')
+                    ($1, $2, $3, $4, $5, $6, 'pending')
                 returning node_id
                 ",
                 &[
@@ -917,6 +1155,7 @@ impl PipelineEngine {
                     &resource_key,
                     &node.options.is_unwind_boundary,
                     &node.options.unwind_on_failure,
+                    &node.options.requires_approval,
                 ],
             )
             .await
@@ -1039,6 +1278,7 @@ impl PipelineEngine {
         let mut con = self.db.get().await?;
         let tx = con.transaction().await?;
         let nodes = self.query_runnable(&tx).await?;
+        let nodes = self.hold_outside_windows(&tx, nodes).await?;
         tx.commit().await?;
         Ok(nodes)
     }
@@ -1064,6 +1304,12 @@ impl PipelineEngine {
                 where
                     n.state = 'executable'
                     and p.state = 'active'
+                    and (not n.requires_approval or n.approved_at is not null)
+                    and not exists (
+                        select 1
+                        from pipeline_engine_paused_project pp
+                        where pp.project_id = p.project_id
+                    )
                     and not exists (
                         select 1
                         from pipeline_engine_edge e
@@ -1109,6 +1355,62 @@ impl PipelineEngine {
         Ok(nodes)
     }
 
+    /// Leave out the nodes deploying to an environment closed to their project, see
+    /// `deployment_window_closed`. Unwinding is never held, it puts back what was there before.
+    async fn hold_outside_windows(
+        &self,
+        tx: &tokio_postgres::Transaction<'_>,
+        nodes: Vec<Node>,
+    ) -> Result<Vec<Node>, anyhow::Error> {
+        let node_ids: Vec<&str> = nodes.iter().map(|n| n.node_id.as_str()).collect();
+        let projects: HashMap<String, String> = tx
+            .query(
+                "
+                select n.node_id, p.project_id
+                from pipeline_engine_node n
+                    join pipeline_engine_pipeline p
+                        on n.pipeline_id = p.pipeline_id
+                where n.node_id = any($1)
+                ",
+                &[&node_ids],
+            )
+            .await
+            .context(function_name!())?
+            .iter()
+            .map(|r| (r.get("node_id"), r.get("project_id")))
+            .collect();
+
+        let now = self.clock.now();
+        let mut closed = HashMap::<(String, String), bool>::new();
+        let mut claimable = vec![];
+        for node in nodes {
+            let (Some(project_id), Some(environment)) =
+                (projects.get(&node.node_id), node_environment(&node.event))
+            else {
+                claimable.push(node);
+                continue;
+            };
+
+            let key = (project_id.clone(), environment);
+            let is_closed = match closed.get(&key) {
+                Some(is_closed) => *is_closed,
+                None => {
+                    let is_closed = deployment_window_closed(tx, &key.0, &key.1, now)
+                        .await?
+                        .is_some();
+                    closed.insert(key, is_closed);
+                    is_closed
+                }
+            };
+
+            if !is_closed {
+                claimable.push(node);
+            }
+        }
+
+        Ok(claimable)
+    }
+
     async fn claim_nodes(
         &self,
         tx: &tokio_postgres::Transaction<'_>,
@@ -1327,7 +1629,7 @@ impl PipelineEngine {
         Ok(())
     }
 
-    /// The main work loop. Call repeatedly (e.g. on a cron/timer).
+    /// The main work loop. Call repeatedly (e.g. from the `pipeline::driver` loop).
     ///
     /// Each tick:
     /// 1. Releases nodes with stale heartbeats (crashed workers)
@@ -1339,7 +1641,7 @@ impl PipelineEngine {
     /// 5. Claims them all atomically (`in_progress`), spawns dispatch tasks
     ///
     /// Returns a `TickHandle`. Call `handle.join()` to await completion (tests,
-    /// graceful shutdown). Drop the handle for fire-and-forget (production cron).
+    /// graceful shutdown), or poll `handle.in_flight()` to keep ticking in the meantime.
     ///
     /// Dispatch success → node becomes `finished` (forward) or `unwound` (unwind).
     /// Dispatch failure → node becomes `failed` (forward) or `unwind_failed` (unwind).
@@ -1361,6 +1663,7 @@ impl PipelineEngine {
             self.complete_unwinding_pipelines(&tx).await?;
 
             let runnable = self.query_runnable(&tx).await?;
+            let runnable = self.hold_outside_windows(&tx, runnable).await?;
             let unwind_eligible = self.query_unwind_eligible(&tx).await?;
 
             let forward_ids: Vec<&str> = runnable.iter().map(|n| n.node_id.as_str()).collect();
@@ -1452,17 +1755,35 @@ impl PipelineEngine {
         let con = self.db.get().await?;
 
         let row = con
-            .query_one(
+            .query_opt(
                 "
-                select node_id, event, state, resource_key
-                from pipeline_engine_node
-                where node_id = $1
+                select
+                    n.node_id,
+                    n.event,
+                    n.state,
+                    n.resource_key,
+                    n.requires_approval,
+                    n.approved_at,
+                    p.project_id,
+                    exists (
+                        select 1
+                        from pipeline_engine_paused_project pp
+                        where pp.project_id = p.project_id
+                    ) as is_paused
+                from pipeline_engine_node n
+                    join pipeline_engine_pipeline p
+                        on n.pipeline_id = p.pipeline_id
+                where n.node_id = $1
                 ",
                 &[&node_id],
             )
             .await
             .with_context(|| format!("{}: {node_id}", function_name!()))?;
 
+        let Some(row) = row else {
+            anyhow::bail!("node {node_id} not found");
+        };
+
         let state: String = row.get("state");
 
         if state != "executable" {
@@ -1504,6 +1825,33 @@ impl PipelineEngine {
             }));
         }
 
+        let requires_approval: bool = row.get("requires_approval");
+        let approved_at: Option<chrono::DateTime<chrono::Utc>> = row.get("approved_at");
+        if requires_approval && approved_at.is_none() {
+            return Ok(Some(BlockReason::AwaitingApproval));
+        }
+
+        if row.get::<_, bool>("is_paused") {
+            return Ok(Some(BlockReason::ProjectPaused {
+                project_id: row.get("project_id"),
+            }));
+        }
+
+        if let Some(environment) = node_environment(&row.get::<_, String>("event")) {
+            let project_id: String = row.get("project_id");
+            if let Some((windows, freeze)) =
+                deployment_window_closed(&**con, &project_id, &environment, self.clock.now())
+                    .await?
+            {
+                return Ok(Some(BlockReason::OutsideDeploymentWindow {
+                    project_id,
+                    environment,
+                    windows,
+                    freeze,
+                }));
+            }
+        }
+
         let resource_key: Option<String> = row.get("resource_key");
 
         if let Some(resource_key) = resource_key {
@@ -1557,10 +1905,302 @@ impl PipelineEngine {
         Ok(None)
     }
 
-    // /// Completely, irreversibly, abort a pipeline for good. Releases locks for resources and stops the propagation.
-    // pub async fn abort_pipeline(&self, pipeline_id: &str) -> Result<(), anyhow::Error> {
-    //     //
-    // }
+    /// Approve a node that requires approval, so the next tick can claim it once it is executable.
+    /// Nodes can be approved ahead of time, while still pending.
+    pub async fn approve(&self, node_id: &str) -> Result<(), anyhow::Error> {
+        let con = self.db.get().await?;
+
+        let row = con
+            .query_opt(
+                "select state, requires_approval, approved_at from pipeline_engine_node where node_id = $1",
+                &[&node_id],
+            )
+            .await
+            .with_context(|| format!("{}: lookup {node_id}", function_name!()))?;
+
+        let Some(row) = row else {
+            anyhow::bail!("node {node_id} not found");
+        };
+
+        let state: String = row.get("state");
+        let requires_approval: bool = row.get("requires_approval");
+        let approved_at: Option<chrono::DateTime<chrono::Utc>> = row.get("approved_at");
+        if !requires_approval {
+            anyhow::bail!("node {node_id} does not require approval");
+        }
+        if approved_at.is_some() {
+            anyhow::bail!("node {node_id} is already approved");
+        }
+        if state != "pending" && state != "executable" {
+            anyhow::bail!("node {node_id} is in state '{state}', cannot approve");
+        }
+
+        con.execute(
+            "
+            update pipeline_engine_node
+            set
+                approved_at = now(),
+                updated_at = now()
+            where
+                node_id = $1
+            ",
+            &[&node_id],
+        )
+        .await
+        .with_context(|| format!("{}: {node_id}", function_name!()))?;
+
+        info!(node_id = %node_id, "node approved");
+        Ok(())
+    }
+
+    /// Stop claiming nodes of the pipelines of a project, forward or unwinding. Nodes in progress
+    /// still finish. Pausing a paused project does nothing.
+    pub async fn pause(&self, project_id: &str) -> Result<(), anyhow::Error> {
+        let con = self.db.get().await?;
+
+        con.execute(
+            "
+            insert into pipeline_engine_paused_project
+                (project_id)
+            values
+                ($1)
+            on conflict (project_id) do nothing
+            ",
+            &[&project_id],
+        )
+        .await
+        .with_context(|| format!("{}: {project_id}", function_name!()))?;
+
+        info!(project_id = %project_id, "project paused");
+        Ok(())
+    }
+
+    /// Undo `pause()`. Resuming a project that is not paused does nothing.
+    pub async fn resume(&self, project_id: &str) -> Result<(), anyhow::Error> {
+        let con = self.db.get().await?;
+
+        con.execute(
+            "delete from pipeline_engine_paused_project where project_id = $1",
+            &[&project_id],
+        )
+        .await
+        .with_context(|| format!("{}: {project_id}", function_name!()))?;
+
+        info!(project_id = %project_id, "project resumed");
+        Ok(())
+    }
+
+    pub async fn is_paused(&self, project_id: &str) -> Result<bool, anyhow::Error> {
+        let con = self.db.get().await?;
+
+        let paused = con
+            .query_opt(
+                "select 1 from pipeline_engine_paused_project where project_id = $1",
+                &[&project_id],
+            )
+            .await
+            .with_context(|| format!("{}: {project_id}", function_name!()))?
+            .is_some();
+
+        Ok(paused)
+    }
+
+    /// Only claim nodes of `project_id`, or of every project without windows of its own, deploying
+    /// to `environment` on `days` between `start_time` and `end_time` in `time_zone`. Returns the
+    /// window ID.
+    pub async fn add_deployment_window(
+        &self,
+        project_id: Option<&str>,
+        environment: &str,
+        days: &[i32],
+        start_time: NaiveTime,
+        end_time: NaiveTime,
+        time_zone: &str,
+    ) -> Result<String, anyhow::Error> {
+        if days.is_empty() || days.iter().any(|d| !(1..=7).contains(d)) {
+            anyhow::bail!("days must be ISO days of the week, 1 through 7, got {days:?}");
+        }
+        if start_time >= end_time {
+            anyhow::bail!("window must start before it ends, got {start_time} to {end_time}");
+        }
+
+        let con = self.db.get().await?;
+
+        // Time zones are checked as they are used, an unknown one would fail every tick.
+        if con
+            .query_opt(
+                "select 1 from pg_timezone_names where name = $1",
+                &[&time_zone],
+            )
+            .await
+            .with_context(|| format!("{}: {time_zone}", function_name!()))?
+            .is_none()
+        {
+            anyhow::bail!("unknown time zone: {time_zone}");
+        }
+
+        let window_id: String = con
+            .query_one(
+                "
+                insert into pipeline_engine_deployment_window
+                    (project_id, environment, days, start_time, end_time, time_zone)
+                values
+                    ($1, $2, $3, $4, $5, $6)
+                returning window_id
+                ",
+                &[
+                    &project_id,
+                    &environment,
+                    &days,
+                    &start_time,
+                    &end_time,
+                    &time_zone,
+                ],
+            )
+            .await
+            .with_context(|| format!("{}: {project_id:?} {environment}", function_name!()))?
+            .get("window_id");
+
+        info!(window_id = %window_id, environment = %environment, "deployment window added");
+        Ok(window_id)
+    }
+
+    /// Remove a deployment window. Returns whether it existed.
+    pub async fn delete_deployment_window(&self, window_id: &str) -> Result<bool, anyhow::Error> {
+        let con = self.db.get().await?;
+
+        let deleted = con
+            .execute(
+                "delete from pipeline_engine_deployment_window where window_id = $1",
+                &[&window_id],
+            )
+            .await
+            .with_context(|| format!("{}: {window_id}", function_name!()))?;
+
+        Ok(deleted > 0)
+    }
+
+    pub async fn deployment_windows(&self) -> Result<Vec<DeploymentWindow>, anyhow::Error> {
+        let con = self.db.get().await?;
+
+        let windows = con
+            .query(
+                "
+                select *
+                from pipeline_engine_deployment_window
+                order by environment, project_id nulls first, window_id
+                ",
+                &[],
+            )
+            .await
+            .context(function_name!())?
+            .iter()
+            .map(row_to_deployment_window)
+            .collect();
+
+        Ok(windows)
+    }
+
+    /// Claim no nodes of `project_id` deploying to `environment` from `starts_at` until `ends_at`,
+    /// with None for every project or environment. Returns the freeze ID.
+    pub async fn add_deployment_freeze(
+        &self,
+        project_id: Option<&str>,
+        environment: Option<&str>,
+        starts_at: DateTime<Utc>,
+        ends_at: DateTime<Utc>,
+        reason: &str,
+    ) -> Result<String, anyhow::Error> {
+        if starts_at >= ends_at {
+            anyhow::bail!("freeze must start before it ends, got {starts_at} to {ends_at}");
+        }
+
+        let con = self.db.get().await?;
+
+        let freeze_id: String = con
+            .query_one(
+                "
+                insert into pipeline_engine_deployment_freeze
+                    (project_id, environment, starts_at, ends_at, reason)
+                values
+                    ($1, $2, $3, $4, $5)
+                returning freeze_id
+                ",
+                &[&project_id, &environment, &starts_at, &ends_at, &reason],
+            )
+            .await
+            .with_context(|| format!("{}: {project_id:?} {environment:?}", function_name!()))?
+            .get("freeze_id");
+
+        info!(freeze_id = %freeze_id, reason = %reason, "deployment freeze added");
+        Ok(freeze_id)
+    }
+
+    /// Remove a freeze, lifting it if it is ongoing. Returns whether it existed.
+    pub async fn delete_deployment_freeze(&self, freeze_id: &str) -> Result<bool, anyhow::Error> {
+        let con = self.db.get().await?;
+
+        let deleted = con
+            .execute(
+                "delete from pipeline_engine_deployment_freeze where freeze_id = $1",
+                &[&freeze_id],
+            )
+            .await
+            .with_context(|| format!("{}: {freeze_id}", function_name!()))?;
+
+        Ok(deleted > 0)
+    }
+
+    /// The freezes that did not end yet, by when they start.
+    pub async fn deployment_freezes(&self) -> Result<Vec<DeploymentFreeze>, anyhow::Error> {
+        let con = self.db.get().await?;
+
+        let freezes = con
+            .query(
+                "
+                select *
+                from pipeline_engine_deployment_freeze
+                where ends_at > $1
+                order by starts_at, freeze_id
+                ",
+                &[&self.clock.now()],
+            )
+            .await
+            .context(function_name!())?
+            .iter()
+            .map(row_to_deployment_freeze)
+            .collect();
+
+        Ok(freezes)
+    }
+
+    /// The pipeline with the ID, if any.
+    pub async fn pipeline(&self, pipeline_id: &str) -> Result<Option<Pipeline>, anyhow::Error> {
+        let con = self.db.get().await?;
+
+        let row = con
+            .query_opt(
+                "
+                select
+                    pipeline_id,
+                    project_id,
+                    revision_id,
+                    state
+                from pipeline_engine_pipeline
+                where pipeline_id = $1
+                ",
+                &[&pipeline_id],
+            )
+            .await
+            .with_context(|| format!("{}: {pipeline_id}", function_name!()))?;
+
+        Ok(row.map(|r| Pipeline {
+            pipeline_id: r.get("pipeline_id"),
+            project_id: r.get("project_id"),
+            revision_id: r.get("revision_id"),
+            state: r.get("state"),
+        }))
+    }
 
     /// Return nodes grouped by topological layer (BFS from roots). Layer 0 = roots, layer N = nodes whose longest path from a root is N edges.
     pub async fn nodes_layered(&self, pipeline_id: &str) -> Result<Vec<Vec<Node>>, anyhow::Error> {
@@ -1732,6 +2372,41 @@ impl PipelineEngine {
         }))
     }
 
+    /// Return, for each resource key, the latest revision (by revision sequence) in which a node of
+    /// that resource with an event containing the JSON `event` finished, and was not unwound since.
+    /// As (resource key, revision ID), ordered by resource key.
+    pub async fn latest_finished_revisions(
+        &self,
+        event: &serde_json::Value,
+    ) -> Result<Vec<(String, String)>, anyhow::Error> {
+        let con = self.db.get().await?;
+
+        let rows = con
+            .query(
+                "
+                select distinct on (n.resource_key)
+                    n.resource_key,
+                    p.revision_id
+                from pipeline_engine_node n
+                    join pipeline_engine_pipeline p on n.pipeline_id = p.pipeline_id
+                    join revision r on p.revision_id = r.revision_id
+                where
+                    n.resource_key is not null
+                    and n.state = 'finished'
+                    and n.event::jsonb @> $1::text::jsonb
+                order by n.resource_key, r.revision_seq desc
+                ",
+                &[&event.to_string()],
+            )
+            .await
+            .with_context(|| format!("{}: {event}", function_name!()))?;
+
+        Ok(rows
+            .iter()
+            .map(|r| (r.get("resource_key"), r.get("revision_id")))
+            .collect())
+    }
+
     /// Return the event log for all nodes in a pipeline, ordered by sequence.
     pub async fn node_events(&self, pipeline_id: &str) -> Result<Vec<NodeEvent>, anyhow::Error> {
         let con = self.db.get().await?;
@@ -1765,6 +2440,139 @@ impl PipelineEngine {
             .collect())
     }
 
+    /// Give the committed transitions that have none a `transition_seq`, in the order of their
+    /// `event_seq`. Events take their `event_seq` when they are inserted, so a transaction
+    /// committing after another one can hold a smaller one, and streams resuming after an
+    /// `event_seq` would skip it. Numbering only sees committed events, and one transaction at a
+    /// time numbers them, so every `transition_seq` is larger than those committed before it.
+    /// Only `pipeline::transitions::listen` calls this, streams just read.
+    pub async fn number_transitions(&self) -> Result<(), anyhow::Error> {
+        let mut con = self.db.get().await?;
+        let tx = con.transaction().await?;
+
+        tx.execute("select pg_advisory_xact_lock($1)", &[&TRANSITION_LOCK_KEY])
+            .await
+            .with_context(|| format!("{}: lock", function_name!()))?;
+        tx.execute(
+            "
+            with numbered as (
+                select kind, event_seq, nextval('pipeline_engine_transition_seq') as transition_seq
+                from (
+                    select 'node' as kind, event_seq::bigint as event_seq
+                    from pipeline_engine_node_event
+                    where transition_seq is null
+                    union all
+                    select 'pipeline', event_seq
+                    from pipeline_engine_pipeline_event
+                    where transition_seq is null
+                    order by event_seq
+                ) unnumbered
+            ),
+            nodes as (
+                update pipeline_engine_node_event e
+                set transition_seq = numbered.transition_seq
+                from numbered
+                where numbered.kind = 'node' and e.event_seq = numbered.event_seq
+            )
+            update pipeline_engine_pipeline_event e
+            set transition_seq = numbered.transition_seq
+            from numbered
+            where numbered.kind = 'pipeline' and e.event_seq = numbered.event_seq
+            ",
+            &[],
+        )
+        .await
+        .with_context(|| format!("{}: number", function_name!()))?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Return at most `limit` transitions of nodes and pipelines in `scope` that came after
+    /// `after_seq`, oldest first. Pass the `event_seq` of the last one to read the next page. Only
+    /// transitions already numbered by `number_transitions` are returned.
+    pub async fn transitions_since(
+        &self,
+        scope: &TransitionScope,
+        after_seq: i64,
+        limit: i64,
+    ) -> Result<Vec<TransitionEvent>, anyhow::Error> {
+        let con = self.db.get().await?;
+
+        let (project_id, pipeline_id) = match scope {
+            TransitionScope::Project(project_id) => (Some(project_id.as_str()), None),
+            TransitionScope::Pipeline(pipeline_id) => (None, Some(pipeline_id.as_str())),
+        };
+
+        let rows = con
+            .query(
+                "
+                select * from (
+                    select
+                        e.transition_seq as event_seq,
+                        p.project_id,
+                        p.pipeline_id,
+                        e.node_id,
+                        e.to_state,
+                        e.reason,
+                        case when e.to_state in ('failed', 'unwind_failed') then (
+                            select j.error
+                            from pipeline_engine_job j
+                            where
+                                j.node_id = e.node_id
+                                and j.state = 'failed'
+                                and j.finished_at <= e.created_at
+                            order by j.finished_at desc
+                            limit 1
+                        ) end as error,
+                        e.created_at
+                    from pipeline_engine_node_event e
+                      join pipeline_engine_node n on e.node_id = n.node_id
+                      join pipeline_engine_pipeline p on n.pipeline_id = p.pipeline_id
+                    where
+                        e.transition_seq > $1
+                        and (p.project_id = $2 or p.pipeline_id = $3)
+
+                    union all
+
+                    select
+                        pe.transition_seq,
+                        p.project_id,
+                        p.pipeline_id,
+                        null::text as node_id,
+                        pe.to_state,
+                        pe.reason,
+                        null::text as error,
+                        pe.created_at
+                    from pipeline_engine_pipeline_event pe
+                      join pipeline_engine_pipeline p on pe.pipeline_id = p.pipeline_id
+                    where
+                        pe.transition_seq > $1
+                        and (p.project_id = $2 or p.pipeline_id = $3)
+                ) t
+                order by event_seq
+                limit $4
+                ",
+                &[&after_seq, &project_id, &pipeline_id, &limit],
+            )
+            .await
+            .with_context(|| format!("{}: {scope:?} after={after_seq}", function_name!()))?;
+
+        Ok(rows
+            .iter()
+            .map(|r| TransitionEvent {
+                event_seq: r.get("event_seq"),
+                project_id: r.get("project_id"),
+                pipeline_id: r.get("pipeline_id"),
+                node_id: r.get("node_id"),
+                to_state: r.get("to_state"),
+                reason: r.get("reason"),
+                error: r.get("error"),
+                created_at: r.get("created_at"),
+            })
+            .collect())
+    }
+
     /// Return the latest job error for each node in a pipeline (only failed jobs).
     pub async fn node_errors(
         &self,
@@ -2123,6 +2931,11 @@ impl PipelineEngine {
                   join pipeline_engine_pipeline p on n.pipeline_id = p.pipeline_id
                   join unwind_scope us on n.node_id = us.node_id
                 where n.state in ('finished', 'failed')
+                  and not exists (
+                    select 1
+                    from pipeline_engine_paused_project pp
+                    where pp.project_id = p.project_id
+                  )
                   and not exists (
                     select 1
                     from pipeline_engine_edge e
@@ -2,6 +2,8 @@
 pub struct NodeOptions {
     pub is_unwind_boundary: bool,
     pub unwind_on_failure: bool,
+    /// If true, the node is not claimed until `PipelineEngine::approve()` is called for it.
+    pub requires_approval: bool,
 }
 
 impl Default for NodeOptions {
@@ -9,6 +11,7 @@ impl Default for NodeOptions {
         Self {
             is_unwind_boundary: false,
             unwind_on_failure: true,
+            requires_approval: false,
         }
     }
 }
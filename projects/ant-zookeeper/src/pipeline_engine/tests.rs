@@ -1,9 +1,12 @@
-use ant_library::db::TypesOfAntsDatabase;
+use std::sync::Arc;
+
+use ant_library::{clock::TestClock, db::TypesOfAntsDatabase};
 use ant_library_test::db::TestDatabase;
 use ant_zookeeper_db::AntZooStorageClient;
+use chrono::{DateTime, NaiveTime, Utc};
 use tracing_test::traced_test;
 
-use super::engine::{BlockReason, Edge, Job, Node, Pipeline, PipelineEngine};
+use super::engine::{BlockReason, PipelineEngine, TransitionScope};
 use super::node::{NodeOptions, NodeSpec};
 
 use crate::pipeline::resource_key::{DeploymentResource, Identifier};
@@ -56,6 +59,21 @@ mod nodes {
             options: NodeOptions::default(),
         }
     }
+
+    pub fn in_environment(name: &str, environment: &str) -> NodeSpec {
+        NodeSpec {
+            event: serde_json::json!({ "type": name, "environment": environment }).to_string(),
+            mutates: None,
+            options: NodeOptions::default(),
+        }
+    }
+}
+
+/// Monday 2026-01-05 12:00:00 UTC, where the clock of the engine starts.
+const CLOCK_START: i64 = 1_767_614_400;
+
+fn time(hour: u32) -> NaiveTime {
+    NaiveTime::from_hms_opt(hour, 0, 0).unwrap()
 }
 
 struct Fixture {
@@ -68,7 +86,10 @@ impl Fixture {
     async fn new() -> Self {
         let guard = TestDatabase::new("ant-zookeeper-db").await;
         let db = AntZooStorageClient::connect(&guard.config).await.unwrap();
-        let engine = PipelineEngine::new(db.pool()).await.unwrap();
+        let engine = PipelineEngine::new(db.pool())
+            .await
+            .unwrap()
+            .with_clock(Arc::new(TestClock::new(CLOCK_START)));
 
         db.register_project("web", true).await.unwrap();
         db.register_project("gateway", true).await.unwrap();
@@ -81,7 +102,6 @@ impl Fixture {
     }
 
     async fn tick_all_done(&self) -> Vec<super::engine::Dispatch> {
-        use std::sync::Arc;
         use tokio::sync::Mutex;
 
         let collected = Arc::new(Mutex::new(vec![]));
@@ -1467,6 +1487,66 @@ async fn pipeline_engine_latest_finished_pipeline() {
     assert_eq!(latest.unwrap().pipeline_id, p2);
 }
 
+#[tokio::test]
+#[traced_test]
+async fn pipeline_engine_latest_finished_revisions_per_resource() {
+    let f = Fixture::new().await;
+    let rev1 = f.db.create_revision("web").await.unwrap();
+    let rev2 = f.db.create_revision("web").await.unwrap();
+    let rev3 = f.db.create_revision("web").await.unwrap();
+    let deployed = serde_json::json!({ "type": "host-deployed" });
+
+    let p1 = f.engine.create_pipeline("web", &rev1).await.unwrap();
+    for host in ["w1", "w2"] {
+        f.engine
+            .add_node(&p1, nodes::host_deployed(host, "web"))
+            .await
+            .unwrap();
+    }
+    f.engine.seal(&p1).await.unwrap();
+
+    // Only replicated, never deployed.
+    let p2 = f.engine.create_pipeline("web", &rev2).await.unwrap();
+    f.engine
+        .add_node(&p2, nodes::host_replicated("w1", "web"))
+        .await
+        .unwrap();
+    f.engine.seal(&p2).await.unwrap();
+
+    // Nothing finished yet
+    let latest = f.engine.latest_finished_revisions(&deployed).await.unwrap();
+    assert!(latest.is_empty());
+
+    f.tick_all_done().await;
+    f.tick_all_done().await;
+
+    let latest = f.engine.latest_finished_revisions(&deployed).await.unwrap();
+    assert_eq!(
+        latest,
+        vec![
+            ("host_service:w1:web".to_string(), rev1.clone()),
+            ("host_service:w2:web".to_string(), rev1.clone()),
+        ]
+    );
+
+    let p3 = f.engine.create_pipeline("web", &rev3).await.unwrap();
+    f.engine
+        .add_node(&p3, nodes::host_deployed("w1", "web"))
+        .await
+        .unwrap();
+    f.engine.seal(&p3).await.unwrap();
+    f.tick_all_done().await;
+
+    let latest = f.engine.latest_finished_revisions(&deployed).await.unwrap();
+    assert_eq!(
+        latest,
+        vec![
+            ("host_service:w1:web".to_string(), rev3),
+            ("host_service:w2:web".to_string(), rev1),
+        ]
+    );
+}
+
 #[tokio::test]
 #[traced_test]
 async fn pipeline_engine_node_job_returns_latest_job() {
@@ -4276,3 +4356,472 @@ async fn pipeline_engine_wave_barrier_waits_for_all_predecessors() {
     )
     .await;
 }
+
+#[tokio::test]
+#[traced_test]
+async fn pipeline_engine_approval_node_waits_until_approved() {
+    let f = Fixture::new().await;
+    let rev = f.db.create_revision("web").await.unwrap();
+
+    let p = f.engine.create_pipeline("web", &rev).await.unwrap();
+    let a = f.engine.add_node(&p, nodes::synthetic("a")).await.unwrap();
+    let gate = f
+        .engine
+        .add_node(
+            &p,
+            NodeSpec {
+                options: NodeOptions {
+                    requires_approval: true,
+                    ..NodeOptions::default()
+                },
+                ..nodes::synthetic("gate")
+            },
+        )
+        .await
+        .unwrap();
+    let b = f.engine.add_node(&p, nodes::synthetic("b")).await.unwrap();
+    f.engine.add_edge(&a, &gate).await.unwrap();
+    f.engine.add_edge(&gate, &b).await.unwrap();
+    f.engine.seal(&p).await.unwrap();
+
+    f.tick_all_done().await;
+    f.tick_all_done().await;
+    f.assert_states(
+        &p,
+        &[("a", "finished"), ("gate", "executable"), ("b", "pending")],
+    )
+    .await;
+
+    // Executable, but never claimed without approval.
+    assert!(f.engine.runnable().await.unwrap().is_empty());
+    assert_eq!(
+        f.engine.why_blocked(&gate).await.unwrap(),
+        Some(BlockReason::AwaitingApproval)
+    );
+
+    f.engine.approve(&gate).await.unwrap();
+    assert!(f.engine.approve(&gate).await.is_err(), "approved twice");
+    assert_eq!(f.engine.why_blocked(&gate).await.unwrap(), None);
+
+    f.tick_all_done().await;
+    f.tick_all_done().await;
+    f.assert_states(
+        &p,
+        &[("a", "finished"), ("gate", "finished"), ("b", "finished")],
+    )
+    .await;
+}
+
+#[tokio::test]
+#[traced_test]
+async fn pipeline_engine_approve_rejects_nodes_without_approval() {
+    let f = Fixture::new().await;
+    let rev = f.db.create_revision("web").await.unwrap();
+
+    let p = f.engine.create_pipeline("web", &rev).await.unwrap();
+    let a = f.engine.add_node(&p, nodes::synthetic("a")).await.unwrap();
+    f.engine.seal(&p).await.unwrap();
+
+    assert!(f.engine.approve(&a).await.is_err());
+    assert!(f.engine.approve("node-nonexistent").await.is_err());
+}
+
+#[tokio::test]
+#[traced_test]
+async fn pipeline_engine_paused_project_claims_nothing_until_resumed() {
+    let f = Fixture::new().await;
+    let rev_web = f.db.create_revision("web").await.unwrap();
+    let rev_gateway = f.db.create_revision("gateway").await.unwrap();
+
+    let p_web = f.engine.create_pipeline("web", &rev_web).await.unwrap();
+    let a = f
+        .engine
+        .add_node(&p_web, nodes::synthetic("a"))
+        .await
+        .unwrap();
+    f.engine.seal(&p_web).await.unwrap();
+
+    let p_gateway = f
+        .engine
+        .create_pipeline("gateway", &rev_gateway)
+        .await
+        .unwrap();
+    f.engine
+        .add_node(&p_gateway, nodes::synthetic("g"))
+        .await
+        .unwrap();
+    f.engine.seal(&p_gateway).await.unwrap();
+
+    f.engine.pause("web").await.unwrap();
+    f.engine.pause("web").await.unwrap();
+    assert!(f.engine.is_paused("web").await.unwrap());
+    assert!(!f.engine.is_paused("gateway").await.unwrap());
+
+    // Only the other project moves on.
+    f.tick_all_done().await;
+    f.assert_states(&p_web, &[("a", "executable")]).await;
+    f.assert_states(&p_gateway, &[("g", "finished")]).await;
+    assert_eq!(
+        f.engine.why_blocked(&a).await.unwrap(),
+        Some(BlockReason::ProjectPaused {
+            project_id: "web".to_string()
+        })
+    );
+
+    f.engine.resume("web").await.unwrap();
+    assert!(!f.engine.is_paused("web").await.unwrap());
+
+    f.tick_all_done().await;
+    f.assert_states(&p_web, &[("a", "finished")]).await;
+}
+
+#[tokio::test]
+#[traced_test]
+async fn pipeline_engine_transitions_since_resumes_in_scope() {
+    let f = Fixture::new().await;
+    let rev_web = f.db.create_revision("web").await.unwrap();
+    let rev_gateway = f.db.create_revision("gateway").await.unwrap();
+
+    let p_web = f.engine.create_pipeline("web", &rev_web).await.unwrap();
+    let a = f
+        .engine
+        .add_node(&p_web, nodes::synthetic("a"))
+        .await
+        .unwrap();
+    let b = f
+        .engine
+        .add_node(&p_web, nodes::synthetic("b"))
+        .await
+        .unwrap();
+    f.engine.add_edge(&a, &b).await.unwrap();
+    f.engine.seal(&p_web).await.unwrap();
+
+    f.tick_all_done().await;
+    f.tick_all_done().await;
+
+    let p_gateway = f
+        .engine
+        .create_pipeline("gateway", &rev_gateway)
+        .await
+        .unwrap();
+    let g = f
+        .engine
+        .add_node(&p_gateway, nodes::synthetic("g"))
+        .await
+        .unwrap();
+    f.engine.seal(&p_gateway).await.unwrap();
+
+    f.tick_all_fail().await;
+
+    // Nothing is streamed before it is numbered.
+    let web = TransitionScope::Project("web".to_string());
+    assert!(f
+        .engine
+        .transitions_since(&web, 0, 100)
+        .await
+        .unwrap()
+        .is_empty());
+
+    f.engine.number_transitions().await.unwrap();
+    let events = f.engine.transitions_since(&web, 0, 100).await.unwrap();
+    assert!(events.iter().all(|e| e.project_id == "web"));
+    assert!(events.windows(2).all(|w| w[0].event_seq < w[1].event_seq));
+
+    // The pipeline finishing is the last transition, without a node.
+    let last = events.last().unwrap();
+    assert_eq!(last.node_id, None);
+    assert_eq!(last.to_state, "finished");
+
+    assert_eq!(
+        f.engine
+            .transitions_since(&TransitionScope::Pipeline(p_web.clone()), 0, 100)
+            .await
+            .unwrap(),
+        events
+    );
+    assert_eq!(
+        f.engine.transitions_since(&web, 0, 2).await.unwrap(),
+        events[..2]
+    );
+    assert_eq!(
+        f.engine
+            .transitions_since(&web, events[1].event_seq, 100)
+            .await
+            .unwrap(),
+        events[2..]
+    );
+
+    // Failures carry the error of the job.
+    let gateway = f
+        .engine
+        .transitions_since(&TransitionScope::Pipeline(p_gateway), 0, 100)
+        .await
+        .unwrap();
+    let failed = gateway
+        .iter()
+        .find(|e| e.node_id.as_ref() == Some(&g) && e.to_state == "failed")
+        .unwrap();
+    assert!(failed.error.as_ref().unwrap().contains("test failure"));
+}
+
+#[tokio::test]
+#[traced_test]
+async fn pipeline_engine_transitions_since_waits_for_earlier_transactions() {
+    let f = Fixture::new().await;
+    let rev = f.db.create_revision("web").await.unwrap();
+    let p = f.engine.create_pipeline("web", &rev).await.unwrap();
+    let a = f.engine.add_node(&p, nodes::synthetic("a")).await.unwrap();
+    f.engine.seal(&p).await.unwrap();
+
+    let web = TransitionScope::Project("web".to_string());
+    f.engine.number_transitions().await.unwrap();
+    let seen = f.engine.transitions_since(&web, 0, 100).await.unwrap();
+    let after_seq = seen.last().unwrap().event_seq;
+
+    // The first transaction logs its transition before the second, but commits after it.
+    let pool = f.db.pool();
+    let mut con_first = pool.get().await.unwrap();
+    let mut con_second = pool.get().await.unwrap();
+    let first = con_first.transaction().await.unwrap();
+    let second = con_second.transaction().await.unwrap();
+    for (tx, reason) in [(&first, "test_first"), (&second, "test_second")] {
+        tx.execute(
+            "insert into pipeline_engine_node_event (node_id, to_state, reason) values ($1, 'executable', $2)",
+            &[&a, &reason],
+        )
+        .await
+        .unwrap();
+    }
+    second.commit().await.unwrap();
+    f.engine.number_transitions().await.unwrap();
+
+    let events = f
+        .engine
+        .transitions_since(&web, after_seq, 100)
+        .await
+        .unwrap();
+    assert_eq!(
+        events.iter().map(|e| e.reason.as_str()).collect::<Vec<_>>(),
+        vec!["test_second"]
+    );
+
+    first.commit().await.unwrap();
+    f.engine.number_transitions().await.unwrap();
+
+    // Resuming after the second still reads the first.
+    let events = f
+        .engine
+        .transitions_since(&web, events[0].event_seq, 100)
+        .await
+        .unwrap();
+    assert_eq!(
+        events.iter().map(|e| e.reason.as_str()).collect::<Vec<_>>(),
+        vec!["test_first"]
+    );
+}
+
+#[tokio::test]
+#[traced_test]
+async fn pipeline_engine_deployment_window_holds_environment() {
+    let f = Fixture::new().await;
+    let rev = f.db.create_revision("web").await.unwrap();
+
+    let p = f.engine.create_pipeline("web", &rev).await.unwrap();
+    let prod = f
+        .engine
+        .add_node(&p, nodes::in_environment("deploy-prod", "prod"))
+        .await
+        .unwrap();
+    f.engine
+        .add_node(&p, nodes::in_environment("deploy-beta", "beta"))
+        .await
+        .unwrap();
+    f.engine
+        .add_node(&p, nodes::synthetic("no-environment"))
+        .await
+        .unwrap();
+    f.engine.seal(&p).await.unwrap();
+
+    // Weekday afternoons, it is Monday noon.
+    let afternoons = f
+        .engine
+        .add_deployment_window(None, "prod", &[1, 2, 3, 4, 5], time(13), time(17), "UTC")
+        .await
+        .unwrap();
+
+    f.tick_all_done().await;
+    f.assert_states(
+        &p,
+        &[
+            ("deploy-prod", "executable"),
+            ("deploy-beta", "finished"),
+            ("no-environment", "finished"),
+        ],
+    )
+    .await;
+    assert!(f.engine.runnable().await.unwrap().is_empty());
+
+    let windows = f.engine.deployment_windows().await.unwrap();
+    assert_eq!(windows.len(), 1);
+    assert_eq!(windows[0].window_id, afternoons);
+    assert_eq!(
+        f.engine.why_blocked(&prod).await.unwrap(),
+        Some(BlockReason::OutsideDeploymentWindow {
+            project_id: "web".to_string(),
+            environment: "prod".to_string(),
+            windows,
+            freeze: None,
+        })
+    );
+
+    // Windows of the project itself replace those of every project.
+    f.engine
+        .add_deployment_window(Some("web"), "prod", &[1], time(9), time(17), "UTC")
+        .await
+        .unwrap();
+    assert_eq!(f.engine.why_blocked(&prod).await.unwrap(), None);
+
+    f.tick_all_done().await;
+    f.assert_states(
+        &p,
+        &[
+            ("deploy-prod", "finished"),
+            ("deploy-beta", "finished"),
+            ("no-environment", "finished"),
+        ],
+    )
+    .await;
+}
+
+#[tokio::test]
+#[traced_test]
+async fn pipeline_engine_deployment_window_in_local_time() {
+    let f = Fixture::new().await;
+    let rev = f.db.create_revision("web").await.unwrap();
+
+    let p = f.engine.create_pipeline("web", &rev).await.unwrap();
+    let prod = f
+        .engine
+        .add_node(&p, nodes::in_environment("deploy-prod", "prod"))
+        .await
+        .unwrap();
+    f.engine.seal(&p).await.unwrap();
+
+    // Monday noon in UTC is Monday 21:00 in Tokyo.
+    let tokyo = f
+        .engine
+        .add_deployment_window(None, "prod", &[1], time(9), time(17), "Asia/Tokyo")
+        .await
+        .unwrap();
+    f.tick_all_done().await;
+    f.assert_states(&p, &[("deploy-prod", "executable")]).await;
+
+    // And Monday 07:00 in New York.
+    f.engine.delete_deployment_window(&tokyo).await.unwrap();
+    f.engine
+        .add_deployment_window(None, "prod", &[1], time(7), time(8), "America/New_York")
+        .await
+        .unwrap();
+    assert_eq!(f.engine.why_blocked(&prod).await.unwrap(), None);
+
+    f.tick_all_done().await;
+    f.assert_states(&p, &[("deploy-prod", "finished")]).await;
+}
+
+#[tokio::test]
+#[traced_test]
+async fn pipeline_engine_deployment_freeze_holds_until_lifted() {
+    let f = Fixture::new().await;
+    let rev_web = f.db.create_revision("web").await.unwrap();
+    let rev_gateway = f.db.create_revision("gateway").await.unwrap();
+
+    let p_web = f.engine.create_pipeline("web", &rev_web).await.unwrap();
+    let prod = f
+        .engine
+        .add_node(&p_web, nodes::in_environment("deploy-prod", "prod"))
+        .await
+        .unwrap();
+    f.engine.seal(&p_web).await.unwrap();
+
+    let p_gateway = f
+        .engine
+        .create_pipeline("gateway", &rev_gateway)
+        .await
+        .unwrap();
+    f.engine
+        .add_node(&p_gateway, nodes::in_environment("deploy-prod", "prod"))
+        .await
+        .unwrap();
+    f.engine.seal(&p_gateway).await.unwrap();
+
+    let now = DateTime::<Utc>::from_timestamp(CLOCK_START, 0).unwrap();
+    let hour = chrono::Duration::hours(1);
+    let freeze = f
+        .engine
+        .add_deployment_freeze(Some("web"), None, now - hour, now + hour, "launch")
+        .await
+        .unwrap();
+    // Freezes that did not start yet hold nothing.
+    f.engine
+        .add_deployment_freeze(None, None, now + hour, now + hour * 2, "holidays")
+        .await
+        .unwrap();
+    assert_eq!(f.engine.deployment_freezes().await.unwrap().len(), 2);
+
+    f.tick_all_done().await;
+    f.assert_states(&p_web, &[("deploy-prod", "executable")])
+        .await;
+    f.assert_states(&p_gateway, &[("deploy-prod", "finished")])
+        .await;
+
+    match f.engine.why_blocked(&prod).await.unwrap() {
+        Some(BlockReason::OutsideDeploymentWindow {
+            windows,
+            freeze: Some(frozen),
+            ..
+        }) => {
+            assert!(windows.is_empty());
+            assert_eq!(frozen.freeze_id, freeze);
+            assert_eq!(frozen.reason, "launch");
+        }
+        reason => panic!("expected a freeze, got {reason:?}"),
+    }
+
+    assert!(f.engine.delete_deployment_freeze(&freeze).await.unwrap());
+    assert!(!f.engine.delete_deployment_freeze(&freeze).await.unwrap());
+
+    f.tick_all_done().await;
+    f.assert_states(&p_web, &[("deploy-prod", "finished")])
+        .await;
+}
+
+#[tokio::test]
+#[traced_test]
+async fn pipeline_engine_deployment_window_validation() {
+    let f = Fixture::new().await;
+
+    for (days, start, end, time_zone) in [
+        (vec![], time(9), time(17), "UTC"),
+        (vec![0], time(9), time(17), "UTC"),
+        (vec![8], time(9), time(17), "UTC"),
+        (vec![1], time(17), time(9), "UTC"),
+        (vec![1], time(9), time(9), "UTC"),
+        (vec![1], time(9), time(17), "Mars/Olympus_Mons"),
+    ] {
+        assert!(
+            f.engine
+                .add_deployment_window(None, "prod", &days, start, end, time_zone)
+                .await
+                .is_err(),
+            "{days:?} {start} {end} {time_zone}"
+        );
+    }
+    assert!(f.engine.deployment_windows().await.unwrap().is_empty());
+
+    let now = Utc::now();
+    assert!(f
+        .engine
+        .add_deployment_freeze(None, None, now, now, "empty")
+        .await
+        .is_err());
+}
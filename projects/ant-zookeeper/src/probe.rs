@@ -0,0 +1,155 @@
+use std::{sync::Arc, time::Duration};
+
+use ant_library::sd::reader::ServiceDiscovery;
+use anthill_manifest::AnthillCluster;
+use http::StatusCode;
+use tracing::debug;
+
+/// The outside world, as seen by the pipeline: the services on the hosts, and Consul.
+#[async_trait::async_trait]
+pub trait Prober: Send + Sync {
+    /// GET http://{host}:{port}{path}, returning the status and body.
+    async fn http_get(
+        &self,
+        host: &str,
+        port: u16,
+        path: &str,
+    ) -> Result<(StatusCode, String), anyhow::Error>;
+
+    /// PUT `body` to http://{host}:{port}{path}, returning the status and body.
+    async fn http_put(
+        &self,
+        host: &str,
+        port: u16,
+        path: &str,
+        body: String,
+    ) -> Result<(StatusCode, String), anyhow::Error>;
+
+    /// DELETE http://{host}:{port}{path}, returning the status and body.
+    async fn http_delete(
+        &self,
+        host: &str,
+        port: u16,
+        path: &str,
+    ) -> Result<(StatusCode, String), anyhow::Error>;
+
+    /// Whether `service_id` passes its Consul health checks on `host_id`. None if the Consul
+    /// cluster of that environment cannot be observed from here.
+    async fn consul_healthy(
+        &self,
+        cluster: &AnthillCluster,
+        environment: &str,
+        service_id: &str,
+        host_id: &str,
+    ) -> Result<Option<bool>, anyhow::Error>;
+}
+
+pub struct RemoteProber {
+    client: reqwest::Client,
+
+    /// The environment of the ant-matchmaker cluster `sd` reads from. Isolated services in other
+    /// environments register with other clusters.
+    environment: String,
+    sd: Arc<ServiceDiscovery>,
+
+    /// Global infrastructure registers with ant-matchmaker-infra, regardless of environment.
+    infra_sd: Option<Arc<ServiceDiscovery>>,
+}
+
+impl RemoteProber {
+    pub fn new(
+        environment: String,
+        sd: Arc<ServiceDiscovery>,
+        infra_sd: Option<Arc<ServiceDiscovery>>,
+    ) -> Self {
+        Self {
+            client: reqwest::Client::builder()
+                .timeout(Duration::from_secs(10))
+                .build()
+                .expect("reqwest client"),
+            environment,
+            sd,
+            infra_sd,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Prober for RemoteProber {
+    async fn http_get(
+        &self,
+        host: &str,
+        port: u16,
+        path: &str,
+    ) -> Result<(StatusCode, String), anyhow::Error> {
+        let res = self
+            .client
+            .get(format!("http://{host}:{port}{path}"))
+            .send()
+            .await?;
+
+        let status = res.status();
+        Ok((status, res.text().await?))
+    }
+
+    async fn http_put(
+        &self,
+        host: &str,
+        port: u16,
+        path: &str,
+        body: String,
+    ) -> Result<(StatusCode, String), anyhow::Error> {
+        let res = self
+            .client
+            .put(format!("http://{host}:{port}{path}"))
+            .body(body)
+            .send()
+            .await?;
+
+        let status = res.status();
+        Ok((status, res.text().await?))
+    }
+
+    async fn http_delete(
+        &self,
+        host: &str,
+        port: u16,
+        path: &str,
+    ) -> Result<(StatusCode, String), anyhow::Error> {
+        let res = self
+            .client
+            .delete(format!("http://{host}:{port}{path}"))
+            .send()
+            .await?;
+
+        let status = res.status();
+        Ok((status, res.text().await?))
+    }
+
+    async fn consul_healthy(
+        &self,
+        cluster: &AnthillCluster,
+        environment: &str,
+        service_id: &str,
+        host_id: &str,
+    ) -> Result<Option<bool>, anyhow::Error> {
+        let sd = match cluster {
+            AnthillCluster::GlobalInfrastructure => match &self.infra_sd {
+                Some(infra_sd) => infra_sd,
+                None => return Ok(None),
+            },
+            AnthillCluster::IsolatedEnvironment if environment == self.environment => &self.sd,
+            AnthillCluster::IsolatedEnvironment => return Ok(None),
+        };
+
+        // Only endpoints passing their health checks are ever resolved.
+        let endpoints = sd.resolve_all(service_id).await;
+        debug!("Healthy [{service_id}] endpoints: {endpoints:?}");
+
+        Ok(Some(endpoints.iter().any(|e| {
+            e.node == host_id
+                || e.address == host_id
+                || host_id.starts_with(&format!("{}.", e.node))
+        })))
+    }
+}
@@ -0,0 +1,102 @@
+use std::collections::BTreeMap;
+
+use ant_library::routes::Routes;
+use ant_zookeeper_db::{ClientRole, StoredArtifact};
+use axum::{
+    extract::State,
+    routing::{get, post},
+    Json,
+};
+use http::StatusCode;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    artifacts::{collect_garbage, GarbageCollection, DEFAULT_KEEP_DEPLOYED_REVISIONS},
+    auth::BearerClaims,
+    err::AntZookeeperError,
+    state::AntZookeeperState,
+};
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ArtifactFile {
+    /// The path of the file under artifacts-db.
+    pub path: String,
+    pub fingerprint: String,
+    pub size_bytes: i64,
+    /// The artifacts of every revision sharing the file.
+    pub references: Vec<StoredArtifact>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListArtifactsResponse {
+    pub files: Vec<ArtifactFile>,
+}
+
+async fn list_artifacts(
+    State(state): State<AntZookeeperState>,
+    claims: BearerClaims,
+) -> Result<(StatusCode, Json<ListArtifactsResponse>), AntZookeeperError> {
+    claims.require(ClientRole::Viewer)?;
+
+    let mut files: BTreeMap<String, ArtifactFile> = BTreeMap::new();
+    for artifact in state.db.list_stored_artifacts().await? {
+        files
+            .entry(artifact.local_path.clone())
+            .or_insert_with(|| ArtifactFile {
+                path: artifact.local_path.clone(),
+                fingerprint: artifact.fingerprint.clone(),
+                size_bytes: artifact.size_bytes,
+                references: vec![],
+            })
+            .references
+            .push(artifact);
+    }
+
+    Ok((
+        StatusCode::OK,
+        Json(ListArtifactsResponse {
+            files: files.into_values().collect(),
+        }),
+    ))
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CollectGarbageRequest {
+    /// How many deployed revisions of each project to retain, defaults to 5.
+    pub keep_deployed_revisions: Option<i64>,
+    /// Only say what would be collected.
+    pub dry_run: Option<bool>,
+}
+
+async fn collect_artifact_garbage(
+    State(state): State<AntZookeeperState>,
+    claims: BearerClaims,
+    Json(req): Json<CollectGarbageRequest>,
+) -> Result<(StatusCode, Json<GarbageCollection>), AntZookeeperError> {
+    claims.require(ClientRole::Admin)?;
+    claims
+        .audit(&state, "collect-artifact-garbage", &req)
+        .await?;
+
+    let keep_deployed = req
+        .keep_deployed_revisions
+        .unwrap_or(DEFAULT_KEEP_DEPLOYED_REVISIONS);
+    if keep_deployed < 1 {
+        return Err(AntZookeeperError::validation_msg(
+            "At least the last deployed revision of each project must be retained.",
+        ));
+    }
+
+    let collection = collect_garbage(&state, keep_deployed, req.dry_run.unwrap_or(false)).await?;
+
+    Ok((StatusCode::OK, Json(collection)))
+}
+
+pub fn routes() -> Routes<AntZookeeperState> {
+    Routes::new()
+        .get("/artifacts", get(list_artifacts))
+        .post("/gc", post(collect_artifact_garbage))
+}
@@ -0,0 +1,48 @@
+use ant_library::routes::Routes;
+use ant_zookeeper_db::{AuditLogEntry, ClientRole};
+use axum::{
+    extract::{Query, State},
+    routing::get,
+    Json,
+};
+use http::StatusCode;
+use serde::{Deserialize, Serialize};
+
+use crate::{auth::BearerClaims, err::AntZookeeperError, state::AntZookeeperState};
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GetAuditLogRequest {
+    /// At most this many entries, defaults to 100.
+    pub limit: Option<i64>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GetAuditLogResponse {
+    /// Newest first.
+    pub entries: Vec<AuditLogEntry>,
+}
+
+async fn get_audit_log(
+    State(state): State<AntZookeeperState>,
+    claims: BearerClaims,
+    Query(req): Query<GetAuditLogRequest>,
+) -> Result<(StatusCode, Json<GetAuditLogResponse>), AntZookeeperError> {
+    claims.require(ClientRole::Admin)?;
+
+    let limit = req.limit.unwrap_or(100);
+    if !(1..=1000).contains(&limit) {
+        return Err(AntZookeeperError::validation_msg(
+            "The limit must be between 1 and 1000.",
+        ));
+    }
+
+    let entries = state.db.list_audit_log(limit).await?;
+
+    Ok((StatusCode::OK, Json(GetAuditLogResponse { entries })))
+}
+
+pub fn routes() -> Routes<AntZookeeperState> {
+    Routes::new().get("/log", get(get_audit_log))
+}
@@ -1,43 +1,19 @@
-use std::time::Duration;
+use std::str::FromStr;
 
-use acme_lib::{persist::FilePersist, Directory};
 use ant_library::routes::Routes;
-use axum::{extract::State, response::IntoResponse, routing::post, Json};
+use ant_zookeeper_db::{Certificate, ClientRole};
+use anthill_manifest::AnthillEnvironment;
+use axum::{
+    extract::State,
+    response::IntoResponse,
+    routing::{get, post},
+    Json,
+};
 use http::StatusCode;
-use rsa::{pkcs1::EncodeRsaPrivateKey, RsaPrivateKey};
 use serde::{Deserialize, Serialize};
-use tokio::{fs::create_dir_all, time::sleep};
-use tracing::{debug, info};
+use tracing::info;
 
-use crate::{err::AntZookeeperError, state::AntZookeeperState};
-
-fn acme_domain(owned_domain: &str) -> String {
-    format!("_acme-challenge.{owned_domain}")
-}
-
-async fn clean_acme_records(
-    state: &AntZookeeperState,
-    domains: &Vec<String>,
-) -> Result<bool, AntZookeeperError> {
-    // Clean old ACME records because LetsEncrypt won't validate if there is more than 1 record there...
-    let mut cleaned = false;
-    for domain in domains {
-        let records = state
-            .dns
-            .lock()
-            .await
-            .list_txt_records(&acme_domain(domain))
-            .await?;
-
-        for record in records {
-            info!("Cleaning old ACME related record: {domain} => {record:?}");
-            state.dns.lock().await.delete_txt_record(&record.id).await?;
-            cleaned = true;
-        }
-    }
-
-    return Ok(cleaned);
-}
+use crate::{auth::BearerClaims, err::AntZookeeperError, state::AntZookeeperState};
 
 #[derive(Serialize, Deserialize)]
 pub struct ProvisionCertificateRequest {
@@ -45,129 +21,60 @@ pub struct ProvisionCertificateRequest {
     pub domains: Vec<String>,
 }
 
+/// Track the certificate of an environment, and have it renewed in the background right away. See
+/// `cert::renewer`.
 async fn provision_certificate(
     State(state): State<AntZookeeperState>,
+    claims: BearerClaims,
     Json(req): Json<ProvisionCertificateRequest>,
 ) -> Result<impl IntoResponse, AntZookeeperError> {
-    if req.domains.is_empty() {
-        return Ok((StatusCode::BAD_REQUEST, "No domains requested.".to_string()));
-    }
+    claims.require(ClientRole::Admin)?;
+    claims.audit(&state, "provision-certificate", &req).await?;
 
-    let persist_dir = state.root_dir.join("certs-db");
-    create_dir_all(&persist_dir).await?;
-    let persist = FilePersist::new(persist_dir);
+    let environment = AnthillEnvironment::from_str(&req.environment)
+        .map_err(|e| AntZookeeperError::validation_msg(&e))?;
 
-    let dir = Directory::from_url(persist, state.acme_url.clone())?;
-
-    let acc = dir.account(&state.acme_contact_email)?;
-
-    // Clean old ACME records because LetsEncrypt won't validate if there is more than 1 record there...
-    let cleaned = clean_acme_records(&state, &req.domains).await?;
-    if cleaned {
-        info!("Sleeping for some time before continuing to let DNS records propagate...");
-        sleep(Duration::from_secs(10)).await;
-        info!("Continuing!");
-    }
-
-    info!("Creating new order...");
-    let mut order = acc.new_order(
-        &req.domains[0],
-        &req.domains[1..]
-            .iter()
-            .map(String::as_str)
-            .collect::<Vec<&str>>(),
-    )?;
-
-    if order.is_validated() {
-        info!("Order confirmed to be valid based on certificate store, returning early...");
-        return Ok((StatusCode::OK, "Certificate provisioned.".to_string()));
+    if req.domains.is_empty() {
+        return Ok((StatusCode::BAD_REQUEST, "No domains requested.".to_string()));
     }
 
-    let csr = loop {
-        if let Some(csr) = order.confirm_validations() {
-            info!("Challenge met, moving to providing private key material...");
-            break csr;
-        }
-
-        let auths = order.authorizations()?;
-
-        for auth in auths {
-            let challenge = auth.dns_challenge();
-
-            info!("Putting DNS record...");
-            state
-                .dns
-                .lock()
-                .await
-                .put_txt_record(&acme_domain(&auth.domain_name()), challenge.dns_proof())
-                .await?;
-
-            loop {
-                info!("Polling dns for presence of record...");
-                if state
-                    .dns
-                    .lock()
-                    .await
-                    .list_txt_records(&acme_domain(&auth.domain_name()))
-                    .await?
-                    .iter()
-                    .find(|record| record.content == challenge.dns_proof())
-                    .is_some()
-                {
-                    break;
-                }
-
-                sleep(Duration::from_millis(500)).await;
-            }
-
-            info!("DNS records input, sleeping for propagation...");
-            sleep(Duration::from_millis(30_000)).await;
-
-            info!("Asking ACME to check...");
-            challenge.validate(5_000)?;
-            order.refresh()?;
-        }
-    };
-
-    debug!("Generating private key...");
-    let mut rng = state.rng;
-    let priv_key = RsaPrivateKey::new(&mut rng, 2048)?;
-
-    debug!("Generating PEM document...");
-    let pem = priv_key.to_pkcs1_pem(base64ct::LineEnding::LF)?;
-
-    debug!("Finalizing order...");
-    let order_certificate = csr.finalize(&pem, 5_000)?;
-
-    debug!("Downloading certificate...");
-    let files = order_certificate.download_and_save_cert()?;
-
-    debug!("Deleting TXT records created during the challenge...");
-    clean_acme_records(&state, &req.domains).await?;
-
     state
         .db
-        .register_new_secret_version(
-            "tls_cert",
-            &req.environment,
-            chrono::Duration::days(files.valid_days_left() - 1), // subtract 1 for some safety
-            files.certificate().as_bytes(),
-        )
+        .upsert_certificate(environment.as_str(), &req.domains)
         .await?;
+    info!(
+        "Scheduled the {} certificate for renewal.",
+        environment.as_str()
+    );
+
+    Ok((
+        StatusCode::ACCEPTED,
+        "Certificate scheduled for renewal.".to_string(),
+    ))
+}
 
-    state
-        .db
-        .register_new_secret_version(
-            "tls_key",
-            &req.environment,
-            chrono::Duration::days(files.valid_days_left() - 1), // subtract 1 for some safety
-            files.private_key().as_bytes(),
-        )
-        .await?;
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListCertificatesResponse {
+    pub certificates: Vec<Certificate>,
+}
+
+async fn list_certificates(
+    State(state): State<AntZookeeperState>,
+    claims: BearerClaims,
+) -> Result<impl IntoResponse, AntZookeeperError> {
+    claims.require(ClientRole::Viewer)?;
+
+    let certificates = state.db.list_certificates().await?;
 
-    return Ok((StatusCode::OK, "Certificate provisioned.".to_string()));
+    Ok((
+        StatusCode::OK,
+        Json(ListCertificatesResponse { certificates }),
+    ))
 }
 
 pub fn routes() -> Routes<AntZookeeperState> {
-    Routes::new().post("/cert", post(provision_certificate))
+    Routes::new()
+        .post("/cert", post(provision_certificate))
+        .get("/certificates", get(list_certificates))
 }
@@ -1,20 +1,23 @@
+use std::str::FromStr;
+
 use ant_library::routes::Routes;
-use anyhow::Context;
-use axum::{extract::State, response::IntoResponse, routing::post, Json};
-use chrono::{DateTime, Utc};
-use futures::future::join_all;
+use ant_zookeeper_db::ClientRole;
+use anthill_manifest::AnthillEnvironment;
+use axum::{
+    extract::{Query, State},
+    response::IntoResponse,
+    routing::{delete, get, post},
+    Json,
+};
+use chrono::{DateTime, NaiveTime, Utc};
 use http::StatusCode;
 use serde::{Deserialize, Serialize};
-use tracing::{error, info, info_span, Instrument};
 
 use crate::{
+    auth::BearerClaims,
     err::AntZookeeperError,
-    event_loop::{
-        drive_revisions,
-        perform::JobCompletion,
-        transition::{self, DeploymentEvent},
-    },
-    pipeline::dispatch::dispatch,
+    pipeline::driver::iterate,
+    pipeline_engine::engine::{BlockReason, DeploymentFreeze, DeploymentWindow},
     state::AntZookeeperState,
 };
 
@@ -24,125 +27,17 @@ pub struct IteratePipelineResponse {}
 
 async fn iterate_pipeline(
     State(state): State<AntZookeeperState>,
+    claims: BearerClaims,
 ) -> Result<impl IntoResponse, AntZookeeperError> {
-    // New pipeline engine tick
-    let state_for_dispatch = state.clone();
-    let tick_handle = state
-        .engine
-        .tick(move |d| {
-            let state = state_for_dispatch.clone();
-            async move { dispatch(state, d).await }
-        })
-        .await
-        .with_context(|| "Pipeline engine tick failure")?;
-    tick_handle
-        .join()
-        .await
-        .with_context(|| "Pipeline engine join failure")?;
-
-    // Legacy: Schedule any deployment jobs available
-    drive_revisions(&state.db, 1)
-        .await
-        .with_context(|| "Pipeline orchestration failure")?;
+    claims.require(ClientRole::Deployer)?;
 
-    let unfinished_jobs = state.db.list_unfinished_deployment_jobs().await?;
-
-    if unfinished_jobs.len() > 0 {
-        info!("Processing {} unfinished jobs...", unfinished_jobs.len());
-    }
-
-    let handles = unfinished_jobs.into_iter().map(|job| {
-        let state = state.clone();
-        async move {
-            let event = DeploymentEvent(job.revision.clone(), job.event_document.clone().into());
-
-            let perform_fn = transition::transition(&state.db, &event).await?.perform;
-
-            let is_success = match perform_fn {
-                None => {
-                    error!(
-                        "ANT-ERR-081: Deployment job rev={} event={} has no perform, but ignoring...",
-                        event.0,
-                        event.1.to_string()
-                    );
-
-                    // Just marking it complete, shouldn't have happened though
-                    Some(true)
-                }
-
-                Some(perform_fn) => {
-                    // Do the work.
-
-                    state.db.start_deployment_job(&job.job_id).await?;
-
-                    let work: Result<
-                        Result<JobCompletion<()>, anyhow::Error>,
-                        tokio::task::JoinError,
-                    > = tokio::spawn({
-                        let job_id = job.job_id.clone();
-                        let state = state.clone();
-                        let event2 = event.clone();
-                        let event3 = event.clone();
-                        let span = info_span!("job", job_id = job_id);
-                        async move {
-                            perform_fn(&state, event2).await.with_context(|| {
-                                format!(
-                                    "Failed to perform scheduled deployment job [{}] for event \
-                                     [{}]",
-                                    job_id, event3
-                                )
-                            })
-                        }
-                        .instrument(span)
-                    })
-                    .await;
-
-                    let is_success: Option<bool> = match &work {
-                        Ok(Ok(JobCompletion::Pending)) => None,
-                        Ok(Ok(JobCompletion::Finished(_))) => {
-                            info!("Completed [{}] for {event}", job.job_id);
-                            Some(true)
-                        }
-                        Ok(Err(e)) => {
-                            error!("ANT-ERR-082: Handler Error: {:?}", e);
-                            Some(false)
-                        }
-                        Err(e) => {
-                            error!("ANT-ERR-083: Orchestration Error: {:?}", e);
-                            Some(false)
-                        }
-                    };
-
-                    is_success
-                }
-            };
-
-            // If the job finished, set its status and finished_at timestamp.
-            if let Some(is_success) = is_success {
-                state
-                    .db
-                    .complete_deployment_job(
-                        &job.job_id,
-                        &job.revision,
-                        &job.event_document,
-                        is_success,
-                    )
-                    .await?;
-            } else {
-                // Clear the started_at field to set the job back to pending.
-                state.db.unstart_deployment_job(&job.job_id).await?;
-            }
-
-            return Ok(());
-        }
-    });
+    // Only accepted when the leader was woken up to tick, rather than ticking here.
+    let status = match iterate(&state).await? {
+        true => StatusCode::OK,
+        false => StatusCode::ACCEPTED,
+    };
 
-    join_all(handles)
-        .await
-        .into_iter()
-        .collect::<Result<(), anyhow::Error>>()?;
-
-    Ok((StatusCode::OK, Json(IteratePipelineResponse {})))
+    Ok((status, Json(IteratePipelineResponse {})))
 }
 
 #[derive(Serialize, Deserialize)]
@@ -160,8 +55,12 @@ pub struct RetryResponse {
 
 async fn retry_job(
     State(state): State<AntZookeeperState>,
+    claims: BearerClaims,
     Json(req): Json<RetryRequest>,
 ) -> Result<impl IntoResponse, AntZookeeperError> {
+    claims.require(ClientRole::Deployer)?;
+    claims.audit(&state, "retry", &req).await?;
+
     // New pipeline engine: retry by node_id
     if let Some(node_id) = &req.node_id {
         state
@@ -215,8 +114,429 @@ async fn retry_job(
     ))
 }
 
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CancelPipelineRequest {
+    pub pipeline_id: String,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CancelPipelineResponse {
+    pub cancelled_at: DateTime<Utc>,
+}
+
+/// Permanently abandon a pipeline, newer revisions of the project are no longer blocked by it.
+async fn cancel_pipeline(
+    State(state): State<AntZookeeperState>,
+    claims: BearerClaims,
+    Json(req): Json<CancelPipelineRequest>,
+) -> Result<impl IntoResponse, AntZookeeperError> {
+    claims.require(ClientRole::Deployer)?;
+    claims.audit(&state, "cancel", &req).await?;
+
+    match state.engine.pipeline(&req.pipeline_id).await? {
+        None => {
+            return Err(AntZookeeperError::ResourceNotFound(req.pipeline_id));
+        }
+        Some(pipeline) => {
+            if pipeline.state != "active" && pipeline.state != "unwinding" {
+                return Err(AntZookeeperError::ValidationError(format!(
+                    "Pipeline {} is {} and cannot be cancelled.",
+                    req.pipeline_id, pipeline.state
+                )));
+            }
+        }
+    }
+
+    state.engine.cancel(&req.pipeline_id).await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(CancelPipelineResponse {
+            cancelled_at: Utc::now(),
+        }),
+    ))
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApproveNodeRequest {
+    pub node_id: String,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApproveNodeResponse {
+    pub approved_at: DateTime<Utc>,
+}
+
+/// Approve a node waiting for manual approval, like the gate between beta and prod.
+async fn approve_node(
+    State(state): State<AntZookeeperState>,
+    claims: BearerClaims,
+    Json(req): Json<ApproveNodeRequest>,
+) -> Result<impl IntoResponse, AntZookeeperError> {
+    claims.require(ClientRole::Deployer)?;
+    claims.audit(&state, "approve", &req).await?;
+
+    state
+        .engine
+        .approve(&req.node_id)
+        .await
+        .map_err(|e| AntZookeeperError::ValidationError(e.to_string()))?;
+
+    Ok((
+        StatusCode::OK,
+        Json(ApproveNodeResponse {
+            approved_at: Utc::now(),
+        }),
+    ))
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PauseProjectRequest {
+    pub project: String,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PauseProjectResponse {
+    pub paused: bool,
+}
+
+/// Stop claiming nodes of the project's pipelines until it is resumed.
+async fn pause_project(
+    State(state): State<AntZookeeperState>,
+    claims: BearerClaims,
+    Json(req): Json<PauseProjectRequest>,
+) -> Result<impl IntoResponse, AntZookeeperError> {
+    claims.require(ClientRole::Deployer)?;
+    claims.audit(&state, "pause", &req).await?;
+
+    if !state.db.get_project(&req.project).await? {
+        return Err(AntZookeeperError::ResourceNotFound(req.project));
+    }
+
+    state.engine.pause(&req.project).await?;
+
+    Ok((StatusCode::OK, Json(PauseProjectResponse { paused: true })))
+}
+
+async fn resume_project(
+    State(state): State<AntZookeeperState>,
+    claims: BearerClaims,
+    Json(req): Json<PauseProjectRequest>,
+) -> Result<impl IntoResponse, AntZookeeperError> {
+    claims.require(ClientRole::Deployer)?;
+    claims.audit(&state, "resume", &req).await?;
+
+    if !state.db.get_project(&req.project).await? {
+        return Err(AntZookeeperError::ResourceNotFound(req.project));
+    }
+
+    state.engine.resume(&req.project).await?;
+
+    Ok((StatusCode::OK, Json(PauseProjectResponse { paused: false })))
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AddDeploymentWindowRequest {
+    /// None for every project without windows of its own in the environment.
+    pub project: Option<String>,
+    pub environment: String,
+    /// ISO days of the week, 1 for Monday through 7 for Sunday.
+    pub days: Vec<i32>,
+    pub start_time: NaiveTime,
+    pub end_time: NaiveTime,
+    /// An IANA time zone, UTC if not given.
+    pub time_zone: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AddDeploymentWindowResponse {
+    pub window_id: String,
+}
+
+/// Only deploy to the environment inside this window, and the other ones of the project there.
+async fn add_deployment_window(
+    State(state): State<AntZookeeperState>,
+    claims: BearerClaims,
+    Json(req): Json<AddDeploymentWindowRequest>,
+) -> Result<impl IntoResponse, AntZookeeperError> {
+    claims.require(ClientRole::Admin)?;
+    claims.audit(&state, "add-deployment-window", &req).await?;
+
+    let environment = AnthillEnvironment::from_str(&req.environment)
+        .map_err(|e| AntZookeeperError::validation_msg(&e))?;
+
+    if let Some(project) = &req.project {
+        if !state.db.get_project(project).await? {
+            return Err(AntZookeeperError::ResourceNotFound(project.clone()));
+        }
+    }
+
+    let window_id = state
+        .engine
+        .add_deployment_window(
+            req.project.as_deref(),
+            environment.as_str(),
+            &req.days,
+            req.start_time,
+            req.end_time,
+            req.time_zone.as_deref().unwrap_or("UTC"),
+        )
+        .await
+        .map_err(|e| AntZookeeperError::ValidationError(e.to_string()))?;
+
+    Ok((
+        StatusCode::OK,
+        Json(AddDeploymentWindowResponse { window_id }),
+    ))
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeleteDeploymentWindowRequest {
+    pub window_id: String,
+}
+
+async fn delete_deployment_window(
+    State(state): State<AntZookeeperState>,
+    claims: BearerClaims,
+    Json(req): Json<DeleteDeploymentWindowRequest>,
+) -> Result<impl IntoResponse, AntZookeeperError> {
+    claims.require(ClientRole::Admin)?;
+    claims
+        .audit(&state, "delete-deployment-window", &req)
+        .await?;
+
+    if !state
+        .engine
+        .delete_deployment_window(&req.window_id)
+        .await?
+    {
+        return Err(AntZookeeperError::ResourceNotFound(req.window_id));
+    }
+
+    Ok((StatusCode::OK, "Deployment window deleted."))
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AddDeploymentFreezeRequest {
+    /// None for every project.
+    pub project: Option<String>,
+    /// None for every environment.
+    pub environment: Option<String>,
+    pub starts_at: DateTime<Utc>,
+    pub ends_at: DateTime<Utc>,
+    pub reason: String,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AddDeploymentFreezeResponse {
+    pub freeze_id: String,
+}
+
+/// Deploy nothing matching the freeze while it lasts, like over the holidays.
+async fn add_deployment_freeze(
+    State(state): State<AntZookeeperState>,
+    claims: BearerClaims,
+    Json(req): Json<AddDeploymentFreezeRequest>,
+) -> Result<impl IntoResponse, AntZookeeperError> {
+    claims.require(ClientRole::Deployer)?;
+    claims.audit(&state, "add-deployment-freeze", &req).await?;
+
+    let environment = req
+        .environment
+        .as_deref()
+        .map(AnthillEnvironment::from_str)
+        .transpose()
+        .map_err(|e| AntZookeeperError::validation_msg(&e))?;
+
+    if let Some(project) = &req.project {
+        if !state.db.get_project(project).await? {
+            return Err(AntZookeeperError::ResourceNotFound(project.clone()));
+        }
+    }
+
+    let freeze_id = state
+        .engine
+        .add_deployment_freeze(
+            req.project.as_deref(),
+            environment.as_ref().map(AnthillEnvironment::as_str),
+            req.starts_at,
+            req.ends_at,
+            &req.reason,
+        )
+        .await
+        .map_err(|e| AntZookeeperError::ValidationError(e.to_string()))?;
+
+    Ok((
+        StatusCode::OK,
+        Json(AddDeploymentFreezeResponse { freeze_id }),
+    ))
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeleteDeploymentFreezeRequest {
+    pub freeze_id: String,
+}
+
+/// Lift a freeze, or call off one that did not start yet.
+async fn delete_deployment_freeze(
+    State(state): State<AntZookeeperState>,
+    claims: BearerClaims,
+    Json(req): Json<DeleteDeploymentFreezeRequest>,
+) -> Result<impl IntoResponse, AntZookeeperError> {
+    claims.require(ClientRole::Deployer)?;
+    claims
+        .audit(&state, "delete-deployment-freeze", &req)
+        .await?;
+
+    if !state
+        .engine
+        .delete_deployment_freeze(&req.freeze_id)
+        .await?
+    {
+        return Err(AntZookeeperError::ResourceNotFound(req.freeze_id));
+    }
+
+    Ok((StatusCode::OK, "Deployment freeze deleted."))
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GetDeploymentWindowsResponse {
+    pub windows: Vec<DeploymentWindow>,
+    /// The freezes that did not end yet.
+    pub freezes: Vec<DeploymentFreeze>,
+}
+
+async fn get_deployment_windows(
+    State(state): State<AntZookeeperState>,
+    claims: BearerClaims,
+) -> Result<impl IntoResponse, AntZookeeperError> {
+    claims.require(ClientRole::Viewer)?;
+
+    let windows = state.engine.deployment_windows().await?;
+    let freezes = state.engine.deployment_freezes().await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(GetDeploymentWindowsResponse { windows, freezes }),
+    ))
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WhyBlockedRequest {
+    pub node_id: String,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WhyBlockedResponse {
+    /// None if the node is runnable.
+    pub reason: Option<BlockReason>,
+}
+
+async fn why_blocked(
+    State(state): State<AntZookeeperState>,
+    claims: BearerClaims,
+    Query(req): Query<WhyBlockedRequest>,
+) -> Result<impl IntoResponse, AntZookeeperError> {
+    claims.require(ClientRole::Viewer)?;
+
+    let reason = state
+        .engine
+        .why_blocked(&req.node_id)
+        .await
+        .map_err(|e| AntZookeeperError::ValidationError(e.to_string()))?;
+
+    Ok((StatusCode::OK, Json(WhyBlockedResponse { reason })))
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecordDatabaseMigrationRequest {
+    pub project: String,
+    pub environment: String,
+    /// The host running the database.
+    pub host_id: String,
+    /// The migration filename, e.g. "003-reset-monitoring-user-password.sh".
+    pub migration_name: String,
+    /// Hex SHA-256 of the migration file, as `sha256sum` prints it.
+    pub migration_sha256: String,
+}
+
+/// Record a migration as applied by hand, for the ones the pipeline can't tell were. See
+/// `event_loop::migrate_db`.
+async fn record_database_migration(
+    State(state): State<AntZookeeperState>,
+    claims: BearerClaims,
+    Json(req): Json<RecordDatabaseMigrationRequest>,
+) -> Result<impl IntoResponse, AntZookeeperError> {
+    claims.require(ClientRole::Admin)?;
+    claims
+        .audit(&state, "record-database-migration", &req)
+        .await?;
+
+    let environment = AnthillEnvironment::from_str(&req.environment)
+        .map_err(|e| AntZookeeperError::validation_msg(&e))?;
+
+    if !state.db.get_project(&req.project).await? {
+        return Err(AntZookeeperError::ResourceNotFound(req.project));
+    }
+
+    let recorded = state
+        .db
+        .list_database_migrations(&req.project, environment.as_str(), &req.host_id)
+        .await?;
+    if recorded
+        .iter()
+        .any(|m| m.migration_name == req.migration_name)
+    {
+        return Err(AntZookeeperError::validation_msg(&format!(
+            "migration {} is already recorded",
+            req.migration_name
+        )));
+    }
+
+    state
+        .db
+        .record_database_migration(
+            &req.project,
+            environment.as_str(),
+            &req.host_id,
+            &req.migration_name,
+            &req.migration_sha256,
+            None,
+        )
+        .await?;
+
+    Ok((StatusCode::OK, "Database migration recorded."))
+}
+
 pub fn routes() -> Routes<AntZookeeperState> {
     Routes::new()
         .post("/iteration", post(iterate_pipeline))
         .post("/retry", post(retry_job))
+        .post("/cancel", post(cancel_pipeline))
+        .post("/approval", post(approve_node))
+        .post("/pause", post(pause_project))
+        .post("/resume", post(resume_project))
+        .get("/windows", get(get_deployment_windows))
+        .post("/window", post(add_deployment_window))
+        .delete("/window", delete(delete_deployment_window))
+        .post("/freeze", post(add_deployment_freeze))
+        .delete("/freeze", delete(delete_deployment_freeze))
+        .get("/blocked", get(why_blocked))
+        .post("/migration", post(record_database_migration))
 }
@@ -0,0 +1,168 @@
+use ant_host_agent::heartbeat::Heartbeat;
+use ant_library::{host_architecture::HostArchitecture, routes::Routes};
+use ant_zookeeper_db::{ClientRole, HostHeartbeat, ServiceDrift};
+use axum::{
+    extract::State,
+    response::IntoResponse,
+    routing::{get, post},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use http::StatusCode;
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use crate::{
+    auth::BearerClaims, err::AntZookeeperError, inventory::unhealthy_hosts,
+    state::AntZookeeperState,
+};
+
+/// Record the heartbeat of a host, as its inventory. Hosts may only send their own.
+async fn post_heartbeat(
+    State(state): State<AntZookeeperState>,
+    claims: BearerClaims,
+    Json(req): Json<Heartbeat>,
+) -> Result<impl IntoResponse, AntZookeeperError> {
+    claims.require(ClientRole::Host)?;
+
+    if claims.role != ClientRole::Host || claims.client_name != req.host_id {
+        return Err(AntZookeeperError::Forbidden(format!(
+            "client {} can not send heartbeats of {}",
+            claims.client_name, req.host_id
+        )));
+    }
+    if !state.services.hosts.contains_key(&req.host_id) {
+        return Err(AntZookeeperError::ValidationError(format!(
+            "Unknown host: {}",
+            req.host_id
+        )));
+    }
+
+    let int = |n: u64| i64::try_from(n).unwrap_or(i64::MAX);
+    let now = Utc::now();
+    state
+        .db
+        .record_host_heartbeat(&HostHeartbeat {
+            host_id: req.host_id.clone(),
+            uptime_seconds: int(req.uptime_seconds),
+            disk_total_bytes: int(req.disk_total_bytes),
+            disk_available_bytes: int(req.disk_available_bytes),
+            memory_total_bytes: int(req.memory_total_bytes),
+            memory_available_bytes: int(req.memory_available_bytes),
+            services: serde_json::to_string(&req.services)?,
+            units: serde_json::to_string(&req.units)?,
+            first_heartbeat_at: now,
+            last_heartbeat_at: now,
+        })
+        .await?;
+    debug!("Heartbeat of {}.", req.host_id);
+
+    Ok((StatusCode::OK, "Heartbeat recorded."))
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum HostHealth {
+    /// Sent a heartbeat recently.
+    Healthy,
+    /// Missed too many heartbeats, left out of new deployments.
+    Unhealthy,
+    /// Never sent a heartbeat.
+    Unknown,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct HostInventory {
+    pub host_id: String,
+    pub architecture: HostArchitecture,
+    /// Whether services.json has the host inactive, or not auto-deployable.
+    pub ineligible_for_deployments: bool,
+    pub health: HostHealth,
+    /// The latest heartbeat, if any.
+    pub heartbeat: Option<Heartbeat>,
+    pub first_heartbeat_at: Option<DateTime<Utc>>,
+    pub last_heartbeat_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct GetHostInventoryResponse {
+    /// Every host in services.json, by ID.
+    pub hosts: Vec<HostInventory>,
+}
+
+async fn get_inventory(
+    State(state): State<AntZookeeperState>,
+    claims: BearerClaims,
+) -> Result<impl IntoResponse, AntZookeeperError> {
+    claims.require(ClientRole::Viewer)?;
+
+    let heartbeats = state.db.list_host_heartbeats().await?;
+    let unhealthy = unhealthy_hosts(&state).await?;
+
+    let mut hosts = state
+        .services
+        .hosts
+        .iter()
+        .map(|(host_id, host)| -> Result<HostInventory, anyhow::Error> {
+            let heartbeat = heartbeats.iter().find(|h| h.host_id == *host_id);
+
+            Ok(HostInventory {
+                host_id: host_id.clone(),
+                architecture: host.architecture.clone(),
+                ineligible_for_deployments: host.ineligible_for_deployments(),
+                health: match heartbeat {
+                    None => HostHealth::Unknown,
+                    Some(_) if unhealthy.contains(host_id) => HostHealth::Unhealthy,
+                    Some(_) => HostHealth::Healthy,
+                },
+                heartbeat: heartbeat
+                    .map(|h| -> Result<Heartbeat, anyhow::Error> {
+                        Ok(Heartbeat {
+                            host_id: h.host_id.clone(),
+                            uptime_seconds: h.uptime_seconds as u64,
+                            disk_total_bytes: h.disk_total_bytes as u64,
+                            disk_available_bytes: h.disk_available_bytes as u64,
+                            memory_total_bytes: h.memory_total_bytes as u64,
+                            memory_available_bytes: h.memory_available_bytes as u64,
+                            services: serde_json::from_str(&h.services)?,
+                            units: serde_json::from_str(&h.units)?,
+                        })
+                    })
+                    .transpose()?,
+                first_heartbeat_at: heartbeat.map(|h| h.first_heartbeat_at),
+                last_heartbeat_at: heartbeat.map(|h| h.last_heartbeat_at),
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    hosts.sort_by(|a, b| a.host_id.cmp(&b.host_id));
+
+    Ok((StatusCode::OK, Json(GetHostInventoryResponse { hosts })))
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct GetServiceDriftResponse {
+    /// Every service deployed to a host, as of the last check, by host and service.
+    pub drift: Vec<ServiceDrift>,
+}
+
+/// Whether each host runs the versions last deployed there, see `drift`.
+async fn get_drift(
+    State(state): State<AntZookeeperState>,
+    claims: BearerClaims,
+) -> Result<impl IntoResponse, AntZookeeperError> {
+    claims.require(ClientRole::Viewer)?;
+
+    let drift = state.db.list_service_drift().await?;
+
+    Ok((StatusCode::OK, Json(GetServiceDriftResponse { drift })))
+}
+
+pub fn routes() -> Routes<AntZookeeperState> {
+    Routes::new()
+        .post("/heartbeat", post(post_heartbeat))
+        .get("/inventory", get(get_inventory))
+        .get("/drift", get(get_drift))
+}
@@ -0,0 +1,30 @@
+use ant_library::routes::Routes;
+use ant_zookeeper_db::ClientRole;
+use axum::{extract::State, routing::get};
+use http::StatusCode;
+
+use crate::{auth::BearerClaims, cert, drift, err::AntZookeeperError, state::AntZookeeperState};
+
+async fn get_metrics(
+    State(state): State<AntZookeeperState>,
+    claims: BearerClaims,
+) -> Result<(StatusCode, String), AntZookeeperError> {
+    claims.require(ClientRole::Viewer)?;
+
+    let certificates = state.db.list_certificates().await?;
+    let service_drift = state.db.list_service_drift().await?;
+
+    Ok((
+        StatusCode::OK,
+        format!(
+            "{}{}{}",
+            state.driver_metrics.render(),
+            cert::renewer::render_metrics(&certificates),
+            drift::render_metrics(&service_drift)
+        ),
+    ))
+}
+
+pub fn routes() -> Routes<AntZookeeperState> {
+    Routes::new().get("/metrics", get(get_metrics))
+}
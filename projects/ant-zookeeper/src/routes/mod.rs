@@ -1,5 +1,10 @@
+pub mod artifact;
+pub mod audit;
 pub mod cert;
 pub mod deployment;
+pub mod host;
+pub mod metrics;
 pub mod pipeline;
 pub mod projects;
+pub mod secret;
 pub mod service;
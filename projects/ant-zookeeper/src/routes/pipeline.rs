@@ -1,19 +1,37 @@
-use std::collections::{HashMap, HashSet};
+use std::{
+    collections::{HashMap, HashSet},
+    convert::Infallible,
+};
 
 use ant_library::host_architecture::HostArchitecture;
 use ant_library::routes::Routes;
-use ant_zookeeper_db::HostGroup;
+use ant_zookeeper_db::{ClientRole, HostGroup};
 use axum::{
     extract::{Query, State},
+    response::{
+        sse::{self, KeepAlive, Sse},
+        IntoResponse,
+    },
     routing::{delete, get, post},
     Json,
 };
 use chrono::{DateTime, Utc};
-use http::StatusCode;
+use futures::Stream;
+use http::{header, HeaderMap, StatusCode};
 use serde::{Deserialize, Serialize};
 use tracing::info;
 
-use crate::{err::AntZookeeperError, event_loop::transition::Event, state::AntZookeeperState};
+use crate::{
+    auth::BearerClaims,
+    err::AntZookeeperError,
+    event_loop::transition::Event,
+    pipeline::{
+        graph::{GraphFormat, PipelineGraph},
+        transitions,
+    },
+    pipeline_engine::engine::TransitionScope,
+    state::AntZookeeperState,
+};
 
 #[derive(Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -194,8 +212,11 @@ async fn revision_progress(
 
 async fn get_pipeline(
     State(state): State<AntZookeeperState>,
+    claims: BearerClaims,
     Query(req): Query<GetPipelineRequest>,
 ) -> Result<(StatusCode, Json<GetPipelineResponse>), AntZookeeperError> {
+    claims.require(ClientRole::Viewer)?;
+
     let pipeline_id = match state.db.get_deployment_pipeline_by_name(&req.name).await? {
         None => {
             return Err(AntZookeeperError::ValidationError(format!(
@@ -445,8 +466,12 @@ pub struct PutPipelineResponse {
 
 async fn put_pipeline(
     State(state): State<AntZookeeperState>,
+    claims: BearerClaims,
     Json(req): Json<PutPipelineRequest>,
 ) -> Result<(StatusCode, Json<PutPipelineResponse>), AntZookeeperError> {
+    claims.require(ClientRole::Admin)?;
+    claims.audit(&state, "put-pipeline", &req).await?;
+
     let pipeline_id = match state.db.get_deployment_pipeline_by_name(&req.name).await? {
         None => state.db.create_deployment_pipeline(&req.name).await?,
         Some(pipeline_id) => pipeline_id,
@@ -584,8 +609,11 @@ pub struct GetHostGroupResponse {
 
 async fn get_host_group(
     State(state): State<AntZookeeperState>,
+    claims: BearerClaims,
     Query(req): Query<GetHostGroupRequest>,
 ) -> Result<(StatusCode, Json<GetHostGroupResponse>), AntZookeeperError> {
+    claims.require(ClientRole::Viewer)?;
+
     match state.db.get_host_group_by_name(&req.name).await? {
         None => {
             return Err(AntZookeeperError::ValidationError(format!(
@@ -619,8 +647,12 @@ pub struct CreateHostGroupResponse {
 
 async fn create_host_group(
     State(state): State<AntZookeeperState>,
+    claims: BearerClaims,
     Json(req): Json<CreateHostGroupRequest>,
 ) -> Result<(StatusCode, Json<CreateHostGroupResponse>), AntZookeeperError> {
+    claims.require(ClientRole::Admin)?;
+    claims.audit(&state, "create-host-group", &req).await?;
+
     if state.db.get_host_group_by_name(&req.name).await?.is_some() {
         return Err(AntZookeeperError::validation_msg(
             "Host group with that name already exists.",
@@ -669,8 +701,12 @@ pub struct AddHostToHostGroupResponse {
 
 async fn add_host_to_host_group(
     State(state): State<AntZookeeperState>,
+    claims: BearerClaims,
     Json(req): Json<AddHostToHostGroupRequest>,
 ) -> Result<(StatusCode, Json<AddHostToHostGroupResponse>), AntZookeeperError> {
+    claims.require(ClientRole::Admin)?;
+    claims.audit(&state, "add-host-to-host-group", &req).await?;
+
     if !state.db.host_group_exists_by_id(&req.host_group_id).await? {
         return Err(AntZookeeperError::validation_msg("No such host group."));
     }
@@ -723,8 +759,14 @@ pub struct RemoveHostFromHostGroupResponse {
 
 async fn remove_host_from_host_group(
     State(state): State<AntZookeeperState>,
+    claims: BearerClaims,
     Json(req): Json<RemoveHostFromHostGroupRequest>,
 ) -> Result<(StatusCode, Json<RemoveHostFromHostGroupResponse>), AntZookeeperError> {
+    claims.require(ClientRole::Admin)?;
+    claims
+        .audit(&state, "remove-host-from-host-group", &req)
+        .await?;
+
     if !state.db.host_group_exists_by_id(&req.host_group_id).await? {
         return Err(AntZookeeperError::validation_msg("No such host group."));
     }
@@ -752,7 +794,10 @@ pub struct ListPipelinesResponse {
 
 async fn list_pipelines(
     State(state): State<AntZookeeperState>,
+    claims: BearerClaims,
 ) -> Result<(StatusCode, Json<ListPipelinesResponse>), AntZookeeperError> {
+    claims.require(ClientRole::Viewer)?;
+
     let pipelines = state
         .db
         .list_deployment_pipelines()
@@ -769,6 +814,90 @@ async fn list_pipelines(
     ))
 }
 
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StreamTransitionsRequest {
+    /// Stream the transitions of all pipelines of this project, or...
+    pub project: Option<String>,
+    /// ...of only this pipeline.
+    pub pipeline_id: Option<String>,
+    /// Resume after this event ID. The `Last-Event-ID` header takes precedence.
+    pub last_event_id: Option<i64>,
+}
+
+/// Server-sent events of the transitions of nodes and pipelines, from the start of the history
+/// unless resuming. The data of each `transition` event is a `TransitionEvent`.
+async fn stream_transitions(
+    State(state): State<AntZookeeperState>,
+    claims: BearerClaims,
+    headers: HeaderMap,
+    Query(req): Query<StreamTransitionsRequest>,
+) -> Result<Sse<impl Stream<Item = Result<sse::Event, Infallible>>>, AntZookeeperError> {
+    claims.require(ClientRole::Viewer)?;
+
+    let scope = match (req.project, req.pipeline_id) {
+        (Some(project), None) => {
+            if !state.db.get_project(&project).await? {
+                return Err(AntZookeeperError::ResourceNotFound(project));
+            }
+            TransitionScope::Project(project)
+        }
+        (None, Some(pipeline_id)) => {
+            if state.engine.pipeline(&pipeline_id).await?.is_none() {
+                return Err(AntZookeeperError::ResourceNotFound(pipeline_id));
+            }
+            TransitionScope::Pipeline(pipeline_id)
+        }
+        _ => {
+            return Err(AntZookeeperError::validation_msg(
+                "Exactly one of project and pipelineId is required.",
+            ))
+        }
+    };
+
+    let after_seq = match headers.get("last-event-id") {
+        Some(id) => id
+            .to_str()
+            .ok()
+            .and_then(|id| id.parse::<i64>().ok())
+            .ok_or_else(|| AntZookeeperError::validation_msg("Last-Event-ID must be a number."))?,
+        None => req.last_event_id.unwrap_or(0),
+    };
+
+    Ok(Sse::new(transitions::stream(state, scope, after_seq)).keep_alive(KeepAlive::default()))
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GetPipelineGraphRequest {
+    pub pipeline_id: String,
+    pub format: GraphFormat,
+}
+
+/// The DAG of a pipeline engine pipeline, rendered in the format, see `pipeline::graph`.
+async fn get_pipeline_graph(
+    State(state): State<AntZookeeperState>,
+    claims: BearerClaims,
+    Query(req): Query<GetPipelineGraphRequest>,
+) -> Result<impl IntoResponse, AntZookeeperError> {
+    claims.require(ClientRole::Viewer)?;
+
+    let Some(graph) = PipelineGraph::load(&state.engine, &req.pipeline_id).await? else {
+        return Err(AntZookeeperError::ResourceNotFound(req.pipeline_id));
+    };
+
+    let content_type = match req.format {
+        GraphFormat::Dot => "text/vnd.graphviz; charset=utf-8",
+        GraphFormat::Mermaid | GraphFormat::Terminal => "text/plain; charset=utf-8",
+    };
+
+    Ok((
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, content_type)],
+        graph.render(req.format),
+    ))
+}
+
 pub fn routes() -> Routes<AntZookeeperState> {
     Routes::new()
         .get("/host-group/host-group", get(get_host_group))
@@ -778,4 +907,6 @@ pub fn routes() -> Routes<AntZookeeperState> {
         .get("/pipeline", get(get_pipeline))
         .post("/pipeline", post(put_pipeline))
         .get("/pipelines", get(list_pipelines))
+        .get("/transitions", get(stream_transitions))
+        .get("/graph", get(get_pipeline_graph))
 }
@@ -1,10 +1,11 @@
 use ant_library::routes::Routes;
+use ant_zookeeper_db::ClientRole;
 use axum::{extract::State, routing::get, Json};
 use chrono::{DateTime, Utc};
 use http::StatusCode;
 use serde::{Deserialize, Serialize};
 
-use crate::{err::AntZookeeperError, state::AntZookeeperState};
+use crate::{auth::BearerClaims, err::AntZookeeperError, state::AntZookeeperState};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -85,8 +86,11 @@ pub struct ProjectDeploymentNode {
 
 async fn get_project_deployments(
     State(state): State<AntZookeeperState>,
+    claims: BearerClaims,
     axum::extract::Path(project_id): axum::extract::Path<String>,
 ) -> Result<(StatusCode, Json<ProjectDeploymentView>), AntZookeeperError> {
+    claims.require(ClientRole::Viewer)?;
+
     let engine = &state.engine;
 
     let build = {
@@ -0,0 +1,158 @@
+use ant_library::routes::Routes;
+use ant_zookeeper_db::{ClientRole, Secret};
+use axum::{
+    extract::State,
+    response::IntoResponse,
+    routing::{get, post, put},
+    Json,
+};
+use http::StatusCode;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::{
+    auth::BearerClaims,
+    err::AntZookeeperError,
+    secrets::{put_secret, redeploy_secret_consumers, secret_consumers, DEFAULT_VALID_FOR},
+    state::AntZookeeperState,
+};
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PutSecretRequest {
+    pub name: String,
+    pub environment: String,
+    /// For host-specific secrets, the host the value is for.
+    pub host: Option<String>,
+    pub value: String,
+    /// Defaults to 10 years.
+    pub valid_for_seconds: Option<i32>,
+}
+
+impl PutSecretRequest {
+    /// What is recorded in the audit log, everything but the value.
+    fn audited(&self) -> serde_json::Value {
+        serde_json::json!({
+            "name": self.name,
+            "environment": self.environment,
+            "host": self.host,
+            "validForSeconds": self.valid_for_seconds,
+        })
+    }
+
+    fn validate(&self) -> Result<(), AntZookeeperError> {
+        let is_valid_name = !self.name.is_empty()
+            && self
+                .name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+        if !is_valid_name {
+            return Err(AntZookeeperError::validation_msg(
+                "Secret names are letters, digits, '_' and '-'.",
+            ));
+        }
+        if self.valid_for_seconds.is_some_and(|s| s <= 0) {
+            return Err(AntZookeeperError::validation_msg(
+                "Secrets must be valid for a positive number of seconds.",
+            ));
+        }
+
+        Ok(())
+    }
+
+    async fn put(&self, state: &AntZookeeperState) -> Result<i32, anyhow::Error> {
+        put_secret(
+            state,
+            &self.name,
+            &self.environment,
+            self.host.as_deref(),
+            self.value.as_bytes(),
+            self.valid_for_seconds
+                .map(|s| chrono::Duration::seconds(s as i64))
+                .unwrap_or(DEFAULT_VALID_FOR),
+        )
+        .await
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PutSecretResponse {
+    pub version: i32,
+    /// The pipelines redeploying the projects using the secret, when rotating it.
+    pub pipelines: Vec<String>,
+}
+
+/// Register the next version of a secret, which revisions deploy with from now on. Revisions that
+/// were already deployed keep the version they were deployed with.
+async fn set_secret(
+    State(state): State<AntZookeeperState>,
+    claims: BearerClaims,
+    Json(req): Json<PutSecretRequest>,
+) -> Result<impl IntoResponse, AntZookeeperError> {
+    claims.require(ClientRole::Admin)?;
+    claims.audit(&state, "set-secret", &req.audited()).await?;
+    req.validate()?;
+
+    let version = req.put(&state).await?;
+    info!("Set {} v{version} in {}.", req.name, req.environment);
+
+    Ok((
+        StatusCode::OK,
+        Json(PutSecretResponse {
+            version,
+            pipelines: vec![],
+        }),
+    ))
+}
+
+/// Register the next version of a secret, and redeploy every project using it so they pick it up.
+async fn rotate_secret(
+    State(state): State<AntZookeeperState>,
+    claims: BearerClaims,
+    Json(req): Json<PutSecretRequest>,
+) -> Result<impl IntoResponse, AntZookeeperError> {
+    claims.require(ClientRole::Admin)?;
+    claims
+        .audit(&state, "rotate-secret", &req.audited())
+        .await?;
+    req.validate()?;
+
+    let consumers = secret_consumers(&state, &req.name, &req.environment).await?;
+    let version = req.put(&state).await?;
+    let pipelines = redeploy_secret_consumers(&state, &req.name, consumers).await?;
+    info!(
+        "Rotated {} to v{version} in {}, redeploying in {pipelines:?}.",
+        req.name, req.environment
+    );
+
+    Ok((
+        StatusCode::OK,
+        Json(PutSecretResponse { version, pipelines }),
+    ))
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListSecretsResponse {
+    /// Every version of every secret, without their values.
+    pub secrets: Vec<Secret>,
+}
+
+async fn list_secrets(
+    State(state): State<AntZookeeperState>,
+    claims: BearerClaims,
+) -> Result<impl IntoResponse, AntZookeeperError> {
+    claims.require(ClientRole::Viewer)?;
+
+    let secrets = state.db.list_secrets().await?;
+
+    Ok((StatusCode::OK, Json(ListSecretsResponse { secrets })))
+}
+
+pub fn routes() -> Routes<AntZookeeperState> {
+    Routes::new()
+        .put("/secret", put(set_secret))
+        .post("/rotate", post(rotate_secret))
+        .get("/secrets", get(list_secrets))
+}
@@ -1,5 +1,4 @@
 use std::collections::HashSet;
-use std::fs::exists;
 use std::io::Read;
 use std::path::PathBuf;
 use std::{fs::File, io::Write};
@@ -8,7 +7,9 @@ use ant_library::headers::{
     XAntArchitectureHeader, XAntProjectHeader, XAntRevisionHeader, XAntVersionHeader,
 };
 use ant_library::host_architecture::HostArchitecture;
+use ant_library::provenance::SignedProvenance;
 use ant_library::routes::Routes;
+use ant_zookeeper_db::ClientRole;
 use anthill_manifest::{AnthillManifest, AnthillSecret};
 use axum::debug_handler;
 use axum::{
@@ -29,10 +30,11 @@ use tracing::{info, warn};
 
 use crate::event_loop::transition::{is_deployment_complete, DeploymentEvent, Event};
 use crate::fs::{
-    artifact_file_name, artifact_persist_dir, envs_persist_dir, host_specific_secret_file_path,
-    project_envs_file_name, secret_file_path,
+    artifact_persist_dir, artifact_store_path, envs_persist_dir, project_envs_file_name,
 };
-use crate::{err::AntZookeeperError, state::AntZookeeperState};
+use crate::inventory::unhealthy_hosts;
+use crate::pipeline::dag::{build_dag, ProjectConfig};
+use crate::{auth::BearerClaims, err::AntZookeeperError, state::AntZookeeperState};
 
 #[derive(Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -54,8 +56,12 @@ pub struct RegisterServiceRequest {
 /// Register a new service if not already done
 async fn register_service(
     State(state): State<AntZookeeperState>,
+    claims: BearerClaims,
     Json(req): Json<RegisterServiceRequest>,
 ) -> Result<impl IntoResponse, AntZookeeperError> {
+    claims.require(ClientRole::Admin)?;
+    claims.audit(&state, "register-service", &req).await?;
+
     if state.db.get_project(&req.project).await? {
         return Ok((
             StatusCode::BAD_REQUEST,
@@ -121,52 +127,41 @@ WantedBy=multi-user.target
     )
 }
 
-fn validate_secret_exists(
+/// Every environment and host the service runs in must have a version of the secret.
+async fn validate_secret_exists(
     state: &AntZookeeperState,
     service_id: &str,
     secret: &AnthillSecret,
 ) -> Result<(), AntZookeeperError> {
-    let hosts = state.services.list_hosts_with_service(service_id);
-    let environments = hosts
-        .iter()
-        .map(|(_, s)| s.env.to_string())
-        .collect::<HashSet<_>>();
-
-    for environment in &environments {
-        if secret.is_host_specific() {
-            for (host_id, _) in hosts.iter() {
-                let secret_file = host_specific_secret_file_path(
-                    &state.root_dir,
-                    environment,
-                    secret.name(),
-                    &host_id,
-                );
-                if !exists(&secret_file)? {
-                    return Err(AntZookeeperError::validation_msg(
-                        format!(
-                            "Secret '{}' is not present in '{}' for '{}' at: {}",
-                            secret.name(),
-                            environment,
-                            host_id,
-                            secret_file.display()
-                        )
-                        .as_str(),
-                    ));
-                }
-            }
-        } else {
-            let secret_file = secret_file_path(&state.root_dir, environment, secret.name());
-            if !exists(&secret_file)? {
-                return Err(AntZookeeperError::validation_msg(
-                    format!(
-                        "Secret '{}' is not present in '{}' at: {}",
-                        secret.name(),
-                        environment,
-                        secret_file.display()
-                    )
-                    .as_str(),
-                ));
-            }
+    let mut required = state
+        .services
+        .list_hosts_with_service(service_id)
+        .into_iter()
+        .map(|(host_id, s)| {
+            (
+                s.env.to_string(),
+                secret.is_host_specific().then(|| host_id.to_string()),
+            )
+        })
+        .collect::<Vec<_>>();
+    required.sort();
+    required.dedup();
+
+    for (environment, host_id) in required {
+        if state
+            .db
+            .get_secret(secret.name(), &environment, host_id.as_deref(), None)
+            .await?
+            .is_none()
+        {
+            let location = match &host_id {
+                Some(host_id) => format!("'{environment}' for '{host_id}'"),
+                None => format!("'{environment}'"),
+            };
+            return Err(AntZookeeperError::validation_msg(&format!(
+                "Secret '{}' is not present in {location}, set it with PUT /secret/secret",
+                secret.name(),
+            )));
         }
     }
 
@@ -177,15 +172,30 @@ fn validate_secret_exists(
 #[debug_handler]
 async fn register_artifact(
     State(state): State<AntZookeeperState>,
+    claims: BearerClaims,
     TypedHeader(revision): TypedHeader<XAntRevisionHeader>,
     TypedHeader(project): TypedHeader<XAntProjectHeader>,
     TypedHeader(arch): TypedHeader<XAntArchitectureHeader>,
     TypedHeader(version): TypedHeader<XAntVersionHeader>,
     mut multipart: Multipart,
 ) -> Result<impl IntoResponse, AntZookeeperError> {
+    claims.require(ClientRole::Ci)?;
+
     let project_id: &String = project.0.as_ref().ok_or(AntZookeeperError::validation_msg(
         "The X-Ant-Project header must be specified.",
     ))?;
+    claims
+        .audit(
+            &state,
+            "register-artifact",
+            &serde_json::json!({
+                "revision": revision.0,
+                "project": project_id,
+                "arch": arch.0,
+                "version": version.0,
+            }),
+        )
+        .await?;
 
     // VALIDATIONS
     {
@@ -223,25 +233,48 @@ async fn register_artifact(
     let temp_file_path = temp_dir.path().join("input.tar.gz");
     let mut temp_file = File::create_new(&temp_file_path)?;
 
+    // The tarball, and the provenance statement its builder signed for it.
+    let mut provenance: Option<SignedProvenance> = None;
     {
-        let mut field = multipart
-            .next_field()
-            .await
-            .map_err(|e| {
+        let mut file_found = false;
+        loop {
+            let mut field = match multipart.next_field().await.map_err(|e| {
                 warn!("ANT-ERR-084: No field in multipart: {e}");
                 AntZookeeperError::validation_msg("No field found in multipart request!")
-            })?
-            .ok_or(AntZookeeperError::validation_msg(
-                "No bytes field found in request!",
-            ))?;
+            })? {
+                None => break,
+                Some(field) => field,
+            };
 
-        while let Some(bytes) = field.chunk().await.unwrap() {
-            info!(
-                "Wrote [{}] to [{}]...",
-                humansize::format_size(bytes.len(), DECIMAL),
-                temp_file_path.display()
-            );
-            temp_file.write_all(&bytes)?;
+            if field.name() == Some("provenance") {
+                let text = field.text().await.map_err(|e| {
+                    AntZookeeperError::validation_msg(&format!(
+                        "Provenance statement could not be read: {e}"
+                    ))
+                })?;
+                provenance = Some(serde_json::from_str(&text).map_err(|e| {
+                    AntZookeeperError::validation_msg(&format!(
+                        "Provenance statement was malformed: {e}"
+                    ))
+                })?);
+                continue;
+            }
+
+            file_found = true;
+            while let Some(bytes) = field.chunk().await.unwrap() {
+                info!(
+                    "Wrote [{}] to [{}]...",
+                    humansize::format_size(bytes.len(), DECIMAL),
+                    temp_file_path.display()
+                );
+                temp_file.write_all(&bytes)?;
+            }
+        }
+
+        if !file_found {
+            return Err(AntZookeeperError::validation_msg(
+                "No bytes field found in request!",
+            ));
         }
         temp_file.flush()?;
         info!("Finished writing to [{}]...", temp_file_path.display());
@@ -252,6 +285,7 @@ async fn register_artifact(
         temp_file_path.display()
     );
 
+    let mut anthill_manifest = None;
     {
         std::io::Seek::seek(&mut temp_file, std::io::SeekFrom::Start(0))?;
         let gz = GzDecoder::new(&temp_file);
@@ -297,10 +331,13 @@ async fn register_artifact(
                     })?;
 
                 info!("Read manifest: {:?}", manifest);
+                manifest.validate().map_err(|e| {
+                    AntZookeeperError::validation_msg(&format!(
+                        "Project configuration file 'anthill.json' was invalid: {e}"
+                    ))
+                })?;
 
-                for secret in manifest.secrets {
-                    validate_secret_exists(&state, &project_id, &secret)?;
-                }
+                anthill_manifest = Some(manifest);
             }
         }
 
@@ -311,17 +348,61 @@ async fn register_artifact(
         }
     }
 
-    // Write the file to final location
-    let filepath = dir.join(artifact_file_name(&project_id, arch.0.as_ref(), &version.0));
+    if let Some(manifest) = &anthill_manifest {
+        for secret in &manifest.secrets {
+            validate_secret_exists(&state, &project_id, secret).await?;
+        }
+    }
+
+    let fingerprint = sha256::try_digest(&temp_file_path)?;
+    let size_bytes = tokio::fs::metadata(&temp_file_path).await?.len() as i64;
+
+    // Only artifacts signed by a trusted builder, for exactly this content, are registered.
+    let provenance = provenance.ok_or(AntZookeeperError::validation_msg(
+        "Artifacts need a signed provenance statement, in the 'provenance' field.",
+    ))?;
     {
+        let architecture = arch.0.as_ref().map(|a| a.as_str()).unwrap_or("noarch");
+        let verified = state
+            .trusted_builders
+            .verify_artifact(&provenance, &project_id, &version.0, &fingerprint)
+            .and_then(|p| match p.architecture == architecture {
+                true => Ok(p),
+                false => Err(anyhow::Error::msg(format!(
+                    "provenance is for architecture {}, not {architecture}",
+                    p.architecture
+                ))),
+            })
+            .map_err(|e| {
+                warn!("ANT-ERR-195: Untrusted artifact of [{project_id}] rejected: {e}");
+                AntZookeeperError::validation_msg(&format!(
+                    "Provenance of the artifact could not be verified: {e}"
+                ))
+            })?;
+        info!(
+            "Artifact built from commit [{}] by [{}], signed by [{}]",
+            verified.commit, verified.builder, provenance.key_id
+        );
+    }
+    let provenance = serde_json::to_string(&provenance)?;
+
+    // Store the file by its content, unless the same build is already stored.
+    let relative_path = artifact_store_path(&fingerprint);
+    let filepath = dir.join(&relative_path);
+    if tokio::fs::try_exists(&filepath).await? {
+        info!("Tarball already stored at [{}]", filepath.display());
+        // Touched, so garbage collection leaves it alone until this artifact is registered.
+        std::fs::File::options()
+            .write(true)
+            .open(&filepath)?
+            .set_modified(std::time::SystemTime::now())?;
+    } else {
         info!("Writing tarball to [{}]", filepath.display());
-        tokio::fs::copy(&temp_dir.path().join("input.tar.gz"), &filepath).await?;
+        create_dir_all(filepath.parent().expect("artifacts are in a directory")).await?;
+        // Renamed into place, so the store never has a partial file under a fingerprint.
+        tokio::fs::rename(&temp_file_path, &filepath).await?;
     }
 
-    let file_metadata = tokio::fs::metadata(&filepath).await?;
-    let size_bytes = file_metadata.len() as i64;
-    let fingerprint = sha256::try_digest(&filepath)?;
-
     // let (revision_id, is_new) = state.db.upsert_revision(&version.0).await?;
 
     // {
@@ -377,24 +458,22 @@ async fn register_artifact(
         .db
         .get_artifact_by_revision(&revision.0, &project_id, arch.0.as_ref())
         .await?;
-    let relative_path = filepath.strip_prefix(&dir).expect(&format!(
-        "[{}] was not parent of [{}]",
-        dir.display(),
-        filepath.display()
-    ));
 
     match artifact_id {
-        Some((artifact_id, _, previous_relative_path)) => {
-            if previous_relative_path != relative_path {
-                let path = dir.join(previous_relative_path);
-                info!("Deleting old artifact: {}", path.display());
-                tokio::fs::remove_file(&path).await?;
-            }
-
+        Some((artifact_id, _, _)) => {
+            // The file of the previous upload stays, other revisions may share it. Garbage
+            // collection removes it once nothing references it.
             info!("Updating existing artifact");
             state
                 .db
-                .update_artifact(&artifact_id, &version.0, &relative_path)
+                .update_artifact(
+                    &artifact_id,
+                    &version.0,
+                    &relative_path,
+                    size_bytes,
+                    &fingerprint,
+                    &provenance,
+                )
                 .await?;
         }
         None => {
@@ -409,6 +488,7 @@ async fn register_artifact(
                     &relative_path,
                     size_bytes,
                     &fingerprint,
+                    &provenance,
                 )
                 .await?;
         }
@@ -440,33 +520,17 @@ async fn register_artifact(
 
         let should_revision_activate = all_on_same_version && all_architectures_present;
         if should_revision_activate {
+            let config = ProjectConfig::from_manifest(
+                &project_id,
+                anthill_manifest.as_ref().expect("anthill.json was found"),
+                &state.services,
+            )
+            .excluding_hosts(&unhealthy_hosts(&state).await?)?;
+
             info!("Activating revision: {}", &revision.0);
             state.db.activate_revision(&revision.0).await?;
 
-            let hosts = state.services.list_hosts_with_service(&project_id);
-            let beta_hosts: Vec<String> = hosts
-                .iter()
-                .filter(|(_, s)| matches!(s.env, ant_library::services::ServiceEnv::Beta))
-                .map(|(h, _)| h.to_string())
-                .collect();
-            let prod_hosts: Vec<String> = hosts
-                .iter()
-                .filter(|(_, s)| matches!(s.env, ant_library::services::ServiceEnv::Prod))
-                .map(|(h, _)| h.to_string())
-                .collect();
-
-            let config = crate::pipeline::dag::ProjectConfig {
-                project_id: project_id.clone(),
-                has_database: false,
-                has_routes: false,
-                has_alerts: false,
-                has_log_rules: false,
-                beta_hosts,
-                prod_hosts,
-            };
-
-            let pipeline_id =
-                crate::pipeline::dag::build_dag(&state.engine, &revision.0, &config).await?;
+            let pipeline_id = build_dag(&state.engine, &revision.0, &config).await?;
 
             info!("Created deployment pipeline: {pipeline_id}");
         } else {
@@ -496,8 +560,23 @@ pub struct PutProjectEnvironmentRequest {
 
 async fn put_project_environment(
     State(state): State<AntZookeeperState>,
+    claims: BearerClaims,
     Json(req): Json<PutProjectEnvironmentRequest>,
 ) -> Result<impl IntoResponse, AntZookeeperError> {
+    claims.require(ClientRole::Admin)?;
+    // The values may be secret, only record which variables changed.
+    claims
+        .audit(
+            &state,
+            "put-project-environment",
+            &serde_json::json!({
+                "project": req.project,
+                "environment": req.environment,
+                "keys": req.variables.iter().map(|v| &v.key).collect::<Vec<_>>(),
+            }),
+        )
+        .await?;
+
     create_dir_all(envs_persist_dir(&state.root_dir)).await?;
     let envs_file_path = envs_persist_dir(&state.root_dir)
         .join(project_envs_file_name(&req.project, &req.environment));
@@ -547,8 +626,12 @@ pub struct UpsertRevisionResponse {
 }
 async fn upsert_revision(
     State(state): State<AntZookeeperState>,
+    claims: BearerClaims,
     Json(req): Json<UpsertRevisionRequest>,
 ) -> Result<impl IntoResponse, AntZookeeperError> {
+    claims.require(ClientRole::Ci)?;
+    claims.audit(&state, "upsert-revision", &req).await?;
+
     if !state.db.get_project(&req.project).await? {
         return Err(AntZookeeperError::ResourceNotFound(
             "No such project".to_string(),
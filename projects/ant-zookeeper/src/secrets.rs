@@ -0,0 +1,493 @@
+//! The secrets of the environments, as versions in the `secret` table encrypted under the master
+//! key of ant-zookeeper.
+//!
+//! Each revision deploys with the version of every secret it was first deployed with, pinned in
+//! `revision_secret`, so retrying or unwinding to a revision deploys the same values. Rotating a
+//! secret registers its next version and redeploys the projects using it in a new revision.
+
+use std::path::{Path, PathBuf};
+
+use ant_zookeeper_db::{AntZooStorageClient, EncryptedSecret, Secret};
+use base64ct::{Base64, Encoding};
+use openssl::symm::{decrypt_aead, encrypt_aead, Cipher};
+use tracing::info;
+
+use crate::{
+    err::AntZookeeperError,
+    fs::{read_artifact_manifest, revision_artifact_path},
+    inventory::unhealthy_hosts,
+    pipeline::dag::{build_dag, ProjectConfig},
+    state::AntZookeeperState,
+};
+
+const NONCE_LEN: usize = 12;
+const TAG_LEN: usize = 16;
+
+/// How long secrets are valid for when not said otherwise.
+pub const DEFAULT_VALID_FOR: chrono::Duration = chrono::Duration::days(365 * 10);
+
+/// Encrypts and decrypts the values of secrets with AES-256-GCM.
+pub struct SecretCipher {
+    key: [u8; 32],
+}
+
+impl SecretCipher {
+    pub fn new(key: [u8; 32]) -> Self {
+        SecretCipher { key }
+    }
+
+    /// The master key as 32 bytes of standard base64, e.g. from `openssl rand -base64 32`.
+    pub fn from_base64(encoded: &str) -> Result<Self, anyhow::Error> {
+        let key = Base64::decode_vec(encoded.trim())
+            .map_err(|e| anyhow::anyhow!("master key is not base64: {e}"))?;
+        let key: [u8; 32] = key
+            .try_into()
+            .map_err(|key: Vec<u8>| anyhow::anyhow!("master key is {} bytes, not 32", key.len()))?;
+
+        Ok(SecretCipher::new(key))
+    }
+
+    /// A cipher with a random key, for tests.
+    pub fn generate() -> Result<Self, anyhow::Error> {
+        let mut key = [0u8; 32];
+        openssl::rand::rand_bytes(&mut key)?;
+        Ok(SecretCipher::new(key))
+    }
+
+    /// Returns the (nonce, ciphertext followed by the tag). The `associated_data` is not
+    /// encrypted, but the value only decrypts with the same.
+    fn encrypt(
+        &self,
+        associated_data: &[u8],
+        value: &[u8],
+    ) -> Result<(Vec<u8>, Vec<u8>), anyhow::Error> {
+        let mut nonce = vec![0u8; NONCE_LEN];
+        openssl::rand::rand_bytes(&mut nonce)?;
+
+        let mut tag = [0u8; TAG_LEN];
+        let mut ciphertext = encrypt_aead(
+            Cipher::aes_256_gcm(),
+            &self.key,
+            Some(&nonce),
+            associated_data,
+            value,
+            &mut tag,
+        )?;
+        ciphertext.extend_from_slice(&tag);
+
+        Ok((nonce, ciphertext))
+    }
+
+    fn decrypt(
+        &self,
+        associated_data: &[u8],
+        nonce: &[u8],
+        ciphertext: &[u8],
+    ) -> Result<Vec<u8>, anyhow::Error> {
+        if ciphertext.len() < TAG_LEN {
+            return Err(anyhow::Error::msg("ciphertext shorter than its tag"));
+        }
+        let (ciphertext, tag) = ciphertext.split_at(ciphertext.len() - TAG_LEN);
+
+        Ok(decrypt_aead(
+            Cipher::aes_256_gcm(),
+            &self.key,
+            Some(nonce),
+            associated_data,
+            ciphertext,
+            tag,
+        )?)
+    }
+}
+
+/// Binds a ciphertext to the secret it is a value of, so it can't be swapped for another's.
+fn associated_data(name: &str, environment: &str, host: Option<&str>) -> Vec<u8> {
+    format!("{name}/{environment}/{}", host.unwrap_or("")).into_bytes()
+}
+
+/// The value of a version of a secret.
+pub fn decrypt_secret(
+    cipher: &SecretCipher,
+    encrypted: &EncryptedSecret,
+) -> Result<Vec<u8>, anyhow::Error> {
+    let secret = &encrypted.secret;
+    cipher
+        .decrypt(
+            &associated_data(&secret.name, &secret.environment, secret.host.as_deref()),
+            &encrypted.nonce,
+            &encrypted.value,
+        )
+        .map_err(|e| {
+            anyhow::anyhow!(
+                "failed to decrypt {} v{} in {}: {e}",
+                secret.name,
+                secret.version,
+                secret.environment
+            )
+        })
+}
+
+/// Encrypt the versions of secrets registered before secrets were encrypted, which the
+/// add-secret-store migration kept as plaintext. Returns how many were sealed.
+pub async fn seal_plaintext_secrets(
+    db: &AntZooStorageClient,
+    cipher: &SecretCipher,
+) -> Result<usize, anyhow::Error> {
+    db.seal_plaintext_secrets(|secret, plaintext| {
+        cipher.encrypt(
+            &associated_data(&secret.name, &secret.environment, secret.host.as_deref()),
+            plaintext,
+        )
+    })
+    .await
+}
+
+/// Register the next version of a secret. Returns its version.
+pub async fn put_secret(
+    state: &AntZookeeperState,
+    name: &str,
+    environment: &str,
+    host: Option<&str>,
+    value: &[u8],
+    valid_for: chrono::Duration,
+) -> Result<i32, anyhow::Error> {
+    let (nonce, ciphertext) = state
+        .secret_cipher
+        .encrypt(&associated_data(name, environment, host), value)?;
+
+    let (_, version) = state
+        .db
+        .register_new_secret_version(name, environment, host, valid_for, &nonce, &ciphertext)
+        .await?;
+
+    Ok(version)
+}
+
+/// The latest version of a secret, if it has any.
+pub async fn get_latest_secret(
+    state: &AntZookeeperState,
+    name: &str,
+    environment: &str,
+    host: Option<&str>,
+) -> Result<Option<(Secret, Vec<u8>)>, anyhow::Error> {
+    match state.db.get_secret(name, environment, host, None).await? {
+        None => Ok(None),
+        Some(encrypted) => {
+            let value = decrypt_secret(&state.secret_cipher, &encrypted)?;
+            Ok(Some((encrypted.secret, value)))
+        }
+    }
+}
+
+/// The value of a secret a revision deploys with, pinning its latest version to the revision if
+/// none was pinned yet.
+pub async fn get_revision_secret(
+    state: &AntZookeeperState,
+    revision: &str,
+    name: &str,
+    environment: &str,
+    host: Option<&str>,
+) -> Result<Vec<u8>, anyhow::Error> {
+    if let Some(pinned) = state
+        .db
+        .get_pinned_secret(revision, name, environment, host)
+        .await?
+    {
+        return decrypt_secret(&state.secret_cipher, &pinned);
+    }
+
+    let latest = state
+        .db
+        .get_secret(name, environment, host, None)
+        .await?
+        .ok_or_else(|| {
+            anyhow::anyhow!("secret {name} not found in {environment} (host {host:?})")
+        })?;
+    state
+        .db
+        .pin_revision_secret(revision, &latest.secret.secret_id)
+        .await?;
+    info!(
+        "Pinned {name} v{} in {environment} to {revision}.",
+        latest.secret.version
+    );
+
+    // Read the pin back, in case another pinned a different version first.
+    let pinned = state
+        .db
+        .get_pinned_secret(revision, name, environment, host)
+        .await?
+        .unwrap_or(latest);
+    decrypt_secret(&state.secret_cipher, &pinned)
+}
+
+/// A project deployed with a secret, to redeploy for it to pick up a new version of the secret.
+pub struct SecretConsumer {
+    project: String,
+    deployed_revision: String,
+    config: ProjectConfig,
+}
+
+/// The latest revision of every project deployed with the secret, and where to redeploy it. Fails
+/// with a validation error if a project can't be redeployed through all of its stages, so a
+/// rotation is refused before the secret is.
+pub async fn secret_consumers(
+    state: &AntZookeeperState,
+    name: &str,
+    environment: &str,
+) -> Result<Vec<SecretConsumer>, AntZookeeperError> {
+    let unhealthy = unhealthy_hosts(state).await?;
+    let mut consumers = vec![];
+
+    for project in state
+        .db
+        .list_projects_using_secret(name, environment)
+        .await?
+    {
+        let Some(deployed_revision) = state.db.get_latest_deployable_revision(&project).await?
+        else {
+            continue;
+        };
+
+        let manifest = read_artifact_manifest(
+            &revision_artifact_path(&state.db, &state.root_dir, &deployed_revision, &project)
+                .await?,
+        )?;
+
+        let config = ProjectConfig::from_manifest(&project, &manifest, &state.services)
+            .excluding_hosts(&unhealthy)?;
+        consumers.push(SecretConsumer {
+            project,
+            deployed_revision,
+            config,
+        });
+    }
+
+    Ok(consumers)
+}
+
+/// Redeploy the consumers of the secret, as a new revision of the same artifacts, for them to pick
+/// up its latest version. Returns the pipelines started.
+pub async fn redeploy_secret_consumers(
+    state: &AntZookeeperState,
+    name: &str,
+    consumers: Vec<SecretConsumer>,
+) -> Result<Vec<String>, anyhow::Error> {
+    let mut pipelines = vec![];
+
+    for SecretConsumer {
+        project,
+        deployed_revision,
+        config,
+    } in consumers
+    {
+        let revision = state.db.create_revision(&project).await?;
+        state
+            .db
+            .copy_revision_artifacts(&deployed_revision, &revision)
+            .await?;
+        state.db.activate_revision(&revision).await?;
+
+        let pipeline_id = build_dag(&state.engine, &revision, &config).await?;
+        info!("Redeploying {project} ({deployed_revision} as {revision}) in {pipeline_id} for the rotated {name}.");
+
+        pipelines.push(pipeline_id);
+    }
+
+    Ok(pipelines)
+}
+
+/// A secret in the files of a secrets-db directory.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct SecretFile {
+    name: String,
+    environment: String,
+    host: Option<String>,
+    path: PathBuf,
+}
+
+/// The secrets in the files of a secrets-db directory: `{env}/{name}.secret` and
+/// `{env}/hosts/{host}/{name}.secret`.
+fn list_secret_files(secrets_dir: &Path) -> Result<Vec<SecretFile>, anyhow::Error> {
+    let secret_name = |path: &Path| -> Option<String> {
+        let file_name = path.file_name()?.to_str()?;
+        file_name.strip_suffix(".secret").map(str::to_string)
+    };
+    let dir_name =
+        |path: &Path| -> Option<String> { Some(path.file_name()?.to_str()?.to_string()) };
+
+    let mut files = vec![];
+    if !secrets_dir.exists() {
+        return Ok(files);
+    }
+
+    for environment_dir in std::fs::read_dir(secrets_dir)? {
+        let environment_dir = environment_dir?.path();
+        let Some(environment) = dir_name(&environment_dir).filter(|_| environment_dir.is_dir())
+        else {
+            continue;
+        };
+
+        for entry in std::fs::read_dir(&environment_dir)? {
+            let path = entry?.path();
+            if let Some(name) = secret_name(&path).filter(|_| path.is_file()) {
+                files.push(SecretFile {
+                    name,
+                    environment: environment.clone(),
+                    host: None,
+                    path,
+                });
+            }
+        }
+
+        let hosts_dir = environment_dir.join("hosts");
+        if !hosts_dir.is_dir() {
+            continue;
+        }
+        for host_dir in std::fs::read_dir(&hosts_dir)? {
+            let host_dir = host_dir?.path();
+            let Some(host) = dir_name(&host_dir).filter(|_| host_dir.is_dir()) else {
+                continue;
+            };
+            for entry in std::fs::read_dir(&host_dir)? {
+                let path = entry?.path();
+                if let Some(name) = secret_name(&path).filter(|_| path.is_file()) {
+                    files.push(SecretFile {
+                        name,
+                        environment: environment.clone(),
+                        host: Some(host.clone()),
+                        path,
+                    });
+                }
+            }
+        }
+    }
+
+    files.sort();
+    Ok(files)
+}
+
+/// Import the plaintext files of `secrets-db`, where secrets used to live, as the first version of
+/// each secret that has none yet. Returns how many were imported. The files are no longer read
+/// afterwards, and can be deleted.
+pub async fn import_secret_files(state: &AntZookeeperState) -> Result<usize, anyhow::Error> {
+    let mut imported = 0;
+
+    for SecretFile {
+        name,
+        environment,
+        host,
+        path,
+    } in list_secret_files(&state.root_dir.join("secrets-db"))?
+    {
+        if state
+            .db
+            .get_secret(&name, &environment, host.as_deref(), None)
+            .await?
+            .is_some()
+        {
+            continue;
+        }
+
+        let value = tokio::fs::read(&path).await?;
+        put_secret(
+            state,
+            &name,
+            &environment,
+            host.as_deref(),
+            &value,
+            DEFAULT_VALID_FOR,
+        )
+        .await?;
+        info!(
+            "Imported secret {name} in {environment} (host {host:?}) from {}.",
+            path.display()
+        );
+        imported += 1;
+    }
+
+    Ok(imported)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{associated_data, list_secret_files, SecretCipher};
+
+    #[test]
+    fn cipher_round_trips_values() {
+        let cipher = SecretCipher::generate().unwrap();
+        let aad = associated_data("jwt", "prod", None);
+
+        let (nonce, ciphertext) = cipher.encrypt(&aad, b"secret value").unwrap();
+        assert_ne!(&ciphertext[..12], b"secret value");
+        assert_eq!(
+            cipher.decrypt(&aad, &nonce, &ciphertext).unwrap(),
+            b"secret value"
+        );
+
+        // Encrypting twice never gives the same ciphertext.
+        let (other_nonce, other_ciphertext) = cipher.encrypt(&aad, b"secret value").unwrap();
+        assert_ne!(nonce, other_nonce);
+        assert_ne!(ciphertext, other_ciphertext);
+    }
+
+    #[test]
+    fn cipher_rejects_values_of_other_secrets_and_keys() {
+        let cipher = SecretCipher::generate().unwrap();
+        let (nonce, ciphertext) = cipher
+            .encrypt(&associated_data("jwt", "prod", None), b"secret value")
+            .unwrap();
+
+        assert!(cipher
+            .decrypt(&associated_data("jwt", "beta", None), &nonce, &ciphertext)
+            .is_err());
+        assert!(cipher
+            .decrypt(
+                &associated_data("jwt", "prod", Some("antworker001")),
+                &nonce,
+                &ciphertext
+            )
+            .is_err());
+        assert!(SecretCipher::generate()
+            .unwrap()
+            .decrypt(&associated_data("jwt", "prod", None), &nonce, &ciphertext)
+            .is_err());
+    }
+
+    #[test]
+    fn cipher_reads_base64_keys() {
+        assert!(SecretCipher::from_base64("AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8=").is_ok());
+        assert!(SecretCipher::from_base64("AAECAwQFBgcICQoLDA0ODw==").is_err());
+        assert!(SecretCipher::from_base64("not base64!").is_err());
+    }
+
+    #[test]
+    fn lists_environment_and_host_secret_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let write = |path: &str| {
+            let path = dir.path().join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "value").unwrap();
+        };
+        write("prod/jwt.secret");
+        write("prod/hosts/antworker001/tls_key.secret");
+        write("beta/README.md");
+
+        let files: Vec<_> = list_secret_files(dir.path())
+            .unwrap()
+            .into_iter()
+            .map(|file| (file.name, file.environment, file.host))
+            .collect();
+        assert_eq!(
+            files,
+            vec![
+                ("jwt".to_string(), "prod".to_string(), None),
+                (
+                    "tls_key".to_string(),
+                    "prod".to_string(),
+                    Some("antworker001".to_string())
+                ),
+            ]
+        );
+    }
+}
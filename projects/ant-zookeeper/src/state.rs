@@ -1,13 +1,17 @@
 use std::{path::PathBuf, sync::Arc};
 
-use acme_lib::DirectoryUrl;
 use ant_host_agent::client::AntHostAgentClientFactory;
+use ant_library::provenance::{ProvenanceSigner, TrustedKeys};
 use ant_library::services::Services;
 use ant_zookeeper_db::AntZooStorageClient;
-use tokio::sync::Mutex;
+use tokio::sync::{broadcast, Mutex};
 
+use crate::cert::CertificateAuthority;
 use crate::dns::Dns;
+use crate::pipeline::driver::DriverMetrics;
 use crate::pipeline_engine::engine::PipelineEngine;
+use crate::probe::Prober;
+use crate::secrets::SecretCipher;
 
 #[derive(Clone)]
 pub struct AntZookeeperState {
@@ -16,13 +20,28 @@ pub struct AntZookeeperState {
     pub services: Arc<Services>,
 
     pub db: AntZooStorageClient,
+    /// Encrypts the secrets in `db` under the master key, see `secrets`.
+    pub secret_cipher: Arc<SecretCipher>,
+    /// The builder keys whose signed provenance artifacts are registered with.
+    pub trusted_builders: Arc<TrustedKeys>,
+    /// Signs the provenance of the service files replicated to hosts, which ant-host-agent checks.
+    pub provenance_signer: Arc<ProvenanceSigner>,
     pub engine: Arc<PipelineEngine>,
+    pub driver_metrics: Arc<DriverMetrics>,
+    /// Woken up whenever transitions are numbered, see `pipeline::transitions::listen`.
+    pub transitions: broadcast::Sender<()>,
 
     pub dns: Arc<Mutex<dyn Dns>>,
-    pub acme_contact_email: String,
-    pub acme_url: DirectoryUrl<'static>,
-
-    pub rng: rsa::rand_core::OsRng,
+    /// Issues the certificates of the environments, proving the domains through `dns`.
+    pub certificate_authority: Arc<dyn CertificateAuthority>,
+    /// The domain under which every host has its address records, like `antworker002.{hosts_domain}`.
+    pub hosts_domain: String,
 
     pub ant_host_agent_factory: Arc<Mutex<dyn AntHostAgentClientFactory>>,
+
+    /// Health probes for deployment verification, and pushing log rules to ant-lumberjack.
+    pub prober: Arc<dyn Prober>,
+
+    /// The "config" port of ant-lumberjack on every host, accepting the log rules of projects.
+    pub lumberjack_config_port: u16,
 }
@@ -0,0 +1,260 @@
+use std::{
+    fs,
+    path::Path,
+    time::{Duration, SystemTime},
+};
+
+use ant_zookeeper::{
+    artifacts::GarbageCollection,
+    pipeline_engine::node::{NodeOptions, NodeSpec},
+    routes::artifact::{CollectGarbageRequest, ListArtifactsResponse},
+};
+use ant_zookeeper_db::ClientRole;
+use http::StatusCode;
+use stdext::function_name;
+use tokio::test;
+use tracing_test::traced_test;
+
+use crate::{
+    dispatch::{upload_artifact_from, upsert_revision},
+    fixture::Fixture,
+};
+
+/// Run a pipeline of the revision to the end, as if it was deployed.
+async fn deploy(fixture: &Fixture, revision_id: &str) {
+    let engine = &fixture.state.engine;
+    let pipeline_id = engine
+        .create_pipeline("ant-host-agent", revision_id)
+        .await
+        .unwrap();
+    engine
+        .add_node(
+            &pipeline_id,
+            NodeSpec {
+                event: "{}".to_string(),
+                mutates: None,
+                options: NodeOptions::default(),
+            },
+        )
+        .await
+        .unwrap();
+    engine.seal(&pipeline_id).await.unwrap();
+    engine
+        .tick(|_| async { Ok(()) })
+        .await
+        .unwrap()
+        .join()
+        .await
+        .unwrap();
+}
+
+/// Pretend a file was written long enough ago to be collected.
+fn backdate(path: &Path) {
+    fs::File::options()
+        .write(true)
+        .open(path)
+        .unwrap()
+        .set_modified(SystemTime::now() - Duration::from_secs(2 * 60 * 60))
+        .unwrap();
+}
+
+async fn list_artifacts(fixture: &Fixture) -> ListArtifactsResponse {
+    let res = fixture.client.get("/artifact/artifacts").send().await;
+    assert_eq!(res.status(), StatusCode::OK);
+    res.json().await
+}
+
+#[test]
+#[traced_test]
+async fn artifacts_are_stored_once_per_content() {
+    let fixture = Fixture::new(function_name!()).await;
+
+    let first_revision = upsert_revision(&fixture).await;
+    // antworker001 has arch aarch64 in the seed data
+    upload_artifact_from(
+        &fixture,
+        "ant-host-agent-and-proj1-v1",
+        &first_revision,
+        "aarch64",
+        "v1",
+    )
+    .await;
+    let second_revision = upsert_revision(&fixture).await;
+    upload_artifact_from(
+        &fixture,
+        "ant-host-agent-and-proj1-v1",
+        &second_revision,
+        "aarch64",
+        "v2",
+    )
+    .await;
+
+    let store_dir = fixture.state.root_dir.join("artifacts-db").join("sha256");
+    assert_eq!(fs::read_dir(&store_dir).unwrap().count(), 1);
+
+    let body = list_artifacts(&fixture).await;
+    assert_eq!(body.files.len(), 1);
+    let file = &body.files[0];
+    assert_eq!(file.path, format!("sha256/{}.bld", file.fingerprint));
+    assert_eq!(
+        file.references
+            .iter()
+            .map(|a| a.revision_id.as_str())
+            .collect::<Vec<_>>(),
+        vec![first_revision.as_str(), second_revision.as_str()]
+    );
+}
+
+#[test]
+#[traced_test]
+async fn artifact_gc_keeps_retained_revisions() {
+    let fixture = Fixture::new(function_name!()).await;
+
+    let mut revisions = vec![];
+    for archive in [
+        "ant-host-agent-and-proj1-v1",
+        "ant-host-agent-monitored-v1",
+        "ant-host-agent-verified-v1",
+        "ant-host-agent-verified-v1",
+    ] {
+        let revision_id = upsert_revision(&fixture).await;
+        upload_artifact_from(&fixture, archive, &revision_id, "aarch64", "v1").await;
+        revisions.push(revision_id);
+    }
+    // The first two were deployed, the third is being deployed, and the fourth is next.
+    deploy(&fixture, &revisions[0]).await;
+    deploy(&fixture, &revisions[1]).await;
+    fixture
+        .state
+        .engine
+        .create_pipeline("ant-host-agent", &revisions[2])
+        .await
+        .unwrap();
+
+    // A service file of a replication from before they were only kept until sent.
+    let services_dir = fixture.state.root_dir.join("services-db");
+    fs::create_dir_all(&services_dir).unwrap();
+    fs::write(
+        services_dir.join("ant-host-agent.aarch64.v1.bld.deployable"),
+        b"service",
+    )
+    .unwrap();
+
+    let store_dir = fixture.state.root_dir.join("artifacts-db").join("sha256");
+    for entry in fs::read_dir(&store_dir).unwrap() {
+        backdate(&entry.unwrap().path());
+    }
+
+    let collected_artifact = {
+        let body = list_artifacts(&fixture).await;
+        assert_eq!(body.files.len(), 3);
+        let file = body
+            .files
+            .iter()
+            .find(|f| f.references[0].revision_id == revisions[0])
+            .unwrap();
+        assert_eq!(file.references.len(), 1);
+        file.references[0].clone()
+    };
+
+    // Nothing is removed on a dry run.
+    {
+        let res = fixture
+            .client
+            .post("/artifact/gc")
+            .json(&CollectGarbageRequest {
+                keep_deployed_revisions: Some(1),
+                dry_run: Some(true),
+            })
+            .send()
+            .await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let body: GarbageCollection = res.json().await;
+        assert_eq!(
+            body.artifact_ids,
+            vec![collected_artifact.artifact_id.clone()]
+        );
+        assert_eq!(
+            body.files,
+            vec![
+                format!("artifacts-db/{}", collected_artifact.local_path),
+                "services-db/ant-host-agent.aarch64.v1.bld.deployable".to_string(),
+            ]
+        );
+        assert_eq!(list_artifacts(&fixture).await.files.len(), 3);
+    }
+
+    {
+        let res = fixture
+            .client
+            .post("/artifact/gc")
+            .json(&CollectGarbageRequest {
+                keep_deployed_revisions: Some(1),
+                dry_run: None,
+            })
+            .send()
+            .await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let body: GarbageCollection = res.json().await;
+        assert_eq!(
+            body.artifact_ids,
+            vec![collected_artifact.artifact_id.clone()]
+        );
+        assert!(body.freed_bytes > 0);
+    }
+
+    assert_eq!(fs::read_dir(&store_dir).unwrap().count(), 2);
+    assert!(!fs::exists(services_dir.join("ant-host-agent.aarch64.v1.bld.deployable")).unwrap());
+    let body = list_artifacts(&fixture).await;
+    assert_eq!(body.files.len(), 2);
+    assert!(body
+        .files
+        .iter()
+        .all(|f| f.references.iter().all(|a| a.revision_id != revisions[0])));
+    // The revision can no longer be deployed.
+    assert!(fixture
+        .state
+        .db
+        .get_artifact_by_revision(
+            &revisions[0],
+            "ant-host-agent",
+            Some(&"aarch64".parse().unwrap())
+        )
+        .await
+        .unwrap()
+        .is_none());
+}
+
+#[test]
+#[traced_test]
+async fn artifact_gc_returns_4xx() {
+    let fixture = Fixture::new(function_name!()).await;
+
+    {
+        let viewer = fixture.client_with_role(ClientRole::Viewer).await;
+        let res = viewer
+            .post("/artifact/gc")
+            .json(&CollectGarbageRequest {
+                keep_deployed_revisions: None,
+                dry_run: Some(true),
+            })
+            .send()
+            .await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+    }
+
+    {
+        let res = fixture
+            .client
+            .post("/artifact/gc")
+            .json(&CollectGarbageRequest {
+                keep_deployed_revisions: Some(0),
+                dry_run: Some(true),
+            })
+            .send()
+            .await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    }
+}
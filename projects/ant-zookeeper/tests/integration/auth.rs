@@ -0,0 +1,125 @@
+use ant_zookeeper::routes::{
+    audit::GetAuditLogResponse, deployment::RetryRequest, pipeline::CreateHostGroupRequest,
+    service::UpsertRevisionRequest,
+};
+use ant_zookeeper_db::ClientRole;
+use http::StatusCode;
+use stdext::function_name;
+use tokio::test;
+use tracing_test::traced_test;
+
+use crate::fixture::Fixture;
+
+#[test]
+#[traced_test]
+async fn auth_rejects_missing_or_unknown_tokens() {
+    let fixture = Fixture::new(function_name!()).await;
+
+    {
+        let res = fixture
+            .anonymous_client
+            .get("/pipeline/pipelines")
+            .send()
+            .await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    {
+        let res = fixture
+            .anonymous_client
+            .with_bearer_token("not-a-token")
+            .post("/deployment/iteration")
+            .send()
+            .await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    }
+}
+
+#[test]
+#[traced_test]
+async fn auth_viewer_can_only_read() {
+    let fixture = Fixture::new(function_name!()).await;
+    let viewer = fixture.client_with_role(ClientRole::Viewer).await;
+
+    {
+        let res = viewer.get("/pipeline/pipelines").send().await;
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    {
+        let res = viewer.post("/deployment/iteration").send().await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+    }
+
+    {
+        let req = CreateHostGroupRequest {
+            name: "ant-host-agent/beta".to_string(),
+            project: "ant-host-agent".to_string(),
+            environment: "beta".to_string(),
+        };
+        let res = viewer
+            .post("/pipeline/host-group/host-group")
+            .json(&req)
+            .send()
+            .await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+    }
+
+    {
+        let res = viewer.get("/audit/log").send().await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+    }
+}
+
+#[test]
+#[traced_test]
+async fn auth_ci_can_only_register_revisions_and_artifacts() {
+    let fixture = Fixture::new(function_name!()).await;
+    let ci = fixture.client_with_role(ClientRole::Ci).await;
+
+    {
+        // Past authorization, the project just does not exist.
+        let req = UpsertRevisionRequest {
+            project: "no-such-project".to_string(),
+        };
+        let res = ci.post("/service/revision").json(&req).send().await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    }
+
+    {
+        let res = ci.get("/pipeline/pipelines").send().await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+    }
+
+    {
+        let res = ci.post("/deployment/iteration").send().await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+    }
+}
+
+#[test]
+#[traced_test]
+async fn auth_deployer_retries_are_audited() {
+    let fixture = Fixture::new(function_name!()).await;
+    let deployer = fixture.client_with_role(ClientRole::Deployer).await;
+
+    {
+        let req = RetryRequest {
+            job_id: Some("some job".to_string()),
+            node_id: None,
+        };
+        let res = deployer.post("/deployment/retry").json(&req).send().await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    }
+
+    {
+        let res = fixture.client.get("/audit/log").send().await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let res: GetAuditLogResponse = res.json().await;
+        assert_eq!(res.entries.len(), 1);
+        assert_eq!(res.entries[0].client_name, "test-deployer");
+        assert_eq!(res.entries[0].action, "retry");
+        assert!(res.entries[0].details.contains("some job"));
+    }
+}
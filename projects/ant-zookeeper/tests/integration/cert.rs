@@ -0,0 +1,172 @@
+use ant_host_agent::routes::gateway::gateway_secrets_dir;
+use ant_zookeeper::{
+    cert::renewer::{renew_due, RenewerConfig},
+    routes::cert::{ListCertificatesResponse, ProvisionCertificateRequest},
+    secrets::get_latest_secret,
+};
+use ant_zookeeper_db::ClientRole;
+use assertables::assert_contains;
+use http::StatusCode;
+use stdext::function_name;
+use tokio::test;
+use tracing_test::traced_test;
+
+use crate::fixture::Fixture;
+
+#[test]
+#[traced_test]
+async fn cert_provisioning_schedules_renewal() {
+    let fixture = Fixture::new(function_name!()).await;
+
+    {
+        let res = fixture
+            .client
+            .post("/cert/cert")
+            .json(&ProvisionCertificateRequest {
+                environment: "beta".to_string(),
+                domains: vec!["beta.typesofants.org".to_string()],
+            })
+            .send()
+            .await;
+        assert_eq!(res.status(), StatusCode::ACCEPTED);
+    }
+
+    {
+        let res = fixture.client.get("/cert/certificates").send().await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let body: ListCertificatesResponse = res.json().await;
+        assert_eq!(body.certificates.len(), 1);
+        assert_eq!(body.certificates[0].environment, "beta");
+        assert_eq!(body.certificates[0].state, "pending");
+        assert_eq!(body.certificates[0].expires_at, None);
+    }
+
+    {
+        let res = fixture.client.get("/metrics").send().await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let body = res.text().await;
+        assert_contains!(
+            body,
+            "ant_zookeeper_certificate_failed_renewals{environment=\"beta\"} 0"
+        );
+    }
+}
+
+#[test]
+#[traced_test]
+async fn cert_provisioning_returns_4xx() {
+    let fixture = Fixture::new(function_name!()).await;
+
+    {
+        let res = fixture
+            .client
+            .post("/cert/cert")
+            .json(&ProvisionCertificateRequest {
+                environment: "beta".to_string(),
+                domains: vec![],
+            })
+            .send()
+            .await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    }
+
+    {
+        let res = fixture
+            .client
+            .post("/cert/cert")
+            .json(&ProvisionCertificateRequest {
+                environment: "../beta".to_string(),
+                domains: vec!["beta.typesofants.org".to_string()],
+            })
+            .send()
+            .await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    }
+
+    {
+        let viewer = fixture.client_with_role(ClientRole::Viewer).await;
+        let res = viewer
+            .post("/cert/cert")
+            .json(&ProvisionCertificateRequest {
+                environment: "beta".to_string(),
+                domains: vec!["beta.typesofants.org".to_string()],
+            })
+            .send()
+            .await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+    }
+}
+
+#[test]
+#[traced_test]
+async fn cert_renewal_rolls_out_to_the_gateways() {
+    let fixture = Fixture::new(function_name!()).await;
+
+    // antworker002 runs the prod ant-gateway.
+    let secrets_dir = gateway_secrets_dir(&fixture.ant_host_agent_state.install_root_dir);
+    tokio::fs::create_dir_all(&secrets_dir).await.unwrap();
+
+    {
+        let res = fixture
+            .client
+            .post("/cert/cert")
+            .json(&ProvisionCertificateRequest {
+                environment: "prod".to_string(),
+                domains: vec!["typesofants.org".to_string()],
+            })
+            .send()
+            .await;
+        assert_eq!(res.status(), StatusCode::ACCEPTED);
+    }
+
+    {
+        let attempts = renew_due(&fixture.state, &RenewerConfig::default())
+            .await
+            .unwrap();
+        assert_eq!(attempts, 1);
+
+        let certificates = fixture.state.db.list_certificates().await.unwrap();
+        assert_eq!(certificates[0].state, "valid");
+        assert_eq!(certificates[0].failed_attempts, 0);
+        assert!(certificates[0].expires_at.is_some());
+
+        // The DNS challenges are cleaned up.
+        assert!(fixture
+            .state
+            .dns
+            .lock()
+            .await
+            .list_txt_records("_acme-challenge.typesofants.org")
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    let (_, certificate) = get_latest_secret(&fixture.state, "tls_cert", "prod", None)
+        .await
+        .unwrap()
+        .unwrap();
+    let certificate = String::from_utf8(certificate).unwrap();
+    assert_contains!(certificate, "BEGIN CERTIFICATE");
+
+    {
+        let gateway_certificate = secrets_dir.join("tls_cert.secret");
+        for _ in 0..10 {
+            let res = fixture.client.post("/deployment/iteration").send().await;
+            assert_eq!(res.status(), StatusCode::OK);
+
+            if tokio::fs::try_exists(&gateway_certificate).await.unwrap() {
+                break;
+            }
+        }
+
+        assert_eq!(
+            tokio::fs::read_to_string(&gateway_certificate)
+                .await
+                .unwrap(),
+            certificate
+        );
+    }
+}
@@ -1,26 +1,28 @@
-use std::{
-    fs::{create_dir_all, File},
-    io::Write,
-};
-
 use ant_library::host_architecture::HostArchitecture;
 use ant_zookeeper::{
     event_loop::transition::{DeploymentEvent, Event as E},
     routes::{
-        deployment::RetryRequest,
+        deployment::{
+            AddDeploymentFreezeRequest, AddDeploymentFreezeResponse, AddDeploymentWindowRequest,
+            AddDeploymentWindowResponse, ApproveNodeRequest, CancelPipelineRequest,
+            DeleteDeploymentFreezeRequest, DeleteDeploymentWindowRequest,
+            GetDeploymentWindowsResponse, PauseProjectRequest, PauseProjectResponse,
+            RecordDatabaseMigrationRequest, RetryRequest,
+        },
         pipeline::{
             AddHostToHostGroupRequest, CreateHostGroupRequest, CreateHostGroupResponse,
             PutPipelineRequest, PutPipelineStage,
         },
+        secret::PutSecretRequest,
         service::{
             ProjectEnvironmentVariable, PutProjectEnvironmentRequest, UpsertRevisionRequest,
             UpsertRevisionResponse,
         },
     },
 };
-use ant_zookeeper_db::DeploymentJob;
+use ant_zookeeper_db::{ClientRole, DeploymentJob};
+use chrono::{NaiveTime, Utc};
 use http::StatusCode;
-use reqwest::multipart::Form;
 use stdext::function_name;
 use tokio::test;
 use tracing_test::traced_test;
@@ -47,6 +49,339 @@ async fn deployment_retry_returns_400_if_no_such_job() {
     }
 }
 
+#[test]
+#[traced_test]
+async fn deployment_controls_return_400_for_unknown_resources() {
+    let fixture = Fixture::new(function_name!()).await;
+
+    {
+        let req = CancelPipelineRequest {
+            pipeline_id: "pipe-nonexistent".to_string(),
+        };
+        let res = fixture
+            .client
+            .post("/deployment/cancel")
+            .json(&req)
+            .send()
+            .await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST)
+    }
+
+    {
+        let req = ApproveNodeRequest {
+            node_id: "node-nonexistent".to_string(),
+        };
+        let res = fixture
+            .client
+            .post("/deployment/approval")
+            .json(&req)
+            .send()
+            .await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST)
+    }
+
+    {
+        let req = PauseProjectRequest {
+            project: "no-such-project".to_string(),
+        };
+        let res = fixture
+            .client
+            .post("/deployment/pause")
+            .json(&req)
+            .send()
+            .await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST)
+    }
+}
+
+#[test]
+#[traced_test]
+async fn deployment_pause_and_resume_project() {
+    let fixture = Fixture::new(function_name!()).await;
+    fixture
+        .state
+        .db
+        .register_project("ant-host-agent", true)
+        .await
+        .unwrap();
+
+    let req = PauseProjectRequest {
+        project: "ant-host-agent".to_string(),
+    };
+
+    {
+        let res = fixture
+            .client
+            .post("/deployment/pause")
+            .json(&req)
+            .send()
+            .await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let res: PauseProjectResponse = res.json().await;
+        assert!(res.paused);
+        assert!(fixture
+            .state
+            .engine
+            .is_paused("ant-host-agent")
+            .await
+            .unwrap());
+    }
+
+    {
+        let res = fixture
+            .client
+            .post("/deployment/resume")
+            .json(&req)
+            .send()
+            .await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let res: PauseProjectResponse = res.json().await;
+        assert!(!res.paused);
+        assert!(!fixture
+            .state
+            .engine
+            .is_paused("ant-host-agent")
+            .await
+            .unwrap());
+    }
+}
+
+fn weekdays(time_zone: Option<&str>) -> AddDeploymentWindowRequest {
+    AddDeploymentWindowRequest {
+        project: None,
+        environment: "prod".to_string(),
+        days: vec![1, 2, 3, 4, 5],
+        start_time: NaiveTime::from_hms_opt(9, 0, 0).unwrap(),
+        end_time: NaiveTime::from_hms_opt(17, 0, 0).unwrap(),
+        time_zone: time_zone.map(str::to_string),
+    }
+}
+
+async fn get_windows(fixture: &Fixture) -> GetDeploymentWindowsResponse {
+    let res = fixture.client.get("/deployment/windows").send().await;
+    assert_eq!(res.status(), StatusCode::OK);
+    res.json().await
+}
+
+#[test]
+#[traced_test]
+async fn deployment_windows_added_and_deleted() {
+    let fixture = Fixture::new(function_name!()).await;
+
+    let window_id = {
+        let res = fixture
+            .client
+            .post("/deployment/window")
+            .json(&weekdays(Some("Europe/Amsterdam")))
+            .send()
+            .await;
+        assert_eq!(res.status(), StatusCode::OK);
+        res.json::<AddDeploymentWindowResponse>().await.window_id
+    };
+
+    let freeze_id = {
+        let now = Utc::now();
+        let res = fixture
+            .client
+            .post("/deployment/freeze")
+            .json(&AddDeploymentFreezeRequest {
+                project: None,
+                environment: Some("prod".to_string()),
+                starts_at: now,
+                ends_at: now + chrono::Duration::days(1),
+                reason: "launch".to_string(),
+            })
+            .send()
+            .await;
+        assert_eq!(res.status(), StatusCode::OK);
+        res.json::<AddDeploymentFreezeResponse>().await.freeze_id
+    };
+
+    let windows = get_windows(&fixture).await;
+    assert_eq!(windows.windows.len(), 1);
+    assert_eq!(windows.windows[0].window_id, window_id);
+    assert_eq!(windows.windows[0].time_zone, "Europe/Amsterdam");
+    assert_eq!(windows.freezes.len(), 1);
+    assert_eq!(windows.freezes[0].freeze_id, freeze_id);
+
+    {
+        let res = fixture
+            .client
+            .delete("/deployment/window")
+            .json(&DeleteDeploymentWindowRequest {
+                window_id: window_id.clone(),
+            })
+            .send()
+            .await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let res = fixture
+            .client
+            .delete("/deployment/freeze")
+            .json(&DeleteDeploymentFreezeRequest {
+                freeze_id: freeze_id.clone(),
+            })
+            .send()
+            .await;
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    let windows = get_windows(&fixture).await;
+    assert!(windows.windows.is_empty());
+    assert!(windows.freezes.is_empty());
+
+    {
+        let res = fixture
+            .client
+            .delete("/deployment/window")
+            .json(&DeleteDeploymentWindowRequest { window_id })
+            .send()
+            .await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    }
+}
+
+#[test]
+#[traced_test]
+async fn deployment_window_returns_400_if_invalid() {
+    let fixture = Fixture::new(function_name!()).await;
+
+    let res = fixture
+        .client
+        .post("/deployment/window")
+        .json(&weekdays(Some("Nowhere/Special")))
+        .send()
+        .await;
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+    let res = fixture
+        .client
+        .post("/deployment/window")
+        .json(&AddDeploymentWindowRequest {
+            project: Some("ant-does-not-exist".to_string()),
+            ..weekdays(None)
+        })
+        .send()
+        .await;
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+    let res = fixture
+        .client
+        .post("/deployment/window")
+        .json(&AddDeploymentWindowRequest {
+            environment: "staging".to_string(),
+            ..weekdays(None)
+        })
+        .send()
+        .await;
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+    let now = Utc::now();
+    let res = fixture
+        .client
+        .post("/deployment/freeze")
+        .json(&AddDeploymentFreezeRequest {
+            project: None,
+            environment: Some("staging".to_string()),
+            starts_at: now,
+            ends_at: now + chrono::Duration::days(1),
+            reason: "launch".to_string(),
+        })
+        .send()
+        .await;
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+    let windows = get_windows(&fixture).await;
+    assert!(windows.windows.is_empty());
+    assert!(windows.freezes.is_empty());
+}
+
+#[test]
+#[traced_test]
+async fn deployment_window_only_for_admins() {
+    let fixture = Fixture::new(function_name!()).await;
+    let deployer = fixture.client_with_role(ClientRole::Deployer).await;
+
+    let res = deployer
+        .post("/deployment/window")
+        .json(&weekdays(None))
+        .send()
+        .await;
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+}
+
+#[test]
+#[traced_test]
+async fn deployment_migration_recorded_by_hand() {
+    let fixture = Fixture::new(function_name!()).await;
+    fixture
+        .state
+        .db
+        .register_project("ant-data-farm", true)
+        .await
+        .unwrap();
+
+    let req = RecordDatabaseMigrationRequest {
+        project: "ant-data-farm".to_string(),
+        environment: "prod".to_string(),
+        host_id: "antworker000.hosts.typesofants.org".to_string(),
+        migration_name: "044_reset_monitoring_user_password.sh".to_string(),
+        migration_sha256: "a".repeat(64),
+    };
+
+    {
+        let deployer = fixture.client_with_role(ClientRole::Deployer).await;
+        let res = deployer
+            .post("/deployment/migration")
+            .json(&req)
+            .send()
+            .await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+    }
+
+    {
+        let res = fixture
+            .client
+            .post("/deployment/migration")
+            .json(&req)
+            .send()
+            .await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let migrations = fixture
+            .state
+            .db
+            .list_database_migrations("ant-data-farm", "prod", &req.host_id)
+            .await
+            .unwrap();
+        assert_eq!(migrations.len(), 1);
+        assert_eq!(migrations[0].migration_name, req.migration_name);
+        assert_eq!(migrations[0].migration_sha256, req.migration_sha256);
+        assert_eq!(migrations[0].revision_id, None);
+    }
+
+    {
+        let res = fixture
+            .client
+            .post("/deployment/migration")
+            .json(&req)
+            .send()
+            .await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+        let res = fixture
+            .client
+            .post("/deployment/migration")
+            .json(&RecordDatabaseMigrationRequest {
+                environment: "../prod".to_string(),
+                ..req
+            })
+            .send()
+            .await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    }
+}
+
 pub async fn get_events(fixture: &Fixture, revision_id: &str) -> Vec<DeploymentEvent> {
     let raw_events = fixture
         .state
@@ -72,17 +407,20 @@ pub async fn get_unfinished_jobs(fixture: &Fixture) -> Vec<DeploymentJob> {
 
 async fn beta_stage_setup(fixture: &Fixture) {
     // register secrets
-    {
-        let secret_root = fixture.state.root_dir.join("secrets-db");
-        let paths = vec![
-            secret_root.join("beta").join("jwt.secret"),
-            secret_root.join("prod").join("jwt.secret"),
-        ];
-        for p in paths {
-            create_dir_all(p.parent().unwrap()).unwrap();
-            let mut file = File::create(p).unwrap();
-            file.write_all("secret value".as_bytes()).unwrap();
-        }
+    for environment in ["beta", "prod"] {
+        let res = fixture
+            .client
+            .put("/secret/secret")
+            .json(&PutSecretRequest {
+                name: "jwt".to_string(),
+                environment: environment.to_string(),
+                host: None,
+                value: "secret value".to_string(),
+                valid_for_seconds: None,
+            })
+            .send()
+            .await;
+        assert_eq!(res.status(), StatusCode::OK);
     }
 
     // Create host group ant-host-agent/beta
@@ -182,7 +520,9 @@ async fn artifact_build_setup(fixture: &Fixture, version: Option<&str>) -> Strin
     // Register artifact for ant-host-agent on arm architecture
     {
         let archive = fixture.make_tarfile_fixture("ant-host-agent-and-proj1-v1");
-        let req = Form::new().file("file", archive.path()).await.unwrap();
+        let req = fixture
+            .artifact_form(archive.path(), "ant-host-agent", "arm", version)
+            .await;
 
         let res = fixture
             .client
@@ -257,7 +597,9 @@ async fn artifact_build_setup(fixture: &Fixture, version: Option<&str>) -> Strin
     // Register the x86 artifact
     {
         let archive = fixture.make_tarfile_fixture("ant-host-agent-and-proj1-v1");
-        let req = Form::new().file("file", archive.path()).await.unwrap();
+        let req = fixture
+            .artifact_form(archive.path(), "ant-host-agent", "x86", version)
+            .await;
 
         let res = fixture
             .client
@@ -296,7 +638,9 @@ async fn artifact_build_setup(fixture: &Fixture, version: Option<&str>) -> Strin
     // Register the (FINAL) Raspian artifact
     {
         let archive = fixture.make_tarfile_fixture("ant-host-agent-and-proj1-v1");
-        let req = Form::new().file("file", archive.path()).await.unwrap();
+        let req = fixture
+            .artifact_form(archive.path(), "ant-host-agent", "raspbian", version)
+            .await;
 
         let res = fixture
             .client
@@ -1,10 +1,12 @@
-use std::fs::{self, File};
-use std::io::Write;
+use std::fs;
+use std::sync::atomic::Ordering;
 
+use ant_host_agent::routes::gateway::gateway_routes_path;
 use ant_zookeeper::{
     pipeline::dispatch::dispatch,
     pipeline_engine::engine::{Dispatch, DispatchDirection, Node},
     routes::service::{UpsertRevisionRequest, UpsertRevisionResponse},
+    secrets::{put_secret, DEFAULT_VALID_FOR},
 };
 use http::StatusCode;
 use stdext::function_name;
@@ -13,12 +15,28 @@ use tracing_test::traced_test;
 
 use crate::fixture::Fixture;
 
-async fn upload_artifact(fixture: &Fixture, rev_id: &str, arch: &str, version: &str) {
-    let archive = fixture.make_tarfile_fixture("ant-host-agent-and-proj1-v1");
-    let req = reqwest::multipart::Form::new()
-        .file("file", archive.path())
-        .await
-        .unwrap();
+pub async fn upload_artifact(fixture: &Fixture, rev_id: &str, arch: &str, version: &str) {
+    upload_artifact_from(
+        fixture,
+        "ant-host-agent-and-proj1-v1",
+        rev_id,
+        arch,
+        version,
+    )
+    .await;
+}
+
+pub async fn upload_artifact_from(
+    fixture: &Fixture,
+    archive: &str,
+    rev_id: &str,
+    arch: &str,
+    version: &str,
+) {
+    let archive = fixture.make_tarfile_fixture(archive);
+    let req = fixture
+        .artifact_form(archive.path(), "ant-host-agent", arch, version)
+        .await;
     let res = fixture
         .client
         .post("/service/artifact")
@@ -32,7 +50,7 @@ async fn upload_artifact(fixture: &Fixture, rev_id: &str, arch: &str, version: &
     assert_eq!(res.status(), StatusCode::OK);
 }
 
-async fn upsert_revision(fixture: &Fixture) -> String {
+pub async fn upsert_revision(fixture: &Fixture) -> String {
     let req = UpsertRevisionRequest {
         project: "ant-host-agent".to_string(),
     };
@@ -80,17 +98,16 @@ async fn dispatch_artifact_replication_registers_and_installs_service() {
     // ant-host-agent-and-proj1-v1/anthill.json requires a "jwt" secret in all environments
     // (register_artifact validates secrets for both "beta" and "prod")
     for env in ["beta", "prod"] {
-        let secret_path = fixture
-            .state
-            .root_dir
-            .join("secrets-db")
-            .join(env)
-            .join("jwt.secret");
-        fs::create_dir_all(secret_path.parent().unwrap()).unwrap();
-        File::create(&secret_path)
-            .unwrap()
-            .write_all(b"secret")
-            .unwrap();
+        put_secret(
+            &fixture.state,
+            "jwt",
+            env,
+            None,
+            b"secret",
+            DEFAULT_VALID_FOR,
+        )
+        .await
+        .unwrap();
     }
 
     let revision_id = upsert_revision(&fixture).await;
@@ -105,9 +122,9 @@ async fn dispatch_artifact_replication_registers_and_installs_service() {
         fixture
             .ant_host_agent_state
             .archive_root_dir
-            .join("deployment.ant-host-agent.v1.tar.gz")
+            .join("deployment.ant-host-agent.v1.tree.json")
             .exists(),
-        "register_service should store the tarball"
+        "register_service_delta should store the tree"
     );
     assert!(
         fixture
@@ -118,16 +135,40 @@ async fn dispatch_artifact_replication_registers_and_installs_service() {
             .exists(),
         "install_service should unpack to versioned dir"
     );
+    assert_eq!(
+        fs::read_to_string(
+            fixture
+                .ant_host_agent_state
+                .install_root_dir
+                .join("ant-host-agent")
+                .join("v1.1")
+                .join("secrets")
+                .join("jwt.secret")
+        )
+        .unwrap(),
+        "secret",
+        "the secret should be injected at the version pinned to the revision"
+    );
+    assert_eq!(
+        fs::read_to_string(
+            fixture
+                .ant_host_agent_state
+                .install_root_dir
+                .join("ant-host-agent")
+                .join("v1.1")
+                .join("secrets")
+                .join("trusted_keys.secret")
+        )
+        .unwrap(),
+        fixture.state.provenance_signer.public_key_pem().unwrap(),
+        "ant-host-agent should be provisioned with the key deployments are signed with"
+    );
 }
 
-#[test]
-#[traced_test]
-async fn dispatch_deployment_verification_returns_ok() {
-    let fixture = Fixture::new(function_name!()).await;
-
+fn deployment_verification_dispatch(revision_id: &str) -> Dispatch {
     let node = Node {
         node_id: "test-node-dv".to_string(),
-        revision_id: "rev-test".to_string(),
+        revision_id: revision_id.to_string(),
         event: serde_json::json!({
             "type": "deployment_verification",
             "host_id": "antworker001",
@@ -138,74 +179,157 @@ async fn dispatch_deployment_verification_returns_ok() {
         state: "executable".to_string(),
         resource_key: None,
     };
-    let d = Dispatch {
+    Dispatch {
         direction: DispatchDirection::Deploy,
-        revision_id: "rev-test".to_string(),
+        revision_id: revision_id.to_string(),
         node,
-    };
+    }
+}
 
-    dispatch(fixture.state.clone(), d).await.unwrap();
+#[test]
+#[traced_test]
+async fn dispatch_deployment_verification_returns_ok() {
+    let fixture = Fixture::new(function_name!()).await;
+
+    let revision_id = upsert_revision(&fixture).await;
+    upload_artifact_from(
+        &fixture,
+        "ant-host-agent-verified-v1",
+        &revision_id,
+        "aarch64",
+        "v1",
+    )
+    .await;
+
+    dispatch(
+        fixture.state.clone(),
+        deployment_verification_dispatch(&revision_id),
+    )
+    .await
+    .unwrap();
+}
+
+#[test]
+#[traced_test]
+async fn dispatch_deployment_verification_fails_when_unhealthy() {
+    let fixture = Fixture::new(function_name!()).await;
+    fixture.prober.healthy.store(false, Ordering::SeqCst);
+
+    let revision_id = upsert_revision(&fixture).await;
+    upload_artifact_from(
+        &fixture,
+        "ant-host-agent-verified-v1",
+        &revision_id,
+        "aarch64",
+        "v1",
+    )
+    .await;
+
+    let err = dispatch(
+        fixture.state.clone(),
+        deployment_verification_dispatch(&revision_id),
+    )
+    .await
+    .unwrap_err();
+    assert!(err.to_string().contains("did not become healthy"), "{err}");
 }
 
 #[test]
 #[traced_test]
-async fn dispatch_route_update_returns_ok() {
+async fn dispatch_deployment_verification_unwind_returns_ok() {
     let fixture = Fixture::new(function_name!()).await;
+    fixture.prober.healthy.store(false, Ordering::SeqCst);
 
+    let mut d = deployment_verification_dispatch("rev-test");
+    d.direction = DispatchDirection::Unwind {
+        restore_revision_id: None,
+    };
+
+    dispatch(fixture.state.clone(), d).await.unwrap();
+}
+
+fn route_update_dispatch(revision_id: &str) -> Dispatch {
     let node = Node {
         node_id: "test-node-ru".to_string(),
-        revision_id: "rev-test".to_string(),
+        revision_id: revision_id.to_string(),
         event: serde_json::json!({
             "type": "route_update",
-            "environment": "beta",
+            "environment": "prod",
         })
         .to_string(),
         state: "executable".to_string(),
         resource_key: None,
     };
-    let d = Dispatch {
+    Dispatch {
         direction: DispatchDirection::Deploy,
-        revision_id: "rev-test".to_string(),
+        revision_id: revision_id.to_string(),
         node,
-    };
+    }
+}
 
-    dispatch(fixture.state.clone(), d).await.unwrap();
+#[test]
+#[traced_test]
+async fn dispatch_route_update_fails_for_unknown_revision() {
+    let fixture = Fixture::new(function_name!()).await;
+
+    let err = dispatch(fixture.state.clone(), route_update_dispatch("rev-test"))
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("revision not found"), "{err}");
 }
 
 #[test]
 #[traced_test]
-async fn dispatch_alert_configuration_returns_ok() {
+async fn dispatch_route_update_pushes_routes_to_gateway() {
     let fixture = Fixture::new(function_name!()).await;
 
+    let revision_id = upsert_revision(&fixture).await;
+    upload_artifact(&fixture, &revision_id, "aarch64", "v1").await;
+
+    dispatch(fixture.state.clone(), route_update_dispatch(&revision_id))
+        .await
+        .unwrap();
+
+    let routes = fs::read_to_string(gateway_routes_path(
+        &fixture.ant_host_agent_state.persist_root_dir,
+    ))
+    .unwrap();
+    assert!(
+        routes.contains("Rendered by ant-zookeeper for prod"),
+        "{routes}"
+    );
+
+    let mut d = route_update_dispatch(&revision_id);
+    d.direction = DispatchDirection::Unwind {
+        restore_revision_id: None,
+    };
+    dispatch(fixture.state.clone(), d).await.unwrap();
+}
+
+fn alert_configuration_dispatch(revision_id: &str) -> Dispatch {
     let node = Node {
         node_id: "test-node-ac".to_string(),
-        revision_id: "rev-test".to_string(),
+        revision_id: revision_id.to_string(),
         event: serde_json::json!({
             "type": "alert_configuration",
             "service_id": "ant-host-agent",
-            "environment": "beta",
+            "environment": "prod",
         })
         .to_string(),
         state: "executable".to_string(),
         resource_key: None,
     };
-    let d = Dispatch {
+    Dispatch {
         direction: DispatchDirection::Deploy,
-        revision_id: "rev-test".to_string(),
+        revision_id: revision_id.to_string(),
         node,
-    };
-
-    dispatch(fixture.state.clone(), d).await.unwrap();
+    }
 }
 
-#[test]
-#[traced_test]
-async fn dispatch_log_rule_configuration_returns_ok() {
-    let fixture = Fixture::new(function_name!()).await;
-
+fn log_rule_configuration_dispatch(revision_id: &str) -> Dispatch {
     let node = Node {
         node_id: "test-node-lrc".to_string(),
-        revision_id: "rev-test".to_string(),
+        revision_id: revision_id.to_string(),
         event: serde_json::json!({
             "type": "log_rule_configuration",
             "host_id": "antworker001",
@@ -215,36 +339,180 @@ async fn dispatch_log_rule_configuration_returns_ok() {
         state: "executable".to_string(),
         resource_key: None,
     };
-    let d = Dispatch {
+    Dispatch {
         direction: DispatchDirection::Deploy,
-        revision_id: "rev-test".to_string(),
+        revision_id: revision_id.to_string(),
         node,
-    };
+    }
+}
+
+#[test]
+#[traced_test]
+async fn dispatch_alert_configuration_fails_without_alert_rules() {
+    let fixture = Fixture::new(function_name!()).await;
+
+    let revision_id = upsert_revision(&fixture).await;
+    upload_artifact(&fixture, &revision_id, "aarch64", "v1").await;
+
+    let err = dispatch(
+        fixture.state.clone(),
+        alert_configuration_dispatch(&revision_id),
+    )
+    .await
+    .unwrap_err();
+    assert!(err.to_string().contains("alert-rules.yml"), "{err}");
+}
+
+#[test]
+#[traced_test]
+async fn dispatch_alert_configuration_records_and_unwinds_rules() {
+    let fixture = Fixture::new(function_name!()).await;
+
+    let revision_id = upsert_revision(&fixture).await;
+    upload_artifact_from(
+        &fixture,
+        "ant-host-agent-monitored-v1",
+        &revision_id,
+        "aarch64",
+        "v1",
+    )
+    .await;
 
+    dispatch(
+        fixture.state.clone(),
+        alert_configuration_dispatch(&revision_id),
+    )
+    .await
+    .unwrap();
+    let rules = fixture
+        .state
+        .db
+        .get_latest_monitoring_rules("alert", "prod", "ant-host-agent")
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(rules.contains("AntHostAgentDown"), "{rules}");
+
+    let mut d = alert_configuration_dispatch(&revision_id);
+    d.direction = DispatchDirection::Unwind {
+        restore_revision_id: None,
+    };
     dispatch(fixture.state.clone(), d).await.unwrap();
+    assert_eq!(
+        fixture
+            .state
+            .db
+            .get_latest_monitoring_rules("alert", "prod", "ant-host-agent")
+            .await
+            .unwrap(),
+        None
+    );
 }
 
 #[test]
 #[traced_test]
-async fn dispatch_database_migration_returns_ok() {
+async fn dispatch_log_rule_configuration_pushes_and_unwinds_rules() {
     let fixture = Fixture::new(function_name!()).await;
 
+    let revision_id = upsert_revision(&fixture).await;
+    upload_artifact_from(
+        &fixture,
+        "ant-host-agent-monitored-v1",
+        &revision_id,
+        "aarch64",
+        "v1",
+    )
+    .await;
+
+    dispatch(
+        fixture.state.clone(),
+        log_rule_configuration_dispatch(&revision_id),
+    )
+    .await
+    .unwrap();
+
+    let mut d = log_rule_configuration_dispatch(&revision_id);
+    d.direction = DispatchDirection::Unwind {
+        restore_revision_id: None,
+    };
+    dispatch(fixture.state.clone(), d).await.unwrap();
+
+    let pushed = fixture.prober.pushed.lock().unwrap().clone();
+    assert_eq!(pushed.len(), 2);
+
+    let (host, port, path, rules) = &pushed[0];
+    assert_eq!(
+        (host.as_str(), *port, path.as_str()),
+        ("antworker001", 3261, "/rules/ant-host-agent")
+    );
+    assert!(rules
+        .as_ref()
+        .unwrap()
+        .contains("ant_host_agent_errors_total"));
+
+    // Nothing was deployed before, so unwinding removes the rules.
+    assert_eq!(pushed[1].3, None);
+}
+
+fn database_migration_dispatch(revision_id: &str) -> Dispatch {
     let node = Node {
         node_id: "test-node-dm".to_string(),
-        revision_id: "rev-test".to_string(),
+        revision_id: revision_id.to_string(),
         event: serde_json::json!({
             "type": "database_migration",
             "service_id": "ant-host-agent",
-            "environment": "beta",
+            "environment": "prod",
         })
         .to_string(),
         state: "executable".to_string(),
         resource_key: None,
     };
-    let d = Dispatch {
+    Dispatch {
         direction: DispatchDirection::Deploy,
-        revision_id: "rev-test".to_string(),
+        revision_id: revision_id.to_string(),
         node,
+    }
+}
+
+#[test]
+#[traced_test]
+async fn dispatch_database_migration_fails_for_non_database_projects() {
+    let fixture = Fixture::new(function_name!()).await;
+
+    let revision_id = upsert_revision(&fixture).await;
+    upload_artifact(&fixture, &revision_id, "aarch64", "v1").await;
+
+    let err = dispatch(
+        fixture.state.clone(),
+        database_migration_dispatch(&revision_id),
+    )
+    .await
+    .unwrap_err();
+    assert!(err.to_string().contains("not a postgres project"), "{err}");
+}
+
+#[test]
+#[traced_test]
+async fn dispatch_database_migration_fails_without_artifact() {
+    let fixture = Fixture::new(function_name!()).await;
+
+    let err = dispatch(
+        fixture.state.clone(),
+        database_migration_dispatch("rev-test"),
+    )
+    .await
+    .unwrap_err();
+    assert!(err.to_string().contains("no artifact"), "{err}");
+}
+
+#[test]
+#[traced_test]
+async fn dispatch_database_migration_unwind_without_migrations_returns_ok() {
+    let fixture = Fixture::new(function_name!()).await;
+
+    let mut d = database_migration_dispatch("rev-test");
+    d.direction = DispatchDirection::Unwind {
+        restore_revision_id: None,
     };
 
     dispatch(fixture.state.clone(), d).await.unwrap();
@@ -0,0 +1,191 @@
+use ant_zookeeper::{
+    drift::{correct_drifted, reconcile, DRIFTED, IN_SYNC},
+    routes::{
+        host::GetServiceDriftResponse,
+        service::{UpsertRevisionRequest, UpsertRevisionResponse},
+    },
+};
+use assertables::assert_contains;
+use http::StatusCode;
+use stdext::function_name;
+use tokio::test;
+use tracing_test::traced_test;
+
+use crate::{
+    fixture::Fixture,
+    projects::{setup_gateway_secrets, upload_artifact},
+};
+
+/// Deploy ant-gateway, on antworker002, as `version`. Returns the revision.
+async fn deploy_gateway(fixture: &Fixture, version: &str) -> String {
+    let res = fixture
+        .client
+        .post("/service/revision")
+        .json(&UpsertRevisionRequest {
+            project: "ant-gateway".to_string(),
+        })
+        .send()
+        .await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let rev_id = res.json::<UpsertRevisionResponse>().await.revision;
+
+    for arch in ["x86_64", "aarch64", "armv7"] {
+        upload_artifact(fixture, &rev_id, arch, version).await;
+    }
+    run_pipelines(fixture).await;
+
+    rev_id
+}
+
+async fn run_pipelines(fixture: &Fixture) {
+    for _ in 0..20 {
+        let res = fixture.client.post("/deployment/iteration").send().await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let active = fixture
+            .state
+            .engine
+            .active_pipelines("ant-gateway")
+            .await
+            .unwrap();
+        if active.is_empty() {
+            return;
+        }
+    }
+
+    panic!("Pipeline did not finish within 20 ticks");
+}
+
+/// Make the host run another version than deployed, like after a manual install.
+fn tamper_version(fixture: &Fixture, version: &str) {
+    std::fs::write(
+        fixture
+            .ant_host_agent_state
+            .install_root_dir
+            .join("ant-gateway")
+            .join("current")
+            .join("VERSION"),
+        version,
+    )
+    .unwrap();
+}
+
+async fn get_drift(fixture: &Fixture) -> GetServiceDriftResponse {
+    let res = fixture.client.get("/host/drift").send().await;
+    assert_eq!(res.status(), StatusCode::OK);
+    res.json().await
+}
+
+#[test]
+#[traced_test]
+async fn drift_in_sync_after_deployment() {
+    let fixture = Fixture::new(function_name!()).await;
+    setup_gateway_secrets(&fixture).await;
+
+    assert!(get_drift(&fixture).await.drift.is_empty());
+
+    let rev_id = deploy_gateway(&fixture, "build-200").await;
+    reconcile(&fixture.state).await.unwrap();
+
+    let drift = get_drift(&fixture).await.drift;
+    assert_eq!(drift.len(), 1);
+    assert_eq!(drift[0].host_id, "antworker002");
+    assert_eq!(drift[0].service_id, "ant-gateway");
+    assert_eq!(drift[0].desired_revision_id, rev_id);
+    assert_eq!(drift[0].desired_version, "build-200");
+    assert_eq!(drift[0].actual_version.as_deref(), Some("build-200"));
+    assert_eq!(drift[0].state, IN_SYNC);
+    assert_eq!(drift[0].drifted_since, None);
+}
+
+#[test]
+#[traced_test]
+async fn drift_reported_without_correcting() {
+    let fixture = Fixture::new(function_name!()).await;
+    setup_gateway_secrets(&fixture).await;
+
+    deploy_gateway(&fixture, "build-200").await;
+    tamper_version(&fixture, "build-199");
+    reconcile(&fixture.state).await.unwrap();
+
+    let drift = get_drift(&fixture).await.drift;
+    assert_eq!(drift.len(), 1);
+    assert_eq!(drift[0].actual_version.as_deref(), Some("build-199"));
+    assert_eq!(drift[0].state, DRIFTED);
+    assert!(drift[0].drifted_since.is_some());
+    assert_eq!(drift[0].corrective_pipeline_id, None);
+
+    assert!(fixture
+        .state
+        .engine
+        .active_pipelines("ant-gateway")
+        .await
+        .unwrap()
+        .is_empty());
+
+    let res = fixture.client.get("/metrics").send().await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let body = res.text().await;
+    assert_contains!(
+        body,
+        "ant_zookeeper_service_drifted{host_id=\"antworker002\",service_id=\"ant-gateway\"} 1"
+    );
+    assert_contains!(
+        body,
+        "ant_zookeeper_service_drift_unreachable{host_id=\"antworker002\",service_id=\"ant-gateway\"} 0"
+    );
+}
+
+#[test]
+#[traced_test]
+async fn drift_corrected_by_redeploying() {
+    let fixture = Fixture::new(function_name!()).await;
+    setup_gateway_secrets(&fixture).await;
+    let reconcile_and_correct = || async {
+        reconcile(&fixture.state).await.unwrap();
+        correct_drifted(&fixture.state).await.unwrap()
+    };
+
+    let rev_id = deploy_gateway(&fixture, "build-200").await;
+    tamper_version(&fixture, "build-199");
+    reconcile_and_correct().await;
+
+    let pipelines = fixture
+        .state
+        .engine
+        .active_pipelines("ant-gateway")
+        .await
+        .unwrap();
+    assert_eq!(pipelines.len(), 1);
+    assert_ne!(pipelines[0].revision_id, rev_id);
+
+    let drift = get_drift(&fixture).await.drift;
+    assert_eq!(drift[0].state, DRIFTED);
+    assert_eq!(
+        drift[0].corrective_pipeline_id.as_deref(),
+        Some(pipelines[0].pipeline_id.as_str())
+    );
+
+    // While the correction runs, no other one is opened.
+    reconcile_and_correct().await;
+    assert_eq!(
+        fixture
+            .state
+            .engine
+            .active_pipelines("ant-gateway")
+            .await
+            .unwrap()
+            .len(),
+        1
+    );
+
+    run_pipelines(&fixture).await;
+    reconcile_and_correct().await;
+
+    let drift = get_drift(&fixture).await.drift;
+    assert_eq!(drift.len(), 1);
+    assert_eq!(drift[0].desired_revision_id, pipelines[0].revision_id);
+    assert_eq!(drift[0].actual_version.as_deref(), Some("build-200"));
+    assert_eq!(drift[0].state, IN_SYNC);
+    assert_eq!(drift[0].corrective_pipeline_id, None);
+}
@@ -1,101 +1,115 @@
-use std::{collections::HashMap, path::PathBuf, sync::Arc};
+use std::{
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
 
 use ant_host_agent::{
     client::{AntHostAgentClient, AntHostAgentClientConfig, AntHostAgentClientFactory},
     state::AntHostAgentState,
 };
 use ant_library::{
-    db::TypesOfAntsDatabase, sd::writer::ServiceDiscoveryWriter, services::Services,
+    db::TypesOfAntsDatabase,
+    host_architecture::HostArchitecture,
+    provenance::{file_sha256, Provenance, ProvenanceSigner, TrustedKeys},
+    sd::writer::ServiceDiscoveryWriter,
+    services::Services,
 };
 use ant_library_test::{
     axum_test_client::TestClient, consul_fixture::ConsulFixture, db::TestDatabase,
 };
 use ant_zookeeper::{
-    dns::{Dns, TxtRecord},
-    make_routes,
+    cert::local::LocalCertificateAuthority, dns::LocalDns, make_routes,
+    pipeline::driver::DriverMetrics, probe::Prober, secrets::SecretCipher,
     state::AntZookeeperState,
 };
-use ant_zookeeper_db::AntZooStorageClient;
+use ant_zookeeper_db::{AntZooStorageClient, ClientRole};
+use anthill_manifest::AnthillCluster;
 use async_trait::async_trait;
-use chrono::Utc;
 use flate2::{write::GzEncoder, Compression};
-use rsa::rand_core::OsRng;
+use http::StatusCode;
 use tempfile::NamedTempFile;
 use tokio::{
     fs::{create_dir_all, File},
     io::AsyncWriteExt,
     net::TcpListener,
-    sync::Mutex,
+    sync::{broadcast, Mutex},
     task::JoinHandle,
 };
 
-struct TestDns {
-    records: Mutex<HashMap<String, Vec<TxtRecord>>>,
-}
+/// Every deployed service is healthy, until told otherwise.
+pub struct TestProber {
+    pub healthy: AtomicBool,
 
-impl TestDns {
-    pub fn new() -> Self {
-        TestDns {
-            records: Mutex::new(HashMap::new()),
-        }
-    }
+    /// The (host, port, path, body) of every PUT and DELETE, with no body for DELETE.
+    pub pushed: std::sync::Mutex<Vec<(String, u16, String, Option<String>)>>,
 }
 
 #[async_trait]
-impl Dns for TestDns {
-    async fn put_txt_record(
+impl Prober for TestProber {
+    async fn http_get(
         &self,
-        domain: &str,
-        val: String,
-    ) -> Result<ant_zookeeper::dns::TxtRecord, anyhow::Error> {
-        if let Some(record) = self
-            .list_txt_records(domain)
-            .await?
-            .iter()
-            .find(|record| record.content == val)
-        {
-            return Ok(record.clone());
+        _host: &str,
+        _port: u16,
+        _path: &str,
+    ) -> Result<(StatusCode, String), anyhow::Error> {
+        match self.healthy.load(Ordering::SeqCst) {
+            true => Ok((StatusCode::OK, "healthy ant".to_string())),
+            false => Ok((StatusCode::SERVICE_UNAVAILABLE, "sick ant".to_string())),
         }
-
-        let record = TxtRecord {
-            id: Utc::now().to_rfc3339(),
-            content: val,
-        };
-        let mut domain_records = self.records.lock().await;
-
-        domain_records
-            .entry(domain.to_string())
-            .or_insert_with(|| vec![])
-            .push(record.clone());
-
-        Ok(record)
     }
 
-    async fn delete_txt_record(&self, record_id: &str) -> Result<(), anyhow::Error> {
-        for (_, v) in self.records.lock().await.iter_mut() {
-            v.iter_mut()
-                .position(|record| record.id == record_id)
-                .map(|idx| v.remove(idx));
-        }
-
-        Ok(())
+    async fn http_put(
+        &self,
+        host: &str,
+        port: u16,
+        path: &str,
+        body: String,
+    ) -> Result<(StatusCode, String), anyhow::Error> {
+        self.pushed
+            .lock()
+            .unwrap()
+            .push((host.to_string(), port, path.to_string(), Some(body)));
+        Ok((StatusCode::OK, String::new()))
     }
 
-    async fn list_txt_records(&self, domain: &str) -> Result<Vec<TxtRecord>, anyhow::Error> {
-        Ok(self
-            .records
+    async fn http_delete(
+        &self,
+        host: &str,
+        port: u16,
+        path: &str,
+    ) -> Result<(StatusCode, String), anyhow::Error> {
+        self.pushed
             .lock()
-            .await
-            .get(domain)
-            .unwrap_or(&vec![])
-            .clone())
+            .unwrap()
+            .push((host.to_string(), port, path.to_string(), None));
+        Ok((StatusCode::OK, String::new()))
+    }
+
+    async fn consul_healthy(
+        &self,
+        _cluster: &AnthillCluster,
+        _environment: &str,
+        _service_id: &str,
+        _host_id: &str,
+    ) -> Result<Option<bool>, anyhow::Error> {
+        Ok(Some(self.healthy.load(Ordering::SeqCst)))
     }
 }
 
 pub struct Fixture {
+    /// Authenticated as an admin.
     pub client: TestClient,
+    /// Without a bearer token.
+    pub anonymous_client: TestClient,
     pub state: AntZookeeperState,
     pub ant_host_agent_state: AntHostAgentState,
+    pub prober: Arc<TestProber>,
+    /// Signs artifacts like anthill, trusted by ant-zookeeper.
+    pub builder: ProvenanceSigner,
 
     _guard: TestDatabase,
     _consul: ConsulFixture,
@@ -170,11 +184,21 @@ impl Fixture {
 
         let consul = ConsulFixture::new().await;
 
+        let builder = ProvenanceSigner::generate().unwrap();
+        let provenance_signer = ProvenanceSigner::generate().unwrap();
+
         let ant_host_agent_state = AntHostAgentState {
             sd: Arc::new(ServiceDiscoveryWriter::new(consul.port())),
             infra_sd: Arc::new(ServiceDiscoveryWriter::new(consul.port())),
             archive_root_dir: root_dir.join("hostagent-archive"),
             install_root_dir: root_dir.join("hostagent-install"),
+            persist_root_dir: root_dir.join("hostagent-persist"),
+            trusted_keys: Some(Arc::new(
+                TrustedKeys::from_pem(&provenance_signer.public_key_pem().unwrap()).unwrap(),
+            )),
+            gateway_exec: vec!["true".to_string()],
+            monitor_check_rules: vec!["true".to_string()],
+            monitor_reload: vec!["true".to_string()],
         };
         create_dir_all(&ant_host_agent_state.archive_root_dir)
             .await
@@ -276,35 +300,74 @@ impl Fixture {
                 .unwrap(),
         );
 
+        let prober = Arc::new(TestProber {
+            healthy: AtomicBool::new(true),
+            pushed: std::sync::Mutex::new(vec![]),
+        });
+
         let state = AntZookeeperState {
-            dns: Arc::new(Mutex::new(TestDns::new())),
-            rng: OsRng,
+            dns: Arc::new(Mutex::new(LocalDns::in_memory())),
+            certificate_authority: Arc::new(LocalCertificateAuthority::new(90).unwrap()),
+            hosts_domain: "hosts.typesofants.org".to_string(),
             services: Arc::new(services),
-            acme_url: acme_lib::DirectoryUrl::LetsEncryptStaging,
-            acme_contact_email: "integ-test@typesofants.org".to_string(),
             root_dir: root_dir,
             db,
+            secret_cipher: Arc::new(SecretCipher::generate().unwrap()),
+            trusted_builders: Arc::new(
+                TrustedKeys::from_pem(&builder.public_key_pem().unwrap()).unwrap(),
+            ),
+            provenance_signer: Arc::new(provenance_signer),
             engine,
+            driver_metrics: Arc::new(DriverMetrics::default()),
+            transitions: broadcast::channel(16).0,
             ant_host_agent_factory: Arc::new(Mutex::new(
                 TestAntHostAgentService::new(ant_host_agent_service)
                     .await
                     .unwrap(),
             )),
+            prober: prober.clone(),
+            lumberjack_config_port: 3261,
         };
 
+        ant_zookeeper::secrets::seal_plaintext_secrets(&state.db, &state.secret_cipher)
+            .await
+            .unwrap();
+
         let routes = make_routes(state.clone()).unwrap();
 
-        let client = TestClient::new(routes).await;
+        let anonymous_client = TestClient::new(routes).await;
+
+        state
+            .db
+            .upsert_client("test-admin", ClientRole::Admin, "test-admin-token")
+            .await
+            .unwrap();
+        let client = anonymous_client.with_bearer_token("test-admin-token");
 
         Fixture {
             client,
+            anonymous_client,
             state,
             ant_host_agent_state,
+            prober,
+            builder,
             _consul: consul,
             _guard,
         }
     }
 
+    /// A client authenticated with a new token of `role`.
+    pub async fn client_with_role(&self, role: ClientRole) -> TestClient {
+        let token = format!("test-{}-token", role.as_str());
+        self.state
+            .db
+            .upsert_client(&format!("test-{}", role.as_str()), role, &token)
+            .await
+            .unwrap();
+
+        self.anonymous_client.with_bearer_token(&token)
+    }
+
     /// Create a tarfile from a test directory in the "archives/" directory
     pub fn make_tarfile_fixture(&self, tar_dir_name: &str) -> NamedTempFile {
         let dst = NamedTempFile::new().unwrap();
@@ -330,4 +393,47 @@ impl Fixture {
 
         dst
     }
+
+    /// The provenance of the artifact at `path`, signed by `signer`, as anthill signs it.
+    pub fn sign_artifact(
+        &self,
+        signer: &ProvenanceSigner,
+        path: &Path,
+        project: &str,
+        arch: &str,
+        version: &str,
+    ) -> String {
+        let signed = signer
+            .sign(&Provenance {
+                project: project.to_string(),
+                version: version.to_string(),
+                commit: "deadbeef".to_string(),
+                builder: "anthill/test".to_string(),
+                architecture: HostArchitecture::from_str(arch)
+                    .map(|arch| arch.as_str().to_string())
+                    .unwrap_or(arch.to_string()),
+                sha256: file_sha256(path).unwrap(),
+                built_at: chrono::Utc::now(),
+            })
+            .unwrap();
+        serde_json::to_string(&signed).unwrap()
+    }
+
+    /// An artifact upload of the tarball at `path`, with the provenance the builder signed for it.
+    pub async fn artifact_form(
+        &self,
+        path: &Path,
+        project: &str,
+        arch: &str,
+        version: &str,
+    ) -> reqwest::multipart::Form {
+        reqwest::multipart::Form::new()
+            .file("file", path)
+            .await
+            .unwrap()
+            .text(
+                "provenance",
+                self.sign_artifact(&self.builder, path, project, arch, version),
+            )
+    }
 }
@@ -0,0 +1,226 @@
+use ant_host_agent::heartbeat::{Heartbeat, InstalledService, UnitState};
+use ant_library_test::axum_test_client::TestClient;
+use ant_zookeeper::routes::host::{GetHostInventoryResponse, HostHealth};
+use ant_zookeeper_db::ClientRole;
+use http::StatusCode;
+use stdext::function_name;
+use tokio::test;
+use tracing_test::traced_test;
+
+use crate::{
+    dispatch::{upload_artifact, upsert_revision},
+    fixture::Fixture,
+};
+
+fn heartbeat(host_id: &str) -> Heartbeat {
+    Heartbeat {
+        host_id: host_id.to_string(),
+        uptime_seconds: 3600,
+        disk_total_bytes: 64_000_000_000,
+        disk_available_bytes: 40_000_000_000,
+        memory_total_bytes: 4_000_000_000,
+        memory_available_bytes: 2_500_000_000,
+        services: vec![InstalledService {
+            service_id: "ant-host-agent".to_string(),
+            version: "v1".to_string(),
+        }],
+        units: vec![UnitState {
+            unit_name: "ant-host-agent.service".to_string(),
+            load_state: "loaded".to_string(),
+            active_state: "active".to_string(),
+            sub_state: "running".to_string(),
+        }],
+    }
+}
+
+/// A client of the host itself, the only one that may send its heartbeats.
+async fn host_client(fixture: &Fixture, host_id: &str) -> TestClient {
+    let token = format!("{host_id}-token");
+    fixture
+        .state
+        .db
+        .upsert_client(host_id, ClientRole::Host, &token)
+        .await
+        .unwrap();
+    fixture.anonymous_client.with_bearer_token(&token)
+}
+
+async fn get_inventory(fixture: &Fixture) -> GetHostInventoryResponse {
+    let res = fixture.client.get("/host/inventory").send().await;
+    assert_eq!(res.status(), StatusCode::OK);
+    res.json().await
+}
+
+/// Make the last heartbeat of every host as old as if the agents stopped an hour ago.
+async fn age_heartbeats(fixture: &Fixture) {
+    fixture
+        .state
+        .db
+        .pool()
+        .get()
+        .await
+        .unwrap()
+        .execute(
+            "update host_heartbeat set last_heartbeat_at = now() - interval '1 hour'",
+            &[],
+        )
+        .await
+        .unwrap();
+}
+
+#[test]
+#[traced_test]
+async fn host_heartbeat_shows_in_inventory() {
+    let fixture = Fixture::new(function_name!()).await;
+
+    let inventory = get_inventory(&fixture).await;
+    let health = inventory
+        .hosts
+        .iter()
+        .map(|h| (h.host_id.as_str(), h.health))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        health,
+        vec![
+            ("antworker001", HostHealth::Unknown),
+            ("antworker002", HostHealth::Unknown)
+        ]
+    );
+
+    let res = host_client(&fixture, "antworker001")
+        .await
+        .post("/host/heartbeat")
+        .json(&heartbeat("antworker001"))
+        .send()
+        .await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let inventory = get_inventory(&fixture).await;
+    let host = &inventory.hosts[0];
+    assert_eq!(host.health, HostHealth::Healthy);
+    assert_eq!(host.heartbeat, Some(heartbeat("antworker001")));
+    assert!(host.last_heartbeat_at.is_some());
+    assert_eq!(inventory.hosts[1].health, HostHealth::Unknown);
+    assert_eq!(inventory.hosts[1].heartbeat, None);
+
+    // Without heartbeats, it is unhealthy until the next one.
+    age_heartbeats(&fixture).await;
+    assert_eq!(
+        get_inventory(&fixture).await.hosts[0].health,
+        HostHealth::Unhealthy
+    );
+
+    let res = host_client(&fixture, "antworker001")
+        .await
+        .post("/host/heartbeat")
+        .json(&heartbeat("antworker001"))
+        .send()
+        .await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(
+        get_inventory(&fixture).await.hosts[0].health,
+        HostHealth::Healthy
+    );
+}
+
+#[test]
+#[traced_test]
+async fn host_heartbeat_returns_400_for_unknown_hosts() {
+    let fixture = Fixture::new(function_name!()).await;
+
+    let res = host_client(&fixture, "antworker999")
+        .await
+        .post("/host/heartbeat")
+        .json(&heartbeat("antworker999"))
+        .send()
+        .await;
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+}
+
+#[test]
+#[traced_test]
+async fn host_heartbeat_only_of_the_host_itself() {
+    let fixture = Fixture::new(function_name!()).await;
+    let host = host_client(&fixture, "antworker001").await;
+
+    {
+        let res = host
+            .post("/host/heartbeat")
+            .json(&heartbeat("antworker002"))
+            .send()
+            .await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+    }
+
+    {
+        let res = host
+            .post("/host/heartbeat")
+            .json(&heartbeat("antworker001"))
+            .send()
+            .await;
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    {
+        let res = host.get("/host/inventory").send().await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+    }
+
+    for role in [ClientRole::Viewer, ClientRole::Deployer] {
+        let client = fixture.client_with_role(role).await;
+        let res = client
+            .post("/host/heartbeat")
+            .json(&heartbeat("antworker001"))
+            .send()
+            .await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+    }
+
+    {
+        let res = fixture
+            .client
+            .post("/host/heartbeat")
+            .json(&heartbeat("antworker001"))
+            .send()
+            .await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+    }
+}
+
+#[test]
+#[traced_test]
+async fn host_unhealthy_is_left_out_of_new_pipelines() {
+    let fixture = Fixture::new(function_name!()).await;
+
+    let res = host_client(&fixture, "antworker001")
+        .await
+        .post("/host/heartbeat")
+        .json(&heartbeat("antworker001"))
+        .send()
+        .await;
+    assert_eq!(res.status(), StatusCode::OK);
+    age_heartbeats(&fixture).await;
+
+    // ant-host-agent only runs on antworker001.
+    let rev_id = upsert_revision(&fixture).await;
+    upload_artifact(&fixture, &rev_id, "aarch64", "v1").await;
+
+    let pipelines = fixture
+        .state
+        .engine
+        .active_pipelines("ant-host-agent")
+        .await
+        .unwrap();
+    assert_eq!(pipelines.len(), 1);
+
+    let nodes = fixture
+        .state
+        .engine
+        .nodes(&pipelines[0].pipeline_id)
+        .await
+        .unwrap();
+    assert!(
+        !nodes.iter().any(|n| n.event.contains("antworker001")),
+        "{nodes:?}"
+    );
+}
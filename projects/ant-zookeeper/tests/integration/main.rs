@@ -1,6 +1,13 @@
+pub mod artifact;
+pub mod auth;
+pub mod cert;
 pub mod deployment;
 pub mod dispatch;
+pub mod drift;
 mod fixture;
+pub mod host;
+pub mod metrics;
 pub mod pipeline;
 pub mod projects;
+pub mod secret;
 pub mod service;
@@ -0,0 +1,28 @@
+use assertables::assert_contains;
+use http::StatusCode;
+use stdext::function_name;
+use tokio::test;
+use tracing_test::traced_test;
+
+use crate::fixture::Fixture;
+
+#[test]
+#[traced_test]
+async fn metrics_returns_driver_metrics() {
+    let fixture = Fixture::new(function_name!()).await;
+
+    {
+        let res = fixture.anonymous_client.get("/metrics").send().await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    {
+        let res = fixture.client.get("/metrics").send().await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let body = res.text().await;
+        assert_contains!(body, "ant_zookeeper_driver_is_leader 0");
+        assert_contains!(body, "ant_zookeeper_driver_tick_seconds_count 0");
+        assert_contains!(body, "ant_zookeeper_driver_in_flight_jobs 0");
+    }
+}
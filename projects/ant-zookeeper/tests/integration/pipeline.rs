@@ -1,10 +1,13 @@
 use ant_library::host_architecture::HostArchitecture;
 use ant_zookeeper::{
     err::AntZookeeperError,
-    routes::pipeline::{
-        AddHostToHostGroupRequest, CreateHostGroupRequest, CreateHostGroupResponse,
-        GetHostGroupResponse, GetPipelineResponse, PutPipelineRequest, PutPipelineStage,
-        RemoveHostFromHostGroupRequest,
+    routes::{
+        pipeline::{
+            AddHostToHostGroupRequest, CreateHostGroupRequest, CreateHostGroupResponse,
+            GetHostGroupResponse, GetPipelineResponse, PutPipelineRequest, PutPipelineStage,
+            RemoveHostFromHostGroupRequest,
+        },
+        service::{UpsertRevisionRequest, UpsertRevisionResponse},
     },
 };
 use http::StatusCode;
@@ -12,7 +15,10 @@ use stdext::function_name;
 use tokio::test;
 use tracing_test::traced_test;
 
-use crate::fixture::{self, Fixture};
+use crate::{
+    fixture::{self, Fixture},
+    projects::{setup_gateway_secrets, upload_artifact},
+};
 
 #[test]
 #[traced_test]
@@ -845,3 +851,161 @@ async fn pipeline_pipeline_post_returns_200_for_pipeline_deploying_multiple_proj
         assert_eq!(body.stages.len(), 2);
     }
 }
+
+#[test]
+#[traced_test]
+async fn pipeline_transitions_returns_4xx() {
+    let fixture = fixture::Fixture::new(function_name!()).await;
+    fixture
+        .state
+        .db
+        .register_project("ant-host-agent", true)
+        .await
+        .unwrap();
+
+    for path in [
+        "/pipeline/transitions",
+        "/pipeline/transitions?project=ant-host-agent&pipelineId=pipeline-abc",
+        "/pipeline/transitions?project=not-a-project",
+        "/pipeline/transitions?pipelineId=pipeline-nonexistent",
+        "/pipeline/transitions?project=ant-host-agent&lastEventId=abc",
+    ] {
+        let res = fixture.client.get(path).send().await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST, "{path}");
+    }
+
+    {
+        let res = fixture
+            .client
+            .get("/pipeline/transitions?project=ant-host-agent")
+            .header("last-event-id", "abc")
+            .send()
+            .await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    }
+
+    {
+        let res = fixture
+            .anonymous_client
+            .get("/pipeline/transitions?project=ant-host-agent")
+            .send()
+            .await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    }
+}
+
+/// Open a pipeline deploying a new revision of ant-gateway. Returns the pipeline.
+async fn open_gateway_pipeline(fixture: &Fixture) -> String {
+    setup_gateway_secrets(fixture).await;
+
+    let res = fixture
+        .client
+        .post("/service/revision")
+        .json(&UpsertRevisionRequest {
+            project: "ant-gateway".to_string(),
+        })
+        .send()
+        .await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let rev_id = res.json::<UpsertRevisionResponse>().await.revision;
+
+    for arch in ["x86_64", "aarch64", "armv7"] {
+        upload_artifact(fixture, &rev_id, arch, "build-200").await;
+    }
+
+    let pipelines = fixture
+        .state
+        .engine
+        .active_pipelines("ant-gateway")
+        .await
+        .unwrap();
+    assert_eq!(pipelines.len(), 1);
+
+    pipelines[0].pipeline_id.clone()
+}
+
+#[test]
+#[traced_test]
+async fn pipeline_graph_get_returns_200_in_each_format() {
+    let fixture = Fixture::new(function_name!()).await;
+    let pipeline_id = open_gateway_pipeline(&fixture).await;
+
+    {
+        let res = fixture
+            .client
+            .get(&format!(
+                "/pipeline/graph?pipelineId={pipeline_id}&format=dot"
+            ))
+            .send()
+            .await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(
+            res.headers()["content-type"],
+            "text/vnd.graphviz; charset=utf-8"
+        );
+
+        let body = res.text().await;
+        assert!(body.starts_with(&format!("digraph \"{pipeline_id}\"")));
+        assert!(body.contains("rank=same;"));
+        assert!(body.contains("ant-gateway"));
+        assert!(body.contains(" -> "));
+    }
+
+    {
+        let res = fixture
+            .client
+            .get(&format!(
+                "/pipeline/graph?pipelineId={pipeline_id}&format=mermaid"
+            ))
+            .send()
+            .await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let body = res.text().await;
+        assert!(body.contains("flowchart LR"));
+        assert!(body.contains("classDef pending"));
+        assert!(body.contains("-->"));
+    }
+
+    {
+        let res = fixture
+            .client
+            .get(&format!(
+                "/pipeline/graph?pipelineId={pipeline_id}&format=terminal"
+            ))
+            .send()
+            .await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let body = res.text().await;
+        assert!(body.contains(&pipeline_id));
+        assert!(body.contains("layer 0"));
+    }
+}
+
+#[test]
+#[traced_test]
+async fn pipeline_graph_get_returns_4xx() {
+    let fixture = Fixture::new(function_name!()).await;
+
+    {
+        let res = fixture
+            .client
+            .get("/pipeline/graph?pipelineId=pipeline-missing&format=dot")
+            .send()
+            .await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    }
+
+    {
+        let pipeline_id = open_gateway_pipeline(&fixture).await;
+        let res = fixture
+            .client
+            .get(&format!(
+                "/pipeline/graph?pipelineId={pipeline_id}&format=png"
+            ))
+            .send()
+            .await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    }
+}
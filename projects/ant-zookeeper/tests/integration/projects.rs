@@ -1,34 +1,33 @@
-use std::fs::{self, File};
-use std::io::Write;
-
 use crate::fixture::{self, Fixture};
 use ant_zookeeper::routes::projects::ProjectDeploymentView;
 use ant_zookeeper::routes::service::{UpsertRevisionRequest, UpsertRevisionResponse};
+use ant_zookeeper::secrets::{put_secret, DEFAULT_VALID_FOR};
 use http::StatusCode;
 use stdext::function_name;
 use tracing_test::traced_test;
 
-fn setup_gateway_secrets(fixture: &Fixture) {
-    let secret_root = fixture.state.root_dir.join("secrets-db");
-    for path in [
-        secret_root.join("beta").join("tls_cert.secret"),
-        secret_root.join("prod").join("tls_cert.secret"),
-        secret_root.join("beta").join("tls_key.secret"),
-        secret_root.join("prod").join("tls_key.secret"),
-    ] {
-        fs::create_dir_all(path.parent().unwrap()).unwrap();
-        File::create(path).unwrap().write_all(b"secret").unwrap();
+pub async fn setup_gateway_secrets(fixture: &Fixture) {
+    for environment in ["beta", "prod"] {
+        for name in ["tls_cert", "tls_key"] {
+            put_secret(
+                &fixture.state,
+                name,
+                environment,
+                None,
+                b"secret",
+                DEFAULT_VALID_FOR,
+            )
+            .await
+            .unwrap();
+        }
     }
 }
 
-async fn upload_artifact(fixture: &Fixture, rev_id: &str, arch: &str, version: &str) {
+pub async fn upload_artifact(fixture: &Fixture, rev_id: &str, arch: &str, version: &str) {
     let tarfile = fixture.make_tarfile_fixture("ant-gateway-v1");
-    let file_bytes = fs::read(tarfile.path()).unwrap();
-
-    let form = reqwest::multipart::Form::new().part(
-        "file",
-        reqwest::multipart::Part::bytes(file_bytes).file_name("artifact.tar.gz"),
-    );
+    let form = fixture
+        .artifact_form(tarfile.path(), "ant-gateway", arch, version)
+        .await;
 
     let res = fixture
         .client
@@ -64,7 +63,7 @@ async fn projects_returns_200_no_activity() {
 #[traced_test]
 async fn projects_returns_200_build_in_progress() {
     let fixture = Fixture::new(function_name!()).await;
-    setup_gateway_secrets(&fixture);
+    setup_gateway_secrets(&fixture).await;
 
     let res = fixture
         .client
@@ -99,7 +98,7 @@ async fn projects_returns_200_build_in_progress() {
 #[traced_test]
 async fn projects_returns_200_build_activates_into_pipeline() {
     let fixture = Fixture::new(function_name!()).await;
-    setup_gateway_secrets(&fixture);
+    setup_gateway_secrets(&fixture).await;
 
     let res = fixture
         .client
@@ -130,7 +129,7 @@ async fn projects_returns_200_build_activates_into_pipeline() {
 #[traced_test]
 async fn projects_returns_200_pipeline_finishes() {
     let fixture = Fixture::new(function_name!()).await;
-    setup_gateway_secrets(&fixture);
+    setup_gateway_secrets(&fixture).await;
 
     let res = fixture
         .client
@@ -167,7 +166,7 @@ async fn projects_returns_200_pipeline_finishes() {
 #[traced_test]
 async fn projects_returns_200_new_build_after_finished() {
     let fixture = Fixture::new(function_name!()).await;
-    setup_gateway_secrets(&fixture);
+    setup_gateway_secrets(&fixture).await;
 
     // First revision: build + activate + run to completion
     let res = fixture
@@ -0,0 +1,342 @@
+use std::path::PathBuf;
+
+use ant_library::db::{DatabaseConfig, TypesOfAntsDatabase};
+use ant_library_test::db::TestDatabase;
+use ant_zookeeper::{
+    routes::secret::{ListSecretsResponse, PutSecretRequest, PutSecretResponse},
+    secrets::{decrypt_secret, get_revision_secret, seal_plaintext_secrets, SecretCipher},
+};
+use ant_zookeeper_db::{AntZooStorageClient, ClientRole};
+use http::StatusCode;
+use stdext::function_name;
+use tokio::test;
+use tracing_test::traced_test;
+
+use crate::{
+    dispatch::{upload_artifact, upsert_revision},
+    fixture::Fixture,
+};
+
+fn jwt(environment: &str, value: &str) -> PutSecretRequest {
+    PutSecretRequest {
+        name: "jwt".to_string(),
+        environment: environment.to_string(),
+        host: None,
+        value: value.to_string(),
+        valid_for_seconds: None,
+    }
+}
+
+#[test]
+#[traced_test]
+async fn secret_versions_are_listed_without_values() {
+    let fixture = Fixture::new(function_name!()).await;
+
+    for (value, version) in [("first value", 1), ("second value", 2)] {
+        let res = fixture
+            .client
+            .put("/secret/secret")
+            .json(&jwt("prod", value))
+            .send()
+            .await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let body: PutSecretResponse = res.json().await;
+        assert_eq!(body.version, version);
+        assert!(body.pipelines.is_empty());
+    }
+
+    {
+        let viewer = fixture.client_with_role(ClientRole::Viewer).await;
+        let res = viewer.get("/secret/secrets").send().await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let body = res.text().await;
+        assert!(!body.contains("first value"));
+        assert!(!body.contains("second value"));
+
+        let body: ListSecretsResponse = serde_json::from_str(&body).unwrap();
+        assert_eq!(body.secrets.len(), 2);
+        assert_eq!(body.secrets[0].name, "jwt");
+        assert_eq!(body.secrets[0].version, 1);
+        assert_eq!(body.secrets[1].version, 2);
+    }
+
+    // The values are encrypted at rest.
+    {
+        let secret = fixture
+            .state
+            .db
+            .get_secret("jwt", "prod", None, None)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(secret.secret.version, 2);
+        assert!(!String::from_utf8_lossy(&secret.value).contains("second value"));
+    }
+}
+
+#[test]
+#[traced_test]
+async fn secret_set_returns_4xx() {
+    let fixture = Fixture::new(function_name!()).await;
+
+    {
+        let viewer = fixture.client_with_role(ClientRole::Viewer).await;
+        let res = viewer
+            .put("/secret/secret")
+            .json(&jwt("prod", "value"))
+            .send()
+            .await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+    }
+
+    {
+        let res = fixture
+            .client
+            .put("/secret/secret")
+            .json(&PutSecretRequest {
+                name: "../jwt".to_string(),
+                ..jwt("prod", "value")
+            })
+            .send()
+            .await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    }
+
+    {
+        let res = fixture
+            .client
+            .put("/secret/secret")
+            .json(&PutSecretRequest {
+                valid_for_seconds: Some(0),
+                ..jwt("prod", "value")
+            })
+            .send()
+            .await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    }
+}
+
+#[test]
+#[traced_test]
+async fn secret_versions_are_pinned_to_revisions() {
+    let fixture = Fixture::new(function_name!()).await;
+
+    fixture
+        .client
+        .put("/secret/secret")
+        .json(&jwt("beta", "first value"))
+        .send()
+        .await;
+
+    let first_revision = fixture
+        .state
+        .db
+        .create_revision("ant-host-agent")
+        .await
+        .unwrap();
+    let value = get_revision_secret(&fixture.state, &first_revision, "jwt", "beta", None)
+        .await
+        .unwrap();
+    assert_eq!(value, b"first value");
+
+    fixture
+        .client
+        .put("/secret/secret")
+        .json(&jwt("beta", "second value"))
+        .send()
+        .await;
+
+    // The revision keeps deploying the version it was first deployed with.
+    let value = get_revision_secret(&fixture.state, &first_revision, "jwt", "beta", None)
+        .await
+        .unwrap();
+    assert_eq!(value, b"first value");
+
+    let second_revision = fixture
+        .state
+        .db
+        .create_revision("ant-host-agent")
+        .await
+        .unwrap();
+    let value = get_revision_secret(&fixture.state, &second_revision, "jwt", "beta", None)
+        .await
+        .unwrap();
+    assert_eq!(value, b"second value");
+
+    // Secrets without any version fail the deployment.
+    assert!(
+        get_revision_secret(&fixture.state, &second_revision, "jwt", "prod", None)
+            .await
+            .is_err()
+    );
+}
+
+#[test]
+#[traced_test]
+async fn secret_rotation_redeploys_projects_using_it() {
+    let fixture = Fixture::new(function_name!()).await;
+
+    // ant-host-agent-and-proj1-v1/anthill.json requires a "jwt" secret in all environments.
+    for environment in ["beta", "prod"] {
+        let res = fixture
+            .client
+            .put("/secret/secret")
+            .json(&jwt(environment, "first value"))
+            .send()
+            .await;
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    let revision_id = upsert_revision(&fixture).await;
+    // antworker001 has arch aarch64 in the seed data
+    upload_artifact(&fixture, &revision_id, "aarch64", "v1").await;
+
+    // Nothing deployed with it yet.
+    {
+        let res = fixture
+            .client
+            .post("/secret/rotate")
+            .json(&jwt("prod", "second value"))
+            .send()
+            .await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let body: PutSecretResponse = res.json().await;
+        assert_eq!(body.version, 2);
+        assert!(body.pipelines.is_empty());
+    }
+
+    // As the pipeline does when deploying the revision to prod.
+    get_revision_secret(&fixture.state, &revision_id, "jwt", "prod", None)
+        .await
+        .unwrap();
+
+    {
+        let res = fixture
+            .client
+            .post("/secret/rotate")
+            .json(&jwt("prod", "third value"))
+            .send()
+            .await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let body: PutSecretResponse = res.json().await;
+        assert_eq!(body.version, 3);
+        assert_eq!(body.pipelines.len(), 1);
+    }
+
+    // The redeploy is a new revision of the same artifacts, which deploys the rotated secret.
+    let (redeployed_revision, activated_at) = fixture
+        .state
+        .db
+        .get_latest_revision("ant-host-agent")
+        .await
+        .unwrap()
+        .unwrap();
+    assert_ne!(redeployed_revision, revision_id);
+    assert!(activated_at.is_some());
+    assert_eq!(
+        fixture
+            .state
+            .db
+            .list_artifacts_for_revision_id(&redeployed_revision)
+            .await
+            .unwrap()
+            .len(),
+        fixture
+            .state
+            .db
+            .list_artifacts_for_revision_id(&revision_id)
+            .await
+            .unwrap()
+            .len()
+    );
+    let value = get_revision_secret(&fixture.state, &redeployed_revision, "jwt", "prod", None)
+        .await
+        .unwrap();
+    assert_eq!(value, b"third value");
+}
+
+#[test]
+#[traced_test]
+async fn secret_from_before_encryption_is_sealed_and_decrypts() {
+    let database = TestDatabase::new("ant-zookeeper-db").await;
+
+    // Split the migrations at the secret store, to register a version the way it was before.
+    let migrations = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("..")
+        .join("ant-zookeeper-db")
+        .join("migrations");
+    let split = tempfile::tempdir().unwrap();
+    let (before, after) = (split.path().join("before"), split.path().join("after"));
+    std::fs::create_dir_all(&before).unwrap();
+    std::fs::create_dir_all(&after).unwrap();
+    for entry in std::fs::read_dir(&migrations).unwrap() {
+        let name = entry.unwrap().file_name().into_string().unwrap();
+        if !name.ends_with(".sql") {
+            continue;
+        }
+        let dir = if name.as_str() < "011" {
+            &before
+        } else {
+            &after
+        };
+        std::fs::copy(migrations.join(&name), dir.join(&name)).unwrap();
+    }
+
+    let db = AntZooStorageClient::connect(&DatabaseConfig {
+        migration_dirs: vec![before],
+        ..database.config.clone()
+    })
+    .await
+    .unwrap();
+    let certificate = b"-----BEGIN CERTIFICATE-----\nMIIB...\n-----END CERTIFICATE-----\n";
+    db.pool()
+        .get()
+        .await
+        .unwrap()
+        .execute(
+            "
+            insert into secret (secret_name, secret_environment, secret_version, valid_for_seconds, secret_value)
+            values ('tls_cert', 'prod', 1, 7776000, $1)
+            ",
+            &[&certificate.as_slice()],
+        )
+        .await
+        .unwrap();
+
+    let db = AntZooStorageClient::connect(&DatabaseConfig {
+        migration_dirs: vec![after],
+        ..database.config.clone()
+    })
+    .await
+    .unwrap();
+    let cipher = SecretCipher::generate().unwrap();
+    assert_eq!(seal_plaintext_secrets(&db, &cipher).await.unwrap(), 1);
+
+    let sealed = db
+        .get_secret("tls_cert", "prod", None, None)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(sealed.secret.version, 1);
+    assert_ne!(sealed.value, certificate);
+    assert_eq!(decrypt_secret(&cipher, &sealed).unwrap(), certificate);
+
+    // The plaintext is gone, and the next version follows it.
+    assert_eq!(seal_plaintext_secrets(&db, &cipher).await.unwrap(), 0);
+    let (_, version) = db
+        .register_new_secret_version(
+            "tls_cert",
+            "prod",
+            None,
+            chrono::Duration::days(90),
+            b"nonce",
+            b"value",
+        )
+        .await
+        .unwrap();
+    assert_eq!(version, 2);
+}
@@ -1,9 +1,6 @@
-use std::{
-    fs::{create_dir_all, exists, File},
-    io::Write,
-    path::Path,
-};
+use std::{fs::exists, path::Path};
 
+use ant_library::{host_architecture::HostArchitecture, provenance::ProvenanceSigner};
 use ant_zookeeper::{
     event_loop::transition::Event as E,
     routes::{
@@ -13,6 +10,7 @@ use ant_zookeeper::{
         },
         service::{UpsertRevisionRequest, UpsertRevisionResponse},
     },
+    secrets::{put_secret, DEFAULT_VALID_FOR},
 };
 use assertables::assert_contains;
 use http::StatusCode;
@@ -144,10 +142,9 @@ async fn service_artifact_returns_400_if_asking_for_unknown_secrets() {
 
     let archive = fixture.make_tarfile_fixture("ant-gateway-v1");
 
-    let req = reqwest::multipart::Form::new()
-        .file("file", archive.path())
-        .await
-        .unwrap();
+    let req = fixture
+        .artifact_form(archive.path(), "ant-gateway", "aarch64", "v1")
+        .await;
 
     let res = fixture
         .client
@@ -168,18 +165,18 @@ async fn service_artifact_returns_200_happy_path() {
     let fixture = fixture::Fixture::new(function_name!()).await;
 
     // register secrets
-    {
-        let secret_root = fixture.state.root_dir.join("secrets-db");
-        let paths = vec![
-            secret_root.join("beta").join("tls_cert.secret"),
-            secret_root.join("prod").join("tls_cert.secret"),
-            secret_root.join("beta").join("tls_key.secret"),
-            secret_root.join("prod").join("tls_key.secret"),
-        ];
-        for p in paths {
-            create_dir_all(p.parent().unwrap()).unwrap();
-            let mut file = File::create(p).unwrap();
-            file.write_all("secret value".as_bytes()).unwrap();
+    for environment in ["beta", "prod"] {
+        for name in ["tls_cert", "tls_key"] {
+            put_secret(
+                &fixture.state,
+                name,
+                environment,
+                None,
+                b"secret value",
+                DEFAULT_VALID_FOR,
+            )
+            .await
+            .unwrap();
         }
     }
 
@@ -204,10 +201,9 @@ async fn service_artifact_returns_200_happy_path() {
     let archive = fixture.make_tarfile_fixture("ant-gateway-v1");
     let input_digest = digest(&archive.path());
 
-    let req = reqwest::multipart::Form::new()
-        .file("file", archive.path())
-        .await
-        .unwrap();
+    let req = fixture
+        .artifact_form(archive.path(), "ant-gateway", "aarch64", "v1")
+        .await;
 
     let res = fixture
         .client
@@ -226,7 +222,8 @@ async fn service_artifact_returns_200_happy_path() {
         .state
         .root_dir
         .join("artifacts-db")
-        .join("ant-gateway.aarch64.v1.bld");
+        .join("sha256")
+        .join(format!("{input_digest}.bld"));
 
     assert_eq!(
         (output_path.clone(), exists(output_path.clone()).unwrap()),
@@ -238,23 +235,128 @@ async fn service_artifact_returns_200_happy_path() {
     assert_eq!(input_digest, output_digest);
 }
 
+#[test]
+#[traced_test]
+async fn service_artifact_returns_400_without_trusted_provenance() {
+    let fixture = fixture::Fixture::new(function_name!()).await;
+
+    for environment in ["beta", "prod"] {
+        for name in ["tls_cert", "tls_key"] {
+            put_secret(
+                &fixture.state,
+                name,
+                environment,
+                None,
+                b"secret value",
+                DEFAULT_VALID_FOR,
+            )
+            .await
+            .unwrap();
+        }
+    }
+
+    let revision = {
+        let res = fixture
+            .client
+            .post("/service/revision")
+            .json(&UpsertRevisionRequest {
+                project: "ant-gateway".to_string(),
+            })
+            .send()
+            .await;
+        assert_eq!(res.status(), StatusCode::OK);
+        res.json::<UpsertRevisionResponse>().await.revision
+    };
+
+    let archive = fixture.make_tarfile_fixture("ant-gateway-v1");
+    let other_archive = fixture.make_tarfile_fixture("ant-host-agent-and-proj1-v1");
+    let untrusted = ProvenanceSigner::generate().unwrap();
+
+    let provenances = [
+        // Not signed at all
+        None,
+        // Signed by a builder that is not trusted
+        Some(fixture.sign_artifact(&untrusted, archive.path(), "ant-gateway", "aarch64", "v1")),
+        // Signed for another tarball, version, project or architecture
+        Some(fixture.sign_artifact(
+            &fixture.builder,
+            other_archive.path(),
+            "ant-gateway",
+            "aarch64",
+            "v1",
+        )),
+        Some(fixture.sign_artifact(
+            &fixture.builder,
+            archive.path(),
+            "ant-gateway",
+            "aarch64",
+            "v2",
+        )),
+        Some(fixture.sign_artifact(
+            &fixture.builder,
+            archive.path(),
+            "ant-host-agent",
+            "aarch64",
+            "v1",
+        )),
+        Some(fixture.sign_artifact(
+            &fixture.builder,
+            archive.path(),
+            "ant-gateway",
+            "x86_64",
+            "v1",
+        )),
+    ];
+
+    for provenance in provenances {
+        let mut req = reqwest::multipart::Form::new()
+            .file("file", archive.path())
+            .await
+            .unwrap();
+        if let Some(provenance) = provenance {
+            req = req.text("provenance", provenance);
+        }
+
+        let res = fixture
+            .client
+            .post("/service/artifact")
+            .header("X-Ant-Revision", &revision)
+            .header("X-Ant-Project", "ant-gateway")
+            .header("X-Ant-Version", "v1")
+            .header("X-Ant-Architecture", "aarch64")
+            .multipart(req)
+            .send()
+            .await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        assert_contains!(res.text().await.to_lowercase(), "provenance");
+    }
+
+    let artifact = fixture
+        .state
+        .db
+        .get_artifact_by_revision(&revision, "ant-gateway", Some(&HostArchitecture::Aarch64))
+        .await
+        .unwrap();
+    assert_eq!(artifact, None);
+}
+
 #[test]
 #[traced_test]
 async fn service_artifact_includes_env_file() {
     let fixture = fixture::Fixture::new(function_name!()).await;
 
     // register secrets
-    {
-        let secret_root = fixture.state.root_dir.join("secrets-db");
-        let paths = vec![
-            secret_root.join("beta").join("jwt.secret"),
-            secret_root.join("prod").join("jwt.secret"),
-        ];
-        for p in paths {
-            create_dir_all(p.parent().unwrap()).unwrap();
-            let mut file = File::create(p).unwrap();
-            file.write_all("secret value".as_bytes()).unwrap();
-        }
+    for environment in ["beta", "prod"] {
+        put_secret(
+            &fixture.state,
+            "jwt",
+            environment,
+            None,
+            b"secret value",
+            DEFAULT_VALID_FOR,
+        )
+        .await
+        .unwrap();
     }
 
     // REPLICATE
@@ -340,10 +442,9 @@ async fn service_artifact_includes_env_file() {
             let archive = fixture.make_tarfile_fixture("ant-host-agent-and-proj1-v1");
             let input_digest = digest(&archive.path());
 
-            let req = reqwest::multipart::Form::new()
-                .file("file", &archive.path())
-                .await
-                .unwrap();
+            let req = fixture
+                .artifact_form(archive.path(), "ant-host-agent", "aarch64", "v1")
+                .await;
 
             let res = fixture
                 .client
@@ -362,7 +463,8 @@ async fn service_artifact_includes_env_file() {
                 .state
                 .root_dir
                 .join("artifacts-db")
-                .join("ant-host-agent.aarch64.v1.bld");
+                .join("sha256")
+                .join(format!("{input_digest}.bld"));
 
             assert_eq!(
                 (output_path.clone(), exists(output_path.clone()).unwrap()),
@@ -392,10 +494,9 @@ async fn service_artifact_includes_env_file() {
                 .header("X-Ant-Version", "v1")
                 .header("X-Ant-Architecture", "x86")
                 .multipart(
-                    reqwest::multipart::Form::new()
-                        .file("file", &archive.path())
-                        .await
-                        .unwrap(),
+                    fixture
+                        .artifact_form(archive.path(), "ant-host-agent", "x86", "v1")
+                        .await,
                 )
                 .send()
                 .await;
@@ -417,10 +518,9 @@ async fn service_artifact_includes_env_file() {
                 .header("X-Ant-Version", "v1")
                 .header("X-Ant-Architecture", "raspbian")
                 .multipart(
-                    reqwest::multipart::Form::new()
-                        .file("file", &archive.path())
-                        .await
-                        .unwrap(),
+                    fixture
+                        .artifact_form(archive.path(), "ant-host-agent", "raspbian", "v1")
+                        .await,
                 )
                 .send()
                 .await;
@@ -491,18 +591,18 @@ async fn service_artifact_docker_compose_includes_variables() {
     let fixture = Fixture::new(function_name!()).await;
 
     // register secrets
-    {
-        let secret_root = fixture.state.root_dir.join("secrets-db");
-        let paths = vec![
-            secret_root.join("beta").join("tls_cert.secret"),
-            secret_root.join("prod").join("tls_cert.secret"),
-            secret_root.join("beta").join("tls_key.secret"),
-            secret_root.join("prod").join("tls_key.secret"),
-        ];
-        for p in paths {
-            create_dir_all(p.parent().unwrap()).unwrap();
-            let mut file = File::create(p).unwrap();
-            file.write_all("secret value".as_bytes()).unwrap();
+    for environment in ["beta", "prod"] {
+        for name in ["tls_cert", "tls_key"] {
+            put_secret(
+                &fixture.state,
+                name,
+                environment,
+                None,
+                b"secret value",
+                DEFAULT_VALID_FOR,
+            )
+            .await
+            .unwrap();
         }
     }
 
@@ -590,10 +690,9 @@ async fn service_artifact_docker_compose_includes_variables() {
 
             // register artifact
             {
-                let req = reqwest::multipart::Form::new()
-                    .file("file", archive.path())
-                    .await
-                    .unwrap();
+                let req = fixture
+                    .artifact_form(archive.path(), "ant-gateway", "aarch64", "v1")
+                    .await;
 
                 let res = fixture
                     .client
@@ -613,7 +712,8 @@ async fn service_artifact_docker_compose_includes_variables() {
                 .state
                 .root_dir
                 .join("artifacts-db")
-                .join("ant-gateway.aarch64.v1.bld");
+                .join("sha256")
+                .join(format!("{input_digest}.bld"));
 
             assert_eq!(
                 (output_path.clone(), exists(output_path.clone()).unwrap()),
@@ -643,10 +743,9 @@ async fn service_artifact_docker_compose_includes_variables() {
                 .header("X-Ant-Version", "v1")
                 .header("X-Ant-Architecture", "x86")
                 .multipart(
-                    reqwest::multipart::Form::new()
-                        .file("file", archive.path())
-                        .await
-                        .unwrap(),
+                    fixture
+                        .artifact_form(archive.path(), "ant-gateway", "x86", "v1")
+                        .await,
                 )
                 .send()
                 .await;
@@ -668,10 +767,9 @@ async fn service_artifact_docker_compose_includes_variables() {
                 .header("X-Ant-Version", "v1")
                 .header("X-Ant-Architecture", "raspbian")
                 .multipart(
-                    reqwest::multipart::Form::new()
-                        .file("file", archive.path())
-                        .await
-                        .unwrap(),
+                    fixture
+                        .artifact_form(archive.path(), "ant-gateway", "raspbian", "v1")
+                        .await,
                 )
                 .send()
                 .await;
@@ -780,17 +878,17 @@ async fn service_revision_returns_200_with_same_if_architectures_unfinished() {
     let fixture = Fixture::new(function_name!()).await;
 
     // register secrets
-    {
-        let secret_root = fixture.state.root_dir.join("secrets-db");
-        let paths = vec![
-            secret_root.join("beta").join("jwt.secret"),
-            secret_root.join("prod").join("jwt.secret"),
-        ];
-        for p in paths {
-            create_dir_all(p.parent().unwrap()).unwrap();
-            let mut file = File::create(p).unwrap();
-            file.write_all("secret value".as_bytes()).unwrap();
-        }
+    for environment in ["beta", "prod"] {
+        put_secret(
+            &fixture.state,
+            "jwt",
+            environment,
+            None,
+            b"secret value",
+            DEFAULT_VALID_FOR,
+        )
+        .await
+        .unwrap();
     }
 
     // Create revision
@@ -815,10 +913,9 @@ async fn service_revision_returns_200_with_same_if_architectures_unfinished() {
 
     // register artifact (aarch64)
     {
-        let req = reqwest::multipart::Form::new()
-            .file("file", archive.path())
-            .await
-            .unwrap();
+        let req = fixture
+            .artifact_form(archive.path(), "ant-host-agent", "aarch64", "v1")
+            .await;
 
         let res = fixture
             .client
@@ -856,10 +953,9 @@ async fn service_revision_returns_200_with_same_if_architectures_unfinished() {
 
     // register artifact (x86)
     {
-        let req = reqwest::multipart::Form::new()
-            .file("file", archive.path())
-            .await
-            .unwrap();
+        let req = fixture
+            .artifact_form(archive.path(), "ant-host-agent", "x86", "v1")
+            .await;
 
         let res = fixture
             .client
@@ -896,10 +992,9 @@ async fn service_revision_returns_200_with_same_if_architectures_unfinished() {
 
     // register artifact (armv7)
     {
-        let req = reqwest::multipart::Form::new()
-            .file("file", archive.path())
-            .await
-            .unwrap();
+        let req = fixture
+            .artifact_form(archive.path(), "ant-host-agent", "armv7", "v1")
+            .await;
 
         let res = fixture
             .client
@@ -942,17 +1037,17 @@ async fn service_revision_returns_200_with_same_if_version_differs() {
     let fixture = Fixture::new(function_name!()).await;
 
     // register secrets
-    {
-        let secret_root = fixture.state.root_dir.join("secrets-db");
-        let paths = vec![
-            secret_root.join("beta").join("jwt.secret"),
-            secret_root.join("prod").join("jwt.secret"),
-        ];
-        for p in paths {
-            create_dir_all(p.parent().unwrap()).unwrap();
-            let mut file = File::create(p).unwrap();
-            file.write_all("secret value".as_bytes()).unwrap();
-        }
+    for environment in ["beta", "prod"] {
+        put_secret(
+            &fixture.state,
+            "jwt",
+            environment,
+            None,
+            b"secret value",
+            DEFAULT_VALID_FOR,
+        )
+        .await
+        .unwrap();
     }
 
     // Create revision
@@ -977,10 +1072,9 @@ async fn service_revision_returns_200_with_same_if_version_differs() {
 
     // register artifact (aarch64 v1)
     {
-        let req = reqwest::multipart::Form::new()
-            .file("file", archive.path())
-            .await
-            .unwrap();
+        let req = fixture
+            .artifact_form(archive.path(), "ant-host-agent", "aarch64", "v1")
+            .await;
 
         let res = fixture
             .client
@@ -998,10 +1092,9 @@ async fn service_revision_returns_200_with_same_if_version_differs() {
 
     // register artifact (aarch64 v2)
     {
-        let req = reqwest::multipart::Form::new()
-            .file("file", archive.path())
-            .await
-            .unwrap();
+        let req = fixture
+            .artifact_form(archive.path(), "ant-host-agent", "aarch64", "v2")
+            .await;
 
         let res = fixture
             .client
@@ -1019,10 +1112,9 @@ async fn service_revision_returns_200_with_same_if_version_differs() {
 
     // register artifact (x86 v1)
     {
-        let req = reqwest::multipart::Form::new()
-            .file("file", archive.path())
-            .await
-            .unwrap();
+        let req = fixture
+            .artifact_form(archive.path(), "ant-host-agent", "x86", "v1")
+            .await;
 
         let res = fixture
             .client
@@ -1040,10 +1132,9 @@ async fn service_revision_returns_200_with_same_if_version_differs() {
 
     // register artifact (armv7 v1)
     {
-        let req = reqwest::multipart::Form::new()
-            .file("file", archive.path())
-            .await
-            .unwrap();
+        let req = fixture
+            .artifact_form(archive.path(), "ant-host-agent", "armv7", "v1")
+            .await;
 
         let res = fixture
             .client
@@ -1080,10 +1171,9 @@ async fn service_revision_returns_200_with_same_if_version_differs() {
 
     // register new artifact (x86 v2)
     {
-        let req = reqwest::multipart::Form::new()
-            .file("file", archive.path())
-            .await
-            .unwrap();
+        let req = fixture
+            .artifact_form(archive.path(), "ant-host-agent", "x86", "v2")
+            .await;
 
         let res = fixture
             .client
@@ -1101,10 +1191,9 @@ async fn service_revision_returns_200_with_same_if_version_differs() {
 
     // register new artifact (armv7 v2)
     {
-        let req = reqwest::multipart::Form::new()
-            .file("file", archive.path())
-            .await
-            .unwrap();
+        let req = fixture
+            .artifact_form(archive.path(), "ant-host-agent", "armv7", "v2")
+            .await;
 
         let res = fixture
             .client
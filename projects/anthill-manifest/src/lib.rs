@@ -1,4 +1,4 @@
-use std::{collections::HashMap, fs::File, io::Read, path::Path};
+use std::{collections::HashMap, fs::File, io::Read, path::Path, str::FromStr};
 
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
@@ -24,6 +24,9 @@ pub struct AnthillManifest {
 
     pub deployment: Option<DeploymentOptions>,
 
+    /// Alert rules and log rules that deploy alongside this project, per environment.
+    pub monitoring: Option<MonitoringOptions>,
+
     /// For the reverse-proxy to the outside, this setting affects the NGINX settings when
     /// this project deploys.
     #[serde(default)]
@@ -242,7 +245,7 @@ pub enum AnthillArchetype {
         password_secret_name: String,
 
         /// The directory containing migration .sql and .sh files, will be applied to the database pre-deployment, for each environment.
-        /// Defaults to "migration"
+        /// Defaults to "migrations"
         #[serde(default = "default_migration_dir")]
         migration_dir: String,
     },
@@ -252,7 +255,7 @@ pub enum AnthillArchetype {
 }
 
 fn default_migration_dir() -> String {
-    "migration".to_string()
+    "migrations".to_string()
 }
 
 // impl FromStr for AnthillArchetype {
@@ -273,6 +276,212 @@ pub struct DeploymentOptions {
         note = "All projects now support unversioned deployments via the 'current' symlink"
     )]
     pub versioned: Option<bool>,
+
+    /// How each host deployment is verified before the deployment moves on. If not set, the
+    /// defaults of [`VerificationOptions`] apply.
+    pub verification: Option<VerificationOptions>,
+
+    /// Whether promoting a revision from beta to prod waits until someone approves it through
+    /// ant-zookeeper. Defaults to false, promoting automatically once beta is verified.
+    /// Cannot be set alongside `pipeline`, set `requires_approval` on its prod stage instead.
+    pub requires_prod_approval: Option<bool>,
+
+    /// The shape of the pipeline every revision deploys through. If not set, revisions deploy to
+    /// beta and then prod, to the hosts of each in waves of 1, 2 and then 4.
+    pub pipeline: Option<PipelineDefinition>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum AnthillEnvironment {
+    #[serde(rename = "beta")]
+    Beta,
+
+    #[serde(rename = "prod")]
+    Prod,
+}
+
+impl AnthillEnvironment {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AnthillEnvironment::Beta => "beta",
+            AnthillEnvironment::Prod => "prod",
+        }
+    }
+}
+
+impl FromStr for AnthillEnvironment {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "beta" => Ok(AnthillEnvironment::Beta),
+            "prod" => Ok(AnthillEnvironment::Prod),
+            s => Err(format!("No such environment: {s}")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct PipelineDefinition {
+    /// The environments to deploy to, in order. Each stage starts once the one before it is
+    /// verified and baked. Hosts in environments without a stage are not deployed to.
+    #[schemars(length(min = 1))]
+    pub stages: Vec<PipelineStage>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct PipelineStage {
+    pub environment: AnthillEnvironment,
+
+    /// How many of the environment's hosts are deployed to at once, wave after wave. The last
+    /// size repeats until all hosts are deployed to. Defaults to waves of 1, 2 and then 4 hosts.
+    pub waves: Option<Vec<WaveSize>>,
+
+    /// Whether the stage waits until someone approves it through ant-zookeeper. Defaults to
+    /// false. The first stage has nothing before it to gate, so cannot require approval.
+    pub requires_approval: Option<bool>,
+
+    /// How long to wait after the last wave is verified, before the next stage may start.
+    /// Defaults to 0.
+    pub bake_time_seconds: Option<u64>,
+
+    /// Whether a failed host deployment in this stage unwinds the revision from the hosts it
+    /// already reached. Defaults to true.
+    pub unwind_on_failure: Option<bool>,
+
+    /// Which of the optional steps run before the hosts of this stage are deployed to.
+    #[serde(default)]
+    pub steps: PipelineSteps,
+}
+
+/// Each step runs by default if the project configures it, and can be turned off per stage.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct PipelineSteps {
+    /// Update the gateway routes in `routing`.
+    pub routes: Option<bool>,
+
+    /// Load the `monitoring.alert_rules` into ant-monitor.
+    pub alerts: Option<bool>,
+
+    /// Send the `monitoring.log_rules` to ant-lumberjack on each host.
+    pub log_rules: Option<bool>,
+
+    /// Migrate the database of a `postgres` archetype.
+    pub database_migration: Option<bool>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum WaveSize {
+    /// This many hosts.
+    #[serde(rename = "hosts")]
+    Hosts(#[schemars(range(min = 1))] usize),
+
+    /// This percentage of the environment's hosts, rounded up.
+    #[serde(rename = "percent")]
+    Percent(#[schemars(range(min = 1, max = 100))] u8),
+}
+
+impl WaveSize {
+    /// The number of hosts in a wave of this size, out of `total` hosts. At least 1.
+    pub fn hosts(&self, total: usize) -> usize {
+        let hosts = match self {
+            WaveSize::Hosts(hosts) => *hosts,
+            WaveSize::Percent(percent) => (total * *percent as usize).div_ceil(100),
+        };
+        hosts.max(1)
+    }
+}
+
+impl PipelineDefinition {
+    pub fn validate(&self) -> Result<(), anyhow::Error> {
+        if self.stages.is_empty() {
+            return Err(anyhow::Error::msg(
+                "The .deployment.pipeline must have at least one stage.",
+            ));
+        }
+
+        for (i, stage) in self.stages.iter().enumerate() {
+            let environment = stage.environment.as_str();
+
+            if self.stages[..i]
+                .iter()
+                .any(|s| s.environment == stage.environment)
+            {
+                return Err(anyhow::Error::msg(format!(
+                    "The .deployment.pipeline has more than one stage for '{environment}'."
+                )));
+            }
+
+            if i == 0 && stage.requires_approval == Some(true) {
+                return Err(anyhow::Error::msg(format!(
+                    "The first stage '{environment}' cannot require approval, there is nothing \
+                     before it to approve."
+                )));
+            }
+
+            if stage.waves.as_ref().is_some_and(|w| w.is_empty()) {
+                return Err(anyhow::Error::msg(format!(
+                    "The waves of stage '{environment}' must have at least one size, or be left \
+                     out for the default."
+                )));
+            }
+
+            for wave in stage.waves.iter().flatten() {
+                let is_valid = match wave {
+                    WaveSize::Hosts(hosts) => *hosts >= 1,
+                    WaveSize::Percent(percent) => (1..=100).contains(percent),
+                };
+                if !is_valid {
+                    return Err(anyhow::Error::msg(format!(
+                        "The waves of stage '{environment}' must have at least 1 host and at \
+                         most 100 percent."
+                    )));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize, JsonSchema)]
+pub struct MonitoringOptions {
+    /// A Prometheus rules file, relative to the project directory, for example "alerts.yml".
+    /// It is loaded by ant-monitor when the project deploys to an environment.
+    pub alert_rules: Option<String>,
+
+    /// A file of log => metric rules, relative to the project directory, for example
+    /// "log-rules.yml". It is sent to the "config" port of ant-lumberjack on each host the
+    /// project deploys to.
+    pub log_rules: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize, JsonSchema)]
+pub struct VerificationOptions {
+    /// Whether to probe GET /ping on the primary port, the `ant_library::api_ping` convention.
+    /// Defaults to true. Projects without a primary port are never pinged.
+    pub ping: Option<bool>,
+
+    /// How long a freshly deployed service has to pass its first probe. Defaults to 60 seconds.
+    pub startup_timeout_seconds: Option<u64>,
+
+    /// How long the service has to keep passing its probes after the first success, before the
+    /// deployment is verified. Defaults to 0, verifying on the first success.
+    pub bake_time_seconds: Option<u64>,
+
+    /// Fail the deployment if too many requests failed during the bake time, according to the
+    /// metrics served from the metrics port.
+    pub error_rate: Option<ErrorRateCheck>,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct ErrorRateCheck {
+    /// A request counter with a `status` label, like the axum-prometheus
+    /// `<prefix>_http_requests_total`. Requests with a 5xx status are errors.
+    pub metric: String,
+
+    /// The highest tolerated fraction of errors over the bake time, e.g. 0.05 for 5%.
+    pub max_error_rate: f64,
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -350,6 +559,19 @@ impl AnthillManifest {
             )));
         }
 
+        if let Some(deployment) = &self.deployment
+            && let Some(pipeline) = &deployment.pipeline
+        {
+            if deployment.requires_prod_approval.is_some() {
+                return Err(anyhow::Error::msg(
+                    "Set .requires_approval on the prod stage of .deployment.pipeline instead \
+                     of .deployment.requires_prod_approval.",
+                ));
+            }
+
+            pipeline.validate()?;
+        }
+
         // If .routing.paths, then also .routing.domain must be set
         for route in self.routing.iter() {
             if !route.paths.is_empty() && route.domain.is_none() {
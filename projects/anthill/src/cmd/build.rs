@@ -1,7 +1,10 @@
 use std::{collections::HashSet, os::unix::fs::PermissionsExt, str::FromStr};
 
 use ant_library::{
-    host_architecture::HostArchitecture, manifest_file::ManifestFile, services::Services,
+    host_architecture::HostArchitecture,
+    manifest_file::ManifestFile,
+    provenance::{file_sha256, Provenance, ProvenanceSigner, SignedProvenance},
+    services::Services,
 };
 use anthill_manifest::{AnthillArchetype, AnthillBuild, AnthillBuildParallelism, AnthillManifest};
 use anyhow::Context;
@@ -56,6 +59,17 @@ pub struct DeploymentFile {
     pub file: TempFile,
     pub version: String,
     pub arch: HostArchitecture,
+    /// Signed with the key in ANTHILL_SIGNING_KEY, ant-zookeeper only registers signed files.
+    pub provenance: SignedProvenance,
+}
+
+/// The signer of build provenance, from the Ed25519 private key at the path in
+/// ANTHILL_SIGNING_KEY.
+fn provenance_signer() -> Result<ProvenanceSigner, anyhow::Error> {
+    let path = std::env::var("ANTHILL_SIGNING_KEY")
+        .context("ANTHILL_SIGNING_KEY must be the path of the key signing builds")?;
+    let pem = std::fs::read_to_string(&path).context(format!("reading signing key {path}"))?;
+    ProvenanceSigner::from_pem(&pem)
 }
 
 pub async fn build(cmd: BuildCmd) -> Vec<DeploymentFile> {
@@ -153,6 +167,15 @@ pub async fn build(cmd: BuildCmd) -> Vec<DeploymentFile> {
 
             let path = file.file.file_path().clone();
             file.file.persist(&path).await.unwrap();
+
+            let mut provenance_path = path.into_os_string();
+            provenance_path.push(".provenance.json");
+            std::fs::write(
+                &provenance_path,
+                serde_json::to_string(&file.provenance).unwrap(),
+            )
+            .unwrap();
+            println!("provenance: \t{}", provenance_path.to_string_lossy());
         }
 
         return vec![];
@@ -173,10 +196,23 @@ async fn build_arch<'a>(
         .await
         .context("build failed")?;
 
+    let provenance = provenance_signer()?
+        .sign(&Provenance {
+            project: cmd.project.clone(),
+            version: git.version(),
+            commit: git.head_sha.clone(),
+            builder: format!("anthill/{}", env!("CARGO_PKG_VERSION")),
+            architecture: arch.as_str().to_string(),
+            sha256: file_sha256(deployment_file.file_path())?,
+            built_at: chrono::Utc::now(),
+        })
+        .context("signing provenance")?;
+
     Ok(DeploymentFile {
         version: git.version(),
         file: deployment_file,
         arch: arch.clone(),
+        provenance,
     })
 }
 
@@ -437,11 +473,52 @@ async fn build_artifact<'a>(
                         migrations_path.display()
                     ));
                 }
+
+                dircpy::copy_dir(
+                    &migrations_path,
+                    tmp_packaging_dir.path().join(".db-migrations"),
+                )
+                .context("copying database migrations")?;
             }
             _ => {}
         }
     }
 
+    // .monitoring: Copy alert and log rules, if any
+    {
+        let monitoring = anthill.monitoring.as_ref();
+        for (rules, name) in [
+            (
+                monitoring.and_then(|m| m.alert_rules.as_ref()),
+                "alert-rules.yml",
+            ),
+            (
+                monitoring.and_then(|m| m.log_rules.as_ref()),
+                "log-rules.yml",
+            ),
+        ] {
+            let Some(rules) = rules else {
+                continue;
+            };
+
+            let rules_path = project_src.join(rules);
+            if !std::fs::exists(&rules_path)? {
+                return Err(anyhow::anyhow!(
+                    "Rules file not found: {}",
+                    rules_path.display()
+                ));
+            }
+
+            tokio::fs::create_dir_all(tmp_packaging_dir.path().join(".monitoring")).await?;
+            tokio::fs::copy(
+                &rules_path,
+                tmp_packaging_dir.path().join(".monitoring").join(name),
+            )
+            .await
+            .with_context(|| format!("copying {}", rules_path.display()))?;
+        }
+    }
+
     // // Copy systemd
     // {
     //     let systemd_path = project_src.join(format!("{}.service", cmd.project));
@@ -14,6 +14,7 @@ pub async fn deploy(cmd: DeployCmd) -> () {
     let client = ant_zookeeper::client::AntZookeeperClient::new(AntZookeeperClientConfig {
         tls: false,
         endpoint: "localhost:3235".to_string(),
+        token: std::env::var("ANT_ZOOKEEPER_TOKEN").ok(),
     });
 
     let revision = client
@@ -36,9 +37,17 @@ pub async fn deploy(cmd: DeployCmd) -> () {
                     ant_zookeeper::client::AntZookeeperClient::new(AntZookeeperClientConfig {
                         tls: false,
                         endpoint: "localhost:3235".to_string(),
+                        token: std::env::var("ANT_ZOOKEEPER_TOKEN").ok(),
                     });
                 client
-                    .register_artifact(&rev2, &project, &f.arch, &f.version, &f.file.file_path())
+                    .register_artifact(
+                        &rev2,
+                        &project,
+                        &f.arch,
+                        &f.version,
+                        &f.file.file_path(),
+                        &f.provenance,
+                    )
                     .await
                     .expect("register artifact");
 
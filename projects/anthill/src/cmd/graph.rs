@@ -0,0 +1,52 @@
+use ant_zookeeper::{
+    client::AntZookeeperClientConfig, pipeline::graph::GraphFormat,
+    routes::pipeline::GetPipelineGraphRequest,
+};
+
+#[derive(Clone, Copy, clap::ValueEnum)]
+pub enum Format {
+    /// Graphviz, e.g. for `dot -Tsvg`.
+    Dot,
+    /// Mermaid, e.g. for a markdown code block.
+    Mermaid,
+    /// Layers of the pipeline, coloured for the terminal.
+    Terminal,
+}
+
+impl From<Format> for GraphFormat {
+    fn from(format: Format) -> Self {
+        match format {
+            Format::Dot => GraphFormat::Dot,
+            Format::Mermaid => GraphFormat::Mermaid,
+            Format::Terminal => GraphFormat::Terminal,
+        }
+    }
+}
+
+#[derive(clap::Args)]
+pub struct GraphCmd {
+    /// Pipeline to export, e.g. "pipeline-abc123".
+    pipeline_id: String,
+
+    #[arg(long, value_enum, default_value = "terminal")]
+    format: Format,
+}
+
+pub async fn graph(cmd: GraphCmd) -> Result<(), anyhow::Error> {
+    let client = ant_zookeeper::client::AntZookeeperClient::new(AntZookeeperClientConfig {
+        tls: false,
+        endpoint: "localhost:3235".to_string(),
+        token: std::env::var("ANT_ZOOKEEPER_TOKEN").ok(),
+    });
+
+    let graph = client
+        .get_pipeline_graph(GetPipelineGraphRequest {
+            pipeline_id: cmd.pipeline_id,
+            format: cmd.format.into(),
+        })
+        .await?;
+
+    print!("{graph}");
+
+    Ok(())
+}
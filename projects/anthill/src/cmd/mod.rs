@@ -2,4 +2,5 @@ pub mod build;
 pub mod curl;
 pub mod deploy;
 pub mod dev;
+pub mod graph;
 pub mod run;
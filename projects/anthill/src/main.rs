@@ -14,6 +14,7 @@ enum Cli {
     Dev(crate::cmd::dev::DevCmd),
     Run(crate::cmd::run::RunCmd),
     Curl(crate::cmd::curl::CurlCmd),
+    Graph(crate::cmd::graph::GraphCmd),
 }
 
 #[tokio::main(flavor = "local")]
@@ -42,6 +43,9 @@ async fn main() -> Result<()> {
         Cli::Curl(cmd) => {
             crate::cmd::curl::curl(cmd).await?;
         }
+        Cli::Graph(cmd) => {
+            crate::cmd::graph::graph(cmd).await?;
+        }
     }
 
     Ok(())
@@ -185,15 +185,18 @@ function register_artifact() {
   local arch="$3"
   local path="$4"
 
+  # The provenance anthill signed next to the artifact, as <path>.provenance.json
   curl \
     --no-progress-meter \
     -X POST \
     --fail-with-body \
     -w "\n" \
+    -H "Authorization: Bearer $ANT_ZOOKEEPER_TOKEN" \
     -H "X-Ant-Project: $project" \
     -H "X-Ant-Version: $version" \
     -H "X-Ant-Architecture: $arch" \
     -F file="@$path" \
+    -F provenance="<$path.provenance.json" \
     localhost:3235/service/artifact
 }
 
@@ -17,6 +17,8 @@ echo "
 PERSIST_DIR=/tmp/ant-host-agent.test/persist
 VERSION=test
 ANT_HOST_AGENT_INSTALL_ROOT_DIR=./install
+ANT_HOST_AGENT_PERSIST_ROOT_DIR=./persist
+ANT_HOST_AGENT_INSTALL_UNVERIFIED=true
 PORT=3333
 " > /tmp/ant-host-agent.test/.env
 '
\ No newline at end of file